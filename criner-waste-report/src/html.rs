@@ -1,9 +1,19 @@
-use super::{AggregateFileInfo, Dict, Fix, Report, VersionInfo};
+use super::{AggregateFileInfo, Breakdown, Dict, Fix, Report, VersionInfo, ROOT_DIR_MARKER};
 use bytesize::ByteSize;
 use dia_semver::Semver;
-use horrorshow::{box_html, helper::doctype, html, Render, RenderBox, RenderOnce, TemplateBuffer};
+use horrorshow::{box_html, helper::doctype, html, owned_html, Render, RenderBox, RenderOnce, TemplateBuffer};
 use std::time::SystemTime;
 
+/// The amount of crates listed on the top-level page, those with the most waste
+pub const TOP_OFFENDERS: usize = 100;
+
+/// The page of a report claiming to be generated at `generated_at`, so that writing the same report twice produces the
+/// same output. Reports rendered on their own claim to be generated at the current time.
+pub struct Page {
+    pub report: Report,
+    pub generated_at: SystemTime,
+}
+
 pub fn fix_to_wasted_files_aggregate(fix: Option<Fix>) -> Option<AggregateFileInfo> {
     match fix.unwrap_or(Fix::RemoveExclude) {
         Fix::ImprovedInclude {
@@ -21,7 +31,7 @@ pub fn fix_to_wasted_files_aggregate(fix: Option<Fix>) -> Option<AggregateFileIn
     })
 }
 
-pub const NO_EXT_MARKER: &str = "<NO_EXT>";
+pub use super::NO_EXT_MARKER;
 
 fn parse_semver(version: &str) -> Semver {
     use std::str::FromStr;
@@ -65,6 +75,20 @@ fn total_section(bytes: u64, files: u64) -> Box<dyn Render> {
     }
 }
 
+fn waste_section(waste: AggregateFileInfo, num_versions: usize) -> Box<dyn Render> {
+    box_html! {
+        section(id="total-waste") {
+            h3: "total waste";
+            p: format!(
+                "{} wasted in {} files across {} versions",
+                ByteSize(waste.total_bytes),
+                waste.total_files,
+                num_versions
+            );
+        }
+    }
+}
+
 fn savings_section(d: Option<AggregateFileInfo>) -> Box<dyn Render> {
     box_html! {
         @ if let Some(all) = d.as_ref() {
@@ -99,6 +123,7 @@ fn info_section(name: String, info: VersionInfo) -> Box<dyn RenderBox> {
         waste,
         potential_gains,
         waste_latest_version,
+        wasted_bytes_per_day,
     } = info;
     box_html! {
         section(id="child-total") {
@@ -118,6 +143,12 @@ fn info_section(name: String, info: VersionInfo) -> Box<dyn RenderBox> {
                 p: format!("{} wasted in {} files", ByteSize(info.total_bytes), info.total_files);
             }
         }
+        @ if let Some(bytes) = wasted_bytes_per_day {
+            section(id="child-waste-per-day") {
+                h3: "Waste across all downloads";
+                p: format!("{} wasted per day", ByteSize(bytes));
+            }
+        }
         @ if let Some(gains) = potential_gains {
             section(id="child-gains") {
                 h3: "Potential Gains";
@@ -127,8 +158,8 @@ fn info_section(name: String, info: VersionInfo) -> Box<dyn RenderBox> {
     }
 }
 
-fn page_footer() -> impl Render {
-    html! {
+fn page_footer(generated_at: SystemTime) -> impl Render {
+    owned_html! {
         footer {
             span {
                 : "Created by ";
@@ -144,7 +175,7 @@ fn page_footer() -> impl Render {
             span {
                  : "Generated by ";
                  a(href="https://github.com/the-lean-crate/criner#the-criner-waste-report"): "Criner";
-                 : format!(" at {}", humantime::format_rfc3339_seconds(SystemTime::now()));
+                 : format!(" at {}", humantime::format_rfc3339_seconds(generated_at));
             }
         }
     }
@@ -162,22 +193,28 @@ fn child_items_section(
     prefix: String,
     suffix: impl Into<String>,
     order: SortOrder,
+    limit: Option<usize>,
 ) -> Box<dyn RenderBox> {
     let title = title.into();
     let suffix = suffix.into();
+    let num_children = info_by_child.len();
+    let limit = limit.unwrap_or(num_children);
     let mut sorted: Vec<_> = info_by_child.into_iter().collect();
     sorted.sort_by(|(ln, le), (rn, re)| match order {
         SortOrder::Semver => parse_semver(ln).cmp(&parse_semver(rn)),
-        SortOrder::Waste => match (&le.waste_latest_version, &re.waste_latest_version) {
-            (Some(le), Some(re)) => le.1.total_bytes.cmp(&re.1.total_bytes),
-            _ => le.waste.total_bytes.cmp(&re.waste.total_bytes),
-        },
+        // Waste weighted by downloads comes first, so popular offenders rank above obscure ones of the same size
+        SortOrder::Waste => le.wasted_bytes_per_day.cmp(&re.wasted_bytes_per_day).then_with(|| {
+            match (&le.waste_latest_version, &re.waste_latest_version) {
+                (Some(le), Some(re)) => le.1.total_bytes.cmp(&re.1.total_bytes),
+                _ => le.waste.total_bytes.cmp(&re.waste.total_bytes),
+            }
+        }),
     });
     box_html! {
         section(id="children") {
             h1: title;
             ol {
-                @ for (name, info) in sorted.into_iter().rev() {
+                @ for (name, info) in sorted.into_iter().rev().take(limit) {
                     li {
                         h3 {
                             a(href=format!("{}{}{}", prefix, name, suffix)) {
@@ -188,6 +225,9 @@ fn child_items_section(
                     }
                 }
             }
+            @ if num_children > limit {
+                p: format!("{} more with less waste aren't listed", num_children - limit);
+            }
         }
     }
 }
@@ -232,13 +272,97 @@ fn by_extension_section(wasted_by_extension: Dict<AggregateFileInfo>) -> Box<dyn
     }
 }
 
+fn breakdown_table(
+    title: &'static str,
+    info_by_name: Dict<AggregateFileInfo>,
+    label: fn(&str) -> String,
+    total_bytes: u64,
+) -> Box<dyn RenderBox> {
+    let mut sorted: Vec<_> = info_by_name.into_iter().collect();
+    sorted.sort_by_key(|(_, e)| std::cmp::Reverse(e.total_bytes));
+    let top_list = 20;
+    let num_skipped = sorted.len().saturating_sub(top_list);
+    box_html! {
+        section {
+            h3: title;
+            table {
+                tr {
+                    th: "name";
+                    th: "size";
+                    th: "share";
+                    th: "files";
+                }
+                @ for (name, info) in sorted.into_iter().take(top_list) {
+                    tr {
+                        td: label(&name);
+                        td: format!("{}", ByteSize(info.total_bytes));
+                        td: format!("{:.1}%", if total_bytes == 0 { 0.0 } else { info.total_bytes as f64 * 100.0 / total_bytes as f64 });
+                        td: info.total_files;
+                    }
+                }
+            }
+            @ if num_skipped > 0 {
+                p: format!("Skipped {} smaller entries", num_skipped)
+            }
+        }
+    }
+}
+
+fn breakdown_section(breakdown: Breakdown, total_bytes: u64) -> Box<dyn RenderBox> {
+    let Breakdown {
+        by_extension,
+        by_directory,
+        by_category,
+    } = breakdown;
+    box_html! {
+        @ if !by_category.is_empty() {
+            : breakdown_table(
+                "Size of tests, benchmarks, examples and fixtures",
+                by_category,
+                |name| name.to_owned(),
+                total_bytes
+            );
+        }
+        @ if !by_extension.is_empty() {
+            : breakdown_table(
+                "Size by extension",
+                by_extension,
+                |name| if name == NO_EXT_MARKER { "no extension".into() } else { format!("*.{}", name) },
+                total_bytes
+            );
+        }
+        @ if !by_directory.is_empty() {
+            : breakdown_table(
+                "Size by top-level directory",
+                by_directory,
+                |name| if name == ROOT_DIR_MARKER { "crate root".into() } else { format!("{}/", name) },
+                total_bytes
+            );
+        }
+    }
+}
+
 impl RenderOnce for Report {
+    fn render_once(self, tmpl: &mut TemplateBuffer<'_>)
+    where
+        Self: Sized,
+    {
+        Page {
+            report: self,
+            generated_at: SystemTime::now(),
+        }
+        .render_once(tmpl)
+    }
+}
+
+impl RenderOnce for Page {
     fn render_once(self, tmpl: &mut TemplateBuffer<'_>)
     where
         Self: Sized,
     {
         use super::Report::*;
-        match self {
+        let Page { report, generated_at } = self;
+        match report {
             Version {
                 crate_name,
                 crate_version,
@@ -246,6 +370,8 @@ impl RenderOnce for Report {
                 total_size_in_bytes,
                 mut wasted_files,
                 suggested_fix,
+                breakdown,
+                downloads_per_day,
             } => {
                 wasted_files.sort_by_key(|(_, s)| *s);
                 let title = format!("{}:{}", crate_name, crate_version);
@@ -257,10 +383,19 @@ impl RenderOnce for Report {
                             article {
                                 : title_section(title);
                                 : total_section(total_size_in_bytes, total_files);
+                                @ if let Some(downloads) = downloads_per_day {
+                                    section {
+                                        p: format!("downloaded {} times per day", downloads);
+                                    }
+                                }
                                 : savings_section(fix_to_wasted_files_aggregate(suggested_fix.clone()));
                                 @ if let Some(suggested_fix) = suggested_fix {
                                     section {
                                         h3: "Fix";
+                                        p {
+                                            a(href=format!("{}.toml", crate_version)): "Cargo.toml excerpt";
+                                            : " to paste into the manifest";
+                                        }
                                         section {
                                             |t| write!(t, "{:#?}", suggested_fix)
                                         }
@@ -268,6 +403,7 @@ impl RenderOnce for Report {
                                 } else {
                                     p: "Perfectly lean!"
                                 }
+                                : breakdown_section(breakdown, total_size_in_bytes);
                                 @ if !wasted_files.is_empty() {
                                     section {
                                         h3: format!("{} wasted files", wasted_files.len());
@@ -281,7 +417,7 @@ impl RenderOnce for Report {
                                 }
                             }
                         }
-                        : page_footer();
+                        : page_footer(generated_at);
                     }
                 }
             }
@@ -293,6 +429,13 @@ impl RenderOnce for Report {
                 wasted_by_extension,
             } => {
                 let gains = potential_savings(&info_by_version);
+                let waste = info_by_version
+                    .values()
+                    .fold(AggregateFileInfo::default(), |mut waste, info| {
+                        waste += info.waste.clone();
+                        waste
+                    });
+                let num_versions = info_by_version.len();
                 let no_prefix = String::new();
                 tmpl << html! {
                     : doctype::HTML;
@@ -302,12 +445,13 @@ impl RenderOnce for Report {
                             article {
                                 : title_section(crate_name.clone());
                                 : total_section(total_size_in_bytes, total_files);
+                                : waste_section(waste, num_versions);
                                 : savings_section(gains);
                                 : by_extension_section(wasted_by_extension);
-                                : child_items_section("Versions", info_by_version, no_prefix, ".html", SortOrder::Semver);
+                                : child_items_section("Versions", info_by_version, no_prefix, ".html", SortOrder::Semver, None);
                             }
                         }
-                        : page_footer();
+                        : page_footer(generated_at);
                     }
                 }
             }
@@ -321,6 +465,11 @@ impl RenderOnce for Report {
                 let no_prefix = String::new();
                 let no_suffix = String::new();
                 let gains = potential_savings(&info_by_crate);
+                let crates_title = if info_by_crate.len() > TOP_OFFENDERS {
+                    format!("Top {} of {} crates", TOP_OFFENDERS, info_by_crate.len())
+                } else {
+                    "Crates".into()
+                };
                 let (waste_in_bytes, wasted_files_count) = wasted_by_extension
                     .iter()
                     .fold((0, 0), |(waste_bytes, waste_files), e| {
@@ -339,10 +488,10 @@ impl RenderOnce for Report {
                                 }
                                 : savings_section(gains);
                                 : by_extension_section(wasted_by_extension);
-                                : child_items_section("Crates", info_by_crate, no_prefix, no_suffix, SortOrder::Waste);
+                                : child_items_section(crates_title, info_by_crate, no_prefix, no_suffix, SortOrder::Waste, Some(TOP_OFFENDERS));
                             }
                         }
-                        : page_footer();
+                        : page_footer(generated_at);
                     }
                 }
             }
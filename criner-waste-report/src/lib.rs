@@ -13,12 +13,12 @@ mod test;
 use serde_derive::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 
-pub use result::{globset_from_patterns, tar_path_to_utf8_str};
+pub use result::{globset_from_patterns, tar_path_to_utf8, tar_path_to_utf8_str};
 
 pub type Patterns = Vec<String>;
 
 /// An entry in a tar archive, including the most important meta-data
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
 pub struct TarHeader {
     /// The normalized path of the entry. May not be unicode encoded.
     pub path: Vec<u8>,
@@ -26,6 +26,26 @@ pub struct TarHeader {
     pub size: u64,
     /// The type of entry, to be analyzed with tar::EntryType
     pub entry_type: u8,
+    /// How the content of the entry is stored, unset if it isn't stored or was stored before it was detected
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<ContentEncoding>,
+    /// The amount of bytes of the content which were stored, set if the content was cut off as the entry was larger than
+    /// allowed. It's only ever set along with `encoding`, as fields are stored by position.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub truncated_at: Option<u64>,
+}
+
+/// The encoding of the content of an entry, as detected before storing it
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ContentEncoding {
+    /// Text which is stored as is, as it was valid UTF-8
+    Utf8,
+    /// Text which was UTF-16 with a byte order mark, stored as UTF-8
+    Utf16,
+    /// Text which was neither UTF-8 nor UTF-16, assumed to be Latin-1 and stored as UTF-8
+    Latin1,
+    /// Binary data which is stored as is
+    Binary,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
@@ -95,6 +115,56 @@ impl Fix {
             (lhs, _) => (lhs, waste),
         }
     }
+
+    /// The part of the `[package]` section of a `Cargo.toml` file which applies this fix, ready to be pasted over
+    /// the existing `include` and `exclude` keys.
+    pub fn to_manifest_excerpt(&self) -> String {
+        fn array(key: &str, patterns: &[String]) -> String {
+            let mut out = format!("{} = [\n", key);
+            for pattern in patterns {
+                out.push_str(&format!("    {},\n", toml::Value::String(pattern.clone())));
+            }
+            out.push_str("]\n");
+            out
+        }
+        const BUILD_SCRIPT_NOTE: &str =
+            "# There is a build script - make sure the files it needs at build time are still included\n";
+        let mut out = String::from("[package]\n");
+        match self {
+            Fix::ImprovedInclude {
+                include,
+                has_build_script,
+                ..
+            }
+            | Fix::NewInclude {
+                include,
+                has_build_script,
+            } => {
+                if *has_build_script {
+                    out.push_str(BUILD_SCRIPT_NOTE);
+                }
+                out.push_str(&array("include", include));
+            }
+            Fix::EnrichedExclude {
+                exclude,
+                has_build_script,
+                ..
+            } => {
+                if *has_build_script {
+                    out.push_str(BUILD_SCRIPT_NOTE);
+                }
+                out.push_str(&array("exclude", exclude));
+            }
+            Fix::RemoveExcludeAndUseInclude { include, .. } => {
+                out.push_str("# Remove 'exclude', as 'include' now takes care of everything\n");
+                out.push_str(&array("include", include));
+            }
+            Fix::RemoveExclude => {
+                out.push_str("# Remove 'exclude', as 'include' already leaves out all files it would exclude\n");
+            }
+        }
+        out
+    }
 }
 
 #[derive(Default, Deserialize)]
@@ -151,6 +221,11 @@ impl PackageSection {
 
 pub type WastedFile = (String, u64);
 
+/// Files without extension are aggregated under this name
+pub const NO_EXT_MARKER: &str = "<NO_EXT>";
+/// Files at the root of a crate are aggregated under this directory name
+pub const ROOT_DIR_MARKER: &str = "<ROOT>";
+
 #[derive(Default, Debug, PartialEq, Clone, Deserialize, Serialize)]
 pub struct AggregateFileInfo {
     pub total_bytes: u64,
@@ -175,9 +250,11 @@ impl std::ops::AddAssign for VersionInfo {
             waste,
             potential_gains,
             waste_latest_version,
+            wasted_bytes_per_day,
         } = rhs;
         self.all += all;
         self.waste += waste;
+        self.wasted_bytes_per_day = add_optional(self.wasted_bytes_per_day, wasted_bytes_per_day);
         self.potential_gains = add_optional_aggregate(self.potential_gains.clone(), potential_gains);
         self.waste_latest_version =
             add_named_optional_aggregate(self.waste_latest_version.clone(), waste_latest_version);
@@ -197,6 +274,13 @@ fn add_named_optional_aggregate(
     })
 }
 
+pub fn add_optional(lhs: Option<u64>, rhs: Option<u64>) -> Option<u64> {
+    match (lhs, rhs) {
+        (None, None) => None,
+        (lhs, rhs) => Some(lhs.unwrap_or(0) + rhs.unwrap_or(0)),
+    }
+}
+
 pub fn add_optional_aggregate(
     lhs: Option<AggregateFileInfo>,
     rhs: Option<AggregateFileInfo>,
@@ -218,12 +302,123 @@ pub struct VersionInfo {
     pub waste: AggregateFileInfo,
     pub waste_latest_version: Option<(String, AggregateFileInfo)>,
     pub potential_gains: Option<AggregateFileInfo>,
+    /// The wasted bytes of each version multiplied by its downloads per day, if download counts are known
+    #[serde(default)]
+    pub wasted_bytes_per_day: Option<u64>,
 }
 
 pub type AggregateVersionInfo = VersionInfo;
 
 pub type Dict<T> = BTreeMap<String, T>;
 
+/// How the bytes and files of a crate version are distributed among its files
+#[derive(Default, Debug, PartialEq, Clone, Deserialize, Serialize)]
+pub struct Breakdown {
+    /// Files by extension, with files without extension under `NO_EXT_MARKER`
+    pub by_extension: Dict<AggregateFileInfo>,
+    /// Files by top-level directory, with files at the root of the crate under `ROOT_DIR_MARKER`
+    pub by_directory: Dict<AggregateFileInfo>,
+    /// Files by the name of their `Category`, for files in one
+    #[serde(default)]
+    pub by_category: Dict<AggregateFileInfo>,
+}
+
+/// Kinds of files which aren't needed to build a crate, but unlike other waste may be shipped on purpose,
+/// so whether they are waste is up to the user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+pub enum Category {
+    Tests,
+    Benches,
+    Examples,
+    Fixtures,
+}
+
+impl Category {
+    pub const ALL: [Category; 4] = [
+        Category::Tests,
+        Category::Benches,
+        Category::Examples,
+        Category::Fixtures,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Category::Tests => "tests",
+            Category::Benches => "benches",
+            Category::Examples => "examples",
+            Category::Fixtures => "fixtures",
+        }
+    }
+
+    /// The patterns of files in this category, as used for excluding them
+    pub fn patterns(&self) -> &'static [&'static str] {
+        match self {
+            Category::Tests => &[
+                "**/test/**/*",
+                "**/*_test.*",
+                "**/*_test/**/*",
+                "**/tests/**/*",
+                "**/*_tests.*",
+                "**/*_tests/**/*",
+                "**/testing/**/*",
+                "**/spec/**/*",
+                "**/*_spec.*",
+                "**/*_spec/**/*",
+                "**/specs/**/*",
+                "**/*_specs.*",
+                "**/*_specs/**/*",
+            ],
+            Category::Benches => &["**/benches/**/*", "**/benchmark/**/*", "**/benchmarks/**/*"],
+            Category::Examples => &["**/example/**/*", "**/examples/**/*", "**/samples/**/*"],
+            Category::Fixtures => &["**/fixtures/**/*", "**/testdata/**/*"],
+        }
+    }
+}
+
+impl std::str::FromStr for Category {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Category::ALL.iter().find(|c| c.name() == s).copied().ok_or_else(|| {
+            format!(
+                "'{}' is not one of {}",
+                s,
+                Category::ALL.iter().map(|c| c.name()).collect::<Vec<_>>().join(", ")
+            )
+        })
+    }
+}
+
+/// The version of the schema of `CrateApi`, which changes only if fields are removed or change their meaning
+pub const API_SCHEMA_VERSION: u32 = 1;
+
+/// A summary of all versions of a crate for tools consuming reports, whose schema is kept stable
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize)]
+pub struct CrateApi {
+    /// Always `API_SCHEMA_VERSION` at the time of writing
+    pub schema_version: u32,
+    pub crate_name: String,
+    /// The size of all versions together
+    pub total_size_in_bytes: u64,
+    /// The amount of files of all versions together
+    pub total_files: u64,
+    /// A summary of each version by its version number
+    pub versions: Dict<VersionApi>,
+}
+
+/// A summary of a single crate version as part of a `CrateApi`
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize)]
+pub struct VersionApi {
+    pub total_bytes: u64,
+    pub total_files: u64,
+    pub wasted_bytes: u64,
+    pub wasted_files: u64,
+    /// The bytes which wouldn't be published anymore with the suggested fix, if there is one
+    pub potential_gains_bytes: Option<u64>,
+    /// True if the version publishes no wasted files
+    pub is_lean: bool,
+}
+
 #[derive(Debug, PartialEq, Clone, Deserialize, Serialize)]
 pub enum Report {
     Version {
@@ -233,6 +428,11 @@ pub enum Report {
         total_files: u64,
         wasted_files: Vec<WastedFile>,
         suggested_fix: Option<Fix>,
+        #[serde(default)]
+        breakdown: Breakdown,
+        /// How often this version is downloaded per day on average, if known
+        #[serde(default)]
+        downloads_per_day: Option<u64>,
     },
     Crate {
         crate_name: String,
@@ -257,36 +457,61 @@ fn remove_implicit_entries(entries: &mut Vec<TarHeader>) {
 }
 
 impl Report {
-    pub fn from_package(
+    pub fn from_package(crate_name: &str, crate_version: &str, package: TarPackage) -> Report {
+        Self::from_package_keeping(crate_name, crate_version, package, &[])
+    }
+
+    /// Like `from_package()`, but files in one of the `keep` categories are not considered waste and remain included.
+    pub fn from_package_keeping(
         crate_name: &str,
         crate_version: &str,
         TarPackage {
             mut entries_meta_data,
             entries,
         }: TarPackage,
+        keep: &[Category],
     ) -> Report {
         remove_implicit_entries(&mut entries_meta_data);
         let total_size_in_bytes = entries_meta_data.iter().map(|e| e.size).sum();
         let total_files = entries_meta_data.len() as u64;
+        let breakdown = Breakdown::from_entries(&entries_meta_data);
         let cargo_config = Self::cargo_config_from_entries(&entries);
         let (includes, excludes, compile_time_includes, build_script_name) =
             Self::cargo_config_into_includes_excludes(cargo_config, &entries, &entries_meta_data);
+        let keep_patterns: Patterns = keep
+            .iter()
+            .flat_map(|c| c.patterns().iter().map(|p| p.to_string()))
+            .collect();
+        let keep_globs = globset_from_patterns(&keep_patterns);
         let (suggested_fix, wasted_files) = match (includes, excludes, build_script_name, compile_time_includes) {
             (Some(includes), Some(excludes), _presence_of_build_script_not_relevant, _) => {
                 Self::compute_includes_from_includes_and_excludes(entries_meta_data, includes, excludes)
             }
             (Some(includes), None, build_script_name, _) => {
-                Self::enrich_includes(entries_meta_data, includes, build_script_name.is_some())
+                Self::enrich_includes(entries_meta_data, includes, build_script_name.is_some(), &keep_globs)
             }
             (None, Some(excludes), build_script_name, compile_time_includes) => Self::enrich_excludes(
                 entries_meta_data,
                 excludes,
                 compile_time_includes,
                 build_script_name.is_some(),
+                &keep_globs,
+            ),
+            (None, None, build_script_name, compile_time_includes) => Self::standard_includes(
+                entries_meta_data,
+                build_script_name,
+                match (compile_time_includes, keep_patterns.is_empty()) {
+                    (compile_time_includes, true) => compile_time_includes,
+                    (compile_time_includes, false) => Some(
+                        compile_time_includes
+                            .unwrap_or_default()
+                            .into_iter()
+                            .chain(keep_patterns)
+                            .collect(),
+                    ),
+                },
+                &keep_globs,
             ),
-            (None, None, build_script_name, compile_time_includes) => {
-                Self::standard_includes(entries_meta_data, build_script_name, compile_time_includes)
-            }
         };
         let wasted_files = Self::convert_to_wasted_files(wasted_files);
         Report::Version {
@@ -296,6 +521,74 @@ impl Report {
             total_files,
             wasted_files,
             suggested_fix,
+            breakdown,
+            downloads_per_day: None,
+        }
+    }
+
+    /// Set how often a `Report::Version` is downloaded per day, to weigh its waste by it. Other reports are returned as is.
+    pub fn with_downloads_per_day(mut self, downloads: Option<u64>) -> Report {
+        if let Report::Version { downloads_per_day, .. } = &mut self {
+            *downloads_per_day = downloads;
+        }
+        self
+    }
+
+    /// The `Cargo.toml` excerpt fixing the waste of a `Report::Version`, or `None` if there is nothing to fix or it's
+    /// another kind of report.
+    pub fn manifest_excerpt(&self) -> Option<String> {
+        match self {
+            Report::Version {
+                crate_name,
+                crate_version,
+                wasted_files,
+                suggested_fix: Some(fix),
+                ..
+            } => Some(format!(
+                "# Suggested for {} {} to stop publishing {} wasted files ({} bytes)\n{}",
+                crate_name,
+                crate_version,
+                wasted_files.len(),
+                wasted_files.iter().map(|(_, size)| size).sum::<u64>(),
+                fix.to_manifest_excerpt()
+            )),
+            _ => None,
+        }
+    }
+
+    /// The summary of all versions of a `Report::Crate` for tools consuming reports, or `None` if it's another kind
+    /// of report.
+    pub fn crate_api(&self) -> Option<CrateApi> {
+        match self {
+            Report::Crate {
+                crate_name,
+                total_size_in_bytes,
+                total_files,
+                info_by_version,
+                ..
+            } => Some(CrateApi {
+                schema_version: API_SCHEMA_VERSION,
+                crate_name: crate_name.clone(),
+                total_size_in_bytes: *total_size_in_bytes,
+                total_files: *total_files,
+                versions: info_by_version
+                    .iter()
+                    .map(|(version, info)| {
+                        (
+                            version.clone(),
+                            VersionApi {
+                                total_bytes: info.all.total_bytes,
+                                total_files: info.all.total_files,
+                                wasted_bytes: info.waste.total_bytes,
+                                wasted_files: info.waste.total_files,
+                                potential_gains_bytes: info.potential_gains.as_ref().map(|g| g.total_bytes),
+                                is_lean: info.waste.total_files == 0,
+                            },
+                        )
+                    })
+                    .collect(),
+            }),
+            _ => None,
         }
     }
 }
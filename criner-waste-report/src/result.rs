@@ -1,4 +1,7 @@
-use super::{CargoConfig, Fix, Patterns, PotentialWaste, Report, TarHeader, WastedFile};
+use super::{
+    Breakdown, CargoConfig, Category, Fix, Patterns, PotentialWaste, Report, TarHeader, WastedFile, NO_EXT_MARKER,
+    ROOT_DIR_MARKER,
+};
 use std::{collections::BTreeSet, path::Path, path::PathBuf};
 
 lazy_static! {
@@ -14,6 +17,16 @@ lazy_static! {
         .cloned()
         .map(|p| (p, make_glob(p).compile_matcher()))
         .collect();
+    static ref REPOSITORY_CRUFT_GLOBSET: globset::GlobSet = globset_from_patterns(repository_cruft_patterns());
+    static ref REPOSITORY_CRUFT_MATCHERS: Vec<(&'static str, globset::GlobMatcher)> = repository_cruft_patterns()
+        .iter()
+        .cloned()
+        .map(|p| (p, make_glob(p).compile_matcher()))
+        .collect();
+    static ref CATEGORY_GLOBSETS: Vec<(Category, globset::GlobSet)> = CATEGORIES_BY_PRECEDENCE
+        .iter()
+        .map(|c| (*c, globset_from_patterns(c.patterns())))
+        .collect();
     static ref STANDARD_INCLUDE_GLOBS: Vec<globset::Glob> =
         standard_include_patterns().iter().map(|p| make_glob(p)).collect();
     static ref STANDARD_INCLUDE_MATCHERS: Vec<(&'static str, globset::GlobMatcher)> = standard_include_patterns()
@@ -23,12 +36,18 @@ lazy_static! {
         .collect();
 }
 
-pub fn tar_path_to_utf8_str(mut bytes: &[u8]) -> &str {
+/// The path of a tar entry relative to the crate root, or an error if it isn't valid UTF-8.
+pub fn tar_path_to_utf8(mut bytes: &[u8]) -> Result<&str, std::str::Utf8Error> {
     // Tar paths include the parent directory, cut it to crate relative paths
     if let Some(pos) = bytes.iter().position(|b| *b == b'/' || *b == b'\\') {
         bytes = bytes.get(pos + 1..).unwrap_or(bytes);
     }
-    std::str::from_utf8(bytes).expect("valid utf8 paths in crate archive")
+    std::str::from_utf8(bytes)
+}
+
+/// Like `tar_path_to_utf8()`, for paths which are known to be valid UTF-8, like those of extracted crates.
+pub fn tar_path_to_utf8_str(bytes: &[u8]) -> &str {
+    tar_path_to_utf8(bytes).expect("valid utf8 paths in crate archive")
 }
 
 fn tar_path_to_path(bytes: &[u8]) -> &Path {
@@ -81,10 +100,27 @@ fn directories_of(entries: &[TarHeader]) -> Vec<TarHeader> {
             path: k.to_str().expect("utf8 paths").as_bytes().to_owned(),
             size: 0,
             entry_type: tar_directory_entry,
+            encoding: None,
+            truncated_at: None,
         })
         .collect()
 }
 
+/// Fixtures are often in test directories, but are more specific
+const CATEGORIES_BY_PRECEDENCE: [Category; 4] = [
+    Category::Fixtures,
+    Category::Tests,
+    Category::Benches,
+    Category::Examples,
+];
+
+fn category_of(path: &str) -> Option<Category> {
+    CATEGORY_GLOBSETS
+        .iter()
+        .find(|(_, globs)| globs.is_match(path))
+        .map(|(category, _)| *category)
+}
+
 fn standard_exclude_patterns() -> &'static [&'static str] {
     &[
         "**/*.jpg",
@@ -128,6 +164,30 @@ fn standard_exclude_patterns() -> &'static [&'static str] {
     ]
 }
 
+/// Files which are only useful in the repository a crate is developed in, like CI configuration, left-overs of merges
+/// and editor state. Unlike standard excludes, they are waste no matter what else is included.
+fn repository_cruft_patterns() -> &'static [&'static str] {
+    &[
+        ".github/**/*",
+        ".gitlab/**/*",
+        ".gitlab-ci.yml",
+        ".travis.yml",
+        ".circleci/**/*",
+        "appveyor.yml",
+        ".appveyor.yml",
+        "azure-pipelines.yml",
+        ".cirrus.yml",
+        ".idea/**/*",
+        ".vscode/**/*",
+        "**/.DS_Store",
+        "**/*.orig",
+        "**/*.rej",
+        "**/*.swp",
+        "**/*.swo",
+        "**/*~",
+    ]
+}
+
 fn standard_include_patterns() -> &'static [&'static str] {
     &[
         "src/**/*",
@@ -241,8 +301,8 @@ fn find_include_patterns_that_incorporate_exclude_patterns(
                 &pattern,
             );
             removed_include_patterns.push(pattern);
-            added_include_patterns.extend(added_includes.clone().into_iter());
-            all_include_patterns.extend(added_includes.into_iter());
+            added_include_patterns.extend(added_includes.clone());
+            all_include_patterns.extend(added_includes);
         } else {
             all_include_patterns.push(pattern);
         }
@@ -506,6 +566,34 @@ fn non_greedy_patterns<S: AsRef<str>>(patterns: impl IntoIterator<Item = S>) ->
         .filter(|p| !p.as_ref().starts_with('*') && p.as_ref().ends_with('*'))
 }
 
+impl Breakdown {
+    /// Aggregate the sizes of all files among `entries` by extension, by top-level directory and by category
+    pub fn from_entries(entries: &[TarHeader]) -> Breakdown {
+        let mut breakdown = Breakdown::default();
+        for e in entries.iter().filter(|e| entry_is_file(e.entry_type)) {
+            let path = tar_path_to_utf8_str(&e.path);
+            let extension = Path::new(path)
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .unwrap_or(NO_EXT_MARKER);
+            let directory = path.split_once('/').map_or(ROOT_DIR_MARKER, |(directory, _)| directory);
+            let category = category_of(path).map(|c| c.name());
+            for (map, key) in [
+                (&mut breakdown.by_extension, Some(extension)),
+                (&mut breakdown.by_directory, Some(directory)),
+                (&mut breakdown.by_category, category),
+            ] {
+                if let Some(key) = key {
+                    let info = map.entry(key.to_owned()).or_default();
+                    info.total_bytes += e.size;
+                    info.total_files += 1;
+                }
+            }
+        }
+        breakdown
+    }
+}
+
 impl Report {
     pub(crate) fn cargo_config_from_entries(entries: &[(TarHeader, Vec<u8>)]) -> CargoConfig {
         find_in_entries(entries, &[], "Cargo.toml")
@@ -524,6 +612,7 @@ impl Report {
         entries: Vec<TarHeader>,
         build_script_name: Option<String>,
         compile_time_include: Option<Patterns>,
+        keep: &globset::GlobSet,
     ) -> (Option<Fix>, Vec<TarHeader>) {
         let mut compile_time_include = compile_time_include.unwrap_or_default();
         let has_build_script = match build_script_name {
@@ -547,6 +636,7 @@ impl Report {
                 .chain(compile_time_include_matchers.iter()),
             included_entries.clone(),
         );
+        let (_kept, included_entries) = split_to_matched_and_unmatched(included_entries, keep);
         let potential = potential_negated_includes(
             included_entries,
             globset_from_patterns(non_greedy_patterns(&compile_time_include)),
@@ -578,7 +668,7 @@ impl Report {
             split_to_matched_and_unmatched(directories, &exclude_globs);
         let (entries_that_should_be_excluded_by_directory, remaining_entries) =
             split_by_matching_directories(remaining_entries, &directories_that_should_be_excluded);
-        entries_that_should_be_excluded.extend(entries_that_should_be_excluded_by_directory.into_iter());
+        entries_that_should_be_excluded.extend(entries_that_should_be_excluded_by_directory);
 
         let fix = if entries_that_should_be_excluded.is_empty() {
             Some(Fix::RemoveExclude)
@@ -607,6 +697,7 @@ impl Report {
         entries: Vec<TarHeader>,
         mut include: Patterns,
         has_build_script: bool,
+        keep: &globset::GlobSet,
     ) -> (Option<Fix>, Vec<TarHeader>) {
         let mut include_removed = Vec::new();
         remove_implicit_includes(&mut include, &mut include_removed);
@@ -615,6 +706,7 @@ impl Report {
             if include_removed.is_empty() {
                 None
             } else {
+                let (_kept, entries) = split_to_matched_and_unmatched(entries, keep);
                 let potential =
                     potential_negated_includes(entries, globset_from_patterns(non_greedy_patterns(&include)));
                 Some(Fix::ImprovedInclude {
@@ -633,13 +725,26 @@ impl Report {
         exclude: Patterns,
         compile_time_include: Option<Patterns>,
         has_build_script: bool,
+        keep: &globset::GlobSet,
     ) -> (Option<Fix>, Vec<TarHeader>) {
+        let (cruft, entries) = split_to_matched_and_unmatched(entries, &REPOSITORY_CRUFT_GLOBSET);
+        let (_kept, entries) = split_to_matched_and_unmatched(entries, keep);
         let (potential_waste, _remaining) = split_to_matched_and_unmatched(entries, &STANDARD_EXCLUDES_GLOBSET);
-        let (wasted_files, exclude, exclude_added) = simplify_standard_excludes_and_match_against_standard_includes(
-            potential_waste,
-            exclude,
-            compile_time_include,
-        );
+        let (mut wasted_files, mut exclude, mut exclude_added) =
+            simplify_standard_excludes_and_match_against_standard_includes(
+                potential_waste,
+                exclude,
+                compile_time_include,
+            );
+        for (pattern, glob) in REPOSITORY_CRUFT_MATCHERS.iter() {
+            if cruft.iter().any(|e| glob.is_match(tar_path_to_utf8_str(&e.path)))
+                && !exclude.iter().any(|p| p == pattern)
+            {
+                exclude.push((*pattern).to_string());
+                exclude_added.push((*pattern).to_string());
+            }
+        }
+        wasted_files.extend(cruft);
         if wasted_files.is_empty() {
             (None, Vec::new())
         } else {
@@ -0,0 +1,79 @@
+use super::super::{AggregateFileInfo, CrateApi, Report, TarPackage, VersionApi, VersionInfo, API_SCHEMA_VERSION};
+
+#[test]
+fn crate_reports_summarize_each_version() {
+    let info = |total_bytes, wasted_bytes, wasted_files| VersionInfo {
+        all: AggregateFileInfo {
+            total_bytes,
+            total_files: 4,
+        },
+        waste: AggregateFileInfo {
+            total_bytes: wasted_bytes,
+            total_files: wasted_files,
+        },
+        potential_gains: Some(AggregateFileInfo {
+            total_bytes: wasted_bytes,
+            total_files: wasted_files,
+        })
+        .filter(|_| wasted_files != 0),
+        ..Default::default()
+    };
+    let report = Report::Crate {
+        crate_name: "a".into(),
+        total_size_in_bytes: 300,
+        total_files: 8,
+        info_by_version: vec![
+            ("1.0.0".to_owned(), info(200, 50, 1)),
+            ("1.1.0".to_owned(), info(100, 0, 0)),
+        ]
+        .into_iter()
+        .collect(),
+        wasted_by_extension: Default::default(),
+    };
+    let api = report.crate_api().expect("crate reports have a summary");
+    assert_eq!(
+        api,
+        CrateApi {
+            schema_version: API_SCHEMA_VERSION,
+            crate_name: "a".into(),
+            total_size_in_bytes: 300,
+            total_files: 8,
+            versions: vec![
+                (
+                    "1.0.0".to_owned(),
+                    VersionApi {
+                        total_bytes: 200,
+                        total_files: 4,
+                        wasted_bytes: 50,
+                        wasted_files: 1,
+                        potential_gains_bytes: Some(50),
+                        is_lean: false,
+                    }
+                ),
+                (
+                    "1.1.0".to_owned(),
+                    VersionApi {
+                        total_bytes: 100,
+                        total_files: 4,
+                        wasted_bytes: 0,
+                        wasted_files: 0,
+                        potential_gains_bytes: None,
+                        is_lean: true,
+                    }
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        }
+    );
+
+    let version = Report::from_package(
+        "a",
+        "1.0.0",
+        TarPackage {
+            entries_meta_data: Vec::new(),
+            entries: Vec::new(),
+        },
+    );
+    assert_eq!(version.crate_api(), None, "only crates have a summary");
+}
@@ -0,0 +1,71 @@
+use super::super::{AggregateFileInfo, Breakdown, Report, TarHeader, TarPackage, NO_EXT_MARKER, ROOT_DIR_MARKER};
+
+fn file(path: &str, size: u64) -> TarHeader {
+    TarHeader {
+        path: format!("a-1.0.0/{}", path).into_bytes(),
+        size,
+        entry_type: b'0',
+        encoding: None,
+        truncated_at: None,
+    }
+}
+
+fn info(total_bytes: u64, total_files: u64) -> AggregateFileInfo {
+    AggregateFileInfo {
+        total_bytes,
+        total_files,
+    }
+}
+
+#[test]
+fn sizes_by_extension_and_top_level_directory() {
+    let entries_meta_data = vec![
+        file("Cargo.toml", 100),
+        file("README", 10),
+        file("src/lib.rs", 50),
+        file("src/bin/tool.rs", 20),
+        file("tests/data.tar.gz", 1000),
+        file(".cargo_vcs_info.json", 5),
+        TarHeader {
+            path: b"a-1.0.0/src".to_vec(),
+            size: 0,
+            entry_type: b'5',
+            encoding: None,
+            truncated_at: None,
+        },
+    ];
+    let report = Report::from_package(
+        "a",
+        "1.0.0",
+        TarPackage {
+            entries_meta_data,
+            entries: Vec::new(),
+        },
+    );
+    let breakdown = match report {
+        Report::Version { breakdown, .. } => breakdown,
+        _ => unreachable!("packages always produce version reports"),
+    };
+    assert_eq!(
+        breakdown,
+        Breakdown {
+            by_extension: vec![
+                (NO_EXT_MARKER.into(), info(10, 1)),
+                ("gz".into(), info(1000, 1)),
+                ("rs".into(), info(70, 2)),
+                ("toml".into(), info(100, 1)),
+            ]
+            .into_iter()
+            .collect(),
+            by_directory: vec![
+                (ROOT_DIR_MARKER.into(), info(110, 2)),
+                ("src".into(), info(70, 2)),
+                ("tests".into(), info(1000, 1)),
+            ]
+            .into_iter()
+            .collect(),
+            by_category: vec![("tests".into(), info(1000, 1))].into_iter().collect(),
+        },
+        "implicit files like .cargo_vcs_info.json and directories are not counted"
+    );
+}
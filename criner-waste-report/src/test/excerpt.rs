@@ -0,0 +1,53 @@
+use super::super::{Fix, Report, TarHeader, TarPackage};
+
+#[test]
+fn excerpts_parse_as_manifest_with_the_suggested_patterns() {
+    let fix = Fix::NewInclude {
+        include: vec!["src/**/*".into(), "Cargo.toml".into(), "odd \"name\".rs".into()],
+        has_build_script: true,
+    };
+    let excerpt = fix.to_manifest_excerpt();
+    assert!(excerpt.contains("build script"), "build scripts are pointed out");
+    let manifest: toml::Value = toml::from_str(&excerpt).expect("valid toml");
+    assert_eq!(
+        manifest["package"]["include"],
+        toml::Value::Array(vec!["src/**/*".into(), "Cargo.toml".into(), "odd \"name\".rs".into()])
+    );
+
+    let excerpt = Fix::EnrichedExclude {
+        exclude: vec!["tests/**/*".into()],
+        exclude_added: vec!["tests/**/*".into()],
+        has_build_script: false,
+    }
+    .to_manifest_excerpt();
+    let manifest: toml::Value = toml::from_str(&excerpt).expect("valid toml");
+    assert_eq!(
+        manifest["package"]["exclude"],
+        toml::Value::Array(vec!["tests/**/*".into()])
+    );
+    assert!(!excerpt.contains("build script"));
+}
+
+#[test]
+fn only_versions_with_a_fix_have_an_excerpt() {
+    let package = |paths: &[&str]| TarPackage {
+        entries_meta_data: paths
+            .iter()
+            .map(|path| TarHeader {
+                path: format!("a-1.0.0/{}", path).into_bytes(),
+                size: 10,
+                entry_type: b'0',
+                encoding: None,
+                truncated_at: None,
+            })
+            .collect(),
+        entries: Vec::new(),
+    };
+    let report = Report::from_package("a", "1.0.0", package(&["Cargo.toml", "src/lib.rs", "tests/a.rs"]));
+    let excerpt = report.manifest_excerpt().expect("tests are waste");
+    assert!(excerpt.starts_with("# Suggested for a 1.0.0 to stop publishing 1 wasted files (10 bytes)\n[package]\n"));
+    toml::from_str::<toml::Value>(&excerpt).expect("valid toml");
+
+    let report = Report::from_package("a", "1.0.0", package(&["Cargo.toml", "src/lib.rs"]));
+    assert_eq!(report.manifest_excerpt(), None, "lean crates need no fix");
+}
@@ -0,0 +1,50 @@
+use super::super::{html::TOP_OFFENDERS, AggregateFileInfo, Report, VersionInfo};
+use horrorshow::Template;
+
+fn info(wasted_bytes: u64) -> VersionInfo {
+    VersionInfo {
+        all: AggregateFileInfo {
+            total_bytes: 1000,
+            total_files: 10,
+        },
+        waste: AggregateFileInfo {
+            total_bytes: wasted_bytes,
+            total_files: 1,
+        },
+        ..Default::default()
+    }
+}
+
+#[test]
+fn crate_pages_show_the_waste_across_all_versions() {
+    let page = Report::Crate {
+        crate_name: "a".into(),
+        total_size_in_bytes: 2000,
+        total_files: 20,
+        info_by_version: vec![("1.0.0".to_owned(), info(100)), ("1.1.0".to_owned(), info(200))]
+            .into_iter()
+            .collect(),
+        wasted_by_extension: Default::default(),
+    }
+    .into_string()
+    .unwrap();
+    assert!(page.contains("300 B wasted in 2 files across 2 versions"), "{}", page);
+}
+
+#[test]
+fn the_top_level_page_lists_only_the_top_offenders() {
+    let page = Report::CrateCollection {
+        total_size_in_bytes: 0,
+        total_files: 0,
+        info_by_crate: (0..=TOP_OFFENDERS as u64)
+            .map(|idx| (format!("crate-{:03}", idx), info(idx + 1)))
+            .collect(),
+        wasted_by_extension: Default::default(),
+    }
+    .into_string()
+    .unwrap();
+    assert!(page.contains("Top 100 of 101 crates"), "{}", page);
+    assert!(page.contains("1 more with less waste aren't listed"));
+    assert!(page.contains("href=\"crate-100\""), "the greatest offender is listed");
+    assert!(!page.contains("href=\"crate-000\""), "the least offender is not");
+}
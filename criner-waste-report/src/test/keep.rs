@@ -0,0 +1,74 @@
+use super::super::{Category, Fix, Report, TarHeader, TarPackage};
+
+fn package(paths: &[&str]) -> TarPackage {
+    TarPackage {
+        entries_meta_data: paths
+            .iter()
+            .map(|path| TarHeader {
+                path: format!("a-1.0.0/{}", path).into_bytes(),
+                size: 10,
+                entry_type: b'0',
+                encoding: None,
+                truncated_at: None,
+            })
+            .collect(),
+        entries: Vec::new(),
+    }
+}
+
+fn waste_and_fix(report: Report) -> (Vec<String>, Option<Fix>) {
+    match report {
+        Report::Version {
+            wasted_files,
+            suggested_fix,
+            ..
+        } => (wasted_files.into_iter().map(|(path, _)| path).collect(), suggested_fix),
+        _ => unreachable!("packages always produce version reports"),
+    }
+}
+
+#[test]
+fn kept_categories_are_not_waste_and_remain_included() {
+    let paths = [
+        "Cargo.toml",
+        "src/lib.rs",
+        "tests/a.rs",
+        "tests/b.rs",
+        "tests/fixtures/data.bin",
+        "examples/demo.rs",
+    ];
+    let (waste, _) = waste_and_fix(Report::from_package("a", "1.0.0", package(&paths)));
+    assert_eq!(
+        waste,
+        vec![
+            "tests/a.rs",
+            "tests/b.rs",
+            "tests/fixtures/data.bin",
+            "examples/demo.rs"
+        ],
+        "by default, all categories are waste"
+    );
+
+    let (waste, fix) = waste_and_fix(Report::from_package_keeping(
+        "a",
+        "1.0.0",
+        package(&paths),
+        &[Category::Tests],
+    ));
+    assert_eq!(waste, vec!["examples/demo.rs"], "fixtures within tests are kept too");
+    assert_eq!(
+        fix,
+        Some(Fix::NewInclude {
+            include: vec!["src/lib.rs".into(), "**/tests/**/*".into()],
+            has_build_script: false
+        })
+    );
+}
+
+#[test]
+fn categories_parse_from_their_names() {
+    for category in Category::ALL.iter() {
+        assert_eq!(category.name().parse::<Category>(), Ok(*category));
+    }
+    assert!("docs".parse::<Category>().is_err());
+}
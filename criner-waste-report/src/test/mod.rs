@@ -1 +1,6 @@
+mod api;
+mod breakdown;
+mod excerpt;
 mod from_package;
+mod html;
+mod keep;
@@ -0,0 +1,34 @@
+use criner::{
+    bench::{DbFixture, Operation},
+    run::Durability,
+};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+const OPERATIONS: usize = 200;
+
+fn table_access(c: &mut Criterion) {
+    let path = std::env::temp_dir().join(format!("criner-criterion-{}.db", std::process::id()));
+    let fixture = DbFixture::new(path, Durability::Relaxed, 1000).expect("database to be populated");
+    let mut group = c.benchmark_group("table-access");
+    group.throughput(Throughput::Elements(OPERATIONS as u64));
+    group.sample_size(10);
+    for operation in Operation::all() {
+        for threads in [1, 4].iter() {
+            group.bench_with_input(
+                BenchmarkId::new(operation.to_string(), threads),
+                threads,
+                |b, threads| {
+                    b.iter(|| {
+                        fixture
+                            .run(*operation, *threads, OPERATIONS)
+                            .expect("operations to succeed")
+                    })
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, table_access);
+criterion_main!(benches);
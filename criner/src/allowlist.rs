@@ -0,0 +1,79 @@
+//! Limiting mining to a subset of crates, like those in the dependency tree of a company.
+//!
+//! Allowlists name one crate per line, optionally followed by a version requirement in the syntax of `Cargo.toml`,
+//! like `serde` for all versions or `tokio >=1.0, <2` for some of them. Empty lines and lines starting with `#` are
+//! ignored, and the requirements of crates listed more than once add up.
+use crate::{
+    registry::split_crate_name,
+    resolve::{parse_version, VersionReq},
+    Error, Result,
+};
+use std::{collections::HashMap, path::Path};
+
+#[derive(Debug, Clone, Default)]
+pub struct Allowlist {
+    /// Requirements by crate name, with `None` allowing all versions, including pre-releases
+    crates: HashMap<String, Option<Vec<VersionReq>>>,
+}
+
+impl std::str::FromStr for Allowlist {
+    type Err = Error;
+
+    fn from_str(content: &str) -> Result<Self> {
+        let mut crates: HashMap<String, Option<Vec<VersionReq>>> = HashMap::new();
+        for (line_number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, requirement) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let requirement = match requirement.trim() {
+                "" => None,
+                requirement => Some(requirement.parse::<VersionReq>().map_err(|err| {
+                    Error::Message(format!("Invalid requirement in line {}: {}", line_number + 1, err))
+                })?),
+            };
+            let requirements = crates.entry(name.to_owned()).or_insert_with(|| Some(Vec::new()));
+            match (requirements, requirement) {
+                (Some(requirements), Some(requirement)) => requirements.push(requirement),
+                (requirements, None) => *requirements = None,
+                (None, Some(_)) => {}
+            }
+        }
+        Ok(Allowlist { crates })
+    }
+}
+
+impl Allowlist {
+    /// Read the allowlist in the file at `path`
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Allowlist> {
+        let path = path.as_ref();
+        std::fs::read_to_string(path)?
+            .parse()
+            .map_err(|err| Error::Message(format!("{}: {}", path.display(), err)))
+    }
+
+    /// The amount of crates in the allowlist
+    pub fn len(&self) -> usize {
+        self.crates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.crates.is_empty()
+    }
+
+    /// Returns true if `version` of the crate named `name` should be mined. `name` may be qualified with its registry.
+    /// Versions which aren't valid semantic versions are only mined if all versions of their crate are.
+    pub fn contains(&self, name: &str, version: &str) -> bool {
+        match self.crates.get(split_crate_name(name).1) {
+            None => false,
+            Some(None) => true,
+            Some(Some(requirements)) => {
+                parse_version(version).is_ok_and(|version| requirements.iter().any(|r| r.matches(&version)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod allowlist_test;
@@ -0,0 +1,41 @@
+use crate::allowlist::Allowlist;
+
+#[test]
+fn crates_are_allowed_by_name_and_requirement() {
+    let allowlist: Allowlist = "
+        # the dependency tree of our service
+        serde
+        tokio >=1.0, <2
+        tokio 0.2
+        rand 0.8
+        rand
+    "
+    .parse()
+    .unwrap();
+    assert_eq!(allowlist.len(), 3);
+
+    assert!(allowlist.contains("serde", "1.0.104"));
+    assert!(
+        allowlist.contains("serde", "2.0.0-alpha"),
+        "all versions without requirement"
+    );
+    assert!(allowlist.contains("acme/serde", "1.0.0"), "names may be qualified");
+    assert!(!allowlist.contains("serde_json", "1.0.0"));
+
+    assert!(allowlist.contains("tokio", "1.25.0"));
+    assert!(allowlist.contains("tokio", "0.2.22"), "requirements add up");
+    assert!(!allowlist.contains("tokio", "0.3.0"));
+    assert!(!allowlist.contains("tokio", "2.0.0"));
+    assert!(!allowlist.contains("tokio", "not-semver"));
+
+    assert!(
+        allowlist.contains("rand", "0.3.0"),
+        "a line without requirement allows all versions"
+    );
+}
+
+#[test]
+fn invalid_requirements_name_their_line() {
+    let err = "serde\ntokio >=x".parse::<Allowlist>().unwrap_err();
+    assert!(err.to_string().contains("line 2"), "{}", err);
+}
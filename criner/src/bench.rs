@@ -0,0 +1,185 @@
+use crate::{
+    model,
+    persistence::{BackendKind, Db, Durability, TableAccess, TaskTable},
+    Result,
+};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+/// An operation of the table access layer to measure
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Get,
+    Update,
+    Upsert,
+}
+
+impl Operation {
+    pub fn all() -> &'static [Operation] {
+        &[Operation::Get, Operation::Update, Operation::Upsert]
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Operation::Get => "get",
+            Operation::Update => "update",
+            Operation::Upsert => "upsert",
+        })
+    }
+}
+
+/// A database pre-populated with tasks whose keys and values resemble the ones written during mining
+pub struct DbFixture {
+    db: Db,
+    path: PathBuf,
+    keys: Vec<String>,
+    task: model::Task,
+}
+
+impl DbFixture {
+    /// Create the database at `path` and fill it with `num_keys` tasks
+    pub fn new(path: impl AsRef<Path>, durability: Durability, num_keys: usize) -> Result<DbFixture> {
+        Self::new_with_backend(path, durability, BackendKind::default(), num_keys)
+    }
+
+    /// Like `new()`, but storing the items of tables in `backend`
+    pub fn new_with_backend(
+        path: impl AsRef<Path>,
+        durability: Durability,
+        backend: BackendKind,
+        num_keys: usize,
+    ) -> Result<DbFixture> {
+        let db = Db::open_with_backend(&path, durability, backend)?;
+        let mut task = crate::engine::work::iobound::default_persisted_download_task();
+        task.state = model::TaskState::AttemptsWithFailure(vec![
+            "HTTP status 503 Service Unavailable while fetching https://crates.io/api/v1/crates/some-crate/0.1.0/download".into(),
+            "Timeout after 10s".into(),
+        ]);
+
+        let root = prodash::Tree::new();
+        let mut progress = root.add_child("populate");
+        let tasks = db.open_tasks()?;
+        let mut keys = Vec::with_capacity(num_keys);
+        for id in 0..num_keys {
+            let mut key = String::new();
+            task.fq_key(
+                &format!("some-crate-{}", id / 10),
+                &format!("0.{}.0", id % 10),
+                &mut key,
+            );
+            tasks.insert(&mut progress, &key, &task)?;
+            keys.push(key);
+        }
+        Ok(DbFixture {
+            db,
+            path: path.as_ref().to_owned(),
+            keys,
+            task,
+        })
+    }
+
+    /// Run `operations` of the given kind spread across `threads`, each with its own connection,
+    /// and return the time it took for all of them to finish.
+    pub fn run(&self, operation: Operation, threads: usize, operations: usize) -> Result<Duration> {
+        let threads = threads.max(1);
+        let start = SystemTime::now();
+        std::thread::scope(|s| {
+            let handles: Vec<_> = (0..threads)
+                .map(|thread_id| {
+                    s.spawn(move || -> Result<()> {
+                        let inner = self.db.open_connection_with_busy_wait()?;
+                        let tasks = TaskTable {
+                            backend: self.db.table_backend(&inner),
+                            inner,
+                        };
+                        let root = prodash::Tree::new();
+                        let mut progress = root.add_child("bench");
+                        for op in (thread_id..operations).step_by(threads) {
+                            let key = &self.keys[op % self.keys.len()];
+                            match operation {
+                                Operation::Get => {
+                                    tasks.get(key)?;
+                                }
+                                Operation::Update => {
+                                    tasks.update(Some(&mut progress), key, |mut t| {
+                                        t.stored_at = SystemTime::now();
+                                        t
+                                    })?;
+                                }
+                                Operation::Upsert => {
+                                    tasks.upsert(&mut progress, key, &self.task)?;
+                                }
+                            }
+                        }
+                        Ok(())
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|h| h.join().expect("no panic in benchmark thread"))
+                .collect::<Result<Vec<_>>>()
+        })?;
+        Ok(start.elapsed()?)
+    }
+}
+
+impl Drop for DbFixture {
+    fn drop(&mut self) {
+        std::fs::remove_dir_all(&self.path).ok();
+    }
+}
+
+pub struct DbOptions {
+    pub durability: Durability,
+    pub backend: BackendKind,
+    /// The amount of distinct keys to operate on
+    pub keys: usize,
+    /// The amount of operations per kind and amount of threads
+    pub operations: usize,
+    /// The maximum amount of threads, starting at one and doubling until reached
+    pub max_threads: usize,
+}
+
+pub struct DbMeasurement {
+    pub operation: Operation,
+    pub threads: usize,
+    pub operations_per_second: f64,
+}
+
+impl fmt::Display for DbMeasurement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:<8} {:>3} threads {:>10.0} ops/s",
+            self.operation, self.threads, self.operations_per_second
+        )
+    }
+}
+
+/// Measure the throughput of all table operations with increasing contention, using a temporary database
+pub fn db_blocking(options: DbOptions) -> Result<Vec<DbMeasurement>> {
+    let path = std::env::temp_dir().join(format!("criner-bench-{}.db", std::process::id()));
+    let fixture = DbFixture::new_with_backend(path, options.durability, options.backend, options.keys)?;
+    let mut measurements = Vec::new();
+    for operation in Operation::all() {
+        let mut threads = 1;
+        while threads <= options.max_threads.max(1) {
+            let elapsed = fixture.run(*operation, threads, options.operations)?;
+            let measurement = DbMeasurement {
+                operation: *operation,
+                threads,
+                operations_per_second: options.operations as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+            };
+            log::info!("{}", measurement);
+            measurements.push(measurement);
+            threads *= 2;
+        }
+    }
+    Ok(measurements)
+}
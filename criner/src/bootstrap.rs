@@ -0,0 +1,198 @@
+use crate::{persistence, registry::Registry, Error, Result};
+use bytesize::ByteSize;
+use sha2::{Digest, Sha256};
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// Log download progress every time this many bytes were received
+const LOG_EVERY_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Configures where to get a database snapshot from and where to put it
+pub struct Options {
+    /// The URL of a `db.msgpack.sqlite` file as published with `criner publish-snapshot`
+    pub url: String,
+    /// The expected sha256 of the snapshot as hex string. If unset, it is read from `<url>.sha256`.
+    pub sha256: Option<String>,
+    /// The database directory to place the snapshot into. It must not contain a database yet.
+    pub db_path: PathBuf,
+    /// If set, only the crates of this registry are kept of the snapshot
+    pub registry: Option<Registry>,
+}
+
+/// The first word of a `sha256sum` style checksum file
+fn parse_checksum_file(text: &str) -> Option<String> {
+    text.split_whitespace()
+        .next()
+        .filter(|hex| hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit()))
+        .map(str::to_ascii_lowercase)
+}
+
+async fn fetch_checksum(client: &reqwest::Client, url: &str) -> Result<String> {
+    let checksum_url = format!("{}.sha256", url);
+    let response = client.get(&checksum_url).send().await?;
+    if !response.status().is_success() {
+        return Err(Error::HttpStatus(response.status()));
+    }
+    parse_checksum_file(&response.text().await?).ok_or_else(|| {
+        Error::Protocol(format!(
+            "'{}' does not contain a sha256 checksum - use --sha256 to provide it",
+            checksum_url
+        ))
+    })
+}
+
+/// The first byte of a `Content-Range` header like `bytes 100-199/200`
+fn content_range_start(value: &str) -> Option<u64> {
+    value.strip_prefix("bytes ")?.split('-').next()?.trim().parse().ok()
+}
+
+/// Download `url` into `out`, resuming a previous partial download if the server supports it
+async fn download_resumable(client: &reqwest::Client, url: &str, out: &Path) -> Result<()> {
+    let start_byte = std::fs::metadata(out).map(|m| m.len()).unwrap_or(0);
+    let mut response = client
+        .get(url)
+        .header(http::header::RANGE, format!("bytes={}-", start_byte))
+        .send()
+        .await?;
+    let append = match response.status().as_u16() {
+        206 => {
+            let range = response
+                .headers()
+                .get(http::header::CONTENT_RANGE)
+                .and_then(|value| value.to_str().ok());
+            if range.and_then(content_range_start) != Some(start_byte) {
+                return Err(Error::Protocol(format!(
+                    "Asked for '{}' from byte {}, but got content range {} - remove '{}' to download it from scratch",
+                    url,
+                    start_byte,
+                    range.unwrap_or("<none>"),
+                    out.display()
+                )));
+            }
+            true
+        }
+        200 => {
+            if start_byte != 0 {
+                log::info!("Server ignored range request, restarting download");
+            }
+            false
+        }
+        416 if start_byte != 0 => {
+            log::info!("Snapshot was downloaded completely before");
+            return Ok(());
+        }
+        _ => return Err(Error::HttpStatus(response.status())),
+    };
+    let mut received = if append { start_byte } else { 0 };
+    let total = response.content_length().map(|remaining| received + remaining);
+    log::info!(
+        "Downloading {} of {}{}",
+        url,
+        total
+            .map(|b| ByteSize(b).to_string())
+            .unwrap_or_else(|| "unknown size".into()),
+        if append {
+            format!(", resuming at {}", ByteSize(start_byte))
+        } else {
+            String::new()
+        }
+    );
+
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(out)
+        .map_err(|err| Error::Storage(format!("Failed to open '{}': {}", out.display(), err)))?;
+    let mut next_log_at = received + LOG_EVERY_BYTES;
+    while let Some(chunk) = response.chunk().await? {
+        file.write_all(&chunk)?;
+        received += chunk.len() as u64;
+        if received >= next_log_at {
+            log::info!("Received {}", ByteSize(received));
+            next_log_at = received + LOG_EVERY_BYTES;
+        }
+    }
+    file.sync_all()?;
+    Ok(())
+}
+
+/// Download a database snapshot, verify its checksum and integrity and make it the database at `db_path`.
+/// Interrupted downloads are resumed when running it again.
+pub fn run_blocking(options: Options) -> Result<()> {
+    let Options {
+        url,
+        sha256,
+        db_path,
+        registry,
+    } = options;
+    let sqlite_path = db_path.join(persistence::SQLITE_FILE_NAME);
+    if sqlite_path.is_file() {
+        return Err(Error::Message(format!(
+            "A database does already exist at '{}' - bootstrapping is only possible for new databases",
+            sqlite_path.display()
+        )));
+    }
+    std::fs::create_dir_all(&db_path)?;
+    let partial_path = db_path.join(format!("{}.part", persistence::SQLITE_FILE_NAME));
+
+    let client = reqwest::ClientBuilder::new()
+        .user_agent(concat!("criner/", env!("CARGO_PKG_VERSION")))
+        .build()?;
+    let expected = futures_lite::future::block_on(crate::spawn({
+        let partial_path = partial_path.clone();
+        async move {
+            let expected = match sha256 {
+                Some(hex) => hex.to_ascii_lowercase(),
+                None => fetch_checksum(&client, &url).await?,
+            };
+            download_resumable(&client, &url, &partial_path).await?;
+            Ok::<_, Error>(expected)
+        }
+    }))?;
+
+    log::info!("Verifying checksum");
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut std::fs::File::open(&partial_path)?, &mut hasher)?;
+    let actual = hex::encode(hasher.finalize());
+    if actual != expected {
+        std::fs::remove_file(&partial_path)?;
+        return Err(Error::ChecksumMismatch(expected, actual));
+    }
+
+    log::info!("Checking database integrity");
+    let integrity: String =
+        rusqlite::Connection::open(&partial_path)?.query_row("PRAGMA quick_check", [], |r| r.get(0))?;
+    if integrity != "ok" {
+        return Err(Error::Corrupt(format!(
+            "The snapshot at '{}' failed the integrity check: {}",
+            partial_path.display(),
+            integrity
+        )));
+    }
+    std::fs::rename(&partial_path, &sqlite_path)?;
+    let db = persistence::Db::open(&db_path)?;
+    if let Some(registry) = registry {
+        let mut connection = db.open_connection_no_async_with_busy_wait()?;
+        let transaction = connection.transaction()?;
+        let removed = registry.remove_other_registries(&transaction)?;
+        transaction.commit()?;
+        connection.execute_batch("VACUUM")?;
+        log::info!(
+            "Removed {} rows about crates of registries other than '{}'",
+            removed,
+            registry.name
+        );
+    }
+    log::info!(
+        "Database ready at '{}' - run 'criner mine' to fetch what changed since the snapshot was taken",
+        db_path.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod bootstrap_test;
@@ -0,0 +1,149 @@
+use super::{content_range_start, download_resumable, parse_checksum_file};
+use crate::{persistence::temp_db::TempDb, Error};
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    path::Path,
+};
+
+/// Answer a single request with `response` and return the URL to request along with a receiver for the request headers
+fn serve_once(response: &'static str) -> (String, std::sync::mpsc::Receiver<Vec<String>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/db.msgpack.sqlite", listener.local_addr().unwrap());
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let mut stream = listener.incoming().next().unwrap().unwrap();
+        let headers: Vec<String> = BufReader::new(&stream)
+            .lines()
+            .map(Result::unwrap)
+            .take_while(|line| !line.is_empty())
+            .collect();
+        stream.write_all(response.as_bytes()).unwrap();
+        tx.send(headers).unwrap();
+    });
+    (url, rx)
+}
+
+fn download(response: &'static str, out: &Path) -> (crate::Result<()>, Option<String>) {
+    let (url, headers) = serve_once(response);
+    let client = reqwest::Client::new();
+    let res = futures_lite::future::block_on(crate::spawn({
+        let out = out.to_owned();
+        async move { download_resumable(&client, &url, &out).await }
+    }));
+    let range = headers
+        .recv()
+        .unwrap()
+        .into_iter()
+        .find(|header| header.to_ascii_lowercase().starts_with("range:"));
+    (res, range)
+}
+
+#[test]
+fn checksum_files_are_parsed_like_sha256sum_writes_them() {
+    let hex = "A".repeat(64);
+    assert_eq!(
+        parse_checksum_file(&format!("{}  db.msgpack.sqlite\n", hex)),
+        Some("a".repeat(64)),
+        "checksums are normalized to lower case"
+    );
+    assert_eq!(
+        parse_checksum_file(&format!("\n{}\n", "0".repeat(64))),
+        Some("0".repeat(64))
+    );
+    assert_eq!(parse_checksum_file(""), None);
+    assert_eq!(parse_checksum_file(&"0".repeat(63)), None, "too short");
+    assert_eq!(parse_checksum_file(&"g".repeat(64)), None, "not hex");
+    assert_eq!(parse_checksum_file("<html>Not Found</html>"), None);
+}
+
+#[test]
+fn content_ranges_are_parsed_for_their_first_byte() {
+    assert_eq!(content_range_start("bytes 100-199/200"), Some(100));
+    assert_eq!(content_range_start("bytes 0-0/*"), Some(0));
+    assert_eq!(content_range_start("bytes */200"), None);
+    assert_eq!(content_range_start("items 1-2/3"), None);
+}
+
+#[test]
+fn downloads_start_from_scratch_without_partial_file() {
+    let db = TempDb::new("bootstrap-new");
+    let out = db.dir.join("db.part");
+    let (res, range) = download(
+        "HTTP/1.1 200 OK\r\ncontent-length: 5\r\nconnection: close\r\n\r\nhello",
+        &out,
+    );
+    res.unwrap();
+    assert_eq!(range.as_deref(), Some("range: bytes=0-"));
+    assert_eq!(std::fs::read(&out).unwrap(), b"hello");
+}
+
+#[test]
+fn partial_downloads_are_resumed() {
+    let db = TempDb::new("bootstrap-resume");
+    let out = db.dir.join("db.part");
+    std::fs::write(&out, "hel").unwrap();
+    let (res, range) = download(
+        "HTTP/1.1 206 Partial Content\r\ncontent-range: bytes 3-4/5\r\ncontent-length: 2\r\nconnection: close\r\n\r\nlo",
+        &out,
+    );
+    res.unwrap();
+    assert_eq!(range.as_deref(), Some("range: bytes=3-"));
+    assert_eq!(std::fs::read(&out).unwrap(), b"hello");
+}
+
+#[test]
+fn servers_ignoring_the_range_restart_the_download() {
+    let db = TempDb::new("bootstrap-restart");
+    let out = db.dir.join("db.part");
+    std::fs::write(&out, "stale data").unwrap();
+    let (res, _range) = download(
+        "HTTP/1.1 200 OK\r\ncontent-length: 5\r\nconnection: close\r\n\r\nhello",
+        &out,
+    );
+    res.unwrap();
+    assert_eq!(std::fs::read(&out).unwrap(), b"hello", "the partial file is replaced");
+}
+
+#[test]
+fn unsatisfiable_ranges_mean_the_download_is_complete() {
+    let db = TempDb::new("bootstrap-complete");
+    let out = db.dir.join("db.part");
+    std::fs::write(&out, "hello").unwrap();
+    let (res, _range) = download(
+        "HTTP/1.1 416 Range Not Satisfiable\r\ncontent-range: bytes */5\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+        &out,
+    );
+    res.unwrap();
+    assert_eq!(std::fs::read(&out).unwrap(), b"hello");
+
+    let empty = db.dir.join("empty.part");
+    let (res, _range) = download(
+        "HTTP/1.1 416 Range Not Satisfiable\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+        &empty,
+    );
+    assert!(
+        matches!(res, Err(Error::HttpStatus(status)) if status.as_u16() == 416),
+        "without anything downloaded, there is nothing to be complete"
+    );
+}
+
+#[test]
+fn partial_content_not_starting_where_the_download_stopped_is_rejected() {
+    let db = TempDb::new("bootstrap-wrong-range");
+    let out = db.dir.join("db.part");
+    std::fs::write(&out, "hel").unwrap();
+    let (res, _range) = download(
+        "HTTP/1.1 206 Partial Content\r\ncontent-range: bytes 0-4/5\r\ncontent-length: 5\r\nconnection: close\r\n\r\nhello",
+        &out,
+    );
+    assert!(matches!(res, Err(Error::Protocol(_))), "{:?}", res);
+    assert_eq!(std::fs::read(&out).unwrap(), b"hel", "nothing is appended");
+
+    let (res, _range) = download(
+        "HTTP/1.1 206 Partial Content\r\ncontent-length: 2\r\nconnection: close\r\n\r\nlo",
+        &out,
+    );
+    assert!(matches!(res, Err(Error::Protocol(_))), "a content range is required");
+    assert_eq!(std::fs::read(&out).unwrap(), b"hel");
+}
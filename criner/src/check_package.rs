@@ -0,0 +1,396 @@
+//! Checking a crate for waste before it's published, the same way the waste report does once it is, and comparing it
+//! with the version published before.
+use crate::{
+    engine::{
+        report::waste::{tar_path_to_utf8, CargoConfig, Category, Report, TarHeader, TarPackage},
+        work::{
+            artifact,
+            cpubound::{default_persisted_extraction_task, meta_data_extraction_task, standard_paths},
+            manifest,
+        },
+    },
+    model::{CrateVersion, TaskResult},
+    persistence::{self, TableAccess},
+    registry::Registry,
+    utils::parse_semver,
+    Error, Result,
+};
+use bytesize::ByteSize;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{Read, Write},
+    path::Path,
+};
+
+/// The amount of bytes of selected files other than the manifest the waste report looks at, like during extraction
+const MAX_SELECTED_FILE_SIZE: usize = 128 * 1024;
+/// New files of at least this size are pointed out when comparing with the previous version
+pub const LARGE_NEW_FILE_SIZE: u64 = 256 * 1024;
+/// Files cargo adds to each package, which say nothing about what a crate includes
+const IMPLICIT_FILES: &[&str] = &[".cargo_vcs_info.json", "Cargo.toml.orig"];
+
+/// Where to find the version published before the checked one
+pub struct Baseline<'a> {
+    /// The database with the crate mined into it
+    pub db_path: &'a Path,
+    /// The registry the crate is published to
+    pub registry: &'a Registry,
+}
+
+/// The outcome of `check_blocking()`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Checked {
+    /// The amount of files which would be published without being needed
+    pub wasted_files: usize,
+    /// The amount of new files which stood out when comparing with the previous version, as they are large or waste
+    pub warnings: usize,
+}
+
+/// Write the waste report of the crate at `path` to `out`, along with the `Cargo.toml` excerpt fixing it, not
+/// considering files of the `keep` categories waste. With a `baseline`, the files are compared with the greatest
+/// version published before, pointing out large new files and new files which are waste.
+///
+/// `path` is either a `.crate` archive, or the directory of a cargo project whose files are determined with
+/// `cargo package --list`, as if it was published.
+pub fn check_blocking(
+    path: impl AsRef<Path>,
+    keep: &[Category],
+    baseline: Option<Baseline<'_>>,
+    mut out: impl Write,
+) -> Result<Checked> {
+    let path = path.as_ref();
+    let (name, version, package) = if path.is_dir() {
+        package_of_project(path)?
+    } else {
+        package_of_archive(std::fs::File::open(path)?, &path.display().to_string())?
+    };
+    let files = files_of(&package);
+    let report = Report::from_package_keeping(&name, &version, package, keep);
+    let excerpt = report.manifest_excerpt();
+    let (total_size_in_bytes, total_files, wasted_files) = match report {
+        Report::Version {
+            total_size_in_bytes,
+            total_files,
+            wasted_files,
+            ..
+        } => (total_size_in_bytes, total_files, wasted_files),
+        _ => unreachable!("reports of packages are version reports"),
+    };
+
+    writeln!(
+        out,
+        "{} {}: {} files, {}",
+        name,
+        version,
+        total_files,
+        ByteSize(total_size_in_bytes)
+    )?;
+    let mut checked = Checked {
+        wasted_files: wasted_files.len(),
+        warnings: 0,
+    };
+    if wasted_files.is_empty() {
+        writeln!(out, "No waste found")?;
+    } else {
+        writeln!(
+            out,
+            "{} wasted files, {}:",
+            wasted_files.len(),
+            ByteSize(wasted_files.iter().map(|(_, size)| size).sum())
+        )?;
+        for (path, size) in &wasted_files {
+            writeln!(out, "  {} ({})", path, ByteSize(*size))?;
+        }
+        if let Some(excerpt) = excerpt.as_ref() {
+            writeln!(out)?;
+            write!(out, "{}", excerpt)?;
+        }
+    }
+
+    if let Some(baseline) = baseline {
+        writeln!(out)?;
+        let crate_name = baseline.registry.qualify(&name);
+        let db = persistence::Db::open(baseline.db_path)?;
+        match previous_version(&db, &crate_name, &version)? {
+            Some(previous) => {
+                let previous_files = files_of_version(&db, baseline.registry, &previous)?;
+                let wasted: Vec<_> = wasted_files.iter().map(|(path, _)| path.as_str()).collect();
+                checked.warnings = write_comparison(&previous, &previous_files, &files, &wasted, &mut out)?;
+            }
+            None => writeln!(out, "No version of {} was published before", crate_name)?,
+        }
+    }
+    Ok(checked)
+}
+
+/// Write how `files` differ from `previous_files` of the `previous` version, and return the amount of warnings about
+/// new files, which are either large or `wasted`.
+fn write_comparison(
+    previous: &CrateVersion,
+    previous_files: &BTreeMap<String, u64>,
+    files: &BTreeMap<String, u64>,
+    wasted: &[&str],
+    mut out: impl Write,
+) -> Result<usize> {
+    let total = |files: &BTreeMap<String, u64>| files.values().sum::<u64>();
+    writeln!(
+        out,
+        "Compared to {}: {} → {} files, {} → {}",
+        previous.version,
+        previous_files.len(),
+        files.len(),
+        ByteSize(total(previous_files)),
+        ByteSize(total(files))
+    )?;
+    let mut num_warnings = 0;
+    for (path, size) in files.iter().filter(|(path, _)| !previous_files.contains_key(*path)) {
+        writeln!(out, "  + {} ({})", path, ByteSize(*size))?;
+        if *size >= LARGE_NEW_FILE_SIZE {
+            writeln!(out, "    warning: large new file")?;
+            num_warnings += 1;
+        }
+        if wasted.contains(&path.as_str()) {
+            writeln!(out, "    warning: new file which isn't needed to build the crate")?;
+            num_warnings += 1;
+        }
+    }
+    for (path, size) in previous_files.iter().filter(|(path, _)| !files.contains_key(*path)) {
+        writeln!(out, "  - {} ({})", path, ByteSize(*size))?;
+    }
+    Ok(num_warnings)
+}
+
+/// The greatest version of `crate_name` which isn't yanked and isn't `version`
+fn previous_version(db: &persistence::Db, crate_name: &str, version: &str) -> Result<Option<CrateVersion>> {
+    let krate = match db.open_crates()?.get(crate_name)? {
+        Some(krate) => krate,
+        None => return Ok(None),
+    };
+    let versions = db.open_crate_versions()?;
+    let mut previous = None;
+    let mut key = String::new();
+    for candidate in krate.versions.iter().filter(|v| *v != version) {
+        key.clear();
+        CrateVersion::key_from(crate_name, candidate, &mut key);
+        let candidate = match versions.get(&key)? {
+            Some(v) if v.kind != crates_index_diff::ChangeKind::Yanked => v,
+            _ => continue,
+        };
+        let semver = match parse_semver(&candidate.version) {
+            Ok(semver) => semver,
+            Err(_) => continue,
+        };
+        if previous.as_ref().is_none_or(|(greatest, _)| semver > *greatest) {
+            previous = Some((semver, candidate));
+        }
+    }
+    Ok(previous.map(|(_, v)| v))
+}
+
+/// The files of `version` as extracted while mining, or as downloaded from crates.io if it wasn't extracted.
+fn files_of_version(
+    db: &persistence::Db,
+    registry: &Registry,
+    version: &CrateVersion,
+) -> Result<BTreeMap<String, u64>> {
+    let results = db.open_results()?;
+    let mut key = String::new();
+    for task in &[default_persisted_extraction_task(), meta_data_extraction_task()] {
+        key.clear();
+        let dummy_result = TaskResult::ExplodedCrate {
+            entries_meta_data: Default::default(),
+            selected_entries: Default::default(),
+        };
+        dummy_result.fq_key(&version.name, &version.version, task, &mut key);
+        if let Some(entries_meta_data) = results.get_meta_data(&key)? {
+            return Ok(files_of(&TarPackage {
+                entries_meta_data,
+                entries: Vec::new(),
+            }));
+        }
+    }
+
+    let url = match (registry.is_crates_io(), (artifact::CRATE.url)(version, None)) {
+        (true, Some(url)) => url,
+        _ => {
+            return Err(Error::Message(format!(
+                "{} {} wasn't extracted yet, and can only be downloaded from crates.io",
+                version.name, version.version
+            )))
+        }
+    };
+    let archive = futures_lite::future::block_on(async_compat::Compat::new(async {
+        let client = crate::engine::run::Connectivity::default().client_builder().build()?;
+        let response = client.get(&url).send().await?;
+        if !response.status().is_success() {
+            return Err(Error::HttpStatus(response.status()));
+        }
+        Ok(response.bytes().await?)
+    }))?;
+    let actual = hex::encode(Sha256::digest(&archive));
+    if actual != version.checksum {
+        return Err(Error::ChecksumMismatch(version.checksum.clone(), actual));
+    }
+    let (_, _, package) = package_of_archive(archive.as_ref(), &url)?;
+    Ok(files_of(&package))
+}
+
+/// The crate relative paths of the files of `package` along with their size, without those cargo adds to each package
+fn files_of(package: &TarPackage) -> BTreeMap<String, u64> {
+    package
+        .entries_meta_data
+        .iter()
+        .filter_map(|e| tar_path_to_utf8(&e.path).ok().map(|path| (path.to_owned(), e.size)))
+        .filter(|(path, _)| !IMPLICIT_FILES.contains(&path.as_str()))
+        .collect()
+}
+
+/// The package cargo would publish for the project in `dir`
+fn package_of_project(dir: &Path) -> Result<(String, String, TarPackage)> {
+    let output = std::process::Command::new(std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into()))
+        .args(["package", "--list", "--allow-dirty", "--quiet"])
+        .current_dir(dir)
+        .output()?;
+    if !output.status.success() {
+        return Err(Error::Message(format!(
+            "'cargo package --list' failed in '{}': {}",
+            dir.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    let mut files = Vec::new();
+    for path in String::from_utf8_lossy(&output.stdout).lines() {
+        // Files generated by cargo while packaging, like `.cargo_vcs_info.json`, aren't there yet
+        if let Ok(meta) = std::fs::metadata(dir.join(path)) {
+            files.push((path.to_owned(), meta.len()));
+        }
+    }
+    package(&files, |path| {
+        if path == "Cargo.toml" {
+            return normalized_manifest(dir);
+        }
+        let mut buf = Vec::new();
+        std::fs::File::open(dir.join(path))?
+            .take(MAX_SELECTED_FILE_SIZE as u64)
+            .read_to_end(&mut buf)?;
+        Ok(buf)
+    })
+}
+
+fn read_manifest(path: &Path) -> Result<toml::Value> {
+    toml::from_slice(&std::fs::read(path)?)
+        .map_err(|err| Error::Message(format!("'{}' is invalid: {}", path.display(), err)))
+}
+
+/// The manifest of the project in `dir` with the fields it inherits from its workspace resolved, as cargo would
+/// publish it. The workspace root is the one named by `package.workspace`, or the closest parent directory with
+/// a `[workspace]` in its manifest.
+fn normalized_manifest(dir: &Path) -> Result<Vec<u8>> {
+    let mut project = read_manifest(&dir.join("Cargo.toml"))?;
+    let root = match project
+        .get("package")
+        .and_then(|p| p.get("workspace"))
+        .and_then(toml::Value::as_str)
+    {
+        Some(root) => Some(read_manifest(&dir.join(root).join("Cargo.toml"))?),
+        None => dir
+            .ancestors()
+            .skip(1)
+            .map(|dir| dir.join("Cargo.toml"))
+            .filter(|path| path.is_file())
+            .map(|path| read_manifest(&path))
+            .find(|root| root.as_ref().map_or(true, |root| root.get("workspace").is_some()))
+            .transpose()?,
+    };
+    manifest::normalize(&mut project, root.as_ref());
+    toml::to_vec(&project).map_err(|err| Error::Message(format!("Could not write the normalized Cargo.toml: {}", err)))
+}
+
+/// The package in the `.crate` `archive` from `origin`
+fn package_of_archive(archive: impl Read, origin: &str) -> Result<(String, String, TarPackage)> {
+    let mut archive = tar::Archive::new(libflate::gzip::Decoder::new(std::io::BufReader::new(archive))?);
+    let mut files = Vec::new();
+    let mut contents = HashMap::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let entry_path = entry.path_bytes();
+        let entry_path = match tar_path_to_utf8(entry_path.as_ref()) {
+            Ok(entry_path) => entry_path.to_owned(),
+            Err(_) => continue,
+        };
+        let mut buf = Vec::new();
+        if entry_path == "Cargo.toml" {
+            entry.read_to_end(&mut buf)?;
+        } else {
+            entry
+                .by_ref()
+                .take(MAX_SELECTED_FILE_SIZE as u64)
+                .read_to_end(&mut buf)?;
+        }
+        files.push((entry_path.clone(), entry.header().size()?));
+        contents.insert(entry_path, buf);
+    }
+    package(&files, |file| {
+        contents
+            .remove(file)
+            .ok_or_else(|| Error::Message(format!("'{}' is not in '{}'", file, origin)))
+    })
+}
+
+/// Assemble a package from crate relative `files` with their size, using `read` to obtain the contents of the files the
+/// waste report looks at. Returns it along with the name and version of its crate.
+fn package(
+    files: &[(String, u64)],
+    mut read: impl FnMut(&str) -> Result<Vec<u8>>,
+) -> Result<(String, String, TarPackage)> {
+    if !files.iter().any(|(path, _)| path == "Cargo.toml") {
+        return Err(Error::Message("The package has no Cargo.toml".into()));
+    }
+    let manifest = read("Cargo.toml")?;
+    let (name, version) = name_and_version(&manifest)?;
+    let config = CargoConfig::from(manifest.as_slice());
+    let mut interesting_paths = vec![
+        config.actual_or_expected_build_script_path().to_owned(),
+        config.lib_path().to_owned(),
+    ];
+    interesting_paths.extend(config.bin_paths().into_iter().map(ToOwned::to_owned));
+    let standard_paths = standard_paths();
+
+    let mut package = TarPackage {
+        entries_meta_data: Vec::with_capacity(files.len()),
+        entries: Vec::new(),
+    };
+    for (path, size) in files {
+        let header = TarHeader {
+            path: format!("{}-{}/{}", name, version, path).into_bytes(),
+            size: *size,
+            entry_type: b'0',
+            encoding: None,
+            truncated_at: None,
+        };
+        if path == "Cargo.toml" {
+            package.entries.push((header.clone(), manifest.clone()));
+        } else if interesting_paths.contains(path) || standard_paths.is_match(path) {
+            package.entries.push((header.clone(), read(path)?));
+        }
+        package.entries_meta_data.push(header);
+    }
+    Ok((name, version, package))
+}
+
+/// The name and version in the `manifest` of a crate, with versions which couldn't be inherited from a workspace shown as such
+fn name_and_version(manifest: &[u8]) -> Result<(String, String)> {
+    let manifest: toml::Value = toml::from_slice(manifest)
+        .map_err(|err| Error::Message(format!("Cargo.toml of the package is invalid: {}", err)))?;
+    let package = manifest.get("package");
+    let field = |name: &str| package.and_then(|p| p.get(name)).and_then(toml::Value::as_str);
+    let name = field("name").ok_or_else(|| Error::Message("Cargo.toml of the package has no name".into()))?;
+    Ok((
+        name.to_owned(),
+        field("version").unwrap_or("workspace-version").to_owned(),
+    ))
+}
+
+#[cfg(test)]
+mod check_package_test;
@@ -0,0 +1,176 @@
+use super::{check_blocking, Baseline, Checked, LARGE_NEW_FILE_SIZE};
+use crate::{
+    engine::{report::waste::Category, work::cpubound::default_persisted_extraction_task},
+    model::{CrateVersion, TarHeader, TaskResult},
+    persistence::{self, temp_db::TempDir, TableAccess},
+    registry::Registry,
+};
+use std::path::Path;
+
+fn project(name: &str) -> TempDir {
+    let dir = TempDir::new(&format!("check-package-{}", name));
+    for (path, content) in &[
+        (
+            "Cargo.toml",
+            "[package]\nname = \"a\"\nversion = \"1.0.0\"\nedition = \"2018\"\n",
+        ),
+        ("src/lib.rs", "pub fn a() {}\n"),
+        ("tests/a.rs", "#[test]\nfn a() {}\n"),
+    ] {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+    dir
+}
+
+fn check(path: &Path, keep: &[Category]) -> (usize, String) {
+    let mut out = Vec::new();
+    let checked = check_blocking(path, keep, None, &mut out).unwrap();
+    (checked.wasted_files, String::from_utf8(out).unwrap())
+}
+
+#[test]
+fn projects_are_checked_as_cargo_would_package_them() {
+    let dir = project("project");
+    let (num_wasted, out) = check(&dir, &[]);
+    assert_eq!(num_wasted, 1, "{}", out);
+    assert!(out.starts_with("a 1.0.0: "), "{}", out);
+    assert!(out.contains("  tests/a.rs ("), "{}", out);
+    assert!(out.contains("[package]\ninclude = ["), "the fix is printed: {}", out);
+
+    let (num_wasted, out) = check(&dir, &[Category::Tests]);
+    assert_eq!(num_wasted, 0);
+    assert!(out.ends_with("No waste found\n"), "{}", out);
+}
+
+#[test]
+fn projects_in_a_workspace_are_checked_with_the_fields_they_inherit() {
+    let dir = project("workspace");
+    let member = dir.join("a");
+    std::fs::create_dir_all(&member).unwrap();
+    for path in &["src", "tests"] {
+        std::fs::rename(dir.join(path), member.join(path)).unwrap();
+    }
+    std::fs::write(
+        member.join("Cargo.toml"),
+        "[package]\nname = \"a\"\nversion.workspace = true\nedition.workspace = true\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("Cargo.toml"),
+        "[workspace]\nmembers = [\"a\"]\n\n[workspace.package]\nversion = \"1.2.3\"\nedition = \"2018\"\n",
+    )
+    .unwrap();
+    let (num_wasted, out) = check(&member, &[]);
+    assert_eq!(num_wasted, 1, "{}", out);
+    assert!(out.starts_with("a 1.2.3: "), "{}", out);
+}
+
+#[test]
+fn crate_archives_are_checked_as_published() {
+    let dir = project("archive");
+    let archive_path = dir.with_extension("crate");
+    {
+        let mut archive =
+            tar::Builder::new(libflate::gzip::Encoder::new(std::fs::File::create(&archive_path).unwrap()).unwrap());
+        for path in &["Cargo.toml", "src/lib.rs", "tests/a.rs"] {
+            archive
+                .append_path_with_name(dir.join(path), Path::new("a-1.0.0").join(path))
+                .unwrap();
+        }
+        archive.into_inner().unwrap().finish().into_result().unwrap();
+    }
+    let (num_wasted, out) = check(&archive_path, &[]);
+    assert_eq!(num_wasted, 1, "{}", out);
+    assert!(out.contains("  tests/a.rs ("), "{}", out);
+    std::fs::remove_file(archive_path).ok();
+}
+
+#[test]
+fn packages_are_compared_with_the_greatest_version_published_before() {
+    let dir = project("compare");
+    std::fs::write(
+        dir.join("tests").join("huge.bin"),
+        vec![0; LARGE_NEW_FILE_SIZE as usize],
+    )
+    .unwrap();
+    let db_path = dir.with_extension("db");
+    std::fs::remove_dir_all(&db_path).ok();
+    let db = persistence::Db::open(&db_path).unwrap();
+    let mut progress = prodash::Tree::new().add_child("compare");
+    for (version, kind) in [
+        ("0.9.0", crates_index_diff::ChangeKind::Added),
+        ("0.10.0", crates_index_diff::ChangeKind::Added),
+        ("0.11.0", crates_index_diff::ChangeKind::Yanked),
+    ] {
+        let v = CrateVersion {
+            name: "a".into(),
+            version: version.into(),
+            kind,
+            ..Default::default()
+        };
+        db.open_crates().unwrap().upsert(&mut progress, "a", &v).unwrap();
+        db.open_crate_versions()
+            .unwrap()
+            .insert(&mut progress, format!("a:{}", version), &v)
+            .unwrap();
+        let header = |path: &str, size| TarHeader {
+            path: format!("a-{}/{}", version, path).into_bytes(),
+            size,
+            entry_type: b'0',
+            encoding: None,
+            truncated_at: None,
+        };
+        let result = TaskResult::ExplodedCrate {
+            entries_meta_data: vec![
+                header("Cargo.toml", 60),
+                header("Cargo.toml.orig", 60),
+                header("src/lib.rs", 14),
+                header("README.md", 100),
+            ],
+            selected_entries: Vec::new(),
+        };
+        let mut key = String::new();
+        result.fq_key("a", version, &default_persisted_extraction_task(), &mut key);
+        db.open_results().unwrap().insert(&mut progress, &key, &result).unwrap();
+    }
+
+    let mut out = Vec::new();
+    let checked = check_blocking(
+        &dir,
+        &[],
+        Some(Baseline {
+            db_path: &db_path,
+            registry: &Registry::default(),
+        }),
+        &mut out,
+    )
+    .unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert_eq!(
+        checked,
+        Checked {
+            wasted_files: 2,
+            warnings: 3,
+        },
+        "{}",
+        out
+    );
+    assert!(out.contains("Compared to 0.10.0: 3 → 4 files"), "{}", out);
+    assert!(
+        out.contains(
+            "  + tests/huge.bin (262.1 KB)\n    warning: large new file\n    warning: new file which isn't needed"
+        ),
+        "{}",
+        out
+    );
+    assert!(out.contains("  + tests/a.rs ("), "{}", out);
+    assert!(out.contains("  - README.md (100 B)"), "{}", out);
+    assert!(
+        !out.contains("Cargo.toml.orig"),
+        "files added by cargo aren't compared: {}",
+        out
+    );
+    std::fs::remove_dir_all(db_path).ok();
+}
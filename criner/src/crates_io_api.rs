@@ -0,0 +1,203 @@
+//! A client for the crates.io API, to be shared by everything that needs data only available there, like download
+//! counts, owners or publish dates of crates which aren't in the database dump yet.
+//!
+//! The crawler policy of crates.io asks for at most one request per second, which the client enforces for all of its
+//! clones together. Responses are cached in the database along with their `ETag`, so asking again for something
+//! which didn't change is cheap for both sides.
+use crate::{engine::run::Connectivity, persistence, Error, Result};
+use serde_derive::Deserialize;
+use std::{
+    io::Write,
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// The minimum time between two requests, as asked for by the crates.io crawler policy
+pub const MIN_REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+const API_URL: &str = "https://crates.io/api/v1";
+
+/// A crate as returned by `/crates/<name>`
+#[derive(Debug, Clone, Deserialize)]
+pub struct Crate {
+    pub name: String,
+    /// The amount of downloads of all versions, all time
+    pub downloads: u64,
+    /// The amount of downloads of all versions in the last 90 days
+    pub recent_downloads: Option<u64>,
+    /// The time the first version was published, like `2015-12-10T23:12:52.456573+00:00`
+    pub created_at: String,
+    /// The time the crate was last changed, usually by publishing a version
+    pub updated_at: String,
+    pub max_version: String,
+    pub repository: Option<String>,
+}
+
+/// A version as returned along with its crate by `/crates/<name>`
+#[derive(Debug, Clone, Deserialize)]
+pub struct Version {
+    /// The version, like `1.0.3`
+    pub num: String,
+    pub downloads: u64,
+    /// The time the version was published
+    pub created_at: String,
+    pub yanked: bool,
+}
+
+/// The response of `/crates/<name>`
+#[derive(Debug, Clone, Deserialize)]
+pub struct CrateResponse {
+    #[serde(rename = "crate")]
+    pub krate: Crate,
+    /// All versions, the most recent first
+    pub versions: Vec<Version>,
+}
+
+/// A user or team owning a crate
+#[derive(Debug, Clone, Deserialize)]
+pub struct Owner {
+    pub login: String,
+    pub name: Option<String>,
+    /// Either `user` or `team`
+    pub kind: String,
+}
+
+#[derive(Deserialize)]
+struct OwnersResponse {
+    users: Vec<Owner>,
+}
+
+/// A throttled and caching client of the crates.io API. Clones share the throttle and the cache.
+#[derive(Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    base_url: String,
+    min_interval: Duration,
+    next_request_at: Arc<async_lock::Mutex<Instant>>,
+    cache: persistence::ApiCacheTable,
+}
+
+impl Client {
+    /// A client of the crates.io API caching responses in `db`
+    pub fn new(db: &persistence::Db, connectivity: &Connectivity) -> Result<Client> {
+        Client::with_base_url(db, connectivity, API_URL, MIN_REQUEST_INTERVAL)
+    }
+
+    /// Like `new()`, but for an API at `base_url` which may be asked every `min_interval`
+    pub fn with_base_url(
+        db: &persistence::Db,
+        connectivity: &Connectivity,
+        base_url: &str,
+        min_interval: Duration,
+    ) -> Result<Client> {
+        Ok(Client {
+            http: connectivity.client_builder().build()?,
+            base_url: base_url.trim_end_matches('/').to_owned(),
+            min_interval,
+            next_request_at: Arc::new(async_lock::Mutex::new(Instant::now())),
+            cache: db.open_api_cache()?,
+        })
+    }
+
+    /// Wait until the next request is allowed. Waiting happens while holding the lock, to serve callers in order.
+    async fn throttle(&self) {
+        let mut next_request_at = self.next_request_at.lock().await;
+        let now = Instant::now();
+        if *next_request_at > now {
+            async_io::Timer::after(*next_request_at - now).await;
+        }
+        *next_request_at = Instant::now() + self.min_interval;
+    }
+
+    /// Fetch the body at `path` of the API, with a conditional request if a response to it was cached
+    async fn get_bytes(&self, path: &str) -> Result<Vec<u8>> {
+        let url = format!("{}{}", self.base_url, path);
+        let cached = self.cache.get(&url)?;
+        let mut request = self.http.get(&url).header(reqwest::header::ACCEPT, "application/json");
+        if let Some((etag, _)) = cached.as_ref() {
+            request = request.header(reqwest::header::IF_NONE_MATCH, etag.as_str());
+        }
+
+        self.throttle().await;
+        let response = request.send().await?;
+        let status = response.status();
+        if status == reqwest::StatusCode::NOT_MODIFIED {
+            return cached
+                .map(|(_, body)| body)
+                .ok_or_else(|| Error::Protocol(format!("{}: Not modified, but nothing was cached", url)));
+        }
+        if !status.is_success() {
+            return Err(Error::HttpStatus(status));
+        }
+        let etag = response
+            .headers()
+            .get(reqwest::header::ETAG)
+            .and_then(|v| v.to_str().ok())
+            .map(ToOwned::to_owned);
+        let body = response.bytes().await?.to_vec();
+        if let Some(etag) = etag {
+            self.cache.put(&url, &etag, &body)?;
+        }
+        Ok(body)
+    }
+
+    async fn get<T: serde::de::DeserializeOwned>(&self, path: &str) -> Result<T> {
+        let body = self.get_bytes(path).await?;
+        Ok(serde_json::from_slice(&body)?)
+    }
+
+    /// The crate called `name` along with all of its versions
+    pub async fn krate(&self, name: &str) -> Result<CrateResponse> {
+        self.get(&format!("/crates/{}", name)).await
+    }
+
+    /// All users and teams owning the crate called `name`
+    pub async fn owners(&self, name: &str) -> Result<Vec<Owner>> {
+        Ok(self
+            .get::<OwnersResponse>(&format!("/crates/{}/owners", name))
+            .await?
+            .users)
+    }
+}
+
+/// Write download counts, owners and publish dates of `crate_name` as returned by the crates.io API to `out`, using
+/// the database at `db_path` to cache responses.
+pub fn write_blocking(db_path: impl AsRef<Path>, crate_name: &str, mut out: impl Write) -> Result<()> {
+    let db = persistence::Db::open(db_path)?;
+    let client = Client::new(&db, &Connectivity::default())?;
+    let (response, owners) = futures_lite::future::block_on(crate::spawn({
+        let crate_name = crate_name.to_owned();
+        async move { Ok::<_, Error>((client.krate(&crate_name).await?, client.owners(&crate_name).await?)) }
+    }))?;
+    let CrateResponse { krate, versions } = response;
+
+    writeln!(out, "{} {}", krate.name, krate.max_version)?;
+    writeln!(out, "  downloads:        {}", krate.downloads)?;
+    if let Some(recent) = krate.recent_downloads {
+        writeln!(out, "  recent downloads: {}", recent)?;
+    }
+    writeln!(out, "  created at:       {}", krate.created_at)?;
+    writeln!(out, "  updated at:       {}", krate.updated_at)?;
+    writeln!(out, "Owners")?;
+    for owner in owners {
+        match owner.name {
+            Some(name) => writeln!(out, "  {} ({}, {})", owner.login, name, owner.kind)?,
+            None => writeln!(out, "  {} ({})", owner.login, owner.kind)?,
+        }
+    }
+    writeln!(out, "Versions")?;
+    for version in versions {
+        writeln!(
+            out,
+            "  {:<16} published {}, {} downloads{}",
+            version.num,
+            version.created_at,
+            version.downloads,
+            if version.yanked { ", yanked" } else { "" }
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod crates_io_api_test;
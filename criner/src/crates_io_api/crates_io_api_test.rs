@@ -0,0 +1,94 @@
+use super::{Client, CrateResponse};
+use crate::{engine::run::Connectivity, persistence::temp_db::TempDb};
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    time::{Duration, Instant},
+};
+
+const CRATE_JSON: &str = r#"{
+    "crate": {
+        "name": "serde", "downloads": 1000, "recent_downloads": 100,
+        "created_at": "2014-12-05T20:20:39.487502+00:00", "updated_at": "2020-06-26T21:40:27.371937+00:00",
+        "max_version": "1.0.114", "repository": "https://github.com/serde-rs/serde"
+    },
+    "versions": [
+        {"num": "1.0.114", "downloads": 10, "created_at": "2020-06-26T21:40:27.371937+00:00", "yanked": false},
+        {"num": "0.1.0", "downloads": 1, "created_at": "2014-12-05T20:20:39.487502+00:00", "yanked": true}
+    ]
+}"#;
+
+/// Serve `CRATE_JSON` with an ETag to `requests` requests, answering conditional requests with 304, and return the
+/// base URL along with a receiver for the request headers of each request.
+fn serve(requests: usize) -> (String, std::sync::mpsc::Receiver<Vec<String>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base_url = format!("http://{}/api/v1", listener.local_addr().unwrap());
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        for stream in listener.incoming().take(requests) {
+            let mut stream = stream.unwrap();
+            let headers: Vec<String> = BufReader::new(&stream)
+                .lines()
+                .map(Result::unwrap)
+                .take_while(|line| !line.is_empty())
+                .collect();
+            let response = if headers.iter().any(|h| h.to_lowercase() == "if-none-match: \"v1\"") {
+                "HTTP/1.1 304 Not Modified\r\netag: \"v1\"\r\nconnection: close\r\n\r\n".to_string()
+            } else {
+                format!(
+                    "HTTP/1.1 200 OK\r\netag: \"v1\"\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    CRATE_JSON.len(),
+                    CRATE_JSON
+                )
+            };
+            stream.write_all(response.as_bytes()).unwrap();
+            tx.send(headers).unwrap();
+        }
+    });
+    (base_url, rx)
+}
+
+#[test]
+fn responses_are_typed_and_cached_by_etag() {
+    let db = TempDb::new("crates-io-api-etag");
+    let (base_url, requests) = serve(2);
+    let client = Client::with_base_url(&db, &Connectivity::default(), &base_url, Duration::default()).unwrap();
+
+    for _ in 0..2 {
+        let CrateResponse { krate, versions } =
+            futures_lite::future::block_on(async_compat::Compat::new(client.krate("serde"))).unwrap();
+        assert_eq!(krate.name, "serde");
+        assert_eq!(krate.downloads, 1000);
+        assert_eq!(versions.len(), 2);
+        assert!(versions[1].yanked);
+    }
+
+    let first = requests.recv().unwrap();
+    assert!(first[0].starts_with("GET /api/v1/crates/serde "));
+    assert!(!first.iter().any(|h| h.to_lowercase().starts_with("if-none-match")));
+    let second = requests.recv().unwrap();
+    assert!(
+        second.iter().any(|h| h.to_lowercase() == "if-none-match: \"v1\""),
+        "the second request is conditional and answered from the cache"
+    );
+}
+
+#[test]
+fn requests_are_throttled_across_clones() {
+    let db = TempDb::new("crates-io-api-throttle");
+    let client = Client::with_base_url(
+        &db,
+        &Connectivity::default(),
+        "http://localhost",
+        Duration::from_millis(100),
+    )
+    .unwrap();
+    let clone = client.clone();
+    let start = Instant::now();
+    futures_lite::future::block_on(async {
+        client.throttle().await;
+        clone.throttle().await;
+        client.throttle().await;
+    });
+    assert!(start.elapsed() >= Duration::from_millis(200));
+}
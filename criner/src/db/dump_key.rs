@@ -0,0 +1,92 @@
+use crate::{
+    model::{db_dump, Context, Crate, CrateVersion, DatabaseInfo, IndexCheck, RunSummary, Task, TaskResult},
+    persistence::{Backend, Db, TableAccess, DATABASE_INFO_KEY, SQLITE_FILE_NAME},
+    Error, Result,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{io::Write, path::Path};
+
+/// The tables whose items are keyed by text and stored as msgpack, along with the name of the model they decode into
+const TABLES: &[(&str, &str)] = &[
+    ("crate", "Crate"),
+    ("crate_version", "CrateVersion"),
+    ("task", "Task"),
+    ("result", "TaskResult"),
+    ("meta", "Context"),
+    ("index_check", "IndexCheck"),
+    ("run_summary", "RunSummary"),
+    ("crates.io-crate", "db_dump::Crate"),
+];
+
+/// The amount of bytes per line of the hex dump
+const BYTES_PER_LINE: usize = 32;
+
+/// `raw` decoded as `T` and written as JSON, or the error along with the value decoded without schema if that fails
+fn decode<T: DeserializeOwned + Serialize>(raw: &[u8]) -> Result<std::result::Result<String, String>> {
+    Ok(match rmp_serde::from_read_ref::<_, T>(raw) {
+        Ok(item) => Ok(serde_json::to_string_pretty(&item)?),
+        Err(err) => Err(format!(
+            "{}\n{:#?}",
+            err,
+            rmpv::decode::value::read_value(&mut std::io::Cursor::new(raw))
+                .map_err(|err| Error::Message(format!("The item isn't valid msgpack: {}", err)))?
+        )),
+    })
+}
+
+/// Write the item stored at `key` in `table` of the database at `db_path` to `out`, both as the msgpack bytes as they
+/// are stored, in hex, and decoded into its model as pretty JSON.
+///
+/// Items which can't be decoded into their model are written as decoded without schema along with the error instead,
+/// which is when this is needed most. Results are written as stored, so deltas to other results are not resolved.
+pub fn dump_key_blocking(db_path: impl AsRef<Path>, table: &str, key: &str, mut out: impl Write) -> Result<()> {
+    let db_path = db_path.as_ref();
+    if !db_path.join(SQLITE_FILE_NAME).is_file() {
+        return Err(Error::Message(format!(
+            "There is no database at '{}'",
+            db_path.display()
+        )));
+    }
+    let (_, mut model) = TABLES.iter().find(|(name, _)| *name == table).ok_or_else(|| {
+        Error::Message(format!(
+            "Table '{}' has no items stored as msgpack, expected one of {}",
+            table,
+            TABLES.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(", ")
+        ))
+    })?;
+    let db = Db::open(db_path)?;
+    let raw = match table {
+        "result" => db.open_results()?.backend().get(table, key)?,
+        _ => db.open_connection()?.get(table, key)?,
+    }
+    .ok_or_else(|| Error::Message(format!("There is no item with key '{}' in table '{}'", key, table)))?;
+    let decoded = match table {
+        "meta" if key == DATABASE_INFO_KEY => {
+            model = "DatabaseInfo";
+            decode::<DatabaseInfo>(&raw)
+        }
+        "crate" => decode::<Crate>(&raw),
+        "crate_version" => decode::<CrateVersion>(&raw),
+        "task" => decode::<Task>(&raw),
+        "result" => decode::<TaskResult>(&raw),
+        "meta" => decode::<Context>(&raw),
+        "index_check" => decode::<IndexCheck>(&raw),
+        "run_summary" => decode::<RunSummary>(&raw),
+        "crates.io-crate" => decode::<db_dump::Crate>(&raw),
+        _ => unreachable!("all tables are listed"),
+    }?;
+
+    writeln!(out, "{} bytes of msgpack:", raw.len())?;
+    for line in raw.chunks(BYTES_PER_LINE) {
+        writeln!(out, "{}", hex::encode(line))?;
+    }
+    writeln!(out)?;
+    match decoded {
+        Ok(json) => writeln!(out, "Decoded as {}:\n{}", model, json)?,
+        Err(err) => writeln!(out, "Could not decode as {}: {}", model, err)?,
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod dump_key_test;
@@ -0,0 +1,58 @@
+use super::dump_key_blocking;
+use crate::{
+    model::CrateVersion,
+    persistence::{temp_db::TempDb, Backend, TableAccess},
+};
+
+fn dump(db_path: &std::path::Path, table: &str, key: &str) -> crate::Result<String> {
+    let mut out = Vec::new();
+    dump_key_blocking(db_path, table, key, &mut out)?;
+    Ok(String::from_utf8(out).unwrap())
+}
+
+#[test]
+fn items_are_dumped_as_stored_and_decoded() {
+    let db = TempDb::new("dump-key");
+    let dir = &db.dir;
+    let version = CrateVersion {
+        name: "a".into(),
+        version: "1.0.0".into(),
+        ..Default::default()
+    };
+    db.open_crate_versions()
+        .unwrap()
+        .insert(&mut prodash::Tree::new().add_child("dump"), "a:1.0.0", &version)
+        .unwrap();
+    let raw = rmp_serde::to_vec(&version).unwrap();
+
+    let out = dump(dir, "crate_version", "a:1.0.0").unwrap();
+    assert!(
+        out.starts_with(&format!("{} bytes of msgpack:\n{}\n", raw.len(), hex::encode(&raw))),
+        "{}",
+        out
+    );
+    assert!(out.contains("Decoded as CrateVersion:\n{"), "{}", out);
+    assert!(out.contains("\"name\": \"a\""), "{}", out);
+
+    db.open_connection()
+        .unwrap()
+        .put("task", "a:1.0.0:extract_crate:1.0.0", &rmp_serde::to_vec(&42).unwrap())
+        .unwrap();
+    let out = dump(dir, "task", "a:1.0.0:extract_crate:1.0.0").unwrap();
+    assert!(out.contains("Could not decode as Task: "), "{}", out);
+    assert!(
+        out.contains("PosInt(\n        42,"),
+        "the item is shown without schema: {}",
+        out
+    );
+
+    let out = dump(dir, "meta", crate::persistence::DATABASE_INFO_KEY).unwrap();
+    assert!(
+        out.contains("Decoded as DatabaseInfo:\n{"),
+        "the record describing the database isn't a context: {}",
+        out
+    );
+
+    assert!(dump(dir, "crate_version", "b:1.0.0").is_err());
+    assert!(dump(dir, "processing_journal", "a:1.0.0").is_err());
+}
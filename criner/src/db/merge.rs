@@ -0,0 +1,233 @@
+use crate::{
+    engine::work::schedule::REGISTRIES_DIR,
+    model::{db_dump, Context, Crate, CrateVersion, Task, TaskResult},
+    persistence::{
+        delta, CrateTable, CrateVersionTable, Db, Merge, MetaTable, TableAccess, TaskResultTable, TaskTable,
+        CONTEXT_CONDITION,
+    },
+    registry::Registry,
+};
+use rusqlite::{params, OptionalExtension, Transaction, TransactionBehavior};
+use std::path::Path;
+
+/// Merge all mining results of the database at `source_db` into the one at `destination_db`, which is created if needed.
+/// If `registry` is set, only its crates and their assets are merged.
+///
+/// Tasks keep the most advanced state as per `TaskState::merged()`, downloaded assets are copied over if they are not yet
+/// present.
+pub fn merge_blocking(
+    source_db: impl AsRef<Path>,
+    destination_db: impl AsRef<Path>,
+    registry: Option<&Registry>,
+) -> crate::Result<()> {
+    let source_sqlite = source_db.as_ref().join(crate::persistence::SQLITE_FILE_NAME);
+    if !source_sqlite.is_file() {
+        return Err(crate::Error::Message(format!(
+            "Source database at '{}' does not exist",
+            source_db.as_ref().display()
+        )));
+    }
+    if source_db.as_ref().canonicalize()? == destination_db.as_ref().canonicalize().unwrap_or_default() {
+        return Err(crate::Error::Message("Cannot merge a database into itself".into()));
+    }
+    let source = Db::open(&source_db)?;
+    source.compatibility()?.verify()?;
+    let destination = Db::open(&destination_db)?;
+    let input = source.open_connection_no_async_with_busy_wait()?;
+    let mut output = destination.open_connection_no_async_with_busy_wait()?;
+
+    let transaction = output.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    merge_table::<CrateVersion>(
+        &input,
+        &transaction,
+        CrateVersionTable::table_name(),
+        None,
+        registry,
+        |_key, existing, new| Ok(existing.unwrap_or(new)),
+    )?;
+    merge_table::<Crate>(
+        &input,
+        &transaction,
+        CrateTable::table_name(),
+        None,
+        registry,
+        |_key, existing, new| {
+            Ok(match existing {
+                Some(existing) => existing.merge(&new),
+                None => new,
+            })
+        },
+    )?;
+    merge_table::<Task>(
+        &input,
+        &transaction,
+        TaskTable::table_name(),
+        None,
+        registry,
+        |_key, existing, new| {
+            Ok(match existing {
+                Some(existing) => most_advanced(existing, new),
+                None => new,
+            })
+        },
+    )?;
+    // Deltas of the source are based on results of the source, which the destination may not have, or have differently.
+    // Thus they are resolved in the source and encoded again against what the destination has, which is why crates
+    // have to be merged first.
+    merge_table::<TaskResult>(
+        &input,
+        &transaction,
+        TaskResultTable::table_name(),
+        None,
+        registry,
+        |key, existing, new| {
+            Ok(match existing {
+                None | Some(TaskResult::None) => {
+                    let new = delta::resolve(&input, key, new)?;
+                    delta::encode(&transaction, key, &new)?.unwrap_or(new)
+                }
+                Some(existing) => existing,
+            })
+        },
+    )?;
+    // The record describing the source database doesn't describe the destination
+    merge_table::<Context>(
+        &input,
+        &transaction,
+        MetaTable::table_name(),
+        Some(CONTEXT_CONDITION),
+        None,
+        |_key, existing, new| {
+            Ok(match existing {
+                Some(existing) => existing.merge(&new),
+                None => new,
+            })
+        },
+    )?;
+    merge_table::<db_dump::Crate>(
+        &input,
+        &transaction,
+        "crates.io-crate",
+        None,
+        registry,
+        |_key, existing, new| {
+            Ok(match existing {
+                Some(existing) => existing.merge(&new),
+                None => new,
+            })
+        },
+    )?;
+    transaction.commit()?;
+
+    let (source_assets, destination_assets) = (
+        source_db.as_ref().join("assets"),
+        destination_db.as_ref().join("assets"),
+    );
+    let num_copied = match registry {
+        Some(registry) if !registry.is_crates_io() => copy_missing_assets(
+            &source_assets.join(REGISTRIES_DIR).join(&registry.name),
+            &destination_assets.join(REGISTRIES_DIR).join(&registry.name),
+            None,
+        )?,
+        Some(_) => copy_missing_assets(
+            &source_assets,
+            &destination_assets,
+            Some(&source_assets.join(REGISTRIES_DIR)),
+        )?,
+        None => copy_missing_assets(&source_assets, &destination_assets, None)?,
+    };
+    log::info!("Copied {} asset files", num_copied);
+    Ok(())
+}
+
+/// Merge the items of `table_name` matching the SQL `condition`, or all of them if `None`, from `input` into `output`,
+/// limited to the crates of `registry` if set.
+/// `merge` receives the key, the item in `output` if there is one, and the item of `input`, returning the item to store.
+fn merge_table<T>(
+    input: &rusqlite::Connection,
+    output: &Transaction,
+    table_name: &str,
+    condition: Option<&str>,
+    registry: Option<&Registry>,
+    mut merge: impl FnMut(&str, Option<T>, T) -> crate::Result<T>,
+) -> crate::Result<()>
+where
+    for<'a> T: serde::Serialize + From<&'a [u8]>,
+{
+    let start = std::time::SystemTime::now();
+    let (registry_condition, registry_values) = match registry {
+        Some(registry) => registry.key_condition(),
+        None => ("1", Vec::new()),
+    };
+    let mut select = input.prepare(&format!(
+        "SELECT key, data FROM '{}' WHERE {} AND {}",
+        table_name,
+        condition.unwrap_or("1"),
+        registry_condition
+    ))?;
+    let mut get = output.prepare(&format!("SELECT data FROM '{}' WHERE key = ?1", table_name))?;
+    let mut insert = output.prepare(&format!("REPLACE INTO '{}' (key, data) VALUES (?1, ?2)", table_name))?;
+    let mut count = 0;
+    for res in select.query_map(rusqlite::params_from_iter(registry_values), |r| {
+        Ok((r.get::<_, String>(0)?, r.get::<_, Vec<u8>>(1)?))
+    })? {
+        let (key, value) = res?;
+        let new = T::from(value.as_slice());
+        let existing = get
+            .query_row(params![key], |r| r.get::<_, Vec<u8>>(0))
+            .optional()?
+            .map(|existing| T::from(existing.as_slice()));
+        let merged = merge(&key, existing, new)?;
+        insert.execute(params![key, rmp_serde::to_vec(&merged)?])?;
+        count += 1;
+    }
+    log::info!(
+        "Merged {} {} in {:?}",
+        count,
+        table_name,
+        std::time::SystemTime::now().duration_since(start).unwrap_or_default()
+    );
+    Ok(())
+}
+
+/// Keep the state which got further with the failures of both, and the process of the task which was stored last.
+fn most_advanced(existing: Task, new: Task) -> Task {
+    let (newer, older) = if new.stored_at > existing.stored_at {
+        (new, existing)
+    } else {
+        (existing, new)
+    };
+    Task {
+        state: older.state.merged(newer.state),
+        ..newer
+    }
+}
+
+/// Copy the files in `source` which are missing in `destination`, leaving out the `excluded` directory
+fn copy_missing_assets(source: &Path, destination: &Path, excluded: Option<&Path>) -> crate::Result<usize> {
+    let mut num_copied = 0;
+    if !source.is_dir() {
+        return Ok(num_copied);
+    }
+    std::fs::create_dir_all(destination)?;
+    for entry in std::fs::read_dir(source)? {
+        let entry = entry?;
+        let destination = destination.join(entry.file_name());
+        if excluded == Some(entry.path().as_path()) {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            num_copied += copy_missing_assets(&entry.path(), &destination, excluded)?;
+        } else if !destination.exists() && entry.path().extension() != Some(std::ffi::OsStr::new("tmp")) {
+            // hard-links are cheap, but only work on the same device
+            if std::fs::hard_link(entry.path(), &destination).is_err() {
+                std::fs::copy(entry.path(), &destination)?;
+            }
+            num_copied += 1;
+        }
+    }
+    Ok(num_copied)
+}
+
+#[cfg(test)]
+mod merge_test;
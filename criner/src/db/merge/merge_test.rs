@@ -0,0 +1,308 @@
+use super::merge_blocking;
+use crate::{
+    model::{CrateVersion, TarHeader, Task, TaskResult, TaskState},
+    persistence::{
+        delta,
+        fixtures::{exploded_crate, exploded_crate_without_content},
+        temp_db::TempDb,
+        TableAccess,
+    },
+    registry::Registry,
+};
+use std::{
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+fn version(name: &str, version: &str) -> CrateVersion {
+    CrateVersion {
+        name: name.into(),
+        version: version.into(),
+        ..Default::default()
+    }
+}
+
+fn task(state: TaskState, seconds_ago: u64) -> Task {
+    Task {
+        stored_at: SystemTime::now() - Duration::from_secs(seconds_ago),
+        process: "download".into(),
+        version: "1.0.0".into(),
+        state,
+    }
+}
+
+fn failures(errors: &[&str]) -> Vec<String> {
+    errors.iter().map(|e| e.to_string()).collect()
+}
+
+fn download(url: &str) -> TaskResult {
+    TaskResult::Download {
+        kind: "crate".into(),
+        url: url.into(),
+        content_length: 42,
+        content_type: None,
+        etag: None,
+        last_modified: None,
+        status: Some(200),
+        final_url: None,
+        server: None,
+        timing: None,
+    }
+}
+
+/// The paths of all entries and of those with content
+fn paths_of(result: Option<TaskResult>) -> (Vec<String>, Vec<String>) {
+    let path = |header: &TarHeader| String::from_utf8(header.path.clone()).unwrap();
+    match result {
+        Some(TaskResult::ExplodedCrate {
+            entries_meta_data,
+            selected_entries,
+        }) => (
+            entries_meta_data.iter().map(path).collect(),
+            selected_entries
+                .iter()
+                .map(|(header, content)| {
+                    assert_eq!(content, &header.path);
+                    path(header)
+                })
+                .collect(),
+        ),
+        other => panic!("expected an extracted crate, got {:?}", other),
+    }
+}
+
+fn url_of(result: Option<TaskResult>) -> String {
+    match result {
+        Some(TaskResult::Download { url, .. }) => url,
+        other => panic!("expected a download, got {:?}", other),
+    }
+}
+
+#[test]
+fn crates_tasks_results_and_assets_are_merged_the_same_way_twice() {
+    let (source, destination) = (TempDb::new("merge-source"), TempDb::new("merge-destination"));
+    let mut progress = prodash::Tree::new().add_child("merge");
+
+    let crates = source.open_crates().unwrap();
+    for (name, v) in [("a", "1.0.0"), ("a", "1.1.0")] {
+        crates.upsert(&mut progress, name, &version(name, v)).unwrap();
+    }
+    let tasks = source.open_tasks().unwrap();
+    let states = [
+        ("a:1.0.0:download:1.0.0", TaskState::Complete, 10),
+        (
+            "a:1.1.0:download:1.0.0",
+            TaskState::AttemptsWithFailure(failures(&["404"])),
+            10,
+        ),
+        ("a:1.2.0:download:1.0.0", TaskState::NotStarted, 0),
+    ];
+    for (key, state, seconds_ago) in states {
+        tasks.insert(&mut progress, key, &task(state, seconds_ago)).unwrap();
+    }
+    let results = source.open_results().unwrap();
+    results
+        .insert(&mut progress, "a:1.0.0:download:1.0.0:crate", &download("source"))
+        .unwrap();
+    results
+        .insert(&mut progress, "a:1.1.0:download:1.0.0:crate", &download("source"))
+        .unwrap();
+    source
+        .open_context()
+        .unwrap()
+        .update_today(|c| c.counts.crates = 2)
+        .unwrap();
+    let assets = source.dir.join("assets").join("a");
+    std::fs::create_dir_all(&assets).unwrap();
+    std::fs::write(assets.join("1.0.0.crate"), "source").unwrap();
+    std::fs::write(assets.join("1.1.0.crate"), "source").unwrap();
+    std::fs::write(assets.join("1.2.0.crate.tmp"), "partial").unwrap();
+
+    let crates = destination.open_crates().unwrap();
+    for (name, v) in [("a", "1.0.0"), ("b", "1.0.0")] {
+        crates.upsert(&mut progress, name, &version(name, v)).unwrap();
+    }
+    let tasks = destination.open_tasks().unwrap();
+    let states = [
+        (
+            "a:1.0.0:download:1.0.0",
+            TaskState::AttemptsWithFailure(failures(&["timeout"])),
+            0,
+        ),
+        (
+            "a:1.1.0:download:1.0.0",
+            TaskState::AttemptsWithFailure(failures(&["404", "reset"])),
+            0,
+        ),
+        ("a:1.2.0:download:1.0.0", TaskState::InProgress(None), 10),
+    ];
+    for (key, state, seconds_ago) in states {
+        tasks.insert(&mut progress, key, &task(state, seconds_ago)).unwrap();
+    }
+    destination
+        .open_results()
+        .unwrap()
+        .insert(&mut progress, "a:1.0.0:download:1.0.0:crate", &download("destination"))
+        .unwrap();
+    let assets = destination.dir.join("assets").join("a");
+    std::fs::create_dir_all(&assets).unwrap();
+    std::fs::write(assets.join("1.0.0.crate"), "destination").unwrap();
+    let info = destination.info().unwrap();
+
+    merge_blocking(&source.dir, &destination.dir, None).unwrap();
+    assert_eq!(
+        destination.info().unwrap(),
+        info,
+        "the description of the source isn't copied"
+    );
+    let (_, context) = destination.open_context().unwrap().most_recent().unwrap().unwrap();
+    assert_eq!(context.counts.crates, 2, "the work of each day adds up");
+
+    merge_blocking(&source.dir, &destination.dir, None).unwrap();
+
+    assert_eq!(crates.get("a").unwrap().unwrap().versions, ["1.0.0", "1.1.0"]);
+    assert_eq!(crates.get("b").unwrap().unwrap().versions, ["1.0.0"]);
+
+    assert!(tasks
+        .get("a:1.0.0:download:1.0.0")
+        .unwrap()
+        .unwrap()
+        .state
+        .is_complete());
+    assert!(
+        matches!(
+            tasks.get("a:1.1.0:download:1.0.0").unwrap().unwrap().state,
+            TaskState::AttemptsWithFailure(f) if f == ["404", "reset"]
+        ),
+        "failures known to both are kept once"
+    );
+    assert!(matches!(
+        tasks.get("a:1.2.0:download:1.0.0").unwrap().unwrap().state,
+        TaskState::InProgress(None)
+    ));
+
+    let results = destination.open_results().unwrap();
+    assert_eq!(
+        url_of(results.get("a:1.0.0:download:1.0.0:crate").unwrap()),
+        "destination",
+        "existing results are kept"
+    );
+    assert_eq!(url_of(results.get("a:1.1.0:download:1.0.0:crate").unwrap()), "source");
+
+    assert_eq!(
+        std::fs::read_to_string(assets.join("1.0.0.crate")).unwrap(),
+        "destination"
+    );
+    assert_eq!(std::fs::read_to_string(assets.join("1.1.0.crate")).unwrap(), "source");
+    assert!(
+        !assets.join("1.2.0.crate.tmp").exists(),
+        "partial downloads aren't copied"
+    );
+}
+
+#[test]
+fn databases_are_not_merged_into_themselves_or_from_nowhere() {
+    let db = TempDb::new("merge-itself");
+    assert!(merge_blocking(&db.dir, &db.dir, None).is_err());
+    assert!(merge_blocking(db.dir.join("missing"), &db.dir, None).is_err());
+}
+
+#[test]
+fn deltas_are_resolved_in_the_source_even_if_the_destination_has_a_different_base() {
+    let (source, destination) = (
+        TempDb::new("merge-delta-source"),
+        TempDb::new("merge-delta-destination"),
+    );
+    let mut progress = prodash::Tree::new().add_child("merge");
+    let (base, next) = ("a:1.0.0:extract_crate:1.0.0", "a:1.1.0:extract_crate:1.0.0");
+    let paths = ["Cargo.toml", "README.md", "src/lib.rs"];
+    for (db, base_result) in [
+        (&source, exploded_crate(&paths)),
+        // like after retention kept only the meta-data of the base, with entries the source doesn't have
+        (
+            &destination,
+            exploded_crate_without_content(&["Cargo.toml", "build.rs"]),
+        ),
+    ] {
+        let crates = db.open_crates().unwrap();
+        for v in ["1.0.0", "1.1.0"] {
+            crates.upsert(&mut progress, "a", &version("a", v)).unwrap();
+        }
+        db.open_results()
+            .unwrap()
+            .insert_as_is(&mut progress, base, &base_result)
+            .unwrap();
+    }
+    let new_paths = [&paths[..], &["src/new.rs"]].concat();
+    let results = source.open_results().unwrap();
+    results
+        .insert(&mut progress, next, &exploded_crate(&new_paths))
+        .unwrap();
+    assert!(
+        matches!(
+            delta::get_raw(&results.connection().lock(), next).unwrap(),
+            Some(TaskResult::ExplodedCrateDelta { .. })
+        ),
+        "the source stores the new version as delta"
+    );
+
+    merge_blocking(&source.dir, &destination.dir, None).unwrap();
+
+    let results = destination.open_results().unwrap();
+    let expected: Vec<_> = new_paths.iter().map(|p| p.to_string()).collect();
+    assert_eq!(paths_of(results.get(next).unwrap()), (expected.clone(), expected));
+    assert_eq!(
+        paths_of(results.get(base).unwrap()).0,
+        ["Cargo.toml", "build.rs"],
+        "the base of the destination is kept"
+    );
+}
+
+#[test]
+fn merges_limited_to_a_registry_only_take_its_crates_and_assets() {
+    let (source, destination) = (
+        TempDb::new("merge-registry-source"),
+        TempDb::new("merge-registry-destination"),
+    );
+    let mut progress = prodash::Tree::new().add_child("merge");
+    let crates = source.open_crates().unwrap();
+    let tasks = source.open_tasks().unwrap();
+    for name in ["a", "acme/a"] {
+        crates.upsert(&mut progress, name, &version(name, "1.0.0")).unwrap();
+        tasks
+            .insert(
+                &mut progress,
+                format!("{}:1.0.0:download:1.0.0", name),
+                &task(TaskState::Complete, 0),
+            )
+            .unwrap();
+    }
+    let assets = source.dir.join("assets");
+    let acme_asset = Path::new("registries")
+        .join("acme")
+        .join("1")
+        .join("a")
+        .join("1.0.0.crate");
+    let crates_io_asset = Path::new("1").join("a").join("1.0.0.crate");
+    for asset in [&acme_asset, &crates_io_asset] {
+        std::fs::create_dir_all(assets.join(asset).parent().unwrap()).unwrap();
+        std::fs::write(assets.join(asset), "source").unwrap();
+    }
+    let acme = Registry {
+        name: "acme".into(),
+        index_url: "https://git.example.com/index.git".into(),
+    };
+
+    merge_blocking(&source.dir, &destination.dir, Some(&acme)).unwrap();
+    let crates = destination.open_crates().unwrap();
+    let tasks = destination.open_tasks().unwrap();
+    assert_eq!((crates.count(), tasks.count()), (1, 1));
+    assert!(crates.get("acme/a").unwrap().is_some());
+    let destination_assets = destination.dir.join("assets");
+    assert!(destination_assets.join(&acme_asset).is_file());
+    assert!(!destination_assets.join(&crates_io_asset).exists());
+
+    merge_blocking(&source.dir, &destination.dir, Some(&Registry::default())).unwrap();
+    assert_eq!((crates.count(), tasks.count()), (2, 2));
+    assert!(destination_assets.join(&crates_io_asset).is_file());
+}
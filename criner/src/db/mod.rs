@@ -0,0 +1,5 @@
+mod dump_key;
+mod merge;
+
+pub use dump_key::dump_key_blocking;
+pub use merge::merge_blocking;
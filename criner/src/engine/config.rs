@@ -0,0 +1,175 @@
+//! Settings which are safe to change while mining, read from the file given with `--config` and applied again whenever
+//! it changes, like
+//!
+//! ```toml
+//! io = 20
+//! max-download-bandwidth = "10MiB/s"
+//! report-glob = "serde*"
+//! ```
+use crate::{
+    engine::work::iobound::{parse_bandwidth, HostLimits},
+    error::Result,
+    Error,
+};
+use async_io::Timer;
+use log::{info, warn};
+use serde_derive::Deserialize;
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+const CONFIG_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The settings in a config file, each of which overrides its command-line argument if set
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct File {
+    io: Option<u32>,
+    cpu: Option<u32>,
+    cpu_o: Option<u32>,
+    max_requests_per_second: Option<f64>,
+    max_download_bandwidth: Option<String>,
+    report_glob: Option<String>,
+}
+
+/// The settings which can change while mining. Pool sizes and the report glob apply to the next run of their stage,
+/// limits of requests and bandwidth to the next request.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tunables {
+    pub io_bound_processors: u32,
+    pub cpu_bound_processors: u32,
+    pub cpu_o_bound_processors: u32,
+    pub max_requests_per_second: Option<f64>,
+    pub max_download_bandwidth: Option<u64>,
+    pub report_glob: Option<String>,
+}
+
+impl fmt::Display for Tunables {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "io = {}, cpu = {}, cpu-o = {}, max-requests-per-second = {}, max-download-bandwidth = {}, report-glob = {}",
+            self.io_bound_processors,
+            self.cpu_bound_processors,
+            self.cpu_o_bound_processors,
+            self.max_requests_per_second
+                .map_or_else(|| "unlimited".into(), |r| r.to_string()),
+            self.max_download_bandwidth.map_or_else(
+                || "unlimited".into(),
+                |b| format!("{}/s", bytesize::ByteSize(b).to_string_as(true))
+            ),
+            self.report_glob.as_deref().unwrap_or("none"),
+        )
+    }
+}
+
+impl Tunables {
+    /// Parse the config file `content` and apply its settings to these, which are the ones given on the command-line
+    pub fn with_config(&self, content: &str) -> Result<Tunables> {
+        let file: File = toml::from_str(content).map_err(|err| Error::Message(err.to_string()))?;
+        let max_requests_per_second = match file.max_requests_per_second {
+            Some(rate) if rate.is_finite() && rate > 0.0 => Some(rate),
+            Some(rate) => {
+                return Err(Error::Message(format!(
+                    "Invalid max-requests-per-second {}, expected a positive number",
+                    rate
+                )))
+            }
+            None => self.max_requests_per_second,
+        };
+        let max_download_bandwidth = match file.max_download_bandwidth {
+            Some(bandwidth) => Some(parse_bandwidth(&bandwidth).map_err(Error::Message)?),
+            None => self.max_download_bandwidth,
+        };
+        Ok(Tunables {
+            io_bound_processors: file.io.unwrap_or(self.io_bound_processors),
+            cpu_bound_processors: file.cpu.unwrap_or(self.cpu_bound_processors),
+            cpu_o_bound_processors: file.cpu_o.unwrap_or(self.cpu_o_bound_processors),
+            max_requests_per_second,
+            max_download_bandwidth,
+            report_glob: file.report_glob.or_else(|| self.report_glob.clone()),
+        })
+    }
+}
+
+/// The current `Tunables`, shared by all stages
+#[derive(Clone)]
+pub struct Live {
+    defaults: Arc<Tunables>,
+    current: Arc<parking_lot::Mutex<Tunables>>,
+}
+
+impl Live {
+    /// Use `defaults` as given on the command-line until a config file is loaded
+    pub fn new(defaults: Tunables) -> Self {
+        Live {
+            current: Arc::new(parking_lot::Mutex::new(defaults.clone())),
+            defaults: Arc::new(defaults),
+        }
+    }
+
+    pub fn get(&self) -> Tunables {
+        self.current.lock().clone()
+    }
+
+    /// Apply the config file `content` to the defaults and return true if that changed the current settings
+    pub fn apply(&self, content: &str) -> Result<bool> {
+        let tunables = self.defaults.with_config(content)?;
+        let mut current = self.current.lock();
+        Ok(if *current == tunables {
+            false
+        } else {
+            *current = tunables;
+            true
+        })
+    }
+
+    /// Load the config file at `path`, failing if it can't be read or parsed
+    pub fn load(&self, path: &Path) -> Result<()> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| Error::Message(format!("Could not read config file '{}': {}", path.display(), err)))?;
+        self.apply(&content)
+            .map_err(|err| Error::Message(format!("Invalid config file '{}': {}", path.display(), err)))?;
+        Ok(())
+    }
+}
+
+/// Check the config file at `path` for changes forever and apply them to `live` and `host_limits`.
+/// Invalid or missing files are reported once and leave the current settings as they are.
+pub async fn watch(path: PathBuf, live: Live, host_limits: HostLimits) {
+    let mut last_seen = std::fs::read_to_string(&path).map_err(|err| err.to_string());
+    loop {
+        Timer::after(CONFIG_CHECK_INTERVAL).await;
+        let content = std::fs::read_to_string(&path).map_err(|err| err.to_string());
+        if content == last_seen {
+            continue;
+        }
+        match &content {
+            Ok(content) => match live.apply(content) {
+                Ok(true) => {
+                    let tunables = live.get();
+                    host_limits.set_rates(tunables.max_requests_per_second, tunables.max_download_bandwidth);
+                    info!("Applied changed config file '{}': {}", path.display(), tunables);
+                }
+                Ok(false) => {}
+                Err(err) => warn!(
+                    "Ignoring invalid config file '{}', keeping the current settings: {}",
+                    path.display(),
+                    err
+                ),
+            },
+            Err(err) => warn!(
+                "Could not read config file '{}', keeping the current settings: {}",
+                path.display(),
+                err
+            ),
+        }
+        last_seen = content;
+    }
+}
+
+#[cfg(test)]
+mod config_test;
@@ -0,0 +1,60 @@
+use super::{Live, Tunables};
+
+fn from_arguments() -> Tunables {
+    Tunables {
+        io_bound_processors: 10,
+        cpu_bound_processors: 2,
+        cpu_o_bound_processors: 10,
+        max_requests_per_second: None,
+        max_download_bandwidth: Some(1024),
+        report_glob: Some("a*".into()),
+    }
+}
+
+#[test]
+fn settings_in_the_config_override_arguments() {
+    assert_eq!(
+        from_arguments()
+            .with_config(
+                r#"
+io = 20
+max-requests-per-second = 0.5
+max-download-bandwidth = "2KiB/s"
+report-glob = "serde*"
+"#
+            )
+            .unwrap(),
+        Tunables {
+            io_bound_processors: 20,
+            max_requests_per_second: Some(0.5),
+            max_download_bandwidth: Some(2048),
+            report_glob: Some("serde*".into()),
+            ..from_arguments()
+        }
+    );
+    assert_eq!(from_arguments().with_config("").unwrap(), from_arguments());
+}
+
+#[test]
+fn invalid_configs_are_rejected() {
+    for config in [
+        "io = -1",
+        "max-requests-per-second = 0.0",
+        "max-download-bandwidth = \"fast\"",
+        "durability = \"none\"",
+    ] {
+        assert!(from_arguments().with_config(config).is_err(), "{}", config);
+    }
+}
+
+#[test]
+fn removed_settings_fall_back_to_arguments() {
+    let live = Live::new(from_arguments());
+    assert!(live.apply("cpu = 4").unwrap());
+    assert_eq!(live.get().cpu_bound_processors, 4);
+    assert!(!live.apply("cpu = 4\n").unwrap(), "nothing changed");
+    assert!(live.apply("").unwrap());
+    assert_eq!(live.get(), from_arguments());
+    assert!(live.apply("cpu = \"many\"").is_err());
+    assert_eq!(live.get(), from_arguments(), "invalid configs change nothing");
+}
@@ -0,0 +1,103 @@
+//! A live view of where a single crate is in the pipeline, shown in the information pane of the TUI
+use crate::{
+    engine::work::task_graph,
+    model::TaskState,
+    persistence::{self, key_prefix_upper_bound, ErrorTelemetryTable, ReportsTree, TableAccess, KEY_SEP_CHAR},
+    timeline::tasks_with_key_prefix,
+    Result,
+};
+use prodash::render::tui::Line;
+use rusqlite::{params, OptionalExtension};
+
+/// The amount of most recent versions to show
+const MAX_VERSIONS: usize = 8;
+
+fn describe_state(state: &TaskState) -> String {
+    match state {
+        TaskState::NotStarted => "not started".into(),
+        TaskState::Complete => "done".into(),
+        TaskState::InProgress(_) => "in progress".into(),
+        TaskState::AttemptsWithFailure(errors) => format!("failed {}x", errors.len()),
+        TaskState::PermanentFailure(errors) => format!("failed {}x for good", errors.len()),
+    }
+}
+
+/// Names of the reports which were generated for keys starting with `prefix`
+fn reports_done(connection: &rusqlite::Connection, prefix: &str) -> Result<Vec<String>> {
+    let mut statement = connection.prepare(&format!(
+        "SELECT key FROM {} WHERE key >= ?1 AND key < ?2 ORDER BY key",
+        ReportsTree::table_name()
+    ))?;
+    let reports = statement
+        .query_map(params![prefix, key_prefix_upper_bound(prefix)], |r| {
+            r.get::<_, String>(0)
+        })?
+        .filter_map(|key| key.ok())
+        .filter_map(|key| key[prefix.len()..].split(KEY_SEP_CHAR).next().map(ToOwned::to_owned))
+        .collect();
+    Ok(reports)
+}
+
+fn last_error(connection: &rusqlite::Connection, prefix: &str) -> Result<Option<(String, String)>> {
+    Ok(connection
+        .query_row(
+            &format!(
+                "SELECT category, message FROM {} WHERE key >= ?1 AND key < ?2
+                 ORDER BY occurred_at DESC LIMIT 1",
+                ErrorTelemetryTable::table_name()
+            ),
+            params![prefix, key_prefix_upper_bound(prefix)],
+            |r| Ok((r.get(0)?, r.get(1)?)),
+        )
+        .optional()?)
+}
+
+/// Describe the most recent versions of `crate_name` with the state of their tasks in the order they run, their last
+/// error and the reports which were generated for them.
+pub fn lines(db: &persistence::Db, crate_name: &str) -> Result<Vec<Line>> {
+    let mut lines = vec![Line::Title(format!("Inspect '{}'", crate_name))];
+    let krate = match db.open_crates()?.get(crate_name)? {
+        Some(krate) => krate,
+        None => {
+            lines.push(Line::Text("not in the crates.io index (yet)".into()));
+            return Ok(lines);
+        }
+    };
+    let connection = db.open_connection_no_async_with_busy_wait()?;
+    lines.push(Line::Text(format!("{} version(s)", krate.versions.len())));
+    let order: Vec<_> = task_graph::in_order().into_iter().map(|node| node.process()).collect();
+    for version in krate.versions.iter().rev().take(MAX_VERSIONS) {
+        let prefix = format!("{}{sep}{}{sep}", crate_name, version, sep = KEY_SEP_CHAR);
+        lines.push(Line::Title(version.clone()));
+        let mut tasks = tasks_with_key_prefix(&connection, &prefix)?;
+        tasks.sort_by_key(|(_, task)| {
+            order
+                .iter()
+                .position(|process| *process == task.process)
+                .unwrap_or(usize::MAX)
+        });
+        if tasks.is_empty() {
+            lines.push(Line::Text("no tasks scheduled yet".into()));
+        }
+        for (key, task) in tasks {
+            lines.push(Line::Text(format!(
+                "{}: {}",
+                &key[prefix.len()..],
+                describe_state(&task.state)
+            )));
+        }
+        let reports = reports_done(&connection, &prefix)?;
+        lines.push(Line::Text(if reports.is_empty() {
+            "reports: none yet".into()
+        } else {
+            format!("reports: {}", reports.join(", "))
+        }));
+        if let Some((category, message)) = last_error(&connection, &prefix)? {
+            lines.push(Line::Text(format!("last error ({}): {}", category, message)));
+        }
+    }
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod inspect_test;
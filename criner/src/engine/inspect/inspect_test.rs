@@ -0,0 +1,109 @@
+use crate::{
+    engine::{
+        inspect::lines,
+        work::{artifact, cpubound},
+    },
+    model::{CrateVersion, Task, TaskState},
+    persistence::{new_key_insertion, temp_db::TempDb, ReportsTree, TableAccess},
+    utils::crate_name_to_inspect,
+};
+use prodash::render::tui::Line;
+
+fn text(line: &str) -> Line {
+    Line::Text(line.into())
+}
+
+fn title(line: &str) -> Line {
+    Line::Title(line.into())
+}
+
+#[test]
+fn crate_names_are_read_without_surrounding_whitespace() {
+    assert_eq!(crate_name_to_inspect("serde\n").as_deref(), Some("serde"));
+    assert_eq!(
+        crate_name_to_inspect("  serde_json \r\n").as_deref(),
+        Some("serde_json")
+    );
+    assert_eq!(crate_name_to_inspect(""), None);
+    assert_eq!(crate_name_to_inspect(" \n"), None, "an emptied file stops inspecting");
+}
+
+#[test]
+fn crates_not_in_the_index_are_reported_as_such() {
+    let db = TempDb::new("inspect-unknown");
+    assert_eq!(
+        lines(&db, "unknown").unwrap(),
+        vec![title("Inspect 'unknown'"), text("not in the crates.io index (yet)")]
+    );
+}
+
+#[test]
+fn the_most_recent_versions_show_their_tasks_in_the_order_they_run_with_reports_and_last_error() {
+    let db = TempDb::new("inspect");
+    let mut progress = prodash::Tree::new().add_child("inspect");
+    let crates = db.open_crates().unwrap();
+    let versions: Vec<_> = (0..10).map(|minor| format!("1.{}.0", minor)).collect();
+    for version in &versions {
+        let crate_version = CrateVersion {
+            name: "a".into(),
+            version: version.clone(),
+            ..Default::default()
+        };
+        crates.upsert(&mut progress, "a", &crate_version).unwrap();
+    }
+
+    let tasks = db.open_tasks().unwrap();
+    let mut key = String::new();
+    for (task, state) in [
+        (artifact::REPO_TARBALL.task(), TaskState::Complete),
+        (artifact::CRATE.task(), TaskState::Complete),
+        (
+            cpubound::meta_data_extraction_task(),
+            TaskState::PermanentFailure(vec!["bad archive".into()]),
+        ),
+        (
+            cpubound::default_persisted_extraction_task(),
+            TaskState::AttemptsWithFailure(vec!["bad archive".into(), "bad archive".into()]),
+        ),
+    ] {
+        key.clear();
+        task.fq_key("a", "1.9.0", &mut key);
+        tasks.insert(&mut progress, &key, &Task { state, ..task }).unwrap();
+    }
+    let connection = db.open_connection_no_async_with_busy_wait().unwrap();
+    let mut insert = new_key_insertion(ReportsTree::table_name(), &connection).unwrap();
+    for report in ["waste", "licenses"] {
+        key.clear();
+        ReportsTree::key_buf("a", "1.9.0", report, "1.0.0", &mut key);
+        insert.execute([key.as_str()]).unwrap();
+    }
+    db.open_error_telemetry()
+        .unwrap()
+        .record("extraction", "a:1.9.0:extract_crate:1.0.0", "bad archive")
+        .unwrap();
+
+    let task_line = |task: Task, state: &str| text(&format!("{}:{}: {}", task.process, task.version, state));
+    let mut expected = vec![
+        title("Inspect 'a'"),
+        text("10 version(s)"),
+        title("1.9.0"),
+        task_line(artifact::CRATE.task(), "done"),
+        task_line(artifact::REPO_TARBALL.task(), "done"),
+        task_line(cpubound::default_persisted_extraction_task(), "failed 2x"),
+        task_line(cpubound::meta_data_extraction_task(), "failed 1x for good"),
+        text("reports: licenses, waste"),
+        text("last error (extraction): bad archive"),
+    ];
+    for version in versions[2..9].iter().rev() {
+        expected.extend(vec![
+            title(version),
+            text("no tasks scheduled yet"),
+            text("reports: none yet"),
+        ]);
+    }
+    assert_eq!(
+        lines(&db, "a").unwrap(),
+        expected,
+        "only the 8 most recent versions are shown, most recent first"
+    );
+}
@@ -0,0 +1,122 @@
+//! Metrics of a running miner in the text format of Prometheus, served over HTTP for alerting when mining stalls.
+//!
+//! Counters start at zero with each run, which Prometheus handles as a counter reset.
+use crate::{
+    engine::work::{cpubound, iobound},
+    http::{self, Response},
+    persistence::Db,
+    stats, Result,
+};
+use std::{collections::BTreeMap, fmt::Write as _, net::SocketAddr};
+
+/// The events of tasks which are counted, in the order they happen
+const TASK_EVENTS: &[&str] = &["started", "retrying", "failed", "finished"];
+
+/// The amount of events of tasks by process and event, since the process started
+static TASK_EVENT_COUNTS: parking_lot::Mutex<BTreeMap<(String, &'static str), u64>> =
+    parking_lot::const_mutex(BTreeMap::new());
+
+/// Count `event` of a task of `process`, which is one of `TASK_EVENTS`
+pub(crate) fn count_task_event(process: &str, event: &'static str) {
+    debug_assert!(TASK_EVENTS.contains(&event), "unknown task event");
+    *TASK_EVENT_COUNTS.lock().entry((process.to_owned(), event)).or_default() += 1;
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Render all metrics of the miner working on `db`
+pub fn render(db: &Db, needed_extractions: Option<&cpubound::NeededExtractions>) -> Result<String> {
+    let mut out = String::new();
+    let (context, _) = stats::context_totals(db)?;
+    let mut metric = |name: &str, kind: &str, help: &str, samples: &[(String, u64)]| {
+        writeln!(out, "# HELP {} {}", name, help).ok();
+        writeln!(out, "# TYPE {} {}", name, kind).ok();
+        for (labels, value) in samples {
+            writeln!(out, "{}{} {}", name, labels, value).ok();
+        }
+    };
+    metric(
+        "criner_crates",
+        "gauge",
+        "The amount of crates in the database",
+        &[(String::new(), context.counts.crates as u64)],
+    );
+    metric(
+        "criner_crate_versions",
+        "gauge",
+        "The amount of crate versions in the database",
+        &[(String::new(), context.counts.crate_versions)],
+    );
+
+    let counts = TASK_EVENT_COUNTS.lock().clone();
+    metric(
+        "criner_task_events_total",
+        "counter",
+        "The amount of events of tasks by process, like started, retrying, failed and finished",
+        &counts
+            .iter()
+            .map(|((process, event), count)| {
+                (
+                    format!("{{process=\"{}\",event=\"{}\"}}", escape_label(process), event),
+                    *count,
+                )
+            })
+            .collect::<Vec<_>>(),
+    );
+    let mut started_and_ended = BTreeMap::<&str, (u64, u64)>::new();
+    for ((process, event), count) in counts.iter() {
+        let (started, ended) = started_and_ended.entry(process).or_default();
+        match *event {
+            "started" => *started += count,
+            _ => *ended += count,
+        }
+    }
+    metric(
+        "criner_tasks_in_progress",
+        "gauge",
+        "The amount of tasks being worked on by process",
+        &started_and_ended
+            .into_iter()
+            .map(|(process, (started, ended))| {
+                (
+                    format!("{{process=\"{}\"}}", escape_label(process)),
+                    started.saturating_sub(ended),
+                )
+            })
+            .collect::<Vec<_>>(),
+    );
+    metric(
+        "criner_downloaded_bytes_total",
+        "counter",
+        "The amount of bytes received by all downloads",
+        &[(String::new(), iobound::bytes_downloaded())],
+    );
+    metric(
+        "criner_queue_depth",
+        "gauge",
+        "The amount of requests waiting in a queue",
+        &[(
+            "{queue=\"needed_extractions\"}".into(),
+            needed_extractions.map_or(0, |n| n.len()) as u64,
+        )],
+    );
+    Ok(out)
+}
+
+/// Serve metrics of the miner working on `db` at `http://<addr>/metrics` from a thread of its own, with a thread for
+/// each connection. Returns the address the metrics are served at, which differs from `addr` if its port is 0. The queue
+/// depth of `needed_extractions` is only known if the miner runs in this process.
+pub fn serve(addr: SocketAddr, db: Db, needed_extractions: Option<cpubound::NeededExtractions>) -> Result<SocketAddr> {
+    http::serve(addr, "metrics", move |path| match path {
+        Some("/metrics") => match render(&db, needed_extractions.as_ref()) {
+            Ok(metrics) => Response::ok("text/plain; version=0.0.4", metrics),
+            Err(err) => Response::text("500 Internal Server Error", err.to_string()),
+        },
+        _ => Response::text("404 Not Found", "Metrics are served at /metrics"),
+    })
+}
+
+#[cfg(test)]
+mod metrics_test;
@@ -0,0 +1,54 @@
+use super::{count_task_event, serve};
+use crate::persistence::temp_db::TempDb;
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+};
+
+fn get(addr: std::net::SocketAddr, path: &str) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(stream, "GET {} HTTP/1.1\r\nhost: localhost\r\n\r\n", path).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn metrics_are_served_in_the_prometheus_text_format() {
+    let db = TempDb::new("metrics");
+    let addr = serve("127.0.0.1:0".parse().unwrap(), db.db.clone(), None).unwrap();
+    for event in ["started", "started", "finished", "started", "failed"] {
+        count_task_event("metrics-test", event);
+    }
+
+    let response = get(addr, "/metrics");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+    assert!(response.contains("content-type: text/plain; version=0.0.4\r\n"));
+    for line in [
+        "# TYPE criner_crates gauge",
+        "criner_crates 0",
+        "criner_crate_versions 0",
+        "criner_task_events_total{process=\"metrics-test\",event=\"started\"} 3",
+        "criner_task_events_total{process=\"metrics-test\",event=\"failed\"} 1",
+        "criner_tasks_in_progress{process=\"metrics-test\"} 1",
+        "criner_queue_depth{queue=\"needed_extractions\"} 0",
+    ] {
+        assert!(
+            response.lines().any(|l| l == line),
+            "'{}' is missing in {}",
+            line,
+            response
+        );
+    }
+    assert!(response.contains("\ncriner_downloaded_bytes_total "));
+
+    assert!(get(addr, "/").starts_with("HTTP/1.1 404 Not Found\r\n"));
+}
+
+#[test]
+fn clients_which_never_send_a_request_do_not_block_others() {
+    let db = TempDb::new("metrics-stalled");
+    let addr = serve("127.0.0.1:0".parse().unwrap(), db.db.clone(), None).unwrap();
+    let _stalled = TcpStream::connect(addr).unwrap();
+    assert!(get(addr, "/metrics").starts_with("HTTP/1.1 200 OK\r\n"));
+}
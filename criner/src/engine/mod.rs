@@ -1,5 +1,10 @@
+pub mod config;
+pub mod inspect;
+pub mod metrics;
+pub mod recovery;
 pub mod report;
 pub mod stage;
+pub mod summary;
 pub mod work;
 
 pub mod run;
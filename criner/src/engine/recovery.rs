@@ -0,0 +1,58 @@
+//! Bringing the database back into a consistent state after the previous run ended, no matter how.
+//!
+//! Each check touches only what a crash can leave behind, so recovery takes about the same time after every run and
+//! does nothing if the previous one ended cleanly.
+use crate::{error::Result, persistence::Db};
+use std::fmt;
+
+/// What was found and repaired when starting up
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Recovery {
+    /// The schema version of the database, which this version of criner supports
+    pub schema_version: u32,
+    /// Tasks which were claimed by processors of the previous run but whose outcome was never recorded
+    pub tasks_reconciled: usize,
+    /// Markers of done reports which were cut short
+    pub report_markers_removed: usize,
+}
+
+impl Recovery {
+    /// Returns true if the previous run didn't leave anything to repair
+    pub fn is_clean(&self) -> bool {
+        self.tasks_reconciled == 0 && self.report_markers_removed == 0
+    }
+}
+
+impl fmt::Display for Recovery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "schema version {}, {} interrupted task(s) will be retried, {} malformed report marker(s) removed",
+            self.schema_version, self.tasks_reconciled, self.report_markers_removed
+        )
+    }
+}
+
+/// Verify that we can work with `db` and repair what an interrupted run may have left behind.
+/// It's safe to call any amount of times, and fails only if the database was written by a newer version of criner
+/// which this one can't read.
+pub fn recover(db: &Db) -> Result<Recovery> {
+    let schema_version = db.verify_schema_version()?;
+    db.compatibility()?.verify()?;
+    let tasks_reconciled = db.open_journal()?.reconcile(&db.open_tasks()?)?;
+    let report_markers_removed = db.open_reports()?.remove_malformed()?;
+    Ok(Recovery {
+        schema_version,
+        tasks_reconciled,
+        report_markers_removed,
+    })
+}
+
+/// Store the tasks which processors were working on when the engine was stopped as failed, so the next run retries them
+/// right away. Call it only once the engine was dropped, or tasks which are still worked on would fail as well.
+pub fn interrupt(db: &Db) -> Result<usize> {
+    db.open_journal()?.interrupt(&db.open_tasks()?)
+}
+
+#[cfg(test)]
+mod recovery_test;
@@ -0,0 +1,81 @@
+use super::{interrupt, recover};
+use crate::{
+    model::TaskState,
+    persistence::{temp_db::TempDb, ReportsTree, TableAccess, SCHEMA_VERSION},
+    Error,
+};
+
+#[test]
+fn interrupted_runs_are_repaired_once() {
+    let db = TempDb::new("recovery-repair");
+    let mut progress = prodash::Tree::new().add_child("recovery");
+    let mut task = crate::engine::work::cpubound::default_persisted_extraction_task();
+    task.state = crate::model::TaskState::InProgress(None);
+    db.open_tasks()
+        .unwrap()
+        .insert(&mut progress, "a:1.0.0:extract_crate:1.0.0", &task)
+        .unwrap();
+    db.open_journal()
+        .unwrap()
+        .claim(&mut progress, "a:1.0.0:extract_crate:1.0.0")
+        .unwrap();
+    {
+        let connection = db.open_connection_no_async_with_busy_wait().unwrap();
+        for key in ["a:1.0.0:waste:1.2.0", "a:1.0.0:was"] {
+            connection
+                .execute(
+                    &format!("INSERT INTO {} (key) VALUES (?1)", ReportsTree::table_name()),
+                    [key],
+                )
+                .unwrap();
+        }
+    }
+
+    let recovery = recover(&db).unwrap();
+    assert_eq!(recovery.schema_version, SCHEMA_VERSION);
+    assert_eq!(recovery.tasks_reconciled, 1);
+    assert_eq!(
+        recovery.report_markers_removed, 1,
+        "only the marker which was cut short"
+    );
+    assert!(db.open_reports().unwrap().is_done("a:1.0.0:waste:1.2.0"));
+
+    assert!(recover(&db).unwrap().is_clean(), "there is nothing left to repair");
+}
+
+#[test]
+fn tasks_in_progress_when_stopping_fail_as_interrupted() {
+    let db = TempDb::new("recovery-interrupt");
+    let mut progress = prodash::Tree::new().add_child("recovery");
+    let tasks = db.open_tasks().unwrap();
+    let mut task = crate::engine::work::cpubound::default_persisted_extraction_task();
+    task.state = TaskState::InProgress(None);
+    tasks
+        .insert(&mut progress, "a:1.0.0:extract_crate:1.0.0", &task)
+        .unwrap();
+    db.open_journal()
+        .unwrap()
+        .claim(&mut progress, "a:1.0.0:extract_crate:1.0.0")
+        .unwrap();
+
+    assert_eq!(interrupt(&db).unwrap(), 1);
+    let task = tasks.get("a:1.0.0:extract_crate:1.0.0").unwrap().unwrap();
+    assert!(matches!(&task.state, TaskState::AttemptsWithFailure(f) if f == &[TaskState::INTERRUPTED]));
+    assert!(
+        recover(&db).unwrap().is_clean(),
+        "the next run doesn't have to reconcile anything"
+    );
+}
+
+#[test]
+fn databases_of_newer_versions_are_refused() {
+    let db = TempDb::new("recovery-newer");
+    db.open_connection_no_async_with_busy_wait()
+        .unwrap()
+        .execute_batch(&format!("PRAGMA user_version = {}", SCHEMA_VERSION + 1))
+        .unwrap();
+    assert!(matches!(
+        recover(&db),
+        Err(Error::SchemaVersion { found, supported }) if found == SCHEMA_VERSION + 1 && supported == SCHEMA_VERSION
+    ));
+}
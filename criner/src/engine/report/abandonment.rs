@@ -0,0 +1,336 @@
+//! How likely crates are to be abandoned, judging by when they were last published, how many of their versions were
+//! yanked and whether they point to a repository, with a focus on crates many others depend on.
+//!
+//! Publish dates and repositories come from the crates.io database dump, so crates of crates.io are only reported once
+//! it was downloaded, while crates of other registries never get a score. Scores are computed when writing pages,
+//! relative to the time reports are generated at.
+use crate::{
+    engine::report::{
+        generic,
+        index::IndexLookup,
+        versioned::{self, write_page, Dict},
+    },
+    error::Result,
+    model::CrateVersion,
+    persistence,
+    registry::split_crate_name,
+    utils::parse_semver,
+};
+use async_trait::async_trait;
+use horrorshow::html;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::{Duration, SystemTime},
+};
+
+const TOP_LIST: usize = 100;
+/// The score from which on crates appear unmaintained
+pub const UNMAINTAINED_RISK: u32 = 50;
+const DAY: Duration = Duration::from_secs(60 * 60 * 24);
+const YEAR: Duration = Duration::from_secs(60 * 60 * 24 * 365);
+
+/// What's known about a single crate version
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct VersionActivity {
+    /// When it was published, if the crates.io database dump knows about it
+    pub published_at: Option<SystemTime>,
+    pub yanked: bool,
+    /// The repository of the crate as of the last crates.io database dump
+    pub repository: Option<String>,
+    /// The crates it depends on, excluding dev dependencies
+    pub dependencies: BTreeSet<String>,
+}
+
+/// The activity of all versions of a crate
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct ActivitySummary {
+    pub versions: u64,
+    pub yanked: u64,
+    /// When any of its versions was published last
+    pub last_published_at: Option<SystemTime>,
+    pub latest_version: String,
+    pub latest: VersionActivity,
+}
+
+impl ActivitySummary {
+    fn merge(mut self, other: ActivitySummary) -> ActivitySummary {
+        self.versions += other.versions;
+        self.yanked += other.yanked;
+        self.last_published_at = self.last_published_at.max(other.last_published_at);
+        if versioned::is_later(&other.latest_version, &self.latest_version) {
+            self.latest_version = other.latest_version;
+            self.latest = other.latest;
+        }
+        self
+    }
+}
+
+/// The risk of a crate to be abandoned as of `now` from 0 to 100, along with the reasons for it, or `None` if its
+/// publish dates are unknown.
+pub fn abandonment_risk(summary: &ActivitySummary, now: SystemTime) -> Option<(u32, Vec<String>)> {
+    let last_published_at = summary.last_published_at?;
+    let mut risk = 0;
+    let mut reasons = Vec::new();
+
+    let since_publish = now.duration_since(last_published_at).unwrap_or_default();
+    if since_publish >= YEAR {
+        risk += ((since_publish.as_secs() / (YEAR.as_secs() / 20)) as u32).min(60);
+        reasons.push(format!(
+            "last published {} days ago",
+            since_publish.as_secs() / DAY.as_secs()
+        ));
+    }
+    if summary.latest.yanked {
+        risk += 20;
+        reasons.push("its latest version is yanked".into());
+    } else if summary.yanked > 0 {
+        risk += (summary.yanked * 10 / summary.versions.max(1)) as u32;
+        reasons.push(format!(
+            "{} of {} versions are yanked",
+            summary.yanked, summary.versions
+        ));
+    }
+    if summary.latest.repository.is_none() {
+        risk += 20;
+        reasons.push("no repository".into());
+    }
+    Some((risk.min(100), reasons))
+}
+
+impl versioned::Analysis for VersionActivity {
+    type Summary = ActivitySummary;
+
+    fn name() -> &'static str {
+        "abandonment"
+    }
+
+    fn version() -> &'static str {
+        "1.0.0"
+    }
+
+    fn add_to_summary(&self, crate_version: &str, summary: &mut Self::Summary) {
+        let this_version = ActivitySummary {
+            versions: 1,
+            yanked: self.yanked as u64,
+            last_published_at: self.published_at,
+            latest_version: crate_version.to_owned(),
+            latest: self.clone(),
+        };
+        *summary = std::mem::take(summary).merge(this_version);
+    }
+
+    fn merge_summaries(lhs: Self::Summary, rhs: Self::Summary) -> Self::Summary {
+        lhs.merge(rhs)
+    }
+
+    fn write_version_html(
+        &self,
+        crate_name: &str,
+        crate_version: &str,
+        generated_at: SystemTime,
+        out: &mut Vec<u8>,
+    ) -> Result<()> {
+        write_page(
+            &format!("Activity of {}:{}", crate_name, crate_version),
+            html! {
+                ul {
+                    li: match self.published_at {
+                        Some(at) => format!("published at {}", humantime::format_rfc3339_seconds(at)),
+                        None => "publish date unknown".into(),
+                    };
+                    @ if self.yanked {
+                        li: "yanked";
+                    }
+                    li: match self.repository.as_ref() {
+                        Some(repository) => format!("repository: {}", repository),
+                        None => "no repository".into(),
+                    };
+                    li: format!("{} dependencies", self.dependencies.len());
+                }
+            },
+            generated_at,
+            out,
+        )
+    }
+
+    fn write_crate_html(
+        crate_name: &str,
+        info_by_version: &Dict<Self>,
+        generated_at: SystemTime,
+        out: &mut Vec<u8>,
+    ) -> Result<()> {
+        let mut versions: Vec<_> = info_by_version.iter().collect();
+        versions.sort_by_key(|(version, _)| parse_semver(version).ok());
+        let summary = info_by_version
+            .iter()
+            .fold(ActivitySummary::default(), |mut s, (version, info)| {
+                info.add_to_summary(version, &mut s);
+                s
+            });
+        let risk = abandonment_risk(&summary, generated_at);
+        write_page(
+            &format!("Abandonment risk of {}", crate_name),
+            html! {
+                section {
+                    @ if let Some((risk, reasons)) = risk.as_ref() {
+                        p: format!("Risk {} of 100{}", risk, if reasons.is_empty() { String::new() } else { format!(": {}", reasons.join(", ")) });
+                    } else {
+                        p: "Publish dates are unknown until the crates.io database dump was downloaded";
+                    }
+                }
+                section {
+                    h3: "Versions";
+                    ol {
+                        @ for (version, info) in versions.into_iter().rev() {
+                            li {
+                                a(href=format!("{}.html", version)): version;
+                                @ if let Some(at) = info.published_at {
+                                    : format!(" - {}", humantime::format_rfc3339_seconds(at));
+                                }
+                                @ if info.yanked {
+                                    : " (yanked)";
+                                }
+                            }
+                        }
+                    }
+                }
+            },
+            generated_at,
+            out,
+        )
+    }
+
+    fn write_collection_html(
+        info_by_crate: &Dict<Self::Summary>,
+        generated_at: SystemTime,
+        out: &mut Vec<u8>,
+    ) -> Result<()> {
+        let now = generated_at;
+        let mut dependents = BTreeMap::<&str, u64>::new();
+        for summary in info_by_crate.values() {
+            for name in summary.latest.dependencies.iter() {
+                *dependents.entry(name).or_default() += 1;
+            }
+        }
+        let scored: Vec<_> = info_by_crate
+            .iter()
+            .filter_map(|(name, summary)| {
+                abandonment_risk(summary, now)
+                    .map(|(risk, reasons)| (name, risk, reasons, dependents.get(name.as_str()).copied().unwrap_or(0)))
+            })
+            .collect();
+        let mut unmaintained: Vec<_> = scored
+            .iter()
+            .filter(|(_, risk, _, _)| *risk >= UNMAINTAINED_RISK)
+            .collect();
+        unmaintained.sort_by_key(|(name, risk, _, dependents)| {
+            (std::cmp::Reverse(*dependents), std::cmp::Reverse(*risk), *name)
+        });
+        let mut by_risk = BTreeMap::<u32, usize>::new();
+        for (_, risk, _, _) in scored.iter() {
+            *by_risk.entry(risk / 10 * 10).or_default() += 1;
+        }
+        write_page(
+            "Crates.io Abandonment Risk Report",
+            html! {
+                section {
+                    h3: format!("Risk of {} crates with known publish dates, out of {}", scored.len(), info_by_crate.len());
+                    ul {
+                        @ for (risk, count) in by_risk.iter() {
+                            li: format!("{} to {}: {} crates", risk, (risk + 9).min(100), count);
+                        }
+                    }
+                }
+                section {
+                    h3: format!("Most depended-on crates which appear unmaintained, with a risk of {} or more", UNMAINTAINED_RISK);
+                    ol {
+                        @ for (name, risk, reasons, dependents) in unmaintained.into_iter().take(TOP_LIST) {
+                            li {
+                                a(href=format!("{}/index.html", name)): name.as_str();
+                                : format!(" used by {} crates, risk {}: {}", dependents, risk, reasons.join(", "));
+                            }
+                        }
+                    }
+                }
+            },
+            generated_at,
+            out,
+        )
+    }
+}
+
+/// Generates the report from the index and the crates.io database dump
+pub struct Generator;
+
+#[async_trait]
+impl generic::Generator for Generator {
+    type Report = versioned::Report<VersionActivity>;
+    type DBResult = VersionActivity;
+
+    fn name() -> &'static str {
+        <VersionActivity as versioned::Analysis>::name()
+    }
+
+    fn version() -> &'static str {
+        <VersionActivity as versioned::Analysis>::version()
+    }
+
+    fn fq_result_key(crate_name: &str, crate_version: &str, key_buf: &mut String) {
+        CrateVersion::key_from(crate_name, crate_version, key_buf);
+    }
+
+    fn get_result(
+        _settings: &super::Settings,
+        connection: persistence::ThreadSafeConnection,
+        crate_name: &str,
+        crate_version: &str,
+        _key_buf: &mut String,
+    ) -> Result<Option<Self::DBResult>> {
+        let dump = persistence::CratesIoCrateTable {
+            inner: connection.clone(),
+        }
+        .get(crate_name)?;
+        if dump.is_none() && split_crate_name(crate_name).0.is_none() {
+            return Ok(None);
+        }
+        let version = match IndexLookup::new(connection).crate_version(crate_name, crate_version)? {
+            Some(version) => version,
+            None => return Ok(None),
+        };
+        Ok(Some(VersionActivity {
+            published_at: dump.as_ref().and_then(|krate| {
+                krate
+                    .versions
+                    .iter()
+                    .find(|v| v.semver == crate_version)
+                    .map(|v| v.created_at)
+            }),
+            yanked: version.kind == crates_index_diff::ChangeKind::Yanked,
+            repository: dump.and_then(|krate| krate.repository),
+            dependencies: version
+                .dependencies
+                .iter()
+                .filter(|d| d.kind.as_deref() != Some("dev"))
+                .map(|d| d.crate_name().to_owned())
+                .collect(),
+        }))
+    }
+
+    async fn generate_report(
+        _settings: &super::Settings,
+        crate_name: &str,
+        crate_version: &str,
+        result: Self::DBResult,
+        _progress: &mut prodash::tree::Item,
+    ) -> Result<Self::Report> {
+        Ok(versioned::Report::Version {
+            crate_name: crate_name.into(),
+            crate_version: crate_version.into(),
+            info: result,
+        })
+    }
+}
+
+#[cfg(test)]
+mod abandonment_test;
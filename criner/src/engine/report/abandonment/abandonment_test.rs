@@ -0,0 +1,54 @@
+use crate::engine::report::abandonment::{abandonment_risk, ActivitySummary, VersionActivity};
+use std::time::{Duration, SystemTime};
+
+const DAY: Duration = Duration::from_secs(60 * 60 * 24);
+
+fn summary(last_published_days_ago: Option<u64>, versions: u64, yanked: u64, latest_yanked: bool) -> ActivitySummary {
+    let now = SystemTime::UNIX_EPOCH + DAY * 10_000;
+    ActivitySummary {
+        versions,
+        yanked,
+        last_published_at: last_published_days_ago.map(|days| now - DAY * days as u32),
+        latest_version: "1.0.0".into(),
+        latest: VersionActivity {
+            yanked: latest_yanked,
+            repository: Some("https://github.com/owner/repo".into()),
+            ..Default::default()
+        },
+    }
+}
+
+fn risk(summary: &ActivitySummary) -> Option<(u32, Vec<String>)> {
+    abandonment_risk(summary, SystemTime::UNIX_EPOCH + DAY * 10_000)
+}
+
+#[test]
+fn crates_without_known_publish_dates_have_no_risk() {
+    assert_eq!(risk(&summary(None, 3, 0, false)), None);
+}
+
+#[test]
+fn recently_published_crates_with_repository_have_no_risk() {
+    assert_eq!(risk(&summary(Some(30), 3, 0, false)), Some((0, Vec::new())));
+}
+
+#[test]
+fn risk_grows_with_time_since_the_last_publish_and_yanks() {
+    assert_eq!(
+        risk(&summary(Some(2 * 365), 10, 5, false)),
+        Some((
+            45,
+            vec![
+                "last published 730 days ago".to_string(),
+                "5 of 10 versions are yanked".into()
+            ]
+        ))
+    );
+    let mut without_repository = summary(Some(20 * 365), 1, 1, true);
+    without_repository.latest.repository = None;
+    assert_eq!(
+        risk(&without_repository).map(|(risk, _)| risk),
+        Some(100),
+        "time is capped at 60, a yanked latest version and no repository add 20 each"
+    );
+}
@@ -0,0 +1,184 @@
+//! A housekeeping page about the assets directory, cross-referenced with the tasks which produced its files.
+use crate::{
+    engine::{
+        report::versioned::{write_page, Dict},
+        work::{artifact, asset_store::AssetStore, cpubound, schedule},
+    },
+    error::Result,
+    model::{Task, TaskState},
+    persistence::{TableAccess, TaskTable, KEY_SEP_CHAR},
+};
+use bytesize::ByteSize;
+use horrorshow::html;
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+const TOP_LIST: usize = 100;
+const DB_DUMP_DIR: &str = "crates-io-db";
+
+/// Files of one category, with a few examples
+#[derive(Default)]
+pub struct Files {
+    pub count: u64,
+    pub bytes: u64,
+    /// Up to `TOP_LIST` files, relative to the assets directory
+    pub examples: Vec<(PathBuf, u64)>,
+}
+
+impl Files {
+    fn add(&mut self, path: &Path, bytes: u64) {
+        self.count += 1;
+        self.bytes += bytes;
+        if self.examples.len() < TOP_LIST {
+            self.examples.push((path.to_owned(), bytes));
+        }
+    }
+
+    fn describe(&self) -> String {
+        format!("{} files, {}", self.count, ByteSize(self.bytes))
+    }
+}
+
+#[derive(Default)]
+pub struct AssetsSummary {
+    pub all: Files,
+    pub bytes_by_crate: Dict<u64>,
+    pub files_by_kind: Dict<Files>,
+    pub db_dumps: Files,
+    /// Downloads which didn't complete yet
+    pub partial_downloads: Files,
+    /// Files no task knows about, possibly left behind by previous task versions
+    pub orphaned: Files,
+    /// Downloaded crates which weren't extracted yet
+    pub pending_extraction: Files,
+}
+
+/// Returns the artifact kind and crate version a downloaded file belongs to
+fn parse_file_name(file_name: &str) -> Option<(&'static artifact::Kind, &str)> {
+    artifact::ALL.iter().find_map(|kind| {
+        let suffix = format!(
+            "-{}{}{}.{}",
+            kind.task_process, KEY_SEP_CHAR, kind.task_version, kind.name
+        );
+        file_name
+            .strip_suffix(suffix.as_str())
+            .map(|crate_version| (*kind, crate_version))
+    })
+}
+
+fn task_state(tasks: &TaskTable, task: Task, crate_name: &str, crate_version: &str) -> Result<Option<TaskState>> {
+    let mut key = String::new();
+    task.fq_key(crate_name, crate_version, &mut key);
+    Ok(tasks.get(&key)?.map(|t| t.state))
+}
+
+/// List all files in `assets_dir` as known to `assets`, which may keep them elsewhere, and categorize them with the
+/// help of the task table.
+pub fn summarize(
+    assets_dir: &Path,
+    assets: &dyn AssetStore,
+    tasks: &TaskTable,
+    progress: &mut prodash::tree::Item,
+) -> Result<AssetsSummary> {
+    progress.blocked("listing assets", None);
+    let files = assets.list(assets_dir)?;
+    progress.init(Some(files.len()), Some("files".into()));
+
+    let mut summary = AssetsSummary::default();
+    for (path, bytes) in files {
+        progress.inc();
+        let relative_path = path.strip_prefix(assets_dir)?;
+        summary.all.add(relative_path, bytes);
+        if relative_path.starts_with(DB_DUMP_DIR) {
+            summary.db_dumps.add(relative_path, bytes);
+            continue;
+        }
+        let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default();
+        if file_name.ends_with(".tmp") {
+            summary.partial_downloads.add(relative_path, bytes);
+            continue;
+        }
+        let crate_name = relative_path.parent().and_then(schedule::crate_name_from_dir);
+        let (crate_name, (kind, crate_version)) = match (crate_name, parse_file_name(file_name)) {
+            (Some(crate_name), Some(parsed)) => (crate_name, parsed),
+            _ => {
+                summary.orphaned.add(relative_path, bytes);
+                continue;
+            }
+        };
+        *summary.bytes_by_crate.entry(crate_name.clone()).or_default() += bytes;
+        summary
+            .files_by_kind
+            .entry(kind.name.to_owned())
+            .or_default()
+            .add(relative_path, bytes);
+
+        if task_state(tasks, kind.task(), &crate_name, crate_version)?.is_none() {
+            summary.orphaned.add(relative_path, bytes);
+        } else if kind.extract()
+            && !matches!(
+                task_state(
+                    tasks,
+                    cpubound::default_persisted_extraction_task(),
+                    &crate_name,
+                    crate_version
+                )?,
+                Some(TaskState::Complete)
+            )
+        {
+            summary.pending_extraction.add(relative_path, bytes);
+        }
+    }
+    progress.done(format!("Looked at {} files", summary.all.count));
+    Ok(summary)
+}
+
+pub fn write_html(summary: &AssetsSummary, generated_at: SystemTime, out: &mut Vec<u8>) -> Result<()> {
+    let mut by_size: Vec<_> = summary.bytes_by_crate.iter().collect();
+    by_size.sort_by_key(|(_, bytes)| std::cmp::Reverse(**bytes));
+    let categories = [
+        ("Partial downloads", &summary.partial_downloads),
+        ("Orphaned files without a task", &summary.orphaned),
+        ("Downloads pending extraction", &summary.pending_extraction),
+        ("crates.io database dumps", &summary.db_dumps),
+    ];
+    write_page(
+        "Criner Assets",
+        html! {
+            section {
+                h3: "All assets";
+                p: summary.all.describe();
+                ul {
+                    @ for (kind, files) in summary.files_by_kind.iter() {
+                        li: format!("{}: {}", kind, files.describe());
+                    }
+                }
+            }
+            section {
+                h3: "Largest crates";
+                ol {
+                    @ for (name, bytes) in by_size.into_iter().take(TOP_LIST) {
+                        li: format!("{}: {}", name, ByteSize(*bytes));
+                    }
+                }
+            }
+            @ for (title, files) in categories.iter() {
+                section {
+                    h3: format!("{}: {}", title, files.describe());
+                    ul {
+                        @ for (path, bytes) in files.examples.iter() {
+                            li: format!("{} ({})", path.display(), ByteSize(*bytes));
+                        }
+                    }
+                }
+            }
+        },
+        generated_at,
+        out,
+    )
+}
+
+#[cfg(test)]
+mod assets_test;
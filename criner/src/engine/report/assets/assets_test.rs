@@ -0,0 +1,101 @@
+use crate::{
+    engine::{
+        report::assets::summarize,
+        work::{
+            artifact,
+            asset_store::{AssetStore, ReadSeek},
+            cpubound, schedule,
+        },
+    },
+    model::TaskState,
+    persistence::{temp_db::TempDb, TableAccess},
+    Result,
+};
+use std::path::{Path, PathBuf};
+
+/// A store which keeps its assets elsewhere and only knows how to list them
+struct Remote(Vec<(PathBuf, u64)>);
+
+impl AssetStore for Remote {
+    fn size(&self, path: &Path) -> Result<Option<u64>> {
+        Ok(self.0.iter().find(|(p, _)| p == path).map(|(_, size)| *size))
+    }
+    fn open(&self, _path: &Path) -> Result<Box<dyn ReadSeek>> {
+        unreachable!("the report doesn't read assets")
+    }
+    fn put(&self, _local_file: &Path, _path: &Path) -> Result<()> {
+        unreachable!("the report doesn't store assets")
+    }
+    fn remove(&self, _path: &Path) -> Result<()> {
+        unreachable!("the report doesn't remove assets")
+    }
+    fn list(&self, dir: &Path) -> Result<Vec<(PathBuf, u64)>> {
+        Ok(self.0.iter().filter(|(p, _)| p.starts_with(dir)).cloned().collect())
+    }
+}
+
+#[test]
+fn assets_are_listed_through_the_store_and_categorized_by_their_tasks() {
+    let db = TempDb::new("assets-report");
+    let assets_dir = db.dir.join("assets");
+    let mut progress = prodash::Tree::new().add_child("assets");
+    let tasks = db.open_tasks().unwrap();
+    let mut key = String::new();
+    for (version, task) in [
+        ("1.0.0", artifact::CRATE.task()),
+        ("1.1.0", artifact::CRATE.task()),
+        ("1.0.0", cpubound::default_persisted_extraction_task()),
+    ] {
+        key.clear();
+        task.fq_key("a", version, &mut key);
+        tasks
+            .insert(
+                &mut progress,
+                &key,
+                &crate::model::Task {
+                    state: TaskState::Complete,
+                    ..task
+                },
+            )
+            .unwrap();
+    }
+    let crate_file = |version: &str| {
+        schedule::download_file_path(
+            &assets_dir,
+            "a",
+            version,
+            artifact::CRATE.task_process,
+            artifact::CRATE.task_version,
+            artifact::CRATE.name,
+        )
+    };
+    let store = Remote(vec![
+        (crate_file("1.0.0"), 100),
+        (crate_file("1.1.0"), 200),
+        (crate_file("2.0.0"), 50),
+        (crate_file("1.2.0").with_extension("crate.tmp"), 10),
+        (assets_dir.join("crates-io-db").join("dump.tar.gz"), 1000),
+        (db.dir.join("elsewhere").join("file"), 1),
+    ]);
+
+    let summary = summarize(&assets_dir, &store, &tasks, &mut progress).unwrap();
+    assert_eq!(
+        (summary.all.count, summary.all.bytes),
+        (5, 1360),
+        "only files in the assets directory count"
+    );
+    assert_eq!(summary.bytes_by_crate.get("a"), Some(&350));
+    assert_eq!(summary.files_by_kind.get("crate").map(|f| f.count), Some(3));
+    assert_eq!(summary.db_dumps.bytes, 1000);
+    assert_eq!(summary.partial_downloads.bytes, 10);
+    let relative = |path: PathBuf| path.strip_prefix(&assets_dir).unwrap().to_owned();
+    assert_eq!(
+        summary.orphaned.examples,
+        vec![(relative(crate_file("2.0.0")), 50)],
+        "no task knows about it"
+    );
+    assert_eq!(
+        summary.pending_extraction.examples,
+        vec![(relative(crate_file("1.1.0")), 200)]
+    );
+}
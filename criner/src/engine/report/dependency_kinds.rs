@@ -0,0 +1,224 @@
+use crate::{
+    engine::report::{
+        index::{self, IndexAnalysis, IndexLookup},
+        versioned::{self, write_page, Dict},
+    },
+    error::Result,
+    model::CrateVersion,
+    utils::parse_semver,
+};
+use horrorshow::html;
+use serde_derive::{Deserialize, Serialize};
+use std::{ops::AddAssign, time::SystemTime};
+
+const TOP_LIST: usize = 100;
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct KindCounts {
+    pub normal: u64,
+    pub dev: u64,
+    pub build: u64,
+}
+
+impl AddAssign<&KindCounts> for KindCounts {
+    fn add_assign(&mut self, rhs: &KindCounts) {
+        self.normal += rhs.normal;
+        self.dev += rhs.dev;
+        self.build += rhs.build;
+    }
+}
+
+impl KindCounts {
+    fn describe(&self) -> String {
+        format!("{} normal, {} dev, {} build", self.normal, self.dev, self.build)
+    }
+}
+
+/// The dependencies of a single crate version by kind
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct DependencyKindInfo {
+    pub counts: KindCounts,
+    /// The estimated weight of each dev-dependency, which contributors have to build to run tests
+    pub dev_dependencies: Dict<u64>,
+}
+
+impl DependencyKindInfo {
+    pub fn dev_weight(&self) -> u64 {
+        self.dev_dependencies.values().sum()
+    }
+
+    fn sorted_dev_dependencies(&self) -> Vec<(&String, &u64)> {
+        let mut sorted: Vec<_> = self.dev_dependencies.iter().collect();
+        sorted.sort_by(|(_, lhs), (_, rhs)| rhs.cmp(lhs));
+        sorted
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct DependencyKindSummary {
+    /// Dependencies of all versions of the crate
+    pub counts: KindCounts,
+    pub latest_version: String,
+    pub latest: DependencyKindInfo,
+}
+
+impl IndexAnalysis for DependencyKindInfo {
+    fn from_crate_version(version: &CrateVersion, index: &mut IndexLookup) -> Self {
+        let mut info = DependencyKindInfo::default();
+        for dependency in version.dependencies.iter() {
+            match dependency.kind.as_deref() {
+                Some("dev") => {
+                    info.counts.dev += 1;
+                    let weight = index.dependency_weight(dependency);
+                    info.dev_dependencies.insert(dependency.crate_name().to_owned(), weight);
+                }
+                Some("build") => info.counts.build += 1,
+                _ => info.counts.normal += 1,
+            }
+        }
+        info
+    }
+}
+
+impl versioned::Analysis for DependencyKindInfo {
+    type Summary = DependencyKindSummary;
+
+    fn name() -> &'static str {
+        "dependency-kinds"
+    }
+
+    fn version() -> &'static str {
+        "1.0.0"
+    }
+
+    fn add_to_summary(&self, crate_version: &str, summary: &mut Self::Summary) {
+        summary.counts += &self.counts;
+        versioned::keep_latest(self, crate_version, &mut summary.latest_version, &mut summary.latest);
+    }
+
+    fn merge_summaries(lhs: Self::Summary, rhs: Self::Summary) -> Self::Summary {
+        let (mut later, earlier) = versioned::later_first(lhs, rhs, |s| &s.latest_version);
+        later.counts += &earlier.counts;
+        later
+    }
+
+    fn write_version_html(
+        &self,
+        crate_name: &str,
+        crate_version: &str,
+        generated_at: SystemTime,
+        out: &mut Vec<u8>,
+    ) -> Result<()> {
+        write_page(
+            &format!("Dependencies of {}:{}", crate_name, crate_version),
+            html! {
+                section {
+                    h3: "Dependencies by kind";
+                    p: self.counts.describe();
+                }
+                section {
+                    h3: format!("Dev-dependencies, pulling in about {} crates", self.dev_weight());
+                    ol {
+                        @ for (name, weight) in self.sorted_dev_dependencies() {
+                            li: format!("{}: {}", name, weight);
+                        }
+                    }
+                }
+            },
+            generated_at,
+            out,
+        )
+    }
+
+    fn write_crate_html(
+        crate_name: &str,
+        info_by_version: &Dict<Self>,
+        generated_at: SystemTime,
+        out: &mut Vec<u8>,
+    ) -> Result<()> {
+        let mut versions: Vec<_> = info_by_version.iter().collect();
+        versions.sort_by_key(|(version, _)| parse_semver(version).ok());
+        write_page(
+            &format!("Dependencies of {}", crate_name),
+            html! {
+                section {
+                    h3: "Versions";
+                    ol {
+                        @ for (version, info) in versions.into_iter().rev() {
+                            li {
+                                a(href=format!("{}.html", version)): version;
+                                : format!(
+                                    " - {}, dev-dependencies pull in about {} crates",
+                                    info.counts.describe(),
+                                    info.dev_weight()
+                                );
+                            }
+                        }
+                    }
+                }
+            },
+            generated_at,
+            out,
+        )
+    }
+
+    fn write_collection_html(
+        info_by_crate: &Dict<Self::Summary>,
+        generated_at: SystemTime,
+        out: &mut Vec<u8>,
+    ) -> Result<()> {
+        let (all_versions, latest_versions) = info_by_crate.values().fold(
+            (KindCounts::default(), KindCounts::default()),
+            |(mut all, mut latest), s| {
+                all += &s.counts;
+                latest += &s.latest.counts;
+                (all, latest)
+            },
+        );
+        let num_crates = info_by_crate.len().max(1) as f64;
+        let percentage = |f: fn(&KindCounts) -> u64| {
+            info_by_crate.values().filter(|s| f(&s.latest.counts) > 0).count() as f64 / num_crates * 100.0
+        };
+        let with_dev = percentage(|c| c.dev);
+        let with_build = percentage(|c| c.build);
+        let mut by_dev_weight: Vec<_> = info_by_crate.iter().collect();
+        by_dev_weight.sort_by_key(|(_, s)| std::cmp::Reverse(s.latest.dev_weight()));
+        write_page(
+            "Crates.io Dependency Kinds Report",
+            html! {
+                section {
+                    h3: "All dependencies of all versions";
+                    p: all_versions.describe();
+                }
+                section {
+                    h3: format!("Dependencies of the latest version of {} crates", info_by_crate.len());
+                    p: latest_versions.describe();
+                    p: format!("{:.1}% of crates have dev-dependencies, {:.1}% have build-dependencies", with_dev, with_build);
+                }
+                section {
+                    h3: "Heaviest dev-dependency trees";
+                    ol {
+                        @ for (name, summary) in by_dev_weight.into_iter().take(TOP_LIST) {
+                            li {
+                                a(href=format!("{}/index.html", name)): name;
+                                : format!(
+                                    " {}: {} dev-dependencies pull in about {} crates",
+                                    summary.latest_version,
+                                    summary.latest.counts.dev,
+                                    summary.latest.dev_weight()
+                                );
+                            }
+                        }
+                    }
+                }
+            },
+            generated_at,
+            out,
+        )
+    }
+}
+
+pub type Generator = index::Generator<DependencyKindInfo>;
+
+#[cfg(test)]
+mod dependency_kinds_test;
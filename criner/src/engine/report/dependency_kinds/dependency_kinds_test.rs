@@ -0,0 +1,97 @@
+use crate::{
+    engine::report::{
+        dependency_kinds::DependencyKindInfo,
+        index::{IndexAnalysis, IndexLookup},
+        versioned::Analysis,
+    },
+    model::{CrateVersion, Dependency},
+    persistence::{temp_db::TempDb, Keyed, TableAccess},
+};
+
+fn dependency(name: &str, kind: Option<&str>, optional: bool) -> Dependency {
+    Dependency {
+        name: name.into(),
+        required_version: "^1".into(),
+        kind: kind.map(ToOwned::to_owned),
+        optional,
+        ..Default::default()
+    }
+}
+
+fn crate_version(name: &str, version: &str, dependencies: Vec<Dependency>) -> CrateVersion {
+    CrateVersion {
+        name: name.into(),
+        version: version.into(),
+        dependencies,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn dependencies_are_counted_by_kind_and_dev_dependencies_are_weighed() {
+    let db = TempDb::new("dependency-kinds");
+    let mut progress = prodash::Tree::new().add_child("dependency-kinds");
+    let crates = db.open_crates().unwrap();
+    let crate_versions = db.open_crate_versions().unwrap();
+    for version in [
+        crate_version("criterion", "0.1.0", Vec::new()),
+        crate_version(
+            "criterion",
+            "0.2.0",
+            vec![
+                dependency("serde", None, false),
+                dependency("rayon", Some("normal"), false),
+                dependency("plotters", None, true),
+                dependency("cc", Some("build"), false),
+                dependency("approx", Some("dev"), false),
+            ],
+        ),
+    ] {
+        crates.upsert(&mut progress, &version.name, &version).unwrap();
+        crate_versions.insert(&mut progress, version.key(), &version).unwrap();
+    }
+    let mut index = IndexLookup::new(db.open_connection().unwrap());
+
+    let info = DependencyKindInfo::from_crate_version(
+        &crate_version(
+            "a",
+            "1.0.0",
+            vec![
+                dependency("log", None, false),
+                dependency("serde", Some("normal"), true),
+                dependency("cc", Some("build"), false),
+                dependency("criterion", Some("dev"), false),
+                Dependency {
+                    package: Some("quickcheck".into()),
+                    ..dependency("qc", Some("dev"), false)
+                },
+            ],
+        ),
+        &mut index,
+    );
+    assert_eq!(info.counts.describe(), "2 normal, 2 dev, 1 build");
+    assert_eq!(
+        info.dev_dependencies.into_iter().collect::<Vec<_>>(),
+        vec![("criterion".to_string(), 3), ("quickcheck".into(), 1)],
+        "only required normal dependencies of the latest version add weight, and unknown crates weigh themselves only"
+    );
+}
+
+#[test]
+fn summaries_count_all_versions_and_keep_the_latest() {
+    let info = |normal: u64| {
+        let mut info = DependencyKindInfo::default();
+        info.counts.normal = normal;
+        info
+    };
+    let mut lhs = Default::default();
+    info(1).add_to_summary("1.0.0", &mut lhs);
+    info(2).add_to_summary("1.1.0", &mut lhs);
+    let mut rhs = Default::default();
+    info(4).add_to_summary("1.0.1", &mut rhs);
+
+    let merged = DependencyKindInfo::merge_summaries(lhs, rhs);
+    assert_eq!(merged.counts.normal, 7);
+    assert_eq!(merged.latest_version, "1.1.0");
+    assert_eq!(merged.latest.counts.normal, 2);
+}
@@ -0,0 +1,389 @@
+//! Which crates look usable without the standard library, on embedded devices or in WebAssembly, judging by their
+//! manifest, the attributes of their library and their dependencies. Each of these is just a hint, which is why
+//! every version page explains what was detected.
+use crate::{
+    engine::report::{
+        generic,
+        index::IndexLookup,
+        targets::Platform,
+        versioned::{self, write_page, Dict},
+        waste::{self, tar_path_to_utf8, TarHeader},
+    },
+    engine::work::{cpubound::Extraction, manifest},
+    error::Result,
+    model::{CrateVersion, TaskResult},
+    persistence,
+    utils::parse_semver,
+};
+use async_trait::async_trait;
+use horrorshow::html;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::SystemTime,
+};
+
+const TOP_LIST: usize = 100;
+const WASM_CRATES: &[&str] = &["wasm-bindgen", "wasm-bindgen-futures", "js-sys", "web-sys", "gloo"];
+const EMBEDDED_CRATES: &[&str] = &[
+    "embedded-hal",
+    "cortex-m",
+    "cortex-m-rt",
+    "riscv",
+    "riscv-rt",
+    "embassy-executor",
+    "defmt",
+    "heapless",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Readiness {
+    /// Usable without the standard library
+    NoStd,
+    /// Made for microcontrollers and other bare metal targets
+    Embedded,
+    /// Made for WebAssembly
+    Wasm,
+}
+
+impl Readiness {
+    fn all() -> &'static [Readiness] {
+        &[Readiness::NoStd, Readiness::Embedded, Readiness::Wasm]
+    }
+}
+
+/// Something about a crate version that suggests its readiness for a kind of target
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Signal {
+    pub readiness: Readiness,
+    /// What was detected, like "`#![no_std]` in src/lib.rs"
+    pub explanation: String,
+}
+
+/// The signals detected in a single crate version
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EmbeddedInfo {
+    pub signals: Vec<Signal>,
+}
+
+#[derive(serde_derive::Deserialize, Default)]
+#[serde(default)]
+struct Manifest {
+    package: Package,
+    lib: Lib,
+}
+
+#[derive(serde_derive::Deserialize, Default)]
+#[serde(default)]
+struct Package {
+    categories: Vec<String>,
+    keywords: Vec<String>,
+}
+
+#[derive(serde_derive::Deserialize, Default)]
+#[serde(default)]
+struct Lib {
+    path: Option<String>,
+}
+
+fn category_readiness(category: &str) -> Option<Readiness> {
+    match category {
+        "no-std" | "no-std::no-alloc" | "no_std" => Some(Readiness::NoStd),
+        "embedded" => Some(Readiness::Embedded),
+        "wasm" | "webassembly" => Some(Readiness::Wasm),
+        _ => None,
+    }
+}
+
+fn target_readiness(target: &str) -> Option<Readiness> {
+    const BARE_METAL: &[&str] = &["target_os = \"none\"", "thumbv", "riscv32", "avr-"];
+    if Platform::from_target(target) == Platform::Wasm {
+        Some(Readiness::Wasm)
+    } else if BARE_METAL.iter().any(|needle| target.contains(needle)) {
+        Some(Readiness::Embedded)
+    } else {
+        None
+    }
+}
+
+impl EmbeddedInfo {
+    /// Detect signals in the index information of a crate version and the files selected when extracting it
+    pub fn from_crate(version: &CrateVersion, files: &[(TarHeader, Vec<u8>)]) -> EmbeddedInfo {
+        let mut info = EmbeddedInfo::default();
+        let file = |path: &str| {
+            files
+                .iter()
+                .find(|(header, _)| tar_path_to_utf8(&header.path) == Ok(path))
+                .map(|(_, content)| String::from_utf8_lossy(content))
+        };
+
+        let manifest: Manifest = file("Cargo.toml")
+            .and_then(|content| manifest::normalized(&content))
+            .unwrap_or_default();
+        for category in manifest.package.categories.iter() {
+            if let Some(readiness) = category_readiness(category) {
+                info.add(readiness, format!("category '{}' in Cargo.toml", category));
+            }
+        }
+        for keyword in manifest.package.keywords.iter() {
+            if let Some(readiness) = category_readiness(keyword) {
+                info.add(readiness, format!("keyword '{}' in Cargo.toml", keyword));
+            }
+        }
+
+        let lib_path = manifest.lib.path.unwrap_or_else(|| "src/lib.rs".into());
+        if let Some(source) = file(&lib_path) {
+            for line in source.lines().map(str::trim).filter(|line| line.starts_with("#![")) {
+                let attribute: String = line.chars().filter(|c| !c.is_whitespace()).collect();
+                if attribute.starts_with("#![no_std]") {
+                    info.add(Readiness::NoStd, format!("`#![no_std]` in {}", lib_path));
+                } else if attribute.starts_with("#![cfg_attr(") && attribute.contains(",no_std") {
+                    info.add(
+                        Readiness::NoStd,
+                        format!("`{}` in {}, so it can be built without std", line, lib_path),
+                    );
+                }
+            }
+        }
+
+        if version.features.contains_key("std") {
+            info.add(
+                Readiness::NoStd,
+                "a 'std' feature, which usually makes the standard library optional".into(),
+            );
+        }
+        for dependency in version.dependencies.iter().filter(|d| d.kind.as_deref() != Some("dev")) {
+            let name = dependency.crate_name();
+            if WASM_CRATES.contains(&name) {
+                info.add(Readiness::Wasm, format!("depends on {}", name));
+            }
+            if EMBEDDED_CRATES.contains(&name) {
+                info.add(Readiness::Embedded, format!("depends on {}", name));
+            }
+            if let Some(target) = dependency.target.as_ref() {
+                if let Some(readiness) = target_readiness(target) {
+                    info.add(readiness, format!("dependencies for target '{}'", target));
+                }
+            }
+        }
+        info
+    }
+
+    fn add(&mut self, readiness: Readiness, explanation: String) {
+        let signal = Signal { readiness, explanation };
+        if !self.signals.contains(&signal) {
+            self.signals.push(signal);
+        }
+    }
+
+    pub fn readiness(&self) -> BTreeSet<Readiness> {
+        self.signals.iter().map(|s| s.readiness).collect()
+    }
+
+    fn signals_for(&self, readiness: Readiness) -> impl Iterator<Item = &str> {
+        self.signals
+            .iter()
+            .filter(move |s| s.readiness == readiness)
+            .map(|s| s.explanation.as_str())
+    }
+}
+
+/// The signals of the most recent version of a crate
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct EmbeddedSummary {
+    pub latest_version: String,
+    pub latest: EmbeddedInfo,
+}
+
+impl versioned::Analysis for EmbeddedInfo {
+    type Summary = EmbeddedSummary;
+
+    fn name() -> &'static str {
+        "embedded"
+    }
+
+    fn version() -> &'static str {
+        "1.0.0"
+    }
+
+    fn add_to_summary(&self, crate_version: &str, summary: &mut Self::Summary) {
+        versioned::keep_latest(self, crate_version, &mut summary.latest_version, &mut summary.latest);
+    }
+
+    fn merge_summaries(lhs: Self::Summary, rhs: Self::Summary) -> Self::Summary {
+        versioned::later_first(lhs, rhs, |s| &s.latest_version).0
+    }
+
+    fn write_version_html(
+        &self,
+        crate_name: &str,
+        crate_version: &str,
+        generated_at: SystemTime,
+        out: &mut Vec<u8>,
+    ) -> Result<()> {
+        write_page(
+            &format!("Embedded and WebAssembly readiness of {}:{}", crate_name, crate_version),
+            html! {
+                @ if self.signals.is_empty() {
+                    p: "Nothing suggests this version is meant to be used without std, on embedded devices or in WebAssembly";
+                }
+                @ for readiness in Readiness::all() {
+                    @ if self.signals_for(*readiness).next().is_some() {
+                        section {
+                            h3: format!("{:?}", readiness);
+                            ul {
+                                @ for explanation in self.signals_for(*readiness) {
+                                    li: explanation;
+                                }
+                            }
+                        }
+                    }
+                }
+            },
+            generated_at,
+            out,
+        )
+    }
+
+    fn write_crate_html(
+        crate_name: &str,
+        info_by_version: &Dict<Self>,
+        generated_at: SystemTime,
+        out: &mut Vec<u8>,
+    ) -> Result<()> {
+        let mut versions: Vec<_> = info_by_version.iter().collect();
+        versions.sort_by_key(|(version, _)| parse_semver(version).ok());
+        write_page(
+            &format!("Embedded and WebAssembly readiness of {}", crate_name),
+            html! {
+                section {
+                    h3: "Versions";
+                    ol {
+                        @ for (version, info) in versions.into_iter().rev() {
+                            li {
+                                a(href=format!("{}.html", version)): version;
+                                : format!(" - {:?}", info.readiness());
+                            }
+                        }
+                    }
+                }
+            },
+            generated_at,
+            out,
+        )
+    }
+
+    fn write_collection_html(
+        info_by_crate: &Dict<Self::Summary>,
+        generated_at: SystemTime,
+        out: &mut Vec<u8>,
+    ) -> Result<()> {
+        let num_crates = info_by_crate.len();
+        let mut crates_by_readiness = BTreeMap::<Readiness, Vec<(&String, &EmbeddedSummary)>>::new();
+        for (name, summary) in info_by_crate.iter() {
+            for readiness in summary.latest.readiness() {
+                crates_by_readiness.entry(readiness).or_default().push((name, summary));
+            }
+        }
+        for crates in crates_by_readiness.values_mut() {
+            crates.sort_by_key(|(name, summary)| (std::cmp::Reverse(summary.latest.signals.len()), *name));
+        }
+        let no_crates = Vec::new();
+        write_page(
+            "Crates.io Embedded and WebAssembly Readiness Report",
+            html! {
+                section {
+                    h3: format!("Signals in the latest version of {} crates", num_crates);
+                    ul {
+                        @ for readiness in Readiness::all() {
+                            li: format!(
+                                "{:?}: {:.2}% of crates",
+                                readiness,
+                                crates_by_readiness.get(readiness).map_or(0, Vec::len) as f64 / num_crates.max(1) as f64 * 100.0
+                            );
+                        }
+                    }
+                }
+                @ for readiness in Readiness::all() {
+                    section {
+                        h3: format!("{:?} crates with the most signals", readiness);
+                        ol {
+                            @ for (name, summary) in crates_by_readiness.get(readiness).unwrap_or(&no_crates).iter().take(TOP_LIST) {
+                                li {
+                                    a(href=format!("{}/index.html", name)): name.as_str();
+                                    : format!(" {}: {}", summary.latest_version, summary.latest.signals_for(*readiness).collect::<Vec<_>>().join(", "));
+                                }
+                            }
+                        }
+                    }
+                }
+            },
+            generated_at,
+            out,
+        )
+    }
+}
+
+/// Generates the report from the extracted crate and its information in the index, for crate versions which were
+/// extracted already.
+pub struct Generator;
+
+#[async_trait]
+impl generic::Generator for Generator {
+    type Report = versioned::Report<EmbeddedInfo>;
+    type DBResult = EmbeddedInfo;
+
+    fn name() -> &'static str {
+        <EmbeddedInfo as versioned::Analysis>::name()
+    }
+
+    fn version() -> &'static str {
+        <EmbeddedInfo as versioned::Analysis>::version()
+    }
+
+    fn fq_result_key(crate_name: &str, crate_version: &str, key_buf: &mut String) {
+        <waste::Generator as generic::Generator>::fq_result_key(crate_name, crate_version, key_buf)
+    }
+
+    fn needed_extraction() -> Option<Extraction> {
+        Some(Extraction::Full)
+    }
+
+    fn get_result(
+        _settings: &super::Settings,
+        connection: persistence::ThreadSafeConnection,
+        crate_name: &str,
+        crate_version: &str,
+        key_buf: &mut String,
+    ) -> Result<Option<Self::DBResult>> {
+        Self::fq_result_key(crate_name, crate_version, key_buf);
+        let files = match (persistence::TaskResultTable {
+            inner: connection.clone(),
+        })
+        .get_selected(&key_buf, waste::is_manifest_or_rust_source)?
+        {
+            Some(TaskResult::ExplodedCrate { selected_entries, .. }) => selected_entries,
+            _ => return Ok(None),
+        };
+        Ok(IndexLookup::new(connection)
+            .crate_version(crate_name, crate_version)?
+            .map(|version| EmbeddedInfo::from_crate(&version, &files)))
+    }
+
+    async fn generate_report(
+        _settings: &super::Settings,
+        crate_name: &str,
+        crate_version: &str,
+        result: Self::DBResult,
+        _progress: &mut prodash::tree::Item,
+    ) -> Result<Self::Report> {
+        Ok(versioned::Report::Version {
+            crate_name: crate_name.into(),
+            crate_version: crate_version.into(),
+            info: result,
+        })
+    }
+}
+
+#[cfg(test)]
+mod embedded_test;
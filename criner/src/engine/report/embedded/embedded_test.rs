@@ -0,0 +1,94 @@
+use crate::{
+    engine::report::embedded::{EmbeddedInfo, Readiness},
+    model::{CrateVersion, Dependency},
+};
+use std::collections::BTreeSet;
+
+fn file(path: &str, content: &str) -> (crate::model::TarHeader, Vec<u8>) {
+    (
+        crate::model::TarHeader {
+            path: format!("krate-1.0.0/{}", path).into_bytes(),
+            size: content.len() as u64,
+            entry_type: b'0',
+            encoding: None,
+            truncated_at: None,
+        },
+        content.as_bytes().to_vec(),
+    )
+}
+
+fn explanations(info: &EmbeddedInfo) -> Vec<&str> {
+    info.signals.iter().map(|s| s.explanation.as_str()).collect()
+}
+
+#[test]
+fn no_signals_in_an_ordinary_crate() {
+    let info = EmbeddedInfo::from_crate(
+        &CrateVersion::default(),
+        &[
+            file(
+                "Cargo.toml",
+                "[package]\nname = \"krate\"\ncategories = [\"command-line-utilities\"]\n",
+            ),
+            file("src/lib.rs", "//! #![no_std] in docs doesn't count\nuse std::fs;\n"),
+        ],
+    );
+    assert!(info.signals.is_empty(), "{:?}", info.signals);
+}
+
+#[test]
+fn signals_are_detected_in_manifest_library_features_and_dependencies() {
+    let mut version = CrateVersion::default();
+    version.features.insert("std".into(), Vec::new());
+    version.dependencies = vec![
+        Dependency {
+            name: "wasm-bindgen".into(),
+            ..Default::default()
+        },
+        Dependency {
+            name: "hal".into(),
+            package: Some("embedded-hal".into()),
+            ..Default::default()
+        },
+        Dependency {
+            name: "cortex-m".into(),
+            kind: Some("dev".into()),
+            ..Default::default()
+        },
+        Dependency {
+            name: "panic-halt".into(),
+            target: Some("cfg(target_os = \"none\")".into()),
+            ..Default::default()
+        },
+    ];
+    let info = EmbeddedInfo::from_crate(
+        &version,
+        &[
+            file(
+                "Cargo.toml",
+                "[package]\nname = \"krate\"\ncategories = [\"no-std\", \"wasm\"]\nkeywords = [\"embedded\"]\n\n[lib]\npath = \"lib.rs\"\n",
+            ),
+            file("lib.rs", "//! docs\n#![cfg_attr(not(feature = \"std\"), no_std)]\n#![deny(missing_docs)]\n"),
+        ],
+    );
+    assert_eq!(
+        explanations(&info),
+        vec![
+            "category 'no-std' in Cargo.toml",
+            "category 'wasm' in Cargo.toml",
+            "keyword 'embedded' in Cargo.toml",
+            "`#![cfg_attr(not(feature = \"std\"), no_std)]` in lib.rs, so it can be built without std",
+            "a 'std' feature, which usually makes the standard library optional",
+            "depends on wasm-bindgen",
+            "depends on embedded-hal",
+            "dependencies for target 'cfg(target_os = \"none\")'",
+        ]
+    );
+    assert_eq!(
+        info.readiness(),
+        [Readiness::NoStd, Readiness::Embedded, Readiness::Wasm]
+            .iter()
+            .copied()
+            .collect::<BTreeSet<_>>()
+    );
+}
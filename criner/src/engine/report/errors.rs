@@ -0,0 +1,125 @@
+//! Items which couldn't be reported, like crate versions whose result can't be decoded, which are left out of their
+//! report so the rest of the report cycle can go on. They are listed on a page of the cycle and tried again in the next.
+use crate::{engine::report::versioned::write_page, error::Result};
+use horrorshow::html;
+use std::{
+    any::Any,
+    io::Write,
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+    time::SystemTime,
+};
+
+/// The file in each report directory listing the items which couldn't be reported in its cycle, one per line as key and
+/// error separated by a tab
+pub const ERRORS_FILE_NAME: &str = "__report_errors__";
+
+/// The amount of items which couldn't be reported since the process started
+static NUM_ERRORS: AtomicU64 = AtomicU64::new(0);
+
+/// An item which couldn't be reported
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ItemError {
+    /// The name of the report the item was left out of
+    pub report: String,
+    /// The key of the item, like `<crate>` or `<crate>:<version>`
+    pub key: String,
+    /// Why the item couldn't be reported
+    pub error: String,
+}
+
+/// The amount of items which couldn't be reported since the process started
+pub(crate) fn num_errors() -> u64 {
+    NUM_ERRORS.load(Ordering::Relaxed)
+}
+
+/// The message of a panic caught while reporting an item
+pub(crate) fn panic_message(panic: Box<dyn Any + Send>) -> String {
+    let message = panic
+        .downcast_ref::<String>()
+        .map(String::as_str)
+        .or_else(|| panic.downcast_ref::<&str>().copied())
+        .unwrap_or("unknown cause");
+    format!("panicked: {}", message)
+}
+
+/// Record that the item with `key` couldn't be reported into the report directory `out_dir` because of `error`
+pub(crate) fn record(out_dir: &Path, key: &str, error: impl std::fmt::Display) -> Result<()> {
+    // Chunks are written by multiple processors at once, but each line must end up in one piece
+    static APPEND: parking_lot::Mutex<()> = parking_lot::const_mutex(());
+    let line = format!("{}\t{}\n", key, error.to_string().replace(['\n', '\t'], " "));
+    NUM_ERRORS.fetch_add(1, Ordering::Relaxed);
+    let _guard = APPEND.lock();
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(out_dir.join(ERRORS_FILE_NAME))?
+        .write_all(line.as_bytes())?;
+    Ok(())
+}
+
+/// All items which couldn't be reported into the reports of the report cycle at `cycle_dir`, by report and key
+pub fn collect(cycle_dir: &Path) -> Result<Vec<ItemError>> {
+    let mut errors = Vec::new();
+    for entry in std::fs::read_dir(cycle_dir)? {
+        let report_dir = entry?.path();
+        if !report_dir.is_dir() {
+            continue;
+        }
+        let content = match std::fs::read_to_string(report_dir.join(ERRORS_FILE_NAME)) {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err.into()),
+        };
+        let report = report_dir
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        for line in content.lines().filter(|line| !line.is_empty()) {
+            let mut parts = line.splitn(2, '\t');
+            errors.push(ItemError {
+                report: report.clone(),
+                key: parts.next().unwrap_or_default().to_owned(),
+                error: parts.next().unwrap_or_default().to_owned(),
+            });
+        }
+    }
+    errors.sort_by(|lhs, rhs| (&lhs.report, &lhs.key).cmp(&(&rhs.report, &rhs.key)));
+    Ok(errors)
+}
+
+pub fn write_html(errors: &[ItemError], generated_at: SystemTime, out: &mut Vec<u8>) -> Result<()> {
+    write_page(
+        "Criner Report Errors",
+        html! {
+            section {
+                p: format!(
+                    "{} item(s) couldn't be reported in this cycle and were left out of their report. \
+                     They are tried again in the next one.",
+                    errors.len()
+                );
+                @ if !errors.is_empty() {
+                    table {
+                        tr {
+                            th: "report";
+                            th: "item";
+                            th: "error";
+                        }
+                        @ for error in errors.iter() {
+                            tr {
+                                td: &error.report;
+                                td: &error.key;
+                                td: &error.error;
+                            }
+                        }
+                    }
+                }
+            }
+        },
+        generated_at,
+        out,
+    )
+}
+
+#[cfg(test)]
+mod errors_test;
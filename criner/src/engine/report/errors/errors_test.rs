@@ -0,0 +1,63 @@
+use super::{collect, panic_message, record, write_html, ItemError, ERRORS_FILE_NAME};
+use crate::persistence::temp_db::TempDir;
+
+fn cycle_dir(name: &str) -> TempDir {
+    let dir = TempDir::new(&format!("errors-{}", name));
+    std::fs::create_dir_all(dir.join("waste")).unwrap();
+    std::fs::create_dir_all(dir.join("features")).unwrap();
+    dir
+}
+
+#[test]
+fn errors_of_all_reports_are_collected_with_their_keys() {
+    let dir = cycle_dir("collect");
+    record(&dir.join("waste"), "b:1.0.0", "the result\tcould not\nbe decoded").unwrap();
+    record(&dir.join("waste"), "a", "panicked: malformed").unwrap();
+    record(&dir.join("features"), "c:0.1.0", "disk full").unwrap();
+    std::fs::write(dir.join("unrelated.html"), "").unwrap();
+
+    let errors = collect(&dir).unwrap();
+    assert_eq!(
+        errors,
+        vec![
+            ItemError {
+                report: "features".into(),
+                key: "c:0.1.0".into(),
+                error: "disk full".into()
+            },
+            ItemError {
+                report: "waste".into(),
+                key: "a".into(),
+                error: "panicked: malformed".into()
+            },
+            ItemError {
+                report: "waste".into(),
+                key: "b:1.0.0".into(),
+                error: "the result could not be decoded".into()
+            },
+        ],
+        "errors are sorted and always one per line"
+    );
+    assert!(super::num_errors() >= 3);
+
+    let mut out = Vec::new();
+    write_html(&errors, std::time::SystemTime::now(), &mut out).unwrap();
+    let page = String::from_utf8(out).unwrap();
+    assert!(page.contains("3 item(s) couldn't be reported"), "{}", page);
+    assert!(page.contains("b:1.0.0"), "{}", page);
+}
+
+#[test]
+fn cycles_without_errors_have_none() {
+    let dir = cycle_dir("none");
+    assert!(collect(&dir).unwrap().is_empty());
+    assert!(!dir.join("waste").join(ERRORS_FILE_NAME).exists());
+}
+
+#[test]
+fn panics_are_described_by_their_message() {
+    let panic = std::panic::catch_unwind(|| panic!("migration should succeed")).unwrap_err();
+    assert_eq!(panic_message(panic), "panicked: migration should succeed");
+    let panic = std::panic::catch_unwind(|| panic!("{} failed", "decoding")).unwrap_err();
+    assert_eq!(panic_message(panic), "panicked: decoding failed");
+}
@@ -0,0 +1,162 @@
+//! An operational page showing where and when failures cluster, derived from the error telemetry table.
+//! Systemic problems, like a misbehaving CDN edge or a broken storage volume, show up as hot rows or columns.
+use crate::{
+    engine::{report::versioned::write_page, work::schedule},
+    error::Result,
+    persistence::{self, ErrorTelemetryTable},
+};
+use horrorshow::{html, owned_html, RenderOnce};
+use rusqlite::params;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// The amount of days to show, ending today
+const DAYS: i64 = 30;
+/// The amount of shards with the most failures to show
+const TOP_SHARDS: usize = 50;
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+#[derive(Default)]
+pub struct Heatmap {
+    /// All error categories which occurred
+    pub categories: BTreeSet<String>,
+    /// The first day shown, in days since epoch
+    pub first_day: i64,
+    /// Failures by category and day since epoch
+    pub by_day: BTreeMap<(String, i64), u64>,
+    /// Failures by shard of the crate name, as used in the assets directory, and category
+    pub by_shard: BTreeMap<(String, String), u64>,
+}
+
+/// Aggregate the failures of the last `DAYS` days until `now`
+pub fn run(db: &persistence::Db, now: SystemTime, progress: &mut prodash::tree::Item) -> Result<Heatmap> {
+    progress.blocked("aggregating error telemetry", None);
+    let now = now.duration_since(UNIX_EPOCH)?.as_secs() as i64;
+    let first_day = now / SECONDS_PER_DAY - (DAYS - 1);
+    let since = first_day * SECONDS_PER_DAY;
+    let connection = db.open_connection_no_async_with_busy_wait()?;
+    let mut heatmap = Heatmap {
+        first_day,
+        ..Default::default()
+    };
+
+    let mut statement = connection.prepare(&format!(
+        "SELECT category, occurred_at / {}, COUNT(*) FROM {} WHERE occurred_at >= ?1 GROUP BY 1, 2",
+        SECONDS_PER_DAY,
+        ErrorTelemetryTable::table_name()
+    ))?;
+    for row in statement.query_map(params![since], |r| {
+        Ok((r.get::<_, String>(0)?, r.get::<_, i64>(1)?, r.get::<_, i64>(2)?))
+    })? {
+        let (category, day, count) = row?;
+        heatmap.categories.insert(category.clone());
+        heatmap.by_day.insert((category, day), count as u64);
+    }
+
+    // Keys start with the crate name, which is all there is to keys without separator
+    let mut statement = connection.prepare(&format!(
+        "SELECT category,
+                CASE WHEN instr(key, ':') > 0 THEN substr(key, 1, instr(key, ':') - 1) ELSE key END,
+                COUNT(*)
+         FROM {} WHERE occurred_at >= ?1 GROUP BY 1, 2",
+        ErrorTelemetryTable::table_name()
+    ))?;
+    for row in statement.query_map(params![since], |r| {
+        Ok((r.get::<_, String>(0)?, r.get::<_, String>(1)?, r.get::<_, i64>(2)?))
+    })? {
+        let (category, crate_name, count) = row?;
+        *heatmap
+            .by_shard
+            .entry((schedule::crate_shard(&crate_name).to_owned(), category))
+            .or_default() += count as u64;
+    }
+    progress.done(format!("Aggregated {} failures", heatmap.by_day.values().sum::<u64>()));
+    Ok(heatmap)
+}
+
+fn cell(count: u64, max: u64) -> impl RenderOnce {
+    let style = format!(
+        "background-color: rgba(220, 40, 40, {:.2}); text-align: right; padding: 0 4px",
+        if max == 0 { 0.0 } else { count as f32 / max as f32 }
+    );
+    owned_html! {
+        td(style=style) {
+            @ if count > 0 {
+                : count
+            }
+        }
+    }
+}
+
+pub fn write_html(heatmap: &Heatmap, generated_at: SystemTime, out: &mut Vec<u8>) -> Result<()> {
+    let days: Vec<i64> = (heatmap.first_day..heatmap.first_day + DAYS).collect();
+    let max_by_day = heatmap.by_day.values().copied().max().unwrap_or(0);
+    let mut shards: BTreeMap<&str, u64> = BTreeMap::new();
+    for ((shard, _), count) in heatmap.by_shard.iter() {
+        *shards.entry(shard.as_str()).or_default() += count;
+    }
+    let mut shards: Vec<_> = shards.into_iter().collect();
+    shards.sort_by(|(_, lhs), (_, rhs)| rhs.cmp(lhs));
+    shards.truncate(TOP_SHARDS);
+    let max_by_shard = heatmap.by_shard.values().copied().max().unwrap_or(0);
+
+    write_page(
+        "Criner Failure Heatmap",
+        html! {
+            section {
+                h3: format!("Failures per day and error category, last {} days", DAYS);
+                table {
+                    tr {
+                        th: "category";
+                        @ for day in days.iter() {
+                            th: humantime::format_rfc3339(
+                                UNIX_EPOCH + Duration::from_secs((*day * SECONDS_PER_DAY) as u64)
+                            ).to_string().get(5..10).unwrap_or_default().to_owned();
+                        }
+                    }
+                    @ for category in heatmap.categories.iter() {
+                        tr {
+                            th: category;
+                            @ for day in days.iter() {
+                                : cell(
+                                    heatmap.by_day.get(&(category.clone(), *day)).copied().unwrap_or(0),
+                                    max_by_day
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+            section {
+                h3: format!(
+                    "Failures per crate name shard and error category, the {} shards with the most failures",
+                    TOP_SHARDS
+                );
+                p: "Shards are the top-level directories of the assets directory, grouping crates by the start of their name.";
+                table {
+                    tr {
+                        th: "shard";
+                        @ for category in heatmap.categories.iter() {
+                            th: category;
+                        }
+                    }
+                    @ for (shard, _) in shards.iter() {
+                        tr {
+                            th: *shard;
+                            @ for category in heatmap.categories.iter() {
+                                : cell(
+                                    heatmap.by_shard.get(&(shard.to_string(), category.clone())).copied().unwrap_or(0),
+                                    max_by_shard
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+        },
+        generated_at,
+        out,
+    )
+}
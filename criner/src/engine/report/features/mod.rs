@@ -0,0 +1,260 @@
+use crate::{
+    engine::report::{
+        index::{self, IndexAnalysis, IndexLookup},
+        versioned::{self, write_page, Dict},
+    },
+    error::Result,
+    model::{CrateVersion, Dependency},
+    utils::parse_semver,
+};
+use horrorshow::html;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeSet, HashMap},
+    time::SystemTime,
+};
+
+/// The amount of toggles from which on we consider the feature matrix of a crate enormous
+const ENORMOUS_FEATURE_MATRIX_TOGGLES: u32 = 16;
+const TOP_LIST: usize = 100;
+
+/// Information about the features of a single crate version
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct FeatureInfo {
+    /// Explicitly declared features, excluding 'default'
+    pub features: u32,
+    /// Optional dependencies, each of which can be toggled like a feature
+    pub optional_dependencies: u32,
+    /// An estimate of the amount of dependencies each feature pulls in transitively
+    pub weight_by_feature: Dict<u64>,
+}
+
+impl FeatureInfo {
+    /// The amount of things to toggle, each doubling the amount of possible feature combinations
+    pub fn toggles(&self) -> u32 {
+        self.features + self.optional_dependencies
+    }
+
+    fn heaviest_feature(&self) -> Option<(&str, u64)> {
+        self.weight_by_feature
+            .iter()
+            .filter(|(name, _)| *name != "default")
+            .max_by_key(|(_, weight)| **weight)
+            .map(|(name, weight)| (name.as_str(), *weight))
+    }
+
+    fn sorted_by_weight(&self) -> Vec<(&String, &u64)> {
+        let mut sorted: Vec<_> = self.weight_by_feature.iter().collect();
+        sorted.sort_by(|(_, lhs), (_, rhs)| rhs.cmp(lhs));
+        sorted
+    }
+}
+
+/// The features of the most recent version of a crate
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct FeatureSummary {
+    pub latest_version: String,
+    pub latest: FeatureInfo,
+}
+
+/// Compute the weight of each feature, which is the sum of weights of all optional dependencies it enables,
+/// following features enabling other features.
+pub fn feature_weights(
+    features: &HashMap<String, Vec<String>>,
+    dependencies: &[Dependency],
+    mut dependency_weight: impl FnMut(&Dependency) -> u64,
+) -> Dict<u64> {
+    let optional_dependency = |name: &str| dependencies.iter().find(|d| d.optional && d.name == name);
+    features
+        .keys()
+        .map(|feature| {
+            let mut seen_features = BTreeSet::new();
+            let mut enabled_dependencies = BTreeSet::new();
+            let mut stack = vec![feature.as_str()];
+            while let Some(feature) = stack.pop() {
+                if !seen_features.insert(feature) {
+                    continue;
+                }
+                for value in features.get(feature).into_iter().flatten() {
+                    let dependency_name = match value.strip_prefix("dep:") {
+                        Some(dependency_name) => Some(dependency_name),
+                        None => match value.find('/') {
+                            Some(pos) if value[..pos].ends_with('?') => None,
+                            Some(pos) => Some(&value[..pos]),
+                            None if features.contains_key(value) => {
+                                stack.push(value);
+                                None
+                            }
+                            None => Some(value.as_str()),
+                        },
+                    };
+                    if let Some(dependency) = dependency_name.and_then(optional_dependency) {
+                        enabled_dependencies.insert(dependency.name.as_str());
+                    }
+                }
+            }
+            let weight = enabled_dependencies
+                .into_iter()
+                .filter_map(optional_dependency)
+                .map(&mut dependency_weight)
+                .sum();
+            (feature.to_owned(), weight)
+        })
+        .collect()
+}
+
+impl IndexAnalysis for FeatureInfo {
+    fn from_crate_version(version: &CrateVersion, index: &mut IndexLookup) -> Self {
+        FeatureInfo {
+            features: version.features.keys().filter(|name| *name != "default").count() as u32,
+            optional_dependencies: version
+                .dependencies
+                .iter()
+                .filter(|d| d.optional && d.is_normal())
+                .count() as u32,
+            weight_by_feature: feature_weights(&version.features, &version.dependencies, |d| {
+                index.dependency_weight(d)
+            }),
+        }
+    }
+}
+
+impl versioned::Analysis for FeatureInfo {
+    type Summary = FeatureSummary;
+
+    fn name() -> &'static str {
+        "features"
+    }
+
+    fn version() -> &'static str {
+        "1.0.0"
+    }
+
+    fn add_to_summary(&self, crate_version: &str, summary: &mut Self::Summary) {
+        versioned::keep_latest(self, crate_version, &mut summary.latest_version, &mut summary.latest);
+    }
+
+    fn merge_summaries(lhs: Self::Summary, rhs: Self::Summary) -> Self::Summary {
+        versioned::later_first(lhs, rhs, |s| &s.latest_version).0
+    }
+
+    fn write_version_html(
+        &self,
+        crate_name: &str,
+        crate_version: &str,
+        generated_at: SystemTime,
+        out: &mut Vec<u8>,
+    ) -> Result<()> {
+        write_page(
+            &format!("Features of {}:{}", crate_name, crate_version),
+            html! {
+                section {
+                    h3: "Feature matrix";
+                    p: format!(
+                        "{} features and {} optional dependencies allow 2^{} combinations",
+                        self.features, self.optional_dependencies, self.toggles()
+                    );
+                }
+                section {
+                    h3: "Estimated dependencies pulled in by feature";
+                    ol {
+                        @ for (name, weight) in self.sorted_by_weight() {
+                            li: format!("{}: {}", name, weight);
+                        }
+                    }
+                }
+            },
+            generated_at,
+            out,
+        )
+    }
+
+    fn write_crate_html(
+        crate_name: &str,
+        info_by_version: &Dict<Self>,
+        generated_at: SystemTime,
+        out: &mut Vec<u8>,
+    ) -> Result<()> {
+        let mut versions: Vec<_> = info_by_version.iter().collect();
+        versions.sort_by_key(|(version, _)| parse_semver(version).ok());
+        write_page(
+            &format!("Features of {}", crate_name),
+            html! {
+                section {
+                    h3: "Versions";
+                    ol {
+                        @ for (version, info) in versions.into_iter().rev() {
+                            li {
+                                a(href=format!("{}.html", version)): version;
+                                : format!(" - 2^{} combinations", info.toggles());
+                                @ if let Some((name, weight)) = info.heaviest_feature() {
+                                    : format!(", heaviest feature '{}' pulls in about {} dependencies", name, weight);
+                                }
+                            }
+                        }
+                    }
+                }
+            },
+            generated_at,
+            out,
+        )
+    }
+
+    fn write_collection_html(
+        info_by_crate: &Dict<Self::Summary>,
+        generated_at: SystemTime,
+        out: &mut Vec<u8>,
+    ) -> Result<()> {
+        let mut by_toggles: Vec<_> = info_by_crate.iter().collect();
+        by_toggles.sort_by_key(|(_, s)| std::cmp::Reverse(s.latest.toggles()));
+        let mut by_weight: Vec<_> = info_by_crate
+            .iter()
+            .filter_map(|(name, s)| s.latest.heaviest_feature().map(|(f, w)| (name, f, w)))
+            .collect();
+        by_weight.sort_by_key(|(_, _, weight)| std::cmp::Reverse(*weight));
+        let enormous = by_toggles
+            .iter()
+            .filter(|(_, s)| s.latest.toggles() >= ENORMOUS_FEATURE_MATRIX_TOGGLES)
+            .count();
+        write_page(
+            "Crates.io Feature Report",
+            html! {
+                section {
+                    p: format!(
+                        "{} of {} crates have {} or more features and optional dependencies in their latest version",
+                        enormous, info_by_crate.len(), ENORMOUS_FEATURE_MATRIX_TOGGLES
+                    );
+                }
+                section {
+                    h3: "Largest feature matrices";
+                    ol {
+                        @ for (name, summary) in by_toggles.into_iter().take(TOP_LIST) {
+                            li {
+                                a(href=format!("{}/index.html", name)): name;
+                                : format!(" {}: 2^{} combinations", summary.latest_version, summary.latest.toggles());
+                            }
+                        }
+                    }
+                }
+                section {
+                    h3: "Heaviest features";
+                    ol {
+                        @ for (name, feature, weight) in by_weight.into_iter().take(TOP_LIST) {
+                            li {
+                                a(href=format!("{}/index.html", name)): name;
+                                : format!(" '{}' pulls in about {} dependencies", feature, weight);
+                            }
+                        }
+                    }
+                }
+            },
+            generated_at,
+            out,
+        )
+    }
+}
+
+pub type Generator = index::Generator<FeatureInfo>;
+
+#[cfg(test)]
+mod report_test;
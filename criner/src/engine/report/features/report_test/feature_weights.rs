@@ -0,0 +1,53 @@
+use crate::{engine::report::features::feature_weights, model::Dependency};
+use common_macros::{b_tree_map, hash_map};
+
+fn optional(name: &str) -> Dependency {
+    Dependency {
+        name: name.into(),
+        optional: true,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn features_enabling_features_and_dependencies_are_followed_transitively() {
+    let features = hash_map! {
+        "default".to_string() => vec!["std".to_string()],
+        "std".to_string() => vec!["serde/std".to_string(), "full".to_string()],
+        "full".to_string() => vec!["dep:regex".to_string(), "log".to_string(), "tokio?/rt".to_string()],
+        "cycle".to_string() => vec!["cycle".to_string(), "tokio".to_string()],
+    };
+    let dependencies = vec![optional("serde"), optional("regex"), optional("log"), optional("tokio")];
+    let weight = |d: &Dependency| match d.name.as_str() {
+        "serde" => 1,
+        "regex" => 10,
+        "log" => 100,
+        "tokio" => 1000,
+        _ => unreachable!("only known dependencies"),
+    };
+    assert_eq!(
+        feature_weights(&features, &dependencies, weight),
+        b_tree_map! {
+            "default".to_string() => 111,
+            "std".to_string() => 111,
+            "full".to_string() => 110,
+            "cycle".to_string() => 1000,
+        },
+        "weak dependencies are not enabled, and each dependency is only counted once"
+    );
+}
+
+#[test]
+fn non_optional_dependencies_carry_no_weight() {
+    let features = hash_map! {
+        "derive".to_string() => vec!["serde/derive".to_string()],
+    };
+    let dependencies = vec![Dependency {
+        name: "serde".into(),
+        ..Default::default()
+    }];
+    assert_eq!(
+        feature_weights(&features, &dependencies, |_| 1),
+        b_tree_map! { "derive".to_string() => 0 }
+    );
+}
@@ -0,0 +1 @@
+mod feature_weights;
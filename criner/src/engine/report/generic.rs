@@ -1,12 +1,44 @@
+use crate::engine::work::cpubound::{Extraction, ExtractionNeed};
 use crate::persistence::{CrateVersionTable, TableAccess};
 use crate::{
-    error::Result,
+    engine::report::{errors, Settings},
+    error::{Error, Result},
     model, persistence,
     persistence::{new_key_insertion, ReportsTree},
+    utils::check,
 };
 use async_trait::async_trait;
+use futures_util::{stream::FuturesUnordered, FutureExt, StreamExt};
 use rusqlite::{params, TransactionBehavior};
-use std::path::{Path, PathBuf};
+use std::{
+    borrow::Cow,
+    io::Write,
+    panic::AssertUnwindSafe,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// The amount of versions of a crate whose reports are generated and written at the same time
+const MAX_CONCURRENT_VERSION_REPORTS: usize = 8;
+
+/// The file in each report directory listing the keys of the reports marked done while writing to it, one per line
+pub const MARKERS_FILE_NAME: &str = "__report_markers__";
+/// The file next to the page of a crate with the content of `Generator::crate_api()`
+pub const CRATE_API_FILE_NAME: &str = "api.json";
+
+fn record_markers(out_dir: &Path, keys: &[String]) -> Result<()> {
+    // Chunks are written by multiple processors at once, but the keys of each must end up in one piece
+    static APPEND: parking_lot::Mutex<()> = parking_lot::const_mutex(());
+    let mut lines = keys.join("\n");
+    lines.push('\n');
+    let _guard = APPEND.lock();
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(out_dir.join(MARKERS_FILE_NAME))?
+        .write_all(lines.as_bytes())?;
+    Ok(())
+}
 
 fn all_but_recently_yanked(
     crate_name: &str,
@@ -54,10 +86,32 @@ where
     Self: Sized,
 {
     fn merge(self, other: Self) -> Self;
-    async fn complete(&mut self, progress: &mut prodash::tree::Item, out: &mut Vec<u8>) -> Result<()>;
-    async fn load_previous_state(&self, out_dir: &Path, progress: &mut prodash::tree::Item) -> Option<Self>;
-    async fn load_previous_top_level_state(out_dir: &Path, progress: &mut prodash::tree::Item) -> Option<Self>;
-    async fn store_current_state(&self, out_dir: &Path, progress: &mut prodash::tree::Item) -> Result<()>;
+    async fn complete(
+        &mut self,
+        settings: &Settings,
+        progress: &mut prodash::tree::Item,
+        out: &mut Vec<u8>,
+    ) -> Result<()>;
+    async fn load_previous_state(
+        &self,
+        settings: &Settings,
+        out_dir: &Path,
+        progress: &mut prodash::tree::Item,
+    ) -> Option<Self>;
+    async fn load_previous_top_level_state(
+        settings: &Settings,
+        out_dir: &Path,
+        progress: &mut prodash::tree::Item,
+    ) -> Option<Self>;
+    async fn store_current_state(
+        &self,
+        settings: &Settings,
+        out_dir: &Path,
+        progress: &mut prodash::tree::Item,
+    ) -> Result<()>;
+    /// Remove the state of `crate_name` in `out_dir` and everything it contributed to the top-level state, so all of its
+    /// versions can be reported once more. Returns true if there was a state of the crate.
+    fn forget_crate(settings: &Settings, out_dir: &Path, crate_name: &str) -> Result<bool>;
 }
 
 #[async_trait]
@@ -67,20 +121,39 @@ pub trait Generator {
 
     fn name() -> &'static str;
     fn version() -> &'static str;
+    /// The version of the reports generated with `settings`, which is `version()` unless the settings change what the
+    /// reports contain. Reports of different versions are never merged.
+    fn report_version(_settings: &Settings) -> Cow<'static, str> {
+        Self::version().into()
+    }
 
     fn fq_result_key(crate_name: &str, crate_version: &str, key_buf: &mut String);
-    fn fq_report_key(crate_name: &str, crate_version: &str, key_buf: &mut String) {
-        ReportsTree::key_buf(crate_name, crate_version, Self::name(), Self::version(), key_buf);
+    fn fq_report_key(settings: &Settings, crate_name: &str, crate_version: &str, key_buf: &mut String) {
+        ReportsTree::key_buf(
+            crate_name,
+            crate_version,
+            Self::name(),
+            &Self::report_version(settings),
+            key_buf,
+        );
     }
 
     fn get_result(
+        settings: &Settings,
         connection: persistence::ThreadSafeConnection,
         crate_name: &str,
         crate_version: &str,
         key_buf: &mut String,
     ) -> Result<Option<Self::DBResult>>;
 
+    /// The extraction results are read from, if any. Versions whose crate wasn't extracted like this yet are reported
+    /// later, and their extraction is requested in case their crate was downloaded already.
+    fn needed_extraction() -> Option<Extraction> {
+        None
+    }
+
     async fn merge_reports(
+        settings: Settings,
         out_dir: PathBuf,
         cache_dir: Option<PathBuf>,
         mut progress: prodash::tree::Item,
@@ -108,9 +181,9 @@ pub trait Generator {
         }
         if let Some(mut report) = report {
             let previous_report = match cache_dir.as_ref() {
-                Some(cd) => match Self::Report::load_previous_top_level_state(cd, &mut progress).await {
+                Some(cd) => match Self::Report::load_previous_top_level_state(&settings, cd, &mut progress).await {
                     Some(r) => Some(r),
-                    None => report.load_previous_state(cd, &mut progress).await,
+                    None => report.load_previous_state(&settings, cd, &mut progress).await,
                 },
                 None => None,
             };
@@ -121,6 +194,7 @@ pub trait Generator {
             {
                 complete_and_write_report(
                     &mut report,
+                    &settings,
                     Vec::new(),
                     &mut progress,
                     out_dir.join("index.html"),
@@ -130,20 +204,40 @@ pub trait Generator {
                 .await?;
             }
             if let Some(cd) = cache_dir {
-                report.store_current_state(&cd, &mut progress).await?;
+                report.store_current_state(&settings, &cd, &mut progress).await?;
             }
         }
         Ok(())
     }
 
     async fn generate_report(
+        settings: &Settings,
         crate_name: &str,
         crate_version: &str,
         result: Self::DBResult,
         progress: &mut prodash::tree::Item,
     ) -> Result<Self::Report>;
 
+    /// Additional files to write next to the page of a version, as pairs of file extension and content, like
+    /// `("toml", …)` to write `<version>.toml`.
+    fn version_artifacts(_report: &Self::Report) -> Vec<(&'static str, Vec<u8>)> {
+        Vec::new()
+    }
+
+    /// A summary of the report of a crate as JSON for consumption by tools, to be written to `CRATE_API_FILE_NAME` next
+    /// to its page.
+    fn crate_api(_report: &Self::Report) -> Result<Option<Vec<u8>>> {
+        Ok(None)
+    }
+
+    /// Write the reports of all versions of `krates` which weren't reported yet, along with a report for each crate.
+    /// Versions of a crate are reported concurrently, and no further crates are reported once `deadline` is reached.
+    ///
+    /// Crates and versions which fail to be reported, even by panicking, are recorded as errors of the report cycle and
+    /// left out, to be tried again in the next cycle, while all others are reported as usual.
+    #[allow(clippy::too_many_arguments)]
     async fn write_files(
+        settings: Settings,
         db: persistence::Db,
         out_dir: PathBuf,
         cache_dir: Option<PathBuf>,
@@ -151,6 +245,7 @@ pub trait Generator {
         mut progress: prodash::tree::Item,
         write: WriteCallback,
         write_state: WriteCallbackState,
+        deadline: Option<SystemTime>,
     ) -> Result<Option<Self::Report>> {
         let mut chunk_report = None::<Self::Report>;
         let crate_versions = db.open_crate_versions()?;
@@ -162,88 +257,119 @@ pub trait Generator {
             let mut key_buf = String::with_capacity(32);
             // delaying writes works because we don't have overlap on work
             for (name, krate) in krates.into_iter() {
-                let c: model::Crate = krate.as_slice().into();
+                crate::utils::wait_while_paused(&mut progress, deadline).await?;
+                // Stop in between crates only, as all versions of a crate are marked done together with its state
+                if check(deadline).is_err() {
+                    progress.info("Stopping early as the deadline was reached");
+                    break;
+                }
+                // Decoding panics on malformed items, which shouldn't stop all other crates from being reported
+                let c: model::Crate = match std::panic::catch_unwind(|| model::Crate::from(krate.as_slice())) {
+                    Ok(c) => c,
+                    Err(panic) => {
+                        let err = errors::panic_message(panic);
+                        progress.fail(format!("{} could not be reported: {}", name, err));
+                        errors::record(&out_dir, &name, err)?;
+                        continue;
+                    }
+                };
                 let crate_dir = crate_dir(&out_dir, &name);
                 progress.init(Some(c.versions.len()), Some("versions".into()));
                 progress.set_name(&name);
 
-                let mut crate_report = None::<Self::Report>;
+                let mut versions_to_report = Vec::new();
                 for version in c.versions.iter().take(all_but_recently_yanked(
                     &name,
                     &c.versions,
                     &crate_versions,
                     &mut key_buf,
                 )?) {
-                    progress.inc();
-
                     key_buf.clear();
-                    Self::fq_report_key(&name, version, &mut key_buf);
+                    Self::fq_report_key(&settings, &name, version, &mut key_buf);
 
                     // If we have no cache, assume we are globbed (yes, I know…sigh), so always produce reports
                     // but don't invalidate data in caches by reading or writing them. Mostly used for testing
                     // as it creates a sub-report, every time without having to fiddle with the
                     // reports_done marker table.
                     if cache_dir.is_none() || !reports.is_done(&key_buf) {
-                        let reports_key = key_buf.clone();
-                        key_buf.clear();
-
-                        if let Some(result) = Self::get_result(connection.clone(), &name, version, &mut key_buf)? {
-                            let mut version_report =
-                                Self::generate_report(&name, version, result, &mut progress).await?;
-
-                            out_buf = complete_and_write_report(
-                                &mut version_report,
-                                out_buf,
-                                &mut progress,
-                                version_html_path(&crate_dir, version),
-                                write,
-                                &write_state,
-                            )
-                            .await?;
-
-                            crate_report = Some(match crate_report {
-                                Some(crate_report) => crate_report.merge(version_report),
-                                None => version_report,
-                            });
-
-                            reports_to_mark_done.push(reports_key);
-                        }
+                        versions_to_report.push((version, key_buf.clone()));
+                    } else {
+                        progress.inc();
                     }
                 }
-                if let Some(mut crate_report) = crate_report {
-                    let previous_state = match cache_dir.as_ref() {
-                        Some(cd) => crate_report.load_previous_state(cd, &mut progress).await,
-                        None => None,
-                    };
-                    match previous_state {
-                        Some(previous_state) => {
-                            let mut absolute_state = previous_state.merge(crate_report.clone());
-                            out_buf = complete_and_write_report(
-                                &mut absolute_state,
-                                out_buf,
-                                &mut progress,
-                                crate_html_path(&crate_dir),
-                                write,
-                                &write_state,
-                            )
-                            .await?;
-                            if let Some(cd) = cache_dir.as_ref() {
-                                absolute_state.store_current_state(cd, &mut progress).await?;
-                            };
-                        }
-                        None => {
-                            out_buf = complete_and_write_report(
-                                &mut crate_report,
-                                out_buf,
-                                &mut progress,
-                                crate_html_path(&crate_dir),
-                                write,
-                                &write_state,
-                            )
-                            .await?;
-                            if let Some(cd) = cache_dir.as_ref() {
-                                crate_report.store_current_state(cd, &mut progress).await?;
-                            }
+
+                let mut crate_report = None::<Self::Report>;
+                let mut crate_reports_to_mark_done = Vec::new();
+                let mut versions_to_report = versions_to_report.into_iter();
+                let mut in_flight = FuturesUnordered::new();
+                // Versions are merged in the order they finish, which is only the same in every run one at a time
+                let max_concurrent_version_reports = if settings.is_deterministic() {
+                    1
+                } else {
+                    MAX_CONCURRENT_VERSION_REPORTS
+                };
+                for idx in 0..max_concurrent_version_reports {
+                    match versions_to_report.next() {
+                        Some((version, reports_key)) => in_flight.push(write_version_report::<Self>(
+                            &settings,
+                            connection.clone(),
+                            &name,
+                            version,
+                            &out_dir,
+                            &crate_dir,
+                            reports_key,
+                            progress.add_child(format!("{}", idx + 1)),
+                            write,
+                            &write_state,
+                        )),
+                        None => break,
+                    }
+                }
+                while let Some((version_report, version_progress)) = in_flight.next().await {
+                    progress.inc();
+                    if let Some((version_report, reports_key)) = version_report? {
+                        crate_report = Some(match crate_report {
+                            Some(crate_report) => crate_report.merge(version_report),
+                            None => version_report,
+                        });
+                        crate_reports_to_mark_done.push(reports_key);
+                    }
+                    if let Some((version, reports_key)) = versions_to_report.next() {
+                        in_flight.push(write_version_report::<Self>(
+                            &settings,
+                            connection.clone(),
+                            &name,
+                            version,
+                            &out_dir,
+                            &crate_dir,
+                            reports_key,
+                            version_progress,
+                            write,
+                            &write_state,
+                        ));
+                    }
+                }
+                if let Some(crate_report) = crate_report {
+                    let written = AssertUnwindSafe(write_crate_report::<Self>(
+                        &settings,
+                        &crate_report,
+                        &crate_dir,
+                        cache_dir.as_deref(),
+                        std::mem::take(&mut out_buf),
+                        &mut progress,
+                        write,
+                        &write_state,
+                    ))
+                    .catch_unwind()
+                    .await
+                    .unwrap_or_else(|panic| Err(Error::Message(errors::panic_message(panic))));
+                    match written {
+                        Ok(buf) => out_buf = buf,
+                        Err(err) => {
+                            // None of its versions are marked done, so the crate is reported once more next cycle
+                            progress.fail(format!("{} could not be reported: {}", name, err));
+                            errors::record(&out_dir, &name, err)?;
+                            continue;
                         }
                     }
                     chunk_report = Some(match chunk_report {
@@ -251,10 +377,13 @@ pub trait Generator {
                         None => crate_report,
                     });
                 }
+                reports_to_mark_done.extend(crate_reports_to_mark_done);
             }
         }
 
         if !reports_to_mark_done.is_empty() {
+            // Recorded before the markers are written, so a report cycle which isn't published can always remove them
+            record_markers(&out_dir, &reports_to_mark_done)?;
             let mut connection = db.open_connection_no_async_with_busy_wait()?;
             progress.blocked("wait for write lock", None);
             let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
@@ -275,6 +404,149 @@ pub trait Generator {
     }
 }
 
+/// Generate and write the report of a single version, returning it along with the key marking it done and `progress`
+/// for reuse. Versions which fail to be reported are recorded as errors of the report in `out_dir` and returned as `None`.
+#[allow(clippy::too_many_arguments)]
+async fn write_version_report<G: Generator + ?Sized>(
+    settings: &Settings,
+    connection: persistence::ThreadSafeConnection,
+    crate_name: &str,
+    crate_version: &str,
+    out_dir: &Path,
+    crate_dir: &Path,
+    reports_key: String,
+    mut progress: prodash::tree::Item,
+    write: WriteCallback,
+    write_state: &WriteCallbackState,
+) -> (Result<Option<(G::Report, String)>>, prodash::tree::Item) {
+    progress.set_name(crate_version);
+    let res = AssertUnwindSafe(async {
+        let mut key_buf = String::new();
+        if let Some(extraction) = G::needed_extraction() {
+            if !is_extracted(&connection, crate_name, crate_version, extraction)? {
+                settings.needed_extractions.request(ExtractionNeed {
+                    crate_name: crate_name.into(),
+                    crate_version: crate_version.into(),
+                    extraction,
+                });
+                return Ok(None);
+            }
+        }
+        let result = match G::get_result(settings, connection, crate_name, crate_version, &mut key_buf)? {
+            Some(result) => result,
+            None => return Ok(None),
+        };
+        let mut version_report = G::generate_report(settings, crate_name, crate_version, result, &mut progress).await?;
+        complete_and_write_report(
+            &mut version_report,
+            settings,
+            Vec::new(),
+            &mut progress,
+            version_html_path(crate_dir, crate_version),
+            write,
+            write_state,
+        )
+        .await?;
+        for (extension, content) in G::version_artifacts(&version_report) {
+            write_file(
+                content,
+                &mut progress,
+                version_artifact_path(crate_dir, crate_version, extension),
+                write,
+                write_state,
+            )
+            .await?;
+        }
+        Ok(Some((version_report, reports_key)))
+    })
+    .catch_unwind()
+    .await
+    .unwrap_or_else(|panic| Err(Error::Message(errors::panic_message(panic))));
+    let res = match res {
+        Err(err) => {
+            progress.fail(format!(
+                "{}:{} could not be reported: {}",
+                crate_name, crate_version, err
+            ));
+            errors::record(out_dir, &format!("{}:{}", crate_name, crate_version), err).map(|_| None)
+        }
+        res => res,
+    };
+    (res, progress)
+}
+
+/// Complete and write the report of a crate merged with its previous state, if there is one in `cache_dir`, and store
+/// the merged state as the new one. Returns the buffer of the written page for reuse.
+#[allow(clippy::too_many_arguments)]
+async fn write_crate_report<G: Generator + ?Sized>(
+    settings: &Settings,
+    crate_report: &G::Report,
+    crate_dir: &Path,
+    cache_dir: Option<&Path>,
+    out_buf: Vec<u8>,
+    progress: &mut prodash::tree::Item,
+    write: WriteCallback,
+    write_state: &WriteCallbackState,
+) -> Result<Vec<u8>> {
+    let previous_state = match cache_dir {
+        Some(cd) => crate_report.load_previous_state(settings, cd, progress).await,
+        None => None,
+    };
+    let mut absolute_state = match previous_state {
+        Some(previous_state) => previous_state.merge(crate_report.clone()),
+        None => crate_report.clone(),
+    };
+    let out_buf = complete_and_write_report(
+        &mut absolute_state,
+        settings,
+        out_buf,
+        progress,
+        crate_html_path(crate_dir),
+        write,
+        write_state,
+    )
+    .await?;
+    write_crate_api::<G>(&absolute_state, crate_dir, progress, write, write_state).await?;
+    if let Some(cd) = cache_dir {
+        absolute_state.store_current_state(settings, cd, progress).await?;
+    }
+    Ok(out_buf)
+}
+
+async fn write_crate_api<G: Generator + ?Sized>(
+    crate_report: &G::Report,
+    crate_dir: &Path,
+    progress: &mut prodash::tree::Item,
+    write: WriteCallback,
+    write_state: &WriteCallbackState,
+) -> Result<()> {
+    if let Some(content) = G::crate_api(crate_report)? {
+        write_file(
+            content,
+            progress,
+            crate_dir.join(CRATE_API_FILE_NAME),
+            write,
+            write_state,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+/// Returns true if the crate of the given version was extracted at least as far as `extraction` requires
+fn is_extracted(
+    connection: &persistence::ThreadSafeConnection,
+    crate_name: &str,
+    crate_version: &str,
+    extraction: Extraction,
+) -> Result<bool> {
+    let tasks = persistence::TaskTable {
+        inner: connection.clone(),
+        backend: connection.clone(),
+    };
+    extraction.node().is_complete(&tasks, crate_name, crate_version)
+}
+
 fn crate_dir(base: &Path, crate_name: &str) -> PathBuf {
     base.join(crate_name)
 }
@@ -282,12 +554,16 @@ fn crate_dir(base: &Path, crate_name: &str) -> PathBuf {
 fn version_html_path(crate_dir: &Path, version: &str) -> PathBuf {
     crate_dir.join(format!("{}.html", version))
 }
+fn version_artifact_path(crate_dir: &Path, version: &str, extension: &str) -> PathBuf {
+    crate_dir.join(format!("{}.{}", version, extension))
+}
 fn crate_html_path(crate_dir: &Path) -> PathBuf {
     crate_dir.join("index.html")
 }
 
 async fn complete_and_write_report(
     report: &mut impl Aggregate,
+    settings: &Settings,
     mut out: Vec<u8>,
     progress: &mut prodash::tree::Item,
     path: impl AsRef<Path>,
@@ -295,7 +571,17 @@ async fn complete_and_write_report(
     write_state: &WriteCallbackState,
 ) -> Result<Vec<u8>> {
     out.clear();
-    report.complete(progress, &mut out).await?;
+    report.complete(settings, progress, &mut out).await?;
+    write_file(out, progress, path, write, write_state).await
+}
+
+async fn write_file(
+    out: Vec<u8>,
+    progress: &mut prodash::tree::Item,
+    path: impl AsRef<Path>,
+    write: WriteCallback,
+    write_state: &WriteCallbackState,
+) -> Result<Vec<u8>> {
     progress.blocked("sending report to writer", None);
     match write(
         WriteRequest {
@@ -314,7 +600,8 @@ async fn complete_and_write_report(
             .await?;
             progress.halted("writing report to disk", None);
 
-            let content = blocking::unblock(move || std::fs::write(path, &content).map(|_| content)).await?;
+            let content =
+                blocking::unblock(move || crate::utils::write_replacing(path, &content).map(|_| content)).await?;
             Ok(content)
         }
         WriteInstruction::Skip => Ok(Vec::new()),
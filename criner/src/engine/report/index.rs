@@ -0,0 +1,127 @@
+//! Reports derived purely from crate versions in the crates.io index, without the need to download anything.
+use crate::{
+    engine::report::versioned::{self, Analysis},
+    error::Result,
+    model::{CrateVersion, Dependency, YankTransition},
+    persistence::{self, CrateTable, CrateVersionTable, TableAccess},
+};
+use async_trait::async_trait;
+use std::marker::PhantomData;
+
+/// An analysis of a single crate version as seen in the crates.io index
+pub trait IndexAnalysis: Analysis {
+    fn from_crate_version(version: &CrateVersion, index: &mut IndexLookup) -> Self;
+}
+
+/// Provides access to other crates in the index, i.e. to learn about dependencies
+pub struct IndexLookup {
+    crates: CrateTable,
+    crate_versions: CrateVersionTable,
+    key_buf: String,
+}
+
+impl IndexLookup {
+    pub fn new(connection: persistence::ThreadSafeConnection) -> Self {
+        IndexLookup {
+            crates: CrateTable {
+                inner: connection.clone(),
+                backend: connection.clone(),
+            },
+            crate_versions: CrateVersionTable {
+                inner: connection.clone(),
+                backend: connection,
+            },
+            key_buf: String::new(),
+        }
+    }
+
+    pub fn crate_version(&mut self, crate_name: &str, crate_version: &str) -> Result<Option<CrateVersion>> {
+        self.key_buf.clear();
+        CrateVersion::key_from(crate_name, crate_version, &mut self.key_buf);
+        self.crate_versions.get(&self.key_buf)
+    }
+
+    /// All versions of the given crate in the order they were published
+    pub fn versions(&mut self, crate_name: &str) -> Vec<String> {
+        self.crates
+            .get(crate_name)
+            .ok()
+            .flatten()
+            .map(|c| c.versions)
+            .unwrap_or_default()
+    }
+
+    /// The changes of the yanked status of the given crate version, oldest first
+    pub fn yank_history(&mut self, crate_name: &str, crate_version: &str) -> Vec<YankTransition> {
+        self.crates
+            .get(crate_name)
+            .ok()
+            .flatten()
+            .and_then(|mut c| c.yank_history.remove(crate_version))
+            .unwrap_or_default()
+    }
+
+    /// The version of the given crate that was published last
+    pub fn latest_version(&mut self, crate_name: &str) -> Option<CrateVersion> {
+        let version = self.versions(crate_name).pop()?;
+        self.crate_version(crate_name, &version).ok()?
+    }
+
+    /// An estimate of how many crates are pulled in by a dependency: itself, and the non-optional dependencies
+    /// of its latest version.
+    pub fn dependency_weight(&mut self, dependency: &Dependency) -> u64 {
+        let own_dependencies = self
+            .latest_version(dependency.crate_name())
+            .map(|v| v.dependencies.iter().filter(|d| !d.optional && d.is_normal()).count())
+            .unwrap_or(0);
+        1 + own_dependencies as u64
+    }
+}
+
+/// Generates a `versioned::Report` for any analysis of index data
+pub struct Generator<A>(PhantomData<A>);
+
+#[async_trait]
+impl<A: IndexAnalysis> super::generic::Generator for Generator<A> {
+    type Report = versioned::Report<A>;
+    type DBResult = A;
+
+    fn name() -> &'static str {
+        A::name()
+    }
+
+    fn version() -> &'static str {
+        A::version()
+    }
+
+    fn fq_result_key(crate_name: &str, crate_version: &str, key_buf: &mut String) {
+        CrateVersion::key_from(crate_name, crate_version, key_buf);
+    }
+
+    fn get_result(
+        _settings: &super::Settings,
+        connection: persistence::ThreadSafeConnection,
+        crate_name: &str,
+        crate_version: &str,
+        _key_buf: &mut String,
+    ) -> Result<Option<Self::DBResult>> {
+        let mut index = IndexLookup::new(connection);
+        Ok(index
+            .crate_version(crate_name, crate_version)?
+            .map(|version| A::from_crate_version(&version, &mut index)))
+    }
+
+    async fn generate_report(
+        _settings: &super::Settings,
+        crate_name: &str,
+        crate_version: &str,
+        result: Self::DBResult,
+        _progress: &mut prodash::tree::Item,
+    ) -> Result<Self::Report> {
+        Ok(versioned::Report::Version {
+            crate_name: crate_name.into(),
+            crate_version: crate_version.into(),
+            info: result,
+        })
+    }
+}
@@ -0,0 +1,175 @@
+//! An audit of downloaded crates, recomputing the checksum of a random sample and comparing it with the one in the index.
+use crate::{
+    engine::{
+        report::versioned::write_page,
+        work::{artifact, asset_store::AssetStore, schedule},
+    },
+    error::Result,
+    model::{CrateVersion, Task, TaskState},
+    persistence::{self, TableAccess, TaskTable, KEY_SEP_CHAR},
+};
+use horrorshow::html;
+use rusqlite::params;
+use sha2::{Digest, Sha256};
+use std::{io::Read, path::Path, time::SystemTime};
+
+/// The amount of downloaded crates to check each time the audit runs
+const SAMPLE_SIZE: usize = 100;
+/// The amount of past findings to show
+const HISTORY_LEN: usize = 100;
+/// The error telemetry category of problems found by the audit
+pub const TELEMETRY_CATEGORY: &str = "integrity";
+
+pub struct Mismatch {
+    pub crate_version: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+#[derive(Default)]
+pub struct Audit {
+    /// The amount of files whose checksum was compared
+    pub checked: usize,
+    /// Crates whose download completed, but whose file isn't on disk anymore, possibly due to retention
+    pub missing: Vec<String>,
+    pub mismatches: Vec<Mismatch>,
+    /// Problems found by previous audits, most recent first, as seconds since epoch, crate version and message
+    pub history: Vec<(i64, String, String)>,
+}
+
+/// Keys of up to `SAMPLE_SIZE` randomly chosen crate versions whose crate was downloaded
+fn sample(db: &persistence::Db) -> Result<Vec<String>> {
+    let connection = db.open_connection_no_async_with_busy_wait()?;
+    let task_suffix = format!(
+        "{sep}{}{sep}{}",
+        artifact::CRATE.task_process,
+        artifact::CRATE.task_version,
+        sep = KEY_SEP_CHAR
+    );
+    let mut statement = connection.prepare(&format!(
+        "SELECT key, data FROM {} WHERE key LIKE '%' || ?1 ORDER BY RANDOM() LIMIT ?2",
+        TaskTable::table_name()
+    ))?;
+    let keys = statement
+        .query_map(params![task_suffix, SAMPLE_SIZE as i64], |r| {
+            Ok((r.get::<_, String>(0)?, r.get::<_, Vec<u8>>(1)?))
+        })?
+        .filter_map(|r| r.ok())
+        .filter(|(_, data)| matches!(Task::from(data.as_slice()).state, TaskState::Complete))
+        .filter_map(|(key, _)| key.strip_suffix(task_suffix.as_str()).map(ToOwned::to_owned))
+        .collect();
+    Ok(keys)
+}
+
+fn sha256_of(mut asset: impl Read) -> Result<String> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut asset, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Check a random sample of downloaded crates against their checksum in the index, and record problems as error telemetry.
+pub fn run(
+    db: &persistence::Db,
+    assets_dir: &Path,
+    assets: &dyn AssetStore,
+    progress: &mut prodash::tree::Item,
+) -> Result<Audit> {
+    progress.blocked("sampling downloaded crates", None);
+    let keys = sample(db)?;
+    let versions = db.open_crate_versions()?;
+    let telemetry = db.open_error_telemetry()?;
+    progress.init(Some(keys.len()), Some("crates".into()));
+
+    let mut audit = Audit::default();
+    for key in keys {
+        progress.inc();
+        let CrateVersion {
+            name,
+            version,
+            checksum,
+            ..
+        } = match versions.get(&key)? {
+            Some(v) => v,
+            None => continue,
+        };
+        let path = schedule::download_file_path(
+            assets_dir,
+            &name,
+            &version,
+            artifact::CRATE.task_process,
+            artifact::CRATE.task_version,
+            artifact::CRATE.name,
+        );
+        if assets.size(&path)?.is_none() {
+            // Not a finding, as retention removes crates of old versions once they are extracted
+            audit.missing.push(key);
+            continue;
+        }
+        let actual = sha256_of(assets.open(&path)?)?;
+        audit.checked += 1;
+        if actual != checksum {
+            let message = crate::Error::ChecksumMismatch(checksum.clone(), actual.clone()).to_string();
+            log::warn!("Integrity audit: '{}': {}", path.display(), message);
+            progress.fail(format!("{}: {}", key, message));
+            telemetry.record(TELEMETRY_CATEGORY, &key, message)?;
+            audit.mismatches.push(Mismatch {
+                crate_version: key,
+                expected: checksum,
+                actual,
+            });
+        }
+    }
+    audit.history = telemetry.recent(TELEMETRY_CATEGORY, HISTORY_LEN)?;
+    progress.done(format!(
+        "Checked {} crates, {} mismatch(es), {} missing",
+        audit.checked,
+        audit.mismatches.len(),
+        audit.missing.len()
+    ));
+    Ok(audit)
+}
+
+pub fn write_html(audit: &Audit, generated_at: SystemTime, out: &mut Vec<u8>) -> Result<()> {
+    write_page(
+        "Criner Integrity Audit",
+        html! {
+            section {
+                h3: "Latest audit";
+                p: format!(
+                    "Recomputed the checksum of {} randomly chosen downloaded crates: {} didn't match the crates.io index, {} were missing or removed by retention.",
+                    audit.checked,
+                    audit.mismatches.len(),
+                    audit.missing.len()
+                );
+                ul {
+                    @ for m in audit.mismatches.iter() {
+                        li: format!("{}: expected {}, got {}", m.crate_version, m.expected, m.actual);
+                    }
+                    @ for key in audit.missing.iter() {
+                        li: format!("{}: missing", key);
+                    }
+                }
+            }
+            section {
+                h3: "Findings of all audits, most recent first";
+                ul {
+                    @ for (occurred_at, key, message) in audit.history.iter() {
+                        li: format!(
+                            "{} {}: {}",
+                            humantime::format_rfc3339_seconds(
+                                std::time::UNIX_EPOCH + std::time::Duration::from_secs(*occurred_at as u64)
+                            ),
+                            key,
+                            message
+                        );
+                    }
+                }
+            }
+        },
+        generated_at,
+        out,
+    )
+}
+
+#[cfg(test)]
+mod integrity_test;
@@ -0,0 +1,86 @@
+use crate::{
+    engine::{
+        report::integrity::{run, sha256_of, write_html, TELEMETRY_CATEGORY},
+        work::{artifact, asset_store, schedule},
+    },
+    model::{CrateVersion, Task, TaskState},
+    persistence::{temp_db::TempDb, Keyed, TableAccess},
+};
+
+#[test]
+fn downloaded_crates_are_compared_with_the_checksum_in_the_index() {
+    let db = TempDb::new("integrity");
+    let assets_dir = db.dir.join("assets");
+    let mut progress = prodash::Tree::new().add_child("integrity");
+    let crate_versions = db.open_crate_versions().unwrap();
+    let tasks = db.open_tasks().unwrap();
+    let intact = sha256_of(&b"intact"[..]).unwrap();
+    for (version, content, state) in [
+        ("1.0.0", Some("intact"), TaskState::Complete),
+        ("1.1.0", Some("tampered"), TaskState::Complete),
+        ("1.2.0", None, TaskState::Complete),
+        ("1.3.0", Some("in progress"), TaskState::InProgress(None)),
+    ] {
+        let crate_version = CrateVersion {
+            name: "a".into(),
+            version: version.into(),
+            checksum: intact.clone(),
+            ..Default::default()
+        };
+        crate_versions
+            .insert(&mut progress, crate_version.key(), &crate_version)
+            .unwrap();
+        let task = Task {
+            process: artifact::CRATE.task_process.into(),
+            version: artifact::CRATE.task_version.into(),
+            state,
+            ..Default::default()
+        };
+        let mut key = String::new();
+        task.fq_key("a", version, &mut key);
+        tasks.insert(&mut progress, key, &task).unwrap();
+        if let Some(content) = content {
+            let path = schedule::download_file_path(
+                &assets_dir,
+                "a",
+                version,
+                artifact::CRATE.task_process,
+                artifact::CRATE.task_version,
+                artifact::CRATE.name,
+            );
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+    }
+
+    let audit = run(&db, &assets_dir, &*asset_store::local(), &mut progress).unwrap();
+    assert_eq!(audit.checked, 2, "only completed downloads are checked");
+    assert_eq!(audit.missing, vec!["a:1.2.0"]);
+    assert_eq!(
+        audit
+            .mismatches
+            .iter()
+            .map(|m| (m.crate_version.as_str(), m.expected == intact, m.actual == intact))
+            .collect::<Vec<_>>(),
+        vec![("a:1.1.0", true, false)]
+    );
+    assert_eq!(
+        audit.history.iter().map(|(_, key, _)| key.as_str()).collect::<Vec<_>>(),
+        vec!["a:1.1.0"],
+        "mismatches are recorded as error telemetry"
+    );
+    assert_eq!(
+        db.open_error_telemetry()
+            .unwrap()
+            .recent(TELEMETRY_CATEGORY, 10)
+            .unwrap()
+            .len(),
+        1
+    );
+
+    let mut out = Vec::new();
+    write_html(&audit, std::time::SystemTime::now(), &mut out).unwrap();
+    let html = String::from_utf8(out).unwrap();
+    assert!(html.contains("Recomputed the checksum of 2 randomly chosen downloaded crates: 1 didn"));
+    assert!(html.contains("a:1.2.0: missing"));
+}
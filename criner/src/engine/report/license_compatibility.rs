@@ -0,0 +1,378 @@
+//! Crates whose dependency tree mixes licenses which can't be combined, like a GPL crate somewhere below an MIT crate,
+//! found by combining the licenses of the license inventory with the dependency graph.
+//!
+//! Licenses are put into broad kinds, and a crate is in conflict with each strong copyleft crate it depends on, directly
+//! or indirectly, unless its own license is strong copyleft as well. Only non-optional normal and build dependencies
+//! of the greatest version of each crate which isn't yanked are followed, as in the dependency graph.
+use crate::{
+    engine::report::{
+        licenses::{self, LicenseInfo},
+        versioned::{self, write_page, Dict},
+    },
+    error::Result,
+    graph::Graph,
+    persistence,
+    registry::Registry,
+    utils::write_replacing,
+};
+use horrorshow::{html, owned_html, RenderOnce};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    path::Path,
+    time::SystemTime,
+};
+
+const TOP_LIST: usize = 100;
+
+/// How restrictive a license is when software using it is distributed, from least to most restrictive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LicenseKind {
+    /// Licenses like MIT or Apache-2.0, which can be used under any other license
+    Permissive,
+    /// Licenses like LGPL or MPL, which only require the licensed files themselves to stay open
+    WeakCopyleft,
+    /// Licenses like GPL or AGPL, which require all software using the licensed software to be licensed alike
+    StrongCopyleft,
+    /// Licenses which aren't known, or crates without SPDX license expression
+    Unknown,
+}
+
+/// All kinds, in the order they are shown
+const ALL_KINDS: [LicenseKind; 4] = [
+    LicenseKind::Permissive,
+    LicenseKind::WeakCopyleft,
+    LicenseKind::StrongCopyleft,
+    LicenseKind::Unknown,
+];
+
+/// Normalized SPDX identifiers of licenses by their kind, as far as they are known
+const PERMISSIVE: &[&str] = &[
+    "MIT",
+    "MIT-0",
+    "APACHE-2.0",
+    "BSD-2-CLAUSE",
+    "BSD-3-CLAUSE",
+    "0BSD",
+    "ISC",
+    "ZLIB",
+    "BSL-1.0",
+    "CC0-1.0",
+    "UNLICENSE",
+    "UNICODE-DFS-2016",
+    "UNICODE-3.0",
+    "WTFPL",
+    "X11",
+];
+const WEAK_COPYLEFT: &[&str] = &[
+    "LGPL-2.0", "LGPL-2.1", "LGPL-3.0", "MPL-2.0", "EPL-1.0", "EPL-2.0", "CDDL-1.0",
+];
+const STRONG_COPYLEFT: &[&str] = &["GPL-2.0", "GPL-3.0", "AGPL-3.0", "EUPL-1.2", "OSL-3.0"];
+
+impl LicenseKind {
+    fn of_license(id: &str) -> LicenseKind {
+        let id = licenses::normalize(id);
+        if PERMISSIVE.contains(&id.as_str()) {
+            LicenseKind::Permissive
+        } else if WEAK_COPYLEFT.contains(&id.as_str()) {
+            LicenseKind::WeakCopyleft
+        } else if STRONG_COPYLEFT.contains(&id.as_str()) {
+            LicenseKind::StrongCopyleft
+        } else {
+            LicenseKind::Unknown
+        }
+    }
+
+    /// The kind of an SPDX `expression`, which is the least restrictive of its alternatives separated by `OR` or `/`,
+    /// each of which is as restrictive as the most restrictive of its known licenses. Parentheses are ignored.
+    pub fn of(expression: &str) -> LicenseKind {
+        let expression = expression.replace(['(', ')'], " ");
+        expression
+            .split('/')
+            .flat_map(|part| part.split(" OR ").flat_map(|part| part.split(" or ")))
+            .map(|alternative| {
+                alternative
+                    .split(" AND ")
+                    .flat_map(|part| part.split(" and "))
+                    .map(|license| {
+                        // Exceptions like `Classpath-exception-2.0` only ever make licenses less restrictive
+                        let license = license.trim();
+                        let license = license.split(" WITH ").next().unwrap_or(license);
+                        LicenseKind::of_license(license.split(" with ").next().unwrap_or(license).trim())
+                    })
+                    .filter(|kind| *kind != LicenseKind::Unknown)
+                    .max()
+                    .unwrap_or(LicenseKind::Unknown)
+            })
+            .min()
+            .unwrap_or(LicenseKind::Unknown)
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            LicenseKind::Permissive => "permissive",
+            LicenseKind::WeakCopyleft => "weak copyleft",
+            LicenseKind::StrongCopyleft => "strong copyleft",
+            LicenseKind::Unknown => "unknown",
+        }
+    }
+
+    /// Returns true if software under this license may not use software of the `dependency` kind
+    pub fn conflicts_with(&self, dependency: LicenseKind) -> bool {
+        dependency == LicenseKind::StrongCopyleft && matches!(self, LicenseKind::Permissive | LicenseKind::WeakCopyleft)
+    }
+}
+
+/// A crate depended on, directly or indirectly, whose license conflicts with the one of the dependent crate
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    /// The crate whose license conflicts
+    pub dependency: String,
+    /// The crates from the dependent crate to `dependency` along the shortest path through the graph, including both
+    pub path: Vec<String>,
+}
+
+/// The licenses of all crates and where they conflict in their dependency trees
+#[derive(Debug, Default)]
+pub struct Compatibility {
+    /// The declared license of each crate, by name, unset if there is no SPDX expression
+    pub licenses: BTreeMap<String, Option<String>>,
+    /// The amount of non-optional direct dependencies by the kind of the license of the dependent and the dependency
+    pub matrix: BTreeMap<(LicenseKind, LicenseKind), u64>,
+    /// The conflicts of each crate which has any, ordered by the length of their path
+    pub conflicts: BTreeMap<String, Vec<Conflict>>,
+}
+
+impl Compatibility {
+    fn kind(&self, crate_name: &str) -> LicenseKind {
+        self.licenses
+            .get(crate_name)
+            .and_then(|license| license.as_deref())
+            .map_or(LicenseKind::Unknown, LicenseKind::of)
+    }
+
+    /// Add the crates of `registry` in `graph` with their licenses as declared by the latest version of each crate in
+    /// `summaries` of the license inventory
+    pub fn add(&mut self, registry: &Registry, graph: &Graph, summaries: &Dict<licenses::LicenseSummary>) {
+        let qualified = |name: &str| registry.qualify(name).into_owned();
+        for name in graph.nodes.keys() {
+            let name = qualified(name);
+            let license = summaries.get(&name).and_then(|s| s.latest.declared.clone());
+            self.licenses.insert(name, license);
+        }
+
+        let mut dependents = BTreeMap::<String, Vec<String>>::new();
+        for edge in graph.edges.iter().filter(|e| !e.optional) {
+            let (from, to) = (qualified(&edge.from), qualified(&edge.to));
+            *self.matrix.entry((self.kind(&from), self.kind(&to))).or_default() += 1;
+            dependents.entry(to).or_default().push(from);
+        }
+
+        // Walk up from each crate which may conflict to all crates depending on it, which is far cheaper than walking
+        // down the dependency tree of every crate as only few crates are strong copyleft
+        let targets: Vec<_> = self
+            .licenses
+            .keys()
+            .filter(|name| registry.contains(name) && self.kind(name) == LicenseKind::StrongCopyleft)
+            .cloned()
+            .collect();
+        for target in targets {
+            let mut towards_target = BTreeMap::<String, String>::new();
+            let mut queue = VecDeque::new();
+            queue.push_back(target.clone());
+            while let Some(name) = queue.pop_front() {
+                for dependent in dependents.get(&name).into_iter().flatten() {
+                    if *dependent == target || towards_target.contains_key(dependent) {
+                        continue;
+                    }
+                    towards_target.insert(dependent.clone(), name.clone());
+                    queue.push_back(dependent.clone());
+                }
+            }
+            for dependent in towards_target.keys() {
+                if !self.kind(dependent).conflicts_with(LicenseKind::StrongCopyleft) {
+                    continue;
+                }
+                let mut path = vec![dependent.clone()];
+                while let Some(next) = towards_target.get(path.last().expect("non-empty")) {
+                    path.push(next.clone());
+                }
+                self.conflicts.entry(dependent.clone()).or_default().push(Conflict {
+                    dependency: target.clone(),
+                    path,
+                });
+            }
+        }
+        for conflicts in self.conflicts.values_mut() {
+            conflicts.sort_by(|lhs, rhs| (lhs.path.len(), &lhs.dependency).cmp(&(rhs.path.len(), &rhs.dependency)));
+        }
+    }
+}
+
+/// Combine the license inventory in `licenses_cache_dir` with the dependency graph of the crates of all registries in `db`
+pub fn run(
+    db: &persistence::Db,
+    licenses_cache_dir: &Path,
+    progress: &mut prodash::tree::Item,
+) -> Result<Compatibility> {
+    progress.blocked("loading license inventory", None);
+    let summaries = versioned::top_level_summaries::<LicenseInfo>(licenses_cache_dir).unwrap_or_default();
+    let mut registries: Vec<_> = db
+        .info()?
+        .map(|info| info.registries)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(name, index_url)| Registry { name, index_url })
+        .collect();
+    if registries.is_empty() {
+        registries.push(Registry::default());
+    }
+
+    let mut compatibility = Compatibility::default();
+    for registry in registries.iter() {
+        progress.blocked("building dependency graph", None);
+        let graph = Graph::from_db(db, registry)?;
+        compatibility.add(registry, &graph, &summaries);
+    }
+    progress.done(format!(
+        "{} of {} crates depend on crates whose license conflicts with theirs",
+        compatibility.conflicts.len(),
+        compatibility.licenses.len()
+    ));
+    Ok(compatibility)
+}
+
+fn license_of<'a>(compatibility: &'a Compatibility, crate_name: &str) -> &'a str {
+    compatibility
+        .licenses
+        .get(crate_name)
+        .and_then(|license| license.as_deref())
+        .unwrap_or("undeclared")
+}
+
+fn path_html(compatibility: &Compatibility, path: &[String]) -> impl RenderOnce {
+    let path = path
+        .iter()
+        .map(|name| format!("{} ({})", name, license_of(compatibility, name)))
+        .collect::<Vec<_>>()
+        .join(" → ");
+    owned_html! {
+        li: path;
+    }
+}
+
+pub fn write_html(compatibility: &Compatibility, generated_at: SystemTime, out: &mut Vec<u8>) -> Result<()> {
+    let mut crates_by_kind = BTreeMap::<LicenseKind, usize>::new();
+    for name in compatibility.licenses.keys() {
+        *crates_by_kind.entry(compatibility.kind(name)).or_default() += 1;
+    }
+    let mut by_conflicts: Vec<_> = compatibility.conflicts.iter().collect();
+    by_conflicts.sort_by_key(|(name, conflicts)| (std::cmp::Reverse(conflicts.len()), *name));
+    write_page(
+        "Crates.io License Compatibility",
+        html! {
+            section {
+                h3: "Direct dependencies by the kind of license of the dependent crate (rows) and the dependency (columns)";
+                p: "Unknown licenses are those the kind isn't known of, and those of crates which weren't extracted or have no SPDX expression.";
+                table {
+                    tr {
+                        th: "";
+                        @ for kind in ALL_KINDS.iter() {
+                            th: kind.name();
+                        }
+                    }
+                    @ for dependent in ALL_KINDS.iter() {
+                        tr {
+                            th: format!("{} ({} crates)", dependent.name(), crates_by_kind.get(dependent).copied().unwrap_or(0));
+                            @ for dependency in ALL_KINDS.iter() {
+                                td {
+                                    : compatibility.matrix.get(&(*dependent, *dependency)).copied().unwrap_or(0);
+                                    @ if dependent.conflicts_with(*dependency) {
+                                        : " (conflicting)";
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            section {
+                h3: format!(
+                    "{} crates depending on crates whose license conflicts with theirs, directly or indirectly",
+                    by_conflicts.len()
+                );
+                ol {
+                    @ for (name, conflicts) in by_conflicts.into_iter().take(TOP_LIST) {
+                        li {
+                            a(href=format!("{}/index.html", name)): name.as_str();
+                            : format!(
+                                " ({}): {} conflicting crate(s) like {}",
+                                license_of(compatibility, name),
+                                conflicts.len(),
+                                conflicts[0].dependency
+                            );
+                        }
+                    }
+                }
+            }
+        },
+        generated_at,
+        out,
+    )
+}
+
+pub fn write_crate_html(
+    compatibility: &Compatibility,
+    crate_name: &str,
+    generated_at: SystemTime,
+    out: &mut Vec<u8>,
+) -> Result<()> {
+    let conflicts = compatibility
+        .conflicts
+        .get(crate_name)
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    write_page(
+        &format!("License compatibility of {}", crate_name),
+        html! {
+            section {
+                p: format!(
+                    "{} is licensed under {} and depends on {} crate(s) whose license requires it to be licensed alike",
+                    crate_name,
+                    license_of(compatibility, crate_name),
+                    conflicts.len()
+                );
+                h3: "Shortest path to each of them";
+                ul {
+                    @ for conflict in conflicts.iter() {
+                        : path_html(compatibility, &conflict.path);
+                    }
+                }
+            }
+        },
+        generated_at,
+        out,
+    )
+}
+
+/// Write the overview and a page for each crate with conflicts into `report_dir`, replacing all pages of crates which
+/// had conflicts in an earlier cycle, all claiming to be generated at `generated_at`
+pub fn write_files(compatibility: &Compatibility, report_dir: &Path, generated_at: SystemTime) -> Result<()> {
+    if report_dir.is_dir() {
+        std::fs::remove_dir_all(report_dir)?;
+    }
+    std::fs::create_dir_all(report_dir)?;
+    let mut out = Vec::new();
+    write_html(compatibility, generated_at, &mut out)?;
+    write_replacing(report_dir.join("index.html"), &out)?;
+    for crate_name in compatibility.conflicts.keys() {
+        out.clear();
+        write_crate_html(compatibility, crate_name, generated_at, &mut out)?;
+        let crate_dir = report_dir.join(crate_name);
+        std::fs::create_dir_all(&crate_dir)?;
+        write_replacing(crate_dir.join("index.html"), &out)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod license_compatibility_test;
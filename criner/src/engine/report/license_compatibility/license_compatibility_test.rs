@@ -0,0 +1,141 @@
+use super::{write_files, Compatibility, Conflict, LicenseKind};
+use crate::{
+    engine::report::{
+        licenses::{LicenseInfo, LicenseSummary},
+        versioned::Dict,
+    },
+    graph::Graph,
+    model::{CrateVersion, Dependency},
+    registry::Registry,
+};
+
+fn version(name: &str, dependencies: &[(&str, bool)]) -> CrateVersion {
+    CrateVersion {
+        name: name.into(),
+        version: "1.0.0".into(),
+        dependencies: dependencies
+            .iter()
+            .map(|(name, optional)| Dependency {
+                name: (*name).into(),
+                required_version: "^1".into(),
+                optional: *optional,
+                ..Default::default()
+            })
+            .collect(),
+        ..Default::default()
+    }
+}
+
+fn summaries(licenses: &[(&str, &str)]) -> Dict<LicenseSummary> {
+    licenses
+        .iter()
+        .map(|(name, license)| {
+            (
+                name.to_string(),
+                LicenseSummary {
+                    latest_version: "1.0.0".into(),
+                    latest: LicenseInfo {
+                        declared: Some(license.to_string()),
+                        ..Default::default()
+                    },
+                },
+            )
+        })
+        .collect()
+}
+
+fn compatibility() -> Compatibility {
+    let graph = Graph::from_versions(
+        &Registry::default(),
+        vec![
+            version("app", &[("lib", false), ("optional-user", false)]),
+            version("lib", &[("gpl", false)]),
+            version("optional-user", &[("gpl", true)]),
+            version("gpl-app", &[("gpl", false)]),
+            version("gpl", &[("app", false)]),
+        ],
+    );
+    let mut compatibility = Compatibility::default();
+    compatibility.add(
+        &Registry::default(),
+        &graph,
+        &summaries(&[
+            ("app", "MIT OR Apache-2.0"),
+            ("lib", "MIT"),
+            ("optional-user", "MIT"),
+            ("gpl-app", "GPL-3.0-or-later"),
+            ("gpl", "GPL-3.0-only"),
+        ]),
+    );
+    compatibility
+}
+
+#[test]
+fn licenses_are_as_restrictive_as_their_least_restrictive_alternative() {
+    assert_eq!(LicenseKind::of("MIT OR Apache-2.0"), LicenseKind::Permissive);
+    assert_eq!(LicenseKind::of("MIT/Apache-2.0"), LicenseKind::Permissive);
+    assert_eq!(LicenseKind::of("GPL-3.0 OR MIT"), LicenseKind::Permissive);
+    assert_eq!(LicenseKind::of("GPL-3.0-or-later"), LicenseKind::StrongCopyleft);
+    assert_eq!(LicenseKind::of("GPL-2.0+"), LicenseKind::StrongCopyleft);
+    assert_eq!(LicenseKind::of("MIT AND GPL-2.0"), LicenseKind::StrongCopyleft);
+    assert_eq!(
+        LicenseKind::of("(MIT OR Apache-2.0) AND Unicode-DFS-2016"),
+        LicenseKind::Permissive
+    );
+    assert_eq!(LicenseKind::of("LGPL-2.1-only"), LicenseKind::WeakCopyleft);
+    assert_eq!(
+        LicenseKind::of("GPL-2.0 WITH Classpath-exception-2.0"),
+        LicenseKind::StrongCopyleft
+    );
+    assert_eq!(LicenseKind::of("LicenseRef-Proprietary"), LicenseKind::Unknown);
+
+    assert!(LicenseKind::Permissive.conflicts_with(LicenseKind::StrongCopyleft));
+    assert!(LicenseKind::WeakCopyleft.conflicts_with(LicenseKind::StrongCopyleft));
+    assert!(!LicenseKind::StrongCopyleft.conflicts_with(LicenseKind::StrongCopyleft));
+    assert!(!LicenseKind::Unknown.conflicts_with(LicenseKind::StrongCopyleft));
+    assert!(!LicenseKind::Permissive.conflicts_with(LicenseKind::WeakCopyleft));
+}
+
+#[test]
+fn conflicts_are_found_through_the_dependency_tree_along_the_shortest_path() {
+    let compatibility = compatibility();
+    let path = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+    assert_eq!(
+        compatibility.conflicts.keys().collect::<Vec<_>>(),
+        vec!["app", "lib"],
+        "optional dependencies and crates which are strong copyleft themselves are fine"
+    );
+    assert_eq!(
+        compatibility.conflicts["app"],
+        vec![Conflict {
+            dependency: "gpl".into(),
+            path: path(&["app", "lib", "gpl"]),
+        }],
+        "cycles back to the dependent don't matter"
+    );
+    assert_eq!(compatibility.conflicts["lib"][0].path, path(&["lib", "gpl"]));
+
+    use LicenseKind::*;
+    assert_eq!(compatibility.matrix[&(Permissive, Permissive)], 2);
+    assert_eq!(compatibility.matrix[&(Permissive, StrongCopyleft)], 1);
+    assert_eq!(compatibility.matrix[&(StrongCopyleft, StrongCopyleft)], 1);
+    assert_eq!(compatibility.matrix[&(StrongCopyleft, Permissive)], 1);
+}
+
+#[test]
+fn pages_of_crates_without_conflicts_are_removed() {
+    let dir = crate::persistence::temp_db::TempDir::new("license-compatibility-pages");
+    std::fs::create_dir_all(dir.join("resolved")).unwrap();
+    std::fs::write(dir.join("resolved").join("index.html"), "conflicts once").unwrap();
+
+    write_files(&compatibility(), &dir, std::time::SystemTime::now()).unwrap();
+    assert!(!dir.join("resolved").exists());
+    let overview = std::fs::read_to_string(dir.join("index.html")).unwrap();
+    assert!(overview.contains("2 crates depending on crates"), "{}", overview);
+    let app = std::fs::read_to_string(dir.join("app").join("index.html")).unwrap();
+    assert!(
+        app.contains("app (MIT OR Apache-2.0) → lib (MIT) → gpl (GPL-3.0-only)"),
+        "{}",
+        app
+    );
+}
@@ -0,0 +1,423 @@
+//! Which licenses crates declare in their manifest and which license files they bundle, and where the two disagree.
+//! Licenses are recognized in bundled files by phrases of their text, and named by their SPDX identifier.
+//! Only crates extracted since license files are stored have their contents checked, older ones only show which files
+//! are bundled.
+use crate::{
+    engine::report::{
+        generic,
+        versioned::{self, write_page, Dict},
+        waste::{self, tar_path_to_utf8, TarHeader},
+    },
+    engine::work::{cpubound::Extraction, manifest},
+    error::Result,
+    model::TaskResult,
+    persistence,
+    utils::parse_semver,
+};
+use async_trait::async_trait;
+use horrorshow::html;
+use serde_derive::{Deserialize, Serialize};
+use std::{collections::BTreeMap, time::SystemTime};
+
+const TOP_LIST: usize = 100;
+
+/// A license file bundled with a crate version
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LicenseFile {
+    /// The path relative to the crate root
+    pub path: String,
+    /// The SPDX identifier of the license recognized in its content, if it was stored and recognized
+    pub detected: Option<String>,
+}
+
+/// The licenses of a single crate version
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LicenseInfo {
+    /// The SPDX expression in the `license` field of Cargo.toml
+    pub declared: Option<String>,
+    /// The `license-file` field of Cargo.toml, for licenses without SPDX identifier
+    pub license_file: Option<String>,
+    /// All bundled license files
+    pub files: Vec<LicenseFile>,
+    /// How bundled license files disagree with Cargo.toml
+    pub mismatches: Vec<String>,
+}
+
+#[derive(serde_derive::Deserialize, Default)]
+#[serde(default)]
+struct Manifest {
+    package: Package,
+}
+
+#[derive(serde_derive::Deserialize, Default)]
+#[serde(default, rename_all = "kebab-case")]
+struct Package {
+    license: Option<String>,
+    license_file: Option<String>,
+}
+
+/// Phrases which identify a license, checked in order so that more specific licenses come first.
+/// Licenses with versions are recognized by their title, as their text mentions other licenses.
+const LICENSE_PHRASES: &[(&str, &[&str])] = &[
+    ("AGPL-3.0", &["GNU AFFERO GENERAL PUBLIC LICENSE VERSION 3"]),
+    ("LGPL-3.0", &["GNU LESSER GENERAL PUBLIC LICENSE VERSION 3"]),
+    ("LGPL-2.1", &["GNU LESSER GENERAL PUBLIC LICENSE VERSION 2.1"]),
+    ("GPL-3.0", &["GNU GENERAL PUBLIC LICENSE VERSION 3"]),
+    ("GPL-2.0", &["GNU GENERAL PUBLIC LICENSE VERSION 2"]),
+    ("Apache-2.0", &["APACHE LICENSE VERSION 2.0"]),
+    ("MPL-2.0", &["MOZILLA PUBLIC LICENSE VERSION 2.0"]),
+    ("BSL-1.0", &["BOOST SOFTWARE LICENSE - VERSION 1.0"]),
+    ("CC0-1.0", &["CC0 1.0 UNIVERSAL"]),
+    (
+        "Unlicense",
+        &["THIS IS FREE AND UNENCUMBERED SOFTWARE RELEASED INTO THE PUBLIC DOMAIN"],
+    ),
+    ("MIT", &["PERMISSION IS HEREBY GRANTED, FREE OF CHARGE"]),
+    (
+        "ISC",
+        &[
+            "PERMISSION TO USE, COPY, MODIFY, AND",
+            "DISTRIBUTE THIS SOFTWARE FOR ANY PURPOSE",
+        ],
+    ),
+    (
+        "Zlib",
+        &["THIS SOFTWARE IS PROVIDED 'AS-IS', WITHOUT ANY EXPRESS OR IMPLIED"],
+    ),
+    (
+        "BSD-3-Clause",
+        &["REDISTRIBUTION AND USE IN SOURCE AND BINARY FORMS", "NEITHER THE NAME"],
+    ),
+    ("BSD-2-Clause", &["REDISTRIBUTION AND USE IN SOURCE AND BINARY FORMS"]),
+];
+
+/// The SPDX identifier of the license in `text`, if it is recognized
+pub fn detect(text: &str) -> Option<&'static str> {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ").to_uppercase();
+    LICENSE_PHRASES
+        .iter()
+        .find(|(_, phrases)| phrases.iter().all(|phrase| text.contains(phrase)))
+        .map(|(id, _)| *id)
+}
+
+/// Make SPDX identifiers comparable no matter how the license version is qualified, like `GPL-3.0-or-later`
+pub(crate) fn normalize(id: &str) -> String {
+    id.trim_end_matches('+')
+        .trim_end_matches("-or-later")
+        .trim_end_matches("-only")
+        .to_uppercase()
+}
+
+/// The normalized identifiers of all licenses in a SPDX `expression`, including the outdated `MIT/Apache-2.0` form
+fn licenses_in(expression: &str) -> Vec<String> {
+    let mut licenses = Vec::new();
+    let mut tokens = expression
+        .split(|c: char| c.is_whitespace() || c == '/' || c == '(' || c == ')')
+        .filter(|t| !t.is_empty());
+    while let Some(token) = tokens.next() {
+        match token {
+            "OR" | "AND" | "or" | "and" => {}
+            "WITH" | "with" => {
+                tokens.next();
+            }
+            license => licenses.push(normalize(license)),
+        }
+    }
+    licenses
+}
+
+/// Returns true if `path` relative to the crate root is a license file by its name
+pub fn is_license_file(path: &str) -> bool {
+    let upper = path.to_uppercase();
+    upper.starts_with("LICENSES/")
+        || (!upper.contains('/')
+            && ["LICENSE", "LICENCE", "COPYING", "UNLICENSE"]
+                .iter()
+                .any(|prefix| upper.starts_with(prefix)))
+}
+
+impl LicenseInfo {
+    /// Compare the manifest with the bundled license files of a crate version, given the meta-data of all its files
+    /// and the files selected when extracting it
+    pub fn from_crate(entries: &[TarHeader], files: &[(TarHeader, Vec<u8>)]) -> LicenseInfo {
+        let content = |path: &str| {
+            files
+                .iter()
+                .find(|(header, _)| tar_path_to_utf8(&header.path) == Ok(path))
+                .map(|(_, content)| String::from_utf8_lossy(content))
+        };
+        let manifest: Manifest = content("Cargo.toml")
+            .and_then(|content| manifest::normalized(&content))
+            .unwrap_or_default();
+        let Package { license, license_file } = manifest.package;
+        let license_file = license_file.map(|path| path.trim_start_matches("./").to_owned());
+
+        let license_files: Vec<_> = entries
+            .iter()
+            .filter_map(|header| tar_path_to_utf8(&header.path).ok())
+            .filter(|path| is_license_file(path) || license_file.as_deref() == Some(*path))
+            .map(|path| LicenseFile {
+                path: path.to_owned(),
+                detected: content(path).and_then(|text| detect(&text)).map(ToOwned::to_owned),
+            })
+            .collect();
+
+        let mut mismatches = Vec::new();
+        if let Some(license_file) = license_file.as_ref() {
+            if !license_files.iter().any(|f| f.path == *license_file) {
+                mismatches.push(format!(
+                    "license-file '{}' of Cargo.toml isn't part of the crate",
+                    license_file
+                ));
+            }
+        }
+        if let Some(declared) = license.as_ref() {
+            let declared_licenses = licenses_in(declared);
+            for file in license_files.iter() {
+                if let Some(detected) = file.detected.as_ref() {
+                    if !declared_licenses.contains(&normalize(detected)) {
+                        mismatches.push(format!(
+                            "{} contains the {} license, but Cargo.toml declares '{}'",
+                            file.path, detected, declared
+                        ));
+                    }
+                }
+            }
+        }
+        LicenseInfo {
+            declared: license,
+            license_file,
+            files: license_files,
+            mismatches,
+        }
+    }
+
+    /// How the license is declared, for display
+    fn declaration(&self) -> String {
+        match (self.declared.as_ref(), self.license_file.as_ref()) {
+            (Some(declared), _) => declared.clone(),
+            (None, Some(file)) => format!("see {}", file),
+            (None, None) => "undeclared".into(),
+        }
+    }
+}
+
+/// The licenses of the most recent version of a crate
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct LicenseSummary {
+    pub latest_version: String,
+    pub latest: LicenseInfo,
+}
+
+impl versioned::Analysis for LicenseInfo {
+    type Summary = LicenseSummary;
+
+    fn name() -> &'static str {
+        "licenses"
+    }
+
+    fn version() -> &'static str {
+        "1.0.0"
+    }
+
+    fn add_to_summary(&self, crate_version: &str, summary: &mut Self::Summary) {
+        versioned::keep_latest(self, crate_version, &mut summary.latest_version, &mut summary.latest);
+    }
+
+    fn merge_summaries(lhs: Self::Summary, rhs: Self::Summary) -> Self::Summary {
+        versioned::later_first(lhs, rhs, |s| &s.latest_version).0
+    }
+
+    fn write_version_html(
+        &self,
+        crate_name: &str,
+        crate_version: &str,
+        generated_at: SystemTime,
+        out: &mut Vec<u8>,
+    ) -> Result<()> {
+        write_page(
+            &format!("Licenses of {}:{}", crate_name, crate_version),
+            html! {
+                section {
+                    h3: "Declared in Cargo.toml";
+                    p: self.declaration();
+                }
+                section {
+                    h3: "Bundled license files";
+                    @ if self.files.is_empty() {
+                        p: "None";
+                    }
+                    ul {
+                        @ for file in self.files.iter() {
+                            li: format!("{} - {}", file.path, file.detected.as_deref().unwrap_or("not recognized"));
+                        }
+                    }
+                }
+                @ if !self.mismatches.is_empty() {
+                    section {
+                        h3: "Mismatches";
+                        ul {
+                            @ for mismatch in self.mismatches.iter() {
+                                li: mismatch;
+                            }
+                        }
+                    }
+                }
+            },
+            generated_at,
+            out,
+        )
+    }
+
+    fn write_crate_html(
+        crate_name: &str,
+        info_by_version: &Dict<Self>,
+        generated_at: SystemTime,
+        out: &mut Vec<u8>,
+    ) -> Result<()> {
+        let mut versions: Vec<_> = info_by_version.iter().collect();
+        versions.sort_by_key(|(version, _)| parse_semver(version).ok());
+        write_page(
+            &format!("Licenses of {}", crate_name),
+            html! {
+                section {
+                    h3: "Versions";
+                    ol {
+                        @ for (version, info) in versions.into_iter().rev() {
+                            li {
+                                a(href=format!("{}.html", version)): version;
+                                : format!(" - {}, {} license file(s), {} mismatch(es)", info.declaration(), info.files.len(), info.mismatches.len());
+                            }
+                        }
+                    }
+                }
+            },
+            generated_at,
+            out,
+        )
+    }
+
+    fn write_collection_html(
+        info_by_crate: &Dict<Self::Summary>,
+        generated_at: SystemTime,
+        out: &mut Vec<u8>,
+    ) -> Result<()> {
+        let num_crates = info_by_crate.len();
+        let mut crates_by_declaration = BTreeMap::<String, usize>::new();
+        for summary in info_by_crate.values() {
+            *crates_by_declaration.entry(summary.latest.declaration()).or_default() += 1;
+        }
+        let mut declarations: Vec<_> = crates_by_declaration.into_iter().collect();
+        declarations.sort_by_key(|(declaration, count)| (std::cmp::Reverse(*count), declaration.clone()));
+        let with_mismatches: Vec<_> = info_by_crate
+            .iter()
+            .filter(|(_, summary)| !summary.latest.mismatches.is_empty())
+            .collect();
+        let without_files: Vec<_> = info_by_crate
+            .iter()
+            .filter(|(_, summary)| summary.latest.files.is_empty())
+            .collect();
+        write_page(
+            "Crates.io License Inventory",
+            html! {
+                section {
+                    h3: format!("Declared licenses of the latest version of {} crates", num_crates);
+                    ol {
+                        @ for (declaration, count) in declarations.iter().take(TOP_LIST) {
+                            li: format!("{}: {} crates ({:.2}%)", declaration, count, *count as f64 / num_crates.max(1) as f64 * 100.0);
+                        }
+                    }
+                }
+                section {
+                    h3: format!("{} crates whose license files don't match Cargo.toml", with_mismatches.len());
+                    ol {
+                        @ for (name, summary) in with_mismatches.iter().take(TOP_LIST) {
+                            li {
+                                a(href=format!("{}/index.html", name)): name.as_str();
+                                : format!(" {}: {}", summary.latest_version, summary.latest.mismatches.join(", "));
+                            }
+                        }
+                    }
+                }
+                section {
+                    h3: format!("{} crates without bundled license files", without_files.len());
+                    ol {
+                        @ for (name, summary) in without_files.iter().take(TOP_LIST) {
+                            li {
+                                a(href=format!("{}/index.html", name)): name.as_str();
+                                : format!(" {}: {}", summary.latest_version, summary.latest.declaration());
+                            }
+                        }
+                    }
+                }
+            },
+            generated_at,
+            out,
+        )
+    }
+}
+
+/// Generates the report from the manifest and license files of extracted crates, for crate versions which were
+/// extracted already.
+pub struct Generator;
+
+#[async_trait]
+impl generic::Generator for Generator {
+    type Report = versioned::Report<LicenseInfo>;
+    type DBResult = LicenseInfo;
+
+    fn name() -> &'static str {
+        <LicenseInfo as versioned::Analysis>::name()
+    }
+
+    fn version() -> &'static str {
+        <LicenseInfo as versioned::Analysis>::version()
+    }
+
+    fn fq_result_key(crate_name: &str, crate_version: &str, key_buf: &mut String) {
+        <waste::Generator as generic::Generator>::fq_result_key(crate_name, crate_version, key_buf)
+    }
+
+    fn needed_extraction() -> Option<Extraction> {
+        Some(Extraction::Full)
+    }
+
+    fn get_result(
+        _settings: &super::Settings,
+        connection: persistence::ThreadSafeConnection,
+        crate_name: &str,
+        crate_version: &str,
+        key_buf: &mut String,
+    ) -> Result<Option<Self::DBResult>> {
+        Self::fq_result_key(crate_name, crate_version, key_buf);
+        Ok(
+            match (persistence::TaskResultTable { inner: connection })
+                // The license file named in the manifest could be anywhere, but it won't be a Rust source
+                .get_selected(&key_buf, |header| !header.path.ends_with(b".rs"))?
+            {
+                Some(TaskResult::ExplodedCrate {
+                    entries_meta_data,
+                    selected_entries,
+                }) => Some(LicenseInfo::from_crate(&entries_meta_data, &selected_entries)),
+                _ => None,
+            },
+        )
+    }
+
+    async fn generate_report(
+        _settings: &super::Settings,
+        crate_name: &str,
+        crate_version: &str,
+        result: Self::DBResult,
+        _progress: &mut prodash::tree::Item,
+    ) -> Result<Self::Report> {
+        Ok(versioned::Report::Version {
+            crate_name: crate_name.into(),
+            crate_version: crate_version.into(),
+            info: result,
+        })
+    }
+}
+
+#[cfg(test)]
+mod licenses_test;
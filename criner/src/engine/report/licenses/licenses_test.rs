@@ -0,0 +1,114 @@
+use crate::{
+    engine::report::licenses::{detect, is_license_file, LicenseFile, LicenseInfo},
+    model::TarHeader,
+};
+
+const MIT: &str = "Permission is hereby granted, free of charge, to any person obtaining a copy
+of this software and associated documentation files (the \"Software\"), to deal";
+const APACHE: &str = "
+                              Apache License
+                        Version 2.0, January 2004
+                     http://www.apache.org/licenses/";
+const GPL: &str = "                    GNU GENERAL PUBLIC LICENSE
+                       Version 3, 29 June 2007
+  13. Use with the GNU Affero General Public License.";
+
+fn header(path: &str) -> TarHeader {
+    TarHeader {
+        path: format!("krate-1.0.0/{}", path).into_bytes(),
+        size: 0,
+        entry_type: b'0',
+        encoding: None,
+        truncated_at: None,
+    }
+}
+
+fn license_info(manifest: &str, stored: &[(&str, &str)], other_paths: &[&str]) -> LicenseInfo {
+    let mut files = vec![(header("Cargo.toml"), manifest.as_bytes().to_vec())];
+    files.extend(
+        stored
+            .iter()
+            .map(|(path, content)| (header(path), content.as_bytes().to_vec())),
+    );
+    let entries: Vec<_> = files
+        .iter()
+        .map(|(header, _)| header.clone())
+        .chain(other_paths.iter().map(|path| header(path)))
+        .collect();
+    LicenseInfo::from_crate(&entries, &files)
+}
+
+#[test]
+fn licenses_are_recognized_by_their_text() {
+    assert_eq!(detect(MIT), Some("MIT"));
+    assert_eq!(detect(APACHE), Some("Apache-2.0"));
+    assert_eq!(detect(GPL), Some("GPL-3.0"), "mentioning the AGPL doesn't make it one");
+    assert_eq!(detect("All rights reserved."), None);
+
+    assert!(is_license_file("LICENSE-MIT"));
+    assert!(is_license_file("license.txt"));
+    assert!(is_license_file("LICENSES/Apache-2.0.txt"));
+    assert!(!is_license_file("src/license.rs"));
+}
+
+#[test]
+fn bundled_files_matching_the_declaration_are_fine() {
+    let info = license_info(
+        "[package]\nname = \"krate\"\nlicense = \"MIT OR Apache-2.0\"\n",
+        &[("LICENSE-MIT", MIT), ("LICENSE-APACHE", APACHE)],
+        &["src/lib.rs"],
+    );
+    assert_eq!(info.declared.as_deref(), Some("MIT OR Apache-2.0"));
+    assert_eq!(
+        info.files,
+        vec![
+            LicenseFile {
+                path: "LICENSE-MIT".into(),
+                detected: Some("MIT".into())
+            },
+            LicenseFile {
+                path: "LICENSE-APACHE".into(),
+                detected: Some("Apache-2.0".into())
+            },
+        ]
+    );
+    assert!(info.mismatches.is_empty(), "{:?}", info.mismatches);
+}
+
+#[test]
+fn mismatches_between_manifest_and_bundled_files_are_reported() {
+    let info = license_info(
+        "[package]\nname = \"krate\"\nlicense = \"MIT/Apache-2.0\"\n",
+        &[("COPYING", GPL)],
+        &["LICENSE-MIT"],
+    );
+    assert_eq!(
+        info.mismatches,
+        vec!["COPYING contains the GPL-3.0 license, but Cargo.toml declares 'MIT/Apache-2.0'"]
+    );
+    assert_eq!(
+        info.files[1].detected, None,
+        "contents of crates extracted before license files were stored are unknown"
+    );
+
+    let info = license_info(
+        "[package]\nname = \"krate\"\nlicense-file = \"./LICENSE.txt\"\n",
+        &[],
+        &[],
+    );
+    assert_eq!(
+        info.mismatches,
+        vec!["license-file 'LICENSE.txt' of Cargo.toml isn't part of the crate"]
+    );
+    assert!(info.files.is_empty());
+
+    let info = license_info(
+        "[package]\nname = \"krate\"\nlicense = \"GPL-3.0-or-later\"\n",
+        &[("COPYING", GPL)],
+        &[],
+    );
+    assert!(
+        info.mismatches.is_empty(),
+        "the qualification of the version doesn't matter"
+    );
+}
@@ -1,2 +1,44 @@
+use crate::engine::work::cpubound::NeededExtractions;
+use std::time::SystemTime;
+
+pub mod abandonment;
+pub mod assets;
+pub mod dependency_kinds;
+pub mod embedded;
+pub mod errors;
+pub mod failures;
+pub mod features;
 pub mod generic;
+pub mod index;
+pub mod integrity;
+pub mod license_compatibility;
+pub mod licenses;
+pub mod release_cadence;
+pub mod semver_discipline;
+pub mod targets;
+pub mod versioned;
 pub mod waste;
+
+/// What all reports of a report cycle are generated with
+#[derive(Clone, Default)]
+pub struct Settings {
+    /// If set, reports claim to be generated at this time instead of the current time and only depend on the database,
+    /// producing the same output for the same database.
+    pub generated_at: Option<SystemTime>,
+    /// The categories of files which are not considered waste
+    pub not_waste: Vec<waste::Category>,
+    /// Where reports ask for the extractions they need to be performed by the processing stage
+    pub needed_extractions: NeededExtractions,
+}
+
+impl Settings {
+    /// The time reports are generated at
+    pub fn generated_at(&self) -> SystemTime {
+        self.generated_at.unwrap_or_else(SystemTime::now)
+    }
+
+    /// Returns true if reports should not depend on anything but the database
+    pub fn is_deterministic(&self) -> bool {
+        self.generated_at.is_some()
+    }
+}
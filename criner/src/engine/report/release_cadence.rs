@@ -0,0 +1,348 @@
+//! How often crates are released, by the amount of versions per year and the median time between two releases, along
+//! with how these are distributed across all crates.
+//!
+//! Publish dates come from the crates.io database dump, so crates of crates.io are only reported once it was
+//! downloaded, while versions of crates of other registries are counted without cadence.
+use crate::{
+    engine::report::{
+        generic,
+        versioned::{self, write_page, Dict},
+    },
+    error::Result,
+    model::CrateVersion,
+    persistence,
+    registry::split_crate_name,
+    utils::parse_semver,
+};
+use async_trait::async_trait;
+use horrorshow::{html, owned_html, Render};
+use serde_derive::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime};
+
+const DAY: Duration = Duration::from_secs(60 * 60 * 24);
+const YEAR: Duration = Duration::from_secs(60 * 60 * 24 * 365);
+/// The width of the longest bar in charts, in pixels
+const CHART_WIDTH: usize = 400;
+
+/// The upper bounds of the buckets of versions per year, and the label of each bucket
+const VERSIONS_PER_YEAR_BUCKETS: &[(f64, &str)] = &[
+    (1.0, "less than 1"),
+    (2.0, "1 to 2"),
+    (5.0, "2 to 5"),
+    (10.0, "5 to 10"),
+    (25.0, "10 to 25"),
+    (f64::INFINITY, "25 or more"),
+];
+
+/// The upper bounds of the buckets of the median time between releases in days, and the label of each bucket
+pub(crate) const MEDIAN_DAYS_BUCKETS: &[(u64, &str)] = &[
+    (7, "less than a week"),
+    (30, "a week to a month"),
+    (91, "one to three months"),
+    (365, "three months to a year"),
+    (u64::MAX, "a year or more"),
+];
+
+/// A single crate version
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Release {
+    /// When it was published, if the crates.io database dump knows about it
+    pub published_at: Option<SystemTime>,
+}
+
+/// All releases of a crate
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Releases {
+    /// When each version was published, if known
+    pub published_at: Dict<Option<SystemTime>>,
+}
+
+/// How often a crate is released
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cadence {
+    /// The amount of versions, including those whose publish date is unknown
+    pub versions: usize,
+    /// The amount of versions with known publish date per year since the first of them, counting at least a year,
+    /// or `None` if no publish date is known
+    pub versions_per_year: Option<f64>,
+    /// The median time between two consecutive releases, or `None` with less than two known publish dates
+    pub median_between_releases: Option<Duration>,
+}
+
+/// The cadence of `releases` as of `now`
+pub fn cadence(releases: &Releases, now: SystemTime) -> Cadence {
+    let mut dates: Vec<_> = releases.published_at.values().flatten().copied().collect();
+    dates.sort();
+    let mut intervals: Vec<_> = dates
+        .windows(2)
+        .map(|pair| pair[1].duration_since(pair[0]).unwrap_or_default())
+        .collect();
+    intervals.sort();
+    let median_between_releases = match intervals.len() {
+        0 => None,
+        n if n % 2 == 1 => Some(intervals[n / 2]),
+        n => Some((intervals[n / 2 - 1] + intervals[n / 2]) / 2),
+    };
+    Cadence {
+        versions: releases.published_at.len(),
+        versions_per_year: dates.first().map(|first| {
+            let years = now.duration_since(*first).unwrap_or_default().as_secs_f64() / YEAR.as_secs_f64();
+            dates.len() as f64 / years.max(1.0)
+        }),
+        median_between_releases,
+    }
+}
+
+fn year_of(at: SystemTime) -> String {
+    humantime::format_rfc3339(at).to_string()[..4].to_owned()
+}
+
+fn days(duration: Duration) -> u64 {
+    duration.as_secs() / DAY.as_secs()
+}
+
+/// The label of the bucket `value` falls into, given buckets with their exclusive upper bound
+pub(crate) fn bucket<T: PartialOrd + Copy>(value: T, buckets: &[(T, &'static str)]) -> &'static str {
+    buckets
+        .iter()
+        .find(|(upper_bound, _)| value < *upper_bound)
+        .or_else(|| buckets.last())
+        .map(|(_, label)| *label)
+        .unwrap_or_default()
+}
+
+/// A horizontal bar for each labelled count, in the given order
+fn bar_chart(bars: Vec<(String, usize)>) -> impl Render {
+    let max = bars.iter().map(|(_, count)| *count).max().unwrap_or(0).max(1);
+    owned_html! {
+        table {
+            @ for (label, count) in bars.iter() {
+                tr {
+                    th: label;
+                    td {
+                        div(style=format!("background: steelblue; height: 1em; width: {}px", count * CHART_WIDTH / max)) {}
+                    }
+                    td: count;
+                }
+            }
+        }
+    }
+}
+
+fn describe(cadence: &Cadence) -> Vec<String> {
+    let mut lines = vec![format!("{} versions", cadence.versions)];
+    match cadence.versions_per_year {
+        Some(per_year) => lines.push(format!("{:.1} versions per year", per_year)),
+        None => lines.push("Publish dates are unknown until the crates.io database dump was downloaded".into()),
+    }
+    if let Some(median) = cadence.median_between_releases {
+        lines.push(format!("{} days between releases, the median", days(median)));
+    }
+    lines
+}
+
+impl versioned::Analysis for Release {
+    type Summary = Releases;
+
+    fn name() -> &'static str {
+        "release-cadence"
+    }
+
+    fn version() -> &'static str {
+        "1.0.0"
+    }
+
+    fn add_to_summary(&self, crate_version: &str, summary: &mut Self::Summary) {
+        summary.published_at.insert(crate_version.to_owned(), self.published_at);
+    }
+
+    fn merge_summaries(mut lhs: Self::Summary, rhs: Self::Summary) -> Self::Summary {
+        lhs.published_at.extend(rhs.published_at);
+        lhs
+    }
+
+    fn write_version_html(
+        &self,
+        crate_name: &str,
+        crate_version: &str,
+        generated_at: SystemTime,
+        out: &mut Vec<u8>,
+    ) -> Result<()> {
+        write_page(
+            &format!("Release of {}:{}", crate_name, crate_version),
+            html! {
+                p: match self.published_at {
+                    Some(at) => format!("published at {}", humantime::format_rfc3339_seconds(at)),
+                    None => "publish date unknown".into(),
+                };
+            },
+            generated_at,
+            out,
+        )
+    }
+
+    fn write_crate_html(
+        crate_name: &str,
+        info_by_version: &Dict<Self>,
+        generated_at: SystemTime,
+        out: &mut Vec<u8>,
+    ) -> Result<()> {
+        let releases = Releases {
+            published_at: info_by_version
+                .iter()
+                .map(|(version, release)| (version.clone(), release.published_at))
+                .collect(),
+        };
+        let cadence = cadence(&releases, generated_at);
+        let mut by_year = Dict::<usize>::new();
+        for at in releases.published_at.values().flatten() {
+            *by_year.entry(year_of(*at)).or_default() += 1;
+        }
+        let mut versions: Vec<_> = info_by_version.iter().collect();
+        versions.sort_by_key(|(version, _)| parse_semver(version).ok());
+        write_page(
+            &format!("Release cadence of {}", crate_name),
+            html! {
+                section {
+                    ul {
+                        @ for line in describe(&cadence) {
+                            li: line;
+                        }
+                    }
+                }
+                @ if !by_year.is_empty() {
+                    section {
+                        h3: "Releases per year";
+                        : bar_chart(by_year.into_iter().collect());
+                    }
+                }
+                section {
+                    h3: "Versions";
+                    ol {
+                        @ for (version, release) in versions.into_iter().rev() {
+                            li {
+                                a(href=format!("{}.html", version)): version;
+                                @ if let Some(at) = release.published_at {
+                                    : format!(" - {}", humantime::format_rfc3339_seconds(at));
+                                }
+                            }
+                        }
+                    }
+                }
+            },
+            generated_at,
+            out,
+        )
+    }
+
+    fn write_collection_html(
+        info_by_crate: &Dict<Self::Summary>,
+        generated_at: SystemTime,
+        out: &mut Vec<u8>,
+    ) -> Result<()> {
+        let now = generated_at;
+        let cadences: Vec<_> = info_by_crate.values().map(|releases| cadence(releases, now)).collect();
+        let chart = |labels: Vec<&'static str>, label_of: &dyn Fn(&Cadence) -> Option<&'static str>| {
+            let mut bars: Vec<_> = labels.into_iter().map(|label| (label.to_owned(), 0)).collect();
+            for label in cadences.iter().filter_map(label_of) {
+                if let Some((_, count)) = bars.iter_mut().find(|(l, _)| l == label) {
+                    *count += 1;
+                }
+            }
+            bar_chart(bars)
+        };
+        let num_versions: usize = cadences.iter().map(|c| c.versions).sum();
+        let num_with_cadence = cadences.iter().filter(|c| c.versions_per_year.is_some()).count();
+        let num_single_release = cadences.iter().filter(|c| c.versions == 1).count();
+        write_page(
+            "Crates.io Release Cadence Report",
+            html! {
+                section {
+                    p: format!(
+                        "{} versions of {} crates, {} of which have known publish dates and {} have a single version",
+                        num_versions,
+                        info_by_crate.len(),
+                        num_with_cadence,
+                        num_single_release
+                    );
+                }
+                section {
+                    h3: "Crates by versions per year";
+                    : chart(
+                        VERSIONS_PER_YEAR_BUCKETS.iter().map(|(_, label)| *label).collect(),
+                        &|c| c.versions_per_year.map(|per_year| bucket(per_year, VERSIONS_PER_YEAR_BUCKETS))
+                    );
+                }
+                section {
+                    h3: "Crates by median time between releases";
+                    : chart(
+                        MEDIAN_DAYS_BUCKETS.iter().map(|(_, label)| *label).collect(),
+                        &|c| c.median_between_releases.map(|median| bucket(days(median), MEDIAN_DAYS_BUCKETS))
+                    );
+                }
+            },
+            generated_at,
+            out,
+        )
+    }
+}
+
+/// Generates the report from the crates.io database dump
+pub struct Generator;
+
+#[async_trait]
+impl generic::Generator for Generator {
+    type Report = versioned::Report<Release>;
+    type DBResult = Release;
+
+    fn name() -> &'static str {
+        <Release as versioned::Analysis>::name()
+    }
+
+    fn version() -> &'static str {
+        <Release as versioned::Analysis>::version()
+    }
+
+    fn fq_result_key(crate_name: &str, crate_version: &str, key_buf: &mut String) {
+        CrateVersion::key_from(crate_name, crate_version, key_buf);
+    }
+
+    fn get_result(
+        _settings: &super::Settings,
+        connection: persistence::ThreadSafeConnection,
+        crate_name: &str,
+        crate_version: &str,
+        _key_buf: &mut String,
+    ) -> Result<Option<Self::DBResult>> {
+        let dump = persistence::CratesIoCrateTable { inner: connection }.get(crate_name)?;
+        if dump.is_none() && split_crate_name(crate_name).0.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(Release {
+            published_at: dump.and_then(|krate| {
+                krate
+                    .versions
+                    .into_iter()
+                    .find(|v| v.semver == crate_version)
+                    .map(|v| v.created_at)
+            }),
+        }))
+    }
+
+    async fn generate_report(
+        _settings: &super::Settings,
+        crate_name: &str,
+        crate_version: &str,
+        result: Self::DBResult,
+        _progress: &mut prodash::tree::Item,
+    ) -> Result<Self::Report> {
+        Ok(versioned::Report::Version {
+            crate_name: crate_name.into(),
+            crate_version: crate_version.into(),
+            info: result,
+        })
+    }
+}
+
+#[cfg(test)]
+mod release_cadence_test;
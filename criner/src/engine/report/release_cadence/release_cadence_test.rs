@@ -0,0 +1,60 @@
+use crate::engine::report::release_cadence::{bucket, cadence, Cadence, Releases, MEDIAN_DAYS_BUCKETS};
+use std::time::{Duration, SystemTime};
+
+const DAY: Duration = Duration::from_secs(60 * 60 * 24);
+
+fn releases(published_days_ago: &[Option<u32>]) -> Releases {
+    let now = SystemTime::UNIX_EPOCH + DAY * 10_000;
+    Releases {
+        published_at: published_days_ago
+            .iter()
+            .enumerate()
+            .map(|(idx, days_ago)| (format!("1.{}.0", idx), days_ago.map(|days| now - DAY * days)))
+            .collect(),
+    }
+}
+
+fn cadence_of(published_days_ago: &[Option<u32>]) -> Cadence {
+    cadence(&releases(published_days_ago), SystemTime::UNIX_EPOCH + DAY * 10_000)
+}
+
+#[test]
+fn crates_without_known_publish_dates_have_no_cadence() {
+    assert_eq!(
+        cadence_of(&[None, None]),
+        Cadence {
+            versions: 2,
+            versions_per_year: None,
+            median_between_releases: None,
+        }
+    );
+}
+
+#[test]
+fn versions_per_year_count_at_least_a_year() {
+    let single = cadence_of(&[Some(30)]);
+    assert_eq!(single.versions_per_year, Some(1.0));
+    assert_eq!(single.median_between_releases, None, "there is nothing in between");
+
+    let versions_per_year = cadence_of(&[Some(730), Some(365), Some(0), None]).versions_per_year;
+    assert_eq!(
+        versions_per_year,
+        Some(1.5),
+        "versions without publish date don't count"
+    );
+}
+
+#[test]
+fn the_median_time_between_releases_is_independent_of_version_order() {
+    assert_eq!(
+        cadence_of(&[Some(10), Some(100), Some(0)]).median_between_releases,
+        Some(DAY * 50),
+        "the mean of 90 and 10 days, as there are two intervals"
+    );
+    assert_eq!(
+        cadence_of(&[Some(100), Some(99), Some(90), Some(0)]).median_between_releases,
+        Some(DAY * 9)
+    );
+    assert_eq!(bucket(9, MEDIAN_DAYS_BUCKETS), "a week to a month");
+    assert_eq!(bucket(10_000, MEDIAN_DAYS_BUCKETS), "a year or more");
+}
@@ -0,0 +1,393 @@
+use crate::{
+    engine::report::{
+        index::{self, IndexAnalysis, IndexLookup},
+        versioned::{self, write_page, Dict},
+    },
+    error::Result,
+    model::{CrateVersion, YankTransition},
+    utils::parse_semver,
+};
+use dia_semver::Semver;
+use horrorshow::html;
+use serde_derive::{Deserialize, Serialize};
+use std::{ops::AddAssign, time::SystemTime};
+
+const TOP_LIST: usize = 100;
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct DisciplineCounts {
+    pub versions: u64,
+    pub zero_major: u64,
+    pub prerelease: u64,
+    pub yanked: u64,
+    /// Patch releases which look like they contain breaking changes
+    pub breaking_patches: u64,
+    /// Releases which directly follow a yanked version of the same release line
+    pub republished_after_yank: u64,
+    /// Versions which were unyanked at least once
+    #[serde(default)]
+    pub unyanked: u64,
+    /// All changes of the yanked status of versions
+    #[serde(default)]
+    pub yank_transitions: u64,
+}
+
+impl AddAssign<&DisciplineCounts> for DisciplineCounts {
+    fn add_assign(&mut self, rhs: &DisciplineCounts) {
+        self.versions += rhs.versions;
+        self.zero_major += rhs.zero_major;
+        self.prerelease += rhs.prerelease;
+        self.yanked += rhs.yanked;
+        self.breaking_patches += rhs.breaking_patches;
+        self.republished_after_yank += rhs.republished_after_yank;
+        self.unyanked += rhs.unyanked;
+        self.yank_transitions += rhs.yank_transitions;
+    }
+}
+
+/// How a single crate version relates to the one it is supposed to be compatible with
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct VersionDiscipline {
+    pub yanked: bool,
+    pub prerelease: bool,
+    pub zero_major: bool,
+    /// The highest earlier version this one should be a drop-in replacement for, if any
+    pub previous_version: Option<String>,
+    /// True if the previous version was yanked
+    pub republished_after_yank: bool,
+    /// Reasons for this version to look like a breaking change compared to the previous version
+    pub breaking_changes: Vec<String>,
+    /// The changes of the yanked status of this version as observed in the index, oldest first
+    #[serde(default)]
+    pub yank_history: Vec<YankTransition>,
+}
+
+impl VersionDiscipline {
+    fn counts(&self) -> DisciplineCounts {
+        DisciplineCounts {
+            versions: 1,
+            zero_major: self.zero_major as u64,
+            prerelease: self.prerelease as u64,
+            yanked: self.yanked as u64,
+            breaking_patches: !self.breaking_changes.is_empty() as u64,
+            republished_after_yank: self.republished_after_yank as u64,
+            unyanked: self.was_unyanked() as u64,
+            yank_transitions: self.yank_history.len() as u64,
+        }
+    }
+
+    fn was_unyanked(&self) -> bool {
+        self.yank_history.iter().any(|transition| !transition.yanked)
+    }
+
+    fn flags(&self) -> String {
+        let flags: Vec<_> = [
+            (self.yanked, "yanked"),
+            (self.was_unyanked(), "unyanked before"),
+            (self.prerelease, "pre-release"),
+            (self.republished_after_yank, "republished after yank"),
+            (!self.breaking_changes.is_empty(), "breaking-looking patch"),
+        ]
+        .iter()
+        .filter_map(|(is_set, flag)| if *is_set { Some(*flag) } else { None })
+        .collect();
+        flags.join(", ")
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct DisciplineSummary {
+    /// Counts across all versions of the crate
+    pub counts: DisciplineCounts,
+    pub latest_version: String,
+    pub latest: VersionDiscipline,
+}
+
+/// Returns true if `version` is meant to be a patch release in the same compatible release line as `previous`.
+/// Versions like 0.0.x are never compatible with each other.
+fn is_patch_release_of(version: &Semver, previous: &Semver) -> bool {
+    version.major() == previous.major()
+        && (version.major() > 0 || (version.minor() == previous.minor() && version.minor() > 0))
+        && version > previous
+}
+
+/// The part of a version requirement which decides its compatibility, like '1' for '^1.2' or '0.3' for '~0.3.1'.
+fn requirement_line(requirement: &str) -> Option<String> {
+    let version = requirement
+        .split(',')
+        .next()?
+        .trim_start_matches(|c: char| "^~=<> ".contains(c));
+    let mut components = version.split('.');
+    match components.next()? {
+        "0" => components.next().map(|minor| format!("0.{}", minor)),
+        major => Some(major.to_owned()),
+    }
+}
+
+fn breaking_changes(version: &CrateVersion, previous: &CrateVersion) -> Vec<String> {
+    let mut reasons = Vec::new();
+    for feature in previous.features.keys() {
+        if !version.features.contains_key(feature) {
+            reasons.push(format!("removed feature '{}'", feature));
+        }
+    }
+    for previous_dependency in previous.dependencies.iter().filter(|d| d.is_normal()) {
+        match version
+            .dependencies
+            .iter()
+            .find(|d| d.is_normal() && d.name == previous_dependency.name)
+        {
+            None if previous_dependency.optional => {
+                reasons.push(format!("removed optional dependency '{}'", previous_dependency.name))
+            }
+            None => {}
+            Some(dependency) => {
+                let (before, after) = (
+                    requirement_line(&previous_dependency.required_version),
+                    requirement_line(&dependency.required_version),
+                );
+                if before != after {
+                    reasons.push(format!(
+                        "dependency '{}' changed from '{}' to '{}'",
+                        dependency.name, previous_dependency.required_version, dependency.required_version
+                    ));
+                }
+            }
+        }
+    }
+    reasons
+}
+
+impl IndexAnalysis for VersionDiscipline {
+    fn from_crate_version(version: &CrateVersion, index: &mut IndexLookup) -> Self {
+        let mut info = VersionDiscipline {
+            yanked: version.kind == crates_index_diff::ChangeKind::Yanked,
+            yank_history: index.yank_history(&version.name, &version.version),
+            ..Default::default()
+        };
+        let semver = match parse_semver(&version.version) {
+            Ok(semver) => semver,
+            Err(_) => return info,
+        };
+        info.prerelease = semver.pre_release().is_some();
+        info.zero_major = semver.major() == 0;
+        if info.prerelease {
+            return info;
+        }
+        let previous_version = index
+            .versions(&version.name)
+            .into_iter()
+            .filter_map(|v| parse_semver(&v).ok().map(|semver| (semver, v)))
+            .filter(|(previous, _)| previous.pre_release().is_none() && is_patch_release_of(&semver, previous))
+            .max_by(|(lhs, _), (rhs, _)| lhs.cmp(rhs))
+            .map(|(_, v)| v);
+        if let Some(previous) = previous_version
+            .as_ref()
+            .and_then(|v| index.crate_version(&version.name, v).ok().flatten())
+        {
+            info.republished_after_yank = previous.kind == crates_index_diff::ChangeKind::Yanked;
+            info.breaking_changes = breaking_changes(version, &previous);
+        }
+        info.previous_version = previous_version;
+        info
+    }
+}
+
+impl versioned::Analysis for VersionDiscipline {
+    type Summary = DisciplineSummary;
+
+    fn name() -> &'static str {
+        "semver-discipline"
+    }
+
+    fn version() -> &'static str {
+        "1.1.0"
+    }
+
+    fn add_to_summary(&self, crate_version: &str, summary: &mut Self::Summary) {
+        summary.counts += &self.counts();
+        versioned::keep_latest(self, crate_version, &mut summary.latest_version, &mut summary.latest);
+    }
+
+    fn merge_summaries(lhs: Self::Summary, rhs: Self::Summary) -> Self::Summary {
+        let (mut later, earlier) = versioned::later_first(lhs, rhs, |s| &s.latest_version);
+        later.counts += &earlier.counts;
+        later
+    }
+
+    fn write_version_html(
+        &self,
+        crate_name: &str,
+        crate_version: &str,
+        generated_at: SystemTime,
+        out: &mut Vec<u8>,
+    ) -> Result<()> {
+        write_page(
+            &format!("Semver discipline of {}:{}", crate_name, crate_version),
+            html! {
+                section {
+                    @ if let Some(previous) = self.previous_version.as_ref() {
+                        p: format!("Compared to previous compatible version {}", previous);
+                    } else {
+                        p: "There is no previous version this one is supposed to be compatible with";
+                    }
+                    p: self.flags();
+                }
+                @ if !self.yank_history.is_empty() {
+                    section {
+                        h3: "Yank history";
+                        ul {
+                            @ for transition in self.yank_history.iter() {
+                                li: format!(
+                                    "{} as of {}",
+                                    if transition.yanked { "yanked" } else { "unyanked" },
+                                    humantime::format_rfc3339_seconds(transition.observed_at)
+                                );
+                            }
+                        }
+                    }
+                }
+                @ if !self.breaking_changes.is_empty() {
+                    section {
+                        h3: "Changes that look breaking";
+                        ul {
+                            @ for reason in self.breaking_changes.iter() {
+                                li: reason;
+                            }
+                        }
+                    }
+                }
+            },
+            generated_at,
+            out,
+        )
+    }
+
+    fn write_crate_html(
+        crate_name: &str,
+        info_by_version: &Dict<Self>,
+        generated_at: SystemTime,
+        out: &mut Vec<u8>,
+    ) -> Result<()> {
+        let mut versions: Vec<_> = info_by_version.iter().collect();
+        versions.sort_by_key(|(version, _)| parse_semver(version).ok());
+        write_page(
+            &format!("Version timeline of {}", crate_name),
+            html! {
+                section {
+                    h3: "Versions";
+                    ol {
+                        @ for (version, info) in versions.into_iter().rev() {
+                            li {
+                                a(href=format!("{}.html", version)): version;
+                                @ if !info.flags().is_empty() {
+                                    : format!(" - {}", info.flags());
+                                }
+                                @ if !info.breaking_changes.is_empty() {
+                                    : format!(": {}", info.breaking_changes.join(", "));
+                                }
+                            }
+                        }
+                    }
+                }
+            },
+            generated_at,
+            out,
+        )
+    }
+
+    fn write_collection_html(
+        info_by_crate: &Dict<Self::Summary>,
+        generated_at: SystemTime,
+        out: &mut Vec<u8>,
+    ) -> Result<()> {
+        let all_versions = info_by_crate.values().fold(DisciplineCounts::default(), |mut all, s| {
+            all += &s.counts;
+            all
+        });
+        let num_crates = info_by_crate.len().max(1) as f64;
+        let percentage = |f: fn(&DisciplineSummary) -> bool| {
+            info_by_crate.values().filter(|s| f(s)).count() as f64 / num_crates * 100.0
+        };
+        let still_zero_major = percentage(|s| s.latest.zero_major);
+        let with_prereleases = percentage(|s| s.counts.prerelease > 0);
+        let with_breaking_patches = percentage(|s| s.counts.breaking_patches > 0);
+        let mut by_breaking_patches: Vec<_> = info_by_crate
+            .iter()
+            .filter(|(_, s)| s.counts.breaking_patches > 0)
+            .collect();
+        by_breaking_patches.sort_by_key(|(_, s)| std::cmp::Reverse(s.counts.breaking_patches));
+        let mut by_republished: Vec<_> = info_by_crate
+            .iter()
+            .filter(|(_, s)| s.counts.republished_after_yank > 0)
+            .collect();
+        by_republished.sort_by_key(|(_, s)| std::cmp::Reverse(s.counts.republished_after_yank));
+        let mut by_unyanked: Vec<_> = info_by_crate.iter().filter(|(_, s)| s.counts.unyanked > 0).collect();
+        by_unyanked.sort_by_key(|(_, s)| std::cmp::Reverse(s.counts.unyanked));
+        write_page(
+            "Crates.io Semver Discipline Report",
+            html! {
+                section {
+                    h3: format!("All {} versions of {} crates", all_versions.versions, info_by_crate.len());
+                    p: format!(
+                        "{} are 0.x releases, {} pre-releases, {} yanked, {} republished right after a yank and {} patch releases look breaking",
+                        all_versions.zero_major,
+                        all_versions.prerelease,
+                        all_versions.yanked,
+                        all_versions.republished_after_yank,
+                        all_versions.breaking_patches
+                    );
+                    p: format!(
+                        "{} versions were unyanked after being yanked, out of {} changes of the yanked status seen in the index",
+                        all_versions.unyanked,
+                        all_versions.yank_transitions
+                    );
+                    p: format!(
+                        "{:.1}% of crates are still at 0.x, {:.1}% have published pre-releases, {:.1}% have published breaking-looking patch releases",
+                        still_zero_major, with_prereleases, with_breaking_patches
+                    );
+                }
+                section {
+                    h3: "Most breaking-looking patch releases";
+                    ol {
+                        @ for (name, summary) in by_breaking_patches.into_iter().take(TOP_LIST) {
+                            li {
+                                a(href=format!("{}/index.html", name)): name;
+                                : format!(" {} of {} versions", summary.counts.breaking_patches, summary.counts.versions);
+                            }
+                        }
+                    }
+                }
+                section {
+                    h3: "Most versions unyanked after being yanked";
+                    ol {
+                        @ for (name, summary) in by_unyanked.into_iter().take(TOP_LIST) {
+                            li {
+                                a(href=format!("{}/index.html", name)): name;
+                                : format!(" {} of {} versions", summary.counts.unyanked, summary.counts.versions);
+                            }
+                        }
+                    }
+                }
+                section {
+                    h3: "Most releases directly following a yank";
+                    ol {
+                        @ for (name, summary) in by_republished.into_iter().take(TOP_LIST) {
+                            li {
+                                a(href=format!("{}/index.html", name)): name;
+                                : format!(" {} of {} versions", summary.counts.republished_after_yank, summary.counts.versions);
+                            }
+                        }
+                    }
+                }
+            },
+            generated_at,
+            out,
+        )
+    }
+}
+
+pub type Generator = index::Generator<VersionDiscipline>;
+
+#[cfg(test)]
+mod semver_discipline_test;
@@ -0,0 +1,151 @@
+use crate::{
+    engine::report::{
+        index::{IndexAnalysis, IndexLookup},
+        semver_discipline::{breaking_changes, is_patch_release_of, requirement_line, VersionDiscipline},
+    },
+    model::{CrateVersion, Dependency},
+    persistence::{temp_db::TempDb, Keyed, TableAccess},
+    utils::parse_semver,
+};
+use crates_index_diff::ChangeKind;
+
+fn is_patch(version: &str, previous: &str) -> bool {
+    is_patch_release_of(&parse_semver(version).unwrap(), &parse_semver(previous).unwrap())
+}
+
+fn dependency(name: &str, required_version: &str, optional: bool, kind: Option<&str>) -> Dependency {
+    Dependency {
+        name: name.into(),
+        required_version: required_version.into(),
+        optional,
+        kind: kind.map(ToOwned::to_owned),
+        ..Default::default()
+    }
+}
+
+fn crate_version(version: &str, features: &[&str], dependencies: Vec<Dependency>) -> CrateVersion {
+    CrateVersion {
+        name: "a".into(),
+        version: version.into(),
+        features: features.iter().map(|f| (f.to_string(), Vec::new())).collect(),
+        dependencies,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn patch_releases_stay_within_their_compatible_release_line() {
+    assert!(is_patch("1.2.4", "1.2.3"));
+    assert!(
+        is_patch("1.3.0", "1.2.3"),
+        "minor releases are compatible from 1.0 onwards"
+    );
+    assert!(!is_patch("2.0.0", "1.2.3"));
+    assert!(!is_patch("1.2.3", "1.2.3"), "a version is no patch release of itself");
+    assert!(!is_patch("1.2.2", "1.2.3"), "patch releases come later");
+
+    assert!(is_patch("0.3.2", "0.3.1"));
+    assert!(!is_patch("0.4.0", "0.3.1"), "minor releases break in 0.x");
+    assert!(
+        !is_patch("0.0.2", "0.0.1"),
+        "0.0.x versions are never compatible with each other"
+    );
+}
+
+#[test]
+fn requirements_are_reduced_to_their_release_line() {
+    assert_eq!(requirement_line("^1.2").as_deref(), Some("1"));
+    assert_eq!(requirement_line("1.2.3").as_deref(), Some("1"));
+    assert_eq!(requirement_line("~0.3.1").as_deref(), Some("0.3"));
+    assert_eq!(
+        requirement_line(">= 0.3, < 0.5").as_deref(),
+        Some("0.3"),
+        "the first bound decides"
+    );
+    assert_eq!(requirement_line("=0.0.7").as_deref(), Some("0.0"));
+    assert_eq!(
+        requirement_line("0").as_deref(),
+        None,
+        "there is no minor version to tell 0.x lines apart"
+    );
+}
+
+#[test]
+fn removed_features_and_optional_dependencies_and_changed_release_lines_look_breaking() {
+    let previous = crate_version(
+        "0.3.1",
+        &["std", "serde"],
+        vec![
+            dependency("serde", "^1.0", true, None),
+            dependency("log", "~0.3.1", false, Some("normal")),
+            dependency("libc", "^0.2", false, None),
+            dependency("criterion", "^0.3", true, Some("dev")),
+        ],
+    );
+    let version = crate_version(
+        "0.3.2",
+        &["std"],
+        vec![
+            dependency("log", "^0.4", false, None),
+            dependency("libc", "^0.2.80", false, None),
+        ],
+    );
+    let mut reasons = breaking_changes(&version, &previous);
+    reasons.sort();
+    assert_eq!(
+        reasons,
+        vec![
+            "dependency 'log' changed from '~0.3.1' to '^0.4'",
+            "removed feature 'serde'",
+            "removed optional dependency 'serde'",
+        ],
+        "required dependencies may go and dev-dependencies don't matter"
+    );
+
+    let same_line = crate_version("0.3.3", &["std"], vec![dependency("log", "~0.4.1", false, None)]);
+    assert!(
+        breaking_changes(&same_line, &version).is_empty(),
+        "'^0.4' and '~0.4.1' are the same release line"
+    );
+    let major = crate_version("0.3.4", &["std"], vec![dependency("log", "^1.2", false, None)]);
+    assert_eq!(
+        breaking_changes(&major, &same_line),
+        vec!["dependency 'log' changed from '~0.4.1' to '^1.2'"]
+    );
+}
+
+#[test]
+fn versions_are_compared_to_the_latest_compatible_release_before_them() {
+    let db = TempDb::new("semver-discipline");
+    let mut progress = prodash::Tree::new().add_child("semver-discipline");
+    let crates = db.open_crates().unwrap();
+    let crate_versions = db.open_crate_versions().unwrap();
+    let mut store = |version: CrateVersion| {
+        crates.upsert(&mut progress, &version.name, &version).unwrap();
+        crate_versions.insert(&mut progress, version.key(), &version).unwrap();
+    };
+    store(crate_version("0.3.0", &["std"], Vec::new()));
+    store(CrateVersion {
+        kind: ChangeKind::Yanked,
+        ..crate_version("0.3.1", &["std"], Vec::new())
+    });
+    store(crate_version("0.4.0-alpha.1", &[], Vec::new()));
+    let mut index = IndexLookup::new(db.open_connection().unwrap());
+
+    let info = VersionDiscipline::from_crate_version(&crate_version("0.3.2", &[], Vec::new()), &mut index);
+    assert_eq!(info.previous_version.as_deref(), Some("0.3.1"));
+    assert!(info.republished_after_yank);
+    assert!(info.zero_major);
+    assert!(!info.prerelease);
+    assert_eq!(info.breaking_changes, vec!["removed feature 'std'"]);
+
+    let info = VersionDiscipline::from_crate_version(&crate_version("0.4.0", &[], Vec::new()), &mut index);
+    assert_eq!(
+        info.previous_version, None,
+        "neither 0.3.x nor pre-releases of 0.4.0 are compatible with it"
+    );
+
+    let info = VersionDiscipline::from_crate_version(&crate_version("0.4.0-alpha.2", &[], Vec::new()), &mut index);
+    assert!(info.prerelease);
+    assert_eq!(info.previous_version, None, "pre-releases aren't compared");
+}
@@ -0,0 +1,255 @@
+use crate::{
+    engine::report::{
+        index::{self, IndexAnalysis, IndexLookup},
+        versioned::{self, write_page, Dict},
+    },
+    error::Result,
+    model::CrateVersion,
+    utils::parse_semver,
+};
+use horrorshow::html;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::SystemTime,
+};
+
+const TOP_LIST: usize = 50;
+/// Crates which are commonly pulled in for a particular platform
+const PLATFORM_CRATES: &[&str] = &[
+    "windows-sys",
+    "windows",
+    "winapi",
+    "libc",
+    "nix",
+    "wasm-bindgen",
+    "js-sys",
+    "web-sys",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Platform {
+    Windows,
+    Unix,
+    Wasm,
+    Other,
+}
+
+impl Platform {
+    /// Classify a target specification like `cfg(windows)` or `x86_64-pc-windows-msvc`.
+    /// Negations and combinations we can't tell apart are considered 'other'.
+    pub fn from_target(target: &str) -> Platform {
+        const UNIX_LIKE: &[&str] = &["unix", "linux", "macos", "android", "ios", "bsd", "darwin"];
+        let target = target.to_ascii_lowercase();
+        if target.contains("not(") || target.contains("any(") {
+            Platform::Other
+        } else if target.contains("windows") {
+            Platform::Windows
+        } else if target.contains("wasm") {
+            Platform::Wasm
+        } else if UNIX_LIKE.iter().any(|name| target.contains(name)) {
+            Platform::Unix
+        } else {
+            Platform::Other
+        }
+    }
+
+    fn all() -> &'static [Platform] {
+        &[Platform::Windows, Platform::Unix, Platform::Wasm, Platform::Other]
+    }
+}
+
+/// Dependencies of a crate version which are only used on particular targets
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct TargetInfo {
+    /// The crates depended on by target specification, like `cfg(windows)`
+    pub dependencies_by_target: Dict<BTreeSet<String>>,
+}
+
+impl TargetInfo {
+    pub fn platforms(&self) -> BTreeSet<Platform> {
+        self.dependencies_by_target
+            .keys()
+            .map(|target| Platform::from_target(target))
+            .collect()
+    }
+
+    fn platform_crates(&self) -> BTreeSet<&str> {
+        self.dependencies_by_target
+            .values()
+            .flatten()
+            .filter_map(|name| PLATFORM_CRATES.iter().find(|c| **c == name).copied())
+            .collect()
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct TargetSummary {
+    pub latest_version: String,
+    pub latest: TargetInfo,
+}
+
+impl IndexAnalysis for TargetInfo {
+    fn from_crate_version(version: &CrateVersion, _index: &mut IndexLookup) -> Self {
+        let mut info = TargetInfo::default();
+        for dependency in version.dependencies.iter().filter(|d| d.kind.as_deref() != Some("dev")) {
+            if let Some(target) = dependency.target.as_ref() {
+                info.dependencies_by_target
+                    .entry(target.to_owned())
+                    .or_default()
+                    .insert(dependency.crate_name().to_owned());
+            }
+        }
+        info
+    }
+}
+
+impl versioned::Analysis for TargetInfo {
+    type Summary = TargetSummary;
+
+    fn name() -> &'static str {
+        "targets"
+    }
+
+    fn version() -> &'static str {
+        "1.0.0"
+    }
+
+    fn add_to_summary(&self, crate_version: &str, summary: &mut Self::Summary) {
+        versioned::keep_latest(self, crate_version, &mut summary.latest_version, &mut summary.latest);
+    }
+
+    fn merge_summaries(lhs: Self::Summary, rhs: Self::Summary) -> Self::Summary {
+        versioned::later_first(lhs, rhs, |s| &s.latest_version).0
+    }
+
+    fn write_version_html(
+        &self,
+        crate_name: &str,
+        crate_version: &str,
+        generated_at: SystemTime,
+        out: &mut Vec<u8>,
+    ) -> Result<()> {
+        write_page(
+            &format!("Target-specific dependencies of {}:{}", crate_name, crate_version),
+            html! {
+                @ if self.dependencies_by_target.is_empty() {
+                    p: "No target-specific dependencies";
+                }
+                @ for (target, dependencies) in self.dependencies_by_target.iter() {
+                    section {
+                        h3: format!("{} ({:?})", target, Platform::from_target(target));
+                        ul {
+                            @ for name in dependencies.iter() {
+                                li: name;
+                            }
+                        }
+                    }
+                }
+            },
+            generated_at,
+            out,
+        )
+    }
+
+    fn write_crate_html(
+        crate_name: &str,
+        info_by_version: &Dict<Self>,
+        generated_at: SystemTime,
+        out: &mut Vec<u8>,
+    ) -> Result<()> {
+        let mut versions: Vec<_> = info_by_version.iter().collect();
+        versions.sort_by_key(|(version, _)| parse_semver(version).ok());
+        write_page(
+            &format!("Target-specific dependencies of {}", crate_name),
+            html! {
+                section {
+                    h3: "Versions";
+                    ol {
+                        @ for (version, info) in versions.into_iter().rev() {
+                            li {
+                                a(href=format!("{}.html", version)): version;
+                                : format!(
+                                    " - {} target(s) affecting {:?}",
+                                    info.dependencies_by_target.len(),
+                                    info.platforms()
+                                );
+                            }
+                        }
+                    }
+                }
+            },
+            generated_at,
+            out,
+        )
+    }
+
+    fn write_collection_html(
+        info_by_crate: &Dict<Self::Summary>,
+        generated_at: SystemTime,
+        out: &mut Vec<u8>,
+    ) -> Result<()> {
+        let num_crates = info_by_crate.len();
+        let percentage = |count: usize| count as f64 / num_crates.max(1) as f64 * 100.0;
+        let mut crates_by_platform = BTreeMap::<Platform, usize>::new();
+        let mut crates_by_platform_crate = BTreeMap::<&str, usize>::new();
+        let mut crates_by_target = BTreeMap::<&str, usize>::new();
+        for summary in info_by_crate.values() {
+            for platform in summary.latest.platforms() {
+                *crates_by_platform.entry(platform).or_default() += 1;
+            }
+            for name in summary.latest.platform_crates() {
+                *crates_by_platform_crate.entry(name).or_default() += 1;
+            }
+            for target in summary.latest.dependencies_by_target.keys() {
+                *crates_by_target.entry(target).or_default() += 1;
+            }
+        }
+        let mut crates_by_target: Vec<_> = crates_by_target.into_iter().collect();
+        crates_by_target.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        write_page(
+            "Crates.io Target-specific Dependencies Report",
+            html! {
+                section {
+                    h3: format!("Platforms with dependencies of their own, in the latest version of {} crates", num_crates);
+                    ul {
+                        @ for platform in Platform::all() {
+                            li: format!(
+                                "{:?}: {:.2}% of crates",
+                                platform,
+                                percentage(crates_by_platform.get(platform).copied().unwrap_or(0))
+                            );
+                        }
+                    }
+                }
+                section {
+                    h3: "Platform crates used as target-specific dependency";
+                    ul {
+                        @ for name in PLATFORM_CRATES {
+                            li: format!(
+                                "{}: {:.2}% of crates",
+                                name,
+                                percentage(crates_by_platform_crate.get(name).copied().unwrap_or(0))
+                            );
+                        }
+                    }
+                }
+                section {
+                    h3: "Most common targets";
+                    ol {
+                        @ for (target, count) in crates_by_target.into_iter().take(TOP_LIST) {
+                            li: format!("{}: {} crates", target, count);
+                        }
+                    }
+                }
+            },
+            generated_at,
+            out,
+        )
+    }
+}
+
+pub type Generator = index::Generator<TargetInfo>;
+
+#[cfg(test)]
+mod targets_test;
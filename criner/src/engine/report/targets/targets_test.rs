@@ -0,0 +1,78 @@
+use crate::{
+    engine::report::{
+        index::{IndexAnalysis, IndexLookup},
+        targets::{Platform, TargetInfo},
+    },
+    model::{CrateVersion, Dependency},
+    persistence::temp_db::TempDb,
+};
+
+fn dependency(name: &str, target: Option<&str>, kind: Option<&str>) -> Dependency {
+    Dependency {
+        name: name.into(),
+        target: target.map(ToOwned::to_owned),
+        kind: kind.map(ToOwned::to_owned),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn targets_are_classified_by_platform() {
+    for (target, platform) in [
+        ("cfg(windows)", Platform::Windows),
+        ("x86_64-pc-windows-msvc", Platform::Windows),
+        ("cfg(target_os = \"Linux\")", Platform::Unix),
+        ("cfg(unix)", Platform::Unix),
+        ("aarch64-apple-darwin", Platform::Unix),
+        ("cfg(target_arch = \"wasm32\")", Platform::Wasm),
+        ("cfg(not(windows))", Platform::Other),
+        ("cfg(any(unix, windows))", Platform::Other),
+        ("cfg(target_pointer_width = \"64\")", Platform::Other),
+    ] {
+        assert_eq!(Platform::from_target(target), platform, "{}", target);
+    }
+}
+
+#[test]
+fn dependencies_are_grouped_by_target_without_dev_dependencies() {
+    let db = TempDb::new("targets");
+    let mut index = IndexLookup::new(db.open_connection().unwrap());
+    let info = TargetInfo::from_crate_version(
+        &CrateVersion {
+            name: "a".into(),
+            version: "1.0.0".into(),
+            dependencies: vec![
+                dependency("log", None, None),
+                dependency("winapi", Some("cfg(windows)"), None),
+                Dependency {
+                    package: Some("windows-sys".into()),
+                    ..dependency("sys", Some("cfg(windows)"), Some("normal"))
+                },
+                dependency("libc", Some("cfg(unix)"), Some("build")),
+                dependency("tempfile", Some("cfg(unix)"), Some("dev")),
+                dependency("atomic", Some("cfg(target_has_atomic = \"64\")"), None),
+            ],
+            ..Default::default()
+        },
+        &mut index,
+    );
+    assert_eq!(
+        info.dependencies_by_target
+            .iter()
+            .map(|(target, names)| (target.as_str(), names.iter().map(String::as_str).collect::<Vec<_>>()))
+            .collect::<Vec<_>>(),
+        vec![
+            ("cfg(target_has_atomic = \"64\")", vec!["atomic"]),
+            ("cfg(unix)", vec!["libc"]),
+            ("cfg(windows)", vec!["winapi", "windows-sys"]),
+        ]
+    );
+    assert_eq!(
+        info.platforms().into_iter().collect::<Vec<_>>(),
+        vec![Platform::Windows, Platform::Unix, Platform::Other]
+    );
+    assert_eq!(
+        info.platform_crates().into_iter().collect::<Vec<_>>(),
+        vec!["libc", "winapi", "windows-sys"]
+    );
+}
@@ -0,0 +1,312 @@
+//! A report which analyses each crate version on its own, and aggregates these into a page per crate
+//! and a summary of all crates.
+use crate::{engine::report::Settings, utils::parse_semver, Result};
+use async_trait::async_trait;
+use horrorshow::{helper::doctype, html, owned_html, Render, RenderOnce, Template};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+pub type Dict<T> = BTreeMap<String, T>;
+
+pub(crate) const TOP_LEVEL_REPORT_NAME: &str = "__top-level-report__";
+
+/// The analysis of a single crate version, which knows how to summarize itself for the crate collection.
+pub trait Analysis: Serialize + DeserializeOwned + Clone + Send + Sync + 'static {
+    /// Information about all versions of a crate, as shown in the crate collection
+    type Summary: Serialize + DeserializeOwned + Clone + Default + Send + Sync + 'static;
+
+    fn name() -> &'static str;
+    fn version() -> &'static str;
+
+    /// Add this version's information to the summary of its crate
+    fn add_to_summary(&self, crate_version: &str, summary: &mut Self::Summary);
+    /// Combine two summaries of the same crate which may have been computed from different versions
+    fn merge_summaries(lhs: Self::Summary, rhs: Self::Summary) -> Self::Summary;
+
+    /// The `write_*_html` functions write pages claiming to be generated at `generated_at`, which is also the time
+    /// analyses relate to if they depend on the time.
+    fn write_version_html(
+        &self,
+        crate_name: &str,
+        crate_version: &str,
+        generated_at: SystemTime,
+        out: &mut Vec<u8>,
+    ) -> Result<()>;
+    fn write_crate_html(
+        crate_name: &str,
+        info_by_version: &Dict<Self>,
+        generated_at: SystemTime,
+        out: &mut Vec<u8>,
+    ) -> Result<()>;
+    fn write_collection_html(
+        info_by_crate: &Dict<Self::Summary>,
+        generated_at: SystemTime,
+        out: &mut Vec<u8>,
+    ) -> Result<()>;
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub enum Report<A: Analysis> {
+    Version {
+        crate_name: String,
+        crate_version: String,
+        info: A,
+    },
+    Crate {
+        crate_name: String,
+        info_by_version: Dict<A>,
+    },
+    CrateCollection {
+        info_by_crate: Dict<A::Summary>,
+    },
+}
+
+fn summarize<A: Analysis>(info_by_version: &Dict<A>) -> A::Summary {
+    info_by_version
+        .iter()
+        .fold(A::Summary::default(), |mut summary, (crate_version, info)| {
+            info.add_to_summary(crate_version, &mut summary);
+            summary
+        })
+}
+
+/// Returns true if `version` is later than `latest`, the latest version seen so far, which is empty if there is none yet.
+/// Versions which aren't valid semver are never later than valid ones.
+pub fn is_later(version: &str, latest: &str) -> bool {
+    latest.is_empty() || parse_semver(version).ok() > parse_semver(latest).ok()
+}
+
+/// For summaries which keep the analysis of the latest version of a crate: make `analysis` of `crate_version` the
+/// `latest` one along with `latest_version` if it's later.
+pub fn keep_latest<A: Clone>(analysis: &A, crate_version: &str, latest_version: &mut String, latest: &mut A) {
+    if is_later(crate_version, latest_version) {
+        *latest_version = crate_version.to_owned();
+        *latest = analysis.clone();
+    }
+}
+
+/// Order two summaries of the same crate by the latest version they know of as returned by `latest_version`, later first.
+pub fn later_first<S>(lhs: S, rhs: S, latest_version: impl Fn(&S) -> &str) -> (S, S) {
+    if is_later(latest_version(&rhs), latest_version(&lhs)) {
+        (rhs, lhs)
+    } else {
+        (lhs, rhs)
+    }
+}
+
+fn merge_collections<A: Analysis>(lhs: Dict<A::Summary>, rhs: Dict<A::Summary>) -> Dict<A::Summary> {
+    rhs.into_iter().fold(lhs, |mut m, (crate_name, summary)| {
+        let merged = match m.remove(&crate_name) {
+            Some(existing) => A::merge_summaries(existing, summary),
+            None => summary,
+        };
+        m.insert(crate_name, merged);
+        m
+    })
+}
+
+impl<A: Analysis> Report<A> {
+    fn into_crate(self) -> Option<(String, Dict<A>)> {
+        match self {
+            Report::Version {
+                crate_name,
+                crate_version,
+                info,
+            } => Some((crate_name, std::iter::once((crate_version, info)).collect())),
+            Report::Crate {
+                crate_name,
+                info_by_version,
+            } => Some((crate_name, info_by_version)),
+            Report::CrateCollection { .. } => None,
+        }
+    }
+
+    fn into_collection(self) -> Dict<A::Summary> {
+        match self {
+            Report::CrateCollection { info_by_crate } => info_by_crate,
+            krate => {
+                let (crate_name, info_by_version) = krate.into_crate().expect("version or crate");
+                std::iter::once((crate_name, summarize(&info_by_version))).collect()
+            }
+        }
+    }
+
+    fn storage_location(&self, out_dir: &Path) -> PathBuf {
+        let prefix = match self {
+            Report::Version { crate_name, .. } | Report::Crate { crate_name, .. } => crate_name.as_str(),
+            Report::CrateCollection { .. } => TOP_LEVEL_REPORT_NAME,
+        };
+        path_from_prefix::<A>(out_dir, prefix)
+    }
+}
+
+fn path_from_prefix<A: Analysis>(out_dir: &Path, prefix: &str) -> PathBuf {
+    out_dir.join(format!("{}-{}-{}.rmp", prefix, A::name(), A::version()))
+}
+
+async fn load_state<A: Analysis>(path: PathBuf, progress: &mut prodash::tree::Item) -> Option<Report<A>> {
+    progress.blocked("loading previous report from disk", None);
+    blocking::unblock(move || std::fs::read(path))
+        .await
+        .ok()
+        .and_then(|v| rmp_serde::from_read(v.as_slice()).ok())
+}
+
+#[async_trait]
+impl<A: Analysis> super::generic::Aggregate for Report<A> {
+    fn merge(self, other: Self) -> Self {
+        match (self, other) {
+            (Report::CrateCollection { info_by_crate }, other) => Report::CrateCollection {
+                info_by_crate: merge_collections::<A>(info_by_crate, other.into_collection()),
+            },
+            (other, Report::CrateCollection { info_by_crate }) => Report::CrateCollection {
+                info_by_crate: merge_collections::<A>(other.into_collection(), info_by_crate),
+            },
+            (lhs, rhs) => {
+                let (lhs_name, mut lhs_versions) = lhs.into_crate().expect("version or crate");
+                let (rhs_name, rhs_versions) = rhs.into_crate().expect("version or crate");
+                if lhs_name == rhs_name {
+                    lhs_versions.extend(rhs_versions);
+                    Report::Crate {
+                        crate_name: lhs_name,
+                        info_by_version: lhs_versions,
+                    }
+                } else {
+                    let mut info_by_crate = Dict::new();
+                    info_by_crate.insert(lhs_name, summarize(&lhs_versions));
+                    info_by_crate.insert(rhs_name, summarize(&rhs_versions));
+                    Report::CrateCollection { info_by_crate }
+                }
+            }
+        }
+    }
+
+    async fn complete(
+        &mut self,
+        settings: &Settings,
+        _progress: &mut prodash::tree::Item,
+        out: &mut Vec<u8>,
+    ) -> Result<()> {
+        let generated_at = settings.generated_at();
+        match self {
+            Report::Version {
+                crate_name,
+                crate_version,
+                info,
+            } => info.write_version_html(crate_name, crate_version, generated_at, out),
+            Report::Crate {
+                crate_name,
+                info_by_version,
+            } => A::write_crate_html(crate_name, info_by_version, generated_at, out),
+            Report::CrateCollection { info_by_crate } => A::write_collection_html(info_by_crate, generated_at, out),
+        }
+    }
+
+    async fn load_previous_state(
+        &self,
+        _settings: &Settings,
+        out_dir: &Path,
+        progress: &mut prodash::tree::Item,
+    ) -> Option<Self> {
+        load_state(self.storage_location(out_dir), progress).await
+    }
+
+    async fn load_previous_top_level_state(
+        _settings: &Settings,
+        out_dir: &Path,
+        progress: &mut prodash::tree::Item,
+    ) -> Option<Self> {
+        load_state(path_from_prefix::<A>(out_dir, TOP_LEVEL_REPORT_NAME), progress).await
+    }
+
+    async fn store_current_state(
+        &self,
+        _settings: &Settings,
+        out_dir: &Path,
+        progress: &mut prodash::tree::Item,
+    ) -> Result<()> {
+        let path = self.storage_location(out_dir);
+        progress.blocked("storing current report to disk", None);
+        let data = rmp_serde::to_vec(self)?;
+        // Crates of registries other than crates.io are stored in a directory named after the registry
+        blocking::unblock(move || {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            crate::utils::write_replacing(path, data)
+        })
+        .await
+        .map_err(Into::into)
+    }
+
+    fn forget_crate(_settings: &Settings, out_dir: &Path, crate_name: &str) -> Result<bool> {
+        if let Some(mut info_by_crate) = top_level_summaries::<A>(out_dir) {
+            if info_by_crate.remove(crate_name).is_some() {
+                let top_level = Report::<A>::CrateCollection { info_by_crate };
+                crate::utils::write_replacing(
+                    path_from_prefix::<A>(out_dir, TOP_LEVEL_REPORT_NAME),
+                    rmp_serde::to_vec(&top_level)?,
+                )?;
+            }
+        }
+        match std::fs::remove_file(path_from_prefix::<A>(out_dir, crate_name)) {
+            Ok(()) => Ok(true),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+/// The summaries of all crates as stored in the cache directory `out_dir` of the report of `A`, if it was generated before
+pub(crate) fn top_level_summaries<A: Analysis>(out_dir: &Path) -> Option<Dict<A::Summary>> {
+    match std::fs::read(path_from_prefix::<A>(out_dir, TOP_LEVEL_REPORT_NAME))
+        .ok()
+        .and_then(|data| rmp_serde::from_read(data.as_slice()).ok())
+    {
+        Some(Report::<A>::CrateCollection { info_by_crate }) => Some(info_by_crate),
+        _ => None,
+    }
+}
+
+/// Write a complete html page with the given title and body, looking like all other report pages, which claims to be
+/// generated at `generated_at`
+pub fn write_page(title: &str, body: impl RenderOnce, generated_at: SystemTime, out: &mut Vec<u8>) -> Result<()> {
+    html! {
+        : doctype::HTML;
+        html {
+            head {
+                title: title;
+            }
+            body {
+                article {
+                    h1: title;
+                    : body;
+                }
+                : page_footer(generated_at);
+            }
+        }
+    }
+    .write_to_io(out)?;
+    Ok(())
+}
+
+fn page_footer(generated_at: SystemTime) -> impl Render {
+    owned_html! {
+        footer {
+            span {
+                 : "Generated by ";
+                 a(href="https://github.com/the-lean-crate/criner"): "Criner";
+                 : format!(" at {}", humantime::format_rfc3339_seconds(generated_at));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod versioned_test;
@@ -0,0 +1,37 @@
+use super::{is_later, keep_latest, later_first};
+
+#[test]
+fn versions_are_later_by_semver_and_invalid_ones_never_are() {
+    assert!(is_later("1.0.0", ""), "anything is later than nothing");
+    assert!(is_later("1.10.0", "1.9.0"));
+    assert!(is_later("1.0.0", "1.0.0-alpha.1"));
+    assert!(!is_later("1.0.0", "1.0.0"));
+    assert!(!is_later("not-semver", "0.1.0"));
+    assert!(is_later("0.1.0", "not-semver"));
+}
+
+#[test]
+fn summaries_keep_the_analysis_of_the_latest_version() {
+    let (mut latest_version, mut latest) = (String::new(), "");
+    for (version, analysis) in [("1.1.0", "b"), ("1.0.0", "a"), ("2.0.0-rc.1", "c")] {
+        keep_latest(&analysis, version, &mut latest_version, &mut latest);
+    }
+    assert_eq!((latest_version.as_str(), latest), ("2.0.0-rc.1", "c"));
+
+    fn version<'a>(summary: &'a (&str, u8)) -> &'a str {
+        summary.0
+    }
+    assert_eq!(
+        later_first(("1.0.0", 1), ("1.1.0", 2), version),
+        (("1.1.0", 2), ("1.0.0", 1))
+    );
+    assert_eq!(
+        later_first(("1.1.0", 1), ("1.0.0", 2), version),
+        (("1.1.0", 1), ("1.0.0", 2))
+    );
+    assert_eq!(
+        later_first(("", 1), ("", 2), version),
+        (("", 2), ("", 1)),
+        "empty summaries are replaced"
+    );
+}
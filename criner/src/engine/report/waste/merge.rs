@@ -1,7 +1,7 @@
-use super::{AggregateFileInfo, AggregateVersionInfo, Dict, Fix, Report, VersionInfo, WastedFile};
-use crate::Result;
+use super::{html::Page, AggregateFileInfo, AggregateVersionInfo, Dict, Fix, Report, VersionInfo, WastedFile};
+use crate::{engine::report::Settings, Result};
 use async_trait::async_trait;
-use criner_waste_report::{add_optional_aggregate, html::NO_EXT_MARKER};
+use criner_waste_report::{add_optional, add_optional_aggregate, NO_EXT_MARKER};
 use std::{
     collections::BTreeMap,
     ops::AddAssign,
@@ -10,23 +10,39 @@ use std::{
 
 const TOP_LEVEL_REPORT_NAME: &str = "__top-level-report__";
 
-fn path_from_prefix(out_dir: &Path, prefix: &str) -> PathBuf {
+fn path_from_prefix(settings: &Settings, out_dir: &Path, prefix: &str) -> PathBuf {
     use crate::engine::report::generic::Generator;
     out_dir.join(format!(
         "{}-{}-{}.rmp",
         prefix,
         super::Generator::name(),
-        super::Generator::version()
+        super::Generator::report_version(settings)
     ))
 }
 
-fn path_to_storage_location(report: &Report, out_dir: &Path) -> PathBuf {
+/// Read the most recently written top-level state in `cache_dir`, whichever categories of files were kept when writing it
+pub(crate) fn read_latest_top_level_state(cache_dir: &Path) -> Option<Report> {
+    let prefix = format!(
+        "{}-{}-",
+        TOP_LEVEL_REPORT_NAME,
+        <super::Generator as crate::engine::report::generic::Generator>::name()
+    );
+    let path = std::fs::read_dir(cache_dir)
+        .ok()?
+        .flatten()
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with(&prefix))
+        .max_by_key(|entry| entry.metadata().and_then(|m| m.modified()).ok())?
+        .path();
+    rmp_serde::from_read(std::fs::read(path).ok()?.as_slice()).ok()
+}
+
+fn path_to_storage_location(settings: &Settings, report: &Report, out_dir: &Path) -> PathBuf {
     use Report::*;
     let prefix = match report {
         Version { crate_name, .. } | Crate { crate_name, .. } => crate_name.as_str(),
         CrateCollection { .. } => TOP_LEVEL_REPORT_NAME,
     };
-    path_from_prefix(out_dir, prefix)
+    path_from_prefix(settings, out_dir, prefix)
 }
 
 pub fn vec_into_map_by_extension(initial: Dict<AggregateFileInfo>, from: Vec<WastedFile>) -> Dict<AggregateFileInfo> {
@@ -87,7 +103,9 @@ pub fn version_to_new_version_map(
     total_files: u64,
     wasted_files: &[WastedFile],
     potential_gains: Option<AggregateFileInfo>,
+    downloads_per_day: Option<u64>,
 ) -> Dict<VersionInfo> {
+    let wasted_bytes = byte_count(wasted_files);
     let mut m = BTreeMap::new();
     m.insert(
         crate_version,
@@ -97,11 +115,12 @@ pub fn version_to_new_version_map(
                 total_files,
             },
             waste: AggregateFileInfo {
-                total_bytes: byte_count(wasted_files),
+                total_bytes: wasted_bytes,
                 total_files: wasted_files.len() as u64,
             },
             potential_gains,
             waste_latest_version: None,
+            wasted_bytes_per_day: downloads_per_day.map(|downloads| downloads.saturating_mul(wasted_bytes)),
         },
     );
     m
@@ -119,8 +138,10 @@ pub fn crate_collection_info_from_version_info(
                 all,
                 potential_gains,
                 waste_latest_version: _unused_and_always_none,
+                wasted_bytes_per_day,
             } = v;
             a.waste.add_assign(waste.clone());
+            a.wasted_bytes_per_day = add_optional(a.wasted_bytes_per_day, wasted_bytes_per_day);
             a.all.add_assign(all);
             a.potential_gains = add_optional_aggregate(a.potential_gains.clone(), potential_gains);
             a.waste_latest_version = if version_name > previous_name {
@@ -162,6 +183,8 @@ pub fn crate_from_version(version: Report) -> Report {
             total_files,
             wasted_files,
             suggested_fix,
+            downloads_per_day,
+            ..
         } => Report::Crate {
             crate_name,
             info_by_version: version_to_new_version_map(
@@ -170,6 +193,7 @@ pub fn crate_from_version(version: Report) -> Report {
                 total_files,
                 &wasted_files,
                 fix_to_wasted_files_aggregate(suggested_fix),
+                downloads_per_day,
             ),
             total_size_in_bytes,
             total_files,
@@ -206,6 +230,8 @@ impl crate::engine::report::generic::Aggregate for Report {
                     total_files: rhs_tf,
                     wasted_files,
                     suggested_fix,
+                    breakdown,
+                    downloads_per_day,
                 },
             ) => {
                 if lhs_crate_name == rhs_crate_name {
@@ -221,6 +247,7 @@ impl crate::engine::report::generic::Aggregate for Report {
                                 rhs_tf,
                                 &wasted_files,
                                 fix_to_wasted_files_aggregate(suggested_fix),
+                                downloads_per_day,
                             ),
                         ),
                         wasted_by_extension: vec_into_map_by_extension(wasted_by_extension, wasted_files),
@@ -234,6 +261,8 @@ impl crate::engine::report::generic::Aggregate for Report {
                             total_files: rhs_tf,
                             wasted_files,
                             suggested_fix,
+                            breakdown,
+                            downloads_per_day,
                         },
                     )
                 }
@@ -317,16 +346,28 @@ impl crate::engine::report::generic::Aggregate for Report {
         }
     }
 
-    async fn complete(&mut self, _progress: &mut prodash::tree::Item, out: &mut Vec<u8>) -> Result<()> {
+    async fn complete(
+        &mut self,
+        settings: &Settings,
+        _progress: &mut prodash::tree::Item,
+        out: &mut Vec<u8>,
+    ) -> Result<()> {
         use horrorshow::Template;
 
-        let report = self.clone();
-        report.write_to_io(out)?;
+        let page = Page {
+            report: self.clone(),
+            generated_at: settings.generated_at(),
+        };
+        page.write_to_io(out)?;
         Ok(())
     }
 
-    async fn load_previous_top_level_state(out_dir: &Path, progress: &mut prodash::tree::Item) -> Option<Self> {
-        let path = path_from_prefix(out_dir, TOP_LEVEL_REPORT_NAME);
+    async fn load_previous_top_level_state(
+        settings: &Settings,
+        out_dir: &Path,
+        progress: &mut prodash::tree::Item,
+    ) -> Option<Self> {
+        let path = path_from_prefix(settings, out_dir, TOP_LEVEL_REPORT_NAME);
         progress.blocked("loading previous top-level waste report from disk", None);
         blocking::unblock(move || std::fs::read(path))
             .await
@@ -334,20 +375,89 @@ impl crate::engine::report::generic::Aggregate for Report {
             .and_then(|v| rmp_serde::from_read(v.as_slice()).ok())
     }
 
-    async fn load_previous_state(&self, out_dir: &Path, progress: &mut prodash::tree::Item) -> Option<Self> {
-        let path = path_to_storage_location(self, out_dir);
+    async fn load_previous_state(
+        &self,
+        settings: &Settings,
+        out_dir: &Path,
+        progress: &mut prodash::tree::Item,
+    ) -> Option<Self> {
+        let path = path_to_storage_location(settings, self, out_dir);
         progress.blocked("loading previous waste report from disk", None);
         blocking::unblock(move || std::fs::read(path))
             .await
             .ok()
             .and_then(|v| rmp_serde::from_read(v.as_slice()).ok())
     }
-    async fn store_current_state(&self, out_dir: &Path, progress: &mut prodash::tree::Item) -> Result<()> {
-        let path = path_to_storage_location(self, out_dir);
+    async fn store_current_state(
+        &self,
+        settings: &Settings,
+        out_dir: &Path,
+        progress: &mut prodash::tree::Item,
+    ) -> Result<()> {
+        let path = path_to_storage_location(settings, self, out_dir);
         progress.blocked("storing current waste report to disk", None);
         let data = rmp_serde::to_vec(self)?;
-        blocking::unblock(move || std::fs::write(path, data))
-            .await
-            .map_err(Into::into)
+        // Crates of registries other than crates.io are stored in a directory named after the registry
+        blocking::unblock(move || {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            crate::utils::write_replacing(path, data)
+        })
+        .await
+        .map_err(Into::into)
+    }
+
+    fn forget_crate(settings: &Settings, out_dir: &Path, crate_name: &str) -> Result<bool> {
+        let crate_path = path_from_prefix(settings, out_dir, crate_name);
+        let krate = match std::fs::read(&crate_path) {
+            Ok(data) => rmp_serde::from_read::<_, Report>(data.as_slice()).ok(),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+            Err(err) => return Err(err.into()),
+        };
+        let krate = match krate {
+            Some(version @ Report::Version { .. }) => Some(crate_from_version(version)),
+            krate => krate,
+        };
+        let top_level_path = path_from_prefix(settings, out_dir, TOP_LEVEL_REPORT_NAME);
+        let top_level = std::fs::read(&top_level_path)
+            .ok()
+            .and_then(|data| rmp_serde::from_read::<_, Report>(data.as_slice()).ok());
+        if let (
+            Some(Report::Crate {
+                total_size_in_bytes: crate_tsb,
+                total_files: crate_tf,
+                wasted_by_extension: crate_wbe,
+                ..
+            }),
+            Some(Report::CrateCollection {
+                total_size_in_bytes,
+                total_files,
+                mut info_by_crate,
+                mut wasted_by_extension,
+            }),
+        ) = (krate, top_level)
+        {
+            if info_by_crate.remove(crate_name).is_some() {
+                for (extension, crate_info) in crate_wbe {
+                    if let Some(info) = wasted_by_extension.get_mut(&extension) {
+                        info.total_bytes = info.total_bytes.saturating_sub(crate_info.total_bytes);
+                        info.total_files = info.total_files.saturating_sub(crate_info.total_files);
+                        if info.total_files == 0 {
+                            wasted_by_extension.remove(&extension);
+                        }
+                    }
+                }
+                let top_level = Report::CrateCollection {
+                    total_size_in_bytes: total_size_in_bytes.saturating_sub(crate_tsb),
+                    total_files: total_files.saturating_sub(crate_tf),
+                    info_by_crate,
+                    wasted_by_extension,
+                };
+                crate::utils::write_replacing(top_level_path, rmp_serde::to_vec(&top_level)?)?;
+            }
+        }
+        std::fs::remove_file(crate_path)?;
+        Ok(true)
     }
 }
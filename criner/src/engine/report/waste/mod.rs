@@ -1,10 +1,43 @@
-use crate::persistence::TableAccess;
-use crate::{error::Result, model::TaskResult, persistence};
+use crate::{engine::work::cpubound::Extraction, error::Result, model::TaskResult, persistence};
 use async_trait::async_trait;
+use std::{borrow::Cow, time::SystemTime};
 
 pub use criner_waste_report::*;
 
 mod merge;
+pub(crate) use merge::read_latest_top_level_state;
+
+const VERSION: &str = "1.2.0";
+
+/// The version of reports which don't consider files of the `not_waste` categories waste. As reports with different
+/// definitions of waste don't mix, the categories become part of the version.
+pub fn version_keeping(not_waste: &[Category]) -> String {
+    let mut keep = not_waste.to_vec();
+    keep.sort();
+    keep.dedup();
+    if keep.is_empty() {
+        VERSION.to_owned()
+    } else {
+        format!(
+            "{}-keeping-{}",
+            VERSION,
+            keep.iter().map(|c| c.name()).collect::<Vec<_>>().join("-")
+        )
+    }
+}
+
+/// The average amount of downloads per day of a version published at `created_at` and downloaded `downloads` times
+/// until `now`, counting at least one day.
+pub fn downloads_per_day(downloads: u32, created_at: SystemTime, now: SystemTime) -> u64 {
+    let days = now.duration_since(created_at).unwrap_or_default().as_secs() / (24 * 60 * 60);
+    downloads as u64 / days.max(1)
+}
+
+/// Returns true for the files whose content is needed to compute the waste of a crate, the manifest and Rust sources which
+/// may include other files, so large files like READMEs are never decoded.
+pub fn is_manifest_or_rust_source(header: &TarHeader) -> bool {
+    header.path == b"Cargo.toml" || header.path.ends_with(b".rs")
+}
 
 pub struct Generator;
 
@@ -13,14 +46,19 @@ pub struct Generator;
 #[async_trait]
 impl super::generic::Generator for Generator {
     type Report = Report;
-    type DBResult = TaskResult;
+    /// The extracted crate and how often it is downloaded per day, if known
+    type DBResult = (TaskResult, Option<u64>);
 
     fn name() -> &'static str {
         "waste"
     }
 
     fn version() -> &'static str {
-        "1.0.0"
+        VERSION
+    }
+
+    fn report_version(settings: &super::Settings) -> Cow<'static, str> {
+        version_keeping(&settings.not_waste).into()
     }
 
     fn fq_result_key(crate_name: &str, crate_version: &str, key_buf: &mut String) {
@@ -32,38 +70,75 @@ impl super::generic::Generator for Generator {
         dummy_result.fq_key(crate_name, crate_version, &dummy_task, key_buf);
     }
 
+    fn needed_extraction() -> Option<Extraction> {
+        Some(Extraction::MetaDataOnly)
+    }
+
     fn get_result(
+        settings: &super::Settings,
         connection: persistence::ThreadSafeConnection,
         crate_name: &str,
         crate_version: &str,
         key_buf: &mut String,
-    ) -> Result<Option<TaskResult>> {
+    ) -> Result<Option<Self::DBResult>> {
         Self::fq_result_key(crate_name, crate_version, key_buf);
-        let table = persistence::TaskResultTable { inner: connection };
-        table.get(&key_buf)
+        let table = persistence::TaskResultTable {
+            inner: connection.clone(),
+        };
+        let result = match table.get_selected(&key_buf, is_manifest_or_rust_source)? {
+            Some(result) => result,
+            None => return Ok(None),
+        };
+        let downloads_per_day = persistence::CratesIoCrateTable { inner: connection }
+            .get(crate_name)?
+            .and_then(|krate| krate.versions.into_iter().find(|v| v.semver == crate_version))
+            .map(|version| downloads_per_day(version.downloads, version.created_at, settings.generated_at()));
+        Ok(Some((result, downloads_per_day)))
     }
 
     async fn generate_report(
+        settings: &super::Settings,
         crate_name: &str,
         crate_version: &str,
-        result: TaskResult,
+        (result, downloads_per_day): Self::DBResult,
         _progress: &mut prodash::tree::Item,
     ) -> Result<Self::Report> {
         Ok(match result {
             TaskResult::ExplodedCrate {
                 entries_meta_data,
                 selected_entries,
-            } => Report::from_package(
+            } => Report::from_package_keeping(
                 crate_name,
                 crate_version,
                 TarPackage {
                     entries_meta_data,
                     entries: selected_entries,
                 },
-            ),
+                &settings.not_waste,
+            )
+            .with_downloads_per_day(downloads_per_day),
             _ => unreachable!("caller must assure we are always an exploded entry"),
         })
     }
+
+    fn version_artifacts(report: &Self::Report) -> Vec<(&'static str, Vec<u8>)> {
+        report
+            .manifest_excerpt()
+            .map(|excerpt| vec![("toml", excerpt.into_bytes())])
+            .unwrap_or_default()
+    }
+
+    fn crate_api(report: &Self::Report) -> Result<Option<Vec<u8>>> {
+        // Crates with a single version reported so far are still a version report
+        let api = match report {
+            Report::Version { .. } => merge::crate_from_version(report.clone()).crate_api(),
+            _ => report.crate_api(),
+        };
+        Ok(match api {
+            Some(api) => Some(serde_json::to_vec_pretty(&api)?),
+            None => None,
+        })
+    }
 }
 
 #[cfg(test)]
@@ -1,10 +1,70 @@
+#![allow(clippy::identity_op)] // keep the arithmetic visible in expectations
+
 use crate::{
-    engine::report::generic::Aggregate,
-    engine::report::waste::{AggregateFileInfo, Fix, PotentialWaste, Report, VersionInfo},
+    engine::report::generic::{Aggregate, Generator},
+    engine::report::waste::{self, AggregateFileInfo, Category, Fix, PotentialWaste, Report, VersionInfo},
+    engine::report::Settings,
     model::TarHeader,
+    persistence::temp_db::TempDb,
 };
 use common_macros::b_tree_map;
-use std::collections::BTreeMap;
+use std::{
+    collections::BTreeMap,
+    time::{Duration, UNIX_EPOCH},
+};
+
+#[test]
+fn states_of_reports_with_different_settings_are_kept_apart_and_pages_claim_their_generation_time() {
+    let db = TempDb::new("waste-settings");
+    let mut progress = prodash::Tree::new().add_child("waste");
+    let report = Report::Version {
+        crate_name: "a".into(),
+        crate_version: "1".into(),
+        total_size_in_bytes: 1,
+        total_files: 4,
+        wasted_files: vec![("a.a".into(), 20)],
+        suggested_fix: None,
+        breakdown: Default::default(),
+        downloads_per_day: None,
+    };
+    let keeping = Settings {
+        generated_at: Some(UNIX_EPOCH + Duration::from_secs(24 * 60 * 60)),
+        not_waste: vec![Category::Tests, Category::Examples, Category::Tests],
+        ..Default::default()
+    };
+    assert_eq!(
+        waste::Generator::report_version(&keeping),
+        format!("{}-keeping-tests-examples", waste::Generator::version())
+    );
+    assert_eq!(
+        waste::Generator::report_version(&Settings::default()),
+        waste::Generator::version()
+    );
+
+    futures_lite::future::block_on(async {
+        report
+            .store_current_state(&keeping, &db.dir, &mut progress)
+            .await
+            .unwrap();
+        assert!(report
+            .load_previous_state(&Settings::default(), &db.dir, &mut progress)
+            .await
+            .is_none());
+        assert_eq!(
+            report.load_previous_state(&keeping, &db.dir, &mut progress).await,
+            Some(report.clone())
+        );
+
+        let mut page = Vec::new();
+        report
+            .clone()
+            .complete(&keeping, &mut progress, &mut page)
+            .await
+            .unwrap();
+        let page = String::from_utf8(page).unwrap();
+        assert!(page.contains("at 1970-01-02T00:00:00Z"), "{}", page);
+    });
+}
 
 #[test]
 fn crate_merging_version_equivalent_to_version_merging_crate() {
@@ -15,6 +75,8 @@ fn crate_merging_version_equivalent_to_version_merging_crate() {
         total_files: 4,
         wasted_files: vec![("a.a".into(), 20)],
         suggested_fix: Some(Fix::RemoveExclude),
+        breakdown: Default::default(),
+        downloads_per_day: None,
     };
 
     let krate = Report::Crate {
@@ -47,12 +109,14 @@ fn crate_and_crate_of_different_name() {
                         total_files: 8
                     }),
                     waste_latest_version: None,
+                    wasted_bytes_per_day: None,
                 },
                 "2".into() => VersionInfo {
                     all: AggregateFileInfo { total_files: 4, total_bytes: 1 },
                     waste: AggregateFileInfo { total_files: 3, total_bytes: 50 },
                     potential_gains: None,
                     waste_latest_version: None,
+                    wasted_bytes_per_day: None,
                 }
             },
             wasted_by_extension: b_tree_map! {
@@ -71,6 +135,7 @@ fn crate_and_crate_of_different_name() {
                     waste: AggregateFileInfo { total_files: 6, total_bytes: 150 },
                     potential_gains: None,
                     waste_latest_version: None,
+                    wasted_bytes_per_day: None,
                 }
             },
             wasted_by_extension: b_tree_map! {
@@ -90,13 +155,15 @@ fn crate_and_crate_of_different_name() {
                         total_bytes: 2,
                         total_files: 8
                     }),
-                    waste_latest_version: Some(("2".into(), AggregateFileInfo { total_files: 3, total_bytes: 50 }))
+                    waste_latest_version: Some(("2".into(), AggregateFileInfo { total_files: 3, total_bytes: 50 })),
+                    wasted_bytes_per_day: None,
                 },
                 "b".into() => VersionInfo {
                     all: AggregateFileInfo { total_files: 8, total_bytes: 10 },
                     waste: AggregateFileInfo { total_files: 6, total_bytes: 150 },
                     potential_gains: None,
-                    waste_latest_version: Some(("2".into(), AggregateFileInfo { total_files: 6, total_bytes: 150 }))
+                    waste_latest_version: Some(("2".into(), AggregateFileInfo { total_files: 6, total_bytes: 150 })),
+                    wasted_bytes_per_day: None,
                 }
             },
             wasted_by_extension: b_tree_map! {
@@ -122,7 +189,8 @@ fn two_crate_collections() {
                     total_files: 5,
                     total_bytes: 10,
                 }),
-                waste_latest_version: Some(("3".into(), AggregateFileInfo { total_files: 1, total_bytes: 20},))
+                waste_latest_version: Some(("3".into(), AggregateFileInfo { total_files: 1, total_bytes: 20},)),
+                wasted_bytes_per_day: None,
             },
         },
         wasted_by_extension: b_tree_map! {
@@ -143,13 +211,15 @@ fn two_crate_collections() {
                     total_files: 50,
                     total_bytes: 100,
                 }),
-                waste_latest_version: Some(("4".into(), AggregateFileInfo { total_files: 2, total_bytes: 40}))
+                waste_latest_version: Some(("4".into(), AggregateFileInfo { total_files: 2, total_bytes: 40})),
+                wasted_bytes_per_day: None,
             },
             "b".into() => VersionInfo {
                 all: AggregateFileInfo { total_files: 8, total_bytes: 10 },
                 waste: AggregateFileInfo { total_files: 6, total_bytes: 150 },
                 potential_gains: None,
-                waste_latest_version: Some(("1".into(), AggregateFileInfo { total_files: 3, total_bytes: 50}))
+                waste_latest_version: Some(("1".into(), AggregateFileInfo { total_files: 3, total_bytes: 50})),
+                wasted_bytes_per_day: None,
             },
         },
         wasted_by_extension: b_tree_map! {
@@ -173,13 +243,15 @@ fn two_crate_collections() {
                         total_files: 55,
                         total_bytes: 110
                     }),
-                    waste_latest_version: Some(("4".into(), AggregateFileInfo { total_files: 2, total_bytes: 40}))
+                    waste_latest_version: Some(("4".into(), AggregateFileInfo { total_files: 2, total_bytes: 40})),
+                    wasted_bytes_per_day: None,
                 },
                 "b".into() => VersionInfo {
                     all: AggregateFileInfo { total_files: 8, total_bytes: 10 },
                     waste: AggregateFileInfo { total_files: 6, total_bytes: 150 },
                     potential_gains: None,
-                    waste_latest_version: Some(("1".into(), AggregateFileInfo { total_files: 3, total_bytes: 50}))
+                    waste_latest_version: Some(("1".into(), AggregateFileInfo { total_files: 3, total_bytes: 50})),
+                    wasted_bytes_per_day: None,
                 }
             },
             wasted_by_extension: b_tree_map! {
@@ -209,6 +281,7 @@ fn crate_and_crate_of_same_name() {
                         total_bytes: 100
                     }),
                     waste_latest_version: None,
+                    wasted_bytes_per_day: None,
                 }
             },
             wasted_by_extension: b_tree_map! {
@@ -230,6 +303,7 @@ fn crate_and_crate_of_same_name() {
                         total_bytes: 10
                     }),
                     waste_latest_version: None,
+                    wasted_bytes_per_day: None,
                 }
             },
             wasted_by_extension: b_tree_map! {
@@ -251,6 +325,7 @@ fn crate_and_crate_of_same_name() {
                         total_bytes: 100
                     }),
                     waste_latest_version: None,
+                    wasted_bytes_per_day: None,
                 },
                 "2".into() => VersionInfo {
                     all: AggregateFileInfo { total_files: 8, total_bytes: 10 },
@@ -260,6 +335,7 @@ fn crate_and_crate_of_same_name() {
                         total_bytes: 10
                     }),
                     waste_latest_version: None,
+                    wasted_bytes_per_day: None,
                 }
             },
             wasted_by_extension: b_tree_map! {
@@ -289,11 +365,15 @@ fn two_versions_of_same_crate() {
                     potential_waste: vec![TarHeader {
                         path: (&b"a/d.c"[..]).into(),
                         size: 10,
-                        entry_type: 0
+                        entry_type: 0,
+                        encoding: None,
+                        truncated_at: None
                     }]
                 }),
                 has_build_script: false
-            })
+            }),
+            breakdown: Default::default(),
+            downloads_per_day: None,
         }
         .merge(Report::Version {
             crate_name: "a".into(),
@@ -309,11 +389,15 @@ fn two_versions_of_same_crate() {
                     potential_waste: vec![TarHeader {
                         path: (&b"a/d.c"[..]).into(),
                         size: 100,
-                        entry_type: 0
+                        entry_type: 0,
+                        encoding: None,
+                        truncated_at: None
                     }]
                 }),
                 has_build_script: false
-            })
+            }),
+            breakdown: Default::default(),
+            downloads_per_day: None,
         }),
         Report::Crate {
             crate_name: "a".into(),
@@ -325,12 +409,14 @@ fn two_versions_of_same_crate() {
                                 waste: AggregateFileInfo { total_files: 3, total_bytes: 50 },
                                 potential_gains: Some(AggregateFileInfo {total_files: 1, total_bytes: 10}),
                                 waste_latest_version: None,
+                                wasted_bytes_per_day: None,
                               },
                  "2".into() => VersionInfo {
                                 all: AggregateFileInfo { total_files: 5, total_bytes: 2 },
                                 waste: AggregateFileInfo { total_files: 3, total_bytes: 180 },
                                 potential_gains: Some(AggregateFileInfo {total_files: 1, total_bytes: 100}),
                                 waste_latest_version: None,
+                                wasted_bytes_per_day: None,
                               },
             },
             wasted_by_extension: b_tree_map! {
@@ -359,11 +445,15 @@ fn two_versions_of_different_crate() {
                     potential_waste: vec![TarHeader {
                         path: (&b"a/b.c"[..]).into(),
                         size: 10,
-                        entry_type: 0
+                        entry_type: 0,
+                        encoding: None,
+                        truncated_at: None
                     }]
                 }),
                 has_build_script: false
-            })
+            }),
+            breakdown: Default::default(),
+            downloads_per_day: None,
         }
         .merge(Report::Version {
             crate_name: "b".into(),
@@ -379,11 +469,15 @@ fn two_versions_of_different_crate() {
                     potential_waste: vec![TarHeader {
                         path: (&b"a/d.c"[..]).into(),
                         size: 100,
-                        entry_type: 0
+                        entry_type: 0,
+                        encoding: None,
+                        truncated_at: None
                     }]
                 }),
                 has_build_script: false
-            })
+            }),
+            breakdown: Default::default(),
+            downloads_per_day: None,
         }),
         Report::CrateCollection {
             total_size_in_bytes: 3,
@@ -393,13 +487,15 @@ fn two_versions_of_different_crate() {
                                 all: AggregateFileInfo { total_files: 4, total_bytes: 1 },
                                 waste: AggregateFileInfo { total_files: 3, total_bytes: 50 },
                                 potential_gains: Some(AggregateFileInfo{total_files: 1, total_bytes: 10}),
-                                waste_latest_version: Some(("1".into(), AggregateFileInfo { total_files: 3, total_bytes: 50 }))
+                                waste_latest_version: Some(("1".into(), AggregateFileInfo { total_files: 3, total_bytes: 50 })),
+                                wasted_bytes_per_day: None,
                               },
                  "b".into() => VersionInfo {
                                 all: AggregateFileInfo { total_files: 5, total_bytes: 2 },
                                 waste: AggregateFileInfo { total_files: 3, total_bytes: 180 },
                                 potential_gains: Some(AggregateFileInfo{total_files: 1, total_bytes: 100}),
-                                waste_latest_version: Some(("1".into(), AggregateFileInfo { total_files: 3, total_bytes: 180 }))
+                                waste_latest_version: Some(("1".into(), AggregateFileInfo { total_files: 3, total_bytes: 180 })),
+                                wasted_bytes_per_day: None,
                               },
             },
             wasted_by_extension: b_tree_map! {
@@ -410,3 +506,39 @@ fn two_versions_of_different_crate() {
         }
     );
 }
+
+#[test]
+fn versions_with_downloads_sum_up_their_waste_per_day() {
+    let version = |crate_version: &str, downloads_per_day| Report::Version {
+        crate_name: "a".into(),
+        crate_version: crate_version.into(),
+        total_size_in_bytes: 100,
+        total_files: 2,
+        wasted_files: vec![("a.a".into(), 20)],
+        suggested_fix: None,
+        breakdown: Default::default(),
+        downloads_per_day,
+    };
+    let per_day = |report: Report| match report {
+        Report::Crate { info_by_version, .. } => {
+            info_by_version
+                .into_iter()
+                .fold(VersionInfo::default(), |mut acc, (_, info)| {
+                    acc += info;
+                    acc
+                })
+                .wasted_bytes_per_day
+        }
+        _ => unreachable!("two versions of the same crate merge into a crate"),
+    };
+    assert_eq!(
+        per_day(version("1", Some(10)).merge(version("2", Some(5)))),
+        Some(20 * 15)
+    );
+    assert_eq!(
+        per_day(version("1", Some(10)).merge(version("2", None))),
+        Some(20 * 10),
+        "versions without known downloads don't add to it"
+    );
+    assert_eq!(per_day(version("1", None).merge(version("2", None))), None);
+}
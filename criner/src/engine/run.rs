@@ -1,13 +1,28 @@
-use crate::{engine::stage, error::Result, model, persistence::Db, utils::*};
+pub use crate::allowlist::Allowlist;
+pub use crate::engine::report::waste::Category as WasteCategory;
+pub use crate::engine::stage::Stage;
+pub use crate::engine::work::artifact;
+pub use crate::engine::work::cpubound::{parse_size, DEFAULT_MAX_STORED_ENTRY_SIZE};
+pub use crate::engine::work::dns::parse_nameserver;
+pub use crate::engine::work::download_schedule::DownloadSchedule;
+pub use crate::engine::work::iobound::{
+    parse_bandwidth, parse_host_override, parse_proxy, parse_requests_per_second, Connectivity, IpVersion,
+};
+pub use crate::engine::work::retry::RetryPolicy;
+pub use crate::persistence::{BackendKind, Durability};
+pub use crate::progress::{Progress, StageProgress};
+pub use crate::registry::{parse_download_url_template, Registry};
+use crate::{engine, engine::stage, engine::work, error::Result, model, persistence::Db, utils::*};
 use futures_util::{
     future::{Either, FutureExt},
     stream::StreamExt,
 };
 use log::{info, warn};
 use prodash::render::tui::{Event, Line};
+pub use reqwest::Proxy;
 use std::{
     path::{Path, PathBuf},
-    time::{Duration, SystemTime},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 pub struct StageRunSettings {
@@ -17,56 +32,329 @@ pub struct StageRunSettings {
     pub at_most: Option<usize>,
 }
 
+impl Default for StageRunSettings {
+    fn default() -> Self {
+        StageRunSettings {
+            every: Duration::from_secs(5 * 60),
+            at_most: None,
+        }
+    }
+}
+
+/// Settings affecting how we talk to servers
+pub struct NetworkSettings {
+    /// The maximum amount of concurrent connections to a single host, shared by all downloaders
+    pub max_connections_per_host: usize,
+    /// The minimum time between requests to hosts other than crates.io
+    pub crawl_delay: std::time::Duration,
+    /// The maximum amount of requests per second to a single host, shared by all downloaders, or unlimited if `None`
+    pub max_requests_per_second: Option<f64>,
+    /// The maximum bandwidth in bytes per second of all downloads together, or unlimited if `None`
+    pub max_download_bandwidth: Option<u64>,
+    /// Bandwidth and concurrency limits by time of day, taking precedence over `max_download_bandwidth`
+    pub download_schedule: Option<DownloadSchedule>,
+    /// How to reach hosts, for networks with broken IPv6 or DNS
+    pub connectivity: Connectivity,
+    /// The template for download URLs of crates as returned by `parse_download_url_template()`, for mirrors and registries
+    /// whose index doesn't say where to download from. If `None`, crates.io or the `config.json` of the index are used.
+    pub download_url_template: Option<String>,
+    /// The template for download URLs of a mirror in the same format as `download_url_template`, to download crates from
+    /// if what was downloaded doesn't match the checksum in the index.
+    pub mirror_url_template: Option<String>,
+    /// When failed downloads and extractions are tried again, and when they are given up on
+    pub task_retry: RetryPolicy,
+}
+
+impl Default for NetworkSettings {
+    fn default() -> Self {
+        NetworkSettings {
+            max_connections_per_host: 4,
+            crawl_delay: Duration::from_secs(1),
+            max_requests_per_second: None,
+            max_download_bandwidth: None,
+            download_schedule: None,
+            connectivity: Default::default(),
+            download_url_template: None,
+            mirror_url_template: None,
+            task_retry: Default::default(),
+        }
+    }
+}
+
 /// Like `StageRunSettings`, but also provides a glob pattern
+#[derive(Default)]
 pub struct GlobStageRunSettings {
     pub glob: Option<String>,
     pub run: StageRunSettings,
 }
 
+/// Configures what the engine does and how, with defaults like those of `criner mine`.
+/// Settings in a `config` file override the ones here they are named after, see `engine::config`.
+pub struct Options {
+    /// The registry whose index is at `crates_io_path`
+    pub registry: Registry,
+    /// If set, stop all stages once it passed
+    pub deadline: Option<SystemTime>,
+    pub io_bound_processors: u32,
+    pub cpu_bound_processors: u32,
+    pub cpu_o_bound_processors: u32,
+    pub fetch_settings: StageRunSettings,
+    pub process_settings: StageRunSettings,
+    pub report_settings: GlobStageRunSettings,
+    /// The local time to download the crates.io database at every day, or right away if `None`
+    pub download_crates_io_database_every_24_hours_starting_at: Option<time::Time>,
+    pub network_settings: NetworkSettings,
+    /// The artifacts to download for each crate version
+    pub artifact_kinds: Vec<&'static artifact::Kind>,
+    /// If set, strip the extracted content of all but the given amount of latest versions of each crate once a day
+    pub keep_full_results_for_latest_versions: Option<usize>,
+    /// Where to store assets, see `work::asset_store::from_url()`, or in the `assets` directory of the database if `None`
+    pub assets_url: Option<String>,
+    /// Schedule versions the index check finds missing in the database
+    pub repair_index_gaps: bool,
+    /// Globs of files to store the content of when extracting crates, in addition to the default ones
+    pub select_globs: Vec<String>,
+    /// Files larger than this are stored without their content
+    pub max_stored_entry_size: u64,
+    /// If set, extract the content of only the given amount of latest versions of each crate
+    pub extract_contents_for_latest: Option<usize>,
+    /// Categories of files the waste report doesn't consider waste
+    pub not_waste: Vec<WasteCategory>,
+    /// Generate reports which only depend on the state of the index, for comparing runs
+    pub deterministic: bool,
+    pub stages: Vec<Stage>,
+    /// If set, only crates it contains are processed
+    pub allowlist: Option<Allowlist>,
+    pub durability: Durability,
+    /// Where to store the items of tables, which must be one whose items can be queried with SQL
+    pub backend: BackendKind,
+    /// If set, serve prometheus metrics at this address
+    pub metrics_addr: Option<std::net::SocketAddr>,
+    /// If set, serve reports and the status of the miner at this address
+    pub serve_addr: Option<std::net::SocketAddr>,
+    /// A file with settings to apply while mining, see `engine::config`
+    pub config: Option<PathBuf>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            registry: Default::default(),
+            deadline: None,
+            io_bound_processors: 10,
+            cpu_bound_processors: 4,
+            cpu_o_bound_processors: 20,
+            fetch_settings: Default::default(),
+            process_settings: Default::default(),
+            report_settings: Default::default(),
+            download_crates_io_database_every_24_hours_starting_at: None,
+            network_settings: Default::default(),
+            artifact_kinds: vec![&artifact::CRATE],
+            keep_full_results_for_latest_versions: None,
+            assets_url: None,
+            repair_index_gaps: false,
+            select_globs: Vec::new(),
+            max_stored_entry_size: DEFAULT_MAX_STORED_ENTRY_SIZE,
+            extract_contents_for_latest: None,
+            not_waste: Vec::new(),
+            deterministic: false,
+            stages: Stage::ALL.to_vec(),
+            allowlist: None,
+            durability: Default::default(),
+            backend: Default::default(),
+            metrics_addr: None,
+            serve_addr: None,
+            config: None,
+        }
+    }
+}
+
+impl Options {
+    /// The settings a config file may change while mining, as given here
+    fn tunables(&self) -> engine::config::Tunables {
+        engine::config::Tunables {
+            io_bound_processors: self.io_bound_processors,
+            cpu_bound_processors: self.cpu_bound_processors,
+            cpu_o_bound_processors: self.cpu_o_bound_processors,
+            max_requests_per_second: self.network_settings.max_requests_per_second,
+            max_download_bandwidth: self.network_settings.max_download_bandwidth,
+            report_glob: self.report_settings.glob.clone(),
+        }
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
 /// Runs the statistics and mining engine.
 /// May run for a long time unless a deadline is specified.
 /// Even though timeouts can be achieved from outside of the future, knowing the deadline may be used
 /// by the engine to manage its time even more efficiently.
+/// The database is opened and served by the caller, which is why `options` about it are ignored here.
 pub async fn non_blocking(
     db: Db,
     crates_io_path: PathBuf,
-    deadline: Option<SystemTime>,
+    options: Options,
     progress: prodash::Tree,
-    io_bound_processors: u32,
-    cpu_bound_processors: u32,
-    cpu_o_bound_processors: u32,
     interrupt_control: InterruptControlEvents,
-    fetch_settings: StageRunSettings,
-    process_settings: StageRunSettings,
-    report_settings: GlobStageRunSettings,
-    download_crates_io_database_every_24_hours_starting_at: Option<time::Time>,
     assets_dir: PathBuf,
+    assets: work::asset_store::SharedAssetStore,
+    needed_extractions: work::cpubound::NeededExtractions,
 ) -> Result<()> {
+    let tunables = engine::config::Live::new(options.tunables());
+    let Options {
+        registry,
+        deadline,
+        mut fetch_settings,
+        mut process_settings,
+        mut report_settings,
+        download_crates_io_database_every_24_hours_starting_at,
+        network_settings,
+        artifact_kinds,
+        keep_full_results_for_latest_versions,
+        repair_index_gaps,
+        select_globs,
+        max_stored_entry_size,
+        extract_contents_for_latest,
+        not_waste,
+        deterministic,
+        stages,
+        allowlist,
+        config,
+        ..
+    } = options;
     check(deadline)?;
+    let newly_published = work::schedule::NewlyPublished::default();
+    let selection = {
+        let mut globs = select_globs;
+        globs.extend(select_globs_from_file()?);
+        work::cpubound::selection(&globs)?
+    };
     let startup_time = SystemTime::now();
+    let mut network_settings = network_settings;
+    if deterministic && network_settings.download_schedule.take().is_some() {
+        info!("Ignoring the download schedule as the run is deterministic");
+    }
+    if let Some(path) = config.as_deref() {
+        tunables.load(path)?;
+        info!("Using config file '{}': {}", path.display(), tunables.get());
+    }
+    let initial = tunables.get();
+    let host_limits = work::iobound::HostLimits::new(
+        network_settings.max_connections_per_host,
+        network_settings.crawl_delay,
+        initial.max_requests_per_second,
+        initial.max_download_bandwidth,
+        network_settings.download_schedule,
+        network_settings.connectivity,
+    );
+    // dropped and thus stopped once the engine is done
+    let _config_watch_handle =
+        config.map(|path| crate::spawn(engine::config::watch(path, tunables.clone(), host_limits.clone())));
+    let download_url_template = network_settings.download_url_template;
+    let mirror_url_template = network_settings.mirror_url_template;
+    let task_retry = network_settings.task_retry;
 
-    let db_download_handle = crate::spawn(repeat_daily_at(
-        download_crates_io_database_every_24_hours_starting_at,
-        {
-            let p = progress.clone();
-            move || p.add_child("Crates.io DB Digest")
-        },
-        deadline,
-        {
-            let db = db.clone();
-            let assets_dir = assets_dir.clone();
-            let progress = progress.clone();
-            move || {
-                stage::db_download::schedule(
-                    db.clone(),
-                    assets_dir.clone(),
-                    progress.add_child("fetching crates-io db"),
-                    startup_time,
-                )
-            }
-        },
-    ));
+    let enabled = |stage| stages.contains(&stage);
+    let (downloads, extract) = (enabled(Stage::Download), enabled(Stage::Extract));
+    for (settings, is_enabled) in [
+        (&mut fetch_settings, enabled(Stage::Fetch)),
+        (&mut process_settings, downloads || extract),
+        (&mut report_settings.run, enabled(Stage::Report)),
+    ] {
+        if !is_enabled {
+            settings.at_most = Some(0);
+        }
+    }
+
+    let recovery = engine::recovery::recover(&db)?;
+    if recovery.is_clean() {
+        info!("Startup self-check: {}", recovery);
+    } else {
+        warn!("Recovered from an interrupted run: {}", recovery);
+    }
+    db.record_mining(&registry)?;
+
+    // The database dump is only available for crates.io
+    let db_download_handle = (registry.is_crates_io() && enabled(Stage::Fetch)).then(|| {
+        crate::spawn(repeat_daily_at(
+            download_crates_io_database_every_24_hours_starting_at,
+            {
+                let p = progress.clone();
+                move || p.add_child("Crates.io DB Digest")
+            },
+            deadline,
+            {
+                let db = db.clone();
+                let assets_dir = assets_dir.clone();
+                let progress = progress.clone();
+                let host_limits = host_limits.clone();
+                move || {
+                    stage::db_download::schedule(
+                        db.clone(),
+                        assets_dir.clone(),
+                        progress.add_child("fetching crates-io db"),
+                        startup_time,
+                        host_limits.clone(),
+                    )
+                }
+            },
+        ))
+    });
+
+    let retention_handle = keep_full_results_for_latest_versions.filter(|_| extract).map(|keep| {
+        crate::spawn(repeat_daily_at(
+            None,
+            {
+                let p = progress.clone();
+                move || p.add_child("Retention Timer")
+            },
+            deadline,
+            {
+                let db = db.clone();
+                let assets_dir = assets_dir.clone();
+                let assets = assets.clone();
+                let progress = progress.clone();
+                move || {
+                    stage::retention::compact(
+                        db.clone(),
+                        assets_dir.clone(),
+                        assets.clone(),
+                        progress.add_child("compact old versions"),
+                        keep,
+                        deadline,
+                    )
+                }
+            },
+        ))
+    });
+
+    let index_check_handle = enabled(Stage::Fetch).then(|| {
+        crate::spawn(repeat_daily_at(
+            None,
+            {
+                let p = progress.clone();
+                move || p.add_child("Index Check Timer")
+            },
+            deadline,
+            {
+                let db = db.clone();
+                let crates_io_path = crates_io_path.clone();
+                let registry = registry.clone();
+                let progress = progress.clone();
+                let allowlist = allowlist.clone();
+                move || {
+                    stage::index_check::check(
+                        crates_io_path.clone(),
+                        registry.clone(),
+                        db.clone(),
+                        progress.add_child(format!("{} index check", registry.name)),
+                        repair_index_gaps,
+                        deadline,
+                        allowlist.clone(),
+                    )
+                }
+            },
+        ))
+    });
 
     let run = fetch_settings;
     let fetch_handle = crate::spawn(repeat_every_s(
@@ -79,18 +367,26 @@ pub async fn non_blocking(
         run.at_most,
         {
             let db = db.clone();
+            let crates_io_path = crates_io_path.clone();
+            let registry = registry.clone();
             let progress = progress.clone();
+            let allowlist = allowlist.clone();
+            let newly_published = newly_published.clone();
             move || {
                 stage::changes::fetch(
                     crates_io_path.clone(),
+                    registry.clone(),
                     db.clone(),
-                    progress.add_child("crates.io refresh"),
+                    progress.add_child(format!("{} refresh", registry.name)),
                     deadline,
+                    allowlist.clone(),
+                    newly_published.clone(),
                 )
             }
         },
     ));
 
+    let report_index = (registry.clone(), crates_io_path.clone());
     let stage = process_settings;
     let processing_handle = crate::spawn(repeat_every_s(
         stage.every.as_secs() as usize,
@@ -104,15 +400,39 @@ pub async fn non_blocking(
             let progress = progress.clone();
             let db = db.clone();
             let assets_dir = assets_dir.clone();
+            let assets = assets.clone();
+            let registry = registry.clone();
+            let tunables = tunables.clone();
+            let needed_extractions = needed_extractions.clone();
             move || {
+                let tunables = tunables.get();
                 stage::processing::process(
                     db.clone(),
+                    assets.clone(),
+                    host_limits.clone(),
                     progress.add_child("Process Crate Versions"),
-                    io_bound_processors,
-                    cpu_bound_processors,
                     progress.add_child("Downloads"),
-                    assets_dir.clone(),
-                    startup_time,
+                    stage::processing::Settings {
+                        registry: registry.clone(),
+                        index_path: crates_io_path.clone(),
+                        io_bound_processors: tunables.io_bound_processors,
+                        cpu_bound_processors: tunables.cpu_bound_processors,
+                        assets_dir: assets_dir.clone(),
+                        startup_time,
+                        deadline,
+                        artifact_kinds: artifact_kinds.clone(),
+                        selection: selection.clone(),
+                        max_stored_entry_size,
+                        extract_contents_for_latest,
+                        downloads,
+                        extract,
+                        download_url_template: download_url_template.clone(),
+                        mirror_url_template: mirror_url_template.clone(),
+                        task_retry,
+                        allowlist: allowlist.clone(),
+                        needed_extractions: needed_extractions.clone(),
+                        newly_published: newly_published.clone(),
+                    },
                 )
             }
         },
@@ -132,19 +452,40 @@ pub async fn non_blocking(
                 let progress = progress.clone();
                 let db = db.clone();
                 let assets_dir = assets_dir.clone();
-                let glob = stage.glob.clone();
+                let assets = assets.clone();
+                let tunables = tunables.get();
                 let interrupt_control = interrupt_control.clone();
+                let (registry, crates_io_path) = report_index.clone();
+                let not_waste = not_waste.clone();
+                let needed_extractions = needed_extractions.clone();
                 async move {
                     let ctrl = interrupt_control;
                     ctrl.send(Interruptible::Deferred).await.ok(); // there might be no TUI
-                    let res = stage::report::generate(
-                        db.clone(),
-                        progress.add_child("Reports"),
-                        assets_dir.clone(),
-                        glob.clone(),
-                        deadline,
-                        cpu_o_bound_processors,
-                    )
+                    let res = async {
+                        // Reports of the same index state are the same, no matter when they are generated
+                        let generated_at = if deterministic {
+                            let time =
+                                blocking::unblock(move || registry.last_seen_commit_time(crates_io_path)).await?;
+                            Some(time.unwrap_or(UNIX_EPOCH))
+                        } else {
+                            None
+                        };
+                        stage::report::generate(
+                            db.clone(),
+                            progress.add_child("Reports"),
+                            assets_dir.clone(),
+                            assets,
+                            tunables.report_glob,
+                            deadline,
+                            tunables.cpu_o_bound_processors,
+                            engine::report::Settings {
+                                generated_at,
+                                not_waste,
+                                needed_extractions,
+                            },
+                        )
+                        .await
+                    }
                     .await;
                     ctrl.send(Interruptible::Instantly).await.ok(); // there might be no TUI
                     res
@@ -154,8 +495,16 @@ pub async fn non_blocking(
     ));
 
     fetch_handle.await?;
-    db_download_handle.await?;
+    if let Some(db_download_handle) = db_download_handle {
+        db_download_handle.await?;
+    }
+    if let Some(index_check_handle) = index_check_handle {
+        index_check_handle.await?;
+    }
     report_handle.await?;
+    if let Some(retention_handle) = retention_handle {
+        retention_handle.await?;
+    }
     processing_handle.await
 }
 
@@ -175,47 +524,71 @@ impl From<Interruptible> for prodash::render::tui::Event {
     }
 }
 
-#[allow(clippy::too_many_arguments)]
+/// How to show the progress of the engine while it runs
+pub enum Frontend {
+    /// Only log progress messages
+    Log,
+    /// Render the progress tree in the terminal
+    Tui(prodash::render::tui::Options),
+    /// Report the progress of each stage to `progress` every `interval`, for tools embedding criner
+    Callback {
+        progress: Box<dyn Progress>,
+        interval: Duration,
+    },
+}
+
 /// For convenience, run the engine and block until done.
+/// `root` receives the progress of all stages, and may be `Default::default()` unless it's displayed elsewhere.
 pub fn blocking(
     db: impl AsRef<Path>,
     crates_io_path: impl AsRef<Path>,
-    deadline: Option<SystemTime>,
-    io_bound_processors: u32,
-    cpu_bound_processors: u32,
-    cpu_o_bound_processors: u32,
-    fetch_settings: StageRunSettings,
-    process_settings: StageRunSettings,
-    report_settings: GlobStageRunSettings,
-    download_crates_io_database_every_24_hours_starting_at: Option<time::Time>,
+    options: Options,
     root: prodash::Tree,
-    gui: Option<prodash::render::tui::Options>,
+    frontend: Frontend,
 ) -> Result<()> {
     let start_of_computation = SystemTime::now();
     let assets_dir = db.as_ref().join("assets");
-    let db = Db::open(db)?;
+    let reports_dir = db.as_ref().join("reports");
+    #[cfg(feature = "redb")]
+    if options.backend == BackendKind::Redb {
+        return Err(crate::Error::Message(
+            "Mining queries tables with SQL, which doesn't see items stored with redb. Try sqlite-pool instead.".into(),
+        ));
+    }
+    let db = Db::open_with_backend(db, options.durability, options.backend)?;
+    let db_size_at_start = db.size_on_disk();
+    let needed_extractions = work::cpubound::NeededExtractions::default();
+    if let Some(addr) = options.metrics_addr {
+        let addr = crate::engine::metrics::serve(addr, db.clone(), Some(needed_extractions.clone()))?;
+        info!("Serving metrics at http://{}/metrics", addr);
+    }
+    if let Some(addr) = options.serve_addr {
+        let addr = crate::serve::serve(addr, db.clone(), Some(needed_extractions.clone()), reports_dir)?;
+        info!("Serving reports and status at http://{}", addr);
+    }
     std::fs::create_dir_all(&assets_dir)?;
+    let assets = work::asset_store::from_url(options.assets_url.as_deref(), &assets_dir)?;
     let (interrupt_control_sink, interrupt_control_stream) = async_channel::bounded::<Interruptible>(1);
+    let (deadline, io_bound_processors, cpu_bound_processors) = (
+        options.deadline,
+        options.io_bound_processors,
+        options.cpu_bound_processors,
+    );
 
     // dropping the work handle will stop (non-blocking) futures
     let work_handle = non_blocking(
         db.clone(),
         crates_io_path.as_ref().into(),
-        deadline,
+        options,
         root.clone(),
-        io_bound_processors,
-        cpu_bound_processors,
-        cpu_o_bound_processors,
         interrupt_control_sink,
-        fetch_settings,
-        process_settings,
-        report_settings,
-        download_crates_io_database_every_24_hours_starting_at,
         assets_dir,
+        assets,
+        needed_extractions,
     );
 
-    match gui {
-        Some(gui_options) => {
+    let (exit_reason, result) = match frontend {
+        Frontend::Tui(gui_options) => {
             let gui = crate::spawn(prodash::render::tui::render_with_input(
                 std::io::stdout(),
                 root,
@@ -233,26 +606,55 @@ pub fn blocking(
             match either {
                 Either::Left((work_result, gui)) => {
                     futures_lite::future::block_on(gui.cancel());
-                    if let Err(e) = work_result? {
-                        warn!("work processor failed: {}", e);
-                    }
+                    let exit_reason = engine::summary::exit_reason(&work_result, deadline);
+                    (
+                        exit_reason,
+                        work_result.map(|r| {
+                            if let Err(e) = r {
+                                warn!("work processor failed: {}", e);
+                            }
+                        }),
+                    )
                 }
-                Either::Right((_, _work_handle)) => {}
+                Either::Right((_, _work_handle)) => ("quit from the terminal user interface".into(), Ok(())),
             }
         }
-        None => {
+        Frontend::Log | Frontend::Callback { .. } => {
             drop(interrupt_control_stream);
-            let work_result = futures_lite::future::block_on(handle_ctrl_c_and_sigterm(work_handle.boxed_local()));
+            let work = handle_ctrl_c_and_sigterm(work_handle.boxed_local());
+            let work_result = match frontend {
+                Frontend::Callback { mut progress, interval } => futures_lite::future::block_on(
+                    crate::progress::report_until(&root, interval, progress.as_mut(), work),
+                ),
+                _ => futures_lite::future::block_on(work),
+            };
+            let exit_reason = engine::summary::exit_reason(&work_result, deadline);
             if let Err(e) = work_result {
                 warn!("work processor failed: {}", e);
             }
+            (exit_reason, Ok(()))
         }
     };
 
     // at this point, we forget all currently running computation, and since it's in the local thread, it's all
     // destroyed/dropped properly.
+    match engine::recovery::interrupt(&db) {
+        Ok(0) => {}
+        Ok(interrupted) => info!("{} interrupted task(s) will be retried in the next run", interrupted),
+        Err(err) => warn!("Could not store the state of interrupted tasks: {}", err),
+    }
     info!("{}", wallclock(start_of_computation));
-    Ok(())
+    if let Err(err) = engine::summary::finish(
+        &db,
+        start_of_computation,
+        db_size_at_start,
+        exit_reason,
+        io_bound_processors,
+        cpu_bound_processors,
+    ) {
+        warn!("Could not summarize the run: {}", err);
+    }
+    result
 }
 
 fn wallclock(since: SystemTime) -> String {
@@ -266,22 +668,33 @@ fn context_stream(db: &Db, start_of_computation: SystemTime) -> impl futures_uti
     prodash::render::tui::ticker(Duration::from_secs(1)).map({
         let db = db.clone();
         move |_| {
-            db.open_context()
+            let mut lines = db
+                .open_context()
                 .ok()
                 .and_then(|c| c.most_recent().ok())
                 .flatten()
                 .map(|(_, c): (_, model::Context)| {
-                    let lines = vec![
+                    vec![
                         Line::Text(wallclock(start_of_computation)),
                         Line::Title("Durations".into()),
                         Line::Text(format!("fetch-crate-versions: {:?}", c.durations.fetch_crate_versions)),
                         Line::Title("Counts".into()),
                         Line::Text(format!("crate-versions: {}", c.counts.crate_versions)),
                         Line::Text(format!("        crates: {}", c.counts.crates)),
-                    ];
-                    Event::SetInformation(lines)
+                    ]
                 })
-                .unwrap_or(Event::Tick)
+                .unwrap_or_default();
+            if let Some(crate_name) = inspected_crate() {
+                lines
+                    .extend(engine::inspect::lines(&db, &crate_name).unwrap_or_else(|err| {
+                        vec![Line::Text(format!("Could not inspect '{}': {}", crate_name, err))]
+                    }));
+            }
+            if lines.is_empty() {
+                Event::Tick
+            } else {
+                Event::SetInformation(lines)
+            }
         }
     })
 }
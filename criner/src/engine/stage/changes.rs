@@ -1,27 +1,189 @@
 use crate::persistence::{key_value_iter, new_key_value_query_old_to_new, CrateTable};
 use crate::{
+    allowlist::Allowlist,
+    engine::work::schedule::NewlyPublished,
     error::{Error, Result},
     model,
     persistence::{self, new_key_value_insertion, CrateVersionTable, Keyed, TableAccess},
-    utils::enforce_threaded,
+    registry::Registry,
+    utils::{enforce_threaded, parse_semver},
 };
-use crates_index_diff::Index;
+use crates_index_diff::{git2, Index};
 use rusqlite::params;
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, HashSet},
     ops::Add,
     path::Path,
     time::{Duration, SystemTime},
 };
 
+/// Like `Index::peek_changes_with_options()`, but also provides the index line each version was parsed from.
+fn peek_changes_with_raw_lines(
+    index: &Index,
+    options: &mut git2::FetchOptions<'_>,
+) -> std::result::Result<(Vec<(crates_index_diff::CrateVersion, String)>, git2::Oid), git2::Error> {
+    const EMPTY_TREE_HASH: &str = "4b825dc642cb6eb9a060e54bf8d69288fbee4904";
+    let repo = index.repository();
+    let from = index
+        .last_seen_reference()
+        .and_then(|r| {
+            r.target()
+                .ok_or_else(|| git2::Error::from_str("last-seen reference did not have a valid target"))
+        })
+        .or_else(|_| git2::Oid::from_str(EMPTY_TREE_HASH))?;
+    repo.find_remote("origin")?
+        .fetch(&["refs/heads/*:refs/remotes/origin/*"], Some(options), None)?;
+    let to = repo.refname_to_id("refs/remotes/origin/master")?;
+
+    let into_tree = |oid: git2::Oid| -> std::result::Result<git2::Tree<'_>, git2::Error> {
+        let obj = repo.find_object(oid, None)?;
+        repo.find_tree(match obj.as_commit() {
+            Some(commit) => commit.tree_id(),
+            None => obj.id(),
+        })
+    };
+    let diff = repo.diff_tree_to_tree(Some(&into_tree(from)?), Some(&into_tree(to)?), None)?;
+    let mut res = Vec::new();
+    diff.print(git2::DiffFormat::Patch, |delta, _, line| {
+        if line.origin() != '+' || !matches!(delta.status(), git2::Delta::Added | git2::Delta::Modified) {
+            return true;
+        }
+        if let Ok(version) = serde_json::from_slice(line.content()) {
+            res.push((version, String::from_utf8_lossy(line.content()).trim_end().to_owned()));
+        }
+        true
+    })?;
+    Ok((res, to))
+}
+
+/// The keys of the crate versions among `crate_versions` of `registry` which were just published, or only those of the
+/// newest version of each crate if all of them were seen `from_scratch`, like when the index is fetched the first time.
+/// The index doesn't know when versions were published, but lists the versions of each crate in the order of publishing.
+fn newly_published_keys(
+    registry: &Registry,
+    crate_versions: &[(crates_index_diff::CrateVersion, String)],
+    from_scratch: bool,
+) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let mut keys: Vec<_> = crate_versions
+        .iter()
+        .rev()
+        .filter(|(version, _)| !from_scratch || seen.insert(&version.name))
+        .map(|(version, _)| {
+            let mut key = String::new();
+            model::CrateVersion::key_from(&registry.qualify(&version.name), &version.version, &mut key);
+            key
+        })
+        .collect();
+    keys.reverse();
+    keys
+}
+
+/// Store `crate_versions` of `registry` along with the index line they were parsed from, and add them to their crates.
+/// Returns the amount of stored crate versions and the amount of crates which didn't exist before.
+pub(crate) fn store_blocking(
+    db: &persistence::Db,
+    registry: &Registry,
+    crate_versions: Vec<(crates_index_diff::CrateVersion, String)>,
+    store_progress: &mut prodash::tree::Item,
+) -> Result<(u64, u32)> {
+    let mut connection = db.open_connection_no_async_with_busy_wait()?;
+    let mut crates_lut: BTreeMap<_, _> = {
+        let transaction = connection.transaction()?;
+        store_progress.blocked("caching crates", None);
+        let mut statement = new_key_value_query_old_to_new(CrateTable::table_name(), &transaction)?;
+        let iter = key_value_iter::<model::Crate>(&mut statement)?.flat_map(Result::ok);
+        iter.collect()
+    };
+
+    let mut key_buf = String::new();
+    let mut new_crate_versions = 0;
+    let mut new_crates = 0;
+    let mut invalid_versions = Vec::new();
+    store_progress.blocked("write lock for crate versions", None);
+    let transaction = connection.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+    {
+        let mut statement = new_key_value_insertion(CrateVersionTable::table_name(), &transaction)?;
+        for version in crate_versions
+            .into_iter()
+            .map(|(version, raw_index_line)| model::CrateVersion {
+                name: registry.qualify(&version.name).into_owned(),
+                raw_index_line: Some(raw_index_line),
+                ..version.into()
+            })
+        {
+            key_buf.clear();
+            version.key_buf(&mut key_buf);
+            statement.execute(params![&key_buf, rmp_serde::to_vec(&version)?])?;
+            if let Err(err) = parse_semver(&version.version) {
+                invalid_versions.push((key_buf.clone(), err));
+            }
+            new_crate_versions += 1;
+
+            key_buf.clear();
+            model::Crate::key_from_version_buf(&version, &mut key_buf);
+            if crates_lut
+                .entry(key_buf.to_owned())
+                .or_default()
+                .merge_mut(&version)
+                .versions
+                .len()
+                == 1
+            {
+                new_crates += 1;
+            }
+
+            store_progress.inc();
+        }
+    }
+
+    store_progress.blocked("commit crate versions", None);
+    transaction.commit()?;
+
+    let transaction = {
+        store_progress.blocked("write lock for crates", None);
+        let mut t = connection.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+        t.set_drop_behavior(rusqlite::DropBehavior::Commit);
+        t
+    };
+    {
+        let mut statement = new_key_value_insertion(CrateTable::table_name(), &transaction)?;
+        store_progress.init(Some(crates_lut.len()), Some("crates".into()));
+        for (key, value) in crates_lut.into_iter() {
+            statement.execute(params![key, rmp_serde::to_vec(&value)?])?;
+            store_progress.inc();
+        }
+    }
+    store_progress.blocked("commit crates", None);
+    transaction.commit()?;
+
+    if !invalid_versions.is_empty() {
+        log::warn!(
+            "{} new crate versions are not valid semantic versions",
+            invalid_versions.len()
+        );
+        let telemetry = db.open_error_telemetry()?;
+        for (key, err) in invalid_versions {
+            telemetry.record(err.category().name(), key, err.to_string())?;
+        }
+    }
+    Ok((new_crate_versions, new_crates))
+}
+
+/// Fetch the changes of the index of `registry` and store the new crate versions, or only those in `allowlist` if set.
+/// They are announced to `newly_published` to be processed ahead of the backlog, or only the newest version of each
+/// crate if the index is fetched for the first time.
 pub async fn fetch(
     crates_io_path: impl AsRef<Path>,
+    registry: Registry,
     db: persistence::Db,
     mut progress: prodash::tree::Item,
     deadline: Option<SystemTime>,
+    allowlist: Option<Allowlist>,
+    newly_published: NewlyPublished,
 ) -> Result<()> {
     let start = SystemTime::now();
-    let mut subprogress = progress.add_child("Fetching changes from crates.io index");
+    let mut subprogress = progress.add_child(format!("Fetching changes from {} index", registry.name));
     subprogress.blocked("potentially cloning", None);
     let index = enforce_threaded(
         deadline.unwrap_or_else(|| SystemTime::now().add(Duration::from_secs(60 * 60))),
@@ -30,10 +192,12 @@ pub async fn fetch(
             if !path.is_dir() {
                 std::fs::create_dir(&path)?;
             }
-            || Index::from_path_or_cloned(path)
+            let registry = registry.clone();
+            move || registry.open_index(path)
         },
     )
     .await??;
+    let from_scratch = index.last_seen_reference().is_err();
     let (crate_versions, last_seen_git_object) = enforce_threaded(
         deadline.unwrap_or_else(|| SystemTime::now().add(Duration::from_secs(10 * 60))),
         move || {
@@ -53,12 +217,16 @@ pub async fn fetch(
                 opts
             };
 
-            index.peek_changes_with_options(Some(&mut opts))
+            peek_changes_with_raw_lines(&index, &mut opts)
         },
     )
     .await??;
 
     progress.done(format!("Fetched {} changed crates", crate_versions.len()));
+    let mut crate_versions = crate_versions;
+    if let Some(allowlist) = allowlist {
+        crate_versions.retain(|(v, _)| allowlist.contains(&v.name, &v.version));
+    }
 
     let mut store_progress = progress.add_child("processing new crates");
     store_progress.init(Some(crate_versions.len()), Some("crate versions".into()));
@@ -69,67 +237,12 @@ pub async fn fetch(
         let db = db.clone();
         let index_path = crates_io_path.as_ref().to_path_buf();
         move || {
-            let mut connection = db.open_connection_no_async_with_busy_wait()?;
-            let mut crates_lut: BTreeMap<_, _> = {
-                let transaction = connection.transaction()?;
-                store_progress.blocked("caching crates", None);
-                let mut statement = new_key_value_query_old_to_new(CrateTable::table_name(), &transaction)?;
-                let iter = key_value_iter::<model::Crate>(&mut statement)?.flat_map(Result::ok);
-                iter.collect()
-            };
-
-            let mut key_buf = String::new();
             let crate_versions_len = crate_versions.len();
-            let mut new_crate_versions = 0;
-            let mut new_crates = 0;
-            store_progress.blocked("write lock for crate versions", None);
-            let transaction = connection.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
-            {
-                let mut statement = new_key_value_insertion(CrateVersionTable::table_name(), &transaction)?;
-                for version in crate_versions.into_iter().map(model::CrateVersion::from) {
-                    key_buf.clear();
-                    version.key_buf(&mut key_buf);
-                    statement.execute(params![&key_buf, rmp_serde::to_vec(&version)?])?;
-                    new_crate_versions += 1;
-
-                    key_buf.clear();
-                    model::Crate::key_from_version_buf(&version, &mut key_buf);
-                    if crates_lut
-                        .entry(key_buf.to_owned())
-                        .or_default()
-                        .merge_mut(&version)
-                        .versions
-                        .len()
-                        == 1
-                    {
-                        new_crates += 1;
-                    }
-
-                    store_progress.inc();
-                }
-            }
-
-            store_progress.blocked("commit crate versions", None);
-            transaction.commit()?;
-
-            let transaction = {
-                store_progress.blocked("write lock for crates", None);
-                let mut t = connection.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
-                t.set_drop_behavior(rusqlite::DropBehavior::Commit);
-                t
-            };
-            {
-                let mut statement = new_key_value_insertion(CrateTable::table_name(), &transaction)?;
-                store_progress.init(Some(crates_lut.len()), Some("crates".into()));
-                for (key, value) in crates_lut.into_iter() {
-                    statement.execute(params![key, rmp_serde::to_vec(&value)?])?;
-                    store_progress.inc();
-                }
-            }
-            store_progress.blocked("commit crates", None);
-            transaction.commit()?;
-
-            Index::from_path_or_cloned(index_path)?.set_last_seen_reference(last_seen_git_object)?;
+            let newly_published_keys = newly_published_keys(&registry, &crate_versions, from_scratch);
+            let (new_crate_versions, new_crates) = store_blocking(&db, &registry, crate_versions, &mut store_progress)?;
+            registry
+                .open_index(index_path)?
+                .set_last_seen_reference(last_seen_git_object)?;
             db.open_context()?.update_today(|c| {
                 c.counts.crate_versions += new_crate_versions;
                 c.counts.crates += new_crates;
@@ -138,9 +251,23 @@ pub async fn fetch(
                     .unwrap_or_else(|_| Duration::default())
             })?;
             store_progress.done(format!("Stored {} crate versions to database", crate_versions_len));
+            let num_newly_published = newly_published_keys.len();
+            if newly_published_keys
+                .into_iter()
+                .map(|key| newly_published.announce(key))
+                .any(|announced| !announced)
+            {
+                log::info!(
+                    "Only some of {} newly published crate versions go ahead of the backlog, the others wait in it",
+                    num_newly_published
+                );
+            }
             Ok::<_, Error>(())
         }
     })
     .await??;
     Ok(())
 }
+
+#[cfg(test)]
+mod changes_test;
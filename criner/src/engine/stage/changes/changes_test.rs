@@ -0,0 +1,54 @@
+use super::newly_published_keys;
+use crate::registry::Registry;
+
+fn versions(name_and_versions: &[(&str, &str)]) -> Vec<(crates_index_diff::CrateVersion, String)> {
+    name_and_versions
+        .iter()
+        .map(|(name, version)| {
+            let line = format!(
+                r#"{{"name":"{}","vers":"{}","deps":[],"cksum":"{}","features":{{}},"yanked":false}}"#,
+                name,
+                version,
+                "0".repeat(64)
+            );
+            (serde_json::from_str(&line).unwrap(), line)
+        })
+        .collect()
+}
+
+#[test]
+fn all_changed_versions_are_newly_published_once_the_index_was_seen() {
+    let versions = versions(&[("a", "1.0.0"), ("a", "1.1.0"), ("b", "0.1.0")]);
+    assert_eq!(
+        newly_published_keys(&Registry::default(), &versions, false),
+        vec!["a:1.0.0", "a:1.1.0", "b:0.1.0"]
+    );
+}
+
+#[test]
+fn only_the_newest_version_of_each_crate_is_newly_published_when_fetching_from_scratch() {
+    let versions = versions(&[
+        ("a", "1.0.0"),
+        ("a", "1.1.0"),
+        ("b", "0.1.0"),
+        ("c", "2.0.0"),
+        ("c", "1.9.9"),
+    ]);
+    assert_eq!(
+        newly_published_keys(&Registry::default(), &versions, true),
+        vec!["a:1.1.0", "b:0.1.0", "c:1.9.9"],
+        "the last line of a crate in the index is the version published last, no matter its semantic version"
+    );
+}
+
+#[test]
+fn versions_of_other_registries_have_qualified_keys() {
+    let registry = Registry {
+        name: "acme".into(),
+        index_url: "https://example.com/index".into(),
+    };
+    assert_eq!(
+        newly_published_keys(&registry, &versions(&[("a", "1.0.0")]), true),
+        vec!["acme/a:1.0.0"]
+    );
+}
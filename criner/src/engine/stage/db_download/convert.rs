@@ -263,11 +263,11 @@ pub fn into_crates(
         let crate_id = krate.id;
         let mut krate: db_dump::Crate = krate.into();
         let mut versions: Vec<_> = std::mem::take(
-            &mut versions_by_crate_id
+            versions_by_crate_id
                 .get_mut(&crate_id)
                 .expect("at least one version per crate"),
         );
-        versions.sort_by_key(|v| parse_semver(&v.semver));
+        versions.sort_by_key(|v| parse_semver(&v.semver).ok());
         krate.versions = versions;
         crate_by_id.insert(crate_id, krate);
     }
@@ -337,5 +337,5 @@ pub fn into_crates(
 
     progress.done(format!("assigned {} owners", crate_owners_len));
 
-    crate_by_id.into_iter().map(|(_, v)| v).collect()
+    crate_by_id.into_values().collect()
 }
@@ -114,16 +114,18 @@ fn extract_and_ingest(db: Db, mut progress: prodash::tree::Item, db_file_path: P
         ByteSize(num_bytes_seen)
     ));
 
-    let users = users.ok_or(Error::Bug("expected users.csv in crates-io db dump"))?;
-    let teams = teams.ok_or(Error::Bug("expected teams.csv in crates-io db dump"))?;
-    let versions = versions.ok_or(Error::Bug("expected versions.csv in crates-io db dump"))?;
-    let crates = crates.ok_or(Error::Bug("expected crates.csv in crates-io db dump"))?;
-    let keywords = keywords.ok_or(Error::Bug("expected keywords.csv in crates-io db dump"))?;
-    let crates_keywords = crates_keywords.ok_or(Error::Bug("expected crates_keywords.csv in crates-io db dump"))?;
-    let categories = categories.ok_or(Error::Bug("expected categories.csv in crates-io db dump"))?;
-    let crates_categories =
-        crates_categories.ok_or(Error::Bug("expected crates_categories.csv in crates-io db dump"))?;
-    let crate_owners = crate_owners.ok_or(Error::Bug("expected crate_owners.csv in crates-io db dump"))?;
+    let users = users.ok_or_else(|| Error::Corrupt("expected users.csv in crates-io db dump".into()))?;
+    let teams = teams.ok_or_else(|| Error::Corrupt("expected teams.csv in crates-io db dump".into()))?;
+    let versions = versions.ok_or_else(|| Error::Corrupt("expected versions.csv in crates-io db dump".into()))?;
+    let crates = crates.ok_or_else(|| Error::Corrupt("expected crates.csv in crates-io db dump".into()))?;
+    let keywords = keywords.ok_or_else(|| Error::Corrupt("expected keywords.csv in crates-io db dump".into()))?;
+    let crates_keywords =
+        crates_keywords.ok_or_else(|| Error::Corrupt("expected crates_keywords.csv in crates-io db dump".into()))?;
+    let categories = categories.ok_or_else(|| Error::Corrupt("expected categories.csv in crates-io db dump".into()))?;
+    let crates_categories = crates_categories
+        .ok_or_else(|| Error::Corrupt("expected crates_categories.csv in crates-io db dump".into()))?;
+    let crate_owners =
+        crate_owners.ok_or_else(|| Error::Corrupt("expected crate_owners.csv in crates-io db dump".into()))?;
 
     progress.init(Some(4), Some("conversion steps".into()));
     progress.set_name("transform actors");
@@ -184,20 +186,22 @@ pub async fn schedule(
     assets_dir: PathBuf,
     mut progress: prodash::tree::Item,
     startup_time: std::time::SystemTime,
+    host_limits: work::iobound::HostLimits,
 ) -> Result<()> {
-    let (tx_result, rx_result) = async_channel::bounded(1);
+    let (tx_result, rx_result) = work::lanes::bounded(1);
     let tx_io = {
-        let (tx_io, rx) = async_channel::bounded(1);
-        let max_retries_on_timeout = 80;
+        let (tx_io, rx) = work::lanes::bounded(1);
+        let max_retries_on_transient_error = 80;
         crate::spawn(
             work::generic::processor(
                 db.clone(),
                 progress.add_child("↓ IDLE"),
                 rx,
-                work::iobound::Agent::new(&db, tx_result, {
-                    move |_, _, output_file_path| Some(output_file_path.to_path_buf())
+                // The dump is read from disk right after, so it never goes to another asset store
+                work::iobound::Agent::new(&db, host_limits, work::asset_store::local(), tx_result, {
+                    move |_, _, _, output_file_path| Some(output_file_path.to_path_buf())
                 })?,
-                max_retries_on_timeout,
+                max_retries_on_transient_error,
             )
             .map(|r| {
                 if let Err(e) = r {
@@ -230,13 +234,20 @@ pub async fn schedule(
         .unwrap_or(true)
     {
         tx_io
+            .lane(work::lanes::Priority::Low)
             .send(work::iobound::DownloadRequest {
                 output_file_path: db_file_path.clone(),
                 progress_name: "db dump".to_string(),
                 task_key,
+                task: work::iobound::default_persisted_download_task(),
                 crate_name_and_version: None,
                 kind: "tar.gz",
                 url: "https://static.crates.io/db-dump.tar.gz".to_string(),
+                fallback_url: None,
+                checksum: None,
+                is_gzip: true,
+                refresh: false,
+                priority: work::lanes::Priority::Low,
             })
             .await
             .map_err(Error::send_msg("Download Request"))?;
@@ -0,0 +1,178 @@
+//! Finding crate versions which are in the index of a registry but not in the database, as missed diffs or crashes
+//! at the wrong time can leave gaps that fetching changes alone never fills.
+use crate::{
+    allowlist::Allowlist,
+    engine::stage::changes,
+    error::{Error, Result},
+    model::{self, IndexCheck},
+    persistence::{self, key_value_iter, new_key_value_query_old_to_new, CrateTable, TableAccess},
+    registry::Registry,
+    utils::enforce_threaded,
+};
+use crates_index_diff::git2;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::Add,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+/// The amount of keys of missing crate versions to keep in the check result
+const MAX_LISTED_MISSING: usize = 100;
+
+/// Blobs of all crate files in the tree of `commit`, skipping files at the root like `config.json`
+fn crate_files(repo: &git2::Repository, commit: git2::Oid) -> std::result::Result<Vec<git2::Oid>, git2::Error> {
+    let tree = repo.find_commit(commit)?.tree()?;
+    let mut blobs = Vec::new();
+    tree.walk(git2::TreeWalkMode::PreOrder, |root, entry| {
+        if !root.is_empty() && entry.kind() == Some(git2::ObjectType::Blob) {
+            blobs.push(entry.id());
+        }
+        git2::TreeWalkResult::Ok
+    })?;
+    Ok(blobs)
+}
+
+fn check_blocking(
+    crates_io_path: &Path,
+    registry: &Registry,
+    db: &persistence::Db,
+    progress: &mut prodash::tree::Item,
+    repair: bool,
+    allowlist: Option<&Allowlist>,
+) -> Result<Option<IndexCheck>> {
+    // Never clone the index here, that's up to fetching changes
+    if git2::Repository::open(crates_io_path).is_err() {
+        return Ok(None);
+    }
+    let index = registry.open_index(crates_io_path)?;
+    let commit = match index.last_seen_reference().ok().and_then(|r| r.target()) {
+        Some(commit) => commit,
+        None => return Ok(None),
+    };
+
+    progress.blocked("loading crates", None);
+    let mut known: BTreeMap<String, BTreeSet<String>> = {
+        let connection = db.open_connection_no_async_with_busy_wait()?;
+        let mut statement = new_key_value_query_old_to_new(CrateTable::table_name(), &connection)?;
+        let crates = key_value_iter::<model::Crate>(&mut statement)?
+            .flat_map(Result::ok)
+            .filter(|(name, _)| registry.contains(name))
+            .map(|(name, krate)| {
+                let versions = krate
+                    .versions
+                    .into_iter()
+                    .filter(|version| allowlist.is_none_or(|a| a.contains(&name, version)))
+                    .collect();
+                (name, versions)
+            })
+            .collect();
+        crates
+    };
+
+    let repo = index.repository();
+    let blobs = crate_files(repo, commit)?;
+    progress.init(Some(blobs.len()), Some("crate files".into()));
+    let mut check = IndexCheck {
+        index_commit: commit.to_string(),
+        ..Default::default()
+    };
+    let mut missing = Vec::new();
+    for blob in blobs {
+        let blob = repo.find_blob(blob)?;
+        let mut crate_name = None;
+        for line in blob.content().split(|b| *b == b'\n').filter(|l| !l.is_empty()) {
+            let version: crates_index_diff::CrateVersion = match serde_json::from_slice(line) {
+                Ok(version) => version,
+                Err(_) => continue,
+            };
+            if allowlist.is_some_and(|a| !a.contains(&version.name, &version.version)) {
+                continue;
+            }
+            check.index_versions += 1;
+            let name = registry.qualify(&version.name).into_owned();
+            let is_known = known
+                .get_mut(&name)
+                .is_some_and(|versions| versions.remove(&version.version));
+            if !is_known {
+                check.num_missing += 1;
+                if check.missing.len() < MAX_LISTED_MISSING {
+                    let mut key = String::new();
+                    model::CrateVersion::key_from(&name, &version.version, &mut key);
+                    check.missing.push(key);
+                }
+                if repair {
+                    missing.push((version.clone(), String::from_utf8_lossy(line).trim_end().to_owned()));
+                }
+            }
+            crate_name.get_or_insert(name);
+        }
+        // All remaining versions of this crate are not in the index anymore
+        if let Some(versions) = crate_name.and_then(|name| known.remove(&name)) {
+            check.num_unknown_to_index += versions.len() as u64;
+        }
+        progress.inc();
+    }
+    check.num_unknown_to_index += known.values().map(|versions| versions.len() as u64).sum::<u64>();
+
+    if !missing.is_empty() {
+        let mut store_progress = progress.add_child("repairing gaps");
+        store_progress.init(Some(missing.len()), Some("crate versions".into()));
+        let (stored, _new_crates) = changes::store_blocking(db, registry, missing, &mut store_progress)?;
+        check.repaired = stored;
+        store_progress.done(format!("Added {} missing crate versions to the database", stored));
+    }
+    check.checked_at = SystemTime::now();
+    db.open_index_checks()?.record(progress, registry, &check)?;
+    Ok(Some(check))
+}
+
+/// Compare all crate versions in the index of `registry` as of the last fetch with those in the database, and record
+/// the result for `criner status`. If `repair` is true, missing crate versions are added to the database.
+/// With an `allowlist`, only the crate versions it contains are compared.
+pub async fn check(
+    crates_io_path: impl AsRef<Path>,
+    registry: Registry,
+    db: persistence::Db,
+    mut progress: prodash::tree::Item,
+    repair: bool,
+    deadline: Option<SystemTime>,
+    allowlist: Option<Allowlist>,
+) -> Result<()> {
+    let crates_io_path = crates_io_path.as_ref().to_path_buf();
+    enforce_threaded(
+        deadline.unwrap_or_else(|| SystemTime::now().add(Duration::from_secs(60 * 60))),
+        move || {
+            progress.set_name(format!("checking {} index for gaps", registry.name));
+            match check_blocking(
+                &crates_io_path,
+                &registry,
+                &db,
+                &mut progress,
+                repair,
+                allowlist.as_ref(),
+            )? {
+                None => progress.info("Skipped as the index was not fetched yet"),
+                Some(check) if check.num_missing > 0 => {
+                    log::warn!(
+                        "{} of {} crate versions in the {} index are missing in the database, {} were repaired",
+                        check.num_missing,
+                        check.index_versions,
+                        registry.name,
+                        check.repaired
+                    );
+                    progress.done(format!("Found {} missing crate versions", check.num_missing));
+                }
+                Some(check) => progress.done(format!(
+                    "All {} crate versions in the {} index are in the database",
+                    check.index_versions, registry.name
+                )),
+            }
+            Ok::<_, Error>(())
+        },
+    )
+    .await?
+}
+
+#[cfg(test)]
+mod index_check_test;
@@ -0,0 +1,99 @@
+use super::check_blocking;
+use crate::{
+    model::CrateVersion,
+    persistence::{temp_db::TempDb, Keyed, TableAccess},
+    registry::Registry,
+};
+use crates_index_diff::git2;
+use std::path::Path;
+
+fn index_line(name: &str, version: &str) -> String {
+    format!(
+        r#"{{"name":"{}","vers":"{}","deps":[],"cksum":"{}","features":{{}},"yanked":false}}"#,
+        name,
+        version,
+        "0".repeat(64)
+    )
+}
+
+/// A bare index clone at `path` whose last seen commit contains the given crate files
+fn index_repo(path: &Path, registry: &Registry, files: &[(&str, &str, &[&str])]) {
+    let repo = git2::Repository::init_bare(path).unwrap();
+    repo.remote("origin", &registry.index_url).unwrap();
+    let mut root = repo.treebuilder(None).unwrap();
+    root.insert("config.json", repo.blob(b"{}").unwrap(), 0o100644).unwrap();
+    for (dir, name, versions) in files {
+        let content: String = versions.iter().map(|v| index_line(name, v) + "\n").collect();
+        let mut subtree = repo.treebuilder(None).unwrap();
+        subtree
+            .insert(name, repo.blob(content.as_bytes()).unwrap(), 0o100644)
+            .unwrap();
+        root.insert(dir, subtree.write().unwrap(), 0o040000).unwrap();
+    }
+    let tree = repo.find_tree(root.write().unwrap()).unwrap();
+    let signature = git2::Signature::now("criner", "criner@localhost").unwrap();
+    repo.commit(
+        Some("refs/heads/crates-index-diff_last-seen"),
+        &signature,
+        &signature,
+        "index",
+        &tree,
+        &[],
+    )
+    .unwrap();
+}
+
+#[test]
+fn versions_missing_in_the_database_are_found_and_repaired() {
+    let db = TempDb::new("index-check");
+    let registry = Registry::default();
+    let index_path = db.dir.join("index.git");
+    let mut progress = prodash::Tree::new().add_child("index-check");
+    assert!(
+        check_blocking(&index_path, &registry, &db, &mut progress, false, None)
+            .unwrap()
+            .is_none(),
+        "indices which weren't fetched are never cloned"
+    );
+
+    index_repo(
+        &index_path,
+        &registry,
+        &[("1", "a", &["1.0.0", "1.1.0"]), ("2", "bc", &["0.1.0"])],
+    );
+    let crates = db.open_crates().unwrap();
+    for (name, version) in [("a", "1.0.0"), ("a", "0.9.0"), ("gone", "1.0.0")] {
+        let version = CrateVersion {
+            name: name.into(),
+            version: version.into(),
+            ..Default::default()
+        };
+        crates.upsert(&mut progress, &version.name, &version).unwrap();
+    }
+
+    let check = check_blocking(&index_path, &registry, &db, &mut progress, false, None)
+        .unwrap()
+        .expect("fetched index");
+    assert_eq!(check.index_versions, 3);
+    assert_eq!(check.num_missing, 2);
+    assert_eq!(check.missing, vec!["a:1.1.0", "bc:0.1.0"]);
+    assert_eq!(
+        check.num_unknown_to_index, 2,
+        "versions of crates in the index as well as of crates which aren't in there anymore"
+    );
+    assert_eq!(check.repaired, 0);
+    assert_eq!(db.open_index_checks().unwrap().count(), 1, "checks are recorded");
+
+    let check = check_blocking(&index_path, &registry, &db, &mut progress, true, None)
+        .unwrap()
+        .expect("fetched index");
+    assert_eq!(check.repaired, 2);
+    let crate_versions = db.open_crate_versions().unwrap();
+    for key in ["a:1.1.0", "bc:0.1.0"] {
+        assert_eq!(crate_versions.get(key).unwrap().map(|v| v.key()).as_deref(), Some(key));
+    }
+    let check = check_blocking(&index_path, &registry, &db, &mut progress, false, None)
+        .unwrap()
+        .expect("fetched index");
+    assert_eq!((check.num_missing, check.repaired), (0, 0), "gaps stay closed");
+}
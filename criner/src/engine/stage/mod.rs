@@ -1,5 +1,43 @@
 pub mod changes;
 pub mod db_download;
+pub mod index_check;
 pub mod processing;
+pub mod retention;
 
 pub mod report;
+
+/// A part of the mining pipeline which can be enabled on its own, i.e. to split the work between machines sharing a database
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    /// Fetch changes of the index, check it for gaps and digest the crates.io database
+    Fetch,
+    /// Download artifacts of crate versions
+    Download,
+    /// Extract downloaded crates, and reduce results of old versions
+    Extract,
+    /// Generate reports from extracted crates
+    Report,
+}
+
+impl Stage {
+    pub const ALL: &'static [Stage] = &[Stage::Fetch, Stage::Download, Stage::Extract, Stage::Report];
+}
+
+impl std::str::FromStr for Stage {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(match s {
+            "fetch" => Stage::Fetch,
+            "download" => Stage::Download,
+            "extract" => Stage::Extract,
+            "report" => Stage::Report,
+            _ => {
+                return Err(format!(
+                    "Invalid stage '{}', expected one of fetch, download, extract or report",
+                    s
+                ))
+            }
+        })
+    }
+}
@@ -1,36 +1,141 @@
+use crate::engine::work::lanes::Priority;
 use crate::persistence::{new_value_query_recent_first, value_iter, CrateVersionTable};
 use crate::{
+    allowlist::Allowlist,
     engine::work,
     error::Result,
     model::CrateVersion,
     persistence::{Db, Keyed, TableAccess},
+    registry::{self, Registry},
 };
 use futures_util::FutureExt;
-use std::{path::PathBuf, time::SystemTime};
+use std::{
+    collections::{HashMap, VecDeque},
+    path::PathBuf,
+    time::SystemTime,
+};
+
+/// Order versions so that every crate gets its turn before any crate gets another one, which keeps
+/// crates with hundreds of versions from monopolizing downloaders.
+/// Versions of the same crate, as well as crates among each other, keep their relative order.
+///
+/// Versions are scheduled in chunks read from the database one after another, and as all versions don't fit into memory
+/// at once the order is only fair within each chunk. A crate with many versions spread over several chunks thus still
+/// gets a turn in each of them, but crates of later chunks have to wait for the earlier chunks.
+fn round_robin_by_crate(versions: Vec<CrateVersion>) -> Vec<CrateVersion> {
+    let num_versions = versions.len();
+    let mut index_by_name = HashMap::new();
+    let mut queues: Vec<VecDeque<CrateVersion>> = Vec::new();
+    for version in versions {
+        let idx = *index_by_name.entry(version.name.clone()).or_insert_with(|| {
+            queues.push(VecDeque::new());
+            queues.len() - 1
+        });
+        queues[idx].push_back(version);
+    }
+
+    let mut queues: VecDeque<_> = queues.into();
+    let mut out = Vec::with_capacity(num_versions);
+    while let Some(mut queue) = queues.pop_front() {
+        if let Some(version) = queue.pop_front() {
+            out.push(version);
+            if !queue.is_empty() {
+                queues.push_back(queue);
+            }
+        }
+    }
+    out
+}
+
+/// What the crate versions of a registry are processed with
+pub struct Settings {
+    pub registry: Registry,
+    /// Where the index of `registry` was fetched to
+    pub index_path: PathBuf,
+    pub io_bound_processors: u32,
+    pub cpu_bound_processors: u32,
+    /// Where downloaded artifacts are stored
+    pub assets_dir: PathBuf,
+    /// Tasks which were in progress before this time were interrupted and are tried again
+    pub startup_time: SystemTime,
+    /// While paused, scheduling waits for no longer than until this time
+    pub deadline: Option<SystemTime>,
+    pub artifact_kinds: Vec<&'static work::artifact::Kind>,
+    /// Files matching it are stored along with manifests and the sources of targets
+    pub selection: globset::GlobSet,
+    /// The size in bytes up to which stored files are kept, unless it's the manifest or lock file
+    pub max_stored_entry_size: u64,
+    /// If set, crates are first extracted to record only the meta-data of their files, and contents are extracted in a
+    /// later run for the given amount of most recent versions of each crate only.
+    pub extract_contents_for_latest: Option<usize>,
+    /// Unless true, only crates which were downloaded already are extracted
+    pub downloads: bool,
+    /// Unless true, nothing is extracted
+    pub extract: bool,
+    /// Where crates are downloaded from if set, or from where the index says otherwise
+    pub download_url_template: Option<String>,
+    /// Where crates are downloaded from if what was downloaded doesn't match the checksum in the index
+    pub mirror_url_template: Option<String>,
+    /// When failed downloads and extractions are tried again
+    pub task_retry: work::retry::RetryPolicy,
+    /// If set, only the crate versions it contains are processed
+    pub allowlist: Option<Allowlist>,
+    /// Extractions needed by reports, which go ahead of everything
+    pub needed_extractions: work::cpubound::NeededExtractions,
+    /// Crate versions which were just published, which go ahead of all others we schedule
+    pub newly_published: work::schedule::NewlyPublished,
+}
 
+/// Download and extract all crate versions of a registry as `settings` say, with downloads limited by `host_limits`.
 pub async fn process(
     db: Db,
+    assets: work::asset_store::SharedAssetStore,
+    host_limits: work::iobound::HostLimits,
     mut progress: prodash::tree::Item,
-    io_bound_processors: u32,
-    cpu_bound_processors: u32,
     mut processing_progress: prodash::tree::Item,
-    assets_dir: PathBuf,
-    startup_time: SystemTime,
+    settings: Settings,
 ) -> Result<()> {
+    let Settings {
+        registry,
+        index_path,
+        io_bound_processors,
+        cpu_bound_processors,
+        assets_dir,
+        startup_time,
+        deadline,
+        artifact_kinds,
+        selection,
+        max_stored_entry_size,
+        extract_contents_for_latest,
+        downloads,
+        extract,
+        download_url_template,
+        mirror_url_template,
+        task_retry,
+        allowlist,
+        needed_extractions,
+        newly_published,
+    } = settings;
     processing_progress.set_name("Downloads and Extractors");
+    let first_pass = match extract_contents_for_latest {
+        Some(_) => work::cpubound::Extraction::MetaDataOnly,
+        None => work::cpubound::Extraction::Full,
+    };
     let tx_cpu = {
-        let (tx_cpu, rx) = async_channel::bounded(1);
+        let (tx_cpu, rx) = work::lanes::bounded(1);
         for idx in 0..cpu_bound_processors {
-            let max_retries_on_timeout = 0;
+            let max_retries_on_transient_error = 0;
             let db = db.clone();
             let assets_dir = assets_dir.clone();
+            let assets = assets.clone();
+            let selection = selection.clone();
             let progress = processing_progress.add_child(format!("{}:CPU IDLE", idx + 1));
             let rx = rx.clone();
             crate::spawn(blocking::unblock(move || -> Result<_> {
-                let agent = work::cpubound::Agent::new(assets_dir, &db)?;
+                let agent = work::cpubound::Agent::new(assets_dir, assets, &db, selection, max_stored_entry_size)?;
                 #[allow(clippy::unit_arg)] // don't know where the unit is supposed to be
                 Ok(futures_lite::future::block_on(
-                    work::generic::processor(db, progress, rx, agent, max_retries_on_timeout).map(|r| {
+                    work::generic::processor(db, progress, rx, agent, max_retries_on_transient_error).map(|r| {
                         if let Err(e) = r {
                             log::warn!("CPU bound processor failed: {}", e);
                         }
@@ -43,22 +148,32 @@ pub async fn process(
     };
 
     let tx_io = {
-        let (tx_io, rx) = async_channel::bounded(1);
+        let (tx_io, rx) = work::lanes::bounded(1);
         for idx in 0..io_bound_processors {
-            let max_retries_on_timeout = 40;
+            let max_retries_on_transient_error = 40;
             crate::spawn(
                 work::generic::processor(
                     db.clone(),
                     processing_progress.add_child(format!("{}: ↓ IDLE", idx + 1)),
                     rx.clone(),
-                    work::iobound::Agent::new(&db, tx_cpu.clone(), |crate_name_and_version, task, _| {
-                        crate_name_and_version.map(|(crate_name, crate_version)| work::cpubound::ExtractRequest {
-                            download_task: task.clone(),
-                            crate_name,
-                            crate_version,
-                        })
-                    })?,
-                    max_retries_on_timeout,
+                    work::iobound::Agent::new(
+                        &db,
+                        host_limits.clone(),
+                        assets.clone(),
+                        tx_cpu.clone(),
+                        move |crate_name_and_version, kind, task, _| {
+                            let extract = extract && matches!(work::artifact::by_name(kind), Some(k) if k.extract());
+                            crate_name_and_version
+                                .filter(|_| extract)
+                                .map(|(crate_name, crate_version)| work::cpubound::ExtractRequest {
+                                    download_task: task.clone(),
+                                    crate_name,
+                                    crate_version,
+                                    extraction: first_pass,
+                                })
+                        },
+                    )?,
+                    max_retries_on_transient_error,
                 )
                 .map(|r| {
                     if let Err(e) = r {
@@ -72,6 +187,11 @@ pub async fn process(
     };
 
     blocking::unblock(move || {
+        let download_url_template = match download_url_template {
+            Some(template) => Some(template),
+            None if registry.is_crates_io() => None,
+            None => Some(registry::download_url_template(&index_path)?),
+        };
         let versions = db.open_crate_versions()?;
         let num_versions = versions.count();
         progress.init(Some(num_versions as usize), Some("crate versions".into()));
@@ -92,30 +212,83 @@ pub async fn process(
                     fetched_versions,
                     auto_checkpoint_every,
                 )?;
-                let iter = value_iter::<CrateVersion>(&mut statement)?;
+                let chunk = value_iter::<CrateVersion>(&mut statement)?.collect::<Result<Vec<_>>>()?;
                 versions.clear();
-                versions.extend(iter);
+                // fair within this chunk only, see `round_robin_by_crate()`
+                versions.extend(round_robin_by_crate(chunk));
                 fetched_versions += versions.len();
 
                 versions.len() != auto_checkpoint_every
             };
 
             let tasks = db.open_tasks()?;
-            for (vid, version) in versions.drain(..).enumerate() {
-                let version = version?;
-
-                progress.set(vid + fetched_versions + 1);
+            let crates_io_crates = db.open_crates_io_crates()?;
+            let crates = db.open_crates()?;
+            let crate_versions = db.open_crate_versions()?;
+            let is_wanted = |version: &CrateVersion| -> bool {
+                registry.contains(&version.name)
+                    && allowlist
+                        .as_ref()
+                        .is_none_or(|a| a.contains(&version.name, &version.version))
+            };
+            let schedule = |version: &CrateVersion, priority: Priority, progress: &mut prodash::tree::Item| {
+                let extraction = match extract_contents_for_latest {
+                    _ if !extract => work::schedule::ExtractionPlan::Skip,
+                    None => work::schedule::ExtractionPlan::Full,
+                    Some(latest) => work::schedule::ExtractionPlan::MetaDataFirst {
+                        contents: crates.get(&version.name)?.is_some_and(|krate| {
+                            krate.versions.iter().rev().take(latest).any(|v| *v == version.version)
+                        }),
+                    },
+                };
                 progress.halted("wait for task consumers", None);
                 futures_lite::future::block_on(work::schedule::tasks(
                     &assets_dir,
                     &tasks,
-                    &version,
+                    &crates_io_crates,
+                    download_url_template.as_deref(),
+                    mirror_url_template.as_deref(),
+                    version,
+                    &artifact_kinds,
+                    downloads,
+                    extraction,
                     progress.add_child(format!("schedule {}", version.key())),
                     work::schedule::Scheduling::AtLeastOne,
                     &tx_io,
                     &tx_cpu,
+                    priority,
+                    &task_retry,
                     startup_time,
-                ))?;
+                ))
+                .map(drop)
+            };
+            for (vid, version) in versions.drain(..).enumerate() {
+                if !is_wanted(&version) {
+                    continue;
+                }
+                futures_lite::future::block_on(crate::utils::wait_while_paused(&mut progress, deadline))?;
+                progress.set(vid + fetched_versions + 1);
+                // Extractions needed by reports go ahead of everything we schedule ourselves
+                while let Some(need) = extract.then(|| needed_extractions.next()).flatten() {
+                    let key = format!("{}:{}", need.crate_name, need.crate_version);
+                    if futures_lite::future::block_on(work::schedule::needed_extraction(
+                        &assets_dir,
+                        &*assets,
+                        &tasks,
+                        need,
+                        startup_time,
+                        tx_cpu.lane(Priority::High),
+                    ))? {
+                        progress.info(format!("Extracting {} as a report needs it", key));
+                    }
+                }
+                // Versions which were just published go ahead of the backlog, which is mostly history
+                while let Some(key) = newly_published.next() {
+                    if let Some(newly_published) = crate_versions.get(&key)?.filter(is_wanted) {
+                        schedule(&newly_published, Priority::High, &mut progress)?;
+                    }
+                }
+                schedule(&version, Priority::Low, &mut progress)?;
             }
 
             // We have too many writers which cause the WAL to get so large that all reads are slowing to a crawl
@@ -139,3 +312,6 @@ pub async fn process(
     })
     .await
 }
+
+#[cfg(test)]
+mod processing_test;
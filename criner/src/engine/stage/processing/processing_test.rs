@@ -0,0 +1,51 @@
+use super::round_robin_by_crate;
+use crate::model::CrateVersion;
+
+fn versions(keys: &[(&str, &str)]) -> Vec<CrateVersion> {
+    keys.iter()
+        .map(|(name, version)| CrateVersion {
+            name: (*name).into(),
+            version: (*version).into(),
+            ..Default::default()
+        })
+        .collect()
+}
+
+fn keys(versions: Vec<CrateVersion>) -> Vec<String> {
+    versions
+        .into_iter()
+        .map(|v| format!("{}:{}", v.name, v.version))
+        .collect()
+}
+
+#[test]
+fn crates_take_turns_and_keep_the_order_of_their_versions() {
+    let input = versions(&[
+        ("big", "3.0.0"),
+        ("big", "2.0.0"),
+        ("big", "1.0.0"),
+        ("small", "1.0.0"),
+        ("big", "0.1.0"),
+        ("other", "2.0.0"),
+        ("other", "1.0.0"),
+    ]);
+    assert_eq!(
+        keys(round_robin_by_crate(input)),
+        [
+            "big:3.0.0",
+            "small:1.0.0",
+            "other:2.0.0",
+            "big:2.0.0",
+            "other:1.0.0",
+            "big:1.0.0",
+            "big:0.1.0",
+        ]
+    );
+}
+
+#[test]
+fn nothing_is_lost_or_added() {
+    assert!(round_robin_by_crate(Vec::new()).is_empty());
+    let input = versions(&[("a", "1.0.0"), ("a", "1.0.0"), ("b", "1.0.0")]);
+    assert_eq!(keys(round_robin_by_crate(input)), ["a:1.0.0", "b:1.0.0", "a:1.0.0"]);
+}
@@ -0,0 +1,167 @@
+//! Publishing reports atomically, so web servers never serve a partially generated site.
+//!
+//! Each report cycle writes into its own directory below `reports/cycles`, seeded with hard-links to all files of the
+//! published cycle, and `reports/current` is a symbolic link to the published cycle which is flipped once a cycle
+//! completes. Cycles which didn't complete are removed when the next one begins, along with the markers of the reports
+//! they wrote, so these are generated again. A few previously published cycles are kept as well.
+use crate::{
+    engine::report::{errors::ERRORS_FILE_NAME, generic::MARKERS_FILE_NAME},
+    persistence::Db,
+    Result,
+};
+use std::path::{Path, PathBuf};
+
+/// The symbolic link in the reports directory pointing to the published cycle
+pub const CURRENT: &str = "current";
+const CYCLES: &str = "cycles";
+/// The amount of published cycles to keep, including the current one, unless fewer are kept explicitly
+pub const KEEP_PUBLISHED: usize = 3;
+
+/// The name of the published cycle, if there is one
+fn published(reports_dir: &Path) -> Option<String> {
+    std::fs::read_link(reports_dir.join(CURRENT))
+        .ok()?
+        .file_name()?
+        .to_str()
+        .map(ToOwned::to_owned)
+}
+
+/// Remove all unpublished cycles and create the directory of a new one, seeded with the files of the published cycle.
+/// Without published cycle, it's seeded with the reports written into `reports_dir` directly by earlier versions.
+pub fn begin(db: &Db, reports_dir: &Path, progress: &mut prodash::tree::Item) -> Result<PathBuf> {
+    let cycles_dir = reports_dir.join(CYCLES);
+    std::fs::create_dir_all(&cycles_dir)?;
+    let published = published(reports_dir);
+    for entry in std::fs::read_dir(&cycles_dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        // Cycles sort by the time they began, and all cycles after the published one didn't complete
+        if published.as_ref().is_none_or(|published| name > *published) {
+            let num_markers = discard(db, &entry.path())?;
+            progress.info(format!(
+                "Discarded unpublished report cycle {} along with {} report marker(s)",
+                name, num_markers
+            ));
+        }
+    }
+
+    let name = time::OffsetDateTime::now_utc().format("%Y%m%dT%H%M%SZ");
+    let name = match published.as_ref() {
+        Some(published) if *published >= name => format!("{}-1", published),
+        _ => name,
+    };
+    let dir = cycles_dir.join(name);
+    std::fs::create_dir_all(&dir)?;
+    progress.blocked("linking published reports into new report cycle", None);
+    let (source, skipped): (PathBuf, &[&str]) = match published {
+        Some(published) => (cycles_dir.join(published), &[]),
+        None => (reports_dir.to_owned(), &[CYCLES, CURRENT]),
+    };
+    for entry in std::fs::read_dir(source)? {
+        let entry = entry?;
+        if skipped.iter().any(|name| entry.file_name() == *name) {
+            continue;
+        }
+        link_files(&entry.path(), &dir.join(entry.file_name()))?;
+    }
+    Ok(dir)
+}
+
+/// Make the cycle at `dir` the published one, keeping only the `KEEP_PUBLISHED` most recently published cycles
+pub fn publish(reports_dir: &Path, dir: &Path) -> Result<()> {
+    let name = dir.file_name().expect("cycle directory to have a name");
+    let tmp_link = reports_dir.join(format!("{}.tmp", CURRENT));
+    std::fs::remove_file(&tmp_link).ok();
+    std::os::unix::fs::symlink(Path::new(CYCLES).join(name), &tmp_link)?;
+    std::fs::rename(&tmp_link, reports_dir.join(CURRENT))?;
+
+    // The markers of published reports are never removed, so there is no need to remember them
+    for entry in std::fs::read_dir(dir)? {
+        let markers = entry?.path().join(MARKERS_FILE_NAME);
+        if markers.is_file() {
+            std::fs::remove_file(markers)?;
+        }
+    }
+    remove_superseded(reports_dir, KEEP_PUBLISHED)?;
+    Ok(())
+}
+
+/// The names of all cycles in `reports_dir` from the oldest to the most recent one, along with the name of the published one
+fn cycles(reports_dir: &Path) -> Result<(Vec<String>, Option<String>)> {
+    let mut names = Vec::new();
+    match std::fs::read_dir(reports_dir.join(CYCLES)) {
+        Ok(entries) => {
+            for entry in entries {
+                names.push(entry?.file_name().to_string_lossy().into_owned());
+            }
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err.into()),
+    }
+    names.sort();
+    Ok((names, published(reports_dir)))
+}
+
+/// Remove all but the `keep` most recently published cycles, always keeping the current one.
+/// Returns the amount of removed cycles.
+pub fn remove_superseded(reports_dir: &Path, keep: usize) -> Result<usize> {
+    let (names, published) = match cycles(reports_dir)? {
+        (names, Some(published)) => (names, published),
+        (_, None) => return Ok(0),
+    };
+    let published_cycles: Vec<_> = names.into_iter().filter(|name| *name <= published).collect();
+    let num_superseded = published_cycles.len().saturating_sub(keep.max(1));
+    for name in &published_cycles[..num_superseded] {
+        std::fs::remove_dir_all(reports_dir.join(CYCLES).join(name))?;
+    }
+    Ok(num_superseded)
+}
+
+/// The directories of the published cycle and of all cycles begun after it, which will be published if they complete
+pub fn current_and_upcoming(reports_dir: &Path) -> Result<Vec<PathBuf>> {
+    let (names, published) = cycles(reports_dir)?;
+    Ok(names
+        .into_iter()
+        .filter(|name| published.as_ref().is_none_or(|published| name >= published))
+        .map(|name| reports_dir.join(CYCLES).join(name))
+        .collect())
+}
+
+/// Remove the cycle at `dir` along with the markers of all reports written in it, returning the amount of removed markers
+fn discard(db: &Db, dir: &Path) -> Result<usize> {
+    let reports = db.open_reports()?;
+    let mut num_removed = 0;
+    for entry in std::fs::read_dir(dir)? {
+        let markers = entry?.path().join(MARKERS_FILE_NAME);
+        if markers.is_file() {
+            num_removed += reports.remove(std::fs::read_to_string(markers)?.lines())?;
+        }
+    }
+    std::fs::remove_dir_all(dir)?;
+    Ok(num_removed)
+}
+
+/// Hard-link all files below `source` to `destination`, skipping leftovers of interrupted writes as well as markers of
+/// written reports and their errors, which are appended to and only about the cycle they were written in
+fn link_files(source: &Path, destination: &Path) -> Result<()> {
+    if source.is_dir() {
+        std::fs::create_dir_all(destination)?;
+        for entry in std::fs::read_dir(source)? {
+            let entry = entry?;
+            link_files(&entry.path(), &destination.join(entry.file_name()))?;
+        }
+    } else if source
+        .file_name()
+        .is_some_and(|name| name != MARKERS_FILE_NAME && name != ERRORS_FILE_NAME)
+        && source.extension() != Some(std::ffi::OsStr::new("tmp"))
+    {
+        // hard-links are cheap, but only work on the same device
+        if std::fs::hard_link(source, destination).is_err() {
+            std::fs::copy(source, destination)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod cycle_test;
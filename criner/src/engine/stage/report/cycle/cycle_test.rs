@@ -0,0 +1,116 @@
+use super::{begin, publish, remove_superseded, CURRENT};
+use crate::{
+    engine::report::{errors::ERRORS_FILE_NAME, generic::MARKERS_FILE_NAME},
+    persistence::{self, temp_db::TempDb, ReportsTree},
+    utils::write_replacing,
+};
+
+fn mark_done(db: &persistence::Db, key: &str) {
+    db.open_connection_no_async_with_busy_wait()
+        .unwrap()
+        .execute(
+            &format!("INSERT INTO {} (key) VALUES (?1)", ReportsTree::table_name()),
+            [key],
+        )
+        .unwrap();
+}
+
+#[test]
+fn published_reports_are_never_changed_by_later_cycles() {
+    let db = TempDb::new("cycle-publish");
+    let dir = &db.dir;
+    let reports_dir = dir.join("reports");
+    std::fs::create_dir_all(reports_dir.join("waste").join("a")).unwrap();
+    std::fs::write(reports_dir.join("waste").join("a").join("1.0.0.html"), "legacy").unwrap();
+    let mut progress = prodash::Tree::new().add_child("reports");
+
+    let first = begin(&db, &reports_dir, &mut progress).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(first.join("waste").join("a").join("1.0.0.html")).unwrap(),
+        "legacy",
+        "reports written before there were cycles are picked up"
+    );
+    assert!(!reports_dir.join(CURRENT).exists(), "nothing is published yet");
+    publish(&reports_dir, &first).unwrap();
+    let current = reports_dir.join(CURRENT).join("waste").join("a");
+    assert_eq!(std::fs::read_to_string(current.join("1.0.0.html")).unwrap(), "legacy");
+
+    let second = begin(&db, &reports_dir, &mut progress).unwrap();
+    write_replacing(second.join("waste").join("a").join("1.0.0.html"), "new").unwrap();
+    write_replacing(second.join("waste").join("a").join("1.1.0.html"), "new").unwrap();
+    assert_eq!(
+        std::fs::read_to_string(current.join("1.0.0.html")).unwrap(),
+        "legacy",
+        "files are shared with the published cycle until they are replaced"
+    );
+    assert!(!current.join("1.1.0.html").exists());
+
+    publish(&reports_dir, &second).unwrap();
+    assert_eq!(std::fs::read_to_string(current.join("1.0.0.html")).unwrap(), "new");
+    assert_eq!(std::fs::read_to_string(current.join("1.1.0.html")).unwrap(), "new");
+    assert_eq!(
+        std::fs::read_to_string(first.join("waste").join("a").join("1.0.0.html")).unwrap(),
+        "legacy",
+        "previously published cycles are kept as they were"
+    );
+
+    assert_eq!(
+        remove_superseded(&reports_dir, 0).unwrap(),
+        1,
+        "the current cycle is always kept"
+    );
+    assert!(!first.exists());
+    assert!(second.exists());
+}
+
+#[test]
+fn errors_of_a_cycle_are_not_carried_over_to_the_next() {
+    let db = TempDb::new("cycle-errors");
+    let dir = &db.dir;
+    let reports_dir = dir.join("reports");
+    let mut progress = prodash::Tree::new().add_child("reports");
+
+    let first = begin(&db, &reports_dir, &mut progress).unwrap();
+    std::fs::create_dir_all(first.join("waste")).unwrap();
+    std::fs::write(first.join("waste").join(ERRORS_FILE_NAME), "a:1.0.0\tpanicked\n").unwrap();
+    publish(&reports_dir, &first).unwrap();
+
+    let second = begin(&db, &reports_dir, &mut progress).unwrap();
+    assert!(second.join("waste").exists());
+    assert!(
+        !second.join("waste").join(ERRORS_FILE_NAME).exists(),
+        "errors are appended to, which must not change the published cycle"
+    );
+    assert!(first.join("waste").join(ERRORS_FILE_NAME).exists());
+}
+
+#[test]
+fn unpublished_cycles_are_discarded_along_with_their_report_markers() {
+    let db = TempDb::new("cycle-discard");
+    let dir = &db.dir;
+    let reports_dir = dir.join("reports");
+    let mut progress = prodash::Tree::new().add_child("reports");
+    let published = begin(&db, &reports_dir, &mut progress).unwrap();
+    std::fs::create_dir_all(published.join("waste")).unwrap();
+    std::fs::write(published.join("waste").join(MARKERS_FILE_NAME), "a:1.0.0:waste:1.2.0\n").unwrap();
+    mark_done(&db, "a:1.0.0:waste:1.2.0");
+    publish(&reports_dir, &published).unwrap();
+
+    let failed = begin(&db, &reports_dir, &mut progress).unwrap();
+    assert!(
+        !failed.join("waste").join(MARKERS_FILE_NAME).exists(),
+        "markers of published cycles are forgotten"
+    );
+    std::fs::write(failed.join("waste").join(MARKERS_FILE_NAME), "b:1.0.0:waste:1.2.0\n").unwrap();
+    mark_done(&db, "b:1.0.0:waste:1.2.0");
+
+    let next = begin(&db, &reports_dir, &mut progress).unwrap();
+    assert!(!failed.exists() || failed == next, "the failed cycle is gone");
+    let reports = db.open_reports().unwrap();
+    assert!(
+        !reports.is_done("b:1.0.0:waste:1.2.0"),
+        "reports of the failed cycle are generated again"
+    );
+    assert!(reports.is_done("a:1.0.0:waste:1.2.0"));
+    assert!(published.exists(), "published cycles are kept");
+}
@@ -44,6 +44,7 @@ fn env_var(name: &str) -> Result<String> {
 pub fn select_callback(
     processors: u32,
     report_dir: &Path,
+    generated_at: Option<SystemTime>,
     mut progress: prodash::tree::Item,
 ) -> (
     WriteCallback,
@@ -126,8 +127,9 @@ pub fn select_callback(
                     {
                         progress.set(3);
                         progress.blocked("writing commit", None);
+                        let generated_at = generated_at.unwrap_or_else(SystemTime::now);
                         let current_time =
-                            git2::Time::new(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64, 0);
+                            git2::Time::new(generated_at.duration_since(UNIX_EPOCH)?.as_secs() as i64, 0);
                         let signature =
                             git2::Signature::new("Criner", "https://github.com/the-lean-crate/criner", &current_time)?;
                         let parent = repo
@@ -241,7 +243,7 @@ pub fn select_callback(
     }
 }
 
-pub fn repo_with_working_dir(req: WriteRequest, send: &WriteCallbackState) -> BoxFuture<Result<WriteInstruction>> {
+pub fn repo_with_working_dir(req: WriteRequest, send: &WriteCallbackState) -> BoxFuture<'_, Result<WriteInstruction>> {
     async move {
         send.as_ref()
             .expect("send to be available if a repo is available")
@@ -253,7 +255,7 @@ pub fn repo_with_working_dir(req: WriteRequest, send: &WriteCallbackState) -> Bo
     .boxed()
 }
 
-pub fn repo_bare(req: WriteRequest, send: &WriteCallbackState) -> BoxFuture<Result<WriteInstruction>> {
+pub fn repo_bare(req: WriteRequest, send: &WriteCallbackState) -> BoxFuture<'_, Result<WriteInstruction>> {
     async move {
         send.as_ref()
             .expect("send to be available if a repo is available")
@@ -265,6 +267,6 @@ pub fn repo_bare(req: WriteRequest, send: &WriteCallbackState) -> BoxFuture<Resu
     .boxed()
 }
 
-pub fn not_available(req: WriteRequest, _state: &WriteCallbackState) -> BoxFuture<Result<WriteInstruction>> {
+pub fn not_available(req: WriteRequest, _state: &WriteCallbackState) -> BoxFuture<'_, Result<WriteInstruction>> {
     async move { Ok(WriteInstruction::DoWrite(req)) }.boxed()
 }
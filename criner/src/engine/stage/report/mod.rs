@@ -1,28 +1,354 @@
 use crate::{
-    engine::report,
-    persistence::{self, new_key_value_query_old_to_new_filtered, TableAccess},
-    utils::check,
+    engine::{report, work::asset_store::SharedAssetStore},
+    persistence::{self, new_key_value_query_by_key_filtered, new_key_value_query_old_to_new_filtered, TableAccess},
+    utils::{check, write_replacing},
     {Error, Result},
 };
 use futures_util::FutureExt;
-use std::{path::PathBuf, time::SystemTime};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
 
+pub mod cycle;
 mod git;
 
+/// The directory of each report with the cached state of its crates
+pub(crate) const CACHE_DIR_NAME: &str = "__incremental_cache__";
+
+/// Remove `crate_name` from the cached states of all reports in the report cycle at `cycle_dir`, so all of its versions
+/// are reported once more without counting them twice. Returns the amount of reports which had a state of the crate.
+pub(crate) fn forget_crate(settings: &report::Settings, cycle_dir: &Path, crate_name: &str) -> Result<usize> {
+    fn forget<G: report::generic::Generator>(
+        settings: &report::Settings,
+        cycle_dir: &Path,
+        crate_name: &str,
+    ) -> Result<bool> {
+        <G::Report as report::generic::Aggregate>::forget_crate(
+            settings,
+            &cycle_dir.join(G::name()).join(CACHE_DIR_NAME),
+            crate_name,
+        )
+    }
+    let forgotten = [
+        forget::<report::waste::Generator>(settings, cycle_dir, crate_name)?,
+        forget::<report::features::Generator>(settings, cycle_dir, crate_name)?,
+        forget::<report::dependency_kinds::Generator>(settings, cycle_dir, crate_name)?,
+        forget::<report::targets::Generator>(settings, cycle_dir, crate_name)?,
+        forget::<report::embedded::Generator>(settings, cycle_dir, crate_name)?,
+        forget::<report::licenses::Generator>(settings, cycle_dir, crate_name)?,
+        forget::<report::semver_discipline::Generator>(settings, cycle_dir, crate_name)?,
+        forget::<report::abandonment::Generator>(settings, cycle_dir, crate_name)?,
+        forget::<report::release_cadence::Generator>(settings, cycle_dir, crate_name)?,
+    ];
+    Ok(forgotten.iter().filter(|forgotten| **forgotten).count())
+}
+
+/// The version of the generator of each report, by the name of the report
+pub(crate) fn generator_versions() -> BTreeMap<String, String> {
+    fn version<G: report::generic::Generator>() -> (String, String) {
+        (G::name().to_owned(), G::version().to_owned())
+    }
+    vec![
+        version::<report::waste::Generator>(),
+        version::<report::features::Generator>(),
+        version::<report::dependency_kinds::Generator>(),
+        version::<report::targets::Generator>(),
+        version::<report::embedded::Generator>(),
+        version::<report::licenses::Generator>(),
+        version::<report::semver_discipline::Generator>(),
+        version::<report::abandonment::Generator>(),
+        version::<report::release_cadence::Generator>(),
+    ]
+    .into_iter()
+    .collect()
+}
+
+/// Generate all reports into a new report cycle with `settings`, which is published once all of them are done. If they are
+/// deterministic, reports about the miner itself, like the failure heatmap and the integrity audit, are skipped.
+#[allow(clippy::too_many_arguments)]
 pub async fn generate(
     db: persistence::Db,
     mut progress: prodash::tree::Item,
     assets_dir: PathBuf,
+    assets: SharedAssetStore,
     glob: Option<String>,
     deadline: Option<SystemTime>,
     cpu_o_bound_processors: u32,
+    settings: report::Settings,
 ) -> Result<()> {
-    use report::generic::Generator;
-    let krates = db.open_crates()?;
-    let output_dir = assets_dir
+    // Reports of chunks of crates are merged in the order they finish, which is only the same in every run one at a time
+    let cpu_o_bound_processors = if settings.is_deterministic() {
+        1
+    } else {
+        cpu_o_bound_processors
+    };
+    let reports_dir = assets_dir
         .parent()
         .expect("assets directory to be in criner.db")
         .join("reports");
+    let (output_dir, mut progress) = blocking::unblock({
+        let (db, reports_dir) = (db.clone(), reports_dir.clone());
+        move || cycle::begin(&db, &reports_dir, &mut progress).map(|dir| (dir, progress))
+    })
+    .await?;
+    generate_report::<report::waste::Generator>(
+        settings.clone(),
+        db.clone(),
+        progress.add_child("waste report"),
+        &output_dir,
+        glob.clone(),
+        deadline,
+        cpu_o_bound_processors,
+    )
+    .await?;
+    generate_report::<report::features::Generator>(
+        settings.clone(),
+        db.clone(),
+        progress.add_child("features report"),
+        &output_dir,
+        glob.clone(),
+        deadline,
+        cpu_o_bound_processors,
+    )
+    .await?;
+    generate_report::<report::dependency_kinds::Generator>(
+        settings.clone(),
+        db.clone(),
+        progress.add_child("dependency kinds report"),
+        &output_dir,
+        glob.clone(),
+        deadline,
+        cpu_o_bound_processors,
+    )
+    .await?;
+    generate_report::<report::targets::Generator>(
+        settings.clone(),
+        db.clone(),
+        progress.add_child("target-specific dependencies report"),
+        &output_dir,
+        glob.clone(),
+        deadline,
+        cpu_o_bound_processors,
+    )
+    .await?;
+    generate_report::<report::embedded::Generator>(
+        settings.clone(),
+        db.clone(),
+        progress.add_child("embedded and wasm readiness report"),
+        &output_dir,
+        glob.clone(),
+        deadline,
+        cpu_o_bound_processors,
+    )
+    .await?;
+    generate_report::<report::licenses::Generator>(
+        settings.clone(),
+        db.clone(),
+        progress.add_child("license inventory"),
+        &output_dir,
+        glob.clone(),
+        deadline,
+        cpu_o_bound_processors,
+    )
+    .await?;
+    generate_report::<report::semver_discipline::Generator>(
+        settings.clone(),
+        db.clone(),
+        progress.add_child("semver discipline report"),
+        &output_dir,
+        glob.clone(),
+        deadline,
+        cpu_o_bound_processors,
+    )
+    .await?;
+    generate_report::<report::abandonment::Generator>(
+        settings.clone(),
+        db.clone(),
+        progress.add_child("abandonment risk report"),
+        &output_dir,
+        glob.clone(),
+        deadline,
+        cpu_o_bound_processors,
+    )
+    .await?;
+    generate_report::<report::release_cadence::Generator>(
+        settings.clone(),
+        db.clone(),
+        progress.add_child("release cadence report"),
+        &output_dir,
+        glob,
+        deadline,
+        cpu_o_bound_processors,
+    )
+    .await?;
+    generate_license_compatibility_report(
+        db.clone(),
+        progress.add_child("license compatibility report"),
+        &output_dir,
+        settings.generated_at(),
+    )
+    .await?;
+    generate_assets_report(
+        db.clone(),
+        progress.add_child("assets report"),
+        assets_dir.clone(),
+        assets.clone(),
+        &output_dir,
+        settings.generated_at(),
+    )
+    .await?;
+    if settings.is_deterministic() {
+        progress.info("Skipping the integrity audit and failure heatmap as reports are deterministic");
+    } else {
+        generate_integrity_report(
+            db.clone(),
+            progress.add_child("integrity audit"),
+            assets_dir,
+            assets,
+            &output_dir,
+            settings.generated_at(),
+        )
+        .await?;
+        generate_failures_report(
+            db,
+            progress.add_child("failure heatmap"),
+            &output_dir,
+            settings.generated_at(),
+        )
+        .await?;
+    }
+    let num_errors = generate_errors_report(
+        progress.add_child("report errors"),
+        &output_dir,
+        settings.generated_at(),
+    )
+    .await?;
+    blocking::unblock(move || cycle::publish(&reports_dir, &output_dir)).await?;
+    if num_errors == 0 {
+        progress.done("Generating reports done");
+    } else {
+        progress.done(format!(
+            "Generating reports done, but {} item(s) could not be reported, see errors/index.html",
+            num_errors
+        ));
+    }
+    Ok(())
+}
+
+async fn generate_assets_report(
+    db: persistence::Db,
+    mut progress: prodash::tree::Item,
+    assets_dir: PathBuf,
+    assets: SharedAssetStore,
+    output_dir: &Path,
+    generated_at: SystemTime,
+) -> Result<()> {
+    let report_dir = output_dir.join("assets");
+    blocking::unblock(move || {
+        let summary = report::assets::summarize(&assets_dir, &*assets, &db.open_tasks()?, &mut progress)?;
+        let mut out = Vec::new();
+        report::assets::write_html(&summary, generated_at, &mut out)?;
+        std::fs::create_dir_all(&report_dir)?;
+        write_replacing(report_dir.join("index.html"), out)?;
+        Ok(())
+    })
+    .await
+}
+
+async fn generate_license_compatibility_report(
+    db: persistence::Db,
+    mut progress: prodash::tree::Item,
+    output_dir: &Path,
+    generated_at: SystemTime,
+) -> Result<()> {
+    let licenses_cache_dir = output_dir
+        .join(<report::licenses::Generator as report::generic::Generator>::name())
+        .join(CACHE_DIR_NAME);
+    let report_dir = output_dir.join("license-compatibility");
+    blocking::unblock(move || {
+        let compatibility = report::license_compatibility::run(&db, &licenses_cache_dir, &mut progress)?;
+        report::license_compatibility::write_files(&compatibility, &report_dir, generated_at)
+    })
+    .await
+}
+
+async fn generate_integrity_report(
+    db: persistence::Db,
+    mut progress: prodash::tree::Item,
+    assets_dir: PathBuf,
+    assets: SharedAssetStore,
+    output_dir: &Path,
+    generated_at: SystemTime,
+) -> Result<()> {
+    let report_dir = output_dir.join("integrity");
+    blocking::unblock(move || {
+        let audit = report::integrity::run(&db, &assets_dir, &*assets, &mut progress)?;
+        let mut out = Vec::new();
+        report::integrity::write_html(&audit, generated_at, &mut out)?;
+        std::fs::create_dir_all(&report_dir)?;
+        write_replacing(report_dir.join("index.html"), out)?;
+        Ok(())
+    })
+    .await
+}
+
+async fn generate_failures_report(
+    db: persistence::Db,
+    mut progress: prodash::tree::Item,
+    output_dir: &Path,
+    generated_at: SystemTime,
+) -> Result<()> {
+    let report_dir = output_dir.join("failures");
+    blocking::unblock(move || {
+        let heatmap = report::failures::run(&db, generated_at, &mut progress)?;
+        let mut out = Vec::new();
+        report::failures::write_html(&heatmap, generated_at, &mut out)?;
+        std::fs::create_dir_all(&report_dir)?;
+        write_replacing(report_dir.join("index.html"), out)?;
+        Ok(())
+    })
+    .await
+}
+
+/// Write the page listing all items which couldn't be reported in the cycle at `output_dir`, returning their amount
+async fn generate_errors_report(
+    mut progress: prodash::tree::Item,
+    output_dir: &Path,
+    generated_at: SystemTime,
+) -> Result<usize> {
+    let output_dir = output_dir.to_owned();
+    blocking::unblock(move || {
+        let errors = report::errors::collect(&output_dir)?;
+        let mut out = Vec::new();
+        report::errors::write_html(&errors, generated_at, &mut out)?;
+        let report_dir = output_dir.join("errors");
+        std::fs::create_dir_all(&report_dir)?;
+        write_replacing(report_dir.join("index.html"), out)?;
+        if errors.is_empty() {
+            progress.done("All items were reported");
+        } else {
+            progress.fail(format!("{} item(s) could not be reported", errors.len()));
+        }
+        Ok(errors.len())
+    })
+    .await
+}
+
+async fn generate_report<G>(
+    settings: report::Settings,
+    db: persistence::Db,
+    mut progress: prodash::tree::Item,
+    output_dir: &Path,
+    glob: Option<String>,
+    deadline: Option<SystemTime>,
+    cpu_o_bound_processors: u32,
+) -> Result<()>
+where
+    G: report::generic::Generator + Send + Sync + 'static,
+{
+    let krates = db.open_crates()?;
     let glob_str = glob.as_deref();
     let num_crates = krates.count_filtered(glob_str) as usize;
     let chunk_size = 500.min(num_crates);
@@ -51,9 +377,9 @@ pub async fn generate(
         (tx_task, rx_result)
     };
 
-    let waste_report_dir = output_dir.join(report::waste::Generator::name());
+    let report_dir = output_dir.join(G::name());
     blocking::unblock({
-        let dir = waste_report_dir.clone();
+        let dir = report_dir.clone();
         move || std::fs::create_dir_all(dir)
     })
     .await?;
@@ -61,7 +387,7 @@ pub async fn generate(
     let (cache_dir, (git_handle, git_state, maybe_join_handle)) = match glob.as_ref() {
         Some(_) => (None, (git::not_available as WriteCallback, None, None)),
         None => {
-            let cd = waste_report_dir.join("__incremental_cache__");
+            let cd = report_dir.join(CACHE_DIR_NAME);
             blocking::unblock({
                 let cd = cd.clone();
                 move || std::fs::create_dir_all(cd)
@@ -69,15 +395,21 @@ pub async fn generate(
             .await?;
             (
                 Some(cd),
-                git::select_callback(cpu_o_bound_processors, &waste_report_dir, progress.add_child("git")),
+                git::select_callback(
+                    cpu_o_bound_processors,
+                    &report_dir,
+                    settings.generated_at,
+                    progress.add_child("git"),
+                ),
             )
         }
     };
     let merge_reports = crate::spawn({
         let mut merge_progress = progress.add_child("report aggregator");
         merge_progress.init(Some(num_crates / chunk_size), Some("Reports".into()));
-        report::waste::Generator::merge_reports(
-            waste_report_dir.clone(),
+        G::merge_reports(
+            settings.clone(),
+            report_dir.clone(),
             cache_dir.clone(),
             merge_progress,
             rx_result,
@@ -89,48 +421,67 @@ pub async fn generate(
     });
 
     let mut fetched_crates = 0;
-    let mut chunk = Vec::<(String, Vec<u8>)>::with_capacity(chunk_size as usize);
+    let mut chunk = Vec::<(String, Vec<u8>)>::with_capacity(chunk_size);
     let mut cid = 0;
     loop {
         let abort_loop = {
             progress.blocked("fetching chunk of crates to schedule", None);
             let connection = db.open_connection_no_async_with_busy_wait()?;
-            let mut statement = new_key_value_query_old_to_new_filtered(
+            let query = if settings.is_deterministic() {
+                new_key_value_query_by_key_filtered
+            } else {
+                new_key_value_query_old_to_new_filtered
+            };
+            let mut statement = query(
                 persistence::CrateTable::table_name(),
                 glob_str,
                 &connection,
-                Some((fetched_crates, chunk_size as usize)),
+                Some((fetched_crates, chunk_size)),
             )?;
 
             chunk.clear();
-            chunk.extend(
-                statement
-                    .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))?
-                    .filter_map(|r| r.ok()),
-            );
-            fetched_crates += chunk.len();
-
-            chunk.len() != chunk_size as usize
+            let mut num_rows = 0;
+            for row in statement.query_map(rusqlite::params_from_iter(glob_str), |r| Ok((r.get(0)?, r.get(1)?)))? {
+                num_rows += 1;
+                match row {
+                    Ok(row) => chunk.push(row),
+                    Err(err) => {
+                        // Without a key to go by, the crate is known by its position among all crates to report
+                        let key = format!("#{}", fetched_crates + num_rows);
+                        progress.fail(format!("crate {} could not be read: {}", key, err));
+                        report::errors::record(&report_dir, &key, err)?;
+                    }
+                }
+            }
+            fetched_crates += num_rows;
+
+            num_rows != chunk_size
         };
 
         cid += 1;
-        check(deadline)?;
+        // Stop in between chunks only, so the report cycle can be published with what was done until then
+        if check(deadline).is_err() {
+            progress.info("Stopping early as the deadline was reached");
+            break;
+        }
 
         progress.set(cid * chunk_size);
         progress.halted("write crate report", None);
         processors
-            .send(report::waste::Generator::write_files(
+            .send(G::write_files(
+                settings.clone(),
                 db.clone(),
-                waste_report_dir.clone(),
+                report_dir.clone(),
                 cache_dir.clone(),
                 chunk,
                 progress.add_child(""),
                 git_handle,
                 git_state.clone(),
+                deadline,
             ))
             .await
             .map_err(Error::send_msg("Chunk of files to write"))?;
-        chunk = Vec::with_capacity(chunk_size as usize);
+        chunk = Vec::with_capacity(chunk_size);
         if abort_loop {
             break;
         }
@@ -139,7 +490,7 @@ pub async fn generate(
     drop(processors);
     progress.set(num_crates);
     merge_reports.await;
-    progress.done("Generating and merging waste report done");
+    progress.done(format!("Generating and merging {} report done", G::name()));
 
     if let Some(handle) = maybe_join_handle {
         progress.blocked("waiting for git to finish", None);
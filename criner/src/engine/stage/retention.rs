@@ -0,0 +1,195 @@
+//! Compaction of results and assets of old crate versions, so that databases and assets don't grow without bound.
+use crate::{
+    engine::work::{
+        artifact,
+        asset_store::{AssetStore, SharedAssetStore},
+        cpubound, schedule,
+    },
+    error::Result,
+    model::{Crate, TaskResult, TaskState},
+    persistence::{self, delta, key_value_iter, new_key_value_query_old_to_new, CrateTable, TableAccess},
+    utils::wait_while_paused,
+};
+use bytesize::ByteSize;
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+#[derive(Default)]
+struct Freed {
+    results: usize,
+    files: usize,
+    bytes: u64,
+}
+
+fn remove_asset(assets: &dyn AssetStore, path: &Path, freed: &mut Freed) -> Result<()> {
+    let bytes = match assets.size(path)? {
+        Some(bytes) => bytes,
+        None => return Ok(()),
+    };
+    assets.remove(path)?;
+    freed.files += 1;
+    freed.bytes += bytes;
+    Ok(())
+}
+
+/// Make the result at `key` stand on its own if it is a delta against a version we are about to strip.
+fn materialize(
+    results: &persistence::TaskResultTable,
+    key: &str,
+    retained: &[String],
+    progress: &mut prodash::tree::Item,
+) -> Result<()> {
+    results.exclusively(progress, |transaction| {
+        let raw = match delta::get_raw(transaction, key)? {
+            Some(raw) => raw,
+            None => return Ok(()),
+        };
+        let base_is_retained = match &raw {
+            TaskResult::ExplodedCrateDelta { base_version, .. } => retained.contains(base_version),
+            _ => true,
+        };
+        if !base_is_retained {
+            let full = delta::resolve(transaction, key, raw)?;
+            delta::put(transaction, key, &full)?;
+        }
+        Ok(())
+    })
+}
+
+/// Replace the result at `key` with one which only keeps the meta-data of all entries.
+/// A result which was encoded against it since its version was looked at is stored in full first.
+fn strip(
+    results: &persistence::TaskResultTable,
+    key: &str,
+    progress: &mut prodash::tree::Item,
+    freed: &mut Freed,
+) -> Result<()> {
+    let stripped = results.exclusively(progress, |transaction| {
+        let raw = match delta::get_raw(transaction, key)? {
+            Some(raw) => raw,
+            None => return Ok(false),
+        };
+        let needs_stripping = match &raw {
+            TaskResult::ExplodedCrate { selected_entries, .. } => !selected_entries.is_empty(),
+            TaskResult::ExplodedCrateDelta { .. } => true,
+            TaskResult::None | TaskResult::Download { .. } | TaskResult::Manifest { .. } => false,
+        };
+        if !needs_stripping {
+            return Ok(false);
+        }
+        delta::materialize_dependents(transaction, key)?;
+        let entries_meta_data = match delta::get_partially(transaction, key, &|_| false)? {
+            Some(TaskResult::ExplodedCrate { entries_meta_data, .. }) => entries_meta_data,
+            _ => return Ok(false),
+        };
+        delta::put(
+            transaction,
+            key,
+            &TaskResult::ExplodedCrate {
+                entries_meta_data,
+                selected_entries: Vec::new(),
+            },
+        )?;
+        Ok(true)
+    })?;
+    if stripped {
+        freed.results += 1;
+    }
+    Ok(())
+}
+
+fn compact_blocking(
+    db: persistence::Db,
+    assets_dir: PathBuf,
+    assets: SharedAssetStore,
+    mut progress: prodash::tree::Item,
+    keep_full_results_for_latest: usize,
+    deadline: Option<SystemTime>,
+) -> Result<()> {
+    progress.blocked("loading all crates", None);
+    let crates = {
+        let connection = db.open_connection_no_async_with_busy_wait()?;
+        let mut statement = new_key_value_query_old_to_new(CrateTable::table_name(), &connection)?;
+        let crates = key_value_iter::<Crate>(&mut statement)?.collect::<Result<Vec<_>>>()?;
+        crates
+    };
+    let results = db.open_results()?;
+    let tasks = db.open_tasks()?;
+    let extraction_task = cpubound::default_persisted_extraction_task();
+    let keep = keep_full_results_for_latest.max(1);
+
+    progress.init(Some(crates.len()), Some("crates".into()));
+    let mut freed = Freed::default();
+    let mut key = String::new();
+    for (name, krate) in crates {
+        futures_lite::future::block_on(wait_while_paused(&mut progress, deadline))?;
+        progress.inc();
+        let num_old = krate.versions.len().saturating_sub(keep);
+        let retained = &krate.versions[num_old..];
+        // Go from newest to oldest so that deltas are always resolved against results we didn't touch yet.
+        for (idx, version) in krate.versions.iter().enumerate().rev() {
+            key.clear();
+            TaskResult::None.fq_key(&name, version, &extraction_task, &mut key);
+            let res = if idx >= num_old {
+                materialize(&results, &key, retained, &mut progress)
+            } else {
+                strip(&results, &key, &mut progress, &mut freed)
+            };
+            if let Err(err) = res {
+                progress.fail(format!("Could not compact result '{}': {}", key, err));
+                continue;
+            }
+            if idx >= num_old {
+                continue;
+            }
+
+            key.clear();
+            extraction_task.fq_key(&name, version, &mut key);
+            let extracted = matches!(tasks.get(&key)?, Some(t) if matches!(t.state, TaskState::Complete));
+            for kind in artifact::ALL.iter().filter(|k| extracted || !k.extract()) {
+                remove_asset(
+                    &*assets,
+                    &schedule::download_file_path(
+                        &assets_dir,
+                        &name,
+                        version,
+                        kind.task_process,
+                        kind.task_version,
+                        kind.name,
+                    ),
+                    &mut freed,
+                )?;
+            }
+        }
+    }
+    progress.done(format!(
+        "Stripped {} result(s) to meta-data only and removed {} file(s) with {}",
+        freed.results,
+        freed.files,
+        ByteSize(freed.bytes)
+    ));
+    Ok(())
+}
+
+/// Keep full results and assets only for the `keep_full_results_for_latest` most recent versions of each crate.
+/// Extraction results of older versions keep the meta-data of all entries, but not their content, and their downloaded
+/// artifacts are removed once they were extracted. Download tasks remain complete and won't be retried.
+/// While paused, compaction waits for no longer than until `deadline`.
+pub async fn compact(
+    db: persistence::Db,
+    assets_dir: PathBuf,
+    assets: SharedAssetStore,
+    progress: prodash::tree::Item,
+    keep_full_results_for_latest: usize,
+    deadline: Option<SystemTime>,
+) -> Result<()> {
+    blocking::unblock(move || {
+        compact_blocking(db, assets_dir, assets, progress, keep_full_results_for_latest, deadline)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod retention_test;
@@ -0,0 +1,206 @@
+use super::{compact_blocking, strip, Freed};
+use crate::{
+    engine::work::{artifact, asset_store, cpubound, schedule},
+    model::{CrateVersion, TaskResult, TaskState},
+    persistence::{delta, fixtures, temp_db::TempDb, TableAccess},
+};
+use std::path::{Path, PathBuf};
+
+const VERSIONS: &[&str] = &["1.0.0", "1.1.0", "1.2.0"];
+
+/// The extracted crate of `version`, which shares most files with the ones of other versions
+fn exploded_crate(version: &str) -> TaskResult {
+    let version_file = format!("src/v{}.rs", version);
+    fixtures::exploded_crate(&[
+        "Cargo.toml",
+        "README.md",
+        "src/lib.rs",
+        "src/a.rs",
+        "src/b.rs",
+        &version_file,
+    ])
+}
+
+fn result_key(version: &str) -> String {
+    let mut key = String::new();
+    TaskResult::None.fq_key("a", version, &cpubound::default_persisted_extraction_task(), &mut key);
+    key
+}
+
+fn asset(assets_dir: &Path, version: &str, kind: &artifact::Kind) -> PathBuf {
+    schedule::download_file_path(
+        assets_dir,
+        "a",
+        version,
+        kind.task_process,
+        kind.task_version,
+        kind.name,
+    )
+}
+
+/// A crate with all `VERSIONS` extracted, each but the first stored as delta against its predecessor
+fn extracted_crate(name: &str) -> TempDb {
+    let db = TempDb::new(name);
+    let mut progress = prodash::Tree::new().add_child("retention");
+    let crates = db.open_crates().unwrap();
+    let results = db.open_results().unwrap();
+    for version in VERSIONS {
+        let crate_version = CrateVersion {
+            name: "a".into(),
+            version: version.to_string(),
+            ..Default::default()
+        };
+        crates.upsert(&mut progress, "a", &crate_version).unwrap();
+        results
+            .insert(&mut progress, result_key(version), &exploded_crate(version))
+            .unwrap();
+    }
+    for version in &VERSIONS[1..] {
+        assert!(
+            matches!(
+                delta::get_raw(&results.connection().lock(), &result_key(version)).unwrap(),
+                Some(TaskResult::ExplodedCrateDelta { .. })
+            ),
+            "{} is stored as delta",
+            version
+        );
+    }
+    db
+}
+
+fn assert_full(db: &TempDb, version: &str) {
+    let result = db.open_results().unwrap().get(result_key(version)).unwrap();
+    assert_eq!(
+        result.map(|r| rmp_serde::to_vec(&r).unwrap()),
+        Some(rmp_serde::to_vec(&exploded_crate(version)).unwrap()),
+        "{} resolves to what was extracted",
+        version
+    );
+}
+
+fn assert_stripped(db: &TempDb, version: &str) {
+    match db.open_results().unwrap().get(result_key(version)).unwrap() {
+        Some(TaskResult::ExplodedCrate {
+            entries_meta_data,
+            selected_entries,
+        }) => {
+            assert!(selected_entries.is_empty(), "the content of {} is gone", version);
+            assert_eq!(
+                entries_meta_data,
+                match exploded_crate(version) {
+                    TaskResult::ExplodedCrate { entries_meta_data, .. } => entries_meta_data,
+                    _ => unreachable!("it's an exploded crate"),
+                },
+                "the meta-data of all entries of {} is kept",
+                version
+            );
+        }
+        _ => panic!("{} must remain an extracted crate", version),
+    }
+}
+
+#[test]
+fn retained_versions_resolve_after_their_base_was_stripped() {
+    let db = extracted_crate("retention-results");
+    compact_blocking(
+        db.db.clone(),
+        db.dir.join("assets"),
+        asset_store::local(),
+        prodash::Tree::new().add_child("compact"),
+        1,
+        None,
+    )
+    .unwrap();
+
+    let results = db.open_results().unwrap();
+    assert!(
+        matches!(
+            delta::get_raw(&results.connection().lock(), &result_key("1.2.0")).unwrap(),
+            Some(TaskResult::ExplodedCrate { .. })
+        ),
+        "the retained version doesn't depend on stripped versions anymore"
+    );
+    assert_full(&db, "1.2.0");
+    assert_stripped(&db, "1.1.0");
+    assert_stripped(&db, "1.0.0");
+}
+
+#[test]
+fn results_encoded_against_a_version_while_it_is_stripped_are_stored_in_full() {
+    let db = extracted_crate("retention-race");
+    let results = db.open_results().unwrap();
+    let mut freed = Freed::default();
+    // As if 1.1.0 was extracted again after retention looked at it, while stripping 1.0.0 which is its base
+    strip(
+        &results,
+        &result_key("1.0.0"),
+        &mut prodash::Tree::new().add_child("strip"),
+        &mut freed,
+    )
+    .unwrap();
+    assert_eq!(freed.results, 1);
+    assert_stripped(&db, "1.0.0");
+    assert_full(&db, "1.1.0");
+    assert!(
+        matches!(
+            delta::get_raw(&results.connection().lock(), &result_key("1.2.0")).unwrap(),
+            Some(TaskResult::ExplodedCrateDelta { .. })
+        ),
+        "only the result based on the stripped one is stored in full"
+    );
+    assert_full(&db, "1.2.0");
+}
+
+#[test]
+fn assets_of_versions_which_were_not_extracted_are_kept() {
+    let db = extracted_crate("retention-assets");
+    let assets_dir = db.dir.join("assets");
+    let mut progress = prodash::Tree::new().add_child("retention");
+    let tasks = db.open_tasks().unwrap();
+    let extraction_task = cpubound::default_persisted_extraction_task();
+    let mut key = String::new();
+    extraction_task.fq_key("a", "1.0.0", &mut key);
+    tasks
+        .insert(
+            &mut progress,
+            &key,
+            &crate::model::Task {
+                state: TaskState::Complete,
+                ..extraction_task
+            },
+        )
+        .unwrap();
+    for version in VERSIONS {
+        for kind in artifact::ALL {
+            let path = asset(&assets_dir, version, kind);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "content").unwrap();
+        }
+    }
+
+    compact_blocking(
+        db.db.clone(),
+        assets_dir.clone(),
+        asset_store::local(),
+        prodash::Tree::new().add_child("compact"),
+        1,
+        None,
+    )
+    .unwrap();
+
+    let exists = |version: &str, kind: &artifact::Kind| asset(&assets_dir, version, kind).is_file();
+    for kind in artifact::ALL {
+        assert!(
+            !exists("1.0.0", kind),
+            "{:?} of the extracted old version is removed",
+            kind
+        );
+        assert!(exists("1.2.0", kind), "{:?} of the retained version is kept", kind);
+        assert_eq!(
+            exists("1.1.0", kind),
+            kind.extract(),
+            "{:?} of the old version which wasn't extracted is kept only if it is still to be extracted",
+            kind
+        );
+    }
+}
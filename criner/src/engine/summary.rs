@@ -0,0 +1,209 @@
+//! A summary of a single run of the miner, printed and stored when it exits, with suggestions for configuring the
+//! next run derived from the bottlenecks observed in this one.
+use crate::{
+    engine::{
+        report::errors,
+        work::{cpubound, iobound},
+    },
+    model::RunSummary,
+    persistence::{self, TaskEventTable, KEY_SEP_CHAR},
+    Error, Result,
+};
+use bytesize::ByteSize;
+use rusqlite::params;
+use std::{
+    io::Write,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// The share of time processors have to be busy to be considered a bottleneck
+const BUSY_RATIO: f64 = 0.8;
+/// The share of failed tasks above which failures are worth looking into
+const FAILURE_RATIO: f64 = 0.1;
+/// The share of retried tasks above which hosts are likely to be overwhelmed
+const RETRY_RATIO: f64 = 0.1;
+
+/// Describe why a run ended, given the result of running the engine, interruptible by signals, and its `deadline`
+pub fn exit_reason(work_result: &Result<Result<()>>, deadline: Option<SystemTime>) -> String {
+    match work_result {
+        Err(Error::Interrupted) => "interrupted by signal".into(),
+        Err(Error::DeadlineExceeded(_)) | Ok(Err(Error::DeadlineExceeded(_))) => "reached the deadline".into(),
+        Err(err) | Ok(Err(err)) => format!("failed: {}", err),
+        Ok(Ok(())) if deadline.is_some_and(|deadline| SystemTime::now() >= deadline) => "reached the deadline".into(),
+        Ok(Ok(())) => "all stages finished".into(),
+    }
+}
+
+fn busy_ratio(busy: Duration, processors: u32, wallclock: Duration) -> f64 {
+    if processors == 0 || wallclock.as_secs_f64() == 0.0 {
+        return 0.0;
+    }
+    busy.as_secs_f64() / (processors as f64 * wallclock.as_secs_f64())
+}
+
+/// Derive hints for configuring the next run from `summary`, for a run with the given amount of processors
+pub fn suggestions(summary: &RunSummary, io_bound_processors: u32, cpu_bound_processors: u32) -> Vec<String> {
+    let extraction = cpubound::default_persisted_extraction_task().process;
+    let wallclock = summary
+        .finished_at
+        .duration_since(summary.started_at)
+        .unwrap_or_default();
+    let (cpu_busy, io_busy) = summary.busy_by_process.iter().fold(
+        (Duration::default(), Duration::default()),
+        |(cpu, io), (process, busy)| {
+            if *process == extraction {
+                (cpu + *busy, io)
+            } else {
+                (cpu, io + *busy)
+            }
+        },
+    );
+
+    let mut suggestions = Vec::new();
+    let io_ratio = busy_ratio(io_busy, io_bound_processors, wallclock);
+    if io_ratio >= BUSY_RATIO {
+        suggestions.push(format!(
+            "IO-bound processors were busy {:.0}% of the time, increase --io-bound-processors if the network allows it",
+            io_ratio * 100.0
+        ));
+    }
+    let cpu_ratio = busy_ratio(cpu_busy, cpu_bound_processors, wallclock);
+    if cpu_ratio >= BUSY_RATIO {
+        suggestions.push(format!(
+            "CPU-bound processors were busy {:.0}% of the time, increase --cpu-bound-processors if there are idle cores",
+            cpu_ratio * 100.0
+        ));
+    }
+    let tasks = summary.tasks_finished + summary.tasks_failed;
+    if tasks > 0 && summary.tasks_failed as f64 / tasks as f64 >= FAILURE_RATIO {
+        suggestions.push(format!(
+            "{} of {} tasks failed, see the failure heatmap of the reports or 'criner timeline' for why",
+            summary.tasks_failed, tasks
+        ));
+    }
+    if tasks > 0 && summary.tasks_retried as f64 / tasks as f64 >= RETRY_RATIO {
+        suggestions.push(format!(
+            "{} tasks were retried after transient errors, lower --max-connections-per-host or raise --crawl-delay",
+            summary.tasks_retried
+        ));
+    }
+    if summary.report_errors > 0 {
+        suggestions.push(format!(
+            "{} items could not be reported, see errors/index.html of the reports for which and why",
+            summary.report_errors
+        ));
+    }
+    suggestions
+}
+
+/// Summarize the run which started at `started_at` from the task events recorded since then
+pub fn collect(
+    db: &persistence::Db,
+    started_at: SystemTime,
+    db_size_at_start: u64,
+    exit_reason: String,
+    io_bound_processors: u32,
+    cpu_bound_processors: u32,
+) -> Result<RunSummary> {
+    let mut summary = RunSummary {
+        started_at,
+        finished_at: SystemTime::now(),
+        exit_reason,
+        bytes_downloaded: iobound::bytes_downloaded(),
+        db_size_at_start,
+        db_size_at_end: db.size_on_disk(),
+        report_errors: errors::num_errors(),
+        ..Default::default()
+    };
+
+    let connection = db.open_connection_no_async_with_busy_wait()?;
+    let mut statement = connection.prepare(&format!(
+        "SELECT key, event, duration_ms FROM {} WHERE occurred_at_ms >= ?1",
+        TaskEventTable::table_name()
+    ))?;
+    let since_ms = started_at.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as i64;
+    for row in statement.query_map(params![since_ms], |r| {
+        Ok((
+            r.get::<_, String>(0)?,
+            r.get::<_, String>(1)?,
+            r.get::<_, Option<i64>>(2)?,
+        ))
+    })? {
+        let (key, event, duration_ms) = row?;
+        match event.as_str() {
+            "finished" => summary.tasks_finished += 1,
+            "failed" => summary.tasks_failed += 1,
+            "retrying" => summary.tasks_retried += 1,
+            _ => continue,
+        }
+        // Task keys end with the process and its version
+        if let (Some(process), Some(duration_ms)) = (key.rsplit(KEY_SEP_CHAR).nth(1), duration_ms) {
+            *summary.busy_by_process.entry(process.to_owned()).or_default() +=
+                Duration::from_millis(duration_ms as u64);
+        }
+    }
+    summary.suggestions = suggestions(&summary, io_bound_processors, cpu_bound_processors);
+    Ok(summary)
+}
+
+/// Write `summary` in a human-readable form to `out`
+pub fn write(summary: &RunSummary, mut out: impl Write) -> Result<()> {
+    writeln!(
+        out,
+        "  from {} to {}, {}",
+        humantime::format_rfc3339_seconds(summary.started_at),
+        humantime::format_rfc3339_seconds(summary.finished_at),
+        summary.exit_reason
+    )?;
+    writeln!(out, "  tasks finished:   {}", summary.tasks_finished)?;
+    writeln!(out, "  tasks failed:     {}", summary.tasks_failed)?;
+    writeln!(out, "  tasks retried:    {}", summary.tasks_retried)?;
+    writeln!(out, "  report errors:    {}", summary.report_errors)?;
+    writeln!(out, "  downloaded:       {}", ByteSize(summary.bytes_downloaded))?;
+    writeln!(
+        out,
+        "  database growth:  {} (from {} to {})",
+        ByteSize(summary.db_size_at_end.saturating_sub(summary.db_size_at_start)),
+        ByteSize(summary.db_size_at_start),
+        ByteSize(summary.db_size_at_end)
+    )?;
+    for (process, busy) in summary.busy_by_process.iter() {
+        writeln!(
+            out,
+            "  busy with {}: {}",
+            process,
+            humantime::format_duration(Duration::from_secs(busy.as_secs()))
+        )?;
+    }
+    for suggestion in summary.suggestions.iter() {
+        writeln!(out, "  suggestion: {}", suggestion)?;
+    }
+    Ok(())
+}
+
+/// Summarize the run which started at `started_at`, store the summary in `db` and print it to stderr
+pub fn finish(
+    db: &persistence::Db,
+    started_at: SystemTime,
+    db_size_at_start: u64,
+    exit_reason: String,
+    io_bound_processors: u32,
+    cpu_bound_processors: u32,
+) -> Result<()> {
+    let summary = collect(
+        db,
+        started_at,
+        db_size_at_start,
+        exit_reason,
+        io_bound_processors,
+        cpu_bound_processors,
+    )?;
+    let mut progress = prodash::Tree::new().add_child("run summary");
+    db.open_run_summaries()?.record(&mut progress, &summary)?;
+    let mut out = std::io::stderr();
+    writeln!(out, "Run summary")?;
+    write(&summary, &mut out)
+}
+
+#[cfg(test)]
+mod summary_test;
@@ -0,0 +1,70 @@
+use super::{exit_reason, suggestions};
+use crate::{model::RunSummary, Error};
+use std::time::{Duration, SystemTime};
+
+fn run_of(wallclock: Duration, busy_by_process: &[(&str, Duration)]) -> RunSummary {
+    let started_at = SystemTime::now();
+    RunSummary {
+        started_at,
+        finished_at: started_at + wallclock,
+        tasks_finished: 100,
+        busy_by_process: busy_by_process
+            .iter()
+            .map(|(process, busy)| (process.to_string(), *busy))
+            .collect(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn saturated_processors_suggest_more_of_them() {
+    let hour = Duration::from_secs(60 * 60);
+    let run = run_of(hour, &[("download", hour * 10), ("extract_crate", hour)]);
+    let hints = suggestions(&run, 10, 4);
+    assert_eq!(hints.len(), 1);
+    assert!(hints[0].contains("--io-bound-processors"));
+
+    let run = run_of(hour, &[("download", hour), ("extract_crate", hour * 4)]);
+    let hints = suggestions(&run, 10, 4);
+    assert_eq!(hints.len(), 1);
+    assert!(hints[0].contains("--cpu-bound-processors"));
+}
+
+#[test]
+fn failures_and_retries_are_pointed_out() {
+    let mut run = run_of(Duration::from_secs(60), &[]);
+    assert!(suggestions(&run, 10, 4).is_empty());
+    run.tasks_failed = 20;
+    run.tasks_retried = 50;
+    let hints = suggestions(&run, 10, 4);
+    assert_eq!(hints.len(), 2);
+    assert!(hints[0].starts_with("20 of 120 tasks failed"));
+    assert!(hints[1].contains("--max-connections-per-host"));
+}
+
+#[test]
+fn items_which_could_not_be_reported_are_pointed_out() {
+    let mut run = run_of(Duration::from_secs(60), &[]);
+    run.report_errors = 3;
+    let hints = suggestions(&run, 10, 4);
+    assert_eq!(hints.len(), 1);
+    assert!(hints[0].starts_with("3 items could not be reported"));
+
+    let mut out = Vec::new();
+    super::write(&run, &mut out).unwrap();
+    assert!(String::from_utf8(out).unwrap().contains("report errors:    3"));
+}
+
+#[test]
+fn exit_reasons() {
+    assert_eq!(exit_reason(&Err(Error::Interrupted), None), "interrupted by signal");
+    assert_eq!(exit_reason(&Ok(Ok(())), None), "all stages finished");
+    assert_eq!(
+        exit_reason(&Ok(Ok(())), Some(SystemTime::now() - Duration::from_secs(1))),
+        "reached the deadline"
+    );
+    assert_eq!(
+        exit_reason(&Ok(Err(Error::Message("broken".into()))), None),
+        "failed: broken"
+    );
+}
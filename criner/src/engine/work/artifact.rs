@@ -0,0 +1,124 @@
+//! The kinds of files we can download for each crate version
+use crate::{
+    engine::work::task_graph,
+    model::{self, db_dump},
+};
+use std::time::Duration;
+
+/// A kind of artifact, which knows where to get it from and how its download is tracked
+pub struct Kind {
+    /// The name as used on the command-line, and as extension of the file on disk
+    pub name: &'static str,
+    /// The download task name, distinct per kind so that each is tracked and stored on its own
+    pub task_process: &'static str,
+    /// The version of the download task, to be increased to download all artifacts of this kind again
+    pub task_version: &'static str,
+    /// Returns the url to download the artifact from, or `None` if the crate version doesn't have one.
+    /// The crate as seen in the crates.io database dump is only provided if `needs_crates_io_crate` is set.
+    pub url: fn(&model::CrateVersion, Option<&db_dump::Crate>) -> Option<String>,
+    /// If true, the url builder needs the crate from the crates.io database dump
+    pub needs_crates_io_crate: bool,
+    /// If true, the artifact is verified against the checksum in the crates.io index
+    pub has_index_checksum: bool,
+    /// If true, the artifact is checked to be a gzip archive
+    pub is_gzip: bool,
+    /// If set, the artifact is downloaded again once its download is older than that, as it may change.
+    /// The download is conditional, so unchanged artifacts are not transferred again.
+    pub refresh_after: Option<Duration>,
+}
+
+impl Kind {
+    pub fn task(&self) -> model::Task {
+        model::Task {
+            stored_at: std::time::SystemTime::now(),
+            process: self.task_process.into(),
+            version: self.task_version.into(),
+            state: Default::default(),
+        }
+    }
+
+    /// Returns true if the artifact is extracted for further analysis once downloaded, as other tasks need its download
+    pub fn extract(&self) -> bool {
+        task_graph::by_process(self.task_process).is_some_and(|node| node.dependents().next().is_some())
+    }
+}
+
+impl std::fmt::Debug for Kind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name)
+    }
+}
+
+/// The crate archive as uploaded with `cargo publish`
+pub const CRATE: Kind = Kind {
+    name: "crate",
+    task_process: "download",
+    task_version: "1.0.0",
+    url: |v, _| {
+        Some(format!(
+            "https://crates.io/api/v1/crates/{name}/{version}/download",
+            name = v.name,
+            version = v.version
+        ))
+    },
+    needs_crates_io_crate: false,
+    has_index_checksum: true,
+    is_gzip: true,
+    refresh_after: None,
+};
+
+/// A tarball of the repository at the tag of the crate version, for crates hosted on GitHub and following
+/// the `v<version>` tagging convention.
+pub const REPO_TARBALL: Kind = Kind {
+    name: "repo.tar.gz",
+    task_process: "download-repo",
+    task_version: "1.0.0",
+    url: |v, krate| {
+        let repository = krate?.repository.as_deref()?;
+        let path = repository
+            .trim_end_matches('/')
+            .trim_end_matches(".git")
+            .strip_prefix("https://github.com/")?;
+        let mut components = path.split('/');
+        let (owner, repo) = (components.next()?, components.next()?);
+        Some(format!(
+            "https://codeload.github.com/{}/{}/tar.gz/refs/tags/v{}",
+            owner, repo, v.version
+        ))
+    },
+    needs_crates_io_crate: true,
+    has_index_checksum: false,
+    is_gzip: true,
+    refresh_after: None,
+};
+
+/// The docs.rs builds of the crate version, including their status
+pub const DOCS_BUILDS: Kind = Kind {
+    name: "docs-builds.json",
+    task_process: "download-docs-builds",
+    task_version: "1.0.0",
+    url: |v, _| Some(format!("https://docs.rs/crate/{}/{}/builds.json", v.name, v.version)),
+    needs_crates_io_crate: false,
+    has_index_checksum: false,
+    is_gzip: false,
+    // Builds are added when docs.rs rebuilds crates, and queued builds finish
+    refresh_after: Some(Duration::from_secs(7 * 24 * 60 * 60)),
+};
+
+/// All kinds of artifacts we know
+pub const ALL: &[&Kind] = &[&CRATE, &REPO_TARBALL, &DOCS_BUILDS];
+
+pub fn by_name(name: &str) -> Option<&'static Kind> {
+    ALL.iter().find(|k| k.name == name).copied()
+}
+
+/// Parse a kind by name, for use on the command-line
+pub fn parse(name: &str) -> Result<&'static Kind, String> {
+    by_name(name).ok_or_else(|| {
+        format!(
+            "Unknown artifact kind '{}', expected one of {}",
+            name,
+            ALL.iter().map(|k| k.name).collect::<Vec<_>>().join(", ")
+        )
+    })
+}
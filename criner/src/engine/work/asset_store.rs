@@ -0,0 +1,107 @@
+//! Where downloaded artifacts are kept once they are complete, on local disk or in object storage.
+//!
+//! Assets are addressed by their path within the local assets directory, as returned by `schedule::download_file_path()`.
+//! Partial downloads always stay on local disk, and are handed to the store once they are complete.
+use crate::{Error, Result};
+use std::{
+    io::{Read, Seek},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+mod s3;
+pub use s3::S3;
+
+pub trait ReadSeek: Read + Seek + Send {}
+impl<T: Read + Seek + Send> ReadSeek for T {}
+
+pub trait AssetStore: Send + Sync {
+    /// The size in bytes of the asset at `path`, or `None` if there is no such asset
+    fn size(&self, path: &Path) -> Result<Option<u64>>;
+    /// Open the asset at `path` for reading
+    fn open(&self, path: &Path) -> Result<Box<dyn ReadSeek>>;
+    /// Move the complete file at `local_file` into the store, making it the asset at `path`
+    fn put(&self, local_file: &Path, path: &Path) -> Result<()>;
+    /// Remove the asset at `path`, which is not an error if there is no such asset
+    fn remove(&self, path: &Path) -> Result<()>;
+    /// All files below `dir` along with their size, which are the assets in the store as well as the files which are
+    /// only on local disk, like partial downloads
+    fn list(&self, dir: &Path) -> Result<Vec<(PathBuf, u64)>>;
+}
+
+/// All files below `dir` on local disk along with their size, or none if `dir` doesn't exist
+fn walk(dir: &Path, files: &mut Vec<(PathBuf, u64)>) -> std::io::Result<()> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+    for entry in entries {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            walk(&entry.path(), files)?;
+        } else if file_type.is_file() {
+            files.push((entry.path(), entry.metadata()?.len()));
+        }
+    }
+    Ok(())
+}
+
+pub type SharedAssetStore = Arc<dyn AssetStore>;
+
+/// Assets are files in the local assets directory
+pub struct Local;
+
+impl AssetStore for Local {
+    fn size(&self, path: &Path) -> Result<Option<u64>> {
+        match std::fs::metadata(path) {
+            Ok(meta) => Ok(Some(meta.len())),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn open(&self, path: &Path) -> Result<Box<dyn ReadSeek>> {
+        Ok(Box::new(std::fs::File::open(path)?))
+    }
+
+    fn put(&self, local_file: &Path, path: &Path) -> Result<()> {
+        if local_file != path {
+            std::fs::rename(local_file, path)?;
+        }
+        Ok(())
+    }
+
+    fn remove(&self, path: &Path) -> Result<()> {
+        match std::fs::remove_file(path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn list(&self, dir: &Path) -> Result<Vec<(PathBuf, u64)>> {
+        let mut files = Vec::new();
+        walk(dir, &mut files)?;
+        Ok(files)
+    }
+}
+
+pub fn local() -> SharedAssetStore {
+    Arc::new(Local)
+}
+
+/// Create the store for `url`, which is either `None` for the local assets directory at `assets_dir`,
+/// or an `s3://bucket/prefix` URL. See `S3::from_env()` for how to configure credentials and endpoints.
+pub fn from_url(url: Option<&str>, assets_dir: &Path) -> Result<SharedAssetStore> {
+    match url {
+        None => Ok(local()),
+        Some(url) => match url.strip_prefix("s3://") {
+            Some(bucket_and_prefix) => Ok(Arc::new(S3::from_env(bucket_and_prefix, assets_dir)?)),
+            None => Err(Error::Message(format!(
+                "Unsupported asset store '{}', expected an URL like 's3://bucket/prefix'",
+                url
+            ))),
+        },
+    }
+}
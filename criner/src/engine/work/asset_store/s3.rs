@@ -0,0 +1,589 @@
+//! Storage of assets in S3 or compatible object stores, using requests signed with AWS signature version 4.
+use super::{walk, AssetStore, ReadSeek};
+use crate::{Error, Result};
+use sha2::{Digest, Sha256};
+use std::{
+    future::Future,
+    io::{Read, Seek, SeekFrom},
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
+
+/// Files larger than this are uploaded in parts of this size, which S3 requires to be at least 5MiB
+const PART_SIZE: u64 = 16 * 1024 * 1024;
+/// The amount of bytes fetched with each ranged request when reading assets
+const READ_BLOCK_SIZE: u64 = 8 * 1024 * 1024;
+/// We don't hash request bodies, which is fine as all requests go through TLS
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+pub(crate) struct Credentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+}
+
+struct Config {
+    /// Like `https://s3.eu-west-1.amazonaws.com`, without trailing slash
+    endpoint: String,
+    /// The host and port of `endpoint`
+    host: String,
+    region: String,
+    bucket: String,
+    /// Prepended to all object keys, without trailing slash
+    prefix: String,
+    /// The local assets directory, as paths of assets are within it
+    assets_dir: PathBuf,
+    credentials: Credentials,
+}
+
+/// An asset store keeping all assets as objects in a bucket, addressed with path-style requests.
+#[derive(Clone)]
+pub struct S3 {
+    config: Arc<Config>,
+    client: reqwest::Client,
+}
+
+fn env(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|v| !v.is_empty())
+}
+
+fn required_env(name: &str) -> Result<String> {
+    env(name).ok_or_else(|| Error::Message(format!("The '{}' environment variable must be set to use S3", name)))
+}
+
+/// Percent-encode `s` as required for canonical requests, keeping `/` if `keep_slash` is set.
+pub(crate) fn uri_encode(s: &str, keep_slash: bool) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => out.push(b as char),
+            b'/' if keep_slash => out.push('/'),
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
+
+fn canonical_query(params: &[(&str, &str)]) -> String {
+    let mut params: Vec<_> = params
+        .iter()
+        .map(|(k, v)| format!("{}={}", uri_encode(k, false), uri_encode(v, false)))
+        .collect();
+    params.sort();
+    params.join("&")
+}
+
+pub(crate) fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    const BLOCK_SIZE: usize = 64;
+    let mut block = [0u8; BLOCK_SIZE];
+    if key.len() > BLOCK_SIZE {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let mut inner = Sha256::new();
+    inner.update(block.iter().map(|b| b ^ 0x36).collect::<Vec<_>>());
+    inner.update(data);
+    let mut outer = Sha256::new();
+    outer.update(block.iter().map(|b| b ^ 0x5c).collect::<Vec<_>>());
+    outer.update(inner.finalize());
+    outer.finalize().to_vec()
+}
+
+/// The value of the `Authorization` header for a request with the given `headers`, all of which are signed.
+/// The payload hash is taken from the `x-amz-content-sha256` header.
+pub(crate) fn authorization(
+    credentials: &Credentials,
+    region: &str,
+    amz_date: &str,
+    method: &str,
+    canonical_uri: &str,
+    canonical_query: &str,
+    headers: &[(&str, &str)],
+) -> String {
+    let mut headers: Vec<_> = headers
+        .iter()
+        .map(|(name, value)| (name.to_ascii_lowercase(), value.trim()))
+        .collect();
+    headers.sort();
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value))
+        .collect();
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>()
+        .join(";");
+    let payload_hash = headers
+        .iter()
+        .find(|(name, _)| name == "x-amz-content-sha256")
+        .map_or(UNSIGNED_PAYLOAD, |(_, value)| value);
+    let canonical_request = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        method, canonical_uri, canonical_query, canonical_headers, signed_headers, payload_hash
+    );
+
+    let date = &amz_date[..8];
+    let scope = format!("{}/{}/s3/aws4_request", date, region);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    );
+    let key = hmac_sha256(
+        format!("AWS4{}", credentials.secret_access_key).as_bytes(),
+        date.as_bytes(),
+    );
+    let key = hmac_sha256(&key, region.as_bytes());
+    let key = hmac_sha256(&key, b"s3");
+    let key = hmac_sha256(&key, b"aws4_request");
+    format!(
+        "AWS4-HMAC-SHA256 Credential={}/{},SignedHeaders={},Signature={}",
+        credentials.access_key_id,
+        scope,
+        signed_headers,
+        hex::encode(hmac_sha256(&key, string_to_sign.as_bytes()))
+    )
+}
+
+/// Replace the entities of XML text with the characters they stand for
+fn xml_unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// The text of all `<tag>` elements in `xml`, in order
+fn xml_elements<'a>(xml: &'a str, tag: &str) -> Vec<&'a str> {
+    let (open, close) = (format!("<{}>", tag), format!("</{}>", tag));
+    xml.split(open.as_str())
+        .skip(1)
+        .filter_map(|s| s.split(close.as_str()).next())
+        .collect()
+}
+
+/// The keys and sizes of objects on one page of a listing, along with the token to get the next page if there is one
+type ObjectPage = (Vec<(String, u64)>, Option<String>);
+
+/// The objects in a `ListObjectsV2` response
+pub(crate) fn parse_object_list(xml: &str) -> Result<ObjectPage> {
+    let mut objects = Vec::new();
+    for contents in xml_elements(xml, "Contents") {
+        let key = xml_elements(contents, "Key").first().copied();
+        let size = xml_elements(contents, "Size")
+            .first()
+            .and_then(|s| s.trim().parse().ok());
+        match key.zip(size) {
+            Some((key, size)) => objects.push((xml_unescape(key), size)),
+            None => return Err(Error::Protocol(format!("Invalid object in S3 listing: {}", contents))),
+        }
+    }
+    let is_truncated = xml_elements(xml, "IsTruncated").first() == Some(&"true");
+    let next_token = xml_elements(xml, "NextContinuationToken")
+        .first()
+        .map(|token| xml_unescape(token));
+    match (is_truncated, next_token) {
+        (true, None) => Err(Error::Protocol(
+            "S3 listing is truncated, but has no continuation token".into(),
+        )),
+        (true, token) => Ok((objects, token)),
+        (false, _) => Ok((objects, None)),
+    }
+}
+
+/// Run `f` on our executor, for use by the blocking methods of the store
+fn block_on<T: Send + 'static>(f: impl Future<Output = Result<T>> + Send + 'static) -> Result<T> {
+    futures_lite::future::block_on(crate::spawn(f))
+}
+
+fn failed(what: &str, key: &str, status: http::StatusCode) -> Error {
+    Error::Storage(format!("S3 {} of '{}' failed with {}", what, key, status))
+}
+
+impl S3 {
+    /// Use the bucket and optional key prefix in `bucket_and_prefix`, like `bucket/criner/assets`, with the configuration
+    /// of the environment: `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and optionally `AWS_SESSION_TOKEN` for credentials,
+    /// `AWS_REGION` (defaulting to `us-east-1`) and `AWS_ENDPOINT_URL` for S3 compatible stores like MinIO.
+    pub fn from_env(bucket_and_prefix: &str, assets_dir: &Path) -> Result<S3> {
+        let mut tokens = bucket_and_prefix.trim_matches('/').splitn(2, '/');
+        let bucket = tokens
+            .next()
+            .filter(|b| !b.is_empty())
+            .ok_or_else(|| Error::Message("The S3 URL must name a bucket, like 's3://bucket/prefix'".into()))?
+            .to_owned();
+        let prefix = tokens.next().unwrap_or("").trim_matches('/').to_owned();
+        let region = env("AWS_REGION")
+            .or_else(|| env("AWS_DEFAULT_REGION"))
+            .unwrap_or_else(|| "us-east-1".into());
+        let endpoint = env("AWS_ENDPOINT_URL")
+            .unwrap_or_else(|| format!("https://s3.{}.amazonaws.com", region))
+            .trim_end_matches('/')
+            .to_owned();
+        let host = endpoint
+            .split_once("://")
+            .map(|(_, host)| host)
+            .ok_or_else(|| Error::Message(format!("'{}' is not a valid S3 endpoint URL", endpoint)))?
+            .to_owned();
+        Ok(S3 {
+            config: Arc::new(Config {
+                endpoint,
+                host,
+                region,
+                bucket,
+                prefix,
+                assets_dir: assets_dir.to_owned(),
+                credentials: Credentials {
+                    access_key_id: required_env("AWS_ACCESS_KEY_ID")?,
+                    secret_access_key: required_env("AWS_SECRET_ACCESS_KEY")?,
+                    session_token: env("AWS_SESSION_TOKEN"),
+                },
+            }),
+            client: reqwest::ClientBuilder::new()
+                .user_agent(concat!("criner/", env!("CARGO_PKG_VERSION")))
+                .build()?,
+        })
+    }
+
+    /// The object key of the asset at `path` within the assets directory
+    fn object_key(&self, path: &Path) -> Result<String> {
+        let relative = path
+            .strip_prefix(&self.config.assets_dir)
+            .map_err(|_| Error::Bug("Assets must be within the assets directory to be stored in S3"))?;
+        let mut key = self.config.prefix.clone();
+        for component in relative.components() {
+            if let Component::Normal(name) = component {
+                if !key.is_empty() {
+                    key.push('/');
+                }
+                key.push_str(&name.to_string_lossy());
+            }
+        }
+        Ok(key)
+    }
+
+    async fn send(
+        self,
+        method: reqwest::Method,
+        key: String,
+        query: Vec<(&'static str, String)>,
+        range: Option<(u64, u64)>,
+        body: Vec<u8>,
+    ) -> Result<reqwest::Response> {
+        let config = &self.config;
+        let canonical_uri = format!("/{}/{}", uri_encode(&config.bucket, false), uri_encode(&key, true));
+        let query = canonical_query(&query.iter().map(|(k, v)| (*k, v.as_str())).collect::<Vec<_>>());
+        let amz_date = time::OffsetDateTime::now_utc().format("%Y%m%dT%H%M%SZ");
+
+        let mut headers = vec![
+            ("host", config.host.as_str()),
+            ("x-amz-content-sha256", UNSIGNED_PAYLOAD),
+            ("x-amz-date", amz_date.as_str()),
+        ];
+        if let Some(token) = config.credentials.session_token.as_deref() {
+            headers.push(("x-amz-security-token", token));
+        }
+        let authorization = authorization(
+            &config.credentials,
+            &config.region,
+            &amz_date,
+            method.as_str(),
+            &canonical_uri,
+            &query,
+            &headers,
+        );
+
+        let url = if query.is_empty() {
+            format!("{}{}", config.endpoint, canonical_uri)
+        } else {
+            format!("{}{}?{}", config.endpoint, canonical_uri, query)
+        };
+        let mut request = self
+            .client
+            .request(method, &url)
+            .header(http::header::AUTHORIZATION, authorization);
+        for (name, value) in headers.into_iter().filter(|(name, _)| *name != "host") {
+            request = request.header(name, value);
+        }
+        if let Some((first, last)) = range {
+            request = request.header(http::header::RANGE, format!("bytes={}-{}", first, last));
+        }
+        Ok(request.body(body).send().await?)
+    }
+
+    /// The object keys below `prefix` along with their size, a page at a time
+    fn list_objects(&self, prefix: &str) -> Result<Vec<(String, u64)>> {
+        let mut objects = Vec::new();
+        let mut continuation_token = None;
+        loop {
+            let mut query = vec![("list-type", "2".to_owned()), ("prefix", prefix.to_owned())];
+            if let Some(token) = continuation_token.take() {
+                query.push(("continuation-token", token));
+            }
+            let this = self.clone();
+            let xml = block_on(async move {
+                let response = this
+                    .send(reqwest::Method::GET, String::new(), query, None, Vec::new())
+                    .await?;
+                if !response.status().is_success() {
+                    return Err(failed("listing", "", response.status()));
+                }
+                Ok(response.text().await?)
+            })?;
+            let (page, next) = parse_object_list(&xml)?;
+            objects.extend(page);
+            match next {
+                Some(token) => continuation_token = Some(token),
+                None => break Ok(objects),
+            }
+        }
+    }
+
+    /// Fetch bytes `first..=last` of the object at `key`
+    fn get_range(&self, key: &str, first: u64, last: u64) -> Result<Vec<u8>> {
+        let (this, key) = (self.clone(), key.to_owned());
+        block_on(async move {
+            let response = this
+                .send(
+                    reqwest::Method::GET,
+                    key.clone(),
+                    Vec::new(),
+                    Some((first, last)),
+                    Vec::new(),
+                )
+                .await?;
+            if !response.status().is_success() {
+                return Err(failed("ranged read", &key, response.status()));
+            }
+            Ok(response.bytes().await?.to_vec())
+        })
+    }
+
+    fn put_object(
+        &self,
+        key: &str,
+        query: Vec<(&'static str, String)>,
+        body: Vec<u8>,
+    ) -> Result<reqwest::header::HeaderMap> {
+        let (this, key) = (self.clone(), key.to_owned());
+        block_on(async move {
+            let response = this.send(reqwest::Method::PUT, key.clone(), query, None, body).await?;
+            if !response.status().is_success() {
+                return Err(failed("upload", &key, response.status()));
+            }
+            Ok(response.headers().clone())
+        })
+    }
+
+    fn post(&self, key: &str, query: Vec<(&'static str, String)>, body: Vec<u8>) -> Result<String> {
+        let (this, key) = (self.clone(), key.to_owned());
+        block_on(async move {
+            let response = this.send(reqwest::Method::POST, key.clone(), query, None, body).await?;
+            if !response.status().is_success() {
+                return Err(failed("multipart upload", &key, response.status()));
+            }
+            Ok(response.text().await?)
+        })
+    }
+
+    fn upload_parts(&self, key: &str, upload_id: &str, file: &mut std::fs::File) -> Result<()> {
+        let mut parts = String::new();
+        for part_number in 1.. {
+            let mut part = Vec::with_capacity(PART_SIZE as usize);
+            file.by_ref().take(PART_SIZE).read_to_end(&mut part)?;
+            if part.is_empty() {
+                break;
+            }
+            let headers = self.put_object(
+                key,
+                vec![
+                    ("partNumber", part_number.to_string()),
+                    ("uploadId", upload_id.to_owned()),
+                ],
+                part,
+            )?;
+            let etag = headers
+                .get(http::header::ETAG)
+                .and_then(|v| v.to_str().ok())
+                .ok_or(Error::InvalidHeader("expected an ETag for each uploaded part"))?;
+            parts.push_str(&format!(
+                "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+                part_number, etag
+            ));
+        }
+        self.post(
+            key,
+            vec![("uploadId", upload_id.to_owned())],
+            format!("<CompleteMultipartUpload>{}</CompleteMultipartUpload>", parts).into_bytes(),
+        )?;
+        Ok(())
+    }
+
+    /// Upload the file at `local_file` in parts, so that it never has to be in memory completely
+    fn multipart_upload(&self, key: &str, local_file: &Path) -> Result<()> {
+        let response = self.post(key, vec![("uploads", String::new())], Vec::new())?;
+        let upload_id = response
+            .split("<UploadId>")
+            .nth(1)
+            .and_then(|s| s.split("</UploadId>").next())
+            .ok_or_else(|| Error::Protocol(format!("No upload id in response to starting upload of '{}'", key)))?
+            .to_owned();
+        let res = self.upload_parts(key, &upload_id, &mut std::fs::File::open(local_file)?);
+        if res.is_err() {
+            let (this, key) = (self.clone(), key.to_owned());
+            // Without aborting, uploaded parts would be kept and billed for
+            block_on(async move {
+                this.send(
+                    reqwest::Method::DELETE,
+                    key,
+                    vec![("uploadId", upload_id)],
+                    None,
+                    Vec::new(),
+                )
+                .await
+            })
+            .ok();
+        }
+        res
+    }
+}
+
+impl AssetStore for S3 {
+    fn size(&self, path: &Path) -> Result<Option<u64>> {
+        let (this, key) = (self.clone(), self.object_key(path)?);
+        block_on(async move {
+            let response = this
+                .send(reqwest::Method::HEAD, key.clone(), Vec::new(), None, Vec::new())
+                .await?;
+            match response.status() {
+                http::StatusCode::NOT_FOUND => Ok(None),
+                status if status.is_success() => Ok(response
+                    .headers()
+                    .get(http::header::CONTENT_LENGTH)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.parse().ok())),
+                status => Err(failed("lookup", &key, status)),
+            }
+        })
+    }
+
+    fn open(&self, path: &Path) -> Result<Box<dyn ReadSeek>> {
+        let len = self.size(path)?.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("'{}' is not in the S3 asset store", path.display()),
+            )
+        })?;
+        Ok(Box::new(RangedReader {
+            store: self.clone(),
+            key: self.object_key(path)?,
+            len,
+            pos: 0,
+            block: Vec::new(),
+            block_start: 0,
+        }))
+    }
+
+    fn put(&self, local_file: &Path, path: &Path) -> Result<()> {
+        let key = self.object_key(path)?;
+        if std::fs::metadata(local_file)?.len() > PART_SIZE {
+            self.multipart_upload(&key, local_file)?;
+        } else {
+            self.put_object(&key, Vec::new(), std::fs::read(local_file)?)?;
+        }
+        std::fs::remove_file(local_file)?;
+        Ok(())
+    }
+
+    fn remove(&self, path: &Path) -> Result<()> {
+        let (this, key) = (self.clone(), self.object_key(path)?);
+        block_on(async move {
+            let response = this
+                .send(reqwest::Method::DELETE, key.clone(), Vec::new(), None, Vec::new())
+                .await?;
+            match response.status() {
+                status if status.is_success() || status == http::StatusCode::NOT_FOUND => Ok(()),
+                status => Err(failed("removal", &key, status)),
+            }
+        })
+    }
+
+    fn list(&self, dir: &Path) -> Result<Vec<(PathBuf, u64)>> {
+        let mut files = Vec::new();
+        walk(dir, &mut files)?;
+        let prefix = match self.object_key(dir)? {
+            key if key.is_empty() => key,
+            key => key + "/",
+        };
+        let key_prefix = match self.config.prefix.as_str() {
+            "" => String::new(),
+            prefix => format!("{}/", prefix),
+        };
+        for (key, size) in self.list_objects(&prefix)? {
+            if let Some(relative) = key.strip_prefix(key_prefix.as_str()) {
+                files.push((self.config.assets_dir.join(relative), size));
+            }
+        }
+        Ok(files)
+    }
+}
+
+/// Reads an object in blocks fetched with ranged requests, so that large assets are never in memory completely.
+struct RangedReader {
+    store: S3,
+    key: String,
+    len: u64,
+    pos: u64,
+    block: Vec<u8>,
+    block_start: u64,
+}
+
+impl Read for RangedReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.pos >= self.len || buf.is_empty() {
+            return Ok(0);
+        }
+        let block_end = self.block_start + self.block.len() as u64;
+        if self.pos < self.block_start || self.pos >= block_end {
+            let last = (self.pos + READ_BLOCK_SIZE).min(self.len) - 1;
+            self.block = self
+                .store
+                .get_range(&self.key, self.pos, last)
+                .map_err(|err| std::io::Error::other(err.to_string()))?;
+            self.block_start = self.pos;
+            if self.block.is_empty() {
+                return Ok(0);
+            }
+        }
+        let offset = (self.pos - self.block_start) as usize;
+        let amount = buf.len().min(self.block.len() - offset);
+        buf[..amount].copy_from_slice(&self.block[offset..offset + amount]);
+        self.pos += amount as u64;
+        Ok(amount)
+    }
+}
+
+impl Seek for RangedReader {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::End(delta) => (self.len as i64)
+                .checked_add(delta)
+                .filter(|p| *p >= 0)
+                .map(|p| p as u64),
+            SeekFrom::Current(delta) => (self.pos as i64)
+                .checked_add(delta)
+                .filter(|p| *p >= 0)
+                .map(|p| p as u64),
+        };
+        self.pos = new_pos
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "seek to a negative position"))?;
+        Ok(self.pos)
+    }
+}
+
+#[cfg(test)]
+mod s3_test;
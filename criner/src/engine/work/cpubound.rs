@@ -1,30 +1,83 @@
-use crate::engine::report::waste::{tar_path_to_utf8_str, CargoConfig};
+use crate::engine::report::waste::{tar_path_to_utf8, CargoConfig};
+use crate::engine::work::asset_store::{ReadSeek, SharedAssetStore};
+use crate::engine::work::task_graph;
 use crate::{error::Result, model, persistence, Error};
 use async_trait::async_trait;
 use std::io::Seek;
-use std::{fs::File, io::BufReader, io::Read, path::PathBuf, time::SystemTime};
+use std::{io::BufReader, io::Read, path::PathBuf, time::SystemTime};
 
 struct ProcessingState {
     downloaded_crate: PathBuf,
     key: String,
+    manifest_key: String,
+    extraction: Extraction,
 }
 pub struct Agent {
     asset_dir: PathBuf,
+    assets: SharedAssetStore,
     results: persistence::TaskResultTable,
     state: Option<ProcessingState>,
-    standard_bin_path: globset::GlobMatcher,
+    standard_paths: globset::GlobSet,
+    selection: globset::GlobSet,
+    max_stored_entry_size: u64,
+}
+
+/// The amount of bytes stored of selected files by default, larger ones are truncated
+pub const DEFAULT_MAX_STORED_ENTRY_SIZE: u64 = 128 * 1024;
+
+/// Parse a size like `256KiB` or `1 MB` into bytes, for use on the command-line
+pub fn parse_size(size: &str) -> std::result::Result<u64, String> {
+    size.trim()
+        .parse::<bytesize::ByteSize>()
+        .map(|b| b.as_u64())
+        .map_err(|err| format!("Invalid size '{}', expected something like '256KiB': {}", size, err))
+}
+
+/// Compile `globs` of additional files to select when extracting crates, like `**/*.proto` or `deny.toml`.
+///
+/// Paths are relative to the crate root, and `*` doesn't match `/`, so `deny.toml` only selects the file at the root.
+pub fn selection(globs: &[String]) -> Result<globset::GlobSet> {
+    let mut set = globset::GlobSetBuilder::new();
+    for glob in globs {
+        set.add(globset::GlobBuilder::new(glob).literal_separator(true).build()?);
+    }
+    Ok(set.build()?)
+}
+
+/// Sources of binaries and license files, which are stored along with manifests and the sources of targets
+pub(crate) fn standard_paths() -> globset::GlobSet {
+    let glob = |glob: &str| {
+        globset::GlobBuilder::new(glob)
+            .literal_separator(true)
+            .case_insensitive(true)
+            .build()
+            .expect("valid statically known glob")
+    };
+    globset::GlobSetBuilder::new()
+        .add(glob("src/bin/*.rs"))
+        .add(glob("{LICENSE,LICENCE,COPYING,UNLICENSE}*"))
+        .add(glob("LICENSES/*"))
+        .build()
+        .expect("valid statically known globs")
 }
 
 impl Agent {
-    pub fn new(asset_dir: PathBuf, db: &persistence::Db) -> Result<Agent> {
+    pub fn new(
+        asset_dir: PathBuf,
+        assets: SharedAssetStore,
+        db: &persistence::Db,
+        selection: globset::GlobSet,
+        max_stored_entry_size: u64,
+    ) -> Result<Agent> {
         let results = db.open_results()?;
         Ok(Agent {
             asset_dir,
+            assets,
             results,
             state: None,
-            standard_bin_path: globset::Glob::new("src/bin/*.rs")
-                .expect("valid statically known glob")
-                .compile_matcher(),
+            standard_paths: standard_paths(),
+            selection,
+            max_stored_entry_size,
         })
     }
 }
@@ -43,12 +96,18 @@ impl crate::engine::work::generic::Processor for Agent {
             download_task,
             crate_name,
             crate_version,
+            extraction,
         } = request;
 
         let progress_info = format!("CPU UNZIP+UNTAR {}:{}", crate_name, crate_version);
         let dummy_task = default_persisted_extraction_task();
+        // Both passes store their result at the same key, but each has its own task to track its progress
+        let task = match extraction {
+            Extraction::Full => dummy_task.clone(),
+            Extraction::MetaDataOnly => meta_data_extraction_task(),
+        };
         let mut task_key = String::new();
-        dummy_task.fq_key(&crate_name, &crate_version, &mut task_key);
+        task.fq_key(&crate_name, &crate_version, &mut task_key);
 
         let downloaded_crate = super::schedule::download_file_path(
             &self.asset_dir,
@@ -56,7 +115,7 @@ impl crate::engine::work::generic::Processor for Agent {
             &crate_version,
             &download_task.process,
             &download_task.version,
-            "crate",
+            super::artifact::CRATE.name,
         );
         let dummy_result = model::TaskResult::ExplodedCrate {
             entries_meta_data: vec![],
@@ -66,8 +125,16 @@ impl crate::engine::work::generic::Processor for Agent {
         let mut key = String::with_capacity(task_key.len() * 2);
         dummy_result.fq_key(&crate_name, &crate_version, &dummy_task, &mut key);
 
-        self.state = Some(ProcessingState { downloaded_crate, key });
-        Ok((dummy_task, task_key, progress_info))
+        let mut manifest_key = String::with_capacity(key.len() + 9);
+        super::manifest::result_key(&crate_name, &crate_version, &mut manifest_key);
+
+        self.state = Some(ProcessingState {
+            downloaded_crate,
+            key,
+            manifest_key,
+            extraction,
+        });
+        Ok((task, task_key, progress_info))
     }
 
     fn idle_message(&self) -> String {
@@ -75,9 +142,51 @@ impl crate::engine::work::generic::Processor for Agent {
     }
 
     async fn process(&mut self, progress: &mut prodash::tree::Item) -> std::result::Result<(), (Error, String)> {
-        let ProcessingState { downloaded_crate, key } = self.state.take().expect("state to be set");
-        extract_crate(&self.results, &key, progress, downloaded_crate, &self.standard_bin_path)
-            .map_err(|err| (err, "Failed to extract crate".into()))
+        let ProcessingState {
+            downloaded_crate,
+            key,
+            manifest_key,
+            extraction,
+        } = self.state.take().ok_or_else(|| {
+            (
+                Error::Bug("processing state to be set"),
+                "Failed to extract crate".into(),
+            )
+        })?;
+        let exploded_crate = extract_crate(
+            &self.results,
+            &key,
+            progress,
+            self.assets
+                .open(&downloaded_crate)
+                .map_err(|err| (err, "Failed to open crate".into()))?,
+            &self.standard_paths,
+            &self.selection,
+            self.max_stored_entry_size,
+            extraction,
+        )
+        .map_err(|err| (err, "Failed to extract crate".into()))?;
+        store_manifest(&self.results, &manifest_key, &exploded_crate, progress)
+            .map_err(|err| (err, "Failed to store manifest".into()))
+    }
+}
+
+/// How much of a crate to extract
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Extraction {
+    /// The meta-data of all files, along with the contents of manifests, the lock file, sources of targets and selected files
+    Full,
+    /// The meta-data of all files and the manifest only, which is enough for size and waste reports and much faster
+    MetaDataOnly,
+}
+
+impl Extraction {
+    /// The task performing this extraction
+    pub fn node(self) -> &'static task_graph::Node {
+        match self {
+            Extraction::Full => &task_graph::EXTRACT_CRATE,
+            Extraction::MetaDataOnly => &task_graph::EXTRACT_CRATE_META_DATA,
+        }
     }
 }
 
@@ -86,6 +195,56 @@ pub struct ExtractRequest {
     pub download_task: model::Task,
     pub crate_name: String,
     pub crate_version: String,
+    pub extraction: Extraction,
+}
+
+/// The maximum amount of extractions needed by reports which wait to be performed, with further ones being dropped
+const MAX_NEEDED_EXTRACTIONS: usize = 1024;
+
+/// A crate version whose extraction a report needs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtractionNeed {
+    pub crate_name: String,
+    pub crate_version: String,
+    pub extraction: Extraction,
+}
+
+/// The extractions needed by reports, which the processing stage performs ahead of all others. Clones share the same
+/// queue, so reports can request extractions while the processing stage of the same engine performs them.
+#[derive(Clone)]
+pub struct NeededExtractions {
+    tx: async_channel::Sender<ExtractionNeed>,
+    rx: async_channel::Receiver<ExtractionNeed>,
+}
+
+impl Default for NeededExtractions {
+    fn default() -> Self {
+        let (tx, rx) = async_channel::bounded(MAX_NEEDED_EXTRACTIONS);
+        NeededExtractions { tx, rx }
+    }
+}
+
+impl NeededExtractions {
+    /// The amount of extractions which wait to be performed
+    pub fn len(&self) -> usize {
+        self.tx.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tx.is_empty()
+    }
+
+    /// Ask the processing stage to perform the extraction a report needs ahead of all others, if the crate was
+    /// downloaded. Returns false if too many extractions are needed already, in which case the report has to ask again
+    /// later.
+    pub fn request(&self, need: ExtractionNeed) -> bool {
+        self.tx.try_send(need).is_ok()
+    }
+
+    /// The next extraction needed by a report, if there is one
+    pub fn next(&self) -> Option<ExtractionNeed> {
+        self.rx.try_recv().ok()
+    }
 }
 
 pub fn default_persisted_extraction_task() -> model::Task {
@@ -99,17 +258,100 @@ pub fn default_persisted_extraction_task() -> model::Task {
     }
 }
 
+/// The task of the fast first pass when extracting in two passes, whose result is stored like the one of a full
+/// extraction so that reports can use it until it is replaced.
+pub fn meta_data_extraction_task() -> model::Task {
+    const TASK_NAME: &str = "extract_crate_meta_data";
+    const TASK_VERSION: &str = "1.0.0";
+    model::Task {
+        stored_at: SystemTime::now(),
+        process: TASK_NAME.into(),
+        version: TASK_VERSION.into(),
+        state: Default::default(),
+    }
+}
+
+/// The bytes after which binary data is expected to have a null byte, like git assumes
+const BINARY_SNIFF_LEN: usize = 8000;
+
+/// Detect the encoding of the `content` of the entry with `header`, which is partial if it's smaller than the entry, and
+/// return it as UTF-8 unless it's binary. A character cut off at the end of partial content is dropped, and the header
+/// records where it was truncated.
+fn to_stored_content(header: &mut model::TarHeader, content: &[u8]) -> Vec<u8> {
+    use model::ContentEncoding::*;
+    let is_partial = (content.len() as u64) < header.size;
+    let (encoding, stored) = match content {
+        [0xff, 0xfe, utf16 @ ..] | [0xfe, 0xff, utf16 @ ..] => {
+            let is_little_endian = content[0] == 0xff;
+            let units = utf16.chunks_exact(2).map(|b| {
+                if is_little_endian {
+                    u16::from_le_bytes([b[0], b[1]])
+                } else {
+                    u16::from_be_bytes([b[0], b[1]])
+                }
+            });
+            let mut text = String::with_capacity(utf16.len());
+            let mut is_text = is_partial || utf16.len() % 2 == 0;
+            let mut chars = std::char::decode_utf16(units).peekable();
+            while let Some(c) = chars.next().filter(|_| is_text) {
+                match c {
+                    Ok(c) => text.push(c),
+                    // Only the last character of partial content may be cut in half
+                    Err(_) if is_partial && chars.peek().is_none() => {}
+                    Err(_) => {
+                        is_text = false;
+                        break;
+                    }
+                }
+            }
+            if is_text {
+                (Utf16, text.into_bytes())
+            } else {
+                (Binary, content.to_owned())
+            }
+        }
+        _ if content[..content.len().min(BINARY_SNIFF_LEN)].contains(&0) => (Binary, content.to_owned()),
+        _ => match std::str::from_utf8(content) {
+            Ok(_) => (Utf8, content.to_owned()),
+            Err(err) if is_partial && err.error_len().is_none() => (Utf8, content[..err.valid_up_to()].to_owned()),
+            Err(_) => (
+                Latin1,
+                content.iter().map(|b| *b as char).collect::<String>().into_bytes(),
+            ),
+        },
+    };
+    header.encoding = Some(encoding);
+    header.truncated_at = is_partial.then_some(content.len() as u64);
+    stored
+}
+
+/// The crate relative path of tar `entry`, failing the extraction if it isn't valid UTF-8
+fn entry_path<R: Read>(entry: &tar::Entry<R>) -> Result<String> {
+    let bytes = entry.path_bytes();
+    tar_path_to_utf8(bytes.as_ref()).map(ToOwned::to_owned).map_err(|_| {
+        Error::Message(format!(
+            "Path '{}' in crate archive is not valid UTF-8",
+            String::from_utf8_lossy(bytes.as_ref())
+        ))
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
 fn extract_crate(
     results: &persistence::TaskResultTable,
     key: &str,
     progress: &mut prodash::tree::Item,
-    downloaded_crate: PathBuf,
-    standard_bin_path: &globset::GlobMatcher,
-) -> Result<()> {
+    downloaded_crate: Box<dyn ReadSeek>,
+    standard_paths: &globset::GlobSet,
+    selection: &globset::GlobSet,
+    max_stored_entry_size: u64,
+    extraction: Extraction,
+) -> Result<model::TaskResult> {
+    if extraction == Extraction::MetaDataOnly {
+        return extract_meta_data(results, key, progress, downloaded_crate);
+    }
     use persistence::TableAccess;
-    let mut archive = tar::Archive::new(libflate::gzip::Decoder::new(BufReader::new(File::open(
-        downloaded_crate,
-    )?))?);
+    let mut archive = tar::Archive::new(libflate::gzip::Decoder::new(BufReader::new(downloaded_crate))?);
 
     let mut buf = Vec::new();
     let mut interesting_paths = vec!["Cargo.toml".to_string(), "Cargo.lock".into()];
@@ -117,7 +359,7 @@ fn extract_crate(
     for e in archive.entries()? {
         progress.inc();
         let mut e: tar::Entry<_> = e?;
-        if tar_path_to_utf8_str(e.path_bytes().as_ref()) == "Cargo.toml" {
+        if entry_path(&e)? == "Cargo.toml" {
             e.read_to_end(&mut buf)?;
             let config = CargoConfig::from(buf.as_slice());
             interesting_paths.push(config.actual_or_expected_build_script_path().to_owned());
@@ -136,38 +378,32 @@ fn extract_crate(
     let mut meta_data = Vec::new();
     let mut meta_count = 0;
     let mut file_count = 0;
-    let mut max_storage_size = [0; 128 * 1024];
     for e in archive.entries()? {
         meta_count += 1;
         progress.set(meta_count);
         let mut e: tar::Entry<_> = e?;
-        meta_data.push(model::TarHeader {
+        let path = entry_path(&e)?;
+        let mut header = model::TarHeader {
             path: e.path_bytes().to_vec(),
             size: e.header().size()?,
             entry_type: e.header().entry_type().as_byte(),
-        });
+            encoding: None,
+            truncated_at: None,
+        };
+        meta_data.push(header.clone());
 
-        if interesting_paths
-            .iter()
-            .any(|p| p == tar_path_to_utf8_str(e.path_bytes().as_ref()))
-            || standard_bin_path.is_match(tar_path_to_utf8_str(e.path_bytes().as_ref()))
-        {
+        if interesting_paths.contains(&path) || standard_paths.is_match(&path) || selection.is_match(&path) {
             file_count += 1;
 
-            let slice = if tar_path_to_utf8_str(e.path_bytes().as_ref()) == "Cargo.toml"
-                || tar_path_to_utf8_str(e.path_bytes().as_ref()) == "Cargo.lock"
-            {
-                buf.clear();
-                e.read_to_end(&mut buf)?;
-                &buf
+            let max_size = if path == "Cargo.toml" || path == "Cargo.lock" {
+                u64::MAX
             } else {
-                let bytes_read = e.read(&mut max_storage_size[..])?;
-                &max_storage_size[..bytes_read]
+                max_stored_entry_size
             };
-            files.push((
-                meta_data.last().expect("to have pushed one just now").to_owned(),
-                slice.to_owned(),
-            ));
+            buf.clear();
+            (&mut e).take(max_size).read_to_end(&mut buf)?;
+            let content = to_stored_content(&mut header, &buf);
+            files.push((header, content));
         }
     }
     progress.info(format!(
@@ -179,7 +415,76 @@ fn extract_crate(
         entries_meta_data: meta_data,
         selected_entries: files,
     };
-    results.insert(progress, &key, &task_result)?;
+    results.insert(progress, key, &task_result)?;
+    Ok(task_result)
+}
 
+/// Parse the `Cargo.toml` selected into `exploded_crate` and store it at `key`.
+/// Manifests which can't be parsed are skipped, as the extraction itself succeeded.
+fn store_manifest(
+    results: &persistence::TaskResultTable,
+    key: &str,
+    exploded_crate: &model::TaskResult,
+    progress: &mut prodash::tree::Item,
+) -> Result<()> {
+    use persistence::TableAccess;
+    let content = match exploded_crate {
+        model::TaskResult::ExplodedCrate { selected_entries, .. } => selected_entries
+            .iter()
+            .find(|(header, _)| tar_path_to_utf8(&header.path) == Ok("Cargo.toml"))
+            .map(|(_, content)| content),
+        _ => None,
+    };
+    match content.map(|content| super::manifest::parse(content)) {
+        Some(Ok(manifest)) => {
+            results.insert(progress, key, &manifest)?;
+        }
+        Some(Err(err)) => progress.info(format!("Not storing manifest: {}", err)),
+        None => {}
+    }
     Ok(())
 }
+
+/// Record the meta-data of all entries of `downloaded_crate` and the contents of its manifest in a single pass.
+fn extract_meta_data(
+    results: &persistence::TaskResultTable,
+    key: &str,
+    progress: &mut prodash::tree::Item,
+    downloaded_crate: Box<dyn ReadSeek>,
+) -> Result<model::TaskResult> {
+    use persistence::TableAccess;
+    let mut archive = tar::Archive::new(libflate::gzip::Decoder::new(BufReader::new(downloaded_crate))?);
+
+    let mut meta_data = Vec::new();
+    let mut files = Vec::new();
+    for e in archive.entries()? {
+        progress.inc();
+        let mut e: tar::Entry<_> = e?;
+        let header = model::TarHeader {
+            path: e.path_bytes().to_vec(),
+            size: e.header().size()?,
+            entry_type: e.header().entry_type().as_byte(),
+            encoding: None,
+            truncated_at: None,
+        };
+        if entry_path(&e)? == "Cargo.toml" {
+            let mut buf = Vec::new();
+            e.read_to_end(&mut buf)?;
+            let mut stored_header = header.clone();
+            let content = to_stored_content(&mut stored_header, &buf);
+            files.push((stored_header, content));
+        }
+        meta_data.push(header);
+    }
+    progress.info(format!("Recorded {} files", meta_data.len()));
+
+    let task_result = model::TaskResult::ExplodedCrate {
+        entries_meta_data: meta_data,
+        selected_entries: files,
+    };
+    results.insert(progress, key, &task_result)?;
+    Ok(task_result)
+}
+
+#[cfg(test)]
+mod cpubound_test;
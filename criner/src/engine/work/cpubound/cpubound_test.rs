@@ -0,0 +1,219 @@
+use super::{
+    extract_crate, selection, standard_paths, to_stored_content, Agent, Extraction, DEFAULT_MAX_STORED_ENTRY_SIZE,
+};
+use crate::{
+    engine::work::{asset_store, generic::Processor},
+    model::{ContentEncoding, TarHeader, TaskResult},
+    persistence::{temp_db::TempDb, TableAccess},
+    Error,
+};
+use std::io::Write;
+
+/// A gzipped crate archive with an empty file at each of `paths`, which may not be valid UTF-8
+fn crate_archive(paths: &[&[u8]]) -> Vec<u8> {
+    crate_archive_with_contents(&paths.iter().map(|path| (*path, &b""[..])).collect::<Vec<_>>())
+}
+
+/// A gzipped crate archive with the given files and their contents
+fn crate_archive_with_contents(files: &[(&[u8], &[u8])]) -> Vec<u8> {
+    let mut builder = tar::Builder::new(Vec::new());
+    for (path, content) in files {
+        let mut header = tar::Header::new_old();
+        header.as_old_mut().name[..path.len()].copy_from_slice(path);
+        header.set_size(content.len() as u64);
+        header.set_cksum();
+        builder.append(&header, *content).unwrap();
+    }
+    let mut encoder = libflate::gzip::Encoder::new(Vec::new()).unwrap();
+    encoder.write_all(&builder.into_inner().unwrap()).unwrap();
+    encoder.finish().into_result().unwrap()
+}
+
+#[test]
+fn selection_globs_are_relative_to_the_crate_root() {
+    let set = selection(&["**/*.proto".to_string(), "deny.toml".into()]).unwrap();
+    assert!(set.is_match("api.proto"));
+    assert!(set.is_match("proto/v1/api.proto"));
+    assert!(set.is_match("deny.toml"));
+    assert!(
+        !set.is_match("ci/deny.toml"),
+        "a plain file name only matches at the root"
+    );
+    assert!(!set.is_match("README.md"));
+}
+
+#[test]
+fn no_globs_select_nothing() {
+    assert!(!selection(&[]).unwrap().is_match("deny.toml"));
+}
+
+#[test]
+fn invalid_globs_are_errors() {
+    assert!(selection(&["src/[lib.rs".to_string()]).is_err());
+}
+
+#[test]
+fn processing_without_request_is_a_task_failure() {
+    let db = TempDb::new("cpubound-no-request");
+    let dir = &db.dir;
+    let mut agent = Agent::new(
+        dir.clone(),
+        asset_store::local(),
+        &db,
+        selection(&[]).unwrap(),
+        DEFAULT_MAX_STORED_ENTRY_SIZE,
+    )
+    .unwrap();
+    let mut progress = prodash::Tree::new().add_child("extract");
+    let res = futures_lite::future::block_on(agent.process(&mut progress));
+    assert!(matches!(res, Err((Error::Bug(_), _))));
+}
+
+#[test]
+fn crates_with_paths_which_are_not_utf8_fail_to_extract() {
+    let db = TempDb::new("cpubound-not-utf8");
+    let results = db.open_results().unwrap();
+    let extract = |archive: Vec<u8>| {
+        extract_crate(
+            &results,
+            "key",
+            &mut prodash::Tree::new().add_child("extract"),
+            Box::new(std::io::Cursor::new(archive)),
+            &standard_paths(),
+            &selection(&[]).unwrap(),
+            DEFAULT_MAX_STORED_ENTRY_SIZE,
+            Extraction::Full,
+        )
+    };
+    assert!(extract(crate_archive(&[b"a-1.0.0/Cargo.toml", b"a-1.0.0/src/lib.rs"])).is_ok());
+    assert!(matches!(
+        extract(crate_archive(&[b"a-1.0.0/Cargo.toml", b"a-1.0.0/src/\xff.rs"])),
+        Err(Error::Message(_))
+    ));
+}
+
+#[test]
+fn meta_data_only_extraction_keeps_the_manifest_and_meta_data_of_all_files() {
+    let db = TempDb::new("cpubound-meta-data-only");
+    let results = db.open_results().unwrap();
+    let archive = crate_archive(&[
+        b"a-1.0.0/Cargo.toml",
+        b"a-1.0.0/src/lib.rs",
+        b"a-1.0.0/README.md",
+        b"a-1.0.0/LICENSE-MIT",
+    ]);
+    let selected_paths = |extraction: Extraction| {
+        extract_crate(
+            &results,
+            "key",
+            &mut prodash::Tree::new().add_child("extract"),
+            Box::new(std::io::Cursor::new(archive.clone())),
+            &standard_paths(),
+            &selection(&[]).unwrap(),
+            DEFAULT_MAX_STORED_ENTRY_SIZE,
+            extraction,
+        )
+        .unwrap();
+        match results.get("key").unwrap() {
+            Some(TaskResult::ExplodedCrate {
+                entries_meta_data,
+                selected_entries,
+            }) => {
+                assert_eq!(entries_meta_data.len(), 4);
+                selected_entries
+                    .into_iter()
+                    .map(|(header, _)| String::from_utf8(header.path).unwrap())
+                    .collect::<Vec<_>>()
+            }
+            _ => unreachable!("extraction stores exploded crates"),
+        }
+    };
+    assert_eq!(selected_paths(Extraction::MetaDataOnly), vec!["a-1.0.0/Cargo.toml"]);
+    assert_eq!(
+        selected_paths(Extraction::Full),
+        vec!["a-1.0.0/Cargo.toml", "a-1.0.0/src/lib.rs", "a-1.0.0/LICENSE-MIT"],
+        "the second pass replaces the result of the first, and license files are always stored"
+    );
+}
+
+#[test]
+fn stored_contents_are_utf8_unless_they_are_binary() {
+    let stored = |content: &[u8], size: usize| {
+        let mut header = TarHeader {
+            path: b"a-1.0.0/LICENSE".to_vec(),
+            size: size as u64,
+            entry_type: 0,
+            encoding: None,
+            truncated_at: None,
+        };
+        let content = to_stored_content(&mut header, content);
+        (header.encoding.expect("always detected"), content)
+    };
+    let text = |content: &[u8]| stored(content, content.len());
+
+    assert_eq!(text("Ünïcödé".as_bytes()), (ContentEncoding::Utf8, "Ünïcödé".into()));
+    assert_eq!(
+        stored(&"Ünï".as_bytes()[..4], 10),
+        (ContentEncoding::Utf8, "Ün".into()),
+        "a character cut off at the end of partial content is dropped"
+    );
+    assert_eq!(
+        text(b"Copyright \xa9 M\xfcller"),
+        (ContentEncoding::Latin1, "Copyright © Müller".into())
+    );
+    assert_eq!(
+        text(b"\xff\xfeM\0I\0T\0"),
+        (ContentEncoding::Utf16, "MIT".into()),
+        "little endian"
+    );
+    assert_eq!(
+        text(b"\xfe\xff\0M\0I\0T"),
+        (ContentEncoding::Utf16, "MIT".into()),
+        "big endian"
+    );
+    assert_eq!(
+        text(b"\xff\xfe\0\xd8\0\0"),
+        (ContentEncoding::Binary, b"\xff\xfe\0\xd8\0\0".to_vec()),
+        "unpaired surrogates aren't UTF-16"
+    );
+    assert_eq!(
+        text(b"\x7fELF\x02\x01\0\0"),
+        (ContentEncoding::Binary, b"\x7fELF\x02\x01\0\0".to_vec())
+    );
+}
+
+#[test]
+fn selected_files_are_truncated_at_the_maximum_size_unless_they_are_manifests() {
+    let db = TempDb::new("cpubound-truncated");
+    let results = db.open_results().unwrap();
+    let (manifest, readme) = ("[package]\nname = \"a\"\n".repeat(4), "# A\n".repeat(10));
+    let archive = crate_archive_with_contents(&[
+        (b"a-1.0.0/Cargo.toml", manifest.as_bytes()),
+        (b"a-1.0.0/README.md", readme.as_bytes()),
+        (b"a-1.0.0/src/lib.rs", b"//! A"),
+    ]);
+    extract_crate(
+        &results,
+        "key",
+        &mut prodash::Tree::new().add_child("extract"),
+        Box::new(std::io::Cursor::new(archive)),
+        &standard_paths(),
+        &selection(&["README.md".to_string()]).unwrap(),
+        8,
+        Extraction::Full,
+    )
+    .unwrap();
+    let selected_entries = match results.get("key").unwrap() {
+        Some(TaskResult::ExplodedCrate { selected_entries, .. }) => selected_entries,
+        _ => unreachable!("extraction stores exploded crates"),
+    };
+    let truncation = selected_entries
+        .into_iter()
+        .map(|(header, content)| (header.truncated_at, content.len()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        truncation,
+        vec![(None, manifest.len()), (Some(8), 8), (None, 5)],
+        "only files larger than the maximum are truncated, and manifests never are"
+    );
+}
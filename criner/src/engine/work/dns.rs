@@ -0,0 +1,118 @@
+//! Resolving the hosts to download from with nameservers of our choice, instead of those of the operating system, and
+//! timing how long it takes.
+use hickory_resolver::{
+    config::{NameServerConfig, NameServerConfigGroup, Protocol, ResolverConfig, ResolverOpts},
+    TokioAsyncResolver,
+};
+use hyper::client::connect::dns::Name;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr, ToSocketAddrs},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// The port of nameservers which are given without one
+const DEFAULT_PORT: u16 = 53;
+
+/// Parse the address of a nameserver like `1.1.1.1`, `[2606:4700:4700::1111]:53` or `127.0.0.1:5353`, for use on the
+/// command-line. Without a port, the standard one is used.
+pub fn parse_nameserver(s: &str) -> std::result::Result<SocketAddr, String> {
+    s.parse::<SocketAddr>()
+        .or_else(|_| s.parse::<IpAddr>().map(|ip| SocketAddr::new(ip, DEFAULT_PORT)))
+        .map_err(|_| {
+            format!(
+                "Invalid nameserver '{}', expected an IP address with an optional port",
+                s
+            )
+        })
+}
+
+/// Resolves host names by asking the given nameservers over UDP, and TCP for large answers, ignoring the hosts file
+/// and the resolver configuration of the operating system.
+#[derive(Clone)]
+pub struct Nameservers(TokioAsyncResolver);
+
+impl Nameservers {
+    pub fn new(addrs: &[SocketAddr]) -> Self {
+        let mut group = NameServerConfigGroup::with_capacity(addrs.len() * 2);
+        for addr in addrs {
+            for protocol in [Protocol::Udp, Protocol::Tcp] {
+                let mut config = NameServerConfig::new(*addr, protocol);
+                config.trust_negative_responses = true;
+                group.push(config);
+            }
+        }
+        Nameservers(TokioAsyncResolver::tokio(
+            ResolverConfig::from_parts(None, Vec::new(), group),
+            ResolverOpts::default(),
+        ))
+    }
+}
+
+impl Resolve for Nameservers {
+    fn resolve(&self, name: Name) -> Resolving {
+        let resolver = self.0.clone();
+        Box::pin(async move {
+            let lookup = resolver.lookup_ip(name.as_str()).await?;
+            // The port is ignored, the one of the URL is used.
+            let addrs: Addrs = Box::new(
+                lookup
+                    .iter()
+                    .map(|ip| SocketAddr::new(ip, 0))
+                    .collect::<Vec<_>>()
+                    .into_iter(),
+            );
+            Ok(addrs)
+        })
+    }
+}
+
+/// Resolves host names like the HTTP client does by default, with the resolver of the operating system
+pub struct System;
+
+impl Resolve for System {
+    fn resolve(&self, name: Name) -> Resolving {
+        let name = name.as_str().to_owned();
+        Box::pin(async move {
+            // The port is ignored, the one of the URL is used.
+            let addrs: Addrs = Box::new(blocking::unblock(move || (name.as_str(), 0).to_socket_addrs()).await?);
+            Ok(addrs)
+        })
+    }
+}
+
+/// How long the last lookup of each host took, until a download takes it.
+#[derive(Clone, Default)]
+pub struct LookupTimes(Arc<parking_lot::Mutex<HashMap<String, Duration>>>);
+
+impl LookupTimes {
+    /// How long the last lookup of `host` took, or `None` if it wasn't resolved since the last call, like when a
+    /// connection to it was reused, or if it is an IP address or has an override.
+    pub fn take(&self, host: &str) -> Option<Duration> {
+        self.0.lock().remove(host)
+    }
+}
+
+/// Records how long each successful lookup of `inner` takes into `times`, as the HTTP client doesn't tell.
+pub struct Timed {
+    pub inner: Arc<dyn Resolve>,
+    pub times: LookupTimes,
+}
+
+impl Resolve for Timed {
+    fn resolve(&self, name: Name) -> Resolving {
+        let (host, times) = (name.as_str().to_owned(), self.times.clone());
+        let lookup = self.inner.resolve(name);
+        Box::pin(async move {
+            let started_at = Instant::now();
+            let addrs = lookup.await?;
+            times.0.lock().insert(host, started_at.elapsed());
+            Ok(addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod dns_test;
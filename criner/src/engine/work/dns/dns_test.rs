@@ -0,0 +1,98 @@
+use super::{parse_nameserver, LookupTimes};
+use crate::engine::work::iobound::Connectivity;
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, UdpSocket},
+};
+
+/// Answer queries for the A record of any host with `127.0.0.1`, and all other queries with no records at all.
+fn serve_dns() -> SocketAddr {
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let addr = socket.local_addr().unwrap();
+    std::thread::spawn(move || {
+        let mut query = [0; 512];
+        while let Ok((len, client)) = socket.recv_from(&mut query) {
+            let mut question_end = 12;
+            while query[question_end] != 0 {
+                question_end += 1 + query[question_end] as usize;
+            }
+            question_end += 5;
+            let is_a = query[question_end - 4..question_end - 2] == [0, 1];
+            assert!(question_end <= len, "the query is complete");
+
+            let mut response = query[..2].to_vec();
+            response.extend_from_slice(&[0x81, 0x80, 0, 1, 0, is_a as u8, 0, 0, 0, 0]);
+            response.extend_from_slice(&query[12..question_end]);
+            if is_a {
+                // A pointer to the name of the question, type A, class IN, a TTL of a minute and the address
+                response.extend_from_slice(&[0xc0, 12, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 127, 0, 0, 1]);
+            }
+            socket.send_to(&response, client).unwrap();
+        }
+    });
+    addr
+}
+
+#[test]
+fn hosts_are_resolved_with_the_nameservers_if_some_are_set() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = std::thread::spawn(move || {
+        let mut stream = listener.incoming().next().unwrap().unwrap();
+        let request: Vec<String> = BufReader::new(&stream)
+            .lines()
+            .map(Result::unwrap)
+            .take_while(|line| !line.is_empty())
+            .collect();
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 3\r\nconnection: close\r\n\r\nnew")
+            .unwrap();
+        request
+    });
+
+    let connectivity = Connectivity {
+        nameservers: vec![serve_dns()],
+        ..Default::default()
+    };
+    let lookup_times = LookupTimes::default();
+    let client = connectivity
+        .client_builder_with_lookup_times(Some(&lookup_times))
+        .build()
+        .unwrap();
+    let body = futures_lite::future::block_on(async_compat::Compat::new(async {
+        client
+            .get(format!("http://crates.example:{}/a/a-1.0.0.crate", port))
+            .send()
+            .await?
+            .text()
+            .await
+    }))
+    .unwrap();
+    assert_eq!(body, "new");
+    assert!(server
+        .join()
+        .unwrap()
+        .contains(&format!("host: crates.example:{}", port)));
+    assert!(lookup_times.take("crates.example").is_some(), "the lookup was timed");
+    assert_eq!(lookup_times.take("crates.example"), None, "each lookup is taken once");
+}
+
+#[test]
+fn nameservers_are_parsed_with_an_optional_port() {
+    assert_eq!(parse_nameserver("1.1.1.1"), Ok("1.1.1.1:53".parse().unwrap()));
+    assert_eq!(
+        parse_nameserver("127.0.0.1:5353"),
+        Ok("127.0.0.1:5353".parse().unwrap())
+    );
+    assert_eq!(
+        parse_nameserver("2606:4700:4700::1111"),
+        Ok("[2606:4700:4700::1111]:53".parse().unwrap())
+    );
+    assert_eq!(
+        parse_nameserver("[2606:4700:4700::1111]:5353"),
+        Ok("[2606:4700:4700::1111]:5353".parse().unwrap())
+    );
+    for invalid in ["", "one.one.one.one", "1.1.1.1:dns"] {
+        assert!(parse_nameserver(invalid).is_err(), "{}", invalid);
+    }
+}
@@ -0,0 +1,105 @@
+//! Limits for downloads which depend on the time of day, like `22:00-06:00=full,06:00-22:00=1MiB/s,2workers`
+use std::str::FromStr;
+
+/// Limits applying to all downloads together
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Limits {
+    /// The maximum bandwidth in bytes per second, or unlimited if `None`
+    pub bytes_per_second: Option<u64>,
+    /// The maximum amount of concurrent downloads, or as many as there are downloaders if `None`
+    pub workers: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Window {
+    start: time::Time,
+    /// Exclusive, and may be before `start` to wrap around midnight
+    end: time::Time,
+    limits: Limits,
+}
+
+impl Window {
+    fn contains(&self, t: time::Time) -> bool {
+        if self.start <= self.end {
+            (self.start <= t && t < self.end) || self.start == self.end
+        } else {
+            t >= self.start || t < self.end
+        }
+    }
+}
+
+/// A set of time windows in local time with the limits to use during each of them
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DownloadSchedule {
+    windows: Vec<Window>,
+}
+
+impl DownloadSchedule {
+    /// The limits of the first window containing `t`, or `None` if there is none
+    pub fn limits_at(&self, t: time::Time) -> Option<Limits> {
+        self.windows.iter().find(|w| w.contains(t)).map(|w| w.limits)
+    }
+
+    /// The limits in effect right now, in local time
+    pub fn current_limits(&self) -> Option<Limits> {
+        let now = time::OffsetDateTime::try_now_local().unwrap_or_else(|_| time::OffsetDateTime::now_utc());
+        self.limits_at(now.time())
+    }
+}
+
+fn parse_window(range: &str) -> Result<(time::Time, time::Time), String> {
+    let mut times = range.splitn(2, '-');
+    match (times.next(), times.next()) {
+        (Some(start), Some(end)) => Ok((
+            time::parse(start.trim(), "%R").map_err(|err| format!("Invalid start time '{}': {}", start, err))?,
+            time::parse(end.trim(), "%R").map_err(|err| format!("Invalid end time '{}': {}", end, err))?,
+        )),
+        _ => Err(format!("Expected a time range like '22:00-06:00', got '{}'", range)),
+    }
+}
+
+fn apply_limit(limits: &mut Limits, limit: &str) -> Result<(), String> {
+    let limit = limit.trim();
+    if limit == "full" {
+        *limits = Limits::default();
+    } else if let Some(workers) = limit.strip_suffix("workers").or_else(|| limit.strip_suffix("worker")) {
+        limits.workers = Some(
+            workers
+                .trim()
+                .parse::<usize>()
+                .map_err(|err| format!("Invalid amount of workers in '{}': {}", limit, err))?
+                .max(1),
+        );
+    } else {
+        limits.bytes_per_second = Some(super::iobound::parse_bandwidth(limit)?);
+    }
+    Ok(())
+}
+
+impl FromStr for DownloadSchedule {
+    type Err = String;
+
+    /// Parse comma separated `HH:MM-HH:MM=<limit>` windows, where each limit is `full`, a bandwidth like `1MiB/s` or
+    /// an amount of workers like `2workers`. More limits for the same window follow after a comma.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut windows: Vec<Window> = Vec::new();
+        for token in s.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+            match token.find('=') {
+                Some(pos) => {
+                    let (start, end) = parse_window(&token[..pos])?;
+                    let mut limits = Limits::default();
+                    apply_limit(&mut limits, &token[pos + 1..])?;
+                    windows.push(Window { start, end, limits });
+                }
+                None => match windows.last_mut() {
+                    Some(window) => apply_limit(&mut window.limits, token)?,
+                    None => return Err(format!("'{}' must follow a time range like '22:00-06:00='", token)),
+                },
+            }
+        }
+        Ok(DownloadSchedule { windows })
+    }
+}
+
+#[cfg(test)]
+mod download_schedule_test;
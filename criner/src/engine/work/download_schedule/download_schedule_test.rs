@@ -0,0 +1,32 @@
+use crate::engine::work::download_schedule::{DownloadSchedule, Limits};
+
+fn at(time: &str) -> time::Time {
+    time::parse(time, "%R").unwrap()
+}
+
+#[test]
+fn windows_may_wrap_around_midnight_and_have_multiple_limits() {
+    let schedule: DownloadSchedule = "22:00-06:00=full,06:00-22:00=1MiB/s,2workers".parse().unwrap();
+    assert_eq!(schedule.limits_at(at("23:30")), Some(Limits::default()));
+    assert_eq!(schedule.limits_at(at("05:59")), Some(Limits::default()));
+    assert_eq!(
+        schedule.limits_at(at("06:00")),
+        Some(Limits {
+            bytes_per_second: Some(1024 * 1024),
+            workers: Some(2),
+        })
+    );
+}
+
+#[test]
+fn times_outside_of_all_windows_have_no_limits() {
+    let schedule: DownloadSchedule = "09:00-17:00=3workers".parse().unwrap();
+    assert_eq!(schedule.limits_at(at("08:59")), None);
+    assert_eq!(schedule.limits_at(at("12:00")).and_then(|l| l.workers), Some(3));
+}
+
+#[test]
+fn limits_without_a_window_are_rejected() {
+    assert!("1MiB/s".parse::<DownloadSchedule>().is_err());
+    assert!("9:00=1MiB/s".parse::<DownloadSchedule>().is_err());
+}
@@ -1,5 +1,6 @@
-use crate::{model, persistence, persistence::TableAccess, Error, Result};
+use crate::{engine::metrics, model, persistence, persistence::TableAccess, Error, Result};
 use async_trait::async_trait;
+use std::time::Instant;
 
 #[async_trait]
 pub trait Processor {
@@ -12,44 +13,79 @@ pub trait Processor {
     async fn schedule_next(&mut self, _progress: &mut prodash::tree::Item) -> Result<()> {
         Ok(())
     }
+    /// Another attempt at `request` after it failed with `err`, for instance from a different source, if there is one.
+    /// The failure is recorded before the returned request is processed.
+    fn fallback(&self, _request: &Self::Item, _err: &Error) -> Option<Self::Item> {
+        None
+    }
 }
 
 pub async fn processor<T: Clone>(
     db: persistence::Db,
     mut progress: prodash::tree::Item,
-    r: async_channel::Receiver<T>,
+    r: super::lanes::Receiver<T>,
     mut agent: impl Processor<Item = T> + Send,
-    max_retries_on_timeout: usize,
+    max_retries_on_transient_error: usize,
 ) -> Result<()> {
     let tasks = db.open_tasks()?;
+    let journal = db.open_journal()?;
+    let telemetry = db.open_error_telemetry()?;
+    let events = db.open_task_events()?;
 
-    while let Ok(request) = r.recv().await {
+    while let Ok(mut request) = r.recv().await {
         let mut try_count = 0;
         let (task, task_key) = loop {
             let (dummy_task, task_key, progress_name) = agent.set(request.clone(), &mut progress)?;
             progress.set_name(progress_name);
+            journal.claim(&mut progress, &task_key)?;
 
             let mut task = tasks.update(Some(&mut progress), &task_key, |mut t| {
                 t.process = dummy_task.process.clone();
                 t.version = dummy_task.version.clone();
-                t.state.merge_with(&model::TaskState::InProgress(None));
+                t.advance_to(model::TaskState::InProgress(None));
                 t
             })?;
 
             try_count += 1;
             progress.blocked("working", None);
+            metrics::count_task_event(&dummy_task.process, "started");
+            events.record(&task_key, "started", None, None)?;
+            let start = Instant::now();
             let res = agent.process(&mut progress).await;
+            let elapsed = Some(start.elapsed());
 
             task.state = match res {
-                Err((err @ Error::Timeout(_, _), _)) if try_count < max_retries_on_timeout => {
-                    progress.fail(format!("{} → retrying ({}/{})", err, try_count, max_retries_on_timeout));
+                Err((err, _)) if err.is_transient() && try_count < max_retries_on_transient_error => {
+                    progress.fail(format!(
+                        "{} → retrying ({}/{})",
+                        err, try_count, max_retries_on_transient_error
+                    ));
+                    metrics::count_task_event(&dummy_task.process, "retrying");
+                    events.record(&task_key, "retrying", elapsed, Some(&err.to_string()))?;
                     continue;
                 }
                 Err((err, msg)) => {
                     progress.fail(format!("{}: {}", msg, err));
-                    model::TaskState::AttemptsWithFailure(vec![err.to_string()])
+                    telemetry.record(err.category().name(), &task_key, format!("{}: {}", msg, err))?;
+                    metrics::count_task_event(&dummy_task.process, "failed");
+                    events.record(&task_key, "failed", elapsed, Some(&format!("{}: {}", msg, err)))?;
+                    let failed = if err.is_permanent() {
+                        model::TaskState::PermanentFailure(vec![err.to_string()])
+                    } else {
+                        model::TaskState::AttemptsWithFailure(vec![err.to_string()])
+                    };
+                    if let Some(fallback) = agent.fallback(&request, &err) {
+                        task.state = failed;
+                        tasks.upsert(&mut progress, &task_key, &task)?;
+                        request = fallback;
+                        try_count = 0;
+                        continue;
+                    }
+                    failed
                 }
                 Ok(_) => {
+                    metrics::count_task_event(&dummy_task.process, "finished");
+                    events.record(&task_key, "finished", elapsed, None)?;
                     agent.schedule_next(&mut progress).await.ok();
                     model::TaskState::Complete
                 }
@@ -58,6 +94,7 @@ pub async fn processor<T: Clone>(
         };
 
         tasks.upsert(&mut progress, &task_key, &task)?;
+        journal.release(&mut progress, &task_key)?;
         progress.set_name(agent.idle_message());
         progress.init(None, None);
     }
@@ -5,47 +5,487 @@ use crate::{
 };
 use bytesize::ByteSize;
 use futures_lite::{io::AsyncWriteExt, FutureExt};
+use sha2::{Digest, Sha256};
 
-use crate::utils::timeout_after;
+use crate::{
+    engine::work::{
+        artifact,
+        asset_store::SharedAssetStore,
+        dns,
+        download_schedule::{DownloadSchedule, Limits},
+        lanes::{self, Priority},
+        robots,
+    },
+    utils::timeout_after,
+};
 use async_trait::async_trait;
 use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
     path::{Path, PathBuf},
-    time::{Duration, SystemTime},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime},
 };
 
 const CONNECT_AND_FETCH_HEAD_TIMEOUT: Duration = Duration::from_secs(15);
 const FETCH_CHUNK_TIMEOUT_SECONDS: Duration = Duration::from_secs(10);
+/// The bytes every gzip archive starts with
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+/// The amount of bytes of an unexpected response body to show in errors
+const BODY_SNIPPET_LEN: usize = 256;
+/// How often to check if the download schedule allows more downloads
+const WORKER_SLOT_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The amount of bytes received by all downloads of this process
+static BYTES_DOWNLOADED: AtomicU64 = AtomicU64::new(0);
+
+/// The amount of bytes received by all downloads since the process started
+pub(crate) fn bytes_downloaded() -> u64 {
+    BYTES_DOWNLOADED.load(Ordering::Relaxed)
+}
+
+/// What we know about a host other than crates.io
+struct Politeness {
+    robots: robots::Robots,
+    next_request_at: Instant,
+}
+
+/// Uninitialized until we have seen the robots.txt of the host
+type SharedPoliteness = Arc<async_lock::Mutex<Option<Politeness>>>;
+
+/// Bytes or requests which may be used right away, refilled at a rate which may change over time, for at most one second worth of them
+struct TokenBucket {
+    available: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    fn new() -> Self {
+        TokenBucket {
+            available: 0.0,
+            refilled_at: Instant::now(),
+        }
+    }
+
+    /// Take `tokens` from the bucket and return how long to wait until they were actually available
+    fn take(&mut self, tokens: f64, tokens_per_second: f64) -> Duration {
+        let now = Instant::now();
+        let refill = now.duration_since(self.refilled_at).as_secs_f64() * tokens_per_second;
+        self.available = (self.available + refill).min(tokens_per_second) - tokens;
+        self.refilled_at = now;
+        if self.available >= 0.0 {
+            Duration::default()
+        } else {
+            Duration::from_secs_f64(-self.available / tokens_per_second)
+        }
+    }
+}
+
+/// Limits of `HostLimits` which may change while downloading
+#[derive(Debug, Clone, Copy)]
+struct Rates {
+    requests_per_second: Option<f64>,
+    bytes_per_second: Option<u64>,
+}
+
+/// Counts running downloads, to be released when dropped
+struct WorkerSlot(Arc<AtomicUsize>);
+
+impl Drop for WorkerSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Parse a rate of requests like `2` or `0.5` for one request every two seconds, for use on the command-line
+pub fn parse_requests_per_second(rate: &str) -> std::result::Result<f64, String> {
+    match rate.trim().parse::<f64>() {
+        Ok(rate) if rate.is_finite() && rate > 0.0 => Ok(rate),
+        _ => Err(format!(
+            "Invalid rate of requests '{}', expected a positive number like '2' or '0.5'",
+            rate
+        )),
+    }
+}
+
+/// Parse a bandwidth like `10MiB/s` or `500 KB` into bytes per second, for use on the command-line
+pub fn parse_bandwidth(bandwidth: &str) -> std::result::Result<u64, String> {
+    bandwidth
+        .trim()
+        .trim_end_matches("/s")
+        .parse::<ByteSize>()
+        .map(|b| b.as_u64())
+        .map_err(|err| {
+            format!(
+                "Invalid bandwidth '{}', expected something like '10MiB/s': {}",
+                bandwidth, err
+            )
+        })
+}
+
+/// The IP protocol version to connect with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IpVersion {
+    /// Use whatever works, trying IPv6 and IPv4 addresses concurrently
+    #[default]
+    Any,
+    V4,
+    V6,
+}
+
+impl std::str::FromStr for IpVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(match s {
+            "any" => IpVersion::Any,
+            "4" | "v4" | "ipv4" => IpVersion::V4,
+            "6" | "v6" | "ipv6" => IpVersion::V6,
+            _ => return Err(format!("Invalid IP version '{}', expected any, 4 or 6", s)),
+        })
+    }
+}
+
+/// How to reach hosts, overriding what the operating system would do
+#[derive(Debug, Clone, Default)]
+pub struct Connectivity {
+    pub ip_version: IpVersion,
+    /// Hosts and the address to use for them instead of resolving them with DNS
+    pub host_overrides: Vec<(String, IpAddr)>,
+    /// The nameservers to resolve hosts with instead of those of the operating system, if not empty
+    pub nameservers: Vec<SocketAddr>,
+    /// The proxy to send all requests through, instead of the one in the `HTTPS_PROXY` or `HTTP_PROXY` environment
+    /// variable, if any
+    pub proxy: Option<reqwest::Proxy>,
+}
+
+/// Parse the URL of a proxy like `socks5://localhost:1080` or `http://proxy.example.com:3128`, for use on the command-line
+pub fn parse_proxy(url: &str) -> std::result::Result<reqwest::Proxy, String> {
+    reqwest::Proxy::all(url).map_err(|err| {
+        format!(
+            "Invalid proxy '{}', expected a URL like 'socks5://localhost:1080' or 'http://proxy:3128': {}",
+            url, err
+        )
+    })
+}
+
+/// Parse a static host override like `static.crates.io=151.101.2.137`, for use on the command-line
+pub fn parse_host_override(s: &str) -> std::result::Result<(String, IpAddr), String> {
+    let mut tokens = s.splitn(2, '=');
+    match (tokens.next(), tokens.next()) {
+        (Some(host), Some(addr)) if !host.trim().is_empty() => Ok((
+            host.trim().to_owned(),
+            addr.trim()
+                .parse()
+                .map_err(|err| format!("Invalid IP address in host override '{}': {}", s, err))?,
+        )),
+        _ => Err(format!("Expected a host override like 'host=ip', got '{}'", s)),
+    }
+}
+
+impl Connectivity {
+    /// A client builder configured for all downloads
+    pub fn client_builder(&self) -> reqwest::ClientBuilder {
+        self.client_builder_with_lookup_times(None)
+    }
+
+    /// Like `client_builder()`, but recording how long resolving each host takes into `lookup_times` if set
+    pub(crate) fn client_builder_with_lookup_times(
+        &self,
+        lookup_times: Option<&dns::LookupTimes>,
+    ) -> reqwest::ClientBuilder {
+        let mut builder = reqwest::ClientBuilder::new()
+            .gzip(true)
+            .user_agent(concat!("criner/", env!("CARGO_PKG_VERSION")));
+        builder = match self.ip_version {
+            IpVersion::Any => builder,
+            IpVersion::V4 => builder.local_address(IpAddr::from(Ipv4Addr::UNSPECIFIED)),
+            IpVersion::V6 => builder.local_address(IpAddr::from(Ipv6Addr::UNSPECIFIED)),
+        };
+        builder = match (self.nameservers.is_empty(), lookup_times) {
+            (true, None) => builder,
+            (false, None) => builder.dns_resolver(Arc::new(dns::Nameservers::new(&self.nameservers))),
+            (no_nameservers, Some(times)) => builder.dns_resolver(Arc::new(dns::Timed {
+                inner: match no_nameservers {
+                    true => Arc::new(dns::System),
+                    false => Arc::new(dns::Nameservers::new(&self.nameservers)),
+                },
+                times: times.clone(),
+            })),
+        };
+        // Overrides take precedence over any resolver
+        for (host, addr) in self.host_overrides.iter() {
+            // The port is ignored, the one of the URL is used.
+            builder = builder.resolve(host, SocketAddr::new(*addr, 0));
+        }
+        // Without a proxy of our own, the one of the environment is used
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(proxy.clone());
+        }
+        builder
+    }
+}
+
+/// Limits the amount of concurrent connections to each host, shared by all agents which download.
+/// Hosts other than crates.io are also asked for their robots.txt, and requests to them are spaced out.
+/// The bandwidth and amount of all downloads together can be limited as well, possibly depending on the time of day.
+/// The rate of requests can be limited for each host.
+#[derive(Clone)]
+pub struct HostLimits {
+    max_connections_per_host: usize,
+    crawl_delay: Duration,
+    rates: Arc<parking_lot::Mutex<Rates>>,
+    requests: Arc<parking_lot::Mutex<HashMap<String, Arc<async_lock::Mutex<TokenBucket>>>>>,
+    semaphores: Arc<parking_lot::Mutex<HashMap<String, Arc<async_lock::Semaphore>>>>,
+    politeness: Arc<parking_lot::Mutex<HashMap<String, SharedPoliteness>>>,
+    schedule: Option<Arc<DownloadSchedule>>,
+    bandwidth: Arc<async_lock::Mutex<TokenBucket>>,
+    running_downloads: Arc<AtomicUsize>,
+    connectivity: Connectivity,
+    lookup_times: dns::LookupTimes,
+}
+
+fn host_of(url: &str) -> String {
+    reqwest::Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(ToOwned::to_owned))
+        .unwrap_or_default()
+}
+
+fn is_crates_io(host: &str) -> bool {
+    host == "crates.io" || host.ends_with(".crates.io")
+}
+
+impl HostLimits {
+    /// `crawl_delay` is the minimum time between requests to hosts other than crates.io, unless their robots.txt
+    /// asks for more. `max_requests_per_second` limits the requests to each host, crates.io included, with bursts of
+    /// at most one second worth of requests. `max_bytes_per_second` limits the bandwidth used by all downloads together, unless `schedule`
+    /// has limits for the current time of day. `connectivity` configures the clients of all downloaders.
+    pub fn new(
+        max_connections_per_host: usize,
+        crawl_delay: Duration,
+        max_requests_per_second: Option<f64>,
+        max_bytes_per_second: Option<u64>,
+        schedule: Option<DownloadSchedule>,
+        connectivity: Connectivity,
+    ) -> Self {
+        HostLimits {
+            max_connections_per_host: max_connections_per_host.max(1),
+            crawl_delay,
+            rates: Arc::new(parking_lot::Mutex::new(Rates {
+                requests_per_second: max_requests_per_second,
+                bytes_per_second: max_bytes_per_second,
+            })),
+            requests: Default::default(),
+            semaphores: Default::default(),
+            politeness: Default::default(),
+            schedule: schedule.map(Arc::new),
+            bandwidth: Arc::new(async_lock::Mutex::new(TokenBucket::new())),
+            running_downloads: Default::default(),
+            connectivity,
+            lookup_times: Default::default(),
+        }
+    }
+
+    /// Change the limits passed to `new()` for all clones, taking effect with the next request or chunk of data
+    pub fn set_rates(&self, max_requests_per_second: Option<f64>, max_bytes_per_second: Option<u64>) {
+        *self.rates.lock() = Rates {
+            requests_per_second: max_requests_per_second,
+            bytes_per_second: max_bytes_per_second,
+        };
+    }
+
+    fn current_limits(&self) -> Limits {
+        self.schedule
+            .as_ref()
+            .and_then(|s| s.current_limits())
+            .unwrap_or(Limits {
+                bytes_per_second: self.rates.lock().bytes_per_second,
+                workers: None,
+            })
+    }
+
+    /// Wait until receiving `bytes` fits into the bandwidth limit, if there is one.
+    /// Waiting happens while holding the bucket to serve all downloads in order.
+    async fn throttle(&self, bytes: usize) {
+        if let Some(bytes_per_second) = self.current_limits().bytes_per_second {
+            let mut bucket = self.bandwidth.lock().await;
+            let wait_for = bucket.take(bytes as f64, bytes_per_second.max(1) as f64);
+            if wait_for > Duration::default() {
+                async_io::Timer::after(wait_for).await;
+            }
+        }
+    }
+
+    /// Wait until another request to `host` fits into the rate limit, if there is one.
+    async fn throttle_requests(&self, host: &str, progress: &mut prodash::tree::Item) {
+        let requests_per_second = self.rates.lock().requests_per_second;
+        if let Some(requests_per_second) = requests_per_second {
+            let bucket = self
+                .requests
+                .lock()
+                .entry(host.to_owned())
+                .or_insert_with(|| Arc::new(async_lock::Mutex::new(TokenBucket::new())))
+                .clone();
+            let mut bucket = bucket.lock().await;
+            let wait_for = bucket.take(1.0, requests_per_second);
+            if wait_for > Duration::default() {
+                progress.blocked("rate limit", Some(SystemTime::now() + wait_for));
+                async_io::Timer::after(wait_for).await;
+            }
+        }
+    }
+
+    /// Wait until the amount of running downloads allows for another one
+    async fn worker_slot(&self, progress: &mut prodash::tree::Item) -> WorkerSlot {
+        loop {
+            let running = self.running_downloads.load(Ordering::SeqCst);
+            match self.current_limits().workers {
+                Some(workers) if running >= workers => {
+                    progress.blocked("waiting for the download schedule to allow more downloads", None);
+                    async_io::Timer::after(WORKER_SLOT_CHECK_INTERVAL).await;
+                }
+                _ => {
+                    if self
+                        .running_downloads
+                        .compare_exchange(running, running + 1, Ordering::SeqCst, Ordering::SeqCst)
+                        .is_ok()
+                    {
+                        return WorkerSlot(self.running_downloads.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    fn semaphore_for(&self, url: &str) -> Arc<async_lock::Semaphore> {
+        self.semaphores
+            .lock()
+            .entry(host_of(url))
+            .or_insert_with(|| Arc::new(async_lock::Semaphore::new(self.max_connections_per_host)))
+            .clone()
+    }
+
+    /// Wait until we may fetch `url`, or fail if the host's robots.txt doesn't allow it.
+    async fn wait_for_permission(
+        &self,
+        client: &reqwest::Client,
+        url: &str,
+        progress: &mut prodash::tree::Item,
+    ) -> Result<()> {
+        let parsed_url = reqwest::Url::parse(url).map_err(|err| Error::Protocol(format!("{}: {}", url, err)))?;
+        let host = parsed_url.host_str().unwrap_or_default().to_owned();
+        if is_crates_io(&host) {
+            return Ok(());
+        }
+        let host_state = self.politeness.lock().entry(host.clone()).or_default().clone();
+        let mut host_state = host_state.lock().await;
+        let state = match &mut *host_state {
+            Some(state) => state,
+            uninitialized => {
+                self.throttle_requests(&host, progress).await;
+                progress.blocked("fetching robots.txt", None);
+                uninitialized.insert(Politeness {
+                    robots: fetch_robots(client, &parsed_url).await?,
+                    next_request_at: Instant::now(),
+                })
+            }
+        };
+
+        let path = match parsed_url.query() {
+            Some(query) => format!("{}?{}", parsed_url.path(), query),
+            None => parsed_url.path().to_owned(),
+        };
+        if !state.robots.is_allowed(&path) {
+            return Err(Error::Protocol(format!(
+                "robots.txt of {} disallows fetching '{}'",
+                host, url
+            )));
+        }
+        let now = Instant::now();
+        if state.next_request_at > now {
+            let wait_for = state.next_request_at - now;
+            progress.blocked("crawl delay", Some(SystemTime::now() + wait_for));
+            async_io::Timer::after(wait_for).await;
+        }
+        let crawl_delay = state.robots.crawl_delay().unwrap_or_default().max(self.crawl_delay);
+        state.next_request_at = Instant::now() + crawl_delay;
+        Ok(())
+    }
+}
+
+async fn fetch_robots(client: &reqwest::Client, url: &reqwest::Url) -> Result<robots::Robots> {
+    let mut robots_url = url.clone();
+    robots_url.set_path("/robots.txt");
+    robots_url.set_query(None);
+    let response = timeout_after(
+        CONNECT_AND_FETCH_HEAD_TIMEOUT,
+        "fetching robots.txt",
+        client.get(robots_url).send().boxed(),
+    )
+    .await??;
+    let status = response.status();
+    if status.is_success() {
+        let text = timeout_after(
+            FETCH_CHUNK_TIMEOUT_SECONDS,
+            "fetching robots.txt",
+            response.text().boxed(),
+        )
+        .await??;
+        Ok(robots::Robots::parse(&text, robots::USER_AGENT))
+    } else if status.is_client_error() {
+        Ok(robots::Robots::allow_all())
+    } else {
+        Err(Error::HttpStatus(status))
+    }
+}
 
 struct ProcessingState {
     url: String,
     kind: &'static str,
     output_file_path: PathBuf,
     result_key: Option<String>,
+    checksum: Option<String>,
+    is_gzip: bool,
+    refresh: bool,
 }
 pub struct Agent<Fn, FnResult> {
     client: reqwest::Client,
+    host_limits: HostLimits,
+    assets: SharedAssetStore,
     results: persistence::TaskResultTable,
-    channel: async_channel::Sender<FnResult>,
+    durability: persistence::Durability,
+    channel: lanes::Sender<FnResult>,
     state: Option<ProcessingState>,
     make_state: Fn,
-    next_action_state: Option<FnResult>,
+    next_action_state: Option<(Priority, FnResult)>,
 }
 
 impl<Fn, FnResult> Agent<Fn, FnResult>
 where
-    Fn: FnMut(Option<(String, String)>, &model::Task, &Path) -> Option<FnResult>,
+    Fn: FnMut(Option<(String, String)>, &'static str, &model::Task, &Path) -> Option<FnResult>,
 {
     pub fn new(
         db: &persistence::Db,
-        channel: async_channel::Sender<FnResult>,
+        host_limits: HostLimits,
+        assets: SharedAssetStore,
+        channel: lanes::Sender<FnResult>,
         make_state: Fn,
     ) -> Result<Agent<Fn, FnResult>> {
-        let client = reqwest::ClientBuilder::new().gzip(true).build()?;
-
+        let client = host_limits
+            .connectivity
+            .client_builder_with_lookup_times(Some(&host_limits.lookup_times))
+            .build()?;
         let results = db.open_results()?;
         Ok(Agent {
             client,
+            host_limits,
+            assets,
             results,
+            durability: db.durability(),
             channel,
             state: None,
             next_action_state: None,
@@ -57,7 +497,7 @@ where
 #[async_trait]
 impl<Fn, FnResult> crate::engine::work::generic::Processor for Agent<Fn, FnResult>
 where
-    Fn: FnMut(Option<(String, String)>, &model::Task, &Path) -> Option<FnResult> + Send,
+    Fn: FnMut(Option<(String, String)>, &'static str, &model::Task, &Path) -> Option<FnResult> + Send,
     FnResult: Send,
 {
     type Item = DownloadRequest;
@@ -72,11 +512,16 @@ where
             output_file_path,
             progress_name,
             task_key,
+            task: dummy_task,
             crate_name_and_version,
             kind,
             url,
+            fallback_url: _,
+            checksum,
+            is_gzip,
+            refresh,
+            priority,
         } = request;
-        let dummy_task = default_persisted_download_task();
         let progress_name = format!("↓ {}", progress_name);
 
         let task_result = model::TaskResult::Download {
@@ -84,9 +529,17 @@ where
             url: String::new(),
             content_length: 0,
             content_type: None,
+            etag: None,
+            last_modified: None,
+            status: None,
+            final_url: None,
+            server: None,
+            timing: None,
         };
 
-        self.next_action_state = (self.make_state)(crate_name_and_version.clone(), &dummy_task, &output_file_path);
+        self.next_action_state =
+            (self.make_state)(crate_name_and_version.clone(), kind, &dummy_task, &output_file_path)
+                .map(|request| (priority, request));
         self.state = Some(ProcessingState {
             url,
             kind,
@@ -96,6 +549,9 @@ where
                 task_result.fq_key(crate_name, crate_version, &dummy_task, &mut result_key);
                 result_key
             }),
+            checksum,
+            is_gzip,
+            refresh,
         });
         Ok((dummy_task, task_key, progress_name))
     }
@@ -110,34 +566,60 @@ where
             kind,
             output_file_path,
             result_key,
-        } = self.state.take().expect("initialized state");
+            checksum,
+            is_gzip,
+            refresh,
+        } = self.state.take().ok_or_else(|| {
+            (
+                Error::Bug("processing state to be set"),
+                "Failed to download file".into(),
+            )
+        })?;
         download_file_and_store_result(
             progress,
             result_key,
             &self.results,
             &self.client,
+            &self.host_limits,
+            &self.assets,
             kind,
             &url,
             output_file_path,
+            checksum,
+            is_gzip,
+            refresh,
+            self.durability.sync_files(),
         )
         .await
         .map_err(|err| (err, format!("Failed to download '{}'", url)))
     }
 
     async fn schedule_next(&mut self, progress: &mut prodash::tree::Item) -> Result<()> {
-        if let Some(request) = self.next_action_state.take() {
+        if let Some((priority, request)) = self.next_action_state.take() {
             progress.blocked("schedule crate extraction", None);
             // Here we risk doing this work twice, but most of the time, we don't. And since it's fast,
             // we take the risk of duplicate work for keeping more processors busy.
             // NOTE: We assume there is no risk of double-scheduling, also we assume the consumer is faster
             // then the producer (us), so we are ok with blocking until the task is scheduled.
             self.channel
+                .lane(priority)
                 .send(request)
                 .await
                 .map_err(Error::send_msg("IO Bound: Schedule next task"))?;
         }
         Ok(())
     }
+
+    fn fallback(&self, request: &Self::Item, err: &Error) -> Option<Self::Item> {
+        match (err, request.fallback_url.as_ref()) {
+            (Error::ChecksumMismatch(..), Some(fallback_url)) => Some(DownloadRequest {
+                url: fallback_url.clone(),
+                fallback_url: None,
+                ..request.clone()
+            }),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -145,63 +627,219 @@ pub struct DownloadRequest {
     pub output_file_path: PathBuf,
     pub progress_name: String,
     pub task_key: String,
+    /// The download task the result is stored for
+    pub task: model::Task,
     pub crate_name_and_version: Option<(String, String)>,
     pub kind: &'static str,
     pub url: String,
+    /// Where to download from instead if what was downloaded from `url` doesn't match `checksum`, like a mirror
+    pub fallback_url: Option<String>,
+    /// The expected sha256 of the downloaded file as hex string, if known
+    pub checksum: Option<String>,
+    /// If true, the download is validated to be a gzip archive
+    pub is_gzip: bool,
+    /// If true, an existing file is downloaded again if it changed since it was downloaded
+    pub refresh: bool,
+    /// The lane the work following the download is submitted to, like the extraction of a downloaded crate
+    pub priority: Priority,
 }
 
 pub fn default_persisted_download_task() -> model::Task {
-    const TASK_NAME: &str = "download";
-    const TASK_VERSION: &str = "1.0.0";
-    model::Task {
-        stored_at: SystemTime::now(),
-        process: TASK_NAME.into(),
-        version: TASK_VERSION.into(),
-        state: Default::default(),
+    artifact::CRATE.task()
+}
+
+fn temporary_file_path(out_file: &Path) -> PathBuf {
+    let mut file_name = out_file.file_name().map(ToOwned::to_owned).unwrap_or_default();
+    file_name.push(".tmp");
+    out_file.with_file_name(file_name)
+}
+
+fn body_snippet(body: &[u8]) -> String {
+    String::from_utf8_lossy(&body[..body.len().min(BODY_SNIPPET_LEN)])
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Servers and proxies in trouble like to respond with error pages instead of a proper status code
+fn unexpected_body(url: &str, what: impl std::fmt::Display, body: &[u8]) -> Error {
+    Error::Network {
+        retryable: true,
+        message: format!("'{}' responded with {}: {}", url, what, body_snippet(body)),
     }
 }
 
+/// Fails if the file at `path` doesn't look like a gzip archive, in which case it is removed.
+async fn ensure_gzip_magic(url: &str, path: PathBuf) -> Result<()> {
+    let head = blocking::unblock({
+        let path = path.clone();
+        move || -> std::io::Result<Vec<u8>> {
+            use std::io::Read;
+            let mut head = Vec::with_capacity(BODY_SNIPPET_LEN);
+            std::fs::File::open(path)?
+                .take(BODY_SNIPPET_LEN as u64)
+                .read_to_end(&mut head)?;
+            Ok(head)
+        }
+    })
+    .await?;
+    if head.starts_with(GZIP_MAGIC) {
+        return Ok(());
+    }
+    blocking::unblock(move || std::fs::remove_file(path)).await?;
+    Err(unexpected_body(url, "something that isn't a gzip archive", &head))
+}
+
+async fn sha256_of_file(path: PathBuf) -> Result<Sha256> {
+    blocking::unblock(move || {
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+        Ok(hasher)
+    })
+    .await
+}
+
+fn header(response: &reqwest::Response, name: http::header::HeaderName) -> Option<String> {
+    response
+        .headers()
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(Into::into)
+}
+
+fn millis(duration: Duration) -> u32 {
+    duration.as_millis().min(u32::MAX as u128) as u32
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn download_file_and_store_result(
     progress: &mut prodash::tree::Item,
     result_key: Option<String>,
     results: &persistence::TaskResultTable,
     client: &reqwest::Client,
+    host_limits: &HostLimits,
+    assets: &SharedAssetStore,
     kind: &str,
     url: &str,
     out_file: PathBuf,
+    checksum: Option<String>,
+    is_gzip: bool,
+    refresh: bool,
+    sync_to_disk: bool,
 ) -> Result<()> {
-    blocking::unblock({
-        let out_file = out_file.clone();
-        move || std::fs::create_dir_all(&out_file.parent().expect("parent directory"))
+    let out_dir = out_file
+        .parent()
+        .ok_or_else(|| Error::Message(format!("'{}' has no parent directory", out_file.display())))?
+        .to_owned();
+    blocking::unblock(move || std::fs::create_dir_all(out_dir)).await?;
+
+    // Files only ever appear under their final name once they are complete, everything else is a partial download.
+    let existing_size = blocking::unblock({
+        let (assets, out_file) = (assets.clone(), out_file.clone());
+        move || assets.size(&out_file)
     })
     .await?;
+    let (etag, last_modified) = match (existing_size, result_key.as_ref()) {
+        (Some(size), _) if !refresh => {
+            progress.done(format!("{} already on disk - skipping", url));
+            if let Some(result_key) = result_key {
+                // The result of the download which put the file there knows more about it, like how long it took
+                if !matches!(results.get(&result_key)?, Some(model::TaskResult::Download { .. })) {
+                    let task_result = model::TaskResult::Download {
+                        kind: kind.to_owned(),
+                        url: url.to_owned(),
+                        content_length: size as u32,
+                        content_type: None,
+                        etag: None,
+                        last_modified: None,
+                        status: None,
+                        final_url: None,
+                        server: None,
+                        timing: None,
+                    };
+                    results.insert(progress, &result_key, &task_result)?;
+                }
+            }
+            return Ok(());
+        }
+        (Some(_), Some(result_key)) => match results.get(result_key)? {
+            Some(model::TaskResult::Download {
+                etag, last_modified, ..
+            }) => (etag, last_modified),
+            _ => (None, None),
+        },
+        _ => (None, None),
+    };
 
-    // NOTE: We assume that the files we download never change, and we assume the server supports resumption!
-    let (start_byte, truncate) = blocking::unblock({
-        let out_file = out_file.clone();
-        move || std::fs::metadata(&out_file)
-    })
-    .await
-    .map(|meta| (meta.len(), false))
-    .unwrap_or((0, true));
+    // NOTE: We assume that the files we download never change unless they are refreshed, and we assume the server
+    // supports resumption! Refreshed files may have changed, so a partial download of them is never resumed.
+    let tmp_file = temporary_file_path(&out_file);
+    let (mut start_byte, mut truncate) = if refresh {
+        (0, true)
+    } else {
+        blocking::unblock({
+            let tmp_file = tmp_file.clone();
+            move || std::fs::metadata(&tmp_file)
+        })
+        .await
+        .map(|meta| (meta.len(), false))
+        .unwrap_or((0, true))
+    };
+
+    let mut hasher = match checksum {
+        Some(_) if start_byte != 0 => {
+            progress.blocked("hashing partial download", None);
+            sha256_of_file(tmp_file.clone()).await?
+        }
+        _ => Sha256::new(),
+    };
+
+    let _worker_slot = host_limits.worker_slot(progress).await;
+    let connection_slot = host_limits.semaphore_for(url);
+    progress.blocked("waiting for a connection to host", None);
+    let _connection_slot = connection_slot.acquire().await;
+    host_limits.wait_for_permission(client, url, progress).await?;
+    host_limits.throttle_requests(&host_of(url), progress).await;
 
     progress.blocked("fetch HEAD", None);
-    let mut response = timeout_after(
-        CONNECT_AND_FETCH_HEAD_TIMEOUT,
-        "fetching HEAD",
-        client
-            .get(url)
-            .header(http::header::RANGE, format!("bytes={}-", start_byte))
-            .send(),
-    )
-    .await??;
+    let mut request = client
+        .get(url)
+        .header(http::header::RANGE, format!("bytes={}-", start_byte));
+    if let Some(etag) = etag.as_deref() {
+        request = request.header(http::header::IF_NONE_MATCH, etag);
+    }
+    if let Some(last_modified) = last_modified.as_deref() {
+        request = request.header(http::header::IF_MODIFIED_SINCE, last_modified);
+    }
+    let requested_at = Instant::now();
+    let mut response = timeout_after(CONNECT_AND_FETCH_HEAD_TIMEOUT, "fetching HEAD", request.send()).await??;
+    let time_to_first_byte = requested_at.elapsed();
+    // Hosts are only resolved for new connections, including those to where the response was redirected from
+    let mut hosts = vec![host_of(url)];
+    hosts.extend(response.url().host_str().map(ToOwned::to_owned));
+    hosts.dedup();
+    let dns_lookup_ms = hosts
+        .iter()
+        .filter_map(|host| host_limits.lookup_times.take(host))
+        .reduce(|a, b| a + b)
+        .map(millis);
 
     match response.status().as_u16() {
+        304 if existing_size.is_some() => {
+            progress.done(format!("{}: not modified - keeping the file on disk", url));
+            return Ok(());
+        }
+        200 if start_byte != 0 => {
+            progress.info(format!("{}: server ignored range request, restarting download", url));
+            start_byte = 0;
+            truncate = true;
+            hasher = Sha256::new();
+        }
         200..=299 => {}
         416 => {
-            // we assume that this means we have fully downloaded the item previously, and that the DB result was written already
-            // but not checked
-            progress.done(format!(
+            // we assume that this means we have fully downloaded the item previously, but didn't get to
+            // finalize it.
+            progress.info(format!(
                 "GET{}:{}: body-size = {}",
                 if start_byte != 0 {
                     "(resumed, already completed)"
@@ -209,16 +847,41 @@ async fn download_file_and_store_result(
                     ""
                 },
                 url,
-                ByteSize(start_byte as u64)
+                ByteSize(start_byte)
             ));
-            return Ok(());
         }
         _ => return Err(Error::HttpStatus(response.status())),
     };
 
-    let remaining_content_length = response
-        .content_length()
-        .ok_or(Error::InvalidHeader("expected content-length"))?;
+    // Only bodies of successful responses are stored, a 416 usually explains itself in HTML while the download is complete
+    if let Some(content_type) = response
+        .headers()
+        .get(http::header::CONTENT_TYPE)
+        .and_then(|t| t.to_str().ok())
+        .filter(|t| is_gzip && response.status().is_success() && t.starts_with("text/"))
+        .map(ToOwned::to_owned)
+    {
+        let body = timeout_after(
+            FETCH_CHUNK_TIMEOUT_SECONDS,
+            "fetching error page",
+            response.chunk().boxed(),
+        )
+        .await??
+        .unwrap_or_default();
+        return Err(unexpected_body(
+            url,
+            format_args!("content-type '{}'", content_type),
+            &body,
+        ));
+    }
+
+    let remaining_content_length = if response.status() == http::StatusCode::RANGE_NOT_SATISFIABLE {
+        0
+    } else {
+        response
+            .content_length()
+            .ok_or(Error::InvalidHeader("expected content-length"))?
+    };
 
     let content_length = (start_byte + remaining_content_length) as usize;
     progress.init(Some(content_length / 1024), Some("Kb".into()));
@@ -232,35 +895,52 @@ async fn download_file_and_store_result(
     if remaining_content_length != 0 {
         let mut out = blocking::Unblock::new(
             blocking::unblock({
-                let out_file = out_file.clone();
+                let tmp_file = tmp_file.clone();
                 move || {
                     std::fs::OpenOptions::new()
-                        .create(truncate)
+                        .create(true)
                         .truncate(truncate)
                         .write(truncate)
                         .append(!truncate)
-                        .open(out_file)
+                        .open(tmp_file)
                 }
             })
             .await
-            .map_err(|err| crate::Error::Message(format!("Failed to open '{}': {}", out_file.display(), err)))?,
+            .map_err(|err| crate::Error::Storage(format!("Failed to open '{}': {}", tmp_file.display(), err)))?,
         );
 
         let mut bytes_received = start_byte as usize;
-        while let Some(chunk) = timeout_after(
-            FETCH_CHUNK_TIMEOUT_SECONDS,
-            format!(
-                "fetched {} of {}",
-                ByteSize(bytes_received as u64),
-                ByteSize(content_length as u64)
-            ),
-            response.chunk().boxed(),
-        )
-        .await??
-        {
-            out.write_all(&chunk).await?;
-            bytes_received += chunk.len();
-            progress.set(bytes_received / 1024);
+        let received = async {
+            while let Some(chunk) = timeout_after(
+                FETCH_CHUNK_TIMEOUT_SECONDS,
+                format!(
+                    "fetched {} of {}",
+                    ByteSize(bytes_received as u64),
+                    ByteSize(content_length as u64)
+                ),
+                response.chunk().boxed(),
+            )
+            .await??
+            {
+                host_limits.throttle(chunk.len()).await;
+                out.write_all(&chunk).await?;
+                hasher.update(&chunk);
+                bytes_received += chunk.len();
+                BYTES_DOWNLOADED.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+                progress.set(bytes_received / 1024);
+            }
+            Ok::<_, Error>(())
+        }
+        .await;
+        if let Err(err) = received {
+            // Whatever arrived so far is kept in the temporary file, the next attempt asks for the rest only.
+            out.flush().await.ok();
+            progress.info(format!(
+                "GET:{}: keeping {} for resuming the download",
+                url,
+                ByteSize(bytes_received as u64)
+            ));
+            return Err(err);
         }
         progress.done(format!(
             "GET{}:{}: body-size = {}",
@@ -269,22 +949,55 @@ async fn download_file_and_store_result(
             ByteSize(bytes_received as u64)
         ));
         out.flush().await?;
-    } else {
-        progress.done(format!("{} already on disk - skipping", url))
+        if sync_to_disk {
+            progress.blocked("syncing download to disk", None);
+            let file = out.into_inner().await;
+            blocking::unblock(move || file.sync_all()).await?;
+        }
+    }
+    let timing = model::DownloadTiming {
+        dns_lookup_ms,
+        time_to_first_byte_ms: millis(time_to_first_byte),
+        total_ms: millis(requested_at.elapsed()),
+    };
+
+    if is_gzip {
+        progress.blocked("validating download", None);
+        ensure_gzip_magic(url, tmp_file.clone()).await?;
     }
 
+    if let Some(expected) = checksum {
+        let actual = hex::encode(hasher.finalize());
+        if actual != expected {
+            // The partial file is useless to us, start over next time.
+            blocking::unblock(move || std::fs::remove_file(tmp_file)).await?;
+            return Err(Error::ChecksumMismatch(expected, actual));
+        }
+    }
+    progress.blocked("finalizing download", None);
+    blocking::unblock({
+        let (assets, out_file) = (assets.clone(), out_file.clone());
+        move || assets.put(&tmp_file, &out_file)
+    })
+    .await?;
+
     if let Some(result_key) = result_key {
         let task_result = model::TaskResult::Download {
             kind: kind.to_owned(),
             url: url.to_owned(),
             content_length: content_length as u32,
-            content_type: response
-                .headers()
-                .get(http::header::CONTENT_TYPE)
-                .and_then(|t| t.to_str().ok())
-                .map(Into::into),
+            content_type: header(&response, http::header::CONTENT_TYPE),
+            etag: header(&response, http::header::ETAG),
+            last_modified: header(&response, http::header::LAST_MODIFIED),
+            status: Some(response.status().as_u16()),
+            final_url: Some(response.url().to_string()),
+            server: header(&response, http::header::SERVER),
+            timing: Some(timing),
         };
         results.insert(progress, &result_key, &task_result)?;
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod iobound_test;
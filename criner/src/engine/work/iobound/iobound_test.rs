@@ -0,0 +1,522 @@
+use super::{download_file_and_store_result, parse_proxy, Agent, Connectivity, DownloadRequest, HostLimits};
+use crate::{
+    engine::work::{artifact, asset_store, generic, generic::Processor, lanes},
+    model,
+    persistence::{temp_db::TempDb, TableAccess},
+    Error,
+};
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+fn host_limits() -> HostLimits {
+    HostLimits::new(1, Duration::default(), None, None, None, Connectivity::default())
+}
+
+#[test]
+fn processing_without_request_is_a_task_failure() {
+    let db = TempDb::new("iobound-no-request");
+    let (tx, _rx) = lanes::bounded::<()>(1);
+    let mut agent = Agent::new(
+        &db,
+        host_limits(),
+        asset_store::local(),
+        tx,
+        |_: Option<(String, String)>, _: &'static str, _: &model::Task, _: &Path| None,
+    )
+    .unwrap();
+    let mut progress = prodash::Tree::new().add_child("download");
+    let res = futures_lite::future::block_on(agent.process(&mut progress));
+    assert!(matches!(res, Err((Error::Bug(_), _))));
+}
+
+#[test]
+fn downloading_into_a_path_without_parent_is_an_error() {
+    let db = TempDb::new("iobound-no-parent");
+    let limits = host_limits();
+    let client = limits.connectivity.client_builder().build().unwrap();
+    let mut progress = prodash::Tree::new().add_child("download");
+    let res = futures_lite::future::block_on(download_file_and_store_result(
+        &mut progress,
+        None,
+        &db.open_results().unwrap(),
+        &client,
+        &limits,
+        &asset_store::local(),
+        "crate",
+        "https://static.crates.io/crates/a/a-1.0.0.crate",
+        PathBuf::new(),
+        None,
+        false,
+        false,
+        false,
+    ));
+    assert!(matches!(res, Err(Error::Message(_))));
+}
+
+/// Serve a file which is at version `"v2"` to `requests` requests, answering requests conditional on that version
+/// with 304, and return the base URL along with a receiver for the request headers of each request.
+fn serve(requests: usize) -> (String, std::sync::mpsc::Receiver<Vec<String>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        for stream in listener.incoming().take(requests) {
+            let mut stream = stream.unwrap();
+            let headers: Vec<String> = BufReader::new(&stream)
+                .lines()
+                .map(Result::unwrap)
+                .take_while(|line| !line.is_empty())
+                .collect();
+            let response = if headers[0].starts_with("GET /robots.txt ") {
+                "HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\nconnection: close\r\n\r\n"
+            } else if headers.iter().any(|h| h.to_lowercase() == "if-none-match: \"v2\"") {
+                "HTTP/1.1 304 Not Modified\r\netag: \"v2\"\r\nconnection: close\r\n\r\n"
+            } else {
+                "HTTP/1.1 200 OK\r\netag: \"v2\"\r\nserver: mock-cdn\r\ncontent-length: 3\r\nconnection: close\r\n\r\nnew"
+            };
+            stream.write_all(response.as_bytes()).unwrap();
+            tx.send(headers).unwrap();
+        }
+    });
+    (base_url, rx)
+}
+
+#[test]
+fn requests_go_through_the_proxy_if_one_is_set() {
+    let (proxy_url, requests) = serve(1);
+    let connectivity = Connectivity {
+        proxy: Some(parse_proxy(&proxy_url).unwrap()),
+        ..Default::default()
+    };
+    let client = connectivity.client_builder().build().unwrap();
+    let body = futures_lite::future::block_on(async_compat::Compat::new(async {
+        client
+            .get("http://crates.invalid/a/a-1.0.0.crate")
+            .send()
+            .await?
+            .text()
+            .await
+    }))
+    .unwrap();
+    assert_eq!(body, "new");
+    assert_eq!(
+        requests.recv().unwrap()[0],
+        "GET http://crates.invalid/a/a-1.0.0.crate HTTP/1.1",
+        "the proxy is asked for the URL"
+    );
+}
+
+#[test]
+fn proxies_are_parsed_from_urls() {
+    for url in [
+        "socks5://localhost:1080",
+        "socks5h://localhost:1080",
+        "http://proxy.example.com:3128",
+    ] {
+        assert!(parse_proxy(url).is_ok(), "{}", url);
+    }
+    assert!(
+        parse_proxy("ftp://proxy.example.com").is_err(),
+        "unknown schemes are refused"
+    );
+}
+
+#[test]
+fn refreshed_downloads_are_conditional() {
+    let db = TempDb::new("iobound-refresh");
+    let dir = &db.dir;
+    let (base_url, requests) = serve(3);
+    let url = format!("{}/crate/a/1.0.0/builds.json", base_url);
+    let out_file = dir.join("assets").join("a-1.0.0.docs-builds.json");
+    std::fs::create_dir_all(out_file.parent().unwrap()).unwrap();
+    std::fs::write(&out_file, "old").unwrap();
+
+    let results = db.open_results().unwrap();
+    let result_key = "a:1.0.0:download-docs-builds:1.0.0:docs-builds.json";
+    let mut progress = prodash::Tree::new().add_child("download");
+    results
+        .insert(
+            &mut progress,
+            result_key,
+            &model::TaskResult::Download {
+                kind: "docs-builds.json".into(),
+                url: url.clone(),
+                content_length: 3,
+                content_type: None,
+                etag: Some("\"v1\"".into()),
+                last_modified: None,
+                status: None,
+                final_url: None,
+                server: None,
+                timing: None,
+            },
+        )
+        .unwrap();
+
+    let limits = host_limits();
+    let client = limits.connectivity.client_builder().build().unwrap();
+    let mut refresh = || {
+        futures_lite::future::block_on(async_compat::Compat::new(download_file_and_store_result(
+            &mut progress,
+            Some(result_key.into()),
+            &results,
+            &client,
+            &limits,
+            &asset_store::local(),
+            "docs-builds.json",
+            &url,
+            out_file.clone(),
+            None,
+            false,
+            true,
+            false,
+        )))
+        .unwrap()
+    };
+
+    refresh();
+    assert_eq!(std::fs::read_to_string(&out_file).unwrap(), "new", "the file changed");
+    match results.get(result_key).unwrap() {
+        Some(model::TaskResult::Download {
+            etag,
+            status,
+            final_url,
+            server,
+            timing,
+            ..
+        }) => {
+            assert_eq!(etag.as_deref(), Some("\"v2\""));
+            assert_eq!(status, Some(200));
+            assert_eq!(final_url.as_deref(), Some(url.as_str()));
+            assert_eq!(server.as_deref(), Some("mock-cdn"));
+            let timing = timing.expect("the download was timed");
+            assert!(timing.time_to_first_byte_ms <= timing.total_ms);
+            assert_eq!(timing.dns_lookup_ms, None, "IP addresses aren't resolved");
+        }
+        _ => unreachable!("a download result"),
+    }
+    refresh();
+    assert_eq!(std::fs::read_to_string(&out_file).unwrap(), "new");
+
+    let _robots = requests.recv().unwrap();
+    assert!(requests
+        .recv()
+        .unwrap()
+        .iter()
+        .any(|h| h.to_lowercase() == "if-none-match: \"v1\""));
+    assert!(
+        requests
+            .recv()
+            .unwrap()
+            .iter()
+            .any(|h| h.to_lowercase() == "if-none-match: \"v2\""),
+        "the second refresh asks for changes since the first one"
+    );
+}
+
+#[test]
+fn downloads_not_matching_their_checksum_are_retried_from_the_mirror() {
+    let db = TempDb::new("iobound-mirror");
+    let dir = &db.dir;
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let request_line = BufReader::new(&stream).lines().next().unwrap().unwrap();
+            let body = if request_line.starts_with("GET /mirror/") {
+                "good"
+            } else {
+                "evil"
+            };
+            let response = if request_line.starts_with("GET /robots.txt ") {
+                "HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\nconnection: close\r\n\r\n".to_owned()
+            } else {
+                format!(
+                    "HTTP/1.1 200 OK\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+            };
+            stream.write_all(response.as_bytes()).ok();
+        }
+    });
+
+    let task = artifact::CRATE.task();
+    let mut task_key = String::new();
+    task.fq_key("a", "1.0.0", &mut task_key);
+    let out_file = dir.join("assets").join("a-1.0.0.crate");
+    let (tx, rx) = lanes::bounded(1);
+    futures_lite::future::block_on(tx.lane(lanes::Priority::Low).send(DownloadRequest {
+        output_file_path: out_file.clone(),
+        progress_name: "a:1.0.0".into(),
+        task_key: task_key.clone(),
+        task,
+        crate_name_and_version: Some(("a".into(), "1.0.0".into())),
+        kind: "crate",
+        url: format!("{}/crates/a/a-1.0.0.crate", base_url),
+        fallback_url: Some(format!("{}/mirror/a/a-1.0.0.crate", base_url)),
+        checksum: Some(hex::encode(<sha2::Sha256 as sha2::Digest>::digest(b"good"))),
+        is_gzip: false,
+        refresh: false,
+        priority: lanes::Priority::Low,
+    }))
+    .unwrap();
+    drop(tx);
+
+    let (tx_next, _rx_next) = lanes::bounded::<()>(1);
+    let agent = Agent::new(
+        &db,
+        host_limits(),
+        asset_store::local(),
+        tx_next,
+        |_: Option<(String, String)>, _: &'static str, _: &model::Task, _: &Path| None,
+    )
+    .unwrap();
+    let progress = prodash::Tree::new().add_child("download");
+    futures_lite::future::block_on(async_compat::Compat::new(generic::processor(
+        db.clone(),
+        progress,
+        rx,
+        agent,
+        0,
+    )))
+    .unwrap();
+
+    assert_eq!(std::fs::read_to_string(&out_file).unwrap(), "good");
+    assert!(db
+        .open_tasks()
+        .unwrap()
+        .get(&task_key)
+        .unwrap()
+        .unwrap()
+        .state
+        .is_complete());
+    let events: Vec<_> = db
+        .open_task_events()
+        .unwrap()
+        .with_key_prefix(&task_key)
+        .unwrap()
+        .into_iter()
+        .map(|e| e.event)
+        .collect();
+    assert_eq!(
+        events,
+        ["started", "failed", "started", "finished"],
+        "the mismatch is recorded before trying the mirror"
+    );
+}
+
+#[test]
+fn interrupted_downloads_are_resumed_with_a_range_request() {
+    let db = TempDb::new("iobound-resume");
+    let dir = &db.dir;
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    let (tx, requests) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        for stream in listener.incoming().take(3) {
+            let mut stream = stream.unwrap();
+            let headers: Vec<String> = BufReader::new(&stream)
+                .lines()
+                .map(Result::unwrap)
+                .take_while(|line| !line.is_empty())
+                .collect();
+            let response = if headers[0].starts_with("GET /robots.txt ") {
+                "HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\nconnection: close\r\n\r\n"
+            } else if headers.iter().any(|h| h.to_lowercase() == "range: bytes=2-") {
+                "HTTP/1.1 206 Partial Content\r\ncontent-range: bytes 2-3/4\r\ncontent-length: 2\r\nconnection: close\r\n\r\nod"
+            } else {
+                "HTTP/1.1 200 OK\r\ncontent-length: 4\r\nconnection: close\r\n\r\ngo"
+            };
+            stream.write_all(response.as_bytes()).unwrap();
+            tx.send(headers).unwrap();
+        }
+    });
+
+    let url = format!("{}/crates/a/a-1.0.0.crate", base_url);
+    let out_file = dir.join("assets").join("a-1.0.0.crate");
+    let results = db.open_results().unwrap();
+    let limits = host_limits();
+    let client = limits.connectivity.client_builder().build().unwrap();
+    let mut progress = prodash::Tree::new().add_child("download");
+    let mut download = || {
+        futures_lite::future::block_on(async_compat::Compat::new(download_file_and_store_result(
+            &mut progress,
+            None,
+            &results,
+            &client,
+            &limits,
+            &asset_store::local(),
+            "crate",
+            &url,
+            out_file.clone(),
+            Some(hex::encode(<sha2::Sha256 as sha2::Digest>::digest(b"good"))),
+            false,
+            false,
+            false,
+        )))
+    };
+
+    assert!(download().is_err(), "the connection closed half-way through the body");
+    assert!(!out_file.exists());
+    download().unwrap();
+    assert_eq!(
+        std::fs::read_to_string(&out_file).unwrap(),
+        "good",
+        "the checksum covers both parts"
+    );
+
+    let _robots = requests.recv().unwrap();
+    let range = |headers: Vec<String>| {
+        headers
+            .into_iter()
+            .find(|h| h.to_lowercase().starts_with("range: "))
+            .map(|h| h.to_lowercase())
+    };
+    assert_eq!(range(requests.recv().unwrap()).as_deref(), Some("range: bytes=0-"));
+    assert_eq!(
+        range(requests.recv().unwrap()).as_deref(),
+        Some("range: bytes=2-"),
+        "only the missing bytes are requested again"
+    );
+}
+
+#[test]
+fn completed_downloads_are_promoted_even_if_the_server_explains_the_unsatisfiable_range_in_html() {
+    let db = TempDb::new("iobound-resume-complete");
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for stream in listener.incoming().take(2) {
+            let mut stream = stream.unwrap();
+            let headers: Vec<String> = BufReader::new(&stream)
+                .lines()
+                .map(Result::unwrap)
+                .take_while(|line| !line.is_empty())
+                .collect();
+            let response = if headers[0].starts_with("GET /robots.txt ") {
+                "HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\nconnection: close\r\n\r\n"
+            } else {
+                "HTTP/1.1 416 Range Not Satisfiable\r\ncontent-type: text/html\r\ncontent-length: 13\r\nconnection: close\r\n\r\n<html></html>"
+            };
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+    });
+
+    let content = b"\x1f\x8bcompressed";
+    let out_file = db.dir.join("assets").join("a-1.0.0.crate");
+    std::fs::create_dir_all(out_file.parent().unwrap()).unwrap();
+    std::fs::write(out_file.with_file_name("a-1.0.0.crate.tmp"), content).unwrap();
+    let limits = host_limits();
+    futures_lite::future::block_on(async_compat::Compat::new(download_file_and_store_result(
+        &mut prodash::Tree::new().add_child("download"),
+        None,
+        &db.open_results().unwrap(),
+        &limits.connectivity.client_builder().build().unwrap(),
+        &limits,
+        &asset_store::local(),
+        "crate",
+        &format!("{}/crates/a/a-1.0.0.crate", base_url),
+        out_file.clone(),
+        Some(hex::encode(<sha2::Sha256 as sha2::Digest>::digest(content))),
+        true,
+        false,
+        false,
+    )))
+    .unwrap();
+    assert_eq!(std::fs::read(&out_file).unwrap(), content);
+}
+
+#[test]
+fn requests_are_rate_limited_by_host() {
+    let limits = HostLimits::new(1, Duration::default(), Some(20.0), None, None, Connectivity::default());
+    let mut progress = prodash::Tree::new().add_child("download");
+    let started_at = std::time::Instant::now();
+    futures_lite::future::block_on(async {
+        for _ in 0..5 {
+            limits.throttle_requests("crates.io", &mut progress).await;
+        }
+    });
+    assert!(started_at.elapsed() >= Duration::from_millis(240));
+
+    let started_at = std::time::Instant::now();
+    futures_lite::future::block_on(limits.throttle_requests("example.com", &mut progress));
+    assert!(
+        started_at.elapsed() < Duration::from_millis(240),
+        "other hosts have their own limit"
+    );
+}
+
+#[test]
+fn files_on_disk_keep_the_result_of_their_download() {
+    let db = TempDb::new("iobound-on-disk");
+    let out_file = db.dir.join("assets").join("a-1.0.0.crate");
+    std::fs::create_dir_all(out_file.parent().unwrap()).unwrap();
+    std::fs::write(&out_file, "crate").unwrap();
+    let results = db.open_results().unwrap();
+    let limits = host_limits();
+    let client = limits.connectivity.client_builder().build().unwrap();
+    let mut progress = prodash::Tree::new().add_child("download");
+    let mut download = |result_key: &str| {
+        futures_lite::future::block_on(download_file_and_store_result(
+            &mut progress,
+            Some(result_key.into()),
+            &results,
+            &client,
+            &limits,
+            &asset_store::local(),
+            "crate",
+            "http://127.0.0.1:1/a/a-1.0.0.crate",
+            out_file.clone(),
+            None,
+            true,
+            false,
+            false,
+        ))
+        .unwrap()
+    };
+
+    let downloaded = model::TaskResult::Download {
+        kind: "crate".into(),
+        url: "http://127.0.0.1:1/a/a-1.0.0.crate".into(),
+        content_length: 5,
+        content_type: Some("application/gzip".into()),
+        etag: Some("\"v1\"".into()),
+        last_modified: None,
+        status: Some(200),
+        final_url: Some("http://127.0.0.1:1/a/a-1.0.0.crate".into()),
+        server: Some("mock-cdn".into()),
+        timing: Some(model::DownloadTiming {
+            dns_lookup_ms: Some(1),
+            time_to_first_byte_ms: 2,
+            total_ms: 3,
+        }),
+    };
+    let key = "a:1.0.0:download:1.0.0:crate";
+    results
+        .insert(&mut prodash::Tree::new().add_child("insert"), key, &downloaded)
+        .unwrap();
+    download(key);
+    assert_eq!(
+        format!("{:?}", results.get(key).unwrap()),
+        format!("{:?}", Some(downloaded)),
+        "what is known about the download is kept"
+    );
+
+    let key = "a:1.0.0:download:1.0.0:crate-without-result";
+    download(key);
+    match results.get(key).unwrap() {
+        Some(model::TaskResult::Download {
+            content_length, status, ..
+        }) => {
+            assert_eq!(content_length, 5);
+            assert_eq!(status, None, "it wasn't downloaded this time");
+        }
+        _ => unreachable!("a download result for the file on disk"),
+    }
+}
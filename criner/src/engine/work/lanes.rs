@@ -0,0 +1,83 @@
+//! Two lanes of requests feeding processors, so that urgent requests go ahead of the bulk of work which may take days.
+use futures_lite::future;
+
+/// How urgently a request should be processed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    /// Requests somebody is waiting for, like extractions needed by reports or crate versions which were just published
+    High,
+    /// Everything scheduled by going through the backlog of all crate versions
+    Low,
+}
+
+pub struct Sender<T> {
+    high: async_channel::Sender<T>,
+    low: async_channel::Sender<T>,
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        Sender {
+            high: self.high.clone(),
+            low: self.low.clone(),
+        }
+    }
+}
+
+impl<T> Sender<T> {
+    /// The channel of the lane with the given `priority`
+    pub fn lane(&self, priority: Priority) -> &async_channel::Sender<T> {
+        match priority {
+            Priority::High => &self.high,
+            Priority::Low => &self.low,
+        }
+    }
+}
+
+pub struct Receiver<T> {
+    high: async_channel::Receiver<T>,
+    low: async_channel::Receiver<T>,
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        Receiver {
+            high: self.high.clone(),
+            low: self.low.clone(),
+        }
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Receive the next request, taking it from the low priority lane only if there is none of high priority.
+    /// Fails once both lanes are closed and empty.
+    pub async fn recv(&self) -> Result<T, async_channel::RecvError> {
+        if let Ok(item) = self.high.try_recv() {
+            return Ok(item);
+        }
+        match future::or(self.high.recv(), self.low.recv()).await {
+            Ok(item) => Ok(item),
+            // One of the lanes is closed and empty, so whatever is left is in the other one
+            Err(_) => match self.high.recv().await {
+                Ok(item) => Ok(item),
+                Err(_) => self.low.recv().await,
+            },
+        }
+    }
+}
+
+/// Create both lanes, each of which holds up to `capacity` requests
+pub fn bounded<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    let (high, high_rx) = async_channel::bounded(capacity);
+    let (low, low_rx) = async_channel::bounded(capacity);
+    (
+        Sender { high, low },
+        Receiver {
+            high: high_rx,
+            low: low_rx,
+        },
+    )
+}
+
+#[cfg(test)]
+mod lanes_test;
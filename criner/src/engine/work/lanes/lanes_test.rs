@@ -0,0 +1,23 @@
+use super::{bounded, Priority};
+
+#[test]
+fn high_priority_requests_go_first_and_all_are_received_once_closed() {
+    let (tx, rx) = bounded(4);
+    futures_lite::future::block_on(async {
+        tx.lane(Priority::Low).send(1).await.unwrap();
+        tx.lane(Priority::Low).send(2).await.unwrap();
+        tx.lane(Priority::High).send(3).await.unwrap();
+        assert_eq!(rx.recv().await, Ok(3), "high priority requests jump the queue");
+        assert_eq!(rx.recv().await, Ok(1));
+
+        tx.lane(Priority::High).send(4).await.unwrap();
+        drop(tx);
+        assert_eq!(rx.recv().await, Ok(4));
+        assert_eq!(
+            rx.recv().await,
+            Ok(2),
+            "requests remain receivable after the lanes were closed"
+        );
+        assert!(rx.recv().await.is_err());
+    });
+}
@@ -0,0 +1,311 @@
+//! Parsing `Cargo.toml` files of extracted crates into `TaskResult::Manifest`, so that reports don't have to parse them
+//! on their own.
+//!
+//! Manifests of crates published before cargo normalized them may declare fields in many ways, which is why parsing is
+//! lenient and ignores fields it doesn't understand.
+//!
+//! Manifests which weren't normalized, like those of projects in a workspace, may inherit fields from the workspace with
+//! `workspace = true`. These are resolved the way `cargo publish` does before looking at them, see `normalize()`.
+use crate::{
+    engine::work::cpubound,
+    model::{DependencyKind, ManifestDependency, ManifestPackage, TaskResult},
+    Error, Result,
+};
+use serde::de::DeserializeOwned;
+use serde_derive::Deserialize;
+use std::collections::BTreeMap;
+
+/// All tables with dependencies, at the top level of a manifest or below a `target`
+const DEPENDENCY_TABLES: &[&str] = &[
+    "dependencies",
+    "dev-dependencies",
+    "dev_dependencies",
+    "build-dependencies",
+    "build_dependencies",
+];
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "kebab-case")]
+struct Manifest {
+    package: Option<Package>,
+    /// Very old manifests call the package a project
+    project: Option<Package>,
+    features: BTreeMap<String, Vec<String>>,
+    dependencies: BTreeMap<String, Dependency>,
+    #[serde(alias = "dev_dependencies")]
+    dev_dependencies: BTreeMap<String, Dependency>,
+    #[serde(alias = "build_dependencies")]
+    build_dependencies: BTreeMap<String, Dependency>,
+    target: BTreeMap<String, Target>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "kebab-case")]
+struct Target {
+    dependencies: BTreeMap<String, Dependency>,
+    #[serde(alias = "dev_dependencies")]
+    dev_dependencies: BTreeMap<String, Dependency>,
+    #[serde(alias = "build_dependencies")]
+    build_dependencies: BTreeMap<String, Dependency>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "kebab-case")]
+struct Package {
+    name: String,
+    version: Option<toml::Value>,
+    edition: Option<String>,
+    rust_version: Option<String>,
+    authors: Option<toml::Value>,
+    description: Option<String>,
+    license: Option<String>,
+    license_file: Option<String>,
+    repository: Option<String>,
+    homepage: Option<String>,
+    documentation: Option<String>,
+    /// A path, or `false` if there is no readme
+    readme: Option<toml::Value>,
+    keywords: Vec<String>,
+    categories: Vec<String>,
+    /// A path, or `false` if there is no build script
+    build: Option<toml::Value>,
+    links: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Dependency {
+    Requirement(String),
+    Detailed(DetailedDependency),
+}
+
+#[derive(Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct DetailedDependency {
+    version: Option<String>,
+    package: Option<String>,
+    registry: Option<String>,
+    optional: bool,
+    #[serde(alias = "default_features")]
+    default_features: bool,
+    features: Vec<String>,
+}
+
+impl Default for DetailedDependency {
+    fn default() -> Self {
+        DetailedDependency {
+            version: None,
+            package: None,
+            registry: None,
+            optional: false,
+            default_features: true,
+            features: Vec::new(),
+        }
+    }
+}
+
+fn string(value: Option<toml::Value>) -> Option<String> {
+    value.and_then(|v| v.as_str().map(ToOwned::to_owned))
+}
+
+fn add_dependencies(
+    out: &mut Vec<ManifestDependency>,
+    dependencies: BTreeMap<String, Dependency>,
+    kind: DependencyKind,
+    target: Option<&str>,
+) {
+    out.extend(dependencies.into_iter().map(|(name, dependency)| {
+        let dependency = match dependency {
+            Dependency::Requirement(req) => DetailedDependency {
+                version: Some(req),
+                ..Default::default()
+            },
+            Dependency::Detailed(dependency) => dependency,
+        };
+        ManifestDependency {
+            name,
+            package: dependency.package,
+            kind,
+            req: dependency.version,
+            target: target.map(ToOwned::to_owned),
+            registry: dependency.registry,
+            optional: dependency.optional,
+            default_features: dependency.default_features,
+            features: dependency.features,
+        }
+    }));
+}
+
+/// Returns true if `value` is a field inherited from the workspace, like `license = { workspace = true }`
+fn is_inherited(value: &toml::Value) -> bool {
+    value.get("workspace").and_then(toml::Value::as_bool) == Some(true)
+}
+
+/// The dependency declared as `member` in a workspace member, with everything it inherits from the dependency of the
+/// same name in the workspace, if there is one. Features of both are combined.
+fn inherited_dependency(member: &toml::value::Table, in_workspace: Option<&toml::Value>) -> toml::Value {
+    let mut dependency = match in_workspace {
+        Some(toml::Value::String(req)) => std::iter::once(("version".to_owned(), req.clone().into())).collect(),
+        Some(toml::Value::Table(dependency)) => dependency.clone(),
+        _ => toml::value::Table::new(),
+    };
+    for (key, value) in member.iter().filter(|(key, _)| *key != "workspace") {
+        match (dependency.get_mut(key), value) {
+            (Some(toml::Value::Array(features)), toml::Value::Array(more)) if key == "features" => features.extend(
+                more.iter()
+                    .filter(|f| !features.contains(f))
+                    .cloned()
+                    .collect::<Vec<_>>(),
+            ),
+            _ => {
+                dependency.insert(key.clone(), value.clone());
+            }
+        }
+    }
+    toml::Value::Table(dependency)
+}
+
+fn normalize_dependencies(table: &mut toml::Value, workspace_dependencies: Option<&toml::Value>) {
+    for name in DEPENDENCY_TABLES {
+        if let Some(toml::Value::Table(dependencies)) = table.get_mut(*name) {
+            for (name, dependency) in dependencies.iter_mut().filter(|(_, d)| is_inherited(d)) {
+                if let toml::Value::Table(member) = dependency {
+                    *dependency = inherited_dependency(member, workspace_dependencies.and_then(|d| d.get(name)));
+                }
+            }
+        }
+    }
+}
+
+/// Resolve the fields of `manifest` inherited with `workspace = true` like `cargo publish` does, using the `[workspace]`
+/// of the `root` manifest of its workspace, or the one in `manifest` itself if it is the root.
+/// Package fields which can't be resolved are removed as their value is unknown, and dependencies which can't be
+/// resolved are kept without version requirement.
+pub fn normalize(manifest: &mut toml::Value, root: Option<&toml::Value>) {
+    let workspace = root
+        .unwrap_or(manifest)
+        .get("workspace")
+        .cloned()
+        .unwrap_or_else(|| toml::Value::Table(Default::default()));
+    for section in &["package", "project"] {
+        if let Some(toml::Value::Table(package)) = manifest.get_mut(*section) {
+            let inherited: Vec<_> = package
+                .iter()
+                .filter(|(_, value)| is_inherited(value))
+                .map(|(field, _)| field.clone())
+                .collect();
+            for field in inherited {
+                match workspace.get("package").and_then(|package| package.get(&field)) {
+                    Some(value) => package.insert(field, value.clone()),
+                    None => package.remove(&field),
+                };
+            }
+        }
+    }
+    let workspace_dependencies = workspace.get("dependencies");
+    normalize_dependencies(manifest, workspace_dependencies);
+    if let Some(toml::Value::Table(targets)) = manifest.get_mut("target") {
+        for (_, target) in targets.iter_mut() {
+            normalize_dependencies(target, workspace_dependencies);
+        }
+    }
+}
+
+/// Deserialize the `Cargo.toml` file with the given `content` after normalizing it, or `None` if it is invalid
+pub fn normalized<T: DeserializeOwned>(content: &str) -> Option<T> {
+    let mut manifest: toml::Value = toml::from_str(content).ok()?;
+    normalize(&mut manifest, None);
+    manifest.try_into().ok()
+}
+
+/// Parse the `Cargo.toml` file with the given `content` into a `TaskResult::Manifest`
+pub fn parse(content: &[u8]) -> Result<TaskResult> {
+    let invalid = |err: toml::de::Error| Error::Corrupt(format!("Invalid Cargo.toml: {}", err));
+    let mut manifest: toml::Value = toml::from_slice(content).map_err(invalid)?;
+    normalize(&mut manifest, None);
+    let manifest: Manifest = manifest.try_into().map_err(invalid)?;
+    let package = manifest.package.or(manifest.project).unwrap_or_default();
+
+    let mut dependencies = Vec::new();
+    add_dependencies(&mut dependencies, manifest.dependencies, DependencyKind::Normal, None);
+    add_dependencies(
+        &mut dependencies,
+        manifest.dev_dependencies,
+        DependencyKind::Development,
+        None,
+    );
+    add_dependencies(
+        &mut dependencies,
+        manifest.build_dependencies,
+        DependencyKind::Build,
+        None,
+    );
+    for (target, deps) in manifest.target {
+        add_dependencies(
+            &mut dependencies,
+            deps.dependencies,
+            DependencyKind::Normal,
+            Some(&target),
+        );
+        add_dependencies(
+            &mut dependencies,
+            deps.dev_dependencies,
+            DependencyKind::Development,
+            Some(&target),
+        );
+        add_dependencies(
+            &mut dependencies,
+            deps.build_dependencies,
+            DependencyKind::Build,
+            Some(&target),
+        );
+    }
+    dependencies.sort_by(|a, b| (a.kind, &a.target, &a.name).cmp(&(b.kind, &b.target, &b.name)));
+
+    Ok(TaskResult::Manifest {
+        package: Box::new(ManifestPackage {
+            name: package.name,
+            version: string(package.version).unwrap_or_default(),
+            edition: package.edition,
+            rust_version: package.rust_version,
+            authors: match package.authors {
+                Some(toml::Value::Array(authors)) => authors
+                    .into_iter()
+                    .filter_map(|a| a.as_str().map(ToOwned::to_owned))
+                    .collect(),
+                _ => Vec::new(),
+            },
+            description: package.description,
+            license: package.license,
+            license_file: package.license_file,
+            repository: package.repository,
+            homepage: package.homepage,
+            documentation: package.documentation,
+            readme: string(package.readme),
+            keywords: package.keywords,
+            categories: package.categories,
+            build: string(package.build),
+            links: package.links,
+        }),
+        features: manifest.features,
+        dependencies,
+    })
+}
+
+/// The key of the manifest stored for the given crate version, next to the result of extracting it
+pub fn result_key(crate_name: &str, crate_version: &str, buf: &mut String) {
+    TaskResult::Manifest {
+        package: Default::default(),
+        features: Default::default(),
+        dependencies: Default::default(),
+    }
+    .fq_key(
+        crate_name,
+        crate_version,
+        &cpubound::default_persisted_extraction_task(),
+        buf,
+    );
+}
+
+#[cfg(test)]
+mod manifest_test;
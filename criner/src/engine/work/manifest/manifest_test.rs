@@ -0,0 +1,208 @@
+use super::{normalize, parse, result_key};
+use crate::model::{DependencyKind, ManifestDependency, TaskResult};
+
+#[test]
+fn normalized_manifests_are_parsed_into_package_features_and_sorted_dependencies() {
+    let manifest = r#"
+[package]
+edition = "2018"
+name = "a"
+version = "1.2.0"
+authors = ["Alice <alice@example.com>"]
+build = false
+readme = "README.md"
+license = "MIT OR Apache-2.0"
+keywords = ["no-std"]
+
+[features]
+default = ["std"]
+std = ["serde/std"]
+
+[dependencies.serde]
+version = "1.0"
+default-features = false
+optional = true
+
+[dependencies.log-crate]
+version = "0.4"
+package = "log"
+
+[dev-dependencies.tempfile]
+version = "3"
+
+[target."cfg(windows)".dependencies.winapi]
+version = "0.3"
+features = ["fileapi"]
+
+[build-dependencies]
+cc = "1.0"
+"#;
+    let (package, features, dependencies) = match parse(manifest.as_bytes()).unwrap() {
+        TaskResult::Manifest {
+            package,
+            features,
+            dependencies,
+        } => (package, features, dependencies),
+        _ => unreachable!("manifests are parsed into manifests"),
+    };
+    assert_eq!(package.name, "a");
+    assert_eq!(package.version, "1.2.0");
+    assert_eq!(package.edition.as_deref(), Some("2018"));
+    assert_eq!(package.authors, vec!["Alice <alice@example.com>"]);
+    assert_eq!(package.build, None, "'false' means there is no build script");
+    assert_eq!(package.readme.as_deref(), Some("README.md"));
+    assert_eq!(package.keywords, vec!["no-std"]);
+    assert_eq!(features["std"], vec!["serde/std"]);
+
+    let dependency = |name: &str, kind, req: &str| ManifestDependency {
+        name: name.into(),
+        package: None,
+        kind,
+        req: Some(req.into()),
+        target: None,
+        registry: None,
+        optional: false,
+        default_features: true,
+        features: Vec::new(),
+    };
+    assert_eq!(
+        dependencies,
+        vec![
+            ManifestDependency {
+                package: Some("log".into()),
+                ..dependency("log-crate", DependencyKind::Normal, "0.4")
+            },
+            ManifestDependency {
+                optional: true,
+                default_features: false,
+                ..dependency("serde", DependencyKind::Normal, "1.0")
+            },
+            ManifestDependency {
+                target: Some("cfg(windows)".into()),
+                features: vec!["fileapi".into()],
+                ..dependency("winapi", DependencyKind::Normal, "0.3")
+            },
+            dependency("tempfile", DependencyKind::Development, "3"),
+            dependency("cc", DependencyKind::Build, "1.0"),
+        ]
+    );
+}
+
+#[test]
+fn old_manifests_with_projects_and_path_dependencies_are_parsed_too() {
+    let manifest = r#"
+[project]
+name = "old"
+version = "0.1.0"
+authors = ["Bob"]
+
+[dependencies.local]
+path = "../local"
+
+[dev_dependencies]
+quickcheck = "*"
+"#;
+    match parse(manifest.as_bytes()).unwrap() {
+        TaskResult::Manifest {
+            package, dependencies, ..
+        } => {
+            assert_eq!(package.name, "old");
+            assert_eq!(package.authors, vec!["Bob"]);
+            assert_eq!(dependencies.len(), 2);
+            assert_eq!(
+                dependencies[0].req, None,
+                "path dependencies have no version requirement"
+            );
+            assert_eq!(dependencies[1].kind, DependencyKind::Development);
+        }
+        _ => unreachable!("manifests are parsed into manifests"),
+    }
+}
+
+#[test]
+fn fields_inherited_from_the_workspace_are_resolved_like_cargo_publish_does() {
+    let root: toml::Value = toml::from_str(
+        r#"
+[workspace.package]
+version = "1.2.3"
+license = "MIT"
+
+[workspace.dependencies]
+serde = { version = "1.0", features = ["derive"] }
+log = "0.4"
+"#,
+    )
+    .unwrap();
+    let mut manifest: toml::Value = toml::from_str(
+        r#"
+[package]
+name = "member"
+version.workspace = true
+license = { workspace = true }
+description.workspace = true
+
+[dependencies]
+serde = { workspace = true, features = ["rc"], optional = true }
+
+[target.'cfg(unix)'.dev-dependencies]
+log.workspace = true
+"#,
+    )
+    .unwrap();
+    normalize(&mut manifest, Some(&root));
+    let expected: toml::Value = toml::from_str(
+        r#"
+[package]
+name = "member"
+version = "1.2.3"
+license = "MIT"
+
+[dependencies]
+serde = { version = "1.0", features = ["derive", "rc"], optional = true }
+
+[target.'cfg(unix)'.dev-dependencies]
+log = { version = "0.4" }
+"#,
+    )
+    .unwrap();
+    assert_eq!(
+        manifest, expected,
+        "description isn't in the workspace, so it's unknown"
+    );
+}
+
+#[test]
+fn manifests_with_fields_inherited_from_an_unknown_workspace_are_parsed_without_them() {
+    let manifest = r#"
+[package]
+name = "member"
+version = "0.1.0"
+license.workspace = true
+
+[dependencies]
+serde = { workspace = true, optional = true }
+"#;
+    match parse(manifest.as_bytes()).unwrap() {
+        TaskResult::Manifest {
+            package, dependencies, ..
+        } => {
+            assert_eq!(package.license, None);
+            assert_eq!(dependencies.len(), 1);
+            assert_eq!(dependencies[0].req, None);
+            assert!(dependencies[0].optional);
+        }
+        _ => unreachable!("manifests are parsed into manifests"),
+    }
+}
+
+#[test]
+fn invalid_manifests_are_errors() {
+    assert!(parse(b"[package\nname = ").is_err());
+}
+
+#[test]
+fn manifests_are_stored_next_to_the_extracted_crate() {
+    let mut key = String::new();
+    result_key("a", "1.0.0", &mut key);
+    assert_eq!(key, "a:1.0.0:extract_crate:1.0.0:manifest");
+}
@@ -1,5 +1,14 @@
+pub mod artifact;
+pub mod asset_store;
+pub mod dns;
+pub mod download_schedule;
 pub mod generic;
 pub mod iobound;
+pub mod lanes;
+pub mod manifest;
+pub mod retry;
+pub mod robots;
 pub mod schedule;
+pub mod task_graph;
 
 pub mod cpubound;
@@ -0,0 +1,97 @@
+//! How often and when failed tasks are tried again, like `max=5,base=30s`
+use crate::model::{Task, TaskState};
+use std::{
+    hash::{Hash, Hasher},
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+
+/// The longest time to wait before trying a task again, no matter how often it failed
+const MAX_DELAY: Duration = Duration::from_secs(60 * 60 * 24);
+
+/// Failed tasks are tried again after a delay doubling with each failure, starting at `base`, until they failed
+/// `max_attempts` times.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// The amount of failed attempts after which a task is given up on
+    pub max_attempts: usize,
+    /// The delay after the first failure, which doubles with each further one
+    pub base: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 8,
+            base: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// How long to wait after the `failures`th failure before trying again, or `None` if the task is given up on.
+    ///
+    /// Half of the delay is jitter derived from `seed`, the same each time for the same seed and amount of failures,
+    /// so tasks failing together aren't all tried again at once, but every look at a task comes to the same conclusion.
+    pub fn delay_after(&self, failures: usize, seed: &str) -> Option<Duration> {
+        if failures >= self.max_attempts {
+            return None;
+        }
+        let exponent = failures.saturating_sub(1).min(31) as u32;
+        let delay = self.base.saturating_mul(1 << exponent).min(MAX_DELAY);
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        (seed, failures).hash(&mut hasher);
+        let jitter = (hasher.finish() % 1024) as f64 / 1024.0;
+        Some(delay / 2 + (delay / 2).mul_f64(jitter))
+    }
+
+    /// When `task` may be tried again, or `None` if it's given up on. Tasks which didn't fail may be started any time.
+    ///
+    /// Attempts which were only interrupted by stopping the run don't count as failures.
+    pub fn next_attempt_at(&self, task: &Task, seed: &str) -> Option<SystemTime> {
+        match &task.state {
+            TaskState::AttemptsWithFailure(errors) => {
+                match errors.iter().filter(|e| !TaskState::is_interruption(e)).count() {
+                    0 => Some(task.stored_at),
+                    failures => self.delay_after(failures, seed).map(|delay| task.stored_at + delay),
+                }
+            }
+            TaskState::PermanentFailure(_) => None,
+            TaskState::NotStarted | TaskState::InProgress(_) | TaskState::Complete => Some(task.stored_at),
+        }
+    }
+}
+
+impl FromStr for RetryPolicy {
+    type Err = String;
+
+    /// Parse comma separated `max=<attempts>` and `base=<duration>` like `max=5,base=30s`, each of which defaults to
+    /// the default policy if omitted.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut policy = RetryPolicy::default();
+        for token in s.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+            let mut parts = token.splitn(2, '=').map(str::trim);
+            match (parts.next(), parts.next()) {
+                (Some("max"), Some(max)) => {
+                    policy.max_attempts = max
+                        .parse()
+                        .map_err(|err| format!("Invalid amount of attempts '{}': {}", max, err))?
+                }
+                (Some("base"), Some(base)) => {
+                    policy.base =
+                        humantime::parse_duration(base).map_err(|err| format!("Invalid delay '{}': {}", base, err))?
+                }
+                _ => {
+                    return Err(format!(
+                        "Expected 'max=<attempts>' or 'base=<duration>', got '{}'",
+                        token
+                    ))
+                }
+            }
+        }
+        Ok(policy)
+    }
+}
+
+#[cfg(test)]
+mod retry_test;
@@ -0,0 +1,117 @@
+use super::RetryPolicy;
+use crate::model::{Task, TaskState};
+use std::time::{Duration, SystemTime};
+
+fn failed(times: usize, stored_at: SystemTime) -> Task {
+    Task {
+        stored_at,
+        state: TaskState::AttemptsWithFailure(vec!["error".into(); times]),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn policies_are_parsed_with_defaults_for_what_is_omitted() {
+    assert_eq!(
+        "max=5,base=30s".parse::<RetryPolicy>().unwrap(),
+        RetryPolicy {
+            max_attempts: 5,
+            base: Duration::from_secs(30),
+        }
+    );
+    assert_eq!(
+        "base=1m".parse::<RetryPolicy>().unwrap(),
+        RetryPolicy {
+            base: Duration::from_secs(60),
+            ..Default::default()
+        }
+    );
+    assert_eq!("".parse::<RetryPolicy>().unwrap(), RetryPolicy::default());
+    for invalid in ["max=many", "base=soon", "min=1", "5"] {
+        assert!(invalid.parse::<RetryPolicy>().is_err(), "{}", invalid);
+    }
+}
+
+#[test]
+fn delays_double_with_each_failure_with_half_of_them_being_jitter() {
+    let policy: RetryPolicy = "max=5,base=100s".parse().unwrap();
+    for (failures, full_delay) in [(1, 100), (2, 200), (3, 400), (4, 800)] {
+        let full_delay = Duration::from_secs(full_delay);
+        let delay = policy.delay_after(failures, "a:1.0.0").unwrap();
+        assert!(delay >= full_delay / 2 && delay <= full_delay, "{:?}", delay);
+        assert_eq!(
+            policy.delay_after(failures, "a:1.0.0"),
+            Some(delay),
+            "the same seed, the same delay"
+        );
+    }
+    assert_eq!(
+        policy.delay_after(5, "a:1.0.0"),
+        None,
+        "given up after the last attempt"
+    );
+    assert!(
+        (0..10).any(|n| policy.delay_after(1, &format!("{}:1.0.0", n)) != policy.delay_after(1, "a:1.0.0")),
+        "other seeds have other jitter"
+    );
+}
+
+#[test]
+fn delays_are_at_most_a_day() {
+    let policy: RetryPolicy = "max=100,base=1h".parse().unwrap();
+    assert!(policy.delay_after(99, "a:1.0.0").unwrap() <= Duration::from_secs(60 * 60 * 24));
+}
+
+#[test]
+fn failed_tasks_are_due_after_their_delay_and_permanent_failures_never() {
+    let policy = RetryPolicy::default();
+    let stored_at = SystemTime::now();
+    let delay = policy.delay_after(1, "a:1.0.0").unwrap();
+    assert_eq!(
+        policy.next_attempt_at(&failed(1, stored_at), "a:1.0.0"),
+        Some(stored_at + delay)
+    );
+    assert_eq!(policy.next_attempt_at(&failed(8, stored_at), "a:1.0.0"), None);
+    let gone = Task {
+        state: TaskState::PermanentFailure(vec!["410 Gone".into()]),
+        ..Default::default()
+    };
+    assert_eq!(policy.next_attempt_at(&gone, "a:1.0.0"), None);
+    let not_started = Task::default();
+    assert_eq!(
+        policy.next_attempt_at(&not_started, "a:1.0.0"),
+        Some(not_started.stored_at)
+    );
+}
+
+#[test]
+fn interrupted_attempts_are_not_counted_as_failures() {
+    let policy: RetryPolicy = "max=2,base=100s".parse().unwrap();
+    let stored_at = SystemTime::now();
+    let interrupted = |errors: &[&str]| Task {
+        stored_at,
+        state: TaskState::AttemptsWithFailure(errors.iter().map(|e| e.to_string()).collect()),
+        ..Default::default()
+    };
+    assert_eq!(
+        policy.next_attempt_at(
+            &interrupted(&[
+                TaskState::INTERRUPTED,
+                TaskState::INTERRUPTED_BEFORE_RECORDED,
+                TaskState::INTERRUPTED
+            ]),
+            "a:1.0.0"
+        ),
+        Some(stored_at),
+        "restarts alone neither delay a task nor give up on it"
+    );
+    assert_eq!(
+        policy.next_attempt_at(&interrupted(&["error", TaskState::INTERRUPTED]), "a:1.0.0"),
+        Some(stored_at + policy.delay_after(1, "a:1.0.0").unwrap()),
+        "only the real failure is backed off on"
+    );
+    assert_eq!(
+        policy.next_attempt_at(&interrupted(&["error", TaskState::INTERRUPTED, "error"]), "a:1.0.0"),
+        None
+    );
+}
@@ -0,0 +1,100 @@
+//! Just enough of robots.txt to be a polite guest on hosts other than crates.io
+use std::time::Duration;
+
+/// The name we identify as, both in robots.txt groups and in the user agent header
+pub const USER_AGENT: &str = "criner";
+
+#[derive(Debug, Default, Clone)]
+struct Group {
+    /// Path patterns, with `true` if they are allowed
+    rules: Vec<(bool, String)>,
+    crawl_delay: Option<Duration>,
+}
+
+/// The rules of a robots.txt file which apply to us
+#[derive(Debug, Default, Clone)]
+pub struct Robots {
+    group: Group,
+}
+
+impl Robots {
+    /// A robots.txt which allows everything, used if a host doesn't have one
+    pub fn allow_all() -> Robots {
+        Robots::default()
+    }
+
+    /// Parse robots.txt and keep only the group matching `user_agent`, or the one for all user agents if there is none.
+    pub fn parse(text: &str, user_agent: &str) -> Robots {
+        let mut specific: Option<Group> = None;
+        let mut wildcard: Option<Group> = None;
+        let mut agents = Vec::new();
+        let mut in_rules = false;
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            let (key, value) = match line.find(':') {
+                Some(pos) => (line[..pos].trim().to_ascii_lowercase(), line[pos + 1..].trim()),
+                None => continue,
+            };
+            if key == "user-agent" {
+                if in_rules {
+                    agents.clear();
+                    in_rules = false;
+                }
+                agents.push(value.to_ascii_lowercase());
+                continue;
+            }
+            in_rules = true;
+            let mut groups = Vec::with_capacity(2);
+            if agents.iter().any(|a| a.eq_ignore_ascii_case(user_agent)) {
+                groups.push(specific.get_or_insert_with(Default::default));
+            }
+            if agents.iter().any(|a| a == "*") {
+                groups.push(wildcard.get_or_insert_with(Default::default));
+            }
+            for group in groups {
+                match key.as_str() {
+                    "allow" | "disallow" if !value.is_empty() => group.rules.push((key == "allow", value.to_owned())),
+                    "crawl-delay" => {
+                        group.crawl_delay = value
+                            .parse::<f64>()
+                            .ok()
+                            .map(|secs| Duration::from_secs_f64(secs.max(0.0)))
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Robots {
+            group: specific.or(wildcard).unwrap_or_default(),
+        }
+    }
+
+    /// The time to wait between requests, if the host asked for it
+    pub fn crawl_delay(&self) -> Option<Duration> {
+        self.group.crawl_delay
+    }
+
+    /// Returns true if `path`, including its query, may be fetched. The most specific rule wins, `allow` wins ties.
+    pub fn is_allowed(&self, path: &str) -> bool {
+        self.group
+            .rules
+            .iter()
+            .filter(|(_, pattern)| matches(pattern.as_bytes(), path.as_bytes()))
+            .max_by_key(|(allow, pattern)| (pattern.len(), *allow))
+            .map(|(allow, _)| *allow)
+            .unwrap_or(true)
+    }
+}
+
+/// Match `path` against a robots.txt prefix `pattern`, which may contain `*` wildcards and end with `$`.
+fn matches(pattern: &[u8], path: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => true,
+        Some((b'$', [])) => path.is_empty(),
+        Some((b'*', rest)) => (0..=path.len()).any(|skip| matches(rest, &path[skip..])),
+        Some((c, rest)) => path.first() == Some(c) && matches(rest, &path[1..]),
+    }
+}
+
+#[cfg(test)]
+mod robots_test;
@@ -0,0 +1,42 @@
+use super::Robots;
+use std::time::Duration;
+
+const ROBOTS_TXT: &str = "
+# comments are ignored
+User-agent: *
+Disallow: /*/archive/
+Disallow: /search$
+Crawl-delay: 2
+
+User-agent: criner
+User-agent: other
+Disallow: /private
+Allow: /private/public
+Crawl-delay: 0.5
+";
+
+#[test]
+fn our_group_takes_precedence_over_the_wildcard() {
+    let robots = Robots::parse(ROBOTS_TXT, "criner");
+    assert_eq!(robots.crawl_delay(), Some(Duration::from_millis(500)));
+    assert!(!robots.is_allowed("/private/file"));
+    assert!(robots.is_allowed("/private/public/file"), "the longer allow rule wins");
+    assert!(robots.is_allowed("/user/repo/archive/main.tar.gz"));
+}
+
+#[test]
+fn the_wildcard_group_applies_if_there_is_no_group_for_us() {
+    let robots = Robots::parse(ROBOTS_TXT, "someone-else");
+    assert_eq!(robots.crawl_delay(), Some(Duration::from_secs(2)));
+    assert!(!robots.is_allowed("/user/repo/archive/main.tar.gz"));
+    assert!(!robots.is_allowed("/search"));
+    assert!(robots.is_allowed("/search/more"), "$ anchors the pattern at the end");
+    assert!(robots.is_allowed("/private"));
+}
+
+#[test]
+fn empty_or_missing_robots_allow_everything() {
+    assert!(Robots::parse("", "criner").is_allowed("/anything"));
+    assert!(Robots::parse("User-agent: *\nDisallow:\n", "criner").is_allowed("/anything"));
+    assert!(Robots::allow_all().is_allowed("/"));
+}
@@ -1,15 +1,56 @@
 use crate::{
-    engine::{work::cpubound, work::iobound},
-    error::Result,
+    engine::{
+        work::artifact,
+        work::asset_store::AssetStore,
+        work::cpubound,
+        work::iobound,
+        work::lanes::{self, Priority},
+        work::retry::RetryPolicy,
+        work::task_graph,
+    },
+    error::{Error, Result},
     model, persistence,
-    persistence::{TableAccess, TaskTable},
+    persistence::{Keyed, TableAccess, TaskTable},
+    registry,
 };
 use std::{
     path::{Path, PathBuf},
-    time::SystemTime,
+    time::{Duration, SystemTime},
 };
 
-const MAX_ATTEMPTS_BEFORE_WE_GIVE_UP: usize = 8;
+/// The maximum amount of newly published crate versions waiting to be scheduled, with further ones being left to the
+/// backlog. It's large enough to hold the newest version of every crate on crates.io when bootstrapping.
+const MAX_NEWLY_PUBLISHED: usize = 250_000;
+
+/// The keys of crate versions which were just published, which the processing stage downloads and extracts ahead of the
+/// backlog of all crate versions. Clones share the same queue, so fetching changes announces them to the processing stage
+/// of the same engine.
+#[derive(Clone)]
+pub struct NewlyPublished {
+    tx: async_channel::Sender<String>,
+    rx: async_channel::Receiver<String>,
+}
+
+impl Default for NewlyPublished {
+    fn default() -> Self {
+        let (tx, rx) = async_channel::bounded(MAX_NEWLY_PUBLISHED);
+        NewlyPublished { tx, rx }
+    }
+}
+
+impl NewlyPublished {
+    /// Ask the processing stage to download and extract the crate version with the given `key` ahead of the backlog,
+    /// as it was just published. Returns false if too many are waiting already, in which case it is processed along
+    /// with the backlog.
+    pub fn announce(&self, key: String) -> bool {
+        self.tx.try_send(key).is_ok()
+    }
+
+    /// The key of the next newly published crate version, if there is one
+    pub fn next(&self) -> Option<String> {
+        self.rx.try_recv().ok()
+    }
+}
 
 #[derive(Clone, Copy)]
 pub enum Scheduling {
@@ -21,6 +62,17 @@ pub enum Scheduling {
     // NeverBlock,
 }
 
+/// How the crate of a version is extracted once it was downloaded
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExtractionPlan {
+    /// Extract the meta-data and selected contents of files at once
+    Full,
+    /// Record only the meta-data of files in a first pass, and extract contents in a second pass if `contents` is true
+    MetaDataFirst { contents: bool },
+    /// Don't extract crates, as the extract stage is disabled
+    Skip,
+}
+
 pub enum AsyncResult {
     // /// The required scheduling cannot be fulfilled without blocking
     // WouldBlock,
@@ -28,64 +80,206 @@ pub enum AsyncResult {
     Done,
 }
 
+/// Schedule downloads of all `artifact_kinds` of `krate`, and the extraction of downloaded crates.
+/// Crates are downloaded from `download_url_template` as returned by `registry::download_url_template()` if set, which
+/// is always the case for registries other than crates.io. Their crates have no other kinds of artifacts.
+/// Artifacts verified against the checksum in the index are downloaded from `mirror_url_template` if the checksum
+/// doesn't match.
+/// The `extraction` plan decides whether crates are extracted at once or in two passes.
+/// Unless `downloads` is true, only artifacts which were downloaded already are considered.
+/// Downloads and extractions are submitted to the lanes of the given `priority`, which is also the lane the extraction
+/// of a crate downloaded later goes to.
+/// Failed tasks are submitted again once the `retry` policy says they are due, unless it gave up on them.
 #[allow(clippy::too_many_arguments)]
 pub async fn tasks(
     assets_dir: &Path,
     tasks: &persistence::TaskTable,
+    crates_io_crates: &persistence::CratesIoCrateTable,
+    download_url_template: Option<&str>,
+    mirror_url_template: Option<&str>,
     krate: &model::CrateVersion,
+    artifact_kinds: &[&'static artifact::Kind],
+    downloads: bool,
+    extraction: ExtractionPlan,
     mut progress: prodash::tree::Item,
     _mode: Scheduling,
-    perform_io: &async_channel::Sender<iobound::DownloadRequest>,
-    perform_cpu: &async_channel::Sender<cpubound::ExtractRequest>,
+    perform_io: &lanes::Sender<iobound::DownloadRequest>,
+    perform_cpu: &lanes::Sender<cpubound::ExtractRequest>,
+    priority: Priority,
+    retry: &RetryPolicy,
     startup_time: SystemTime,
 ) -> Result<AsyncResult> {
     use SubmitResult::*;
     let mut key_buf = String::with_capacity(32);
-    let io_task = task_or_default(tasks, &mut key_buf, krate, iobound::default_persisted_download_task)?;
-
-    let kind = "crate";
-    let submit_result = submit_single(startup_time, io_task, &mut progress, perform_io, 1, 1, || {
-        let dummy_task = iobound::default_persisted_download_task();
-        let mut task_key = String::new();
-        dummy_task.fq_key(&krate.name, &krate.version, &mut task_key);
-
-        iobound::DownloadRequest {
-            output_file_path: download_file_path(
-                assets_dir,
-                &krate.name,
-                &krate.version,
-                &dummy_task.process,
-                &dummy_task.version,
-                kind,
-            ),
-            progress_name: format!("{}:{}", krate.name, krate.version),
-            task_key,
-            crate_name_and_version: Some((krate.name.clone(), krate.version.clone())),
-            kind,
-            url: format!(
-                "https://crates.io/api/v1/crates/{name}/{version}/download",
-                name = krate.name,
-                version = krate.version
-            ),
+    let crates_io_crate = if artifact_kinds.iter().any(|k| k.needs_crates_io_crate) {
+        crates_io_crates.get(&krate.name)?
+    } else {
+        None
+    };
+
+    for kind in artifact_kinds {
+        let url = match (
+            registry::split_crate_name(&krate.name),
+            download_url_template.filter(|_| kind.has_index_checksum),
+        ) {
+            ((_, name), Some(template)) => {
+                Some(registry::download_url(template, name, &krate.version, &krate.checksum))
+            }
+            ((None, _), None) => (kind.url)(krate, crates_io_crate.as_ref()),
+            ((Some(_), _), None) => None,
+        };
+        let url = match url {
+            Some(url) => url,
+            None => continue,
+        };
+        let mut io_task = task_or_default(tasks, &mut key_buf, krate, || kind.task())?;
+        let refresh = is_due_for_refresh(&io_task, kind.refresh_after);
+        if refresh {
+            io_task.state = model::TaskState::NotStarted;
         }
-    })
-    .await;
-
-    Ok(match submit_result {
-        PermanentFailure | Submitted => AsyncResult::Done,
-        Done(download_crate_task) => {
-            let cpu_task = task_or_default(tasks, &mut key_buf, krate, cpubound::default_persisted_extraction_task)?;
-            submit_single(startup_time, cpu_task, &mut progress, perform_cpu, 2, 2, || {
-                cpubound::ExtractRequest {
-                    download_task: download_crate_task,
-                    crate_name: krate.name.clone(),
-                    crate_version: krate.version.clone(),
+        if !downloads && !matches!(io_task.state, model::TaskState::Complete) {
+            continue;
+        }
+        let submit_result = submit_single(
+            startup_time,
+            retry,
+            &krate.key(),
+            io_task,
+            &mut progress,
+            perform_io.lane(priority),
+            1,
+            1,
+            || {
+                let dummy_task = kind.task();
+                let mut task_key = String::new();
+                dummy_task.fq_key(&krate.name, &krate.version, &mut task_key);
+
+                iobound::DownloadRequest {
+                    output_file_path: download_file_path(
+                        assets_dir,
+                        &krate.name,
+                        &krate.version,
+                        &dummy_task.process,
+                        &dummy_task.version,
+                        kind.name,
+                    ),
+                    progress_name: format!("{}:{}", krate.name, krate.version),
+                    task_key,
+                    task: dummy_task,
+                    crate_name_and_version: Some((krate.name.clone(), krate.version.clone())),
+                    kind: kind.name,
+                    url,
+                    fallback_url: mirror_url_template.filter(|_| kind.has_index_checksum).map(|template| {
+                        let (_, name) = registry::split_crate_name(&krate.name);
+                        registry::download_url(template, name, &krate.version, &krate.checksum)
+                    }),
+                    checksum: if kind.has_index_checksum {
+                        Some(krate.checksum.clone())
+                    } else {
+                        None
+                    },
+                    is_gzip: kind.is_gzip,
+                    refresh,
+                    priority,
+                }
+            },
+        )
+        .await?;
+
+        match submit_result {
+            Done(download_task) if kind.extract() && extraction != ExtractionPlan::Skip => {
+                let mut cpu_task =
+                    task_or_default(tasks, &mut key_buf, krate, cpubound::default_persisted_extraction_task)?;
+                let mut extraction_kind = cpubound::Extraction::Full;
+                if let ExtractionPlan::MetaDataFirst { contents } = extraction {
+                    let meta_data_task =
+                        task_or_default(tasks, &mut key_buf, krate, cpubound::meta_data_extraction_task)?;
+                    let first_pass_pending = !matches!(meta_data_task.state, model::TaskState::Complete);
+                    let fully_extracted = matches!(cpu_task.state, model::TaskState::Complete);
+                    if !fully_extracted && (first_pass_pending || !contents) {
+                        cpu_task = meta_data_task;
+                        extraction_kind = cpubound::Extraction::MetaDataOnly;
+                    }
                 }
-            })
-            .await;
-            AsyncResult::Done
+                submit_single(
+                    startup_time,
+                    retry,
+                    &krate.key(),
+                    cpu_task,
+                    &mut progress,
+                    perform_cpu.lane(priority),
+                    2,
+                    2,
+                    || cpubound::ExtractRequest {
+                        download_task,
+                        crate_name: krate.name.clone(),
+                        crate_version: krate.version.clone(),
+                        extraction: extraction_kind,
+                    },
+                )
+                .await?;
+            }
+            GivenUp | Postponed | Submitted | Done(_) => {}
         }
-    })
+    }
+    Ok(AsyncResult::Done)
+}
+
+/// Submit the extraction a report `need`s if the tasks it needs are complete, i.e. the crate was downloaded, and it isn't
+/// being extracted already. Returns true if the extraction was submitted.
+pub async fn needed_extraction(
+    assets_dir: &Path,
+    assets: &dyn AssetStore,
+    tasks: &persistence::TaskTable,
+    need: cpubound::ExtractionNeed,
+    startup_time: SystemTime,
+    perform_cpu: &async_channel::Sender<cpubound::ExtractRequest>,
+) -> Result<bool> {
+    let task = |make_task: fn() -> model::Task| -> Result<model::Task> {
+        let task = make_task();
+        let mut key = String::new();
+        task.fq_key(&need.crate_name, &need.crate_version, &mut key);
+        Ok(tasks.get(&key)?.unwrap_or(task))
+    };
+    let node = need.extraction.node();
+    if !node
+        .unmet_needs(tasks, &need.crate_name, &need.crate_version)?
+        .is_empty()
+    {
+        return Ok(false);
+    }
+    let download_task = task(task_graph::DOWNLOAD_CRATE.task)?;
+    let downloaded_crate = download_file_path(
+        assets_dir,
+        &need.crate_name,
+        &need.crate_version,
+        &download_task.process,
+        &download_task.version,
+        artifact::CRATE.name,
+    );
+    if assets.size(&downloaded_crate)?.is_none() {
+        return Ok(false);
+    }
+    let extraction_task = task(node.task)?;
+    if matches!(extraction_task.state, model::TaskState::InProgress(_)) && extraction_task.stored_at > startup_time {
+        return Ok(false);
+    }
+    perform_cpu
+        .send(cpubound::ExtractRequest {
+            download_task,
+            crate_name: need.crate_name,
+            crate_version: need.crate_version,
+            extraction: need.extraction,
+        })
+        .await
+        .map_err(Error::send_msg("Submit needed extraction"))?;
+    Ok(true)
+}
+
+/// Returns true if `task` completed longer than `refresh_after` ago
+fn is_due_for_refresh(task: &model::Task, refresh_after: Option<Duration>) -> bool {
+    matches!(task.state, model::TaskState::Complete)
+        && refresh_after.is_some_and(|after| task.stored_at.elapsed().is_ok_and(|elapsed| elapsed >= after))
 }
 
 fn task_or_default(
@@ -102,19 +296,26 @@ fn task_or_default(
 
 enum SubmitResult {
     Submitted,
+    /// The task failed and isn't due to be tried again yet
+    Postponed,
     Done(model::Task),
-    PermanentFailure,
+    GivenUp,
 }
 
+/// Submit `task` unless it's done, in progress or failed and not due yet according to `retry`, with `seed` being
+/// the same each time the task is looked at.
+#[allow(clippy::too_many_arguments)]
 async fn submit_single<R>(
     startup_time: SystemTime,
+    retry: &RetryPolicy,
+    seed: &str,
     task: model::Task,
     progress: &mut prodash::tree::Item,
     channel: &async_channel::Sender<R>,
     step: usize,
     max_step: usize,
     f: impl FnOnce() -> R,
-) -> SubmitResult {
+) -> Result<SubmitResult> {
     use model::TaskState::*;
     use SubmitResult::*;
     let mut configure = || {
@@ -122,41 +323,84 @@ async fn submit_single<R>(
         progress.set(max_step);
         progress.blocked("wait for consumer", None);
     };
-    match task.state {
+    let send_msg = || Error::send_msg("Submit task");
+    Ok(match task.state {
         InProgress(_) => {
             if startup_time > task.stored_at {
                 configure();
-                channel.send(f()).await.unwrap();
+                channel.send(f()).await.map_err(send_msg())?;
             };
             Submitted
         }
         NotStarted => {
             configure();
-            channel.send(f()).await.unwrap();
-            Submitted
-        }
-        AttemptsWithFailure(ref v) if v.len() < MAX_ATTEMPTS_BEFORE_WE_GIVE_UP => {
-            configure();
-            progress.info(format!("Retrying task, attempt {}", v.len() + 1));
-            channel.send(f()).await.unwrap();
+            channel.send(f()).await.map_err(send_msg())?;
             Submitted
         }
-        AttemptsWithFailure(_) => PermanentFailure,
+        AttemptsWithFailure(ref v) => match retry.next_attempt_at(&task, seed) {
+            Some(due_at) if due_at <= SystemTime::now() => {
+                configure();
+                progress.info(format!("Retrying task, attempt {}", v.len() + 1));
+                channel.send(f()).await.map_err(send_msg())?;
+                Submitted
+            }
+            Some(_) => Postponed,
+            None => GivenUp,
+        },
+        PermanentFailure(_) => GivenUp,
         Complete => Done(task),
+    })
+}
+
+/// The top-level directory of a crate within the assets directory of its registry, which groups crates by the start of their name
+pub fn crate_shard(crate_name: &str) -> &str {
+    let crate_name = registry::split_crate_name(crate_name).1;
+    // Crate names are ascii, but names in other registries might not be, so split at characters instead of bytes
+    match crate_name.chars().count() {
+        0 | 1 => "1",
+        2 => "2",
+        3 => "3",
+        _ => crate_name
+            .char_indices()
+            .nth(2)
+            .map_or(crate_name, |(end, _)| &crate_name[..end]),
     }
 }
 
+/// The directory which holds crates of registries other than crates.io, in one sub-directory per registry
+pub(crate) const REGISTRIES_DIR: &str = "registries";
+
 fn crate_dir(assets_dir: &Path, crate_name: &str) -> PathBuf {
-    // we can safely assume ascii here - otherwise we panic
-    let crate_path = match crate_name.len() {
-        1 => Path::new("1").join(crate_name),
-        2 => Path::new("2").join(crate_name),
-        3 => Path::new("3").join(&crate_name[..1]).join(&crate_name[1..]),
-        _ => Path::new(&crate_name[..2]).join(&crate_name[2..4]).join(crate_name),
+    let (assets_dir, crate_name) = match registry::split_crate_name(crate_name) {
+        (Some(registry), crate_name) => (assets_dir.join(REGISTRIES_DIR).join(registry), crate_name),
+        (None, crate_name) => (assets_dir.to_owned(), crate_name),
+    };
+    let shard = Path::new(crate_shard(crate_name));
+    let chars: Vec<char> = crate_name.chars().collect();
+    let part = |range: std::ops::Range<usize>| chars[range].iter().collect::<String>();
+    let crate_path = match chars.len() {
+        0..=2 => shard.join(crate_name),
+        3 => shard.join(part(0..1)).join(part(1..3)),
+        _ => shard.join(part(2..4)).join(crate_name),
     };
     assets_dir.join(crate_path)
 }
 
+/// The inverse of `crate_dir()`, taking the directory of a crate relative to the assets directory
+pub fn crate_name_from_dir(relative_dir: &Path) -> Option<String> {
+    let components: Vec<_> = relative_dir.iter().map(|c| c.to_str()).collect::<Option<_>>()?;
+    if let [REGISTRIES_DIR, registry, crate_dir @ ..] = components.as_slice() {
+        return crate_name_from_dir(&crate_dir.iter().collect::<PathBuf>())
+            .map(|name| format!("{}{}{}", registry, registry::REGISTRY_SEP_CHAR, name));
+    }
+    match components.as_slice() {
+        ["1", name] | ["2", name] => Some((*name).to_owned()),
+        ["3", first, rest] => Some(format!("{}{}", first, rest)),
+        [_, _, name] if name.len() > 3 => Some((*name).to_owned()),
+        _ => None,
+    }
+}
+
 pub fn download_file_path(
     assets_dir: &Path,
     crate_name: &str,
@@ -174,3 +418,6 @@ pub fn download_file_path(
         crate_version = crate_version
     ))
 }
+
+#[cfg(test)]
+mod schedule_test;
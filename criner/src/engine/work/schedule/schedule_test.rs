@@ -0,0 +1,166 @@
+use super::{
+    crate_dir, crate_name_from_dir, crate_shard, download_file_path, needed_extraction, submit_single, SubmitResult,
+};
+use crate::{
+    engine::work::{artifact, asset_store, cpubound, retry::RetryPolicy},
+    model,
+    persistence::{temp_db::TempDb, TableAccess},
+    Error,
+};
+use std::{
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+#[test]
+fn crate_directories_of_non_ascii_names_split_at_characters() {
+    let assets_dir = Path::new("assets");
+    for (name, expected) in [
+        ("a", "1/a"),
+        ("serde", "se/rd/serde"),
+        ("äbc", "3/ä/bc"),
+        ("äöüßx", "äö/üß/äöüßx"),
+        ("acme/äöüß", "registries/acme/äö/üß/äöüß"),
+    ] {
+        let dir = crate_dir(assets_dir, name);
+        assert_eq!(dir, assets_dir.join(expected));
+        assert_eq!(
+            crate_name_from_dir(dir.strip_prefix(assets_dir).unwrap()).as_deref(),
+            Some(name)
+        );
+    }
+}
+
+#[test]
+fn crate_shards_of_non_ascii_names_split_at_characters() {
+    for (name, expected) in [
+        ("a", "1"),
+        ("äö", "2"),
+        ("äbc", "3"),
+        ("serde", "se"),
+        ("äöüßx", "äö"),
+        ("acme/äöüß", "äö"),
+    ] {
+        assert_eq!(crate_shard(name), expected, "{}", name);
+    }
+}
+
+#[test]
+fn submitting_into_a_closed_channel_is_an_error() {
+    let (tx, rx) = async_channel::bounded::<()>(1);
+    drop(rx);
+    let mut progress = prodash::Tree::new().add_child("schedule");
+    let res = futures_lite::future::block_on(submit_single(
+        SystemTime::now(),
+        &RetryPolicy::default(),
+        "a:1.0.0",
+        cpubound::default_persisted_extraction_task(),
+        &mut progress,
+        &tx,
+        1,
+        1,
+        || (),
+    ));
+    assert!(matches!(res, Err(Error::ChannelSendMessage(_))));
+}
+
+#[test]
+fn failed_tasks_are_submitted_again_once_due_unless_given_up_on() {
+    let (tx, rx) = async_channel::bounded::<()>(1);
+    let policy: RetryPolicy = "max=2,base=1h".parse().unwrap();
+    let submit = |state: model::TaskState, failed_ago: Duration| {
+        let task = model::Task {
+            stored_at: SystemTime::now() - failed_ago,
+            state,
+            ..Default::default()
+        };
+        let mut progress = prodash::Tree::new().add_child("schedule");
+        futures_lite::future::block_on(submit_single(
+            SystemTime::now(),
+            &policy,
+            "a:1.0.0",
+            task,
+            &mut progress,
+            &tx,
+            1,
+            1,
+            || (),
+        ))
+        .unwrap()
+    };
+    let failed_once = || model::TaskState::AttemptsWithFailure(vec!["503 Service Unavailable".into()]);
+    assert!(matches!(
+        submit(failed_once(), Duration::from_secs(60)),
+        SubmitResult::Postponed
+    ));
+    assert!(rx.try_recv().is_err());
+    assert!(matches!(
+        submit(failed_once(), Duration::from_secs(60 * 60)),
+        SubmitResult::Submitted
+    ));
+    assert!(rx.try_recv().is_ok());
+
+    let failed_twice = model::TaskState::AttemptsWithFailure(vec!["503 Service Unavailable".into(); 2]);
+    assert!(matches!(
+        submit(failed_twice, Duration::from_secs(60 * 60 * 24)),
+        SubmitResult::GivenUp
+    ));
+    let not_found = model::TaskState::PermanentFailure(vec!["404 Not Found".into()]);
+    assert!(matches!(submit(not_found, Duration::ZERO), SubmitResult::GivenUp));
+    assert!(rx.try_recv().is_err());
+    assert!(Error::HttpStatus(http::StatusCode::GONE).is_permanent());
+    assert!(!Error::HttpStatus(http::StatusCode::SERVICE_UNAVAILABLE).is_permanent());
+}
+
+#[test]
+fn needed_extractions_are_submitted_only_for_downloaded_crates() {
+    let db = TempDb::new("schedule-needed");
+    let dir = &db.dir;
+    let tasks = db.open_tasks().unwrap();
+    let (tx, rx) = async_channel::bounded(1);
+    let need = cpubound::ExtractionNeed {
+        crate_name: "a".into(),
+        crate_version: "1.0.0".into(),
+        extraction: cpubound::Extraction::Full,
+    };
+    let submit = || {
+        futures_lite::future::block_on(needed_extraction(
+            dir,
+            &*asset_store::local(),
+            &tasks,
+            need.clone(),
+            SystemTime::now(),
+            &tx,
+        ))
+        .unwrap()
+    };
+    assert!(!submit(), "nothing was downloaded yet");
+
+    let mut progress = prodash::Tree::new().add_child("schedule");
+    let mut download_task = artifact::CRATE.task();
+    download_task.state = model::TaskState::Complete;
+    let mut key = String::new();
+    download_task.fq_key("a", "1.0.0", &mut key);
+    tasks.insert(&mut progress, &key, &download_task).unwrap();
+    assert!(
+        !submit(),
+        "the download task is done, but the crate isn't there anymore"
+    );
+
+    let path = download_file_path(
+        dir,
+        "a",
+        "1.0.0",
+        artifact::CRATE.task_process,
+        artifact::CRATE.task_version,
+        artifact::CRATE.name,
+    );
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(&path, b"crate").unwrap();
+    assert!(submit());
+    let request = rx.try_recv().unwrap();
+    assert_eq!(
+        (request.crate_name.as_str(), request.extraction),
+        ("a", cpubound::Extraction::Full)
+    );
+}
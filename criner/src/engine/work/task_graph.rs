@@ -0,0 +1,147 @@
+//! The tasks performed for each crate version and which of them have to be complete before another one can run, like
+//! the download of a crate before its extraction, which is consulted by the scheduler and reports instead of knowing
+//! the order themselves.
+use crate::{
+    engine::work::{artifact, cpubound},
+    model,
+    persistence::{TableAccess, TaskTable},
+    Result,
+};
+
+/// A task performed for each crate version
+pub struct Node {
+    /// Creates the task as it is stored for each crate version, whose process identifies the node
+    pub task: fn() -> model::Task,
+    /// The tasks which have to be complete before this one can run
+    pub needs: &'static [&'static Node],
+    /// The tasks whose result has everything the result of this one has, which makes it complete if any of them is
+    pub superseded_by: &'static [&'static Node],
+}
+
+impl std::fmt::Debug for Node {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.process())
+    }
+}
+
+impl PartialEq for Node {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+fn download_crate() -> model::Task {
+    artifact::CRATE.task()
+}
+
+fn download_repo() -> model::Task {
+    artifact::REPO_TARBALL.task()
+}
+
+fn download_docs_builds() -> model::Task {
+    artifact::DOCS_BUILDS.task()
+}
+
+pub static DOWNLOAD_CRATE: Node = Node {
+    task: download_crate,
+    needs: &[],
+    superseded_by: &[],
+};
+
+pub static DOWNLOAD_REPO: Node = Node {
+    task: download_repo,
+    needs: &[],
+    superseded_by: &[],
+};
+
+pub static DOWNLOAD_DOCS_BUILDS: Node = Node {
+    task: download_docs_builds,
+    needs: &[],
+    superseded_by: &[],
+};
+
+pub static EXTRACT_CRATE: Node = Node {
+    task: cpubound::default_persisted_extraction_task,
+    needs: &[&DOWNLOAD_CRATE],
+    superseded_by: &[],
+};
+
+/// The first pass of extracting in two passes, whose result is replaced by the one of a full extraction
+pub static EXTRACT_CRATE_META_DATA: Node = Node {
+    task: cpubound::meta_data_extraction_task,
+    needs: &[&DOWNLOAD_CRATE],
+    superseded_by: &[&EXTRACT_CRATE],
+};
+
+/// All tasks, which may be listed in any order as `in_order()` orders them by what they need
+pub static ALL: &[&Node] = &[
+    &DOWNLOAD_CRATE,
+    &DOWNLOAD_REPO,
+    &DOWNLOAD_DOCS_BUILDS,
+    &EXTRACT_CRATE,
+    &EXTRACT_CRATE_META_DATA,
+];
+
+impl Node {
+    /// The name of the process of the task, as used in its key
+    pub fn process(&self) -> String {
+        (self.task)().process
+    }
+
+    /// The tasks which need this one to be complete before they can run
+    pub fn dependents(&'static self) -> impl Iterator<Item = &'static Node> {
+        ALL.iter().copied().filter(move |node| node.needs.contains(&self))
+    }
+
+    /// Returns true if the task of this node or of one superseding it is complete for the given crate version
+    pub fn is_complete(&self, tasks: &TaskTable, crate_name: &str, crate_version: &str) -> Result<bool> {
+        let task = (self.task)();
+        let mut key = String::new();
+        task.fq_key(crate_name, crate_version, &mut key);
+        if tasks.get(&key)?.is_some_and(|t| t.state.is_complete()) {
+            return Ok(true);
+        }
+        for node in self.superseded_by {
+            if node.is_complete(tasks, crate_name, crate_version)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// The tasks this one needs which aren't complete yet for the given crate version, so it can run if there are none
+    pub fn unmet_needs(&self, tasks: &TaskTable, crate_name: &str, crate_version: &str) -> Result<Vec<&'static Node>> {
+        let mut unmet = Vec::new();
+        for node in self.needs {
+            if !node.is_complete(tasks, crate_name, crate_version)? {
+                unmet.push(*node);
+            }
+        }
+        Ok(unmet)
+    }
+}
+
+/// The node of the task with the given `process`, if it is known
+pub fn by_process(process: &str) -> Option<&'static Node> {
+    ALL.iter().copied().find(|node| node.process() == process)
+}
+
+/// All tasks ordered so that each comes after the tasks it needs, and otherwise in the order of `ALL`.
+/// Tasks which are part of a cycle, which would never run, are left out.
+pub fn in_order() -> Vec<&'static Node> {
+    let mut ordered: Vec<&'static Node> = Vec::with_capacity(ALL.len());
+    while ordered.len() < ALL.len() {
+        let ready = ALL
+            .iter()
+            .copied()
+            .find(|node| !ordered.contains(node) && node.needs.iter().all(|need| ordered.contains(need)));
+        match ready {
+            Some(node) => ordered.push(node),
+            None => break,
+        }
+    }
+    ordered
+}
+
+#[cfg(test)]
+mod task_graph_test;
@@ -0,0 +1,63 @@
+use super::{by_process, in_order, ALL, DOWNLOAD_CRATE, EXTRACT_CRATE, EXTRACT_CRATE_META_DATA};
+use crate::{
+    engine::work::{artifact, cpubound::Extraction},
+    model::{Task, TaskState},
+    persistence::{temp_db::TempDb, TableAccess},
+};
+
+#[test]
+fn tasks_are_ordered_after_the_tasks_they_need() {
+    let ordered = in_order();
+    assert_eq!(ordered.len(), ALL.len(), "there are no cycles");
+    for (idx, node) in ordered.iter().enumerate() {
+        for need in node.needs {
+            assert!(ordered[..idx].contains(need), "{:?} needs {:?} first", node, need);
+        }
+    }
+    assert_eq!(ordered[0], &DOWNLOAD_CRATE);
+}
+
+#[test]
+fn crates_are_extracted_as_extraction_needs_their_download() {
+    assert_eq!(
+        DOWNLOAD_CRATE.dependents().collect::<Vec<_>>(),
+        vec![&EXTRACT_CRATE, &EXTRACT_CRATE_META_DATA]
+    );
+    assert!(artifact::CRATE.extract());
+    assert!(!artifact::REPO_TARBALL.extract() && !artifact::DOCS_BUILDS.extract());
+    assert_eq!(by_process("extract_crate"), Some(&EXTRACT_CRATE));
+    assert_eq!(by_process("geiger"), None);
+}
+
+#[test]
+fn tasks_can_run_once_their_needs_are_complete_and_full_extractions_supersede_the_first_pass() {
+    let db = TempDb::new("task-graph");
+    let tasks = db.open_tasks().unwrap();
+    let complete = |node: &super::Node| {
+        let task = Task {
+            state: TaskState::Complete,
+            ..(node.task)()
+        };
+        let mut key = String::new();
+        task.fq_key("a", "1.0.0", &mut key);
+        tasks
+            .insert(&mut prodash::Tree::new().add_child("test"), &key, &task)
+            .unwrap();
+    };
+
+    let meta_data = Extraction::MetaDataOnly.node();
+    assert_eq!(
+        meta_data.unmet_needs(&tasks, "a", "1.0.0").unwrap(),
+        vec![&DOWNLOAD_CRATE]
+    );
+    complete(&DOWNLOAD_CRATE);
+    assert!(meta_data.unmet_needs(&tasks, "a", "1.0.0").unwrap().is_empty());
+    assert!(
+        !meta_data.unmet_needs(&tasks, "a", "2.0.0").unwrap().is_empty(),
+        "per crate version"
+    );
+
+    assert!(!meta_data.is_complete(&tasks, "a", "1.0.0").unwrap());
+    complete(Extraction::Full.node());
+    assert!(meta_data.is_complete(&tasks, "a", "1.0.0").unwrap());
+}
@@ -30,9 +30,36 @@ quick_error! {
         InvalidHeader(d: &'static str) {
             display("{}", d)
         }
+        Network { retryable: bool, message: String } {
+            display("{}", message)
+        }
+        Storage(message: String) {
+            display("{}", message)
+        }
+        Corrupt(message: String) {
+            display("{}", message)
+        }
+        SchemaVersion { found: u32, supported: u32 } {
+            display("The database has schema version {}, but this version of criner only supports up to {}", found, supported)
+        }
+        IncompatibleDatabase(message: String) {
+            display("{}", message)
+        }
+        Protocol(message: String) {
+            display("{}", message)
+        }
         HttpStatus(status: http::StatusCode) {
             display("{}", status)
         }
+        InvalidVersion(version: String) {
+            display("'{}' is not a semantic version, even when parsed leniently", version)
+        }
+        InvalidVersionRequirement(requirement: String, err: semver::Error) {
+            display("'{}' is not a valid version requirement: {}", requirement, err)
+        }
+        ChecksumMismatch(expected: String, actual: String) {
+            display("Checksum mismatch: expected sha256 {}, got {}", expected, actual)
+        }
         DeadlineExceeded(d: FormatDeadline) {
             display("Stopped computation as deadline was reached {}.", d)
         }
@@ -90,6 +117,10 @@ quick_error! {
             from()
             source(err)
         }
+        SerdeJson(err: serde_json::Error) {
+            from()
+            source(err)
+        }
         GlobPattern(err: glob::PatternError) {
             from()
             source(err)
@@ -104,7 +135,108 @@ quick_error! {
     }
 }
 
+/// The broad kind of an error, which decides whether trying again may help
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    /// Talking to a remote failed, possibly only this time
+    Network { retryable: bool },
+    /// Reading or writing local files or the database failed
+    Storage,
+    /// Data we stored or received isn't what it is supposed to be
+    Corrupt,
+    /// We ran out of time
+    Deadline,
+    /// A remote or the crates index didn't behave according to its specification
+    Protocol,
+    /// Programming errors and everything else
+    Other,
+}
+
+impl Category {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Category::Network { .. } => "network",
+            Category::Storage => "storage",
+            Category::Corrupt => "corrupt",
+            Category::Deadline => "deadline",
+            Category::Protocol => "protocol",
+            Category::Other => "other",
+        }
+    }
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
 impl Error {
+    pub fn category(&self) -> Category {
+        use Error::*;
+        match self {
+            Network { retryable, .. } => Category::Network { retryable: *retryable },
+            HttpStatus(status) => {
+                if status.is_server_error() || *status == http::StatusCode::TOO_MANY_REQUESTS {
+                    Category::Network { retryable: true }
+                } else {
+                    Category::Protocol
+                }
+            }
+            Reqwest(err) => Category::Network {
+                retryable: err.is_timeout() || err.is_connect() || err.is_request() || err.is_body(),
+            },
+            Git2(err) => match err.class() {
+                git2::ErrorClass::Net | git2::ErrorClass::Http | git2::ErrorClass::Ssl => {
+                    Category::Network { retryable: true }
+                }
+                _ => Category::Other,
+            },
+            Timeout(..) => Category::Network { retryable: true },
+            DeadlineExceeded(_) | Interrupted => Category::Deadline,
+            Storage(_)
+            | SchemaVersion { .. }
+            | IncompatibleDatabase(_)
+            | Io(_)
+            | Rusqlite(_)
+            | StripPrefixError(_)
+            | Glob(_) => Category::Storage,
+            Corrupt(_) | ChecksumMismatch(..) | RmpSerdeEncode(_) | FromUtf8(_) | Csv(_) | SerdeJson(_) => {
+                Category::Corrupt
+            }
+            Protocol(_) | InvalidHeader(_) | InvalidVersion(_) | InvalidVersionRequirement(..) | ParseInt(_) => {
+                Category::Protocol
+            }
+            Bug(_)
+            | Message(_)
+            | GlobSet(_)
+            | Horrorshow(_)
+            | SystemTime(_)
+            | GlobPattern(_)
+            | ChannelSendMessage(_) => Category::Other,
+        }
+    }
+
+    /// Returns true if the operation that caused this error may succeed if it is tried again
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Error::Rusqlite(rusqlite::Error::SqliteFailure(err, _)) => matches!(
+                err.code,
+                rusqlite::ffi::ErrorCode::DatabaseBusy | rusqlite::ffi::ErrorCode::DatabaseLocked
+            ),
+            err => matches!(err.category(), Category::Network { retryable: true }),
+        }
+    }
+
+    /// Returns true if the operation that caused this error will fail no matter how often it is tried again, like
+    /// downloading a file which doesn't exist (anymore)
+    pub fn is_permanent(&self) -> bool {
+        matches!(
+            self,
+            Error::HttpStatus(status) if *status == http::StatusCode::NOT_FOUND || *status == http::StatusCode::GONE
+        )
+    }
+
     pub fn send_msg<T>(msg: &'static str) -> impl FnOnce(async_channel::SendError<T>) -> Error {
         move |_err| Error::ChannelSendMessage(msg)
     }
@@ -0,0 +1,115 @@
+//! Writing the tables of an exported database into flat files, one per table, for tools which don't read SQLite.
+use crate::{Error, Result};
+use rusqlite::{types::ValueRef, Connection};
+use std::{io::Write, path::Path};
+
+/// The format to export the database in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    /// Another SQLite database with all data exploded into tables and fields
+    #[default]
+    Sqlite,
+    /// A directory with a file of JSON objects, one per line, for each table
+    Jsonl,
+    /// A directory with a CSV file with a header line for each table
+    Csv,
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(match s {
+            "sqlite" => Format::Sqlite,
+            "jsonl" => Format::Jsonl,
+            "csv" => Format::Csv,
+            _ => return Err(format!("Invalid export format '{}', expected sqlite, jsonl or csv", s)),
+        })
+    }
+}
+
+impl Format {
+    fn extension(&self) -> &'static str {
+        match self {
+            Format::Sqlite => "db",
+            Format::Jsonl => "jsonl",
+            Format::Csv => "csv",
+        }
+    }
+}
+
+/// Write each table and view of the exported database at `connection` into `<out_dir>/<table>.<jsonl|csv>` according
+/// to `format`. Blobs, like the contents of crate entries, are written hex-encoded.
+/// Returns the amount of files written.
+pub fn write_tables(connection: &Connection, out_dir: &Path, format: Format) -> Result<usize> {
+    if format == Format::Sqlite {
+        return Err(Error::Bug("SQLite exports aren't written as flat files"));
+    }
+    let tables = {
+        let mut statement =
+            connection.prepare("SELECT name FROM sqlite_master WHERE type IN ('table', 'view') ORDER BY name")?;
+        let names = statement
+            .query_map([], |r| r.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        names
+    };
+    std::fs::create_dir_all(out_dir)?;
+    for table in tables.iter() {
+        let path = out_dir.join(format!("{}.{}", table, format.extension()));
+        let out = std::io::BufWriter::new(std::fs::File::create(&path)?);
+        let count = write_table(connection, table, format, out)?;
+        log::info!("Wrote {} rows of {} to '{}'", count, table, path.display());
+    }
+    Ok(tables.len())
+}
+
+fn write_table(connection: &Connection, table: &str, format: Format, mut out: impl Write) -> Result<usize> {
+    let mut statement = connection.prepare(&format!("SELECT * FROM \"{}\"", table.replace('"', "\"\"")))?;
+    let columns: Vec<String> = statement.column_names().into_iter().map(ToOwned::to_owned).collect();
+    let mut rows = statement.query([])?;
+    let mut count = 0;
+    match format {
+        Format::Csv => {
+            let mut writer = csv::Writer::from_writer(out);
+            writer.write_record(&columns)?;
+            while let Some(row) = rows.next()? {
+                let mut record = Vec::with_capacity(columns.len());
+                for idx in 0..columns.len() {
+                    record.push(match row.get_ref(idx)? {
+                        ValueRef::Null => String::new(),
+                        ValueRef::Integer(v) => v.to_string(),
+                        ValueRef::Real(v) => v.to_string(),
+                        ValueRef::Text(v) => String::from_utf8_lossy(v).into_owned(),
+                        ValueRef::Blob(v) => hex::encode(v),
+                    });
+                }
+                writer.write_record(&record)?;
+                count += 1;
+            }
+            writer.flush()?;
+        }
+        Format::Jsonl | Format::Sqlite => {
+            while let Some(row) = rows.next()? {
+                let mut object = serde_json::Map::with_capacity(columns.len());
+                for (idx, column) in columns.iter().enumerate() {
+                    let value = match row.get_ref(idx)? {
+                        ValueRef::Null => serde_json::Value::Null,
+                        ValueRef::Integer(v) => v.into(),
+                        ValueRef::Real(v) => v.into(),
+                        ValueRef::Text(v) => String::from_utf8_lossy(v).into(),
+                        ValueRef::Blob(v) => hex::encode(v).into(),
+                    };
+                    object.insert(column.clone(), value);
+                }
+                serde_json::to_writer(&mut out, &object)?;
+                writeln!(out)?;
+                count += 1;
+            }
+            out.flush()?;
+        }
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod flat_test;
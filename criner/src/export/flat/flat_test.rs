@@ -0,0 +1,57 @@
+use super::{write_tables, Format};
+use crate::persistence::temp_db::TempDir;
+use rusqlite::{params, Connection};
+
+fn exported_db() -> Connection {
+    let connection = Connection::open_in_memory().unwrap();
+    connection
+        .execute_batch(
+            "CREATE TABLE crate_entry (path TEXT, size INTEGER, ratio REAL, data BLOB);
+             CREATE VIEW big_entry AS SELECT path FROM crate_entry WHERE size > 10;",
+        )
+        .unwrap();
+    for (path, size, data) in [("src/lib.rs", 5, Some(&b"\x00\xff"[..])), ("a,\"b\".md", 20, None)] {
+        connection
+            .execute(
+                "INSERT INTO crate_entry VALUES (?1, ?2, 0.5, ?3)",
+                params![path, size, data],
+            )
+            .unwrap();
+    }
+    connection
+}
+
+fn out_dir(name: &str) -> TempDir {
+    TempDir::new(&format!("flat-{}", name))
+}
+
+#[test]
+fn each_table_and_view_is_written_as_json_lines() {
+    let dir = out_dir("jsonl");
+    assert_eq!(write_tables(&exported_db(), &dir, Format::Jsonl).unwrap(), 2);
+    assert_eq!(
+        std::fs::read_to_string(dir.join("crate_entry.jsonl")).unwrap(),
+        "{\"data\":\"00ff\",\"path\":\"src/lib.rs\",\"ratio\":0.5,\"size\":5}\n\
+         {\"data\":null,\"path\":\"a,\\\"b\\\".md\",\"ratio\":0.5,\"size\":20}\n"
+    );
+    assert_eq!(
+        std::fs::read_to_string(dir.join("big_entry.jsonl")).unwrap(),
+        "{\"path\":\"a,\\\"b\\\".md\"}\n"
+    );
+}
+
+#[test]
+fn each_table_and_view_is_written_as_csv_with_header() {
+    let dir = out_dir("csv");
+    assert_eq!(write_tables(&exported_db(), &dir, Format::Csv).unwrap(), 2);
+    assert_eq!(
+        std::fs::read_to_string(dir.join("crate_entry.csv")).unwrap(),
+        "path,size,ratio,data\nsrc/lib.rs,5,0.5,00ff\n\"a,\"\"b\"\".md\",20,0.5,\n"
+    );
+}
+
+#[test]
+fn formats_are_parsed_by_name() {
+    assert_eq!("csv".parse::<Format>(), Ok(Format::Csv));
+    assert!("parquet".parse::<Format>().is_err());
+}
@@ -1,4 +1,6 @@
+mod flat;
 mod run;
 mod to_sql;
 
+pub use flat::Format;
 pub use run::run_blocking;
@@ -1,16 +1,54 @@
-use super::to_sql::SqlConvert;
-use crate::model;
-use rusqlite::Connection;
+use super::{
+    flat::{self, Format},
+    to_sql::{to_seconds_since_epoch, SqlConvert},
+};
+use crate::{model, registry::Registry};
+use rusqlite::{params, Connection, OptionalExtension};
 use std::path::Path;
 
-pub fn run_blocking(source_db: impl AsRef<Path>, destination_db: impl AsRef<Path>) -> crate::Result<()> {
-    if destination_db.as_ref().is_file() {
+/// The name recorded in `export_registry` for exports of the crates of all registries
+const ALL_REGISTRIES: &str = "";
+
+/// Export the database at `source_db` to `destination` in the given `format`, which is a database for `Format::Sqlite`
+/// and a directory with a file per table otherwise. If `registry` is set, only its crates are exported.
+///
+/// A database written by a previous export is updated with the rows which were added or changed since, but rows deleted
+/// from `source_db` in the mean time are kept. It must have been exported for the same `registry`.
+/// Other destinations must not exist yet.
+pub fn run_blocking(
+    source_db: impl AsRef<Path>,
+    destination: impl AsRef<Path>,
+    format: Format,
+    registry: Option<&Registry>,
+) -> crate::Result<()> {
+    let destination = destination.as_ref();
+    if destination.exists() && !(format == Format::Sqlite && is_previous_export(destination)?) {
         return Err(crate::Error::Message(format!(
-            "Destination database at '{}' does already exist - this is currently unsupported",
-            destination_db.as_ref().display()
+            "Destination at '{}' does already exist and is not a database written by a previous export",
+            destination.display()
         )));
     }
+    match format {
+        Format::Sqlite => export_to_sqlite(source_db, destination, registry),
+        Format::Jsonl | Format::Csv => {
+            // Flat files are written from an exported database to get the same tables and columns
+            std::fs::create_dir_all(destination)?;
+            let exported_db = destination.join(".criner-export.db");
+            export_to_sqlite(source_db, &exported_db, registry)?;
+            let res = flat::write_tables(&Connection::open(&exported_db)?, destination, format);
+            std::fs::remove_file(&exported_db)?;
+            res.map(|num_tables| log::info!("Wrote {} tables to '{}'", num_tables, destination.display()))
+        }
+    }
+}
+
+fn export_to_sqlite(
+    source_db: impl AsRef<Path>,
+    destination_db: impl AsRef<Path>,
+    registry: Option<&Registry>,
+) -> crate::Result<()> {
     let mut input = Connection::open(source_db)?;
+    crate::persistence::Compatibility::of_database(&input)?.verify()?;
     let mut output = Connection::open(destination_db)?;
 
     // Turn off keychecks during insertion - we assume we can't get it wrong
@@ -21,28 +59,104 @@ pub fn run_blocking(source_db: impl AsRef<Path>, destination_db: impl AsRef<Path
     PRAGMA journal_mode = 'OFF' -- no journal, direct writes
 ",
     )?;
+    output.execute_batch(
+        "CREATE TABLE IF NOT EXISTS export_watermark (
+             source_table        TEXT PRIMARY KEY NOT NULL,
+             max_rowid           INTEGER NOT NULL, -- source rows with a greater rowid were added or changed since
+             exported_at         TIMESTAMP NOT NULL
+        )",
+    )?;
+    record_registry(&output, registry)?;
 
-    transfer::<model::db_dump::Crate>(&mut input, &mut output)?;
-    transfer::<model::Crate>(&mut input, &mut output)?;
-    transfer::<model::Task>(&mut input, &mut output)?;
-    transfer::<model::Context>(&mut input, &mut output)?;
-    transfer::<model::CrateVersion>(&mut input, &mut output)?;
-    transfer::<model::TaskResult>(&mut input, &mut output)?;
+    transfer::<model::db_dump::Crate>(&mut input, &mut output, registry)?;
+    transfer::<model::Crate>(&mut input, &mut output, registry)?;
+    transfer::<model::Task>(&mut input, &mut output, registry)?;
+    transfer::<model::Context>(&mut input, &mut output, registry)?;
+    transfer::<model::CrateVersion>(&mut input, &mut output, registry)?;
+    transfer::<model::TaskResult>(&mut input, &mut output, registry)?;
 
     Ok(())
 }
 
-fn transfer<T>(input: &mut Connection, output: &mut Connection) -> crate::Result<()>
+/// Remember which crates were exported to `output`, and fail if a previous export into it was of different crates, as
+/// updating it would mix both.
+fn record_registry(output: &Connection, registry: Option<&Registry>) -> crate::Result<()> {
+    output.execute_batch("CREATE TABLE IF NOT EXISTS export_registry (name TEXT PRIMARY KEY NOT NULL)")?;
+    let name = registry.map_or(ALL_REGISTRIES, |r| r.name.as_str());
+    let exported: Option<String> = output
+        .query_row("SELECT name FROM export_registry", [], |r| r.get(0))
+        .optional()?;
+    // Exports made before the registry was recorded are of all crates
+    let has_watermarks = output
+        .query_row("SELECT 1 FROM export_watermark LIMIT 1", [], |_| Ok(()))
+        .optional()?
+        .is_some();
+    let exported = exported.or_else(|| has_watermarks.then(|| ALL_REGISTRIES.to_owned()));
+    match exported {
+        Some(exported) if exported != name => Err(crate::Error::Message(format!(
+            "The destination holds {} and can't be updated with {} - export into a new destination instead",
+            describe_registry(&exported),
+            describe_registry(name)
+        ))),
+        _ => {
+            output.execute("REPLACE INTO export_registry (name) VALUES (?1)", params![name])?;
+            Ok(())
+        }
+    }
+}
+
+fn describe_registry(name: &str) -> String {
+    if name == ALL_REGISTRIES {
+        "the crates of all registries".into()
+    } else {
+        format!("the crates of registry '{}'", name)
+    }
+}
+
+fn transfer<T>(input: &mut Connection, output: &mut Connection, registry: Option<&Registry>) -> crate::Result<()>
 where
     for<'a> T: SqlConvert + From<&'a [u8]>,
 {
-    output.execute_batch(T::init_table_statement())?;
-    let mut istm = input.prepare(&format!("SELECT key, data FROM '{}'", T::source_table_name()))?;
+    let table = T::source_table_name();
+    let exported_rowid: Option<i64> = output
+        .query_row(
+            "SELECT max_rowid FROM export_watermark WHERE source_table = ?1",
+            params![table],
+            |r| r.get(0),
+        )
+        .optional()?;
+    let incremental = exported_rowid.is_some();
+    if !incremental {
+        output.execute_batch(T::init_table_statement())?;
+    }
+    if let Some(statement) = T::added_tables_statement() {
+        output.execute_batch(statement)?;
+    }
+    // Rows are replaced when written, which gives them a rowid greater than that of all other rows
+    let max_rowid: i64 = input.query_row(&format!("SELECT COALESCE(MAX(rowid), 0) FROM '{}'", table), [], |r| {
+        r.get(0)
+    })?;
+    let (registry_condition, registry_values) = match registry.filter(|_| T::is_keyed_by_crate()) {
+        Some(registry) => registry.key_condition(),
+        None => ("1", Vec::new()),
+    };
+    let mut istm = input.prepare(&format!(
+        "SELECT key, data, rowid FROM '{}' WHERE rowid > {} AND rowid <= {} AND {} AND {}",
+        table,
+        exported_rowid.unwrap_or(0),
+        max_rowid,
+        T::source_condition().unwrap_or("1"),
+        registry_condition
+    ))?;
+    // Bound once here, as converters run the statement with `raw_query()`
+    for (index, value) in registry_values.iter().enumerate() {
+        istm.raw_bind_parameter(index + 1, value)?;
+    }
     let transaction = output.transaction()?;
     let mut count = 0;
     let start = std::time::SystemTime::now();
     {
-        if let Some(res) = T::convert_to_sql(&mut istm, &transaction) {
+        if let Some(res) = T::convert_to_sql(input, &mut istm, &transaction, incremental) {
             count = res?;
         } else {
             let mut ostm = transaction.prepare(T::replace_statement())?;
@@ -50,28 +164,51 @@ where
                 Some(s) => Some(transaction.prepare(s)?),
                 None => None,
             };
-            for (uid, res) in istm
-                .query_map([], |r| {
-                    let key: String = r.get(0)?;
-                    let value: Vec<u8> = r.get(1)?;
-                    Ok((key, value))
-                })?
-                .enumerate()
-            {
+            for res in istm.raw_query().mapped(|r| {
+                let key: String = r.get(0)?;
+                let value: Vec<u8> = r.get(1)?;
+                let rowid: i64 = r.get(2)?;
+                Ok((key, value, rowid))
+            }) {
                 count += 1;
-                let (key, value) = res?;
+                let (key, value, rowid) = res?;
+                if incremental {
+                    T::delete_exported(&key, &transaction)?;
+                }
                 let value = T::from(value.as_slice());
-                value.insert(&key, uid as i32, &mut ostm, secondary_ostm.as_mut())?;
+                value.insert(&key, rowid, &mut ostm, secondary_ostm.as_mut())?;
             }
         }
     }
+    transaction.execute(
+        "REPLACE INTO export_watermark (source_table, max_rowid, exported_at) VALUES (?1, ?2, ?3)",
+        params![table, max_rowid, to_seconds_since_epoch(std::time::SystemTime::now())],
+    )?;
     transaction.commit()?;
     log::info!(
-        "Inserted {} {} in {:?}",
+        "{} {} {} in {:?}",
+        if incremental { "Updated" } else { "Inserted" },
         count,
-        T::source_table_name(),
+        table,
         std::time::SystemTime::now().duration_since(start).unwrap()
     );
 
     Ok(())
 }
+
+/// True if `path` is a database with the watermarks of a previous export
+fn is_previous_export(path: &Path) -> crate::Result<bool> {
+    if !path.is_file() {
+        return Ok(false);
+    }
+    let connection = Connection::open(path)?;
+    let num_tables: i64 = connection.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'export_watermark'",
+        [],
+        |r| r.get(0),
+    )?;
+    Ok(num_tables == 1)
+}
+
+#[cfg(test)]
+mod run_test;
@@ -0,0 +1,164 @@
+use super::run_blocking;
+use crate::{
+    export::Format,
+    model::{CrateVersion, Task, TaskState},
+    persistence::{fixtures::exploded_crate_without_content, temp_db::TempDb, TableAccess, SQLITE_FILE_NAME},
+    registry::Registry,
+};
+use rusqlite::Connection;
+
+fn failed_task(errors: &[&str]) -> Task {
+    Task {
+        process: "extract_crate".into(),
+        version: "1.0.0".into(),
+        state: TaskState::AttemptsWithFailure(errors.iter().map(|e| e.to_string()).collect()),
+        ..Default::default()
+    }
+}
+
+fn count(connection: &Connection, table: &str) -> i64 {
+    connection
+        .query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |r| r.get(0))
+        .unwrap()
+}
+
+#[test]
+fn exporting_into_a_previous_export_only_updates_what_changed() {
+    let db = TempDb::new("export");
+    let dir = &db.dir;
+    let mut progress = prodash::Tree::new().add_child("export");
+    let (crates, tasks, results) = (
+        db.open_crates().unwrap(),
+        db.open_tasks().unwrap(),
+        db.open_results().unwrap(),
+    );
+    for name in ["a", "b"] {
+        let version = CrateVersion {
+            name: name.into(),
+            version: "1.0.0".into(),
+            ..Default::default()
+        };
+        crates.upsert(&mut progress, name, &version).unwrap();
+        let key = format!("{}:1.0.0:extract_crate:1.0.0", name);
+        tasks.insert(&mut progress, &key, &failed_task(&["timeout"])).unwrap();
+        results
+            .insert_as_is(
+                &mut progress,
+                &key,
+                &exploded_crate_without_content(&["Cargo.toml", "src/lib.rs"]),
+            )
+            .unwrap();
+    }
+    let (source, exported) = (dir.join(SQLITE_FILE_NAME), dir.join("export.db"));
+    run_blocking(&source, &exported, Format::Sqlite, None).unwrap();
+    {
+        let export = Connection::open(&exported).unwrap();
+        assert_eq!(count(&export, "crate_entry"), 4);
+        assert_eq!(count(&export, "task_error"), 2);
+    }
+
+    let key = "b:1.0.0:extract_crate:1.0.0";
+    tasks
+        .insert(&mut progress, key, &failed_task(&["timeout", "reset"]))
+        .unwrap();
+    results
+        .insert_as_is(&mut progress, key, &exploded_crate_without_content(&["Cargo.toml"]))
+        .unwrap();
+    run_blocking(&source, &exported, Format::Sqlite, None).unwrap();
+
+    let export = Connection::open(&exported).unwrap();
+    assert_eq!(count(&export, "crate_entry"), 3, "the entries of 'b' were replaced");
+    assert_eq!(count(&export, "result_extract_crate"), 2);
+    assert_eq!(count(&export, "task_error"), 3, "the errors of 'b' were replaced");
+    assert_eq!(count(&export, "task"), 2);
+    assert_eq!(count(&export, "crate"), 2, "unchanged rows are kept");
+    assert_eq!(
+        count(&export, "export_watermark"),
+        6,
+        "there is a watermark for each exported source table"
+    );
+
+    assert!(
+        run_blocking(&source, dir.join(SQLITE_FILE_NAME), Format::Sqlite, None).is_err(),
+        "only previous exports are updated"
+    );
+}
+
+#[test]
+fn yank_history_is_exported_into_exports_of_earlier_versions_too() {
+    let db = TempDb::new("export-yanks");
+    let dir = &db.dir;
+    let mut progress = prodash::Tree::new().add_child("export");
+    let crates = db.open_crates().unwrap();
+    let version = |kind| CrateVersion {
+        name: "a".into(),
+        version: "1.0.0".into(),
+        kind,
+        ..Default::default()
+    };
+    crates
+        .upsert(&mut progress, "a", &version(crates_index_diff::ChangeKind::Yanked))
+        .unwrap();
+    let (source, exported) = (dir.join(SQLITE_FILE_NAME), dir.join("export.db"));
+    run_blocking(&source, &exported, Format::Sqlite, None).unwrap();
+    {
+        let export = Connection::open(&exported).unwrap();
+        assert_eq!(count(&export, "crate_yank"), 1);
+        export.execute_batch("DROP TABLE crate_yank").unwrap();
+    }
+
+    crates
+        .upsert(&mut progress, "a", &version(crates_index_diff::ChangeKind::Added))
+        .unwrap();
+    run_blocking(&source, &exported, Format::Sqlite, None).unwrap();
+    let export = Connection::open(&exported).unwrap();
+    let yanked: Vec<bool> = export
+        .prepare("SELECT yanked FROM crate_yank WHERE name = 'a' ORDER BY rowid")
+        .unwrap()
+        .query_map([], |r| r.get(0))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(yanked, vec![true, false], "the yank and the unyank");
+}
+
+#[test]
+fn exports_limited_to_a_registry_only_hold_its_crates_and_are_only_updated_for_it() {
+    let db = TempDb::new("export-registry");
+    let dir = &db.dir;
+    let mut progress = prodash::Tree::new().add_child("export");
+    let (crates, tasks) = (db.open_crates().unwrap(), db.open_tasks().unwrap());
+    for name in ["a", "acme/a", "acme/b"] {
+        let version = CrateVersion {
+            name: name.into(),
+            version: "1.0.0".into(),
+            ..Default::default()
+        };
+        crates.upsert(&mut progress, name, &version).unwrap();
+        let key = format!("{}:1.0.0:extract_crate:1.0.0", name);
+        tasks.insert(&mut progress, &key, &failed_task(&["timeout"])).unwrap();
+    }
+    let acme = Registry {
+        name: "acme".into(),
+        index_url: "https://git.example.com/index.git".into(),
+    };
+    let source = dir.join(SQLITE_FILE_NAME);
+    for (registry, num_crates) in [(&acme, 2), (&Registry::default(), 1)] {
+        let exported = dir.join(format!("{}.db", registry.name));
+        run_blocking(&source, &exported, Format::Sqlite, Some(registry)).unwrap();
+        run_blocking(&source, &exported, Format::Sqlite, Some(registry)).unwrap();
+        let export = Connection::open(&exported).unwrap();
+        assert_eq!(count(&export, "crate"), num_crates);
+        assert_eq!(count(&export, "task"), num_crates);
+
+        assert!(
+            run_blocking(&source, &exported, Format::Sqlite, None).is_err(),
+            "adding the crates of all registries would mix them"
+        );
+    }
+
+    let exported = dir.join("all.db");
+    run_blocking(&source, &exported, Format::Sqlite, None).unwrap();
+    assert_eq!(count(&Connection::open(&exported).unwrap(), "crate"), 3);
+    assert!(run_blocking(&source, &exported, Format::Sqlite, Some(&acme)).is_err());
+}
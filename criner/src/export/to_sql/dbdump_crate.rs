@@ -2,9 +2,9 @@ use crate::{
     export::to_sql::{to_seconds_since_epoch, SqlConvert},
     model,
 };
-use rusqlite::{params, Statement};
+use rusqlite::{params, Statement, Transaction};
 
-impl<'a> SqlConvert for model::db_dump::Crate {
+impl SqlConvert for model::db_dump::Crate {
     fn replace_statement() -> &'static str {
         "will not be called"
     }
@@ -60,16 +60,26 @@ impl<'a> SqlConvert for model::db_dump::Crate {
     }
 
     fn convert_to_sql(
+        _input: &rusqlite::Connection,
         input_statement: &mut rusqlite::Statement,
         transaction: &rusqlite::Transaction,
+        incremental: bool,
     ) -> Option<crate::Result<usize>> {
-        Some(do_it(input_statement, transaction))
+        Some(do_it(input_statement, transaction, incremental))
+    }
+
+    fn delete_exported(key: &str, transaction: &Transaction<'_>) -> crate::Result<()> {
+        transaction.execute(
+            "DELETE FROM 'crates.io-crate_version' WHERE parent_id IN (SELECT rowid FROM 'crates.io-crate' WHERE name = ?1)",
+            params![key],
+        )?;
+        Ok(())
     }
 
     fn insert(
         &self,
         _key: &str,
-        _uid: i32,
+        _uid: i64,
         _stm: &mut Statement<'_>,
         _sstm: Option<&mut rusqlite::Statement<'_>>,
     ) -> crate::Result<usize> {
@@ -77,7 +87,11 @@ impl<'a> SqlConvert for model::db_dump::Crate {
     }
 }
 
-fn do_it(input_statement: &mut rusqlite::Statement, transaction: &rusqlite::Transaction) -> crate::Result<usize> {
+fn do_it(
+    input_statement: &mut rusqlite::Statement,
+    transaction: &rusqlite::Transaction,
+    incremental: bool,
+) -> crate::Result<usize> {
     let mut insert_crate = transaction
         .prepare("
             REPLACE INTO 'crates.io-crate'
@@ -100,18 +114,21 @@ fn do_it(input_statement: &mut rusqlite::Statement, transaction: &rusqlite::Tran
             "
             INSERT OR IGNORE INTO 'crates.io-crate_version'
                      (parent_id, crate_name, semver, created_at, updated_at, downloads, features, license, crate_size, published_by, is_yanked)
-              VALUES (?1       , ?2        , ?3        , ?4        , ?5        , ?6       , ?7      , ?8 , ?9        , ?10         , , ?11);
+              VALUES (?1       , ?2        , ?3        , ?4        , ?5        , ?6       , ?7      , ?8 , ?9        , ?10         , ?11);
         ",
         )
         .unwrap();
 
     let mut count = 0;
-    for res in input_statement.query_map([], |r| {
+    for res in input_statement.raw_query().mapped(|r| {
         let key: String = r.get(0)?;
         let value: Vec<u8> = r.get(1)?;
         Ok((key, value))
-    })? {
-        let (_crate_name, bytes) = res?;
+    }) {
+        let (crate_name, bytes) = res?;
+        if incremental {
+            model::db_dump::Crate::delete_exported(&crate_name, transaction)?;
+        }
         let model::db_dump::Crate {
             name,
             stored_at,
@@ -154,6 +171,7 @@ fn do_it(input_statement: &mut rusqlite::Statement, transaction: &rusqlite::Tran
             serde_json::to_string_pretty(&keywords).unwrap(),
             serde_json::to_string_pretty(&categories).unwrap(),
         ])?;
+        let crate_row_id = transaction.last_insert_rowid();
 
         for version in versions {
             let model::db_dump::CrateVersion {
@@ -168,7 +186,7 @@ fn do_it(input_statement: &mut rusqlite::Statement, transaction: &rusqlite::Tran
                 is_yanked,
             } = version;
             insert_crate_version.execute(params![
-                count as i32,
+                crate_row_id,
                 name,
                 semver,
                 to_seconds_since_epoch(created_at),
@@ -1,12 +1,22 @@
-use crate::{export::to_sql::SqlConvert, model};
-use rusqlite::{params, Statement};
+use crate::{
+    export::to_sql::{to_seconds_since_epoch, SqlConvert},
+    model,
+};
+use rusqlite::{params, Statement, Transaction};
 
-impl<'a> SqlConvert for model::Crate {
+impl SqlConvert for model::Crate {
     fn replace_statement() -> &'static str {
         "REPLACE INTO crate
                    (name, version)
             VALUES (?1,   ?2)"
     }
+    fn secondary_replace_statement() -> Option<&'static str> {
+        Some(
+            "INSERT INTO crate_yank
+                        (name, version, yanked, observed_at)
+                VALUES  (?1,   ?2,      ?3,     ?4)",
+        )
+    }
     fn source_table_name() -> &'static str {
         "crate"
     }
@@ -17,22 +27,50 @@ impl<'a> SqlConvert for model::Crate {
              PRIMARY KEY (name, version)
         )"
     }
+    fn added_tables_statement() -> Option<&'static str> {
+        Some(
+            "CREATE TABLE IF NOT EXISTS crate_yank (
+                 name           TEXT NOT NULL,
+                 version        TEXT NOT NULL,
+                 yanked         INTEGER NOT NULL, -- 1 if the version was yanked, 0 if it was unyanked
+                 observed_at    TIMESTAMP NOT NULL,
+                 FOREIGN KEY (name, version) REFERENCES crate(name, version)
+            )",
+        )
+    }
+
+    fn delete_exported(key: &str, transaction: &Transaction<'_>) -> crate::Result<()> {
+        transaction.execute("DELETE FROM crate WHERE name = ?1", params![key])?;
+        transaction.execute("DELETE FROM crate_yank WHERE name = ?1", params![key])?;
+        Ok(())
+    }
 
     fn insert(
         &self,
         key: &str,
-        _uid: i32,
+        _uid: i64,
         stm: &mut Statement<'_>,
-        _sstm: Option<&mut rusqlite::Statement<'_>>,
+        sstm: Option<&mut rusqlite::Statement<'_>>,
     ) -> crate::Result<usize> {
         let mut tokens = key.split(crate::persistence::KEY_SEP_CHAR);
         let name = tokens.next().unwrap();
         assert!(tokens.next().is_none());
 
-        let Self { versions } = self;
+        let Self { versions, yank_history } = self;
         for version in versions.iter() {
             stm.execute(params![name, version])?;
         }
+        let sstm = sstm.ok_or(crate::Error::Bug("need secondary statement"))?;
+        for (version, transitions) in yank_history.iter() {
+            for transition in transitions.iter() {
+                sstm.execute(params![
+                    name,
+                    version,
+                    transition.yanked,
+                    to_seconds_since_epoch(transition.observed_at)
+                ])?;
+            }
+        }
         Ok(versions.len())
     }
 }
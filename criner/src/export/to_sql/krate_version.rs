@@ -1,11 +1,11 @@
 use crate::{export::to_sql::SqlConvert, model};
-use rusqlite::{params, Statement};
+use rusqlite::{params, Statement, Transaction};
 
 impl SqlConvert for model::CrateVersion {
     fn replace_statement() -> &'static str {
         "REPLACE INTO crate_version
-                   (id, name, version, kind, checksum, features)
-            VALUES (?1, ?2  , ?3     , ?4  , ?5      , ?6);
+                   (id, name, version, kind, checksum, features, raw_index_line)
+            VALUES (?1, ?2  , ?3     , ?4  , ?5      , ?6      , ?7);
         "
     }
 
@@ -29,6 +29,7 @@ impl SqlConvert for model::CrateVersion {
             kind                TEXT NOT NULL,
             checksum            TEXT NOT NULL,
             features            JSON NOT NULL,
+            raw_index_line      JSON,
             PRIMARY KEY (name, version)
         );
         CREATE TABLE crate_version_dependency (
@@ -46,10 +47,21 @@ impl SqlConvert for model::CrateVersion {
         "
     }
 
+    fn delete_exported(key: &str, transaction: &Transaction<'_>) -> crate::Result<()> {
+        let mut tokens = key.splitn(2, crate::persistence::KEY_SEP_CHAR);
+        let (name, version) = (tokens.next().unwrap(), tokens.next().unwrap_or_default());
+        transaction.execute(
+            "DELETE FROM crate_version_dependency
+              WHERE parent_id IN (SELECT id FROM crate_version WHERE name = ?1 AND version = ?2)",
+            params![name, version],
+        )?;
+        Ok(())
+    }
+
     fn insert(
         &self,
         _key: &str,
-        uid: i32,
+        uid: i64,
         stm: &mut Statement<'_>,
         sstm: Option<&mut Statement<'_>>,
     ) -> crate::Result<usize> {
@@ -60,6 +72,7 @@ impl SqlConvert for model::CrateVersion {
             checksum,
             features,
             dependencies,
+            raw_index_line,
         } = self;
 
         use crates_index_diff::ChangeKind::*;
@@ -72,7 +85,8 @@ impl SqlConvert for model::CrateVersion {
                 Yanked => "yanked",
             },
             checksum,
-            serde_json::to_string_pretty(features).unwrap()
+            serde_json::to_string_pretty(features).unwrap(),
+            raw_index_line
         ])?;
 
         let sstm = sstm.expect("secondary statement to be set");
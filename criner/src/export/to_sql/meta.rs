@@ -4,7 +4,7 @@ use rusqlite::{params, Statement};
 
 impl SqlConvert for model::Context {
     fn replace_statement() -> &'static str {
-        "INSERT INTO runtime_statistic
+        "REPLACE INTO runtime_statistic
                 (sample_day, num_new_crate_versions, num_new_crates, dur_s_fetch_new_crate_versions)
          VALUES (?1        , ?2                    , ?3            , ?4);
         "
@@ -14,6 +14,14 @@ impl SqlConvert for model::Context {
         "meta"
     }
 
+    fn source_condition() -> Option<&'static str> {
+        Some(crate::persistence::CONTEXT_CONDITION)
+    }
+
+    fn is_keyed_by_crate() -> bool {
+        false
+    }
+
     fn init_table_statement() -> &'static str {
         "CREATE TABLE runtime_statistic (
             sample_day                      TIMESTAMP NOT NULL,
@@ -28,7 +36,7 @@ impl SqlConvert for model::Context {
     fn insert(
         &self,
         key: &str,
-        _uid: i32,
+        _uid: i64,
         stm: &mut Statement<'_>,
         _sstm: Option<&mut Statement<'_>>,
     ) -> crate::Result<usize> {
@@ -9,23 +9,46 @@ pub fn to_seconds_since_epoch(time: std::time::SystemTime) -> i64 {
     time.duration_since(std::time::UNIX_EPOCH).unwrap().as_secs() as i64
 }
 
+/// Writes the rows of a source table into tables of the export. The input statement yields the key, data and rowid of
+/// each source row, the latter of which is used as `uid` to keep ids unique across incremental exports. Its parameters
+/// are bound already, so it must be run with `raw_query()`.
 pub trait SqlConvert {
+    /// If `incremental` is true, rows were exported before and `delete_exported()` must be called for each key
     fn convert_to_sql(
+        _input: &rusqlite::Connection,
         _input_statement: &mut rusqlite::Statement,
         _transaction: &rusqlite::Transaction,
+        _incremental: bool,
     ) -> Option<crate::Result<usize>> {
         None
     }
+    /// Delete what a previous export wrote for the source row at `key` and isn't overwritten by writing it again
+    fn delete_exported(_key: &str, _transaction: &rusqlite::Transaction) -> crate::Result<()> {
+        Ok(())
+    }
     fn replace_statement() -> &'static str;
     fn secondary_replace_statement() -> Option<&'static str> {
         None
     }
     fn source_table_name() -> &'static str;
+    /// An SQL condition for the source rows to export if not all of them are of this type
+    fn source_condition() -> Option<&'static str> {
+        None
+    }
+    /// False if the keys of source rows aren't crate names, like dates, which makes them part of exports of any registry
+    fn is_keyed_by_crate() -> bool {
+        true
+    }
     fn init_table_statement() -> &'static str;
+    /// Statements creating tables added after `init_table_statement()` was first released if they don't exist yet,
+    /// which are run on each export so previous exports get them too
+    fn added_tables_statement() -> Option<&'static str> {
+        None
+    }
     fn insert(
         &self,
         key: &str,
-        uid: i32,
+        uid: i64,
         stm: &mut rusqlite::Statement,
         sstm: Option<&mut rusqlite::Statement>,
     ) -> crate::Result<usize>;
@@ -1,22 +1,26 @@
+use crate::engine::report::waste::{Fix, Report, TarPackage};
 use crate::export::to_sql::SqlConvert;
 use crate::model;
-use rusqlite::{params, Statement};
+use rusqlite::{params, Statement, Transaction};
 
-impl<'a> SqlConvert for model::TaskResult {
+impl SqlConvert for model::TaskResult {
     fn convert_to_sql(
+        input: &rusqlite::Connection,
         istm: &mut rusqlite::Statement,
         transaction: &rusqlite::Transaction,
+        incremental: bool,
     ) -> Option<crate::Result<usize>> {
         let res = (|| {
             let mut num_downloads = 0;
             let mut num_extract_crates = 0;
             let mut num_crate_entries = 0;
+            let mut num_waste_reports = 0;
             let mut insert_download = transaction
                 .prepare(
                     "
             REPLACE INTO result_download
-                     (crate_name, crate_version, version, kind, url, content_length, content_type)
-              VALUES (?1        , ?2           , ?3     , ?4  , ?5 , ?6            , ?7);
+                     (crate_name, crate_version, version, kind, url, content_length, content_type, status, final_url, server, dns_lookup_ms, time_to_first_byte_ms, total_ms)
+              VALUES (?1        , ?2           , ?3     , ?4  , ?5 , ?6            , ?7          , ?8    , ?9       , ?10   , ?11          , ?12                  , ?13);
         ",
                 )
                 .unwrap();
@@ -40,12 +44,36 @@ impl<'a> SqlConvert for model::TaskResult {
                 )
                 .unwrap();
 
-            for res in istm.query_map([], |r| {
+            let mut insert_waste_report = transaction
+                .prepare(
+                    "
+            REPLACE INTO report_waste
+                     (parent_id, crate_name, crate_version, total_size_in_bytes, total_files, wasted_bytes, wasted_files, suggested_fix, suggested_patterns)
+              VALUES (?1       , ?2        , ?3           , ?4                 , ?5         , ?6          , ?7          , ?8           , ?9);
+        ",
+                )
+                .unwrap();
+
+            let mut insert_wasted_file = transaction
+                .prepare(
+                    "
+            REPLACE INTO report_wasted_file
+                     (parent_id, path, size)
+              VALUES (?1       , ?2  , ?3);
+        ",
+                )
+                .unwrap();
+
+            for res in istm.raw_query().mapped(|r| {
                 let key: String = r.get(0)?;
                 let value: Vec<u8> = r.get(1)?;
-                Ok((key, value))
-            })? {
-                let (key, value) = res?;
+                let rowid: i64 = r.get(2)?;
+                Ok((key, value, rowid))
+            }) {
+                let (key, value, rowid) = res?;
+                if incremental {
+                    Self::delete_exported(&key, transaction)?;
+                }
                 let mut tokens = key.split(crate::persistence::KEY_SEP_CHAR);
                 let crate_name = tokens.next().unwrap();
                 let crate_version = tokens.next().unwrap();
@@ -54,7 +82,7 @@ impl<'a> SqlConvert for model::TaskResult {
                 let optional_last_key = tokens.next();
                 assert!(tokens.next().is_none());
 
-                let value = Self::from(value.as_slice());
+                let value = crate::persistence::delta::resolve(input, &key, Self::from(value.as_slice()))?;
 
                 use model::TaskResult;
                 match value {
@@ -63,6 +91,11 @@ impl<'a> SqlConvert for model::TaskResult {
                         url,
                         content_length,
                         content_type,
+                        status,
+                        final_url,
+                        server,
+                        timing,
+                        ..
                     } => {
                         assert_eq!(process, "download");
                         assert_eq!(Some(kind.as_ref()), optional_last_key);
@@ -73,7 +106,13 @@ impl<'a> SqlConvert for model::TaskResult {
                             kind,
                             url,
                             content_length,
-                            content_type
+                            content_type,
+                            status,
+                            final_url,
+                            server,
+                            timing.and_then(|t| t.dns_lookup_ms),
+                            timing.map(|t| t.time_to_first_byte_ms),
+                            timing.map(|t| t.total_ms)
                         ])?;
                         num_downloads += 1;
                     }
@@ -82,7 +121,7 @@ impl<'a> SqlConvert for model::TaskResult {
                         selected_entries,
                     } => {
                         assert_eq!(process, "extract_crate");
-                        let id = num_extract_crates as i32;
+                        let id = rowid;
                         insert_extract_crate.execute(params![
                             id,
                             crate_name,
@@ -91,7 +130,9 @@ impl<'a> SqlConvert for model::TaskResult {
                             entries_meta_data.len() as i64
                         ])?;
                         for entry in entries_meta_data.iter() {
-                            let model::TarHeader { path, size, entry_type } = entry;
+                            let model::TarHeader {
+                                path, size, entry_type, ..
+                            } = entry;
                             insert_crate_entry.execute(params![
                                 id,
                                 std::str::from_utf8(path).expect("utf8 path in crate - lets see how long this is true"),
@@ -102,7 +143,9 @@ impl<'a> SqlConvert for model::TaskResult {
                             num_crate_entries += 1;
                         }
                         for (entry, data) in selected_entries.iter() {
-                            let model::TarHeader { path, size, entry_type } = entry;
+                            let model::TarHeader {
+                                path, size, entry_type, ..
+                            } = entry;
                             insert_crate_entry.execute(params![
                                 id,
                                 std::str::from_utf8(path).expect("utf8 path in crate - lets see how long this is true"),
@@ -112,16 +155,67 @@ impl<'a> SqlConvert for model::TaskResult {
                             ])?;
                             num_crate_entries += 1;
                         }
+                        if let Report::Version {
+                            total_size_in_bytes,
+                            total_files,
+                            wasted_files,
+                            suggested_fix,
+                            ..
+                        } = Report::from_package(
+                            crate_name,
+                            crate_version,
+                            TarPackage {
+                                entries_meta_data,
+                                entries: selected_entries,
+                            },
+                        ) {
+                            let (fix_name, patterns) = describe_fix(suggested_fix.as_ref());
+                            insert_waste_report.execute(params![
+                                id,
+                                crate_name,
+                                crate_version,
+                                total_size_in_bytes as i64,
+                                total_files as i64,
+                                wasted_files.iter().map(|(_, size)| size).sum::<u64>() as i64,
+                                wasted_files.len() as i64,
+                                fix_name,
+                                patterns.map(|p| p.join("\n"))
+                            ])?;
+                            for (path, size) in wasted_files {
+                                insert_wasted_file.execute(params![id, path, size as i64])?;
+                            }
+                            num_waste_reports += 1;
+                        }
                         num_extract_crates += 1;
                     }
-                    TaskResult::None => {}
+                    TaskResult::None | TaskResult::ExplodedCrateDelta { .. } | TaskResult::Manifest { .. } => {}
                 };
             }
-            Ok(num_downloads + num_extract_crates + num_crate_entries)
+            Ok(num_downloads + num_extract_crates + num_crate_entries + num_waste_reports)
         })();
         Some(res)
     }
 
+    fn delete_exported(key: &str, transaction: &Transaction<'_>) -> crate::Result<()> {
+        let mut tokens = key.split(crate::persistence::KEY_SEP_CHAR);
+        if let (Some(crate_name), Some(crate_version), Some("extract_crate"), Some(process_version)) =
+            (tokens.next(), tokens.next(), tokens.next(), tokens.next())
+        {
+            for statement in &[
+                "DELETE FROM report_wasted_file WHERE parent_id IN
+                    (SELECT id FROM result_extract_crate WHERE crate_name = ?1 AND crate_version = ?2 AND version = ?3)",
+                "DELETE FROM report_waste WHERE parent_id IN
+                    (SELECT id FROM result_extract_crate WHERE crate_name = ?1 AND crate_version = ?2 AND version = ?3)",
+                "DELETE FROM crate_entry WHERE parent_id IN
+                    (SELECT id FROM result_extract_crate WHERE crate_name = ?1 AND crate_version = ?2 AND version = ?3)",
+                "DELETE FROM result_extract_crate WHERE crate_name = ?1 AND crate_version = ?2 AND version = ?3",
+            ] {
+                transaction.execute(statement, params![crate_name, crate_version, process_version])?;
+            }
+        }
+        Ok(())
+    }
+
     fn replace_statement() -> &'static str {
         "will not be called"
     }
@@ -142,6 +236,12 @@ impl<'a> SqlConvert for model::TaskResult {
             url                             TEXT NOT NULL,
             content_length                  INTEGER NOT NULL,
             content_type                    TEXT,
+            status                          INTEGER, -- the HTTP status code, unset if the file was on disk already
+            final_url                       TEXT, -- the URL after following redirects
+            server                          TEXT, -- the 'Server' header of the response
+            dns_lookup_ms                   INTEGER,
+            time_to_first_byte_ms           INTEGER,
+            total_ms                        INTEGER,
             PRIMARY KEY (crate_name, crate_version, version, kind)
         );
         CREATE TABLE result_extract_crate (
@@ -164,6 +264,38 @@ impl<'a> SqlConvert for model::TaskResult {
             PRIMARY KEY (parent_id, path),
             FOREIGN KEY (parent_id) REFERENCES result_extract_crate(id)
         );
+        CREATE TABLE report_waste (
+            parent_id                       INTEGER UNIQUE NOT NULL,
+            crate_name                      TEXT NOT NULL,
+            crate_version                   TEXT NOT NULL,
+
+            total_size_in_bytes             INTEGER NOT NULL,
+            total_files                     INTEGER NOT NULL,
+            wasted_bytes                    INTEGER NOT NULL, -- what could be saved by applying the suggested fix
+            wasted_files                    INTEGER NOT NULL,
+            suggested_fix                   TEXT, -- the kind of fix, if one is needed
+            suggested_patterns              TEXT, -- the include or exclude patterns of the fix, one per line
+
+            FOREIGN KEY (parent_id) REFERENCES result_extract_crate(id)
+        );
+        CREATE TABLE report_wasted_file (
+            parent_id                       INTEGER NOT NULL,
+            path                            TEXT NOT NULL,
+            size                            INTEGER NOT NULL, -- size in bytes
+
+            PRIMARY KEY (parent_id, path),
+            FOREIGN KEY (parent_id) REFERENCES report_waste(parent_id)
+        );
+        CREATE VIEW report_waste_by_crate AS
+            SELECT crate_name,
+                   COUNT(*)                 AS num_versions,
+                   SUM(total_size_in_bytes) AS total_size_in_bytes,
+                   SUM(total_files)         AS total_files,
+                   SUM(wasted_bytes)        AS wasted_bytes,
+                   SUM(wasted_files)        AS wasted_files,
+                   SUM(suggested_fix IS NOT NULL) AS versions_with_suggested_fix
+            FROM report_waste
+            GROUP BY crate_name;
         COMMIT;
         "
     }
@@ -171,10 +303,22 @@ impl<'a> SqlConvert for model::TaskResult {
     fn insert(
         &self,
         _key: &str,
-        _uid: i32,
+        _uid: i64,
         _stm: &mut Statement<'_>,
         _sstm: Option<&mut Statement<'_>>,
     ) -> crate::Result<usize> {
         unimplemented!("we implement convert_to_sql instead (having our own loop and unlimited prepared statements")
     }
 }
+
+/// The name of the fix and the patterns to put into the manifest
+fn describe_fix(fix: Option<&Fix>) -> (Option<&'static str>, Option<&[String]>) {
+    match fix {
+        None => (None, None),
+        Some(Fix::ImprovedInclude { include, .. }) => (Some("ImprovedInclude"), Some(include)),
+        Some(Fix::EnrichedExclude { exclude, .. }) => (Some("EnrichedExclude"), Some(exclude)),
+        Some(Fix::NewInclude { include, .. }) => (Some("NewInclude"), Some(include)),
+        Some(Fix::RemoveExcludeAndUseInclude { include, .. }) => (Some("RemoveExcludeAndUseInclude"), Some(include)),
+        Some(Fix::RemoveExclude) => (Some("RemoveExclude"), None),
+    }
+}
@@ -2,7 +2,7 @@ use crate::{
     export::to_sql::{to_seconds_since_epoch, SqlConvert},
     model,
 };
-use rusqlite::{params, Statement};
+use rusqlite::{params, Statement, Transaction};
 
 impl SqlConvert for model::Task {
     fn replace_statement() -> &'static str {
@@ -39,10 +39,18 @@ impl SqlConvert for model::Task {
          COMMIT;"
     }
 
+    fn delete_exported(key: &str, transaction: &Transaction<'_>) -> crate::Result<()> {
+        transaction.execute(
+            "DELETE FROM task_error WHERE parent_id IN (SELECT id FROM task WHERE key = ?1)",
+            params![key],
+        )?;
+        Ok(())
+    }
+
     fn insert(
         &self,
         key: &str,
-        uid: i32,
+        uid: i64,
         stm: &mut Statement<'_>,
         sstm: Option<&mut rusqlite::Statement<'_>>,
     ) -> crate::Result<usize> {
@@ -65,10 +73,11 @@ impl SqlConvert for model::Task {
                 Complete => "Complete",
                 InProgress(_) => "InProgress",
                 AttemptsWithFailure(_) => "AttemptsWithFailure",
+                PermanentFailure(_) => "PermanentFailure",
             },
         ])?;
         match state {
-            InProgress(Some(errors)) | AttemptsWithFailure(errors) => {
+            InProgress(Some(errors)) | AttemptsWithFailure(errors) | PermanentFailure(errors) => {
                 let sstm = sstm.ok_or(crate::Error::Bug("need secondary statement"))?;
                 for error in errors.iter() {
                     sstm.execute(params![uid, error])?;
@@ -0,0 +1,271 @@
+//! The dependency graph between the crates of a registry, for visualization with tools like Graphviz or Gephi.
+//!
+//! Each crate is represented by its greatest version which isn't yanked, and depends on the crates its normal and
+//! build dependencies refer to. Dev dependencies are left out as they don't end up in dependents.
+use crate::{
+    model::CrateVersion,
+    persistence::{self, CrateVersionTable, TableAccess},
+    registry::{split_crate_name, Registry},
+    utils::parse_semver,
+    Error, Result,
+};
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    io::Write,
+    path::Path,
+};
+
+/// The file format to write a graph in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// The language of Graphviz
+    Dot,
+    /// The XML format of Gephi
+    Gexf,
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(match s {
+            "dot" => Format::Dot,
+            "gexf" => Format::Gexf,
+            _ => return Err(format!("Invalid graph format '{}', expected dot or gexf", s)),
+        })
+    }
+}
+
+/// A dependency of one crate on another
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Edge {
+    /// The name of the dependent crate
+    pub from: String,
+    /// The name of the crate depended on
+    pub to: String,
+    /// The version requirement, like `^1.0`
+    pub requirement: String,
+    /// Either `normal` or `build`
+    pub kind: String,
+    pub optional: bool,
+}
+
+/// Crates by name, with the version they are represented by, and the dependencies between them
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Graph {
+    /// The version of each crate, or `None` for crates which are depended on but not stored
+    pub nodes: BTreeMap<String, Option<String>>,
+    pub edges: BTreeSet<Edge>,
+}
+
+impl Graph {
+    /// Build the graph of the crates of `registry` from all their `versions`, which may be in any order and include
+    /// versions of other registries, which are ignored.
+    pub fn from_versions(registry: &Registry, versions: impl IntoIterator<Item = CrateVersion>) -> Graph {
+        let mut latest = BTreeMap::<String, (dia_semver::Semver, CrateVersion)>::new();
+        for version in versions {
+            if !registry.contains(&version.name) || version.kind == crates_index_diff::ChangeKind::Yanked {
+                continue;
+            }
+            let semver = match parse_semver(&version.version) {
+                Ok(semver) => semver,
+                Err(_) => continue,
+            };
+            let name = split_crate_name(&version.name).1.to_owned();
+            match latest.get(&name) {
+                Some((greatest, _)) if *greatest >= semver => {}
+                _ => {
+                    latest.insert(name, (semver, version));
+                }
+            }
+        }
+
+        let mut graph = Graph::default();
+        for (name, (_, version)) in latest {
+            for dependency in version.dependencies.iter() {
+                if dependency.kind.as_deref() == Some("dev") {
+                    continue;
+                }
+                graph.edges.insert(Edge {
+                    from: name.clone(),
+                    to: dependency.crate_name().to_owned(),
+                    requirement: dependency.required_version.clone(),
+                    kind: dependency.kind.clone().unwrap_or_else(|| "normal".into()),
+                    optional: dependency.optional,
+                });
+            }
+            graph.nodes.insert(name, Some(version.version));
+        }
+        for edge in graph.edges.iter() {
+            graph.nodes.entry(edge.to.clone()).or_insert(None);
+        }
+        graph
+    }
+
+    /// Build the graph of the crates of `registry` from all of their versions in `db`
+    pub fn from_db(db: &persistence::Db, registry: &Registry) -> Result<Graph> {
+        let connection = db.open_connection_no_async_with_busy_wait()?;
+        let (condition, values) = registry.key_condition();
+        let mut statement = connection.prepare(&format!(
+            "SELECT data FROM {} WHERE {}",
+            CrateVersionTable::table_name(),
+            condition
+        ))?;
+        let versions = statement
+            .query_map(rusqlite::params_from_iter(values), |r| {
+                r.get::<_, Vec<u8>>(0).map(|data| CrateVersion::from(data.as_slice()))
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(Graph::from_versions(registry, versions))
+    }
+
+    /// Keep only `root` and all crates it depends on, directly or indirectly
+    pub fn restrict_to_closure(self, root: &str) -> Result<Graph> {
+        if !self.nodes.contains_key(root) {
+            return Err(Error::Message(format!("The crate '{}' is not part of the graph", root)));
+        }
+        let mut reachable = BTreeSet::new();
+        let mut queue = VecDeque::new();
+        reachable.insert(root.to_owned());
+        queue.push_back(root.to_owned());
+        while let Some(name) = queue.pop_front() {
+            for edge in self.edges.iter().filter(|e| e.from == name) {
+                if reachable.insert(edge.to.clone()) {
+                    queue.push_back(edge.to.clone());
+                }
+            }
+        }
+        Ok(Graph {
+            nodes: self
+                .nodes
+                .into_iter()
+                .filter(|(name, _)| reachable.contains(name))
+                .collect(),
+            edges: self.edges.into_iter().filter(|e| reachable.contains(&e.from)).collect(),
+        })
+    }
+
+    pub fn write(&self, format: Format, out: impl Write) -> Result<()> {
+        match format {
+            Format::Dot => self.write_dot(out),
+            Format::Gexf => self.write_gexf(out),
+        }
+    }
+
+    fn write_dot(&self, mut out: impl Write) -> Result<()> {
+        fn quoted(s: &str) -> String {
+            format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+        }
+        writeln!(out, "digraph crates {{")?;
+        for (name, version) in self.nodes.iter() {
+            let label = match version {
+                Some(version) => format!("{} {}", name, version),
+                None => name.clone(),
+            };
+            writeln!(out, "  {} [label={}];", quoted(name), quoted(&label))?;
+        }
+        for edge in self.edges.iter() {
+            write!(
+                out,
+                "  {} -> {} [label={}",
+                quoted(&edge.from),
+                quoted(&edge.to),
+                quoted(&edge.requirement)
+            )?;
+            if edge.optional {
+                write!(out, ", style=dashed")?;
+            }
+            if edge.kind != "normal" {
+                write!(out, ", color=gray")?;
+            }
+            writeln!(out, "];")?;
+        }
+        writeln!(out, "}}")?;
+        Ok(())
+    }
+
+    fn write_gexf(&self, mut out: impl Write) -> Result<()> {
+        fn escaped(s: &str) -> String {
+            s.replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;")
+                .replace('"', "&quot;")
+        }
+        writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(out, r#"<gexf xmlns="http://www.gexf.net/1.2draft" version="1.2">"#)?;
+        writeln!(out, r#"  <graph mode="static" defaultedgetype="directed">"#)?;
+        writeln!(out, r#"    <attributes class="node">"#)?;
+        writeln!(out, r#"      <attribute id="version" title="version" type="string"/>"#)?;
+        writeln!(out, r#"    </attributes>"#)?;
+        writeln!(out, r#"    <attributes class="edge">"#)?;
+        writeln!(
+            out,
+            r#"      <attribute id="requirement" title="requirement" type="string"/>"#
+        )?;
+        writeln!(out, r#"      <attribute id="kind" title="kind" type="string"/>"#)?;
+        writeln!(
+            out,
+            r#"      <attribute id="optional" title="optional" type="boolean"/>"#
+        )?;
+        writeln!(out, r#"    </attributes>"#)?;
+        writeln!(out, r#"    <nodes>"#)?;
+        for (name, version) in self.nodes.iter() {
+            let name = escaped(name);
+            match version {
+                Some(version) => {
+                    writeln!(out, r#"      <node id="{}" label="{}">"#, name, name)?;
+                    writeln!(
+                        out,
+                        r#"        <attvalues><attvalue for="version" value="{}"/></attvalues>"#,
+                        escaped(version)
+                    )?;
+                    writeln!(out, r#"      </node>"#)?;
+                }
+                None => writeln!(out, r#"      <node id="{}" label="{}"/>"#, name, name)?,
+            }
+        }
+        writeln!(out, r#"    </nodes>"#)?;
+        writeln!(out, r#"    <edges>"#)?;
+        for (id, edge) in self.edges.iter().enumerate() {
+            writeln!(
+                out,
+                r#"      <edge id="{}" source="{}" target="{}">"#,
+                id,
+                escaped(&edge.from),
+                escaped(&edge.to)
+            )?;
+            writeln!(
+                out,
+                r#"        <attvalues><attvalue for="requirement" value="{}"/><attvalue for="kind" value="{}"/><attvalue for="optional" value="{}"/></attvalues>"#,
+                escaped(&edge.requirement),
+                escaped(&edge.kind),
+                edge.optional
+            )?;
+            writeln!(out, r#"      </edge>"#)?;
+        }
+        writeln!(out, r#"    </edges>"#)?;
+        writeln!(out, r#"  </graph>"#)?;
+        writeln!(out, r#"</gexf>"#)?;
+        Ok(())
+    }
+}
+
+/// Write the dependency graph of the crates of `registry` in the database at `db_path` to `out` in the given `format`.
+/// If `root` is set, only the crates it depends on directly or indirectly are written, along with `root` itself.
+pub fn write_blocking(
+    db_path: impl AsRef<Path>,
+    registry: &Registry,
+    format: Format,
+    root: Option<&str>,
+    out: impl Write,
+) -> Result<()> {
+    let graph = Graph::from_db(&persistence::Db::open(db_path)?, registry)?;
+    let graph = match root {
+        Some(root) => graph.restrict_to_closure(root)?,
+        None => graph,
+    };
+    graph.write(format, out)
+}
+
+#[cfg(test)]
+mod graph_test;
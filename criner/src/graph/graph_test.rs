@@ -0,0 +1,117 @@
+use crate::{
+    graph::{self, Format, Graph},
+    model::{CrateVersion, Dependency},
+    persistence::{temp_db::TempDb, TableAccess},
+    registry::Registry,
+};
+
+fn version(name: &str, version: &str, dependencies: &[(&str, &str, Option<&str>)]) -> CrateVersion {
+    CrateVersion {
+        name: name.into(),
+        version: version.into(),
+        dependencies: dependencies
+            .iter()
+            .map(|(name, requirement, kind)| Dependency {
+                name: (*name).into(),
+                required_version: (*requirement).into(),
+                kind: kind.map(Into::into),
+                ..Default::default()
+            })
+            .collect(),
+        ..Default::default()
+    }
+}
+
+fn versions() -> Vec<CrateVersion> {
+    let mut yanked = version("app", "2.0.0", &[("gone", "^1", None)]);
+    yanked.kind = crates_index_diff::ChangeKind::Yanked;
+    vec![
+        version(
+            "app",
+            "1.1.0",
+            &[("lib", "^0.2", None), ("test-helper", "^1", Some("dev"))],
+        ),
+        version("app", "1.0.0", &[("old", "^1", None)]),
+        yanked,
+        version("lib", "0.2.1", &[("cc", "^1", Some("build"))]),
+        version("unrelated", "0.1.0", &[("other", "*", None)]),
+        version("acme/app", "9.0.0", &[("private", "^1", None)]),
+    ]
+}
+
+#[test]
+fn crates_are_represented_by_their_greatest_version_which_is_not_yanked() {
+    let graph = Graph::from_versions(&Registry::default(), versions());
+    assert_eq!(
+        graph
+            .nodes
+            .iter()
+            .map(|(n, v)| (n.as_str(), v.as_deref()))
+            .collect::<Vec<_>>(),
+        vec![
+            ("app", Some("1.1.0")),
+            ("cc", None),
+            ("lib", Some("0.2.1")),
+            ("other", None),
+            ("unrelated", Some("0.1.0"))
+        ]
+    );
+    assert_eq!(
+        graph
+            .edges
+            .iter()
+            .map(|e| (e.from.as_str(), e.to.as_str(), e.kind.as_str()))
+            .collect::<Vec<_>>(),
+        vec![
+            ("app", "lib", "normal"),
+            ("lib", "cc", "build"),
+            ("unrelated", "other", "normal")
+        ]
+    );
+}
+
+#[test]
+fn restricting_to_a_root_keeps_only_what_it_depends_on() {
+    let graph = Graph::from_versions(&Registry::default(), versions())
+        .restrict_to_closure("app")
+        .unwrap();
+    assert_eq!(graph.nodes.keys().collect::<Vec<_>>(), vec!["app", "cc", "lib"]);
+    assert_eq!(graph.edges.len(), 2);
+    assert!(Graph::default().restrict_to_closure("app").is_err());
+}
+
+#[test]
+fn graphs_are_written_from_the_database_in_all_formats() {
+    let db = TempDb::new("graph");
+    let dir = &db.dir;
+    let table = db.open_crate_versions().unwrap();
+    let mut progress = prodash::Tree::new().add_child("insert");
+    let mut key = String::new();
+    for version in versions() {
+        key.clear();
+        CrateVersion::key_from(&version.name, &version.version, &mut key);
+        table.insert(&mut progress, &key, &version).unwrap();
+    }
+
+    let mut dot = Vec::new();
+    graph::write_blocking(dir, &Registry::default(), Format::Dot, Some("lib"), &mut dot).unwrap();
+    assert_eq!(
+        String::from_utf8(dot).unwrap(),
+        "digraph crates {\n  \"cc\" [label=\"cc\"];\n  \"lib\" [label=\"lib 0.2.1\"];\n  \"lib\" -> \"cc\" [label=\"^1\", color=gray];\n}\n"
+    );
+
+    let mut gexf = Vec::new();
+    let acme = Registry {
+        name: "acme".into(),
+        index_url: String::new(),
+    };
+    graph::write_blocking(dir, &acme, Format::Gexf, None, &mut gexf).unwrap();
+    let gexf = String::from_utf8(gexf).unwrap();
+    assert!(gexf.contains(r#"<node id="app" label="app">"#), "{}", gexf);
+    assert!(
+        gexf.contains(r#"<edge id="0" source="app" target="private">"#),
+        "{}",
+        gexf
+    );
+    assert!(!gexf.contains("lib"), "{}", gexf);
+}
@@ -0,0 +1,94 @@
+//! Just enough of HTTP/1.1 to serve the dashboard and metrics, answering a single request per connection.
+use crate::Result;
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::Arc,
+    time::Duration,
+};
+
+/// How long to wait for a request, or for the client to read the response, before giving up on its connection
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The answer to a request
+pub(crate) struct Response {
+    /// The status code along with its reason, like `200 OK`
+    pub status: &'static str,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn ok(content_type: &'static str, body: impl Into<Vec<u8>>) -> Self {
+        Response {
+            status: "200 OK",
+            content_type,
+            body: body.into(),
+        }
+    }
+
+    /// A plain text `message` with `status`, like for errors
+    pub fn text(status: &'static str, message: impl Into<String>) -> Self {
+        Response {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: message.into().into_bytes(),
+        }
+    }
+}
+
+fn respond(stream: TcpStream, handle: &dyn Fn(Option<&str>) -> Response) -> Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    let mut request_line = String::new();
+    let mut reader = BufReader::new(&stream);
+    reader.read_line(&mut request_line)?;
+    // Skip the headers, whatever they say the response is the same
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+    let response = handle(request_line.split(' ').nth(1));
+    write!(
+        &stream,
+        "HTTP/1.1 {}\r\ncontent-type: {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len(),
+    )?;
+    (&stream).write_all(&response.body)?;
+    Ok(())
+}
+
+/// Serve requests at `addr` from a thread called `name`, with a thread for each connection, answering each with what
+/// `handle` returns for its path, which is `None` if the request couldn't be understood.
+/// Returns the address requests are served at, which differs from `addr` if its port is 0.
+pub(crate) fn serve(
+    addr: SocketAddr,
+    name: &str,
+    handle: impl Fn(Option<&str>) -> Response + Send + Sync + 'static,
+) -> Result<SocketAddr> {
+    let listener = TcpListener::bind(addr)?;
+    let local_addr = listener.local_addr()?;
+    let handle = Arc::new(handle);
+    let (name, request_name) = (name.to_owned(), format!("{}-request", name));
+    std::thread::Builder::new().name(name.clone()).spawn(move || {
+        for stream in listener.incoming() {
+            let (handle, request_of) = (handle.clone(), name.clone());
+            let res = stream.and_then(|stream| {
+                std::thread::Builder::new()
+                    .name(request_name.clone())
+                    .spawn(move || {
+                        if let Err(err) = respond(stream, &*handle) {
+                            log::warn!("Failed to answer a request for the {}: {}", request_of, err);
+                        }
+                    })
+                    .map(drop)
+            });
+            if let Err(err) = res {
+                log::warn!("Failed to answer a request for the {}: {}", name, err);
+            }
+        }
+    })?;
+    Ok(local_addr)
+}
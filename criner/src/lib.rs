@@ -1,5 +1,6 @@
 #![allow(clippy::unneeded_field_pattern)]
 #![deny(unsafe_code)]
+#![recursion_limit = "256"]
 
 #[macro_use]
 extern crate lazy_static;
@@ -13,9 +14,31 @@ pub mod migration;
 pub mod error;
 pub use error::{Error, Result};
 
+pub mod allowlist;
+pub mod bench;
+pub mod bootstrap;
+pub mod check_package;
+pub mod crates_io_api;
+pub mod db;
 pub mod export;
+pub mod graph;
+pub(crate) mod http;
+#[cfg(feature = "mock-network")]
+pub mod mock_network;
 pub(crate) mod model;
 pub(crate) mod persistence;
+pub mod progress;
+pub mod query;
+pub mod reanalyze;
+pub mod registry;
+pub mod reports;
+pub mod resolve;
+pub mod serve;
+pub mod snapshot;
+pub mod stats;
+pub mod status;
+pub mod timeline;
+pub mod tune;
 pub(crate) mod utils;
 
 mod spawn;
@@ -5,6 +5,7 @@ use std::path::Path;
 pub fn migrate(db_path: impl AsRef<Path>) -> crate::Result<()> {
     log::info!("open db");
     let db = crate::persistence::Db::open(&db_path)?;
+    db.compatibility()?.verify()?;
     let mut connection = db.open_connection_no_async_with_busy_wait()?;
     let mut keys = Vec::<String>::new();
     let table_name = TaskResultTable::table_name();
@@ -82,6 +83,12 @@ fn migrate_iterate_assets_and_update_db(db_path: impl AsRef<Path>) -> crate::Res
             .into(),
             content_length: file_size as u32,
             content_type: Some("application/x-tar".into()),
+            etag: None,
+            last_modified: None,
+            status: None,
+            final_url: None,
+            server: None,
+            timing: None,
         };
         task_result.fq_key(name, version, &task, &mut key);
         results.insert(&mut progress, &key, &task_result)?;
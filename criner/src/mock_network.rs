@@ -0,0 +1,258 @@
+//! A stand-in for the network, serving canned crate archives and a registry index from the local machine, for testing
+//! the whole pipeline of fetching, downloading, extracting and reporting without touching any real server.
+//!
+//! Only available with the `mock-network` feature.
+use crate::{
+    registry::{self, Registry},
+    Result,
+};
+use crates_index_diff::git2;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+/// A crate version to put into the registry, with the files of its archive relative to the crate root
+#[derive(Debug, Clone)]
+pub struct Crate {
+    pub name: String,
+    pub version: String,
+    pub files: Vec<(String, Vec<u8>)>,
+}
+
+impl Crate {
+    /// A crate version with just a manifest and a library
+    pub fn new(name: &str, version: &str) -> Crate {
+        let manifest = format!(
+            "[package]\nname = \"{}\"\nversion = \"{}\"\nedition = \"2018\"\n",
+            name, version
+        );
+        Crate {
+            name: name.into(),
+            version: version.into(),
+            files: vec![
+                ("Cargo.toml".into(), manifest.into_bytes()),
+                ("src/lib.rs".into(), b"pub fn it_works() {}\n".to_vec()),
+            ],
+        }
+    }
+
+    /// Add a file at `path` relative to the crate root
+    pub fn with_file(mut self, path: &str, content: impl Into<Vec<u8>>) -> Crate {
+        self.files.push((path.into(), content.into()));
+        self
+    }
+
+    /// The gzipped tarball as uploaded by `cargo publish`, with all files in the `<name>-<version>` directory
+    pub fn archive(&self) -> Result<Vec<u8>> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (path, content) in self.files.iter() {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            builder.append_data(
+                &mut header,
+                format!("{}-{}/{}", self.name, self.version, path),
+                content.as_slice(),
+            )?;
+        }
+        let mut encoder = libflate::gzip::Encoder::new(Vec::new())?;
+        encoder.write_all(&builder.into_inner()?)?;
+        Ok(encoder.finish().into_result()?)
+    }
+}
+
+/// A registry whose index is a local git repository and whose crates are served by a local HTTP server, which runs
+/// until the process exits.
+pub struct Network {
+    /// The registry to mine, with its index at a `file://` URL
+    pub registry: Registry,
+    base_url: String,
+    files: Arc<Mutex<BTreeMap<String, Vec<u8>>>>,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl Network {
+    /// Serve the archives of `crates` and create the index of the registry called `name` for them at `index_dir`
+    pub fn start(name: &str, index_dir: impl AsRef<Path>, crates: &[Crate]) -> Result<Network> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let base_url = format!("http://{}", listener.local_addr()?);
+
+        let mut files = BTreeMap::new();
+        let mut index_files = BTreeMap::<String, String>::new();
+        for krate in crates {
+            let archive = krate.archive()?;
+            let line = serde_json::json!({
+                "name": krate.name,
+                "vers": krate.version,
+                "deps": [],
+                "cksum": hex::encode(Sha256::digest(&archive)),
+                "features": {},
+                "yanked": false,
+            });
+            let index_file = index_files
+                .entry(format!("{}/{}", registry::index_prefix(&krate.name), krate.name))
+                .or_default();
+            index_file.push_str(&line.to_string());
+            index_file.push('\n');
+            files.insert(format!("/crates/{}/{}/download", krate.name, krate.version), archive);
+        }
+        index_files.insert(
+            "config.json".into(),
+            serde_json::json!({ "dl": format!("{}/crates", base_url) }).to_string(),
+        );
+        let index_dir = index_dir.as_ref();
+        commit_index(index_dir, &index_files)?;
+
+        let files = Arc::new(Mutex::new(files));
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        std::thread::spawn({
+            let files = files.clone();
+            let requests = requests.clone();
+            move || serve(listener, files, requests)
+        });
+        Ok(Network {
+            registry: Registry {
+                name: name.into(),
+                index_url: format!("file://{}", index_dir.display()),
+            },
+            base_url,
+            files,
+            requests,
+        })
+    }
+
+    /// Serve `content` at `path`, like `/snapshots/db.msgpack.sqlite`, in addition to the crates of the registry
+    pub fn serve_file(&self, path: &str, content: impl Into<Vec<u8>>) {
+        if let Ok(mut files) = self.files.lock() {
+            files.insert(path.into(), content.into());
+        }
+    }
+
+    /// The URL of the server, like `http://127.0.0.1:4242`
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// The paths of all requests received so far, in order
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().map(|r| r.clone()).unwrap_or_default()
+    }
+}
+
+/// Commit `files` by their path to the `master` branch of a bare repository at `dir`, creating it if needed
+fn commit_index(dir: &Path, files: &BTreeMap<String, String>) -> Result<()> {
+    fn insert(
+        repo: &git2::Repository,
+        tree: Option<&git2::Tree<'_>>,
+        path: &[&str],
+        content: &str,
+    ) -> std::result::Result<git2::Oid, git2::Error> {
+        let mut builder = repo.treebuilder(tree)?;
+        match path {
+            [name] => {
+                builder.insert(name, repo.blob(content.as_bytes())?, 0o100644)?;
+            }
+            [dir, rest @ ..] => {
+                let subtree = match tree.and_then(|t| t.get_name(dir)) {
+                    Some(entry) => Some(repo.find_tree(entry.id())?),
+                    None => None,
+                };
+                builder.insert(dir, insert(repo, subtree.as_ref(), rest, content)?, 0o040000)?;
+            }
+            [] => return Err(git2::Error::from_str("empty path")),
+        }
+        builder.write()
+    }
+
+    let repo = git2::Repository::init_bare(dir)?;
+    let mut tree_id = repo.treebuilder(None)?.write()?;
+    for (path, content) in files {
+        let tree = repo.find_tree(tree_id)?;
+        tree_id = insert(&repo, Some(&tree), &path.split('/').collect::<Vec<_>>(), content)?;
+    }
+    let tree = repo.find_tree(tree_id)?;
+    let signature = git2::Signature::now("criner", "criner@localhost")?;
+    let parent = match repo.find_reference("refs/heads/master") {
+        Ok(reference) => Some(reference.peel_to_commit()?),
+        Err(_) => None,
+    };
+    repo.commit(
+        Some("refs/heads/master"),
+        &signature,
+        &signature,
+        "update crates",
+        &tree,
+        &parent.iter().collect::<Vec<_>>(),
+    )?;
+    repo.set_head("refs/heads/master")?;
+    Ok(())
+}
+
+/// The first byte of a `Range: bytes=<first>-` header, the only kind of range request criner makes
+fn range_start(headers: &[String]) -> Option<usize> {
+    headers.iter().find_map(|header| {
+        let (name, value) = header.split_once(':')?;
+        if !name.eq_ignore_ascii_case("range") {
+            return None;
+        }
+        value.trim().strip_prefix("bytes=")?.strip_suffix('-')?.parse().ok()
+    })
+}
+
+/// Answer each request with the file at its path, or 404, honoring requests for the remainder of a file
+fn serve(listener: TcpListener, files: Arc<Mutex<BTreeMap<String, Vec<u8>>>>, requests: Arc<Mutex<Vec<String>>>) {
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(_) => continue,
+        };
+        let headers: Vec<String> = BufReader::new(&stream)
+            .lines()
+            .map_while(std::result::Result::ok)
+            .take_while(|line| !line.is_empty())
+            .collect();
+        let path = match headers.first().and_then(|request_line| request_line.split(' ').nth(1)) {
+            Some(path) => path.to_owned(),
+            None => continue,
+        };
+        let body = files.lock().ok().and_then(|files| files.get(&path).cloned());
+        let response = match (body, range_start(&headers)) {
+            (Some(body), Some(start)) if start >= body.len() => format!(
+                "HTTP/1.1 416 Range Not Satisfiable\r\ncontent-range: bytes */{}\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                body.len()
+            )
+            .into_bytes(),
+            (Some(body), Some(start)) => {
+                let mut response = format!(
+                    "HTTP/1.1 206 Partial Content\r\ncontent-type: application/octet-stream\r\ncontent-range: bytes {}-{}/{}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+                    start,
+                    body.len() - 1,
+                    body.len(),
+                    body.len() - start
+                )
+                .into_bytes();
+                response.extend_from_slice(&body[start..]);
+                response
+            }
+            (Some(body), None) => {
+                let mut response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/octet-stream\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+                    body.len()
+                )
+                .into_bytes();
+                response.extend_from_slice(&body);
+                response
+            }
+            (None, _) => b"HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\nconnection: close\r\n\r\n".to_vec(),
+        };
+        if let Ok(mut requests) = requests.lock() {
+            requests.push(path);
+        }
+        stream.write_all(&response).ok();
+    }
+}
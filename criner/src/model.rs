@@ -1,6 +1,11 @@
-pub use crate::engine::report::waste::TarHeader;
+pub use crate::engine::report::waste::{ContentEncoding, TarHeader};
 use serde_derive::{Deserialize, Serialize};
-use std::{collections::HashMap, ops::Add, time::Duration, time::SystemTime};
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::Add,
+    time::Duration,
+    time::SystemTime,
+};
 
 /// Represents a top-level crate and associated information
 #[derive(Serialize, Deserialize, Default, Clone)]
@@ -8,16 +13,39 @@ pub struct Crate {
     /// All versions published to crates.io, guaranteed to be sorted so that the most recent version is last.
     /// The format is as specified in Cargo.toml:version
     pub versions: Vec<String>,
+    /// The changes of the yanked status of versions as observed in the index, oldest first, by version.
+    /// Versions which were never seen yanked have none, and versions first seen yanked start with a yank.
+    #[serde(default)]
+    pub yank_history: BTreeMap<String, Vec<YankTransition>>,
+}
+
+impl Crate {
+    /// Returns true if `version` is yanked according to its yank history
+    pub fn is_yanked(&self, version: &str) -> bool {
+        self.yank_history
+            .get(version)
+            .and_then(|history| history.last())
+            .is_some_and(|transition| transition.yanked)
+    }
 }
 
 impl From<CrateVersion> for Crate {
     fn from(v: CrateVersion) -> Self {
-        Crate {
-            versions: vec![v.version],
-        }
+        let mut c = Crate::default();
+        c.merge_mut(&v);
+        c
     }
 }
 
+/// A change of the yanked status of a crate version
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct YankTransition {
+    /// True if the version was yanked, false if it was unyanked
+    pub yanked: bool,
+    /// The time the change was stored by the miner, which is when it was fetched from the index and not when it happened
+    pub observed_at: SystemTime,
+}
+
 /// Stores element counts of various kinds
 #[derive(Default, Debug, Serialize, Deserialize, Ord, PartialOrd, Eq, PartialEq, Clone)]
 pub struct Counts {
@@ -59,8 +87,109 @@ impl Add<&Context> for Context {
     }
 }
 
-/// A single dependency of a specific crate version
-#[derive(Clone, Serialize, Deserialize, Ord, PartialOrd, Eq, PartialEq, Debug)]
+/// The outcome of comparing the crate versions in the database with all versions in the index of a registry
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct IndexCheck {
+    /// The time at which the check was completed
+    pub checked_at: SystemTime,
+    /// The commit of the index which was checked, the last one the database was updated with
+    pub index_commit: String,
+    /// The amount of crate versions in the index
+    pub index_versions: u64,
+    /// The amount of crate versions in the index which are not in the database
+    pub num_missing: u64,
+    /// Keys of the first missing crate versions, in the same format as keys of the `crate_version` table
+    pub missing: Vec<String>,
+    /// The amount of crate versions in the database which are not in the index, like those of deleted crates
+    pub num_unknown_to_index: u64,
+    /// The amount of missing crate versions which were added to the database
+    pub repaired: u64,
+}
+
+impl Default for IndexCheck {
+    fn default() -> Self {
+        IndexCheck {
+            checked_at: SystemTime::now(),
+            index_commit: Default::default(),
+            index_versions: 0,
+            num_missing: 0,
+            missing: Vec::new(),
+            num_unknown_to_index: 0,
+            repaired: 0,
+        }
+    }
+}
+
+/// What happened during a single run of the miner, written when it exits
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RunSummary {
+    /// The time at which the run started
+    pub started_at: SystemTime,
+    /// The time at which the run ended
+    pub finished_at: SystemTime,
+    /// Why the run ended, like reaching the deadline, being interrupted or an error
+    pub exit_reason: String,
+    /// The amount of tasks which finished successfully
+    pub tasks_finished: u64,
+    /// The amount of tasks which failed, after all retries
+    pub tasks_failed: u64,
+    /// The amount of times a task was retried after a transient error
+    pub tasks_retried: u64,
+    /// The time spent working on tasks, by the name of their process, like `download` or `extract_crate`
+    pub busy_by_process: BTreeMap<String, Duration>,
+    /// The amount of bytes received by all downloads
+    pub bytes_downloaded: u64,
+    /// The size of the database files when the run started
+    pub db_size_at_start: u64,
+    /// The size of the database files when the run ended
+    pub db_size_at_end: u64,
+    /// Hints for configuring the next run, derived from the bottlenecks observed in this one
+    pub suggestions: Vec<String>,
+    /// The amount of items which couldn't be reported and were left out of the reports of their cycle
+    #[serde(default)]
+    pub report_errors: u64,
+}
+
+impl Default for RunSummary {
+    fn default() -> Self {
+        RunSummary {
+            started_at: SystemTime::now(),
+            finished_at: SystemTime::now(),
+            exit_reason: Default::default(),
+            tasks_finished: 0,
+            tasks_failed: 0,
+            tasks_retried: 0,
+            busy_by_process: Default::default(),
+            bytes_downloaded: 0,
+            db_size_at_start: 0,
+            db_size_at_end: 0,
+            suggestions: Vec::new(),
+            report_errors: 0,
+        }
+    }
+}
+
+/// Describes a database along with the versions of criner and its models which wrote it, so other versions of criner
+/// can tell whether they are able to work with it. It's stored in the `meta` table next to the `Context` of each day.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct DatabaseInfo {
+    /// The version of criner which created the database, or `None` if it was created before databases described themselves
+    pub created_by: Option<String>,
+    /// The time at which the database was created, if known
+    pub created_at: Option<SystemTime>,
+    /// The version of criner which mined into the database last
+    pub written_by: String,
+    /// The version of the layout of the database when it was written last
+    pub schema_version: u32,
+    /// A hash of the model of the items of each table stored as msgpack, by table name
+    pub model_hashes: BTreeMap<String, String>,
+    /// The version of each report generator, by the name of its report
+    pub generator_versions: BTreeMap<String, String>,
+    /// The registries whose crates are mined into the database, by name, along with the URL of their index
+    pub registries: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, Ord, PartialOrd, Eq, PartialEq, Debug)]
 pub struct Dependency {
     /// The crate name
     pub name: String,
@@ -81,6 +210,17 @@ pub struct Dependency {
     pub package: Option<String>,
 }
 
+impl Dependency {
+    /// The name of the crate on crates.io, which differs from the dependency name if it was renamed
+    pub fn crate_name(&self) -> &str {
+        self.package.as_deref().unwrap_or(&self.name)
+    }
+
+    pub fn is_normal(&self) -> bool {
+        matches!(self.kind.as_deref(), None | Some("normal"))
+    }
+}
+
 impl From<crates_index_diff::Dependency> for Dependency {
     fn from(v: crates_index_diff::Dependency) -> Self {
         let crates_index_diff::Dependency {
@@ -125,58 +265,148 @@ pub struct CrateVersion {
     /// All crate dependencies
     #[serde(rename = "deps")]
     pub dependencies: Vec<Dependency>,
+    /// The line in the crates.io index this version was parsed from, exactly as published.
+    /// It's unset for versions stored before it was recorded.
+    #[serde(default)]
+    pub raw_index_line: Option<String>,
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Serialize, Deserialize, Clone, Copy)]
 pub enum ReportResult {
     Done,
     NotStarted,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub enum TaskState {
     /// The task was never started
+    #[default]
     NotStarted,
     /// The task tried to run, but failed N time with errors
     AttemptsWithFailure(Vec<String>),
     /// The task completed successfully
     Complete,
     /// Indicates a task is currently running
-    /// Please note that this would be unsafe as we don't update tasks in case the user requests
-    /// a shutdown or the program is killed.
-    /// Thus we cleanup in-progress tasks by checking if their stored_at time is before the process startup time.
+    /// When shutting down on a signal, these are stored as `AttemptsWithFailure([INTERRUPTED])`, and tasks of runs which
+    /// were killed are failed with `INTERRUPTED_BEFORE_RECORDED` when starting up next time. Neither counts as a failed
+    /// attempt when retrying.
+    /// Only if neither happened, like with databases of older versions, in-progress tasks are cleaned up by checking if
+    /// their stored_at time is before the process startup time.
     InProgress(Option<Vec<String>>),
+    /// The task failed in a way trying again won't fix, like downloading a file which doesn't exist, along with the
+    /// errors of all attempts. It's not started again unless it's reset.
+    PermanentFailure(Vec<String>),
 }
 
 impl TaskState {
+    /// The error of tasks which were stopped when shutting down on a signal
+    pub const INTERRUPTED: &'static str = "interrupted";
+    /// The error of tasks which were in progress when their run was killed, as found when starting up next time
+    pub const INTERRUPTED_BEFORE_RECORDED: &'static str = "Interrupted before the result was recorded";
+
+    /// True if `error` was recorded because the run stopped while the task was in progress, not because it failed.
+    pub fn is_interruption(error: &str) -> bool {
+        error == Self::INTERRUPTED || error == Self::INTERRUPTED_BEFORE_RECORDED
+    }
+
     pub fn is_complete(&self) -> bool {
         matches!(self, TaskState::Complete)
     }
-    pub fn merge_with(&mut self, other: &TaskState) {
-        fn merge_vec(mut existing: Vec<String>, new: &[String]) -> Vec<String> {
-            existing.extend(new.iter().cloned());
+    /// Transition into the `next` state and return the state to store.
+    ///
+    /// The resulting state always has the kind of `next`, but failures recorded by previous attempts
+    /// are carried over unless the task is completed or reset.
+    /// Transitions which shouldn't happen are logged and handled as gracefully as possible.
+    pub fn advance_to(self, next: TaskState) -> TaskState {
+        use TaskState::*;
+        fn concat(mut existing: Vec<String>, new: Vec<String>) -> Vec<String> {
+            existing.extend(new);
             existing
         }
-        use TaskState::*;
-        *self = match (&self, other) {
-            (AttemptsWithFailure(existing), AttemptsWithFailure(new)) => {
-                AttemptsWithFailure(merge_vec(existing.clone(), new))
+        match (self, next) {
+            (_, NotStarted) => NotStarted,
+            (_, Complete) => Complete,
+            (AttemptsWithFailure(existing), InProgress(None))
+            | (PermanentFailure(existing), InProgress(None))
+            | (InProgress(Some(existing)), InProgress(None)) => InProgress(Some(existing)),
+            (NotStarted, InProgress(None)) | (Complete, InProgress(None)) | (InProgress(None), InProgress(None)) => {
+                InProgress(None)
             }
-            (AttemptsWithFailure(existing), InProgress(None)) => InProgress(Some(existing.clone())),
-            (AttemptsWithFailure(_), InProgress(Some(_))) => {
-                panic!("One must not create inProgress preloaded with failed attempts, I think :D")
+            (current, InProgress(Some(new))) => {
+                log::warn!(
+                    "Tasks should start without failures, but got {:?} when advancing from {:?}",
+                    new,
+                    current
+                );
+                InProgress(Some(match current {
+                    AttemptsWithFailure(existing) | PermanentFailure(existing) | InProgress(Some(existing)) => {
+                        concat(existing, new)
+                    }
+                    NotStarted | Complete | InProgress(None) => new,
+                }))
             }
-            (InProgress(Some(existing)), AttemptsWithFailure(other)) => {
-                AttemptsWithFailure(merge_vec(existing.clone(), other))
-            }
-            (_, other) => other.clone(),
-        };
+            (AttemptsWithFailure(existing), AttemptsWithFailure(new))
+            | (PermanentFailure(existing), AttemptsWithFailure(new))
+            | (InProgress(Some(existing)), AttemptsWithFailure(new)) => AttemptsWithFailure(concat(existing, new)),
+            (NotStarted, AttemptsWithFailure(new))
+            | (Complete, AttemptsWithFailure(new))
+            | (InProgress(None), AttemptsWithFailure(new)) => AttemptsWithFailure(new),
+            (AttemptsWithFailure(existing), PermanentFailure(new))
+            | (PermanentFailure(existing), PermanentFailure(new))
+            | (InProgress(Some(existing)), PermanentFailure(new)) => PermanentFailure(concat(existing, new)),
+            (NotStarted, PermanentFailure(new))
+            | (Complete, PermanentFailure(new))
+            | (InProgress(None), PermanentFailure(new)) => PermanentFailure(new),
+        }
     }
-}
 
-impl Default for TaskState {
-    fn default() -> Self {
-        TaskState::NotStarted
+    /// Combine this state with `other`, which was recorded independently for the same task, like in another database.
+    ///
+    /// The state which got further wins, completions over permanent failures over failed attempts over tasks in progress.
+    /// Failures recorded by both are kept once, so that merging the same state again changes nothing.
+    pub fn merged(self, other: TaskState) -> TaskState {
+        use TaskState::*;
+        let mut failures = self.failures().to_vec();
+        failures.extend(
+            other
+                .failures()
+                .iter()
+                .filter(|failure| !self.failures().contains(failure))
+                .cloned(),
+        );
+        match if other.progress() > self.progress() {
+            other
+        } else {
+            self
+        } {
+            state @ (NotStarted | Complete) => state,
+            InProgress(_) if failures.is_empty() => InProgress(None),
+            InProgress(_) => InProgress(Some(failures)),
+            AttemptsWithFailure(_) => AttemptsWithFailure(failures),
+            PermanentFailure(_) => PermanentFailure(failures),
+        }
+    }
+
+    /// How far a task got, to compare states which didn't follow from each other
+    fn progress(&self) -> u8 {
+        use TaskState::*;
+        match self {
+            NotStarted => 0,
+            InProgress(_) => 1,
+            AttemptsWithFailure(_) => 2,
+            PermanentFailure(_) => 3,
+            Complete => 4,
+        }
+    }
+
+    /// The failures of all attempts recorded so far
+    fn failures(&self) -> &[String] {
+        use TaskState::*;
+        match self {
+            AttemptsWithFailure(failures) | PermanentFailure(failures) | InProgress(Some(failures)) => failures,
+            NotStarted | Complete | InProgress(None) => &[],
+        }
     }
 }
 
@@ -205,6 +435,11 @@ impl Default for Task {
 }
 
 impl Task {
+    /// Move this task into the `next` state, see `TaskState::advance_to()`.
+    pub fn advance_to(&mut self, next: TaskState) {
+        self.state = std::mem::take(&mut self.state).advance_to(next);
+    }
+
     // NOTE: Racy if task should be spawned based on the outcome, only for tasks with no contention!
     pub fn can_be_started(&self, startup_time: std::time::SystemTime) -> bool {
         match self.state {
@@ -215,18 +450,31 @@ impl Task {
     }
 }
 
+/// Timings of a download in milliseconds, to learn about the latency of the servers crates are downloaded from
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DownloadTiming {
+    /// How long it took to resolve the host names of the URL and where it redirected to, unset if none was resolved,
+    /// e.g. as it was an IP address or a connection to the host was reused
+    pub dns_lookup_ms: Option<u32>,
+    /// Time from sending the request to receiving the response headers
+    pub time_to_first_byte_ms: u32,
+    /// Time from sending the request to receiving the last byte of the body
+    pub total_ms: u32,
+}
+
 /// Append-variant-only data structure, otherwise migrations are needed
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub enum TaskResult {
     /// A dummy value just so that we can have a default value
+    #[default]
     None,
     /// Most interesting information about an unpacked crate
     ExplodedCrate {
         /// Meta data of all entries in the crate
         entries_meta_data: Vec<TarHeader>,
         /// The actual content of selected files, Cargo.*, build.rs and lib/main
-        /// IMPORTANT: This file may be partial and limited in size unless it is Cargo.toml, which
-        /// is always complete.
+        /// IMPORTANT: This file may be partial and limited in size unless it is Cargo.toml or Cargo.lock, which
+        /// are always complete. `TarHeader::truncated_at` is set for partial files extracted since it was introduced.
         /// Note that these are also present in entries_meta_data.
         selected_entries: Vec<(TarHeader, Vec<u8>)>,
     },
@@ -237,13 +485,103 @@ pub enum TaskResult {
         content_length: u32,
         /// The content type, it's optional because it might not be set (even though it should)
         content_type: Option<String>,
+        /// The `ETag` of the response, to ask whether it changed when downloading it again
+        #[serde(default)]
+        etag: Option<String>,
+        /// The `Last-Modified` header of the response, to ask whether it changed when downloading it again
+        #[serde(default)]
+        last_modified: Option<String>,
+        /// The HTTP status code of the response, unset if nothing was downloaded as the file was on disk already
+        #[serde(default)]
+        status: Option<u16>,
+        /// The URL the content was finally received from, after following all redirects
+        #[serde(default)]
+        final_url: Option<String>,
+        /// The `Server` header of the response, which often names the CDN serving it
+        #[serde(default)]
+        server: Option<String>,
+        /// How long the download took, unset if nothing was downloaded
+        #[serde(default)]
+        timing: Option<DownloadTiming>,
+    },
+    /// An `ExplodedCrate` stored as difference to the result of a previous version of the same crate.
+    /// It is resolved into an `ExplodedCrate` when read through the `TaskResultTable`.
+    ExplodedCrateDelta {
+        /// The crate version whose result, after resolving it, this delta applies to
+        base_version: String,
+        /// The amount of deltas to apply to get to the full result, including this one
+        chain_length: u8,
+        entries_meta_data: Vec<DeltaOp<TarHeader>>,
+        selected_entries: Vec<DeltaOp<(TarHeader, Vec<u8>)>>,
+    },
+    /// The parsed `Cargo.toml` of an extracted crate, stored next to the `ExplodedCrate` it was taken from
+    Manifest {
+        package: Box<ManifestPackage>,
+        /// All features by name, along with the features and dependencies they enable
+        features: BTreeMap<String, Vec<String>>,
+        /// All dependencies, including development, build and target specific ones, sorted by kind, target and name
+        dependencies: Vec<ManifestDependency>,
     },
 }
 
-impl Default for TaskResult {
-    fn default() -> Self {
-        TaskResult::None
-    }
+/// The `[package]` section of a `Cargo.toml` file
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct ManifestPackage {
+    pub name: String,
+    pub version: String,
+    pub edition: Option<String>,
+    pub rust_version: Option<String>,
+    pub authors: Vec<String>,
+    pub description: Option<String>,
+    pub license: Option<String>,
+    pub license_file: Option<String>,
+    pub repository: Option<String>,
+    pub homepage: Option<String>,
+    pub documentation: Option<String>,
+    /// The path to the readme, unset if there is none or it wasn't declared
+    pub readme: Option<String>,
+    pub keywords: Vec<String>,
+    pub categories: Vec<String>,
+    /// The path to the build script, unset if there is none or it wasn't declared
+    pub build: Option<String>,
+    /// The name of the native library the crate links to
+    pub links: Option<String>,
+}
+
+/// The section of `Cargo.toml` a dependency is declared in
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DependencyKind {
+    Normal,
+    Development,
+    Build,
+}
+
+/// A dependency declared in a `Cargo.toml` file
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ManifestDependency {
+    /// The name the dependency is known by in the crate
+    pub name: String,
+    /// The name of the dependency on the registry, if it was renamed
+    pub package: Option<String>,
+    pub kind: DependencyKind,
+    /// The version requirement, unset for dependencies on paths or git repositories only
+    pub req: Option<String>,
+    /// The `cfg()` expression or target triple the dependency is limited to
+    pub target: Option<String>,
+    /// The registry the dependency comes from, unset for the default one
+    pub registry: Option<String>,
+    pub optional: bool,
+    pub default_features: bool,
+    pub features: Vec<String>,
+}
+
+/// One step to build a list from the items of a base list
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub enum DeltaOp<T> {
+    /// Take `len` items of the base list, starting at `start`
+    Copy { start: u32, len: u32 },
+    /// Take the given items, which are not in the base list
+    Insert(Vec<T>),
 }
 
 impl From<crates_index_diff::CrateVersion> for CrateVersion {
@@ -263,6 +601,7 @@ impl From<crates_index_diff::CrateVersion> for CrateVersion {
             checksum,
             features,
             dependencies: dependencies.into_iter().map(Into::into).collect(),
+            raw_index_line: None,
         }
     }
 }
@@ -305,6 +644,7 @@ pub mod db_dump {
         pub crates: Vec<String>,
     }
 
+    #[allow(dead_code)]
     #[derive(Clone, Default, Serialize, Deserialize, Ord, PartialOrd, Eq, PartialEq, Debug)]
     pub struct Person {
         pub name: String,
@@ -373,3 +713,6 @@ pub mod db_dump {
         pub owners: Vec<Actor>,
     }
 }
+
+#[cfg(test)]
+mod task_state_test;
@@ -0,0 +1,91 @@
+use crate::model::TaskState::{self, *};
+use proptest::prelude::*;
+
+fn failures() -> impl Strategy<Value = Vec<String>> {
+    prop::collection::vec("[a-z ]{0,8}", 0..4)
+}
+
+fn task_state() -> impl Strategy<Value = TaskState> {
+    prop_oneof![
+        Just(NotStarted),
+        Just(Complete),
+        failures().prop_map(AttemptsWithFailure),
+        failures().prop_map(PermanentFailure),
+        prop::option::of(failures()).prop_map(InProgress),
+    ]
+}
+
+fn recorded_failures(state: &TaskState) -> Vec<String> {
+    match state {
+        AttemptsWithFailure(f) | PermanentFailure(f) | InProgress(Some(f)) => f.clone(),
+        NotStarted | Complete | InProgress(None) => Vec::new(),
+    }
+}
+
+proptest! {
+    #[test]
+    fn the_resulting_state_is_of_the_requested_kind(current in task_state(), next in task_state()) {
+        let res = current.advance_to(next.clone());
+        prop_assert_eq!(std::mem::discriminant(&res), std::mem::discriminant(&next));
+    }
+
+    #[test]
+    fn failures_are_kept_in_order_unless_completed_or_reset(current in task_state(), next in task_state()) {
+        let res = current.clone().advance_to(next.clone());
+        match next {
+            Complete | NotStarted => prop_assert!(recorded_failures(&res).is_empty()),
+            _ => {
+                let mut expected = recorded_failures(&current);
+                expected.extend(recorded_failures(&next));
+                prop_assert_eq!(recorded_failures(&res), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn merging_a_state_with_itself_changes_nothing(state in task_state()) {
+        let res = state.clone().merged(state.clone());
+        prop_assert_eq!(std::mem::discriminant(&res), std::mem::discriminant(&state));
+        prop_assert_eq!(recorded_failures(&res), recorded_failures(&state));
+    }
+
+    #[test]
+    fn merging_keeps_the_further_state_regardless_of_order(lhs in task_state(), rhs in task_state()) {
+        let (res, flipped) = (lhs.clone().merged(rhs.clone()), rhs.clone().merged(lhs.clone()));
+        prop_assert_eq!(std::mem::discriminant(&res), std::mem::discriminant(&flipped));
+        if !matches!(res, Complete | NotStarted) {
+            for failure in recorded_failures(&lhs).iter().chain(recorded_failures(&rhs).iter()) {
+                prop_assert!(recorded_failures(&res).contains(failure));
+            }
+        }
+    }
+}
+
+#[test]
+fn a_typical_failed_and_retried_task() {
+    let state = NotStarted
+        .advance_to(InProgress(None))
+        .advance_to(AttemptsWithFailure(vec!["first".into()]))
+        .advance_to(InProgress(None));
+    assert!(matches!(&state, InProgress(Some(f)) if f == &["first"]));
+    let state = state.advance_to(AttemptsWithFailure(vec!["second".into()]));
+    assert!(matches!(&state, AttemptsWithFailure(f) if f == &["first", "second"]));
+    assert!(state.advance_to(InProgress(None)).advance_to(Complete).is_complete());
+}
+
+#[test]
+fn in_progress_with_failures_does_not_panic() {
+    let state = AttemptsWithFailure(vec!["a".into()]).advance_to(InProgress(Some(vec!["b".into()])));
+    assert!(matches!(&state, InProgress(Some(f)) if f == &["a", "b"]));
+}
+
+#[test]
+fn merged_states_prefer_completion_and_keep_distinct_failures() {
+    assert!(AttemptsWithFailure(vec!["a".into()]).merged(Complete).is_complete());
+    assert!(Complete.merged(InProgress(None)).is_complete());
+    let state =
+        AttemptsWithFailure(vec!["a".into(), "b".into()]).merged(PermanentFailure(vec!["b".into(), "c".into()]));
+    assert!(matches!(&state, PermanentFailure(f) if f == &["a", "b", "c"]));
+    let state = InProgress(None).merged(NotStarted);
+    assert!(matches!(state, InProgress(None)));
+}
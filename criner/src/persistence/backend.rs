@@ -0,0 +1,278 @@
+//! The key-value operations tables are built on, to allow storing them elsewhere than in a single sqlite file.
+use crate::Result;
+use rusqlite::{params, OptionalExtension};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A store of tables mapping string keys to opaque values, which are msgpack encoded items.
+///
+/// The sqlite implementations store items in the sqlite database, either through a connection per table or through a
+/// pool shared by all tables, so bulk iteration and reporting via SQL through `TableAccess::connection()` see all items.
+/// Items stored with `Redb` are only seen through the backend, which isn't enough for mining.
+pub trait Backend {
+    /// Return the value stored at `key` in `table`, if present
+    fn get(&self, table: &str, key: &str) -> Result<Option<Vec<u8>>>;
+    /// Store `value` at `key` in `table`, replacing any previous value
+    fn put(&self, table: &str, key: &str, value: &[u8]) -> Result<()>;
+    /// Atomically replace the value at `key` in `table` with the one returned by `f`, which receives the current value.
+    /// `f` may be called more than once if the backend retries the operation.
+    fn update(&self, table: &str, key: &str, f: &mut dyn FnMut(Option<Vec<u8>>) -> Result<Vec<u8>>) -> Result<Vec<u8>>;
+    /// The amount of items in `table`, or of those whose key matches `glob`
+    fn count(&self, table: &str, glob: Option<&str>) -> Result<u64>;
+}
+
+/// A backend shared by all tables of a database
+pub type SharedBackend = std::sync::Arc<dyn Backend + Send + Sync>;
+
+/// The kinds of backends to store tables in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BackendKind {
+    /// A sqlite connection per opened table, shared by everything using that table
+    #[default]
+    Sqlite,
+    /// The given amount of connections to the same sqlite database, shared by all tables
+    SqlitePool(usize),
+    /// A redb database in the database directory, shared by all tables
+    #[cfg(feature = "redb")]
+    Redb,
+}
+
+impl std::str::FromStr for BackendKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let mut tokens = s.splitn(2, ':');
+        Ok(match (tokens.next(), tokens.next()) {
+            (Some("sqlite"), None) => BackendKind::Sqlite,
+            (Some("sqlite-pool"), None) => BackendKind::SqlitePool(8),
+            (Some("sqlite-pool"), Some(size)) => match size.parse() {
+                Ok(size) if size > 0 => BackendKind::SqlitePool(size),
+                _ => return Err(format!("Invalid pool size '{}', expected a positive number", size)),
+            },
+            #[cfg(feature = "redb")]
+            (Some("redb"), None) => BackendKind::Redb,
+            _ => {
+                return Err(format!(
+                    "Invalid backend '{}', expected sqlite, sqlite-pool or sqlite-pool:<size>{}",
+                    s,
+                    if cfg!(feature = "redb") { ", or redb" } else { "" }
+                ))
+            }
+        })
+    }
+}
+
+/// Multiple connections to the same sqlite database, so that threads reading and writing items don't wait for each other
+/// to release a single connection. Sqlite still allows only one writer at a time, but readers aren't blocked by it.
+pub struct SqlitePool {
+    connections: Vec<parking_lot::Mutex<rusqlite::Connection>>,
+    next: AtomicUsize,
+}
+
+impl SqlitePool {
+    /// Create a pool from `connections`, which must not be empty
+    pub fn new(connections: Vec<rusqlite::Connection>) -> SqlitePool {
+        assert!(!connections.is_empty(), "a pool needs at least one connection");
+        SqlitePool {
+            connections: connections.into_iter().map(parking_lot::Mutex::new).collect(),
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// An idle connection, or the next one in turn if all of them are busy
+    fn connection(&self) -> &parking_lot::Mutex<rusqlite::Connection> {
+        self.connections
+            .iter()
+            .find(|connection| !connection.is_locked())
+            .unwrap_or_else(|| &self.connections[self.next.fetch_add(1, Ordering::Relaxed) % self.connections.len()])
+    }
+}
+
+impl Backend for SqlitePool {
+    fn get(&self, table: &str, key: &str) -> Result<Option<Vec<u8>>> {
+        self.connection().get(table, key)
+    }
+
+    fn put(&self, table: &str, key: &str, value: &[u8]) -> Result<()> {
+        self.connection().put(table, key, value)
+    }
+
+    fn update(&self, table: &str, key: &str, f: &mut dyn FnMut(Option<Vec<u8>>) -> Result<Vec<u8>>) -> Result<Vec<u8>> {
+        self.connection().update(table, key, f)
+    }
+
+    fn count(&self, table: &str, glob: Option<&str>) -> Result<u64> {
+        self.connection().count(table, glob)
+    }
+}
+
+/// The sqlite backend, with one `(key TEXT PRIMARY KEY, data BLOB)` table per table name
+impl Backend for parking_lot::Mutex<rusqlite::Connection> {
+    fn get(&self, table: &str, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self
+            .lock()
+            .query_row(
+                &format!("SELECT data FROM '{}' WHERE key = ?1", table),
+                params![key],
+                |r| r.get::<_, Vec<u8>>(0),
+            )
+            .optional()?)
+    }
+
+    fn put(&self, table: &str, key: &str, value: &[u8]) -> Result<()> {
+        self.lock().execute(
+            &format!("REPLACE INTO '{}' (key, data) VALUES (?1, ?2)", table),
+            params![key, value],
+        )?;
+        Ok(())
+    }
+
+    fn update(&self, table: &str, key: &str, f: &mut dyn FnMut(Option<Vec<u8>>) -> Result<Vec<u8>>) -> Result<Vec<u8>> {
+        let mut guard = self.lock();
+        let transaction = guard.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+        let new_value = f(transaction
+            .query_row(
+                &format!("SELECT data FROM '{}' WHERE key = ?1", table),
+                params![key],
+                |r| r.get::<_, Vec<u8>>(0),
+            )
+            .optional()?)?;
+        transaction.execute(
+            &format!("REPLACE INTO '{}' (key, data) VALUES (?1, ?2)", table),
+            params![key, new_value],
+        )?;
+        transaction.commit()?;
+        Ok(new_value)
+    }
+
+    fn count(&self, table: &str, glob: Option<&str>) -> Result<u64> {
+        let connection = self.lock();
+        let count = match glob {
+            Some(glob) => connection.query_row(
+                &format!("SELECT COUNT(*) FROM '{}' WHERE key GLOB ?1", table),
+                params![glob],
+                |r| r.get::<_, i64>(0),
+            )?,
+            None => connection.query_row(&format!("SELECT COUNT(*) FROM '{}'", table), [], |r| r.get::<_, i64>(0))?,
+        };
+        Ok(count as u64)
+    }
+}
+
+#[cfg(feature = "redb")]
+pub use self::redb_backend::Redb;
+
+#[cfg(feature = "redb")]
+mod redb_backend {
+    use super::Backend;
+    use crate::{persistence::Durability, Error, Result};
+    use redb::ReadableTable;
+    use std::path::Path;
+
+    type Table<'db, 'txn> = redb::Table<'db, 'txn, &'static str, &'static [u8]>;
+
+    fn definition(table: &str) -> redb::TableDefinition<'_, &'static str, &'static [u8]> {
+        redb::TableDefinition::new(table)
+    }
+
+    fn storage(err: impl Into<redb::Error>) -> Error {
+        Error::Storage(err.into().to_string())
+    }
+
+    /// The items of all tables in a single redb database, with a redb table for each table.
+    ///
+    /// Readers see the last committed state without waiting for writers, which are serialized by redb.
+    pub struct Redb {
+        db: redb::Database,
+        durability: redb::Durability,
+    }
+
+    impl Redb {
+        /// Open or create the database at `path`, committing writes with `durability`
+        pub fn open(path: impl AsRef<Path>, durability: Durability) -> Result<Redb> {
+            Ok(Redb {
+                db: redb::Database::create(path).map_err(storage)?,
+                // Without ever syncing, redb wouldn't reuse the space of deleted pages
+                durability: match durability {
+                    Durability::Full => redb::Durability::Immediate,
+                    Durability::Relaxed | Durability::None => redb::Durability::Eventual,
+                },
+            })
+        }
+
+        fn write<T>(&self, table: &str, f: impl FnOnce(&mut Table<'_, '_>) -> Result<T>) -> Result<T> {
+            let mut transaction = self.db.begin_write().map_err(storage)?;
+            transaction.set_durability(self.durability);
+            let res = f(&mut transaction.open_table(definition(table)).map_err(storage)?)?;
+            transaction.commit().map_err(storage)?;
+            Ok(res)
+        }
+
+        /// Call `f` with `table`, or return `None` if nothing was ever written to it
+        fn read<T>(
+            &self,
+            table: &str,
+            f: impl FnOnce(&redb::ReadOnlyTable<&'static str, &'static [u8]>) -> Result<T>,
+        ) -> Result<Option<T>> {
+            let transaction = self.db.begin_read().map_err(storage)?;
+            let res = match transaction.open_table(definition(table)) {
+                Ok(table) => f(&table).map(Some),
+                Err(redb::TableError::TableDoesNotExist(_)) => Ok(None),
+                Err(err) => Err(storage(err)),
+            };
+            res
+        }
+    }
+
+    impl Backend for Redb {
+        fn get(&self, table: &str, key: &str) -> Result<Option<Vec<u8>>> {
+            Ok(self
+                .read(table, |table| {
+                    Ok(table.get(key).map_err(storage)?.map(|v| v.value().to_vec()))
+                })?
+                .flatten())
+        }
+
+        fn put(&self, table: &str, key: &str, value: &[u8]) -> Result<()> {
+            self.write(table, |table| {
+                table.insert(key, value).map_err(storage)?;
+                Ok(())
+            })
+        }
+
+        fn update(
+            &self,
+            table: &str,
+            key: &str,
+            f: &mut dyn FnMut(Option<Vec<u8>>) -> Result<Vec<u8>>,
+        ) -> Result<Vec<u8>> {
+            self.write(table, |table| {
+                let current = table.get(key).map_err(storage)?.map(|v| v.value().to_vec());
+                let new_value = f(current)?;
+                table.insert(key, new_value.as_slice()).map_err(storage)?;
+                Ok(new_value)
+            })
+        }
+
+        /// Globs are matched like sqlite does, except for character classes which are negated with `[!…]` instead of `[^…]`.
+        fn count(&self, table: &str, glob: Option<&str>) -> Result<u64> {
+            let pattern = glob.map(glob::Pattern::new).transpose()?;
+            Ok(self
+                .read(table, |table| match &pattern {
+                    None => table.len().map_err(storage),
+                    Some(pattern) => {
+                        let mut count = 0;
+                        for item in table.iter().map_err(storage)? {
+                            if pattern.matches(item.map_err(storage)?.0.value()) {
+                                count += 1;
+                            }
+                        }
+                        Ok(count)
+                    }
+                })?
+                .unwrap_or(0))
+        }
+    }
+}
+
+#[cfg(test)]
+mod backend_test;
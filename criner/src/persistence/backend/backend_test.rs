@@ -0,0 +1,179 @@
+use crate::persistence::{self, temp_db::TempDir, Backend, BackendKind, TableAccess};
+
+fn sqlite() -> parking_lot::Mutex<rusqlite::Connection> {
+    let connection = rusqlite::Connection::open_in_memory().unwrap();
+    connection
+        .execute_batch("CREATE TABLE 'crate' (key TEXT PRIMARY KEY NOT NULL, data BLOB NOT NULL)")
+        .unwrap();
+    parking_lot::Mutex::new(connection)
+}
+
+#[test]
+fn sqlite_put_get_update_and_count() {
+    let backend = sqlite();
+    assert_eq!(backend.get("crate", "a").unwrap(), None);
+
+    backend.put("crate", "a", b"1").unwrap();
+    backend.put("crate", "b", b"2").unwrap();
+    assert_eq!(backend.get("crate", "a").unwrap(), Some(b"1".to_vec()));
+
+    let updated = backend
+        .update("crate", "a", &mut |existing| {
+            let mut v = existing.unwrap_or_default();
+            v.push(b'!');
+            Ok(v)
+        })
+        .unwrap();
+    assert_eq!(updated, b"1!".to_vec());
+    assert_eq!(backend.get("crate", "a").unwrap(), Some(b"1!".to_vec()));
+
+    assert_eq!(backend.count("crate", None).unwrap(), 2);
+    assert_eq!(backend.count("crate", Some("b*")).unwrap(), 1);
+}
+
+#[test]
+fn sqlite_pool_shares_the_database_among_its_connections() {
+    let dir = TempDir::new("backend-pool");
+    let db = persistence::Db::open_with_backend(&dir, Default::default(), BackendKind::SqlitePool(3)).unwrap();
+    let tasks = db.open_tasks().unwrap();
+    let mut progress = prodash::Tree::new().add_child("pool");
+
+    std::thread::scope(|s| {
+        for thread_id in 0..4 {
+            let tasks = &tasks;
+            s.spawn(move || {
+                let mut progress = prodash::Tree::new().add_child("thread");
+                for id in 0..10 {
+                    tasks
+                        .insert(&mut progress, format!("{}-{}", thread_id, id), &Default::default())
+                        .unwrap();
+                }
+            });
+        }
+    });
+    assert_eq!(tasks.count(), 40);
+    tasks
+        .update(Some(&mut progress), "0-0", |mut t| {
+            t.process = "updated".into();
+            t
+        })
+        .unwrap();
+    assert_eq!(
+        db.open_tasks().unwrap().get("0-0").unwrap().unwrap().process,
+        "updated",
+        "all tables use the same pool"
+    );
+    assert_eq!(
+        tasks
+            .connection()
+            .lock()
+            .query_row("SELECT COUNT(*) FROM task", [], |r| r.get::<_, i64>(0))
+            .unwrap(),
+        40,
+        "items are in the sqlite database and can be queried as usual"
+    );
+}
+
+#[test]
+fn backends_are_named_on_the_command_line() {
+    assert_eq!("sqlite".parse(), Ok(BackendKind::Sqlite));
+    assert_eq!("sqlite-pool".parse(), Ok(BackendKind::SqlitePool(8)));
+    assert_eq!("sqlite-pool:2".parse(), Ok(BackendKind::SqlitePool(2)));
+    assert!("sqlite-pool:0".parse::<BackendKind>().is_err());
+    assert!("sled".parse::<BackendKind>().is_err());
+}
+
+#[cfg(feature = "redb")]
+mod redb {
+    use crate::persistence::{self, temp_db::TempDb, Backend, BackendKind, Redb, TableAccess};
+
+    #[test]
+    fn put_get_update_and_count() {
+        let db = TempDb::new("backend-redb-items");
+        let backend = Redb::open(db.dir.join(persistence::REDB_FILE_NAME), Default::default()).unwrap();
+        assert_eq!(
+            backend.get("crate", "a").unwrap(),
+            None,
+            "tables don't exist before they are written"
+        );
+        assert_eq!(backend.count("crate", None).unwrap(), 0);
+
+        backend.put("crate", "a", b"1").unwrap();
+        backend.put("crate", "b", b"2").unwrap();
+        backend.put("task", "a", b"other table").unwrap();
+        assert_eq!(backend.get("crate", "a").unwrap(), Some(b"1".to_vec()));
+
+        let updated = backend
+            .update("crate", "a", &mut |existing| {
+                let mut v = existing.unwrap_or_default();
+                v.push(b'!');
+                Ok(v)
+            })
+            .unwrap();
+        assert_eq!(updated, b"1!".to_vec());
+        assert_eq!(backend.get("crate", "a").unwrap(), Some(b"1!".to_vec()));
+        assert!(
+            backend
+                .update("crate", "b", &mut |_| Err(crate::Error::Bug("failed")))
+                .is_err(),
+            "failures are returned"
+        );
+        assert_eq!(
+            backend.get("crate", "b").unwrap(),
+            Some(b"2".to_vec()),
+            "and nothing is written"
+        );
+
+        assert_eq!(backend.count("crate", None).unwrap(), 2);
+        assert_eq!(backend.count("crate", Some("b*")).unwrap(), 1);
+        assert_eq!(backend.count("task", Some("?")).unwrap(), 1);
+    }
+
+    #[test]
+    fn tables_store_their_items_in_the_redb_database() {
+        let db = TempDb::new("backend-redb");
+        let db = persistence::Db::open_with_backend(&db.dir, Default::default(), BackendKind::Redb).unwrap();
+        let tasks = db.open_tasks().unwrap();
+        let mut progress = prodash::Tree::new().add_child("redb");
+        std::thread::scope(|s| {
+            for thread_id in 0..4 {
+                let tasks = &tasks;
+                s.spawn(move || {
+                    let mut progress = prodash::Tree::new().add_child("thread");
+                    for id in 0..10 {
+                        tasks
+                            .insert(&mut progress, format!("{}-{}", thread_id, id), &Default::default())
+                            .unwrap();
+                    }
+                });
+            }
+        });
+        assert_eq!(tasks.count(), 40);
+        tasks
+            .update(Some(&mut progress), "0-0", |mut t| {
+                t.process = "updated".into();
+                t
+            })
+            .unwrap();
+        assert_eq!(
+            db.open_tasks().unwrap().get("0-0").unwrap().unwrap().process,
+            "updated",
+            "all tables use the same database"
+        );
+        assert_eq!(
+            tasks
+                .connection()
+                .lock()
+                .query_row("SELECT COUNT(*) FROM task", [], |r| r.get::<_, i64>(0))
+                .unwrap(),
+            0,
+            "nothing is stored in sqlite"
+        );
+    }
+
+    #[test]
+    fn it_is_named_on_the_command_line() {
+        assert_eq!("redb".parse(), Ok(BackendKind::Redb));
+        assert!("redb:2".parse::<BackendKind>().is_err());
+    }
+}
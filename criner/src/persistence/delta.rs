@@ -0,0 +1,335 @@
+//! Storing the results of extracted crates as difference to the result of the previous version, as consecutive
+//! versions usually share most of their files.
+use crate::{
+    model::{Crate, DeltaOp, TaskResult},
+    persistence::{
+        key_prefix_upper_bound,
+        partial::{self, Wanted},
+        CrateTable, TableAccess, TaskResultTable, KEY_SEP_CHAR,
+    },
+    Error, Result,
+};
+use rusqlite::{params, OptionalExtension};
+use std::{collections::HashMap, hash::Hash};
+
+/// The maximum amount of deltas to apply to get to a full result, which bounds the cost of reading one
+pub const MAX_CHAIN_LENGTH: u8 = 8;
+
+/// Compute the operations to build `new` from `base`, copying runs of items `base` has in the same order.
+pub fn diff<T: Eq + Hash + Clone>(base: &[T], new: &[T]) -> Vec<DeltaOp<T>> {
+    let mut position_in_base = HashMap::with_capacity(base.len());
+    for (idx, item) in base.iter().enumerate().rev() {
+        position_in_base.insert(item, idx);
+    }
+
+    let mut ops = Vec::new();
+    let mut idx = 0;
+    while idx < new.len() {
+        match position_in_base.get(&new[idx]) {
+            Some(&start) => {
+                let len = base[start..]
+                    .iter()
+                    .zip(&new[idx..])
+                    .take_while(|(lhs, rhs)| lhs == rhs)
+                    .count();
+                ops.push(DeltaOp::Copy {
+                    start: start as u32,
+                    len: len as u32,
+                });
+                idx += len;
+            }
+            None => {
+                match ops.last_mut() {
+                    Some(DeltaOp::Insert(items)) => items.push(new[idx].clone()),
+                    _ => ops.push(DeltaOp::Insert(vec![new[idx].clone()])),
+                }
+                idx += 1;
+            }
+        }
+    }
+    ops
+}
+
+/// Build the list described by `ops` from `base`.
+pub fn apply<T: Clone>(base: &[T], ops: Vec<DeltaOp<T>>) -> Result<Vec<T>> {
+    let mut out = Vec::with_capacity(base.len());
+    for op in ops {
+        match op {
+            DeltaOp::Copy { start, len } => {
+                let (start, len) = (start as usize, len as usize);
+                let items = base.get(start..start + len).ok_or_else(|| {
+                    Error::Corrupt(format!(
+                        "Delta copies items {}..{} of a base with only {} items",
+                        start,
+                        start + len,
+                        base.len()
+                    ))
+                })?;
+                out.extend_from_slice(items);
+            }
+            DeltaOp::Insert(items) => out.extend(items),
+        }
+    }
+    Ok(out)
+}
+
+fn inserted_items<T>(ops: &[DeltaOp<T>]) -> usize {
+    ops.iter()
+        .map(|op| match op {
+            DeltaOp::Copy { .. } => 0,
+            DeltaOp::Insert(items) => items.len(),
+        })
+        .sum()
+}
+
+/// Replace the crate version in a fully qualified result `key` with `version`
+fn with_version(key: &str, version: &str) -> Result<String> {
+    let mut tokens: Vec<_> = key.split(KEY_SEP_CHAR).collect();
+    match tokens.get_mut(1) {
+        Some(v) => *v = version,
+        None => return Err(Error::Corrupt(format!("'{}' is not a result key", key))),
+    }
+    Ok(tokens.join(&KEY_SEP_CHAR.to_string()))
+}
+
+/// Store `result` at `key` exactly as given
+pub(crate) fn put(connection: &rusqlite::Connection, key: &str, result: &TaskResult) -> Result<()> {
+    connection.execute(
+        &format!(
+            "REPLACE INTO {} (key, data) VALUES (?1, ?2)",
+            TaskResultTable::table_name()
+        ),
+        params![key, rmp_serde::to_vec(result)?],
+    )?;
+    Ok(())
+}
+
+/// The name and version of the crate a fully qualified result `key` belongs to
+fn crate_name_and_version(key: &str) -> Option<(&str, &str)> {
+    let mut tokens = key.split(KEY_SEP_CHAR);
+    tokens.next().zip(tokens.next())
+}
+
+/// All versions of `crate_name` in the order results are encoded against each other
+fn versions_of(connection: &rusqlite::Connection, crate_name: &str) -> Result<Vec<String>> {
+    Ok(connection
+        .query_row(
+            &format!("SELECT data FROM {} WHERE key = ?1", CrateTable::table_name()),
+            params![crate_name],
+            |r| r.get::<_, Vec<u8>>(0),
+        )
+        .optional()?
+        .map(|d| Crate::from(d.as_slice()).versions)
+        .unwrap_or_default())
+}
+
+pub(crate) fn get_raw(connection: &rusqlite::Connection, key: &str) -> Result<Option<TaskResult>> {
+    Ok(connection
+        .query_row(
+            &format!("SELECT data FROM {} WHERE key = ?1", TaskResultTable::table_name()),
+            params![key],
+            |r| r.get::<_, Vec<u8>>(0),
+        )
+        .optional()?
+        .map(|d| TaskResult::from(d.as_slice())))
+}
+
+fn get_raw_partially(connection: &rusqlite::Connection, key: &str, wanted: Wanted<'_>) -> Result<Option<TaskResult>> {
+    connection
+        .query_row(
+            &format!("SELECT data FROM {} WHERE key = ?1", TaskResultTable::table_name()),
+            params![key],
+            |r| r.get::<_, Vec<u8>>(0),
+        )
+        .optional()?
+        .map(|d| partial::decode(&d, wanted))
+        .transpose()
+}
+
+/// Like `get_raw()` followed by `resolve()`, but only selected entries of extracted crates for which `wanted` returns true
+/// are kept, and the content of all others isn't even decoded.
+pub(crate) fn get_partially(
+    connection: &rusqlite::Connection,
+    key: &str,
+    wanted: Wanted<'_>,
+) -> Result<Option<TaskResult>> {
+    let result = match get_raw_partially(connection, key, wanted)? {
+        Some(result) => result,
+        None => return Ok(None),
+    };
+    Ok(Some(match resolve_with(connection, key, result, Some(wanted))? {
+        TaskResult::ExplodedCrate {
+            entries_meta_data,
+            mut selected_entries,
+        } => {
+            selected_entries.retain(|(header, _)| wanted(header));
+            TaskResult::ExplodedCrate {
+                entries_meta_data,
+                selected_entries,
+            }
+        }
+        result => result,
+    }))
+}
+
+/// Turn `result` stored at `key` into an `ExplodedCrate` if it is a delta, or return it unchanged.
+pub fn resolve(connection: &rusqlite::Connection, key: &str, result: TaskResult) -> Result<TaskResult> {
+    resolve_with(connection, key, result, None)
+}
+
+/// Like `resolve()`, but bases are decoded partially if `wanted` is set
+fn resolve_with(
+    connection: &rusqlite::Connection,
+    key: &str,
+    result: TaskResult,
+    wanted: Option<Wanted<'_>>,
+) -> Result<TaskResult> {
+    match result {
+        TaskResult::ExplodedCrateDelta {
+            base_version,
+            chain_length: _,
+            entries_meta_data,
+            selected_entries,
+        } => {
+            let base_key = with_version(key, &base_version)?;
+            let base = match wanted {
+                Some(wanted) => get_raw_partially(connection, &base_key, wanted)?,
+                None => get_raw(connection, &base_key)?,
+            }
+            .ok_or_else(|| Error::Corrupt(format!("Base result '{}' of delta '{}' is missing", base_key, key)))?;
+            match resolve_with(connection, &base_key, base, wanted)? {
+                TaskResult::ExplodedCrate {
+                    entries_meta_data: base_entries_meta_data,
+                    selected_entries: base_selected_entries,
+                } => Ok(TaskResult::ExplodedCrate {
+                    entries_meta_data: apply(&base_entries_meta_data, entries_meta_data)?,
+                    selected_entries: apply(&base_selected_entries, selected_entries)?,
+                }),
+                _ => Err(Error::Corrupt(format!(
+                    "Base result '{}' of delta '{}' isn't an extracted crate",
+                    base_key, key
+                ))),
+            }
+        }
+        result => Ok(result),
+    }
+}
+
+/// Return a delta of `result`, to be stored at `key`, against the result of the previous version of the same crate.
+/// Returns `None` if `result` should be stored as is, as there is no previous result, the chain of deltas would get too long,
+/// or if the delta wouldn't save much.
+pub fn encode(connection: &rusqlite::Connection, key: &str, result: &TaskResult) -> Result<Option<TaskResult>> {
+    let (entries_meta_data, selected_entries) = match result {
+        TaskResult::ExplodedCrate {
+            entries_meta_data,
+            selected_entries,
+        } => (entries_meta_data, selected_entries),
+        _ => return Ok(None),
+    };
+    let (crate_name, crate_version) = match crate_name_and_version(key) {
+        Some(name_and_version) => name_and_version,
+        None => return Ok(None),
+    };
+    let versions = versions_of(connection, crate_name)?;
+    let base_version = match versions
+        .iter()
+        .position(|v| v == crate_version)
+        .and_then(|pos| versions[..pos].last())
+    {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+
+    let base_key = with_version(key, base_version)?;
+    let base = match get_raw(connection, &base_key)? {
+        Some(base) => base,
+        None => return Ok(None),
+    };
+    let chain_length = match base {
+        TaskResult::ExplodedCrate { .. } => 1,
+        TaskResult::ExplodedCrateDelta { chain_length, .. } if chain_length < MAX_CHAIN_LENGTH => chain_length + 1,
+        _ => return Ok(None),
+    };
+    let (base_entries_meta_data, base_selected_entries) = match resolve(connection, &base_key, base)? {
+        TaskResult::ExplodedCrate {
+            entries_meta_data,
+            selected_entries,
+        } => (entries_meta_data, selected_entries),
+        _ => return Ok(None),
+    };
+    // The content of old versions may have been dropped by retention, which would make for a poor base.
+    if base_selected_entries.is_empty() && !selected_entries.is_empty() {
+        return Ok(None);
+    }
+
+    let num_entries = entries_meta_data.len();
+    let entries_meta_data = diff(&base_entries_meta_data, entries_meta_data);
+    if inserted_items(&entries_meta_data) * 2 > num_entries {
+        return Ok(None);
+    }
+    let selected_entries = diff(&base_selected_entries, selected_entries);
+    Ok(Some(TaskResult::ExplodedCrateDelta {
+        base_version: base_version.to_owned(),
+        chain_length,
+        entries_meta_data,
+        selected_entries,
+    }))
+}
+
+/// Store all results which are encoded as delta against the result at `key` in full, so that the result at `key` can be
+/// changed without breaking them. Any other version of the same crate may be based on it, as versions published later
+/// can sort in between a result and its base. Returns the amount of results stored in full.
+///
+/// Call it in the same transaction which changes the result at `key`, as otherwise a delta against it may be stored in between.
+pub(crate) fn materialize_dependents(connection: &rusqlite::Connection, key: &str) -> Result<usize> {
+    let (crate_name, crate_version) = match crate_name_and_version(key) {
+        Some(name_and_version) => name_and_version,
+        None => return Ok(0),
+    };
+    let exists: Option<i64> = connection
+        .query_row(
+            &format!("SELECT 1 FROM {} WHERE key = ?1", TaskResultTable::table_name()),
+            params![key],
+            |r| r.get(0),
+        )
+        .optional()?;
+    if exists.is_none() {
+        return Ok(0);
+    }
+    let prefix = format!("{}{}", crate_name, KEY_SEP_CHAR);
+    let candidates = {
+        let mut statement = connection.prepare(&format!(
+            "SELECT key FROM {} WHERE key >= ?1 AND key < ?2",
+            TaskResultTable::table_name()
+        ))?;
+        let keys = statement
+            .query_map(params![prefix, key_prefix_upper_bound(&prefix)], |r| {
+                r.get::<_, String>(0)
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        keys
+    };
+
+    let mut materialized = 0;
+    for dependent_key in candidates {
+        // Only results of the same task of another version can be based on `key`
+        if dependent_key == key || with_version(&dependent_key, crate_version)? != key {
+            continue;
+        }
+        let is_based_on_key = matches!(
+            get_raw_partially(connection, &dependent_key, &|_| false)?,
+            Some(TaskResult::ExplodedCrateDelta { base_version, .. }) if base_version == crate_version
+        );
+        if !is_based_on_key {
+            continue;
+        }
+        let dependent = get_raw(connection, &dependent_key)?.expect("the result was just read");
+        let full = resolve(connection, &dependent_key, dependent)?;
+        put(connection, &dependent_key, &full)?;
+        materialized += 1;
+    }
+    Ok(materialized)
+}
+
+#[cfg(test)]
+mod delta_test;
@@ -0,0 +1,34 @@
+use crate::model::DeltaOp;
+use crate::persistence::delta::{apply, diff};
+use proptest::prelude::*;
+
+fn items() -> impl Strategy<Value = Vec<u8>> {
+    prop::collection::vec(0u8..16, 0..32)
+}
+
+proptest! {
+    #[test]
+    fn applying_a_diff_reproduces_the_new_list(base in items(), new in items()) {
+        let ops = diff(&base, &new);
+        prop_assert_eq!(apply(&base, ops).unwrap(), new);
+    }
+}
+
+#[test]
+fn unchanged_runs_are_copied() {
+    let base = ["Cargo.toml", "src/lib.rs", "src/a.rs", "src/b.rs"];
+    let new = ["Cargo.toml", "src/lib.rs", "src/new.rs", "src/a.rs", "src/b.rs"];
+    assert_eq!(
+        diff(&base, &new),
+        vec![
+            DeltaOp::Copy { start: 0, len: 2 },
+            DeltaOp::Insert(vec!["src/new.rs"]),
+            DeltaOp::Copy { start: 2, len: 2 },
+        ]
+    );
+}
+
+#[test]
+fn copies_beyond_the_base_are_rejected() {
+    assert!(apply(&[1, 2], vec![DeltaOp::Copy { start: 1, len: 2 }]).is_err());
+}
@@ -0,0 +1,32 @@
+//! Results of extracted crates for tests, shaped like the ones stored by the extraction.
+use crate::model::{TarHeader, TaskResult};
+
+/// The header of a regular file at `path`
+pub fn header(path: &str) -> TarHeader {
+    TarHeader {
+        path: path.as_bytes().to_vec(),
+        size: 100,
+        entry_type: b'0',
+        encoding: None,
+        truncated_at: None,
+    }
+}
+
+/// An extracted crate with an entry for each of `paths`, all of which are selected with their path as content
+pub fn exploded_crate(paths: &[&str]) -> TaskResult {
+    TaskResult::ExplodedCrate {
+        entries_meta_data: paths.iter().map(|path| header(path)).collect(),
+        selected_entries: paths
+            .iter()
+            .map(|path| (header(path), path.as_bytes().to_vec()))
+            .collect(),
+    }
+}
+
+/// Like `exploded_crate()`, but without any selected entries, like results whose content was dropped by retention
+pub fn exploded_crate_without_content(paths: &[&str]) -> TaskResult {
+    TaskResult::ExplodedCrate {
+        entries_meta_data: paths.iter().map(|path| header(path)).collect(),
+        selected_entries: Vec::new(),
+    }
+}
@@ -0,0 +1,246 @@
+//! The record describing a database, written when it's created and whenever criner mines into it, which tells other
+//! versions of criner whether they can work with it.
+use crate::{
+    model::{self, DatabaseInfo},
+    persistence::{MetaTable, TableAccess, SCHEMA_VERSION},
+    registry::Registry,
+    utils::parse_semver,
+    Error, Result,
+};
+use rusqlite::{params, OptionalExtension};
+use serde::de::{self, DeserializeOwned, Deserializer, Visitor};
+use std::{collections::BTreeMap, fmt::Write, time::SystemTime};
+
+/// The key of the record describing the database in the `meta` table, which sorts before the keys of all `Context` items
+pub const DATABASE_INFO_KEY: &str = "about";
+
+/// An SQL condition matching only the `Context` items of the `meta` table, one per day
+pub const CONTEXT_CONDITION: &str = "key LIKE 'context/%'";
+
+/// The version of criner, as recorded in the databases it writes
+pub const CRINER_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// A deserializer which records the name and fields of the struct, or the variants of the enum, it is asked for, and
+/// fails right after.
+struct Layout<'a>(&'a mut String);
+
+impl<'de, 'a> Deserializer<'de> for Layout<'a> {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> std::result::Result<V::Value, Self::Error> {
+        Err(de::Error::custom("only structs and enums have a layout"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> std::result::Result<V::Value, Self::Error> {
+        write!(self.0, "struct {} {{ {} }}", name, fields.join(", ")).ok();
+        Err(de::Error::custom("layout recorded"))
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        _visitor: V,
+    ) -> std::result::Result<V::Value, Self::Error> {
+        write!(self.0, "enum {} {{ {} }}", name, variants.join(", ")).ok();
+        Err(de::Error::custom("layout recorded"))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf option unit unit_struct
+        newtype_struct seq tuple tuple_struct map identifier ignored_any
+    }
+}
+
+/// A hash of the names of the fields of `T`, or of its variants if it's an enum. Types it contains aren't looked at.
+fn model_hash<T: DeserializeOwned>() -> String {
+    let mut layout = String::new();
+    T::deserialize(Layout(&mut layout)).ok();
+    hex::encode(&<sha2::Sha256 as sha2::Digest>::digest(layout.as_bytes())[..8])
+}
+
+/// The hash of the model of the items of each table stored as msgpack, by table name
+pub fn model_hashes() -> BTreeMap<String, String> {
+    vec![
+        ("crate", model_hash::<model::Crate>()),
+        ("crate_version", model_hash::<model::CrateVersion>()),
+        ("task", model_hash::<model::Task>()),
+        ("result", model_hash::<model::TaskResult>()),
+        ("meta", model_hash::<model::Context>()),
+        ("index_check", model_hash::<model::IndexCheck>()),
+        ("run_summary", model_hash::<model::RunSummary>()),
+        ("crates.io-crate", model_hash::<model::db_dump::Crate>()),
+    ]
+    .into_iter()
+    .map(|(table, hash)| (table.to_owned(), hash))
+    .collect()
+}
+
+/// Describe a database written by this version of criner, which created it at `created_at` if set
+fn describe(created_at: Option<SystemTime>) -> DatabaseInfo {
+    DatabaseInfo {
+        created_by: created_at.map(|_| CRINER_VERSION.to_owned()),
+        created_at,
+        written_by: CRINER_VERSION.to_owned(),
+        schema_version: SCHEMA_VERSION,
+        model_hashes: model_hashes(),
+        generator_versions: crate::engine::stage::report::generator_versions(),
+        registries: BTreeMap::new(),
+    }
+}
+
+/// The record describing the database of `connection`, if it was created or mined into by a version of criner which
+/// writes one
+pub fn read(connection: &rusqlite::Connection) -> Result<Option<DatabaseInfo>> {
+    connection
+        .query_row(
+            &format!("SELECT data FROM '{}' WHERE key = ?1", MetaTable::table_name()),
+            params![DATABASE_INFO_KEY],
+            |r| r.get::<_, Vec<u8>>(0),
+        )
+        .optional()?
+        .map(|data| {
+            rmp_serde::from_read_ref(&data)
+                .map_err(|err| Error::Corrupt(format!("The record describing the database can't be read: {}", err)))
+        })
+        .transpose()
+}
+
+fn write(connection: &rusqlite::Connection, info: &DatabaseInfo) -> Result<()> {
+    connection.execute(
+        &format!("REPLACE INTO '{}' (key, data) VALUES (?1, ?2)", MetaTable::table_name()),
+        params![DATABASE_INFO_KEY, rmp_serde::to_vec(info)?],
+    )?;
+    Ok(())
+}
+
+/// Describe the database of `connection`, which was just created
+pub(crate) fn record_creation(connection: &rusqlite::Connection) -> Result<()> {
+    write(connection, &describe(Some(SystemTime::now())))
+}
+
+/// Record that this version of criner mines crates of `registry` into the database of `connection`, keeping what is
+/// known about its creation and the other registries mined into it. Returns the updated record.
+pub(crate) fn record_mining(connection: &rusqlite::Connection, registry: &Registry) -> Result<DatabaseInfo> {
+    let previous = read(connection)?;
+    let mut info = describe(previous.as_ref().and_then(|p| p.created_at));
+    if let Some(previous) = previous {
+        info.created_by = previous.created_by;
+        info.registries = previous.registries;
+    }
+    info.registries
+        .insert(registry.name.clone(), registry.index_url.clone());
+    write(connection, &info)?;
+    Ok(info)
+}
+
+/// How this version of criner relates to a database, as told by the record describing it
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Compatibility {
+    /// The version of criner which wrote the database last, or `None` if it isn't known
+    pub written_by: Option<String>,
+    /// True if the database was written by a newer version of criner than this one
+    pub written_by_newer: bool,
+    /// The version of the layout of the database if it's newer than the one this version of criner supports
+    pub newer_schema_version: Option<u32>,
+    /// Tables whose items were written with a model other than the one of this version of criner
+    pub changed_models: Vec<String>,
+    /// Reports whose generator has a version other than the one of this version of criner
+    pub changed_generators: Vec<String>,
+}
+
+impl Compatibility {
+    /// How this version of criner relates to the database described by `info`, or to one which doesn't describe itself
+    /// if `None`, which is the case for databases written by older versions of criner
+    pub fn of(info: Option<&DatabaseInfo>) -> Compatibility {
+        let info = match info {
+            Some(info) => info,
+            None => return Compatibility::default(),
+        };
+        let ours = (model_hashes(), crate::engine::stage::report::generator_versions());
+        let changed = |recorded: &BTreeMap<String, String>, ours: &BTreeMap<String, String>| {
+            recorded
+                .iter()
+                .filter(|(name, value)| ours.get(*name).is_some_and(|our_value| our_value != *value))
+                .map(|(name, _)| name.clone())
+                .collect()
+        };
+        Compatibility {
+            written_by: Some(info.written_by.clone()),
+            written_by_newer: match (parse_semver(&info.written_by), parse_semver(CRINER_VERSION)) {
+                (Ok(theirs), Ok(ours)) => theirs > ours,
+                _ => info.written_by != CRINER_VERSION,
+            },
+            newer_schema_version: Some(info.schema_version).filter(|v| *v > SCHEMA_VERSION),
+            changed_models: changed(&info.model_hashes, &ours.0),
+            changed_generators: changed(&info.generator_versions, &ours.1),
+        }
+    }
+
+    /// How this version of criner relates to the database of `connection`
+    pub fn of_database(connection: &rusqlite::Connection) -> Result<Compatibility> {
+        Ok(Compatibility::of(read(connection)?.as_ref()))
+    }
+
+    /// Fail unless this version of criner can read the database, and warn about the differences it adapts to otherwise.
+    pub fn verify(self) -> Result<Compatibility> {
+        self.verify_readable()?;
+        if let Some(differences) = self.differences() {
+            log::warn!("{}", differences);
+        }
+        Ok(self)
+    }
+
+    /// Items written with the model of an older version of criner are migrated when read, but those written by a newer
+    /// version with a model we don't know can't be read.
+    fn verify_readable(&self) -> Result<()> {
+        if let Some(found) = self.newer_schema_version {
+            return Err(Error::SchemaVersion {
+                found,
+                supported: SCHEMA_VERSION,
+            });
+        }
+        if self.written_by_newer && !self.changed_models.is_empty() {
+            return Err(Error::IncompatibleDatabase(format!(
+                "The database was written by criner {}, whose items of table(s) {} can't be read by criner {}",
+                self.written_by.as_deref().unwrap_or("of unknown version"),
+                self.changed_models.join(", "),
+                CRINER_VERSION
+            )));
+        }
+        Ok(())
+    }
+
+    /// What differs from this version of criner in a readable database, like models of items which are migrated when
+    /// read, or `None` if nothing does
+    pub fn differences(&self) -> Option<String> {
+        let mut differences = Vec::new();
+        if !self.changed_models.is_empty() {
+            differences.push(format!(
+                "items of table(s) {} are migrated when read",
+                self.changed_models.join(", ")
+            ));
+        }
+        if !self.changed_generators.is_empty() {
+            differences.push(format!(
+                "report(s) {} were generated by other versions of their generator",
+                self.changed_generators.join(", ")
+            ));
+        }
+        (!differences.is_empty()).then(|| {
+            format!(
+                "The database was written by criner {}: {}",
+                self.written_by.as_deref().unwrap_or("of unknown version"),
+                differences.join(", and ")
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod info_test;
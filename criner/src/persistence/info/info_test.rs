@@ -0,0 +1,160 @@
+use super::{model_hash, model_hashes, read, write, Compatibility, CRINER_VERSION};
+use crate::{
+    model::DatabaseInfo,
+    persistence::{
+        self,
+        temp_db::{TempDb, TempDir},
+        SCHEMA_VERSION, SQLITE_FILE_NAME,
+    },
+    registry::Registry,
+    Error,
+};
+use serde_derive::Deserialize;
+
+fn written_by(version: &str, changed_table: Option<&str>) -> DatabaseInfo {
+    let mut model_hashes = model_hashes();
+    if let Some(table) = changed_table {
+        model_hashes.insert(table.into(), "0000000000000000".into());
+    }
+    DatabaseInfo {
+        created_by: Some(version.into()),
+        created_at: None,
+        written_by: version.into(),
+        schema_version: SCHEMA_VERSION,
+        model_hashes,
+        generator_versions: crate::engine::stage::report::generator_versions(),
+        registries: Default::default(),
+    }
+}
+
+#[test]
+fn databases_describe_themselves_when_created_and_mined_into() {
+    let db = TempDb::new("info-created");
+    let info = db.info().unwrap().expect("written on creation");
+    assert_eq!(info.created_by.as_deref(), Some(CRINER_VERSION));
+    assert!(info.created_at.is_some());
+    assert_eq!(info.written_by, CRINER_VERSION);
+    assert_eq!(info.model_hashes, model_hashes());
+    assert!(info.generator_versions.contains_key("waste"));
+    assert!(info.registries.is_empty());
+
+    let acme = Registry {
+        name: "acme".into(),
+        index_url: "https://example.com/index".into(),
+    };
+    db.record_mining(&Registry::default()).unwrap();
+    let mined = db.record_mining(&acme).unwrap();
+    assert_eq!(mined.created_at, info.created_at, "creation is kept");
+    assert_eq!(
+        mined.registries.keys().collect::<Vec<_>>(),
+        vec!["acme", "crates-io"],
+        "all registries mined into the database are known"
+    );
+    assert_eq!(db.info().unwrap(), Some(mined));
+    assert_eq!(
+        db.compatibility().unwrap(),
+        Compatibility {
+            written_by: Some(CRINER_VERSION.into()),
+            ..Default::default()
+        }
+    );
+}
+
+#[test]
+fn databases_of_earlier_versions_are_not_described_until_mined_into() {
+    let dir = TempDir::new("info-earlier");
+    rusqlite::Connection::open(dir.join(SQLITE_FILE_NAME))
+        .unwrap()
+        .execute_batch("CREATE TABLE meta (key TEXT PRIMARY KEY NOT NULL, data BLOB NOT NULL)")
+        .unwrap();
+    let db = persistence::Db::open(&dir).unwrap();
+    assert_eq!(db.info().unwrap(), None);
+    assert!(db.compatibility().unwrap().verify().is_ok());
+
+    let info = db.record_mining(&Registry::default()).unwrap();
+    assert_eq!(info.created_by, None, "we don't know who created it");
+    assert_eq!(info.created_at, None);
+}
+
+#[test]
+fn the_record_is_not_mistaken_for_the_context_of_a_day() {
+    let db = TempDb::new("info-context");
+    let context = db.open_context().unwrap();
+    assert!(context.most_recent().unwrap().is_none());
+    context.update_today(|c| c.counts.crates += 1).unwrap();
+    let (key, most_recent) = context.most_recent().unwrap().unwrap();
+    assert!(key.starts_with("context/"), "{}", key);
+    assert_eq!(most_recent.counts.crates, 1);
+    assert_eq!(crate::stats::context_totals(&db).unwrap().0.counts.crates, 1);
+}
+
+#[test]
+fn databases_written_by_newer_versions_with_other_models_are_refused() {
+    let db = TempDb::new("info-newer");
+    let connection = db.open_connection_no_async_with_busy_wait().unwrap();
+    write(&connection, &written_by("999.0.0", Some("crate_version"))).unwrap();
+    let compatibility = Compatibility::of_database(&connection).unwrap();
+    assert!(compatibility.written_by_newer);
+    assert_eq!(compatibility.changed_models, vec!["crate_version"]);
+    assert!(matches!(compatibility.verify(), Err(Error::IncompatibleDatabase(_))));
+
+    write(&connection, &written_by("999.0.0", None)).unwrap();
+    assert!(
+        Compatibility::of_database(&connection).unwrap().verify().is_ok(),
+        "items of the same model can be read no matter who wrote them"
+    );
+
+    let mut info = written_by("999.0.0", None);
+    info.schema_version = SCHEMA_VERSION + 1;
+    write(&connection, &info).unwrap();
+    assert!(matches!(
+        Compatibility::of_database(&connection).unwrap().verify(),
+        Err(Error::SchemaVersion { found, .. }) if found == SCHEMA_VERSION + 1
+    ));
+}
+
+#[test]
+fn databases_written_by_older_versions_with_other_models_are_migrated_when_read() {
+    let db = TempDb::new("info-older");
+    let connection = db.open_connection_no_async_with_busy_wait().unwrap();
+    let mut info = written_by("0.0.1", Some("task"));
+    info.generator_versions.insert("waste".into(), "0.0.1".into());
+    write(&connection, &info).unwrap();
+    assert_eq!(read(&connection).unwrap(), Some(info));
+    let compatibility = Compatibility::of_database(&connection).unwrap();
+    assert_eq!(compatibility.changed_generators, vec!["waste"]);
+    assert_eq!(
+        compatibility.differences().as_deref(),
+        Some(
+            "The database was written by criner 0.0.1: items of table(s) task are migrated when read, and report(s) waste \
+             were generated by other versions of their generator"
+        )
+    );
+    assert!(compatibility.verify().is_ok());
+}
+
+#[test]
+fn models_hash_differently_once_their_fields_change() {
+    #[derive(Deserialize)]
+    #[allow(dead_code)]
+    struct Before {
+        a: u8,
+    }
+    #[derive(Deserialize)]
+    #[serde(rename = "Before")]
+    #[allow(dead_code)]
+    struct After {
+        a: u8,
+        b: u8,
+    }
+    #[derive(Deserialize)]
+    #[allow(dead_code)]
+    enum Kind {
+        A,
+        B,
+    }
+    assert_eq!(model_hash::<Before>(), model_hash::<Before>());
+    assert_ne!(model_hash::<Before>(), model_hash::<After>());
+    assert_ne!(model_hash::<Kind>(), model_hash::<Before>());
+    assert_eq!(model_hash::<Before>().len(), 16);
+}
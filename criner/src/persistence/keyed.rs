@@ -47,7 +47,11 @@ impl Keyed for TaskResult {
                 buf.push(KEY_SEP_CHAR);
                 buf.push_str(kind)
             }
-            TaskResult::None | TaskResult::ExplodedCrate { .. } => {}
+            TaskResult::Manifest { .. } => {
+                buf.push(KEY_SEP_CHAR);
+                buf.push_str("manifest")
+            }
+            TaskResult::None | TaskResult::ExplodedCrate { .. } | TaskResult::ExplodedCrateDelta { .. } => {}
         }
     }
 }
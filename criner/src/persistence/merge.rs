@@ -1,5 +1,6 @@
-use crate::model::{self, Context, CrateVersion, Task};
+use crate::model::{self, db_dump, Context, Crate, CrateVersion, Task, YankTransition};
 use crate::utils::parse_semver;
+use std::time::SystemTime;
 
 pub trait Merge<T> {
     fn merge(self, other: &T) -> Self;
@@ -9,31 +10,7 @@ impl Merge<model::Task> for model::Task {
     fn merge(mut self, other: &Task) -> Self {
         let my_state = self.state;
         self = other.clone();
-        self.state = my_state.merge(&other.state);
-        self
-    }
-}
-
-impl Merge<model::TaskState> for model::TaskState {
-    fn merge(mut self, other: &model::TaskState) -> Self {
-        fn merge_vec(mut existing: Vec<String>, new: &[String]) -> Vec<String> {
-            existing.extend(new.iter().cloned());
-            existing
-        }
-        use model::TaskState::*;
-        self = match (&self, other) {
-            (AttemptsWithFailure(existing), AttemptsWithFailure(new)) => {
-                AttemptsWithFailure(merge_vec(existing.clone(), new))
-            }
-            (AttemptsWithFailure(existing), InProgress(None)) => InProgress(Some(existing.clone())),
-            (AttemptsWithFailure(_), InProgress(Some(_))) => {
-                panic!("One must not create inProgress preloaded with failed attempts, I think :D")
-            }
-            (InProgress(Some(existing)), AttemptsWithFailure(other)) => {
-                AttemptsWithFailure(merge_vec(existing.clone(), other))
-            }
-            (_, other) => other.clone(),
-        };
+        self.state = my_state.advance_to(other.state.clone());
         self
     }
 }
@@ -44,16 +21,13 @@ impl Merge<model::Context> for model::Context {
     }
 }
 
-fn sort_semver(versions: &mut Vec<String>) {
-    versions.sort_by_key(|v| parse_semver(v));
+fn sort_semver(versions: &mut [String]) {
+    versions.sort_by_key(|v| parse_semver(v).ok());
 }
 
 impl Merge<model::CrateVersion> for model::Crate {
     fn merge(mut self, other: &CrateVersion) -> Self {
-        if !self.versions.contains(&other.version) {
-            self.versions.push(other.version.to_owned());
-        }
-        sort_semver(&mut self.versions);
+        self.merge_mut(other);
         self
     }
 }
@@ -64,6 +38,52 @@ impl model::Crate {
             self.versions.push(other.version.to_owned());
         }
         sort_semver(&mut self.versions);
+        let yanked = other.kind == crates_index_diff::ChangeKind::Yanked;
+        if self.is_yanked(&other.version) != yanked {
+            self.yank_history
+                .entry(other.version.to_owned())
+                .or_default()
+                .push(YankTransition {
+                    yanked,
+                    observed_at: SystemTime::now(),
+                });
+        }
+        self
+    }
+}
+
+impl Merge<model::Crate> for model::Crate {
+    fn merge(mut self, other: &Crate) -> Self {
+        for version in other.versions.iter() {
+            if !self.versions.contains(version) {
+                self.versions.push(version.to_owned());
+            }
+        }
+        sort_semver(&mut self.versions);
+        for (version, transitions) in other.yank_history.iter() {
+            let history = self.yank_history.entry(version.to_owned()).or_default();
+            history.extend(
+                transitions
+                    .iter()
+                    .filter(|t| !history.contains(t))
+                    .copied()
+                    .collect::<Vec<_>>(),
+            );
+            history.sort_by_key(|t| t.observed_at);
+        }
         self
     }
 }
+
+impl Merge<db_dump::Crate> for db_dump::Crate {
+    fn merge(self, other: &db_dump::Crate) -> Self {
+        if other.stored_at > self.stored_at {
+            other.clone()
+        } else {
+            self
+        }
+    }
+}
+
+#[cfg(test)]
+mod merge_test;
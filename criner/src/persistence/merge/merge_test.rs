@@ -0,0 +1,49 @@
+use super::Merge;
+use crate::model::{Crate, CrateVersion};
+use crates_index_diff::ChangeKind;
+
+fn version(version: &str, kind: ChangeKind) -> CrateVersion {
+    CrateVersion {
+        name: "a".into(),
+        version: version.into(),
+        kind,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn yanks_and_unyanks_are_recorded_once_per_change() {
+    let krate = Crate::from(version("1.0.0", ChangeKind::Added))
+        .merge(&version("1.1.0", ChangeKind::Yanked))
+        .merge(&version("1.0.0", ChangeKind::Added));
+    assert!(
+        !krate.yank_history.contains_key("1.0.0"),
+        "versions which were never yanked have no history"
+    );
+    assert!(krate.is_yanked("1.1.0"), "versions first seen yanked start with a yank");
+
+    let krate = krate
+        .merge(&version("1.1.0", ChangeKind::Yanked))
+        .merge(&version("1.1.0", ChangeKind::Added))
+        .merge(&version("1.0.0", ChangeKind::Yanked));
+    assert_eq!(
+        krate.yank_history["1.1.0"].iter().map(|t| t.yanked).collect::<Vec<_>>(),
+        vec![true, false],
+        "seeing the same status again isn't a change"
+    );
+    assert!(!krate.is_yanked("1.1.0"));
+    assert!(krate.is_yanked("1.0.0"));
+    assert_eq!(krate.versions, vec!["1.0.0", "1.1.0"]);
+}
+
+#[test]
+fn crates_merge_their_yank_histories() {
+    let yanked = Crate::from(version("1.0.0", ChangeKind::Yanked));
+    let unyanked = yanked.clone().merge(&version("1.0.0", ChangeKind::Added));
+    let merged = yanked.merge(&unyanked);
+    assert_eq!(
+        merged.yank_history, unyanked.yank_history,
+        "transitions are not duplicated"
+    );
+    assert!(!merged.is_yanked("1.0.0"));
+}
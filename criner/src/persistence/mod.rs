@@ -1,34 +1,123 @@
-use crate::Result;
+use crate::{model::DatabaseInfo, registry::Registry, Result};
 use std::path::{Path, PathBuf};
 
+mod backend;
+#[cfg(feature = "redb")]
+pub use backend::Redb;
+pub use backend::{Backend, BackendKind, SharedBackend, SqlitePool};
+
+pub mod delta;
+mod info;
+pub use info::{Compatibility, CONTEXT_CONDITION, DATABASE_INFO_KEY};
 mod keyed;
 mod merge;
+mod partial;
 pub use keyed::*;
+pub use merge::Merge;
 
 mod serde;
 mod table;
 pub use table::*;
 
+#[cfg(test)]
+pub(crate) mod fixtures;
+#[cfg(test)]
+pub(crate) mod temp_db;
+
+/// Determines how hard we try to not lose data in case of crashes or power loss
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Durability {
+    /// fsync the database on every commit, and fsync downloaded assets before they are finalized
+    Full,
+    /// fsync the database only in critical moments, and leave flushing assets to the operating system
+    #[default]
+    Relaxed,
+    /// never fsync - a crash of the operating system may corrupt the database
+    None,
+}
+
+impl Durability {
+    fn sqlite_synchronous(&self) -> &'static str {
+        match self {
+            Durability::Full => "FULL",
+            Durability::Relaxed => "NORMAL",
+            Durability::None => "OFF",
+        }
+    }
+
+    /// Returns true if written files should be synced to disk before considering them written
+    pub fn sync_files(&self) -> bool {
+        matches!(self, Durability::Full)
+    }
+}
+
+impl std::str::FromStr for Durability {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(match s {
+            "full" => Durability::Full,
+            "relaxed" => Durability::Relaxed,
+            "none" => Durability::None,
+            _ => return Err(format!("Invalid durability '{}', expected full, relaxed or none", s)),
+        })
+    }
+}
+
+/// The version of the layout of the database, to be increased whenever older versions of criner can't use it anymore
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// The name of the sqlite database file within the database directory
+pub const SQLITE_FILE_NAME: &str = "db.msgpack.sqlite";
+
+/// The name of the redb database file within the database directory, used by `BackendKind::Redb`
+pub const REDB_FILE_NAME: &str = "db.redb";
+
 #[derive(Clone)]
 pub struct Db {
     sqlite_path: PathBuf,
+    durability: Durability,
+    /// The pool of connections shared by all tables which may use one, instead of their own connection
+    pool: Option<SharedBackend>,
 }
 
 impl Db {
     pub fn open(path: impl AsRef<Path>) -> Result<Db> {
+        Self::open_with_durability(path, Durability::default())
+    }
+
+    pub fn open_with_durability(path: impl AsRef<Path>, durability: Durability) -> Result<Db> {
+        Self::open_with_backend(path, durability, BackendKind::default())
+    }
+
+    pub fn open_with_backend(path: impl AsRef<Path>, durability: Durability, backend: BackendKind) -> Result<Db> {
         std::fs::create_dir_all(&path)?;
-        let sqlite_path = path.as_ref().join("db.msgpack.sqlite");
+        let sqlite_path = path.as_ref().join(SQLITE_FILE_NAME);
+        let is_new = !sqlite_path.is_file();
+        let mut db = Db {
+            sqlite_path,
+            durability,
+            pool: None,
+        };
         {
-            let mut connection = rusqlite::Connection::open(&sqlite_path)?;
+            let mut connection = db.connection()?;
             connection.execute_batch("
                 PRAGMA journal_mode = WAL;          -- better write-concurrency
-                PRAGMA synchronous = NORMAL;        -- fsync only in critical moments
                 PRAGMA wal_autocheckpoint = 1000;   -- write WAL changes back every 1000 pages, for an in average 1MB WAL file. May affect readers if number is increased
                 PRAGMA wal_checkpoint(TRUNCATE);    -- free some space by truncating possibly massive WAL files from the last run.
             ")?;
 
             let transaction = connection.transaction()?;
-            for name in &["meta", "crate_version", "crate", "task", "result", "crates.io-crate"] {
+            for name in &[
+                "meta",
+                "crate_version",
+                "crate",
+                "task",
+                "result",
+                "crates.io-crate",
+                "index_check",
+                "run_summary",
+            ] {
                 transaction.execute_batch(&format!(
                     "CREATE TABLE IF NOT EXISTS '{}' (
                           key             TEXT PRIMARY KEY NOT NULL,
@@ -42,43 +131,162 @@ impl Db {
                         key             TEXT PRIMARY KEY NOT NULL
                 )",
             )?;
+            transaction.execute_batch(
+                "CREATE TABLE IF NOT EXISTS processing_journal (
+                        key             TEXT PRIMARY KEY NOT NULL
+                )",
+            )?;
+            transaction.execute_batch(
+                "CREATE TABLE IF NOT EXISTS error_telemetry (
+                        occurred_at     INTEGER NOT NULL,
+                        category        TEXT NOT NULL,
+                        key             TEXT NOT NULL,
+                        message         TEXT NOT NULL
+                )",
+            )?;
+            transaction.execute_batch("CREATE INDEX IF NOT EXISTS error_telemetry_key ON error_telemetry (key)")?;
+            transaction.execute_batch(
+                "CREATE TABLE IF NOT EXISTS task_event (
+                        id              INTEGER PRIMARY KEY AUTOINCREMENT,
+                        occurred_at_ms  INTEGER NOT NULL,
+                        key             TEXT NOT NULL,
+                        event           TEXT NOT NULL,
+                        duration_ms     INTEGER,
+                        message         TEXT
+                )",
+            )?;
+            transaction.execute_batch(
+                "CREATE TABLE IF NOT EXISTS api_cache (
+                        url             TEXT PRIMARY KEY NOT NULL,
+                        etag            TEXT NOT NULL,
+                        body            BLOB NOT NULL
+                )",
+            )?;
             transaction.commit()?;
+            if is_new {
+                info::record_creation(&connection)?;
+            }
         }
+        db.pool = match backend {
+            BackendKind::Sqlite => None,
+            BackendKind::SqlitePool(size) => Some(std::sync::Arc::new(SqlitePool::new(
+                (0..size)
+                    .map(|_| db.open_connection_no_async_with_busy_wait())
+                    .collect::<Result<_>>()?,
+            ))),
+            #[cfg(feature = "redb")]
+            BackendKind::Redb => Some(std::sync::Arc::new(Redb::open(
+                path.as_ref().join(REDB_FILE_NAME),
+                durability,
+            )?)),
+        };
 
-        Ok(Db { sqlite_path })
+        Ok(db)
+    }
+
+    /// Fail if the database was written by a newer version of criner, and record the schema version of databases which
+    /// don't have one yet. Returns the schema version of the database.
+    pub fn verify_schema_version(&self) -> Result<u32> {
+        let connection = self.connection()?;
+        let found: u32 = connection.query_row("PRAGMA user_version", [], |r| r.get(0))?;
+        if found > SCHEMA_VERSION {
+            return Err(crate::Error::SchemaVersion {
+                found,
+                supported: SCHEMA_VERSION,
+            });
+        }
+        if found < SCHEMA_VERSION {
+            connection.execute_batch(&format!("PRAGMA user_version = {}", SCHEMA_VERSION))?;
+        }
+        Ok(SCHEMA_VERSION)
+    }
+
+    /// The record describing the database, if it was created or mined into by a version of criner which writes one
+    pub fn info(&self) -> Result<Option<DatabaseInfo>> {
+        info::read(&self.connection()?)
+    }
+
+    /// How this version of criner relates to the one which wrote the database
+    pub fn compatibility(&self) -> Result<Compatibility> {
+        Compatibility::of_database(&self.connection()?)
+    }
+
+    /// Record that this version of criner mines crates of `registry` into the database, along with its models and report
+    /// generators. Returns the updated record.
+    pub fn record_mining(&self, registry: &Registry) -> Result<DatabaseInfo> {
+        info::record_mining(&self.connection()?, registry)
+    }
+
+    /// The amount of bytes taken by the database files, including the write-ahead log and the redb database, if any
+    pub fn size_on_disk(&self) -> u64 {
+        let wal_path = self.sqlite_path.with_extension("sqlite-wal");
+        let redb_path = self.sqlite_path.with_file_name(REDB_FILE_NAME);
+        [&self.sqlite_path, &wal_path, &redb_path]
+            .iter()
+            .filter_map(|path| std::fs::metadata(path).ok())
+            .map(|m| m.len())
+            .sum()
+    }
+
+    pub fn durability(&self) -> Durability {
+        self.durability
+    }
+
+    /// The backend of a table using `connection`, which is the pool shared by all tables if there is one
+    pub(crate) fn table_backend(&self, connection: &ThreadSafeConnection) -> SharedBackend {
+        self.pool.clone().unwrap_or_else(|| connection.clone())
+    }
+
+    /// The synchronous pragma is per connection, so each one has to be configured
+    fn connection(&self) -> Result<rusqlite::Connection> {
+        let connection = rusqlite::Connection::open(&self.sqlite_path)?;
+        connection.execute_batch(&format!(
+            "PRAGMA synchronous = {};",
+            self.durability.sqlite_synchronous()
+        ))?;
+        Ok(connection)
     }
 
     pub fn open_connection(&self) -> Result<ThreadSafeConnection> {
-        Ok(std::sync::Arc::new(parking_lot::Mutex::new(
-            rusqlite::Connection::open(&self.sqlite_path)?,
-        )))
+        Ok(std::sync::Arc::new(parking_lot::Mutex::new(self.connection()?)))
     }
 
     pub fn open_connection_with_busy_wait(&self) -> Result<ThreadSafeConnection> {
-        let connection = rusqlite::Connection::open(&self.sqlite_path)?;
+        let connection = self.connection()?;
         connection.busy_handler(Some(sleeper))?;
         Ok(std::sync::Arc::new(parking_lot::Mutex::new(connection)))
     }
 
     pub fn open_connection_no_async_with_busy_wait(&self) -> Result<rusqlite::Connection> {
-        let connection = rusqlite::Connection::open(&self.sqlite_path)?;
+        let connection = self.connection()?;
         connection.busy_handler(Some(sleeper))?;
         Ok(connection)
     }
 
     pub fn open_crate_versions(&self) -> Result<CrateVersionTable> {
+        let inner = self.open_connection()?;
         Ok(CrateVersionTable {
-            inner: self.open_connection()?,
+            backend: self.table_backend(&inner),
+            inner,
         })
     }
     pub fn open_crates(&self) -> Result<CrateTable> {
+        let inner = self.open_connection()?;
         Ok(CrateTable {
+            backend: self.table_backend(&inner),
+            inner,
+        })
+    }
+    pub fn open_crates_io_crates(&self) -> Result<CratesIoCrateTable> {
+        Ok(CratesIoCrateTable {
             inner: self.open_connection()?,
         })
     }
     pub fn open_tasks(&self) -> Result<TaskTable> {
+        let inner = self.open_connection()?;
         Ok(TaskTable {
-            inner: self.open_connection()?,
+            backend: self.table_backend(&inner),
+            inner,
         })
     }
     pub fn open_results(&self) -> Result<TaskResultTable> {
@@ -87,8 +295,46 @@ impl Db {
         })
     }
     pub fn open_context(&self) -> Result<MetaTable> {
+        let inner = self.open_connection()?;
         Ok(MetaTable {
-            inner: self.open_connection()?,
+            backend: self.table_backend(&inner),
+            inner,
+        })
+    }
+    pub fn open_index_checks(&self) -> Result<IndexCheckTable> {
+        let inner = self.open_connection_with_busy_wait()?;
+        Ok(IndexCheckTable {
+            backend: self.table_backend(&inner),
+            inner,
+        })
+    }
+    pub fn open_run_summaries(&self) -> Result<RunSummaryTable> {
+        let inner = self.open_connection()?;
+        Ok(RunSummaryTable {
+            backend: self.table_backend(&inner),
+            inner,
+        })
+    }
+
+    pub fn open_api_cache(&self) -> Result<ApiCacheTable> {
+        Ok(ApiCacheTable {
+            inner: self.open_connection_with_busy_wait()?,
+        })
+    }
+
+    pub fn open_journal(&self) -> Result<JournalTable> {
+        Ok(JournalTable {
+            inner: self.open_connection_with_busy_wait()?,
+        })
+    }
+    pub fn open_error_telemetry(&self) -> Result<ErrorTelemetryTable> {
+        Ok(ErrorTelemetryTable {
+            inner: self.open_connection_with_busy_wait()?,
+        })
+    }
+    pub fn open_task_events(&self) -> Result<TaskEventTable> {
+        Ok(TaskEventTable {
+            inner: self.open_connection_with_busy_wait()?,
         })
     }
     pub fn open_reports(&self) -> Result<ReportsTree> {
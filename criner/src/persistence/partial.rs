@@ -0,0 +1,252 @@
+//! Decoding stored results of extracted crates without materializing the content of selected entries nobody asked for.
+//!
+//! Results are decoded by position just like `TaskResult` itself, which is why `Variant` must list its variants in order.
+use crate::{
+    model::{DeltaOp, TarHeader, TaskResult},
+    Error, Result,
+};
+use serde::de::{self, DeserializeSeed, Deserializer, EnumAccess, IgnoredAny, SeqAccess, VariantAccess, Visitor};
+use serde_derive::Deserialize;
+use std::fmt;
+
+/// Tells whether the content of a selected entry should be decoded
+pub type Wanted<'a> = &'a dyn Fn(&TarHeader) -> bool;
+
+/// The variants of `TaskResult`, in the same order
+#[derive(Deserialize)]
+#[serde(variant_identifier)]
+enum Variant {
+    None,
+    ExplodedCrate,
+    Download,
+    ExplodedCrateDelta,
+    Manifest,
+}
+
+/// The variants of `DeltaOp`, in the same order
+#[derive(Deserialize)]
+#[serde(variant_identifier)]
+enum DeltaOpVariant {
+    Copy,
+    Insert,
+}
+
+/// The fields of `DeltaOp::Copy`
+#[derive(Deserialize)]
+struct CopyFields {
+    start: u32,
+    len: u32,
+}
+
+/// Decode the `TaskResult` in `data`. The selected entries of an `ExplodedCrate` or `ExplodedCrateDelta` for which `wanted`
+/// returns false keep their header, but their content is skipped and left empty.
+pub fn decode(data: &[u8], wanted: Wanted<'_>) -> Result<TaskResult> {
+    let mut deserializer = rmp_serde::Deserializer::from_read_ref(data);
+    match ResultSeed(wanted).deserialize(&mut deserializer) {
+        Ok(Some(result)) => Ok(result),
+        // Other results are small enough to decode as usual
+        Ok(None) => Ok(TaskResult::from(data)),
+        Err(err) => Err(Error::Corrupt(format!("Could not decode task result: {}", err))),
+    }
+}
+
+struct ResultSeed<'a>(Wanted<'a>);
+
+impl<'de, 'a> DeserializeSeed<'de> for ResultSeed<'a> {
+    type Value = Option<TaskResult>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> std::result::Result<Self::Value, D::Error> {
+        deserializer.deserialize_enum("TaskResult", &[], self)
+    }
+}
+
+impl<'de, 'a> Visitor<'de> for ResultSeed<'a> {
+    type Value = Option<TaskResult>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a task result")
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> std::result::Result<Self::Value, A::Error> {
+        let (variant, access) = data.variant()?;
+        Ok(match variant {
+            Variant::ExplodedCrate => Some(access.struct_variant(&[], ExplodedCrateVisitor(self.0))?),
+            Variant::ExplodedCrateDelta => Some(access.struct_variant(&[], ExplodedCrateDeltaVisitor(self.0))?),
+            Variant::None | Variant::Download | Variant::Manifest => {
+                access.newtype_variant::<IgnoredAny>()?;
+                None
+            }
+        })
+    }
+}
+
+fn next<'de, A: SeqAccess<'de>, T: de::Deserialize<'de>>(
+    seq: &mut A,
+    idx: usize,
+    expected: &dyn de::Expected,
+) -> std::result::Result<T, A::Error> {
+    seq.next_element()?
+        .ok_or_else(|| de::Error::invalid_length(idx, expected))
+}
+
+struct ExplodedCrateVisitor<'a>(Wanted<'a>);
+
+impl<'de, 'a> Visitor<'de> for ExplodedCrateVisitor<'a> {
+    type Value = TaskResult;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("an exploded crate")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+        let entries_meta_data = next(&mut seq, 0, &self)?;
+        let selected_entries = seq
+            .next_element_seed(EntriesSeed(self.0))?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        Ok(TaskResult::ExplodedCrate {
+            entries_meta_data,
+            selected_entries,
+        })
+    }
+}
+
+struct ExplodedCrateDeltaVisitor<'a>(Wanted<'a>);
+
+impl<'de, 'a> Visitor<'de> for ExplodedCrateDeltaVisitor<'a> {
+    type Value = TaskResult;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a delta of an exploded crate")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+        let base_version = next(&mut seq, 0, &self)?;
+        let chain_length = next(&mut seq, 1, &self)?;
+        let entries_meta_data = next(&mut seq, 2, &self)?;
+        let selected_entries = seq
+            .next_element_seed(DeltaOpsSeed(self.0))?
+            .ok_or_else(|| de::Error::invalid_length(3, &self))?;
+        Ok(TaskResult::ExplodedCrateDelta {
+            base_version,
+            chain_length,
+            entries_meta_data,
+            selected_entries,
+        })
+    }
+}
+
+/// Decodes `Vec<(TarHeader, Vec<u8>)>`
+struct EntriesSeed<'a>(Wanted<'a>);
+
+impl<'de, 'a> DeserializeSeed<'de> for EntriesSeed<'a> {
+    type Value = Vec<(TarHeader, Vec<u8>)>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> std::result::Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, 'a> Visitor<'de> for EntriesSeed<'a> {
+    type Value = Vec<(TarHeader, Vec<u8>)>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("selected entries")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+        let mut entries = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(entry) = seq.next_element_seed(EntrySeed(self.0))? {
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+}
+
+/// Decodes `(TarHeader, Vec<u8>)`, skipping the content unless it's wanted
+struct EntrySeed<'a>(Wanted<'a>);
+
+impl<'de, 'a> DeserializeSeed<'de> for EntrySeed<'a> {
+    type Value = (TarHeader, Vec<u8>);
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> std::result::Result<Self::Value, D::Error> {
+        deserializer.deserialize_tuple(2, self)
+    }
+}
+
+impl<'de, 'a> Visitor<'de> for EntrySeed<'a> {
+    type Value = (TarHeader, Vec<u8>);
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a selected entry")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+        let header: TarHeader = next(&mut seq, 0, &self)?;
+        let content = if (self.0)(&header) {
+            next(&mut seq, 1, &self)?
+        } else {
+            next::<_, IgnoredAny>(&mut seq, 1, &self)?;
+            Vec::new()
+        };
+        Ok((header, content))
+    }
+}
+
+/// Decodes `Vec<DeltaOp<(TarHeader, Vec<u8>)>>`
+struct DeltaOpsSeed<'a>(Wanted<'a>);
+
+impl<'de, 'a> DeserializeSeed<'de> for DeltaOpsSeed<'a> {
+    type Value = Vec<DeltaOp<(TarHeader, Vec<u8>)>>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> std::result::Result<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, 'a> Visitor<'de> for DeltaOpsSeed<'a> {
+    type Value = Vec<DeltaOp<(TarHeader, Vec<u8>)>>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("delta operations on selected entries")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error> {
+        let mut ops = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(op) = seq.next_element_seed(DeltaOpSeed(self.0))? {
+            ops.push(op);
+        }
+        Ok(ops)
+    }
+}
+
+struct DeltaOpSeed<'a>(Wanted<'a>);
+
+impl<'de, 'a> DeserializeSeed<'de> for DeltaOpSeed<'a> {
+    type Value = DeltaOp<(TarHeader, Vec<u8>)>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> std::result::Result<Self::Value, D::Error> {
+        deserializer.deserialize_enum("DeltaOp", &[], self)
+    }
+}
+
+impl<'de, 'a> Visitor<'de> for DeltaOpSeed<'a> {
+    type Value = DeltaOp<(TarHeader, Vec<u8>)>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a delta operation")
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> std::result::Result<Self::Value, A::Error> {
+        let (variant, access) = data.variant()?;
+        Ok(match variant {
+            DeltaOpVariant::Copy => {
+                let CopyFields { start, len } = access.newtype_variant()?;
+                DeltaOp::Copy { start, len }
+            }
+            DeltaOpVariant::Insert => DeltaOp::Insert(access.newtype_variant_seed(EntriesSeed(self.0))?),
+        })
+    }
+}
+
+#[cfg(test)]
+mod partial_test;
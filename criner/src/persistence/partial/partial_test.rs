@@ -0,0 +1,147 @@
+use super::decode;
+use crate::{
+    model::{CrateVersion, DeltaOp, ManifestPackage, TarHeader, TaskResult},
+    persistence::{
+        delta,
+        fixtures::{exploded_crate, header},
+        temp_db::TempDb,
+        TableAccess,
+    },
+};
+
+fn is_manifest(header: &TarHeader) -> bool {
+    header.path == b"Cargo.toml"
+}
+
+#[test]
+fn only_wanted_contents_are_decoded() {
+    let data = rmp_serde::to_vec(&exploded_crate(&["Cargo.toml", "README.md"])).unwrap();
+    match decode(&data, &is_manifest).unwrap() {
+        TaskResult::ExplodedCrate {
+            entries_meta_data,
+            selected_entries,
+        } => {
+            assert_eq!(entries_meta_data, vec![header("Cargo.toml"), header("README.md")]);
+            assert_eq!(
+                selected_entries,
+                vec![
+                    (header("Cargo.toml"), b"Cargo.toml".to_vec()),
+                    (header("README.md"), Vec::new())
+                ],
+                "headers are kept to not change the position of entries"
+            );
+        }
+        _ => unreachable!("it's an exploded crate"),
+    }
+
+    let delta = TaskResult::ExplodedCrateDelta {
+        base_version: "1.0.0".into(),
+        chain_length: 1,
+        entries_meta_data: vec![DeltaOp::Copy { start: 0, len: 2 }],
+        selected_entries: vec![
+            DeltaOp::Copy { start: 0, len: 1 },
+            DeltaOp::Insert(vec![(header("README.md"), b"large".to_vec())]),
+        ],
+    };
+    match decode(&rmp_serde::to_vec(&delta).unwrap(), &is_manifest).unwrap() {
+        TaskResult::ExplodedCrateDelta {
+            base_version,
+            chain_length,
+            entries_meta_data,
+            selected_entries,
+        } => {
+            assert_eq!((base_version.as_str(), chain_length), ("1.0.0", 1));
+            assert_eq!(entries_meta_data, vec![DeltaOp::Copy { start: 0, len: 2 }]);
+            assert_eq!(
+                selected_entries,
+                vec![
+                    DeltaOp::Copy { start: 0, len: 1 },
+                    DeltaOp::Insert(vec![(header("README.md"), Vec::new())])
+                ]
+            );
+        }
+        _ => unreachable!("it's a delta"),
+    }
+}
+
+#[test]
+fn other_results_are_decoded_in_full() {
+    let manifest = TaskResult::Manifest {
+        package: Box::new(ManifestPackage {
+            name: "a".into(),
+            ..Default::default()
+        }),
+        features: Default::default(),
+        dependencies: Vec::new(),
+    };
+    assert!(matches!(
+        decode(&rmp_serde::to_vec(&manifest).unwrap(), &|_| false).unwrap(),
+        TaskResult::Manifest { package, .. } if package.name == "a"
+    ));
+    assert!(matches!(
+        decode(&rmp_serde::to_vec(&TaskResult::None).unwrap(), &|_| false).unwrap(),
+        TaskResult::None
+    ));
+}
+
+#[test]
+fn deltas_are_resolved_with_wanted_contents_only() {
+    let db = TempDb::new("partial");
+    let mut progress = prodash::Tree::new().add_child("partial");
+    let crates = db.open_crates().unwrap();
+    for version in ["1.0.0", "1.1.0"] {
+        let version = CrateVersion {
+            name: "a".into(),
+            version: version.into(),
+            ..Default::default()
+        };
+        crates.upsert(&mut progress, "a", &version).unwrap();
+    }
+    let results = db.open_results().unwrap();
+    let paths = ["Cargo.toml", "README.md", "src/lib.rs", "src/a.rs"];
+    results
+        .insert(&mut progress, "a:1.0.0:extract_crate:1.0.0", &exploded_crate(&paths))
+        .unwrap();
+    results
+        .insert(
+            &mut progress,
+            "a:1.1.0:extract_crate:1.0.0",
+            &exploded_crate(&[&paths[..], &["src/b.rs"]].concat()),
+        )
+        .unwrap();
+    assert!(
+        matches!(
+            delta::get_raw(&results.connection().lock(), "a:1.1.0:extract_crate:1.0.0").unwrap(),
+            Some(TaskResult::ExplodedCrateDelta { .. })
+        ),
+        "the second version is stored as delta"
+    );
+
+    match results
+        .get_selected("a:1.1.0:extract_crate:1.0.0", |header| header.path.starts_with(b"src/"))
+        .unwrap()
+    {
+        Some(TaskResult::ExplodedCrate {
+            entries_meta_data,
+            selected_entries,
+        }) => {
+            assert_eq!(entries_meta_data.len(), 5);
+            assert_eq!(
+                selected_entries
+                    .iter()
+                    .map(|(header, content)| (header.path.as_slice(), content.len()))
+                    .collect::<Vec<_>>(),
+                vec![(&b"src/lib.rs"[..], 10), (b"src/a.rs", 8), (b"src/b.rs", 8)]
+            );
+        }
+        _ => unreachable!("deltas resolve into exploded crates"),
+    }
+    assert_eq!(
+        results
+            .get_meta_data("a:1.1.0:extract_crate:1.0.0")
+            .unwrap()
+            .map(|m| m.len()),
+        Some(5)
+    );
+    assert_eq!(results.get_meta_data("a:2.0.0:extract_crate:1.0.0").unwrap(), None);
+}
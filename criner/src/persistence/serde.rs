@@ -1,4 +1,4 @@
-use crate::model::{db_dump, Context, Crate, CrateVersion, ReportResult, Task, TaskResult};
+use crate::model::{db_dump, Context, Crate, CrateVersion, IndexCheck, ReportResult, RunSummary, Task, TaskResult};
 
 fn expect<T, E: std::fmt::Display>(r: std::result::Result<T, E>, panic_message: impl FnOnce(E) -> String) -> T {
     match r {
@@ -28,5 +28,7 @@ impl_deserialize!(Task);
 impl_deserialize!(TaskResult);
 impl_deserialize!(CrateVersion);
 impl_deserialize!(Context);
+impl_deserialize!(IndexCheck);
+impl_deserialize!(RunSummary);
 impl_deserialize!(ReportResult);
 impl_deserialize!(db_dump::Crate);
@@ -1,8 +1,9 @@
 use crate::persistence::KEY_SEP_CHAR;
 use crate::{
-    model::{Context, Crate, TaskResult},
+    model::{db_dump, Context, Crate, IndexCheck, RunSummary, TarHeader, TaskResult, TaskState},
     model::{CrateVersion, Task},
-    persistence::{merge::Merge, Keyed},
+    persistence::{delta, merge::Merge, Backend, Keyed, SharedBackend},
+    registry::Registry,
     Result,
 };
 use rusqlite::{params, OptionalExtension};
@@ -13,6 +14,12 @@ use std::time::{Duration, SystemTime};
 /// Also no one can prevent futures from being resumed in after having been send to a different thread.
 pub type ThreadSafeConnection = std::sync::Arc<parking_lot::Mutex<rusqlite::Connection>>;
 
+/// The smallest key greater than all keys starting with `prefix`, to select them with `key >= prefix AND key < upper_bound`
+/// which, unlike matching the prefix with a function, uses the index on keys.
+pub fn key_prefix_upper_bound(prefix: &str) -> String {
+    format!("{}{}", prefix, char::MAX)
+}
+
 pub fn new_value_query_recent_first<'conn>(
     table_name: &str,
     connection: &'conn rusqlite::Connection,
@@ -25,19 +32,42 @@ pub fn new_value_query_recent_first<'conn>(
     ))?)
 }
 
+/// Query keys and values from oldest to newest, matching keys against `glob` if set, which is then the only parameter
+/// to bind when running the query.
 pub fn new_key_value_query_old_to_new_filtered<'conn>(
     table_name: &str,
     glob: Option<&str>,
     connection: &'conn rusqlite::Connection,
     chunk: Option<(usize, usize)>,
+) -> Result<rusqlite::Statement<'conn>> {
+    new_key_value_query_filtered(table_name, "_rowid_", glob, connection, chunk)
+}
+
+/// Like `new_key_value_query_old_to_new_filtered()`, but ordered by key, which doesn't depend on when rows were written
+pub fn new_key_value_query_by_key_filtered<'conn>(
+    table_name: &str,
+    glob: Option<&str>,
+    connection: &'conn rusqlite::Connection,
+    chunk: Option<(usize, usize)>,
+) -> Result<rusqlite::Statement<'conn>> {
+    new_key_value_query_filtered(table_name, "key", glob, connection, chunk)
+}
+
+fn new_key_value_query_filtered<'conn>(
+    table_name: &str,
+    order_by: &str,
+    glob: Option<&str>,
+    connection: &'conn rusqlite::Connection,
+    chunk: Option<(usize, usize)>,
 ) -> Result<rusqlite::Statement<'conn>> {
     Ok(connection.prepare(&format!(
-        "SELECT key,data FROM {} {} ORDER BY _rowid_ ASC {}",
+        "SELECT key,data FROM {} {} ORDER BY {} ASC {}",
         table_name,
         match glob {
-            Some(glob) => format!("where key glob \"{}\"", glob),
-            None => "".into(),
+            Some(_) => "WHERE key GLOB ?1",
+            None => "",
         },
+        order_by,
         match chunk {
             Some((offset, limit)) => format!("LIMIT {}, {}", offset, limit),
             None => "".into(),
@@ -103,40 +133,26 @@ pub trait TableAccess {
         Self::StorageItem::from(new_item.clone())
     }
 
+    #[allow(dead_code)]
     fn into_connection(self) -> ThreadSafeConnection;
 
+    /// The store of the items of this table, which is its own connection unless it may share one with other tables
+    fn backend(&self) -> &dyn Backend {
+        &**self.connection()
+    }
+
     fn count(&self) -> u64 {
         self.count_filtered(None)
     }
 
     fn count_filtered(&self, glob: Option<&str>) -> u64 {
-        self.connection()
-            .lock()
-            .query_row(
-                &format!(
-                    "SELECT COUNT(*) FROM {} {}",
-                    Self::table_name(),
-                    match glob {
-                        Some(glob) => format!("where key glob \"{}\"", glob),
-                        None => "".into(),
-                    }
-                ),
-                [],
-                |r| r.get::<_, i64>(0),
-            )
-            .unwrap_or(0) as u64
+        self.backend().count(Self::table_name(), glob).unwrap_or(0)
     }
 
     fn get(&self, key: impl AsRef<str>) -> Result<Option<Self::StorageItem>> {
         Ok(self
-            .connection()
-            .lock()
-            .query_row(
-                &format!("SELECT data FROM {} WHERE key = '{}'", Self::table_name(), key.as_ref()),
-                [],
-                |r| r.get::<_, Vec<u8>>(0),
-            )
-            .optional()?
+            .backend()
+            .get(Self::table_name(), key.as_ref())?
             .map(|d| Self::StorageItem::from(d.as_slice())))
     }
 
@@ -149,23 +165,14 @@ pub trait TableAccess {
         f: impl Fn(Self::StorageItem) -> Self::StorageItem,
     ) -> Result<Self::StorageItem> {
         retry_on_db_busy(progress, || {
-            let mut guard = self.connection().lock();
-            let transaction = guard.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
-            let new_value = transaction
-                .query_row(
-                    &format!("SELECT data FROM {} WHERE key = '{}'", Self::table_name(), key.as_ref()),
-                    [],
-                    |r| r.get::<_, Vec<u8>>(0),
-                )
-                .optional()?
-                .map_or_else(|| f(Self::StorageItem::default()), |d| f(d.as_slice().into()));
-            transaction.execute(
-                &format!("REPLACE INTO {} (key, data) VALUES (?1, ?2)", Self::table_name()),
-                params![key.as_ref(), rmp_serde::to_vec(&new_value)?],
-            )?;
-            transaction.commit()?;
-
-            Ok(new_value)
+            let new_value = self
+                .backend()
+                .update(Self::table_name(), key.as_ref(), &mut |existing| {
+                    let new_value =
+                        existing.map_or_else(|| f(Self::StorageItem::default()), |d| f(d.as_slice().into()));
+                    Ok(rmp_serde::to_vec(&new_value)?)
+                })?;
+            Ok(Self::StorageItem::from(new_value.as_slice()))
         })
     }
 
@@ -177,35 +184,25 @@ pub trait TableAccess {
         item: &Self::InsertItem,
     ) -> Result<Self::StorageItem> {
         retry_on_db_busy(Some(progress), || {
-            let mut guard = self.connection().lock();
-            let transaction = guard.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
-
-            let new_value = {
-                let maybe_vec = transaction
-                    .query_row(
-                        &format!("SELECT data FROM {} WHERE key = '{}'", Self::table_name(), key.as_ref()),
-                        [],
-                        |r| r.get::<_, Vec<u8>>(0),
-                    )
-                    .optional()?;
-                Self::merge(item, maybe_vec.map(|v| v.as_slice().into()))
-            };
-            transaction.execute(
-                &format!("REPLACE INTO {} (key, data) VALUES (?1, ?2)", Self::table_name()),
-                params![key.as_ref(), rmp_serde::to_vec(&new_value)?],
-            )?;
-            transaction.commit()?;
-            Ok(new_value)
+            let new_value = self
+                .backend()
+                .update(Self::table_name(), key.as_ref(), &mut |existing| {
+                    Ok(rmp_serde::to_vec(&Self::merge(
+                        item,
+                        existing.map(|v| v.as_slice().into()),
+                    ))?)
+                })?;
+            Ok(Self::StorageItem::from(new_value.as_slice()))
         })
     }
 
     fn insert(&self, progress: &mut prodash::tree::Item, key: impl AsRef<str>, v: &Self::InsertItem) -> Result<()> {
         retry_on_db_busy(Some(progress), || {
-            self.connection().lock().execute(
-                &format!("REPLACE INTO {} (key, data) VALUES (?1, ?2)", Self::table_name()),
-                params![key.as_ref(), rmp_serde::to_vec(&Self::merge(v, None))?],
-            )?;
-            Ok(())
+            self.backend().put(
+                Self::table_name(),
+                key.as_ref(),
+                &rmp_serde::to_vec(&Self::merge(v, None))?,
+            )
         })
     }
 }
@@ -224,9 +221,7 @@ fn retry_on_db_busy<T>(mut progress: Option<&mut prodash::tree::Item>, mut f: im
         match f() {
             Ok(v) => return Ok(v),
             Err(
-                err
-                @
-                Error::Rusqlite(SqliteError::SqliteFailure(
+                err @ Error::Rusqlite(SqliteError::SqliteFailure(
                     SqliteFFIError {
                         code: SqliteFFIErrorCode::DatabaseBusy,
                         ..
@@ -258,6 +253,7 @@ fn retry_on_db_busy<T>(mut progress: Option<&mut prodash::tree::Item>, mut f: im
 
 pub struct TaskTable {
     pub(crate) inner: ThreadSafeConnection,
+    pub(crate) backend: SharedBackend,
 }
 
 impl TableAccess for TaskTable {
@@ -280,6 +276,20 @@ impl TableAccess for TaskTable {
     fn into_connection(self) -> ThreadSafeConnection {
         self.inner
     }
+
+    fn backend(&self) -> &dyn Backend {
+        &*self.backend
+    }
+}
+
+impl TaskTable {
+    /// Remove all tasks whose key starts with `prefix`, so they are scheduled once more. Returns the amount of removed tasks.
+    pub fn remove_with_prefix(&self, prefix: &str) -> Result<usize> {
+        Ok(self.inner.lock().execute(
+            &format!("DELETE FROM {} WHERE key >= ?1 AND key < ?2", Self::table_name()),
+            params![prefix, key_prefix_upper_bound(prefix)],
+        )?)
+    }
 }
 
 pub struct ReportsTree {
@@ -306,17 +316,322 @@ impl ReportsTree {
         self.inner
             .lock()
             .query_row(
-                &format!("SELECT key FROM {} where key = '{}'", Self::table_name(), key.as_ref()),
-                [],
+                &format!("SELECT key FROM {} WHERE key = ?1", Self::table_name()),
+                params![key.as_ref()],
                 |_r| Ok(()),
             )
             .optional()
             .ok()
             .unwrap_or_default()
-            .map_or(false, |_: ()| true)
+            .is_some_and(|_: ()| true)
+    }
+
+    /// Remove the markers with the given `keys`, so their reports are generated again. Returns the amount of removed markers.
+    pub fn remove(&self, keys: impl IntoIterator<Item = impl AsRef<str>>) -> Result<usize> {
+        let mut connection = self.inner.lock();
+        let transaction = connection.transaction()?;
+        let mut num_removed = 0;
+        {
+            let mut statement = transaction.prepare(&format!("DELETE FROM {} WHERE key = ?1", Self::table_name()))?;
+            for key in keys {
+                num_removed += statement.execute(params![key.as_ref()])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(num_removed)
+    }
+
+    /// Remove all markers whose key starts with `prefix`, returning the amount of removed markers
+    pub fn remove_with_prefix(&self, prefix: &str) -> Result<usize> {
+        Ok(self.inner.lock().execute(
+            &format!("DELETE FROM {} WHERE key >= ?1 AND key < ?2", Self::table_name()),
+            params![prefix, key_prefix_upper_bound(prefix)],
+        )?)
+    }
+
+    /// Remove markers which don't name a crate, its version, a report and its version, like those cut short when
+    /// writing them was interrupted. Returns the amount of removed markers.
+    pub fn remove_malformed(&self) -> Result<usize> {
+        Ok(self.inner.lock().execute(
+            &format!("DELETE FROM {} WHERE key NOT LIKE ?1", Self::table_name()),
+            params![format!("%{sep}%{sep}%{sep}%", sep = KEY_SEP_CHAR)],
+        )?)
+    }
+}
+
+/// A write-ahead record of task keys handed to processors, which is cleared once their outcome was stored.
+///
+/// Keys remaining in the journal after a crash belong to work that was claimed but never recorded.
+pub struct JournalTable {
+    pub(crate) inner: ThreadSafeConnection,
+}
+
+impl JournalTable {
+    pub fn table_name() -> &'static str {
+        "processing_journal"
+    }
+
+    pub fn claim(&self, progress: &mut prodash::tree::Item, key: impl AsRef<str>) -> Result<()> {
+        retry_on_db_busy(Some(progress), || {
+            self.inner.lock().execute(
+                &format!("REPLACE INTO {} (key) VALUES (?1)", Self::table_name()),
+                params![key.as_ref()],
+            )?;
+            Ok(())
+        })
+    }
+
+    pub fn release(&self, progress: &mut prodash::tree::Item, key: impl AsRef<str>) -> Result<()> {
+        retry_on_db_busy(Some(progress), || {
+            self.inner.lock().execute(
+                &format!("DELETE FROM {} WHERE key = ?1", Self::table_name()),
+                params![key.as_ref()],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Mark all tasks which were claimed but never recorded as failed so they will be retried, and clear the journal.
+    /// Returns the amount of tasks that were affected.
+    pub fn reconcile(&self, tasks: &TaskTable) -> Result<usize> {
+        self.fail_claimed(tasks, TaskState::INTERRUPTED_BEFORE_RECORDED)
+    }
+
+    /// Like `reconcile()`, but for shutting down after all processors were stopped, whose claimed tasks fail as `interrupted`.
+    pub fn interrupt(&self, tasks: &TaskTable) -> Result<usize> {
+        self.fail_claimed(tasks, TaskState::INTERRUPTED)
+    }
+
+    fn fail_claimed(&self, tasks: &TaskTable, reason: &str) -> Result<usize> {
+        let keys = {
+            let guard = self.inner.lock();
+            let mut statement = guard.prepare(&format!("SELECT key FROM {}", Self::table_name()))?;
+            let keys = statement
+                .query_map([], |r| r.get::<_, String>(0))?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            keys
+        };
+        let mut reconciled = 0;
+        for key in keys.iter() {
+            let mut task = match tasks.get(key)? {
+                Some(task) => task,
+                None => {
+                    log::warn!("Claimed task '{}' was never stored, skipping it", key);
+                    continue;
+                }
+            };
+            if let TaskState::InProgress(_) = task.state {
+                task.advance_to(TaskState::AttemptsWithFailure(vec![reason.into()]));
+                tasks.connection().lock().execute(
+                    &format!("REPLACE INTO {} (key, data) VALUES (?1, ?2)", TaskTable::table_name()),
+                    params![key, rmp_serde::to_vec(&task)?],
+                )?;
+                reconciled += 1;
+            }
+        }
+        self.inner
+            .lock()
+            .execute(&format!("DELETE FROM {}", Self::table_name()), [])?;
+        Ok(reconciled)
+    }
+}
+
+/// Errors worth looking at in aggregate, like versions we couldn't parse, one row per occurrence
+pub struct ErrorTelemetryTable {
+    pub(crate) inner: ThreadSafeConnection,
+}
+
+impl ErrorTelemetryTable {
+    pub fn table_name() -> &'static str {
+        "error_telemetry"
+    }
+
+    /// Record an error of the given `category` which occurred while handling the item with `key`
+    pub fn record(&self, category: &str, key: impl AsRef<str>, message: impl AsRef<str>) -> Result<()> {
+        let occurred_at = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs() as i64;
+        retry_on_db_busy(None, || {
+            self.inner.lock().execute(
+                &format!(
+                    "INSERT INTO {} (occurred_at, category, key, message) VALUES (?1, ?2, ?3, ?4)",
+                    Self::table_name()
+                ),
+                params![occurred_at, category, key.as_ref(), message.as_ref()],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// The most recent `limit` errors of `category`, most recent first, as seconds since epoch, key and message
+    pub fn recent(&self, category: &str, limit: usize) -> Result<Vec<(i64, String, String)>> {
+        let connection = self.inner.lock();
+        let mut statement = connection.prepare(&format!(
+            "SELECT occurred_at, key, message FROM {} WHERE category = ?1 ORDER BY occurred_at DESC LIMIT ?2",
+            Self::table_name()
+        ))?;
+        let rows = statement
+            .query_map(params![category, limit as i64], |r| {
+                Ok((r.get(0)?, r.get(1)?, r.get(2)?))
+            })?
+            .collect::<std::result::Result<_, _>>()?;
+        Ok(rows)
+    }
+}
+
+/// What happened to a task, as recorded by task processors
+#[derive(Debug, Clone)]
+pub struct TaskEvent {
+    /// Milliseconds since unix epoch
+    pub occurred_at_ms: i64,
+    /// The fully qualified key of the task
+    pub key: String,
+    /// One of 'started', 'retrying', 'failed' or 'finished'
+    pub event: String,
+    /// How long the attempt took, for all events but 'started'
+    pub duration: Option<Duration>,
+    pub message: Option<String>,
+}
+
+/// Progress transitions of tasks, keeping only the most recent `MAX_EVENTS` as a ring buffer
+pub struct TaskEventTable {
+    pub(crate) inner: ThreadSafeConnection,
+}
+
+impl TaskEventTable {
+    /// The amount of events to keep
+    pub const MAX_EVENTS: i64 = 1_000_000;
+    /// Prune old events whenever this many were recorded
+    const PRUNE_EVERY: i64 = 10_000;
+
+    pub fn table_name() -> &'static str {
+        "task_event"
+    }
+
+    pub fn record(
+        &self,
+        key: impl AsRef<str>,
+        event: &str,
+        duration: Option<Duration>,
+        message: Option<&str>,
+    ) -> Result<()> {
+        let occurred_at_ms = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as i64;
+        retry_on_db_busy(None, || {
+            let connection = self.inner.lock();
+            connection.execute(
+                &format!(
+                    "INSERT INTO {} (occurred_at_ms, key, event, duration_ms, message) VALUES (?1, ?2, ?3, ?4, ?5)",
+                    Self::table_name()
+                ),
+                params![
+                    occurred_at_ms,
+                    key.as_ref(),
+                    event,
+                    duration.map(|d| d.as_millis() as i64),
+                    message
+                ],
+            )?;
+            let id = connection.last_insert_rowid();
+            if id % Self::PRUNE_EVERY == 0 {
+                connection.execute(
+                    &format!("DELETE FROM {} WHERE id <= ?1", Self::table_name()),
+                    params![id - Self::MAX_EVENTS],
+                )?;
+            }
+            Ok(())
+        })
+    }
+
+    /// All recorded events of tasks whose key starts with `prefix`, oldest first
+    pub fn with_key_prefix(&self, prefix: &str) -> Result<Vec<TaskEvent>> {
+        let connection = self.inner.lock();
+        let mut statement = connection.prepare(&format!(
+            "SELECT occurred_at_ms, key, event, duration_ms, message FROM {}
+             WHERE substr(key, 1, length(?1)) = ?1 ORDER BY id",
+            Self::table_name()
+        ))?;
+        let events = statement
+            .query_map(params![prefix], |r| {
+                Ok(TaskEvent {
+                    occurred_at_ms: r.get(0)?,
+                    key: r.get(1)?,
+                    event: r.get(2)?,
+                    duration: r.get::<_, Option<i64>>(3)?.map(|ms| Duration::from_millis(ms as u64)),
+                    message: r.get(4)?,
+                })
+            })?
+            .collect::<std::result::Result<_, _>>()?;
+        Ok(events)
+    }
+}
+
+/// Responses of HTTP APIs along with their `ETag`, keyed by URL, to make repeated requests conditional
+#[derive(Clone)]
+pub struct ApiCacheTable {
+    pub(crate) inner: ThreadSafeConnection,
+}
+
+impl ApiCacheTable {
+    pub fn table_name() -> &'static str {
+        "api_cache"
+    }
+
+    /// The `ETag` and body of the response to the most recent request of `url`, if it had an `ETag`
+    pub fn get(&self, url: &str) -> Result<Option<(String, Vec<u8>)>> {
+        Ok(self
+            .inner
+            .lock()
+            .query_row(
+                &format!("SELECT etag, body FROM {} WHERE url = ?1", Self::table_name()),
+                params![url],
+                |r| Ok((r.get(0)?, r.get(1)?)),
+            )
+            .optional()?)
+    }
+
+    pub fn put(&self, url: &str, etag: &str, body: &[u8]) -> Result<()> {
+        retry_on_db_busy(None, || {
+            self.inner.lock().execute(
+                &format!(
+                    "REPLACE INTO {} (url, etag, body) VALUES (?1, ?2, ?3)",
+                    Self::table_name()
+                ),
+                params![url, etag, body],
+            )?;
+            Ok(())
+        })
+    }
+}
+
+/// Crates as seen in the crates.io database dump, keyed by name
+pub struct CratesIoCrateTable {
+    pub(crate) inner: ThreadSafeConnection,
+}
+
+impl CratesIoCrateTable {
+    pub fn table_name() -> &'static str {
+        "crates.io-crate"
+    }
+
+    pub fn get(&self, crate_name: &str) -> Result<Option<db_dump::Crate>> {
+        Ok(self
+            .inner
+            .lock()
+            .query_row(
+                &format!("SELECT data FROM '{}' WHERE key = ?1", Self::table_name()),
+                params![crate_name],
+                |r| r.get::<_, Vec<u8>>(0),
+            )
+            .optional()?
+            .map(|d| db_dump::Crate::from(d.as_slice())))
     }
 }
 
+/// Results always use their own connection, as deltas are read and written along with the results they are based on
 pub struct TaskResultTable {
     pub(crate) inner: ThreadSafeConnection,
 }
@@ -334,10 +649,74 @@ impl TableAccess for TaskResultTable {
     fn into_connection(self) -> ThreadSafeConnection {
         self.inner
     }
+
+    /// Return the result at `key`, resolving deltas into the `ExplodedCrate` they describe
+    fn get(&self, key: impl AsRef<str>) -> Result<Option<TaskResult>> {
+        let connection = self.inner.lock();
+        delta::get_raw(&connection, key.as_ref())?
+            .map(|result| delta::resolve(&connection, key.as_ref(), result))
+            .transpose()
+    }
+
+    /// Store `v` at `key`, as delta to the result of the previous crate version if it's an `ExplodedCrate`
+    fn insert(&self, progress: &mut prodash::tree::Item, key: impl AsRef<str>, v: &TaskResult) -> Result<()> {
+        // The base is read in the same transaction the delta is stored in, so it can't be stripped by retention in between,
+        // and results based on the one which is replaced are stored in full before, as it may differ from the new one.
+        self.exclusively(progress, |transaction| {
+            delta::materialize_dependents(transaction, key.as_ref())?;
+            let delta = delta::encode(transaction, key.as_ref(), v)?;
+            delta::put(transaction, key.as_ref(), delta.as_ref().unwrap_or(v))
+        })
+    }
+}
+
+impl TaskResultTable {
+    /// Like `get()`, but an `ExplodedCrate` only keeps the selected entries for which `wanted` returns true. The content
+    /// of all others isn't decoded at all, which saves a lot for crates with large files.
+    pub fn get_selected(
+        &self,
+        key: impl AsRef<str>,
+        wanted: impl Fn(&TarHeader) -> bool,
+    ) -> Result<Option<TaskResult>> {
+        delta::get_partially(&self.inner.lock(), key.as_ref(), &wanted)
+    }
+
+    /// The meta-data of all entries of the extracted crate at `key`, without decoding the content of any of them.
+    /// Returns `None` if there is no result at `key` or if it isn't an extracted crate.
+    pub fn get_meta_data(&self, key: impl AsRef<str>) -> Result<Option<Vec<TarHeader>>> {
+        Ok(match self.get_selected(key, |_| false)? {
+            Some(TaskResult::ExplodedCrate { entries_meta_data, .. }) => Some(entries_meta_data),
+            _ => None,
+        })
+    }
+
+    /// Run `f` in a transaction which keeps all other connections from writing until it is committed, for changes to results
+    /// which depend on other results, like deltas, or that other results depend on.
+    pub fn exclusively<T>(
+        &self,
+        progress: &mut prodash::tree::Item,
+        mut f: impl FnMut(&rusqlite::Transaction<'_>) -> Result<T>,
+    ) -> Result<T> {
+        retry_on_db_busy(Some(progress), || {
+            let mut connection = self.inner.lock();
+            let transaction = connection.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+            let res = f(&transaction)?;
+            transaction.commit()?;
+            Ok(res)
+        })
+    }
+
+    /// Store `v` at `key` exactly as given, without encoding it as delta
+    pub fn insert_as_is(&self, progress: &mut prodash::tree::Item, key: impl AsRef<str>, v: &TaskResult) -> Result<()> {
+        retry_on_db_busy(Some(progress), || {
+            self.inner.put(Self::table_name(), key.as_ref(), &rmp_serde::to_vec(v)?)
+        })
+    }
 }
 
 pub struct MetaTable {
     pub(crate) inner: ThreadSafeConnection,
+    pub(crate) backend: SharedBackend,
 }
 
 impl TableAccess for MetaTable {
@@ -357,6 +736,10 @@ impl TableAccess for MetaTable {
     fn into_connection(self) -> ThreadSafeConnection {
         self.inner
     }
+
+    fn backend(&self) -> &dyn Backend {
+        &*self.backend
+    }
 }
 
 impl MetaTable {
@@ -372,17 +755,125 @@ impl MetaTable {
         Ok(self
             .connection()
             .lock()
-            .query_row("SELECT key, data FROM meta ORDER BY key DESC limit 1", [], |r| {
-                Ok((r.get::<_, String>(0)?, r.get::<_, Vec<u8>>(1)?))
-            })
+            .query_row(
+                &format!(
+                    "SELECT key, data FROM meta WHERE {} ORDER BY key DESC limit 1",
+                    super::CONTEXT_CONDITION
+                ),
+                [],
+                |r| Ok((r.get::<_, String>(0)?, r.get::<_, Vec<u8>>(1)?)),
+            )
             .optional()?
             .map(|(k, v)| (k, Context::from(v.as_slice()))))
     }
 }
 
+#[derive(Clone)]
+pub struct IndexCheckTable {
+    pub(crate) inner: ThreadSafeConnection,
+    pub(crate) backend: SharedBackend,
+}
+
+impl TableAccess for IndexCheckTable {
+    type StorageItem = IndexCheck;
+    type InsertItem = IndexCheck;
+
+    fn connection(&self) -> &ThreadSafeConnection {
+        &self.inner
+    }
+    fn table_name() -> &'static str {
+        "index_check"
+    }
+    fn into_connection(self) -> ThreadSafeConnection {
+        self.inner
+    }
+
+    fn backend(&self) -> &dyn Backend {
+        &*self.backend
+    }
+}
+
+impl IndexCheckTable {
+    /// Store `check` of the index of `registry` keyed by the time it was completed, so that the most recent check sorts last
+    pub fn record(&self, progress: &mut prodash::tree::Item, registry: &Registry, check: &IndexCheck) -> Result<()> {
+        self.insert(
+            progress,
+            registry.qualify(&humantime::format_rfc3339_seconds(check.checked_at).to_string()),
+            check,
+        )
+    }
+
+    pub fn most_recent(&self, registry: &Registry) -> Result<Option<IndexCheck>> {
+        let (condition, values) = registry.key_condition();
+        Ok(self
+            .connection()
+            .lock()
+            .query_row(
+                &format!(
+                    "SELECT data FROM {} WHERE {} ORDER BY key DESC LIMIT 1",
+                    Self::table_name(),
+                    condition
+                ),
+                rusqlite::params_from_iter(values),
+                |r| r.get::<_, Vec<u8>>(0),
+            )
+            .optional()?
+            .map(|v| IndexCheck::from(v.as_slice())))
+    }
+}
+
+pub struct RunSummaryTable {
+    pub(crate) inner: ThreadSafeConnection,
+    pub(crate) backend: SharedBackend,
+}
+
+impl TableAccess for RunSummaryTable {
+    type StorageItem = RunSummary;
+    type InsertItem = RunSummary;
+
+    fn connection(&self) -> &ThreadSafeConnection {
+        &self.inner
+    }
+    fn table_name() -> &'static str {
+        "run_summary"
+    }
+    fn into_connection(self) -> ThreadSafeConnection {
+        self.inner
+    }
+
+    fn backend(&self) -> &dyn Backend {
+        &*self.backend
+    }
+}
+
+impl RunSummaryTable {
+    /// Store `summary` keyed by the time its run started, so that the most recent run sorts last
+    pub fn record(&self, progress: &mut prodash::tree::Item, summary: &RunSummary) -> Result<()> {
+        self.insert(
+            progress,
+            humantime::format_rfc3339_seconds(summary.started_at).to_string(),
+            summary,
+        )
+    }
+
+    pub fn most_recent(&self) -> Result<Option<RunSummary>> {
+        Ok(self
+            .connection()
+            .lock()
+            .query_row(
+                &format!("SELECT data FROM {} ORDER BY key DESC LIMIT 1", Self::table_name()),
+                [],
+                |r| r.get::<_, Vec<u8>>(0),
+            )
+            .optional()?
+            .map(|v| RunSummary::from(v.as_slice())))
+    }
+}
+
 #[derive(Clone)]
 pub struct CrateTable {
     pub(crate) inner: ThreadSafeConnection,
+    pub(crate) backend: SharedBackend,
 }
 
 impl TableAccess for CrateTable {
@@ -402,11 +893,16 @@ impl TableAccess for CrateTable {
     fn into_connection(self) -> ThreadSafeConnection {
         self.inner
     }
+
+    fn backend(&self) -> &dyn Backend {
+        &*self.backend
+    }
 }
 
 #[derive(Clone)]
 pub struct CrateVersionTable {
     pub(crate) inner: ThreadSafeConnection,
+    pub(crate) backend: SharedBackend,
 }
 
 impl TableAccess for CrateVersionTable {
@@ -422,4 +918,11 @@ impl TableAccess for CrateVersionTable {
     fn into_connection(self) -> ThreadSafeConnection {
         self.inner
     }
+
+    fn backend(&self) -> &dyn Backend {
+        &*self.backend
+    }
 }
+
+#[cfg(test)]
+mod table_test;
@@ -0,0 +1,121 @@
+use crate::{
+    model::{CrateVersion, TaskResult},
+    persistence::{
+        self, delta, fixtures::exploded_crate, new_key_insertion, new_key_value_query_old_to_new_filtered,
+        temp_db::TempDb, ReportsTree, TableAccess,
+    },
+};
+
+const ADVERSARIAL_KEYS: &[&str] = &[
+    "it's",
+    "\"quoted\"",
+    "a' OR '1'='1",
+    "x'; DROP TABLE crate; --",
+    "back\\slash",
+    "*[?]",
+];
+
+#[test]
+fn keys_are_never_interpreted_as_sql() {
+    let db = TempDb::new("table-keys");
+    let crates = db.open_crates().unwrap();
+    let versions = db.open_crate_versions().unwrap();
+    let mut progress = prodash::Tree::new().add_child("keys");
+    for name in ADVERSARIAL_KEYS {
+        let version = CrateVersion {
+            name: (*name).into(),
+            version: "1.0.0".into(),
+            ..Default::default()
+        };
+        crates.upsert(&mut progress, name, &version).unwrap();
+        versions
+            .insert(&mut progress, format!("{}:1.0.0", name), &version)
+            .unwrap();
+    }
+    assert_eq!(crates.count(), ADVERSARIAL_KEYS.len() as u64, "nothing was dropped");
+    for name in ADVERSARIAL_KEYS {
+        assert_eq!(crates.get(name).unwrap().unwrap().versions, vec!["1.0.0".to_owned()]);
+        assert_eq!(versions.get(format!("{}:1.0.0", name)).unwrap().unwrap().name, *name);
+    }
+    assert!(crates.get("a").unwrap().is_none());
+
+    let connection = db.open_connection_no_async_with_busy_wait().unwrap();
+    let mut insert = new_key_insertion(ReportsTree::table_name(), &connection).unwrap();
+    insert.execute([ADVERSARIAL_KEYS[2]]).unwrap();
+    let reports = db.open_reports().unwrap();
+    assert!(reports.is_done(ADVERSARIAL_KEYS[2]));
+    assert!(!reports.is_done("a"), "the key is not a condition");
+    assert!(!reports.is_done(ADVERSARIAL_KEYS[0]));
+}
+
+#[test]
+fn globs_only_match_keys() {
+    let db = TempDb::new("table-globs");
+    let crates = db.open_crates().unwrap();
+    let mut progress = prodash::Tree::new().add_child("globs");
+    for name in ADVERSARIAL_KEYS {
+        crates.upsert(&mut progress, name, &Default::default()).unwrap();
+    }
+    let connection = db.open_connection_no_async_with_busy_wait().unwrap();
+    for (glob, expected) in [("it'*", 1), ("*\" OR \"1\"=\"1", 0), ("*", ADVERSARIAL_KEYS.len())] {
+        let mut statement = new_key_value_query_old_to_new_filtered(
+            persistence::CrateTable::table_name(),
+            Some(glob),
+            &connection,
+            None,
+        )
+        .unwrap();
+        let num_matches = statement.query_map([glob], |_| Ok(())).unwrap().count();
+        assert_eq!(num_matches, expected, "{}", glob);
+        assert_eq!(crates.count_filtered(Some(glob)), expected as u64);
+    }
+}
+
+#[test]
+fn replacing_a_result_keeps_the_results_based_on_it_intact() {
+    let db = TempDb::new("table-replace-base");
+    let mut progress = prodash::Tree::new().add_child("replace");
+    let crates = db.open_crates().unwrap();
+    let add_version = |version: &str, progress: &mut prodash::tree::Item| {
+        let crate_version = CrateVersion {
+            name: "a".into(),
+            version: version.into(),
+            ..Default::default()
+        };
+        crates.upsert(progress, "a", &crate_version).unwrap();
+    };
+    let key = |version: &str| format!("a:{}:extract_crate:1.0.0", version);
+    let paths = ["Cargo.toml", "README.md", "src/lib.rs", "src/a.rs", "src/b.rs"];
+    let results = db.open_results().unwrap();
+    for version in ["1.1.0", "1.2.0"] {
+        add_version(version, &mut progress);
+        results
+            .insert(&mut progress, key(version), &exploded_crate(&paths))
+            .unwrap();
+    }
+    assert!(matches!(
+        delta::get_raw(&results.connection().lock(), &key("1.2.0")).unwrap(),
+        Some(TaskResult::ExplodedCrateDelta { .. })
+    ));
+    // A backport published later sorts between the delta and its base
+    add_version("1.1.1", &mut progress);
+
+    results
+        .insert(&mut progress, key("1.1.0"), &exploded_crate(&paths[..2]))
+        .unwrap();
+    assert_eq!(
+        results
+            .get(key("1.2.0"))
+            .unwrap()
+            .map(|r| rmp_serde::to_vec(&r).unwrap()),
+        Some(rmp_serde::to_vec(&exploded_crate(&paths)).unwrap()),
+        "the result based on the replaced one resolves to what was inserted"
+    );
+    assert_eq!(
+        results
+            .get(key("1.1.0"))
+            .unwrap()
+            .map(|r| rmp_serde::to_vec(&r).unwrap()),
+        Some(rmp_serde::to_vec(&exploded_crate(&paths[..2])).unwrap())
+    );
+}
@@ -0,0 +1,72 @@
+//! Databases and directories for tests, each of its own which is removed once the test is done with it.
+use crate::persistence::Db;
+use std::path::{Path, PathBuf};
+
+/// A database in a fresh temporary directory, which is removed when dropped, even if the test using it panics.
+pub struct TempDb {
+    pub db: Db,
+    /// The directory of the database, which is also a good place for assets and reports of the test
+    pub dir: PathBuf,
+}
+
+impl TempDb {
+    /// Open a new database in a directory named after `name`, which must be unique among all tests, removing whatever a
+    /// previous run of the test may have left behind.
+    pub fn new(name: &str) -> TempDb {
+        let dir = std::env::temp_dir().join(format!("criner-test-{}-{}", name, std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        TempDb {
+            db: Db::open(&dir).unwrap(),
+            dir,
+        }
+    }
+}
+
+impl std::ops::Deref for TempDb {
+    type Target = Db;
+
+    fn deref(&self) -> &Db {
+        &self.db
+    }
+}
+
+impl Drop for TempDb {
+    fn drop(&mut self) {
+        std::fs::remove_dir_all(&self.dir).ok();
+    }
+}
+
+/// A fresh and empty temporary directory, which is removed when dropped, even if the test using it panics.
+/// It's for tests which need files of their own, or open their database in a particular way.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Create a new directory named after `name`, which must be unique among all tests, removing whatever a previous
+    /// run of the test may have left behind.
+    pub fn new(name: &str) -> TempDir {
+        let dir = std::env::temp_dir().join(format!("criner-test-dir-{}-{}", name, std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        TempDir(dir)
+    }
+}
+
+impl std::ops::Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        std::fs::remove_dir_all(&self.0).ok();
+    }
+}
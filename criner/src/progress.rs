@@ -0,0 +1,83 @@
+//! A simple way to follow the progress of the engine for tools embedding criner without rendering a `prodash` tree.
+use async_io::Timer;
+use futures_util::future::{self, Either, FutureExt};
+use std::{future::Future, time::Duration};
+
+/// How far a stage of the engine got, as passed to `Progress::report()`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StageProgress {
+    /// The name of the stage, prefixed by the names of the stages it is part of, like "Reports / waste"
+    pub name: String,
+    /// The amount of items done so far
+    pub done: usize,
+    /// The amount of items to do in total, if known
+    pub total: Option<usize>,
+    /// What is being counted, like "crate versions"
+    pub unit: Option<String>,
+}
+
+/// Receives the progress of the stages of the engine which are currently running, in the order they would be shown
+/// in the terminal user interface.
+pub trait Progress {
+    fn report(&mut self, stages: &[StageProgress]);
+}
+
+impl<F> Progress for F
+where
+    F: FnMut(&[StageProgress]),
+{
+    fn report(&mut self, stages: &[StageProgress]) {
+        self(stages)
+    }
+}
+
+/// The progress of all items in `root` which count something, skipping those only used to group other items
+pub fn stages(root: &prodash::Tree) -> Vec<StageProgress> {
+    let mut snapshot = Vec::new();
+    root.sorted_snapshot(&mut snapshot);
+    let mut path: Vec<String> = Vec::new();
+    let mut stages = Vec::new();
+    for (key, task) in snapshot {
+        path.truncate(key.level().saturating_sub(1) as usize);
+        path.push(task.name);
+        if let Some(value) = task.progress {
+            let done = value.step;
+            stages.push(StageProgress {
+                name: path.join(" / "),
+                done,
+                total: value.done_at,
+                unit: value.unit.map(|unit| {
+                    let mut label = String::new();
+                    unit.as_display_value().display_unit(&mut label, done).ok();
+                    label
+                }),
+            });
+        }
+    }
+    stages
+}
+
+/// Report the stages in `root` to `progress` every `interval` and once more when `work` is done, returning its output.
+pub async fn report_until<T>(
+    root: &prodash::Tree,
+    interval: Duration,
+    progress: &mut dyn Progress,
+    work: impl Future<Output = T>,
+) -> T {
+    let mut work = work.boxed_local();
+    loop {
+        match future::select(work, Timer::after(interval)).await {
+            Either::Left((output, _)) => {
+                progress.report(&stages(root));
+                return output;
+            }
+            Either::Right((_, unfinished)) => {
+                progress.report(&stages(root));
+                work = unfinished;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod progress_test;
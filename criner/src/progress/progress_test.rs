@@ -0,0 +1,61 @@
+use super::{report_until, stages, StageProgress};
+use std::time::Duration;
+
+#[test]
+fn only_items_counting_something_are_stages_named_by_their_path() {
+    let root = prodash::Tree::new();
+    let mut reports = root.add_child("Reports");
+    let mut waste = reports.add_child("waste");
+    waste.init(Some(10), Some("crates".into()));
+    waste.set(3);
+    let mut timer = root.add_child("Fetch Timer");
+    timer.init(None, None);
+    timer.set(5);
+
+    assert_eq!(
+        stages(&root),
+        vec![
+            StageProgress {
+                name: "Reports / waste".into(),
+                done: 3,
+                total: Some(10),
+                unit: Some("crates".into()),
+            },
+            StageProgress {
+                name: "Fetch Timer".into(),
+                done: 5,
+                total: None,
+                unit: None,
+            }
+        ]
+    );
+    drop(reports);
+}
+
+#[test]
+fn stages_are_reported_while_working_and_when_done() {
+    let root = prodash::Tree::new();
+    let mut reported = Vec::new();
+    let output = futures_lite::future::block_on(report_until(
+        &root,
+        Duration::from_millis(10),
+        &mut |stages: &[StageProgress]| reported.push(stages.iter().map(|s| s.done).collect::<Vec<_>>()),
+        {
+            let mut item = root.add_child("Process Crate Versions");
+            async move {
+                item.init(Some(2), None);
+                async_io::Timer::after(Duration::from_millis(50)).await;
+                item.set(2);
+                42
+            }
+        },
+    ));
+    assert_eq!(output, 42);
+    assert!(reported.len() > 1, "reported every interval and once at the end");
+    assert_eq!(reported[0], vec![0]);
+    assert_eq!(
+        reported.last().unwrap(),
+        &Vec::<usize>::new(),
+        "finished stages are gone"
+    );
+}
@@ -0,0 +1,142 @@
+//! Ad-hoc inspection of everything stored about a crate, as JSON
+use crate::{
+    model::{ContentEncoding, CrateVersion, TarHeader, Task, TaskResult},
+    persistence::{self, delta, key_prefix_upper_bound, TableAccess, TaskResultTable, KEY_SEP_CHAR},
+    registry::Registry,
+    timeline::tasks_with_key_prefix,
+    Result,
+};
+use rusqlite::params;
+use serde_derive::Serialize;
+use serde_json::{json, Value};
+use std::{collections::BTreeMap, io::Write, path::Path};
+
+#[derive(Debug, Serialize)]
+pub struct CrateDump {
+    /// The name of the crate as stored in the database, qualified with the registry unless it is crates.io
+    pub name: String,
+    /// All stored versions, from oldest to most recent
+    pub versions: Vec<VersionDump>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VersionDump {
+    pub version: String,
+    /// The version as parsed from the index, if it is stored
+    pub index_entry: Option<CrateVersion>,
+    /// Tasks by the part of their key following the crate version, like `download:1.0.0`
+    pub tasks: BTreeMap<String, Task>,
+    /// Task results by the part of their key following the crate version, with deltas resolved
+    pub results: BTreeMap<String, Value>,
+}
+
+fn header_json(header: &TarHeader) -> Value {
+    let mut json = json!({
+        "path": String::from_utf8_lossy(&header.path),
+        "size": header.size,
+        "entry_type": header.entry_type,
+    });
+    if let Some(encoding) = header.encoding {
+        json["encoding"] = json!(encoding);
+    }
+    if let Some(truncated_at) = header.truncated_at {
+        json["truncated_at"] = json!(truncated_at);
+    }
+    json
+}
+
+/// Paths and contents are written as text, as arrays of bytes are unreadable in JSON. Binary contents are `null`.
+fn result_json(result: TaskResult) -> Result<Value> {
+    Ok(match result {
+        TaskResult::ExplodedCrate {
+            entries_meta_data,
+            selected_entries,
+        } => json!({
+            "ExplodedCrate": {
+                "entries_meta_data": entries_meta_data.iter().map(header_json).collect::<Vec<_>>(),
+                "selected_entries": selected_entries
+                    .iter()
+                    .map(|(header, content)| json!({
+                        "header": header_json(header),
+                        "content": match header.encoding {
+                            Some(ContentEncoding::Binary) => Value::Null,
+                            _ => json!(String::from_utf8_lossy(content)),
+                        },
+                    }))
+                    .collect::<Vec<_>>(),
+            }
+        }),
+        result => serde_json::to_value(result)?,
+    })
+}
+
+fn results_with_key_prefix(connection: &rusqlite::Connection, prefix: &str) -> Result<Vec<(String, TaskResult)>> {
+    let mut statement = connection.prepare(&format!(
+        "SELECT key, data FROM {} WHERE key >= ?1 AND key < ?2 ORDER BY key",
+        TaskResultTable::table_name()
+    ))?;
+    let results = statement
+        .query_map(params![prefix, key_prefix_upper_bound(prefix)], |r| {
+            Ok((
+                r.get::<_, String>(0)?,
+                TaskResult::from(r.get::<_, Vec<u8>>(1)?.as_slice()),
+            ))
+        })?
+        .collect::<std::result::Result<_, _>>()?;
+    Ok(results)
+}
+
+/// Collect all stored versions of `crate_name` of `registry` along with their tasks and task results.
+/// Returns `None` if the crate isn't known.
+pub fn crate_blocking(db_path: impl AsRef<Path>, registry: &Registry, crate_name: &str) -> Result<Option<CrateDump>> {
+    let db = persistence::Db::open(db_path)?;
+    let name = registry.qualify(crate_name).into_owned();
+    let krate = match db.open_crates()?.get(&name)? {
+        Some(krate) => krate,
+        None => return Ok(None),
+    };
+    let crate_versions = db.open_crate_versions()?;
+    let connection = db.open_connection_no_async_with_busy_wait()?;
+    let mut versions = Vec::with_capacity(krate.versions.len());
+    let mut prefix = String::new();
+    for version in krate.versions {
+        prefix.clear();
+        CrateVersion::key_from(&name, &version, &mut prefix);
+        let index_entry = crate_versions.get(&prefix)?;
+        prefix.push(KEY_SEP_CHAR);
+
+        let tasks = tasks_with_key_prefix(&connection, &prefix)?
+            .into_iter()
+            .map(|(key, task)| (key[prefix.len()..].to_owned(), task))
+            .collect();
+        let mut results = BTreeMap::new();
+        for (key, result) in results_with_key_prefix(&connection, &prefix)? {
+            let result = delta::resolve(&connection, &key, result)?;
+            results.insert(key[prefix.len()..].to_owned(), result_json(result)?);
+        }
+        versions.push(VersionDump {
+            version,
+            index_entry,
+            tasks,
+            results,
+        });
+    }
+    Ok(Some(CrateDump { name, versions }))
+}
+
+/// Write everything stored about `crate_name` of `registry` in the database at `db_path` to `out` as pretty-printed JSON
+pub fn write_crate_blocking(
+    db_path: impl AsRef<Path>,
+    registry: &Registry,
+    crate_name: &str,
+    mut out: impl Write,
+) -> Result<()> {
+    let dump = crate_blocking(db_path, registry, crate_name)?
+        .ok_or_else(|| crate::Error::Message(format!("Crate '{}' is not in the database", crate_name)))?;
+    serde_json::to_writer_pretty(&mut out, &dump)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod query_test;
@@ -0,0 +1,66 @@
+use super::crate_blocking;
+use crate::{
+    model::{CrateVersion, TarHeader, TaskResult},
+    persistence::{temp_db::TempDb, TableAccess},
+    registry::Registry,
+};
+
+#[test]
+fn everything_stored_about_a_crate_is_collected() {
+    let db = TempDb::new("query");
+    let dir = &db.dir;
+    let mut progress = prodash::Tree::new().add_child("query");
+
+    assert!(crate_blocking(dir, &Registry::default(), "a").unwrap().is_none());
+
+    for version in ["1.0.0", "1.1.0"] {
+        let v = CrateVersion {
+            name: "a".into(),
+            version: version.into(),
+            ..Default::default()
+        };
+        db.open_crates().unwrap().upsert(&mut progress, "a", &v).unwrap();
+        db.open_crate_versions()
+            .unwrap()
+            .insert(&mut progress, format!("a:{}", version), &v)
+            .unwrap();
+    }
+    let task = crate::engine::work::cpubound::default_persisted_extraction_task();
+    let key = "a:1.0.0:extract_crate:1.0.0";
+    db.open_tasks().unwrap().insert(&mut progress, key, &task).unwrap();
+    let manifest = TarHeader {
+        path: b"Cargo.toml".to_vec(),
+        size: 9,
+        entry_type: 0,
+        encoding: None,
+        truncated_at: None,
+    };
+    db.open_results()
+        .unwrap()
+        .insert(
+            &mut progress,
+            key,
+            &TaskResult::ExplodedCrate {
+                entries_meta_data: vec![manifest.clone()],
+                selected_entries: vec![(manifest, b"[package]".to_vec())],
+            },
+        )
+        .unwrap();
+
+    let dump = crate_blocking(dir, &Registry::default(), "a").unwrap().expect("stored");
+    assert_eq!(dump.name, "a");
+    assert_eq!(
+        dump.versions.iter().map(|v| v.version.as_str()).collect::<Vec<_>>(),
+        ["1.0.0", "1.1.0"]
+    );
+    let first = &dump.versions[0];
+    assert!(first.index_entry.is_some());
+    assert!(first.tasks.contains_key("extract_crate:1.0.0"));
+    let result = &first.results["extract_crate:1.0.0"]["ExplodedCrate"];
+    assert_eq!(result["entries_meta_data"][0]["path"], "Cargo.toml");
+    assert_eq!(
+        result["selected_entries"][0]["content"], "[package]",
+        "contents are written as text"
+    );
+    assert!(dump.versions[1].tasks.is_empty() && dump.versions[1].results.is_empty());
+}
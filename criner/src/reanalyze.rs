@@ -0,0 +1,69 @@
+//! Analysing all versions of a crate once more, i.e. after the analysis was improved.
+//!
+//! Instead of doing the work right away, everything done for the crate is forgotten so the next `criner mine` does it
+//! again with the current versions of all tasks and reports.
+use crate::{
+    engine::stage::report::{self, cycle},
+    model::CrateVersion,
+    persistence::{self, TableAccess, KEY_SEP_CHAR},
+    registry::Registry,
+    Error, Result,
+};
+use std::{fmt, path::Path};
+
+/// What was forgotten by `reset_blocking()`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Reset {
+    /// The versions of the crate
+    pub versions: usize,
+    /// Download and extraction tasks which are scheduled once more, without downloading assets which are present
+    pub tasks: usize,
+    /// Markers of reports of versions which are generated once more
+    pub report_markers: usize,
+    /// Cached states of reports of the crate, which are removed from the state of all crates as well
+    pub cached_reports: usize,
+}
+
+impl fmt::Display for Reset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "reset {} task(s), {} report marker(s) and {} cached report(s) of {} version(s)",
+            self.tasks, self.report_markers, self.cached_reports, self.versions
+        )
+    }
+}
+
+/// Forget all tasks and reports of all versions of `crate_name` of `registry` in the database at `db_path`, so the next
+/// `criner mine` extracts and reports them once more, and downloads them if they are missing.
+/// Cached reports are forgotten in the published report cycle and in the one generated right now, if any.
+pub fn reset_blocking(db_path: impl AsRef<Path>, registry: &Registry, crate_name: &str) -> Result<Reset> {
+    let db_path = db_path.as_ref();
+    let db = persistence::Db::open(db_path)?;
+    let name = registry.qualify(crate_name).into_owned();
+    let krate = db
+        .open_crates()?
+        .get(&name)?
+        .ok_or_else(|| Error::Message(format!("Crate '{}' is not in the database", crate_name)))?;
+    let (tasks, reports) = (db.open_tasks()?, db.open_reports()?);
+
+    let mut reset = Reset {
+        versions: krate.versions.len(),
+        ..Default::default()
+    };
+    let mut prefix = String::new();
+    for version in krate.versions.iter() {
+        prefix.clear();
+        CrateVersion::key_from(&name, version, &mut prefix);
+        prefix.push(KEY_SEP_CHAR);
+        reset.tasks += tasks.remove_with_prefix(&prefix)?;
+        reset.report_markers += reports.remove_with_prefix(&prefix)?;
+    }
+    for cycle_dir in cycle::current_and_upcoming(&db_path.join("reports"))? {
+        reset.cached_reports += report::forget_crate(&Default::default(), &cycle_dir, &name)?;
+    }
+    Ok(reset)
+}
+
+#[cfg(test)]
+mod reanalyze_test;
@@ -0,0 +1,112 @@
+use super::{reset_blocking, Reset};
+use crate::{
+    engine::{
+        report::{generic::Aggregate, waste, Settings},
+        stage::report::{cycle, CACHE_DIR_NAME},
+    },
+    model::CrateVersion,
+    persistence::{temp_db::TempDb, ReportsTree, TableAccess},
+    registry::Registry,
+};
+
+fn version_report(crate_name: &str, total_size_in_bytes: u64) -> waste::Report {
+    waste::Report::Version {
+        crate_name: crate_name.into(),
+        crate_version: "1.0.0".into(),
+        total_size_in_bytes,
+        total_files: 3,
+        wasted_files: vec![("logo.png".into(), total_size_in_bytes / 2)],
+        suggested_fix: None,
+        breakdown: Default::default(),
+        downloads_per_day: None,
+    }
+}
+
+#[test]
+fn tasks_report_markers_and_cached_reports_of_all_versions_are_forgotten() {
+    let db = TempDb::new("reanalyze");
+    let dir = &db.dir;
+    let mut progress = prodash::Tree::new().add_child("reanalyze");
+    for (name, version) in [("a", "1.0.0"), ("a", "1.1.0"), ("ab", "1.0.0")] {
+        let v = CrateVersion {
+            name: name.into(),
+            version: version.into(),
+            ..Default::default()
+        };
+        db.open_crates().unwrap().upsert(&mut progress, name, &v).unwrap();
+        db.open_tasks()
+            .unwrap()
+            .insert(
+                &mut progress,
+                format!("{}:{}:extract:1.0.0", name, version),
+                &Default::default(),
+            )
+            .unwrap();
+        db.open_connection_no_async_with_busy_wait()
+            .unwrap()
+            .execute(
+                &format!("INSERT INTO {} (key) VALUES (?1)", ReportsTree::table_name()),
+                [format!("{}:{}:waste:2.0.0", name, version)],
+            )
+            .unwrap();
+    }
+
+    let reports_dir = dir.join("reports");
+    let cycle_dir = cycle::begin(&db, &reports_dir, &mut progress).unwrap();
+    let cache_dir = cycle_dir.join("waste").join(CACHE_DIR_NAME);
+    let (a, ab) = (version_report("a", 100), version_report("ab", 40));
+    let top_level = a.clone().merge(ab.clone());
+    let settings = Settings::default();
+    futures_lite::future::block_on(async {
+        for report in [&a, &ab, &top_level] {
+            report
+                .store_current_state(&settings, &cache_dir, &mut progress)
+                .await
+                .unwrap();
+        }
+    });
+    cycle::publish(&reports_dir, &cycle_dir).unwrap();
+
+    assert_eq!(
+        reset_blocking(dir, &Registry::default(), "a").unwrap(),
+        Reset {
+            versions: 2,
+            tasks: 2,
+            report_markers: 2,
+            cached_reports: 1,
+        }
+    );
+    assert_eq!(db.open_tasks().unwrap().count(), 1, "tasks of 'ab' are kept");
+    let reports = db.open_reports().unwrap();
+    assert!(!reports.is_done("a:1.0.0:waste:2.0.0"));
+    assert!(reports.is_done("ab:1.0.0:waste:2.0.0"));
+
+    futures_lite::future::block_on(async {
+        assert!(a
+            .load_previous_state(&settings, &cache_dir, &mut progress)
+            .await
+            .is_none());
+        assert_eq!(
+            ab.load_previous_state(&settings, &cache_dir, &mut progress).await,
+            Some(ab)
+        );
+        let forgotten = waste::Report::load_previous_top_level_state(&settings, &cache_dir, &mut progress)
+            .await
+            .unwrap();
+        assert_eq!(
+            forgotten.merge(a),
+            top_level,
+            "reporting the crate once more leads to the same state of all crates"
+        );
+    });
+
+    assert_eq!(
+        reset_blocking(dir, &Registry::default(), "a").unwrap(),
+        Reset {
+            versions: 2,
+            ..Default::default()
+        },
+        "nothing is left to forget"
+    );
+    assert!(reset_blocking(dir, &Registry::default(), "unknown").is_err());
+}
@@ -0,0 +1,234 @@
+//! Registries other than crates.io share the database with it by namespacing their crates.
+//!
+//! Crates of other registries are stored with their name prefixed by the registry name and a `/`, like `acme/foo`,
+//! which can't be part of a crate name. As all keys start with the crate name, this keeps their crate versions, tasks,
+//! results and reports apart. Crates of crates.io are stored without prefix, so existing databases remain valid.
+use crate::{persistence::key_prefix_upper_bound, Error, Result};
+use crates_index_diff::{git2, CloneOptions, Index};
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// The name of crates.io as used by cargo
+pub const CRATES_IO: &str = "crates-io";
+/// Separates the registry name from the crate name in stored crate names
+pub const REGISTRY_SEP_CHAR: char = '/';
+const CRATES_IO_INDEX_URL: &str = "https://github.com/rust-lang/crates.io-index";
+
+/// Tables whose keys start with the stored crate name, or with the registry name as qualified by `Registry::qualify()`
+pub(crate) const TABLES_KEYED_BY_CRATE: &[&str] = &[
+    "crate",
+    "crate_version",
+    "task",
+    "result",
+    "crates.io-crate",
+    "index_check",
+    "report_done",
+    "processing_journal",
+    "error_telemetry",
+    "task_event",
+];
+
+/// A registry whose crates are mined
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Registry {
+    /// The name as used in `.cargo/config.toml`, like `crates-io`
+    pub name: String,
+    /// The URL of the git repository with the index of the registry
+    pub index_url: String,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Registry {
+            name: CRATES_IO.into(),
+            index_url: CRATES_IO_INDEX_URL.into(),
+        }
+    }
+}
+
+/// Split a stored crate name into the name of its registry, `None` for crates.io, and the name of the crate in it
+pub fn split_crate_name(name: &str) -> (Option<&str>, &str) {
+    match name.split_once(REGISTRY_SEP_CHAR) {
+        Some((registry, name)) => (Some(registry), name),
+        None => (None, name),
+    }
+}
+
+impl Registry {
+    /// Look up the registry called `name` like cargo does, taking the index URL of registries other than crates.io
+    /// from the `CARGO_REGISTRIES_<NAME>_INDEX` environment variable.
+    pub fn by_name(name: &str) -> Result<Registry> {
+        if name == CRATES_IO {
+            return Ok(Registry::default());
+        }
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            return Err(Error::Message(format!(
+                "'{}' is not a valid registry name, which consists of letters, numbers, '-' and '_'",
+                name
+            )));
+        }
+        let variable = format!("CARGO_REGISTRIES_{}_INDEX", name.to_ascii_uppercase().replace('-', "_"));
+        let index_url = std::env::var(&variable).map_err(|_| {
+            Error::Message(format!(
+                "The index of registry '{}' is unknown, set it with the {} environment variable",
+                name, variable
+            ))
+        })?;
+        Ok(Registry {
+            name: name.to_owned(),
+            index_url: index_url.trim_start_matches("git+").to_owned(),
+        })
+    }
+
+    /// Like `by_name()`, for use on the command-line
+    pub fn parse(name: &str) -> std::result::Result<Registry, String> {
+        Registry::by_name(name).map_err(|err| err.to_string())
+    }
+
+    pub fn is_crates_io(&self) -> bool {
+        self.name == CRATES_IO
+    }
+
+    /// The name under which crate `name` of this registry is stored
+    pub fn qualify<'a>(&self, name: &'a str) -> Cow<'a, str> {
+        if self.is_crates_io() {
+            name.into()
+        } else {
+            format!("{}{}{}", self.name, REGISTRY_SEP_CHAR, name).into()
+        }
+    }
+
+    /// Returns true if the crate stored as `name` belongs to this registry
+    pub fn contains(&self, name: &str) -> bool {
+        match split_crate_name(name).0 {
+            None => self.is_crates_io(),
+            Some(registry) => registry == self.name,
+        }
+    }
+
+    /// An SQL condition selecting the keys of crates of this registry, with the values for its parameters
+    pub(crate) fn key_condition(&self) -> (&'static str, Vec<String>) {
+        if self.is_crates_io() {
+            ("key NOT GLOB ?1", vec![format!("*{}*", REGISTRY_SEP_CHAR)])
+        } else {
+            let prefix = self.qualify("").into_owned();
+            let upper_bound = key_prefix_upper_bound(&prefix);
+            ("key >= ?1 AND key < ?2", vec![prefix, upper_bound])
+        }
+    }
+
+    /// Remove the crates of all other registries from the database behind `connection`, along with everything stored about them,
+    /// returning the amount of removed rows. Rows which aren't about crates, like the counts of each day, are kept.
+    pub(crate) fn remove_other_registries(&self, connection: &rusqlite::Connection) -> Result<usize> {
+        let (condition, values) = self.key_condition();
+        let mut removed = 0;
+        for table in TABLES_KEYED_BY_CRATE {
+            removed += connection.execute(
+                &format!("DELETE FROM '{}' WHERE NOT ({})", table, condition),
+                rusqlite::params_from_iter(values.iter()),
+            )?;
+        }
+        Ok(removed)
+    }
+
+    /// Where to keep the clone of the index unless configured otherwise
+    pub fn default_index_path(&self) -> PathBuf {
+        std::env::temp_dir().join(format!("criner-{}-bare-index.git", self.name))
+    }
+
+    /// Open the clone of the index at `path`, or clone it there if it doesn't exist yet
+    pub(crate) fn open_index(&self, path: impl AsRef<Path>) -> std::result::Result<Index, git2::Error> {
+        Index::from_path_or_cloned_with_options(
+            path,
+            CloneOptions {
+                repository_url: self.index_url.clone(),
+            },
+        )
+    }
+
+    /// The time of the commit of the index at `path` which was seen by the last fetch, or `None` if it was never fetched
+    pub(crate) fn last_seen_commit_time(&self, path: impl AsRef<Path>) -> Result<Option<SystemTime>> {
+        // Never clone the index here, that's up to fetching changes
+        if git2::Repository::open(path.as_ref()).is_err() {
+            return Ok(None);
+        }
+        let index = self.open_index(path)?;
+        let commit = match index.last_seen_reference() {
+            Ok(reference) => reference.peel_to_commit()?,
+            Err(_) => return Ok(None),
+        };
+        Ok(Some(
+            UNIX_EPOCH + Duration::from_secs(commit.time().seconds().max(0) as u64),
+        ))
+    }
+}
+
+/// The template for download URLs in the `config.json` of the fetched index at `index_path`, with the markers
+/// `{crate}` and `{version}` appended as cargo does if the template has none.
+pub(crate) fn download_url_template(index_path: &Path) -> Result<String> {
+    #[derive(serde_derive::Deserialize)]
+    struct Config {
+        dl: String,
+    }
+    let repo = git2::Repository::open(index_path)?;
+    let tree = repo.find_reference("refs/remotes/origin/master")?.peel_to_tree()?;
+    let config = tree
+        .get_name("config.json")
+        .ok_or_else(|| Error::Protocol(format!("The index at {} has no config.json", index_path.display())))?
+        .to_object(&repo)?
+        .peel_to_blob()?;
+    let Config { dl } = serde_json::from_slice(config.content())?;
+    Ok(with_markers(dl))
+}
+
+fn with_markers(template: String) -> String {
+    if ["{crate}", "{version}", "{prefix}", "{lowerprefix}", "{sha256-checksum}"]
+        .iter()
+        .any(|marker| template.contains(marker))
+    {
+        template
+    } else {
+        format!("{}/{{crate}}/{{version}}/download", template.trim_end_matches('/'))
+    }
+}
+
+/// Parse a template for download URLs of crates like in the `config.json` of an index, for use on the command-line.
+/// As with cargo, `/{crate}/{version}/download` is appended if the template has no markers.
+pub fn parse_download_url_template(template: &str) -> std::result::Result<String, String> {
+    if !(template.starts_with("https://") || template.starts_with("http://")) {
+        return Err(format!(
+            "Invalid download URL template '{}', expected it to start with https:// or http://",
+            template
+        ));
+    }
+    Ok(with_markers(template.to_owned()))
+}
+
+/// The directories of `name` in the index, like `se/rd` for `serde`, as used by the `{prefix}` marker
+pub(crate) fn index_prefix(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let part = |range: std::ops::Range<usize>| chars[range].iter().collect::<String>();
+    match chars.len() {
+        0 | 1 => "1".into(),
+        2 => "2".into(),
+        3 => format!("3/{}", part(0..1)),
+        _ => format!("{}/{}", part(0..2), part(2..4)),
+    }
+}
+
+/// Fill in the markers of a `template` as returned by `download_url_template()` or `parse_download_url_template()` for a crate version
+pub(crate) fn download_url(template: &str, name: &str, version: &str, checksum: &str) -> String {
+    let prefix = index_prefix(name);
+    template
+        .replace("{crate}", name)
+        .replace("{version}", version)
+        .replace("{lowerprefix}", &prefix.to_lowercase())
+        .replace("{prefix}", &prefix)
+        .replace("{sha256-checksum}", checksum)
+}
+
+#[cfg(test)]
+mod registry_test;
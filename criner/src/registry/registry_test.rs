@@ -0,0 +1,145 @@
+use crate::{
+    model::{CrateVersion, IndexCheck, Task},
+    persistence::{temp_db::TempDb, TableAccess},
+    registry::{download_url, parse_download_url_template, split_crate_name, Registry},
+};
+
+fn acme() -> Registry {
+    Registry {
+        name: "acme".into(),
+        index_url: "https://git.example.com/index.git".into(),
+    }
+}
+
+#[test]
+fn crates_of_other_registries_are_namespaced() {
+    let crates_io = Registry::default();
+    assert_eq!(crates_io.qualify("serde"), "serde");
+    assert_eq!(acme().qualify("serde"), "acme/serde");
+
+    assert_eq!(split_crate_name("serde"), (None, "serde"));
+    assert_eq!(split_crate_name("acme/serde"), (Some("acme"), "serde"));
+
+    assert!(crates_io.contains("serde"));
+    assert!(!crates_io.contains("acme/serde"));
+    assert!(acme().contains("acme/serde"));
+    assert!(!acme().contains("serde"));
+    assert!(!acme().contains("other/serde"));
+}
+
+#[test]
+fn registries_are_configured_like_for_cargo() {
+    assert!(Registry::by_name("crates-io").unwrap().is_crates_io());
+    assert!(Registry::by_name("not/valid").is_err());
+    assert!(Registry::by_name("criner-test-unconfigured").is_err());
+
+    std::env::set_var(
+        "CARGO_REGISTRIES_CRINER_TEST_INDEX",
+        "git+https://git.example.com/index.git",
+    );
+    let registry = Registry::by_name("criner-test").unwrap();
+    assert_eq!(registry.index_url, "https://git.example.com/index.git");
+    assert!(!registry.is_crates_io());
+}
+
+#[test]
+fn download_urls_fill_in_all_markers() {
+    assert_eq!(
+        download_url(
+            "https://dl.example.com/{prefix}/{lowerprefix}/{crate}/{version}/{sha256-checksum}",
+            "Serde",
+            "1.0.0",
+            "abc"
+        ),
+        "https://dl.example.com/Se/rd/se/rd/Serde/1.0.0/abc"
+    );
+    assert_eq!(
+        download_url("https://dl.example.com/{prefix}/{crate}", "foo", "1.0.0", ""),
+        "https://dl.example.com/3/f/foo"
+    );
+}
+
+#[test]
+fn download_urls_of_crates_with_non_ascii_names_split_at_characters() {
+    assert_eq!(
+        download_url("https://dl.example.com/{prefix}/{crate}", "äöüß", "1.0.0", ""),
+        "https://dl.example.com/äö/üß/äöüß"
+    );
+    assert_eq!(
+        download_url("https://dl.example.com/{prefix}/{crate}", "äbc", "1.0.0", ""),
+        "https://dl.example.com/3/ä/äbc"
+    );
+}
+
+#[test]
+fn download_url_templates_are_completed_like_by_cargo() {
+    assert_eq!(
+        parse_download_url_template("https://mirror.example.com/api/v1/crates/{crate}/{version}/download").unwrap(),
+        "https://mirror.example.com/api/v1/crates/{crate}/{version}/download"
+    );
+    assert_eq!(
+        parse_download_url_template("https://mirror.example.com/crates/").unwrap(),
+        "https://mirror.example.com/crates/{crate}/{version}/download"
+    );
+    assert!(parse_download_url_template("mirror.example.com/{crate}").is_err());
+}
+
+#[test]
+fn other_registries_are_removed_with_everything_stored_about_their_crates() {
+    let db = TempDb::new("registry-remove-others");
+    let mut progress = prodash::Tree::new().add_child("registries");
+    let (crates, tasks, index_checks) = (
+        db.open_crates().unwrap(),
+        db.open_tasks().unwrap(),
+        db.open_index_checks().unwrap(),
+    );
+    for name in ["serde", "acme/serde", "other/foo"] {
+        let version = CrateVersion {
+            name: name.into(),
+            version: "1.0.0".into(),
+            ..Default::default()
+        };
+        crates.upsert(&mut progress, name, &version).unwrap();
+        tasks
+            .insert(
+                &mut progress,
+                format!("{}:1.0.0:download:1.0.0", name),
+                &Task::default(),
+            )
+            .unwrap();
+    }
+    let check = IndexCheck {
+        checked_at: std::time::SystemTime::now(),
+        index_commit: "abc".into(),
+        index_versions: 1,
+        num_missing: 0,
+        missing: Vec::new(),
+        num_unknown_to_index: 0,
+        repaired: 0,
+    };
+    for registry in [Registry::default(), acme()] {
+        index_checks.record(&mut progress, &registry, &check).unwrap();
+    }
+    db.open_context()
+        .unwrap()
+        .update_today(|c| c.counts.crates = 3)
+        .unwrap();
+
+    let connection = db.open_connection_no_async_with_busy_wait().unwrap();
+    assert_eq!(acme().remove_other_registries(&connection).unwrap(), 5);
+    assert_eq!(
+        (crates.count(), tasks.count()),
+        (1, 1),
+        "only the crate of 'acme' is left"
+    );
+    assert!(crates.get("acme/serde").unwrap().is_some());
+    assert!(index_checks.most_recent(&acme()).unwrap().is_some());
+    assert!(index_checks.most_recent(&Registry::default()).unwrap().is_none());
+    assert!(
+        db.open_context().unwrap().most_recent().unwrap().is_some(),
+        "the counts of each day aren't about a registry"
+    );
+
+    assert_eq!(Registry::default().remove_other_registries(&connection).unwrap(), 3);
+    assert_eq!((crates.count(), tasks.count()), (0, 0));
+}
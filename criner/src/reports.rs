@@ -0,0 +1,212 @@
+//! Garbage collection of generated reports.
+//!
+//! Report cycles are published one after another, and each one starts out with all files of the one before it. Files
+//! whose purpose is gone thus stay around forever: cached states of reports written by previous versions of their
+//! generator, and pages of crates and versions which were deleted from the index or yanked.
+use crate::{
+    engine::{
+        report::{generic::CRATE_API_FILE_NAME, versioned::TOP_LEVEL_REPORT_NAME},
+        stage::report::{cycle, CACHE_DIR_NAME},
+    },
+    model::{Crate, CrateVersion},
+    persistence::{self, CrateTable, CrateVersionTable, TableAccess, KEY_SEP_CHAR},
+    Result,
+};
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// What was removed by `gc_blocking()`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Collected {
+    /// Published report cycles which were superseded by the ones to keep
+    pub cycles: usize,
+    /// Cached states of reports written by previous versions of their generator
+    pub stale_cache_files: usize,
+    /// Pages and other files of crates and versions which were deleted or yanked
+    pub orphaned_files: usize,
+}
+
+impl fmt::Display for Collected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "removed {} superseded report cycle(s), {} stale cache file(s) and {} orphaned page(s)",
+            self.cycles, self.stale_cache_files, self.orphaned_files
+        )
+    }
+}
+
+/// Remove all but the `keep` most recently published report cycles of the database at `db_path`, and remove stale cache
+/// files and orphaned pages from the published cycle as well as from the cycle which is generated right now, if any.
+/// Markers of reports whose pages are removed are removed as well, so they are generated once more if a version
+/// is unyanked.
+pub fn gc_blocking(db_path: impl AsRef<Path>, keep: usize) -> Result<Collected> {
+    let db_path = db_path.as_ref();
+    let reports_dir = db_path.join("reports");
+    let db = persistence::Db::open(db_path)?;
+    let mut crates = Crates {
+        crates: db.open_crates()?,
+        crate_versions: db.open_crate_versions()?,
+        known: HashMap::new(),
+    };
+    let reports = db.open_reports()?;
+
+    let mut collected = Collected {
+        cycles: cycle::remove_superseded(&reports_dir, keep)?,
+        ..Default::default()
+    };
+    for cycle_dir in cycle::current_and_upcoming(&reports_dir)? {
+        for report in std::fs::read_dir(cycle_dir)? {
+            let report = report?;
+            if !report.file_type()?.is_dir() {
+                continue;
+            }
+            let report_dir = report.path();
+            collected.stale_cache_files += remove_stale_cache_files(&report_dir.join(CACHE_DIR_NAME))?;
+
+            let report_name = report.file_name().to_string_lossy().into_owned();
+            for (crate_name, version, path) in orphaned_files(&report_dir, &mut crates)? {
+                std::fs::remove_file(path)?;
+                collected.orphaned_files += 1;
+                if let Some(version) = version {
+                    reports.remove_with_prefix(&format!(
+                        "{crate_name}{sep}{version}{sep}{report}{sep}",
+                        crate_name = crate_name,
+                        version = version,
+                        report = report_name,
+                        sep = KEY_SEP_CHAR
+                    ))?;
+                }
+            }
+        }
+    }
+    Ok(collected)
+}
+
+/// Remove all cached states in `cache_dir` which were written by another version of the report generator than the one
+/// which wrote the most recent top-level state. Returns the amount of removed files.
+fn remove_stale_cache_files(cache_dir: &Path) -> Result<usize> {
+    let entries = match std::fs::read_dir(cache_dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err.into()),
+    };
+    let mut top_level_states = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with(TOP_LEVEL_REPORT_NAME) {
+            top_level_states.push((entry.metadata()?.modified()?, name));
+        }
+    }
+    // Like `__top-level-report__-waste-1.1.0.rmp`, whose suffix `-waste-1.1.0.rmp` is shared by all current states
+    let current_suffix = match top_level_states.into_iter().max() {
+        Some((_, name)) => name[TOP_LEVEL_REPORT_NAME.len()..].to_owned(),
+        None => return Ok(0),
+    };
+
+    let mut num_removed = 0;
+    for path in files_below(cache_dir)? {
+        let is_stale = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.ends_with(".rmp") && !name.ends_with(&current_suffix));
+        if is_stale {
+            std::fs::remove_file(path)?;
+            num_removed += 1;
+        }
+    }
+    Ok(num_removed)
+}
+
+/// Files of crates in `report_dir` which shouldn't be there anymore, along with their crate and version, which is unset
+/// for the files of the crate itself.
+fn orphaned_files(report_dir: &Path, crates: &mut Crates) -> Result<Vec<(String, Option<String>, PathBuf)>> {
+    let mut orphans = Vec::new();
+    for path in files_below(report_dir)? {
+        let relative_path = path.strip_prefix(report_dir).expect("files to be below their report");
+        let (crate_dir, file_name) = match (relative_path.parent(), relative_path.file_name()) {
+            (Some(crate_dir), Some(file_name)) if crate_dir != Path::new("") => (crate_dir, file_name),
+            _ => continue,
+        };
+        if crate_dir.starts_with(CACHE_DIR_NAME) || crate_dir.starts_with(".git") {
+            continue;
+        }
+        // Crates of registries other than crates.io are in a directory named after their registry
+        let crate_name = crate_dir
+            .iter()
+            .map(|c| c.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let version = match Path::new(file_name).file_stem().map(|stem| stem.to_string_lossy()) {
+            Some(stem) if file_name != "index.html" && file_name != CRATE_API_FILE_NAME => Some(stem.into_owned()),
+            _ => None,
+        };
+        let is_orphaned = match version.as_deref() {
+            Some(version) => !crates.has_unyanked_version(&crate_name, version)?,
+            None => !crates.exists(&crate_name)?,
+        };
+        if is_orphaned {
+            orphans.push((crate_name, version, path));
+        }
+    }
+    Ok(orphans)
+}
+
+/// Lookups of crates and their versions, remembering crates as many files belong to the same one
+struct Crates {
+    crates: CrateTable,
+    crate_versions: CrateVersionTable,
+    known: HashMap<String, Option<Crate>>,
+}
+
+impl Crates {
+    fn get(&mut self, name: &str) -> Result<Option<&Crate>> {
+        if !self.known.contains_key(name) {
+            let krate = self.crates.get(name)?;
+            self.known.insert(name.to_owned(), krate);
+        }
+        Ok(self.known[name].as_ref())
+    }
+
+    fn exists(&mut self, name: &str) -> Result<bool> {
+        Ok(self.get(name)?.is_some())
+    }
+
+    fn has_unyanked_version(&mut self, name: &str, version: &str) -> Result<bool> {
+        if !self
+            .get(name)?
+            .is_some_and(|krate| krate.versions.iter().any(|v| v == version))
+        {
+            return Ok(false);
+        }
+        let mut key = String::new();
+        CrateVersion::key_from(name, version, &mut key);
+        Ok(self
+            .crate_versions
+            .get(&key)?
+            .is_some_and(|v| v.kind != crates_index_diff::ChangeKind::Yanked))
+    }
+}
+
+fn files_below(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_owned()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                dirs.push(entry.path());
+            } else {
+                files.push(entry.path());
+            }
+        }
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod reports_test;
@@ -0,0 +1,101 @@
+use super::{gc_blocking, Collected};
+use crate::{
+    engine::stage::report::cycle::{self, CURRENT},
+    model::CrateVersion,
+    persistence::{temp_db::TempDb, ReportsTree, TableAccess},
+};
+
+#[test]
+fn superseded_cycles_stale_cache_files_and_orphaned_pages_are_removed() {
+    let db = TempDb::new("reports");
+    let dir = &db.dir;
+    let mut progress = prodash::Tree::new().add_child("gc");
+    for (version, kind) in [
+        ("1.0.0", crates_index_diff::ChangeKind::Added),
+        ("1.1.0", crates_index_diff::ChangeKind::Yanked),
+    ] {
+        let v = CrateVersion {
+            name: "a".into(),
+            version: version.into(),
+            kind,
+            ..Default::default()
+        };
+        db.open_crates().unwrap().upsert(&mut progress, "a", &v).unwrap();
+        db.open_crate_versions()
+            .unwrap()
+            .insert(&mut progress, format!("a:{}", version), &v)
+            .unwrap();
+        db.open_connection_no_async_with_busy_wait()
+            .unwrap()
+            .execute(
+                &format!("INSERT INTO {} (key) VALUES (?1)", ReportsTree::table_name()),
+                [format!("a:{}:waste:2.0.0", version)],
+            )
+            .unwrap();
+    }
+
+    let reports_dir = dir.join("reports");
+    let first = cycle::begin(&db, &reports_dir, &mut progress).unwrap();
+    cycle::publish(&reports_dir, &first).unwrap();
+    let second = cycle::begin(&db, &reports_dir, &mut progress).unwrap();
+    let files = [
+        "waste/index.html",
+        "waste/a/index.html",
+        "waste/a/api.json",
+        "waste/a/1.0.0.html",
+        "waste/a/1.1.0.html",
+        "waste/a/1.1.0.toml",
+        "waste/gone/index.html",
+        "waste/gone/api.json",
+        "waste/gone/1.0.0.html",
+        "waste/__incremental_cache__/__top-level-report__-waste-2.0.0.rmp",
+        "waste/__incremental_cache__/a-waste-2.0.0.rmp",
+        "waste/__incremental_cache__/a-waste-1.0.0.rmp",
+        "assets/index.html",
+    ];
+    for file in &files {
+        let path = second.join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, "").unwrap();
+    }
+    cycle::publish(&reports_dir, &second).unwrap();
+
+    assert_eq!(
+        gc_blocking(dir, 1).unwrap(),
+        Collected {
+            cycles: 1,
+            stale_cache_files: 1,
+            orphaned_files: 5,
+        }
+    );
+    assert!(!first.exists());
+    let remaining: Vec<_> = files
+        .iter()
+        .filter(|file| reports_dir.join(CURRENT).join(file).exists())
+        .copied()
+        .collect();
+    assert_eq!(
+        remaining,
+        [
+            "waste/index.html",
+            "waste/a/index.html",
+            "waste/a/api.json",
+            "waste/a/1.0.0.html",
+            "waste/__incremental_cache__/__top-level-report__-waste-2.0.0.rmp",
+            "waste/__incremental_cache__/a-waste-2.0.0.rmp",
+            "assets/index.html",
+        ]
+    );
+    let reports = db.open_reports().unwrap();
+    assert!(reports.is_done("a:1.0.0:waste:2.0.0"));
+    assert!(
+        !reports.is_done("a:1.1.0:waste:2.0.0"),
+        "if unyanked, the version is reported again"
+    );
+
+    assert_eq!(
+        gc_blocking(dir, 1).unwrap(),
+        Collected::default(),
+        "nothing is left to do"
+    );
+}
@@ -0,0 +1,92 @@
+//! Simulating which version of a crate cargo would select for a version requirement, to be shared by all analyses
+//! that need to know what dependents actually get.
+//!
+//! Requirements follow the syntax of `Cargo.toml`, like `1.2`, `^0.3.1`, `~1`, `>=1.0, <1.4`, `=2.0.0-rc.1` or `1.*`.
+use crate::{
+    model::CrateVersion,
+    persistence::{self, TableAccess},
+    registry::Registry,
+    utils::parse_semver,
+    Error, Result,
+};
+use std::{path::Path, str::FromStr};
+
+/// A parsed version requirement, matching versions like cargo does
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionReq(semver::VersionReq);
+
+impl FromStr for VersionReq {
+    type Err = Error;
+
+    fn from_str(requirement: &str) -> Result<Self> {
+        if requirement.trim().is_empty() {
+            return Ok(VersionReq(semver::VersionReq::STAR));
+        }
+        semver::VersionReq::parse(requirement)
+            .map(VersionReq)
+            .map_err(|err| Error::InvalidVersionRequirement(requirement.into(), err))
+    }
+}
+
+/// Parse `version` like `parse_semver()` does, leniently, for matching it against requirements
+pub fn parse_version(version: &str) -> Result<semver::Version> {
+    semver::Version::parse(version)
+        .ok()
+        .or_else(|| semver::Version::parse(&parse_semver(version).ok()?.to_string()).ok())
+        .ok_or_else(|| Error::InvalidVersion(version.into()))
+}
+
+impl VersionReq {
+    /// Returns true if `version` satisfies this requirement
+    pub fn matches(&self, version: &semver::Version) -> bool {
+        self.0.matches(version)
+    }
+
+    /// Select the greatest of `versions` which satisfies this requirement, skipping yanked versions and versions
+    /// which don't parse. Each version comes with a flag which is true if it was yanked.
+    pub fn select<'a>(&self, versions: impl IntoIterator<Item = (&'a str, bool)>) -> Option<&'a str> {
+        versions
+            .into_iter()
+            .filter(|(_, yanked)| !yanked)
+            .filter_map(|(version, _)| parse_version(version).ok().map(|semver| (semver, version)))
+            .filter(|(semver, _)| self.matches(semver))
+            .max_by(|(lhs, _), (rhs, _)| lhs.cmp(rhs))
+            .map(|(_, version)| version)
+    }
+}
+
+/// Returns the stored version of `crate_name` which cargo would select for `requirement`, or `None` if there is
+/// no such crate or no version satisfies the requirement.
+pub(crate) fn resolve(db: &persistence::Db, crate_name: &str, requirement: &VersionReq) -> Result<Option<String>> {
+    let krate = match db.open_crates()?.get(crate_name)? {
+        Some(krate) => krate,
+        None => return Ok(None),
+    };
+    let versions = db.open_crate_versions()?;
+    let mut candidates = Vec::with_capacity(krate.versions.len());
+    let mut key = String::new();
+    for version in krate.versions.iter() {
+        key.clear();
+        CrateVersion::key_from(crate_name, version, &mut key);
+        let yanked = versions
+            .get(&key)?
+            .is_some_and(|v| v.kind == crates_index_diff::ChangeKind::Yanked);
+        candidates.push((version.as_str(), yanked));
+    }
+    Ok(requirement.select(candidates).map(ToOwned::to_owned))
+}
+
+/// Like `resolve()`, but opens the database at `db_path` and parses `requirement` first, for `crate_name` of `registry`.
+pub fn resolve_blocking(
+    db_path: impl AsRef<Path>,
+    registry: &Registry,
+    crate_name: &str,
+    requirement: &str,
+) -> Result<Option<String>> {
+    let requirement: VersionReq = requirement.parse()?;
+    let db = persistence::Db::open(db_path)?;
+    resolve(&db, &registry.qualify(crate_name), &requirement)
+}
+
+#[cfg(test)]
+mod resolve_test;
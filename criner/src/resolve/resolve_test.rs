@@ -0,0 +1,79 @@
+use crate::resolve::{parse_version, VersionReq};
+
+fn matches(requirement: &str, version: &str) -> bool {
+    requirement
+        .parse::<VersionReq>()
+        .unwrap_or_else(|err| panic!("{}: {}", requirement, err))
+        .matches(&parse_version(version).unwrap())
+}
+
+#[test]
+fn requirements_match_like_cargo() {
+    for (requirement, matching, not_matching) in &[
+        ("1.2.3", &["1.2.3", "1.9.0"][..], &["1.2.2", "2.0.0", "1.3.0-alpha"][..]),
+        ("^0.2.3", &["0.2.3", "0.2.9"], &["0.3.0", "0.2.2"]),
+        ("0.0.3", &["0.0.3"], &["0.0.4"]),
+        ("0.0", &["0.0.7"], &["0.1.0"]),
+        ("~1.2", &["1.2.0", "1.2.9"], &["1.3.0"]),
+        ("~1", &["1.0.0", "1.9.9"], &["2.0.0"]),
+        (">=1.0, <1.4", &["1.0.0", "1.3.9"], &["1.4.0", "0.9.0"]),
+        ("=1.2.3", &["1.2.3"], &["1.2.4"]),
+        ("1.*", &["1.0.0", "1.8.1"], &["2.0.0"]),
+        ("*", &["0.0.1", "17.1.0"], &["1.0.0-beta"]),
+        ("", &["3.1.4"], &[]),
+        (">1.2", &["1.3.0"], &["1.2.9"]),
+        ("<=1.2", &["1.2.9", "0.1.0"], &["1.3.0"]),
+        (
+            "^1.0.0-beta.2",
+            &["1.0.0-beta.10", "1.0.0", "1.5.0"],
+            &["1.0.0-beta.1", "1.1.0-beta.3"],
+        ),
+    ] {
+        for version in matching.iter() {
+            assert!(
+                matches(requirement, version),
+                "'{}' should match {}",
+                requirement,
+                version
+            );
+        }
+        for version in not_matching.iter() {
+            assert!(
+                !matches(requirement, version),
+                "'{}' should not match {}",
+                requirement,
+                version
+            );
+        }
+    }
+}
+
+#[test]
+fn invalid_requirements_are_errors() {
+    for requirement in &["^", "1.2.3.4", "a.b", ">*", "1.*.3", "1.2-beta", "1.2.3-"] {
+        assert!(requirement.parse::<VersionReq>().is_err(), "{}", requirement);
+    }
+}
+
+#[test]
+fn versions_which_dont_follow_the_specification_are_matched_leniently() {
+    assert!(matches("^1.2", "1.2"));
+    assert!(matches("^1", "01.2.3"));
+    assert!(parse_version("x.y.z").is_err());
+}
+
+#[test]
+fn the_greatest_matching_version_which_is_not_yanked_is_selected() {
+    let req: VersionReq = "^1.1".parse().unwrap();
+    let versions = vec![
+        ("1.0.0", false),
+        ("1.1.0", false),
+        ("1.10.0", false),
+        ("1.11.0", true),
+        ("1.2.0", false),
+        ("2.0.0", false),
+        ("1.12.0-rc.1", false),
+    ];
+    assert_eq!(req.select(versions.iter().copied()), Some("1.10.0"));
+    assert_eq!("^3".parse::<VersionReq>().unwrap().select(versions), None);
+}
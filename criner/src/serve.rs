@@ -0,0 +1,135 @@
+//! A dashboard serving the published reports along with the status of mining as JSON, for watching a miner remotely.
+use crate::{
+    engine::{self, stage::report::cycle, work::cpubound::NeededExtractions},
+    http::{self, Response},
+    persistence::{CrateVersionTable, Db, JournalTable, TableAccess, TaskTable},
+    stats, Result,
+};
+use serde_derive::Serialize;
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+/// The state of mining as served at `/status.json`
+#[derive(Debug, Serialize)]
+pub struct Status {
+    /// The amount of crates in the database
+    pub crates: u64,
+    /// The amount of crate versions in the database
+    pub crate_versions: u64,
+    /// Crate versions without any task yet, which are still to be processed
+    pub crate_versions_pending: u64,
+    /// The amount of tasks in the database, no matter their state
+    pub tasks: u64,
+    /// Tasks which were claimed by a miner, but whose outcome isn't stored yet
+    pub tasks_in_progress: u64,
+    /// The day crate versions were last fetched from the index, like `2020-01-31`
+    pub last_fetched_on: Option<String>,
+}
+
+/// Gather the status of mining into `db`
+pub fn status(db: &Db) -> Result<Status> {
+    let connection = db.open_connection_no_async_with_busy_wait()?;
+    let count = |query: &str| -> Result<u64> { Ok(connection.query_row(query, [], |r| r.get::<_, i64>(0))? as u64) };
+    let (context, last_fetched_on) = stats::context_totals(db)?;
+    Ok(Status {
+        crates: context.counts.crates as u64,
+        crate_versions: count(&format!("SELECT COUNT(*) FROM '{}'", CrateVersionTable::table_name()))?,
+        // Task keys start with the key of their crate version and a ':', and ';' is the next character after it
+        crate_versions_pending: count(&format!(
+            "SELECT COUNT(*) FROM '{versions}' AS v WHERE NOT EXISTS
+                (SELECT 1 FROM '{tasks}' WHERE key > v.key || ':' AND key < v.key || ';')",
+            versions = CrateVersionTable::table_name(),
+            tasks = TaskTable::table_name()
+        ))?,
+        tasks: count(&format!("SELECT COUNT(*) FROM '{}'", TaskTable::table_name()))?,
+        tasks_in_progress: count(&format!("SELECT COUNT(*) FROM '{}'", JournalTable::table_name()))?,
+        last_fetched_on,
+    })
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("json") => "application/json",
+        Some("css") => "text/css",
+        Some("js") => "application/javascript",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("toml") | Some("txt") => "text/plain; charset=utf-8",
+        _ => "application/octet-stream",
+    }
+}
+
+/// The file below `root` which is requested with `path`, or `None` if it points to a hidden or internal file or outside of it.
+/// Directories resolve to their `index.html`.
+fn file_below(root: &Path, path: &str) -> Option<PathBuf> {
+    let path = path.split(['?', '#']).next().unwrap_or_default();
+    let mut file = root.to_owned();
+    for component in path.split('/').filter(|c| !c.is_empty()) {
+        if component.starts_with('.') || component.starts_with("__") || component.contains('\\') {
+            return None;
+        }
+        file.push(component);
+    }
+    if file.is_dir() {
+        file.push("index.html");
+    }
+    Some(file)
+}
+
+fn respond(
+    path: Option<&str>,
+    db: &Db,
+    needed_extractions: Option<&NeededExtractions>,
+    reports_dir: &Path,
+) -> Response {
+    match path {
+        Some("/status.json") => match status(db).and_then(|s| Ok(serde_json::to_vec_pretty(&s)?)) {
+            Ok(status) => Response::ok("application/json", status),
+            Err(err) => Response::text("500 Internal Server Error", err.to_string()),
+        },
+        Some("/metrics") => match engine::metrics::render(db, needed_extractions) {
+            Ok(metrics) => Response::ok("text/plain; version=0.0.4", metrics),
+            Err(err) => Response::text("500 Internal Server Error", err.to_string()),
+        },
+        Some(path) => {
+            match file_below(&reports_dir.join(cycle::CURRENT), path).map(|file| (std::fs::read(&file), file)) {
+                Some((Ok(content), file)) => Response::ok(content_type(&file), content),
+                _ => Response::text("404 Not Found", format!("Nothing to see at '{}'", path)),
+            }
+        }
+        None => Response::text("400 Bad Request", "Expected a request like 'GET /status.json HTTP/1.1'"),
+    }
+}
+
+/// Serve the published reports in `reports_dir` along with the status of mining into `db` at `/status.json` and its
+/// metrics at `/metrics`, with a thread for each connection. Returns the address they are served at, which differs
+/// from `addr` if its port is 0. The queue depth of `needed_extractions` is only known if the miner runs in this process.
+pub fn serve(
+    addr: SocketAddr,
+    db: Db,
+    needed_extractions: Option<NeededExtractions>,
+    reports_dir: PathBuf,
+) -> Result<SocketAddr> {
+    http::serve(addr, "dashboard", move |path| {
+        respond(path, &db, needed_extractions.as_ref(), &reports_dir)
+    })
+}
+
+/// Serve the reports and status of the database at `db_path` at `addr` until the process is stopped, for miners
+/// running elsewhere on the same database. Fails if this version of criner can't read the database.
+pub fn run_blocking(db_path: impl AsRef<Path>, addr: SocketAddr) -> Result<()> {
+    let db_path = db_path.as_ref();
+    let db = Db::open(db_path)?;
+    db.compatibility()?.verify()?;
+    let addr = serve(addr, db, None, db_path.join("reports"))?;
+    log::info!("Serving reports and status at http://{}", addr);
+    loop {
+        std::thread::park();
+    }
+}
+
+#[cfg(test)]
+mod serve_test;
@@ -0,0 +1,74 @@
+use super::{file_below, serve};
+use crate::{
+    model::CrateVersion,
+    persistence::{temp_db::TempDb, TableAccess},
+};
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    path::Path,
+};
+
+fn get(addr: std::net::SocketAddr, path: &str) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(stream, "GET {} HTTP/1.1\r\nhost: localhost\r\n\r\n", path).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn reports_and_status_are_served() {
+    let db = TempDb::new("serve");
+    let dir = &db.dir;
+    let mut progress = prodash::Tree::new().add_child("serve");
+    for version in ["1.0.0", "1.1.0"] {
+        let version = CrateVersion {
+            name: "a".into(),
+            version: version.into(),
+            ..Default::default()
+        };
+        db.open_crate_versions()
+            .unwrap()
+            .insert(&mut progress, format!("a:{}", version.version), &version)
+            .unwrap();
+    }
+    db.open_tasks()
+        .unwrap()
+        .insert(&mut progress, "a:1.0.0:extract_crate:1.0.0", &Default::default())
+        .unwrap();
+    let reports = dir.join("reports");
+    let cycle = reports.join("cycles").join("1");
+    std::fs::create_dir_all(cycle.join("waste").join("a")).unwrap();
+    std::fs::write(cycle.join("waste").join("index.html"), "<h1>waste</h1>").unwrap();
+    std::fs::write(cycle.join("waste").join("a").join("api.json"), "{}").unwrap();
+    std::os::unix::fs::symlink(&cycle, reports.join("current")).unwrap();
+
+    let addr = serve("127.0.0.1:0".parse().unwrap(), db.db.clone(), None, reports).unwrap();
+    let response = get(addr, "/status.json");
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+    let status: serde_json::Value = serde_json::from_str(response.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+    assert_eq!(status["crate_versions"], 2);
+    assert_eq!(status["crate_versions_pending"], 1, "'a:1.1.0' has no task yet");
+    assert_eq!(status["tasks"], 1);
+    assert_eq!(status["tasks_in_progress"], 0);
+
+    let response = get(addr, "/waste/");
+    assert!(
+        response.contains("content-type: text/html; charset=utf-8\r\n"),
+        "{}",
+        response
+    );
+    assert!(response.ends_with("<h1>waste</h1>"));
+    assert!(get(addr, "/waste/a/api.json?v=1").contains("content-type: application/json\r\n"));
+    assert!(get(addr, "/waste/b/api.json").starts_with("HTTP/1.1 404 Not Found\r\n"));
+}
+
+#[test]
+fn files_outside_of_the_reports_or_internal_to_them_are_not_served() {
+    let root = Path::new("/reports");
+    assert_eq!(file_below(root, "/waste/a.html"), Some(root.join("waste/a.html")));
+    assert_eq!(file_below(root, "/waste/../../etc/passwd"), None);
+    assert_eq!(file_below(root, "/waste/__incremental_cache__/a"), None);
+    assert_eq!(file_below(root, "/.git/config"), None);
+}
@@ -0,0 +1,212 @@
+//! Publishing of database snapshots for others to `criner bootstrap` from or to explore with SQL
+use crate::{
+    engine::work::asset_store::{AssetStore, S3},
+    persistence,
+    registry::Registry,
+    utils, Error, Result,
+};
+use serde_derive::Serialize;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// The name of the snapshot in the export schema, see `criner export`
+pub const EXPORT_FILE_NAME: &str = "export.sqlite";
+/// The name of the file describing all files of a snapshot
+pub const MANIFEST_FILE_NAME: &str = "manifest.json";
+
+/// Configures what to snapshot, where to put it and how often
+pub struct Options {
+    /// The criner database directory to take the snapshot from
+    pub db_path: PathBuf,
+    /// A directory, or an `s3://bucket/prefix` URL to upload to, configured like the S3 asset store, see `S3::from_env()`
+    pub out: String,
+    /// If set, publish a snapshot every day at the given local time after publishing the first one right away
+    pub every_day_at: Option<time::Time>,
+    /// If set, the snapshot only holds the crates of this registry
+    pub registry: Option<Registry>,
+}
+
+#[derive(Serialize)]
+struct FileInfo {
+    name: String,
+    bytes: u64,
+    sha256: String,
+    /// The amount of rows per table
+    rows: BTreeMap<String, u64>,
+}
+
+#[derive(Serialize)]
+struct Manifest {
+    /// Seconds since unix epoch
+    created_at: u64,
+    criner_version: &'static str,
+    /// The registry whose crates the snapshot holds, or `None` if it holds those of all registries
+    registry: Option<String>,
+    files: Vec<FileInfo>,
+}
+
+fn sha256_of_file(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut std::fs::File::open(path)?, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+fn row_counts(sqlite_path: &Path) -> Result<BTreeMap<String, u64>> {
+    let connection = rusqlite::Connection::open(sqlite_path)?;
+    let mut statement = connection.prepare("SELECT name FROM sqlite_master WHERE type = 'table' ORDER BY name")?;
+    let tables = statement
+        .query_map([], |r| r.get::<_, String>(0))?
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    let mut counts = BTreeMap::new();
+    for table in tables {
+        let count: i64 = connection.query_row(&format!("SELECT COUNT(*) FROM '{}'", table), [], |r| r.get(0))?;
+        counts.insert(table, count as u64);
+    }
+    Ok(counts)
+}
+
+/// Write `<file>.sha256` in the format of `sha256sum` and describe the file for the manifest
+fn describe_and_write_checksum(path: &Path) -> Result<FileInfo> {
+    let name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .expect("file name we chose ourselves")
+        .to_owned();
+    let sha256 = sha256_of_file(path)?;
+    std::fs::write(
+        path.with_file_name(format!("{}.sha256", name)),
+        format!("{}  {}\n", sha256, name),
+    )?;
+    Ok(FileInfo {
+        bytes: std::fs::metadata(path)?.len(),
+        rows: row_counts(path)?,
+        name,
+        sha256,
+    })
+}
+
+/// Produce all files of a snapshot in `staging_dir`, which must be empty, with only the crates of `registry` if set.
+fn create(db_path: &Path, registry: Option<&Registry>, staging_dir: &Path) -> Result<()> {
+    let sqlite_path = staging_dir.join(persistence::SQLITE_FILE_NAME);
+    log::info!("Compacting database into '{}'", sqlite_path.display());
+    // Makes sure the database exists and has all tables.
+    persistence::Db::open(db_path)?;
+    rusqlite::Connection::open(db_path.join(persistence::SQLITE_FILE_NAME))?.execute(
+        "VACUUM INTO ?1",
+        [sqlite_path
+            .to_str()
+            .ok_or_else(|| Error::Message(format!("'{}' is not valid UTF-8", sqlite_path.display())))?],
+    )?;
+    let mut snapshot = rusqlite::Connection::open(&sqlite_path)?;
+    snapshot.execute_batch("PRAGMA journal_mode = DELETE")?;
+    if let Some(registry) = registry {
+        let transaction = snapshot.transaction()?;
+        let removed = registry.remove_other_registries(&transaction)?;
+        transaction.commit()?;
+        snapshot.execute_batch("VACUUM")?;
+        log::info!(
+            "Removed {} rows about crates of registries other than '{}'",
+            removed,
+            registry.name
+        );
+    }
+    drop(snapshot);
+
+    let export_path = staging_dir.join(EXPORT_FILE_NAME);
+    log::info!("Exporting database into '{}'", export_path.display());
+    crate::export::run_blocking(&sqlite_path, &export_path, crate::export::Format::Sqlite, None)?;
+    rusqlite::Connection::open(&export_path)?.execute_batch("VACUUM")?;
+
+    log::info!("Computing checksums and row counts");
+    let manifest = Manifest {
+        created_at: SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?.as_secs(),
+        criner_version: env!("CARGO_PKG_VERSION"),
+        registry: registry.map(|r| r.name.clone()),
+        files: vec![
+            describe_and_write_checksum(&sqlite_path)?,
+            describe_and_write_checksum(&export_path)?,
+        ],
+    };
+    std::fs::write(
+        staging_dir.join(MANIFEST_FILE_NAME),
+        serde_json::to_vec_pretty(&manifest)?,
+    )?;
+    Ok(())
+}
+
+/// Move all files of a snapshot from `staging_dir` to `out`, the manifest last, so that it only ever refers to complete files.
+fn publish(staging_dir: &Path, out: &str) -> Result<()> {
+    let s3 = out
+        .strip_prefix("s3://")
+        .map(|bucket_and_prefix| S3::from_env(bucket_and_prefix, staging_dir))
+        .transpose()?;
+    let mut names: Vec<_> = std::fs::read_dir(staging_dir)?
+        .map(|e| e.map(|e| e.file_name()))
+        .collect::<std::io::Result<_>>()?;
+    names.sort_by_key(|name| name == MANIFEST_FILE_NAME);
+    for name in names {
+        let source = staging_dir.join(&name);
+        match &s3 {
+            Some(s3) => {
+                log::info!(
+                    "Uploading '{}' to '{}/{}'",
+                    source.display(),
+                    out.trim_end_matches('/'),
+                    name.to_string_lossy()
+                );
+                // Large files are uploaded in parts, and removed once they are complete
+                s3.put(&source, &source)?;
+            }
+            None => {
+                std::fs::create_dir_all(out)?;
+                std::fs::rename(&source, Path::new(out).join(&name))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn create_and_publish(db_path: &Path, registry: Option<&Registry>, out: &str) -> Result<()> {
+    // Stage next to the output if possible to allow renaming files into place.
+    let staging_dir = if out.starts_with("s3://") {
+        db_path.join("snapshot.tmp")
+    } else {
+        Path::new(out).join(".snapshot.tmp")
+    };
+    if staging_dir.is_dir() {
+        std::fs::remove_dir_all(&staging_dir)?;
+    }
+    std::fs::create_dir_all(&staging_dir)?;
+    create(db_path, registry, &staging_dir)?;
+    publish(&staging_dir, out)?;
+    std::fs::remove_dir_all(&staging_dir)?;
+    log::info!("Published snapshot to '{}'", out);
+    Ok(())
+}
+
+/// Publish a snapshot, and keep publishing one every day if configured.
+pub fn publish_blocking(options: Options) -> Result<()> {
+    let Options {
+        db_path,
+        out,
+        every_day_at,
+        registry,
+    } = options;
+    if every_day_at.is_none() {
+        return create_and_publish(&db_path, registry.as_ref(), &out);
+    }
+    let root = prodash::Tree::new();
+    futures_lite::future::block_on(crate::spawn(utils::repeat_daily_at(
+        every_day_at,
+        move || root.add_child("publish snapshot"),
+        None,
+        move || {
+            let (db_path, registry, out) = (db_path.clone(), registry.clone(), out.clone());
+            blocking::unblock(move || create_and_publish(&db_path, registry.as_ref(), &out))
+        },
+    )))
+}
@@ -0,0 +1,169 @@
+//! Registry-wide statistics as JSON, for dashboards and monitoring.
+//!
+//! Everything is read from aggregates which are kept up to date while mining: the daily counts of new crates and
+//! versions, the incremental state of the waste report and the summary of the last run. Nothing scans all crates,
+//! so it's cheap enough to be asked for often.
+use crate::{
+    engine::report::waste::{self, Report},
+    model::Context,
+    persistence::{self, MetaTable, TableAccess},
+    Result,
+};
+use serde_derive::Serialize;
+use std::{io::Write, path::Path, time::SystemTime};
+
+/// The upper bounds of the buckets of the crate size distribution, in bytes
+const SIZE_BUCKETS: &[u64] = &[
+    10 * 1024,
+    100 * 1024,
+    1024 * 1024,
+    10 * 1024 * 1024,
+    100 * 1024 * 1024,
+    u64::MAX,
+];
+
+#[derive(Debug, Default, Serialize)]
+pub struct Stats {
+    /// The amount of crates in the database
+    pub crates: u64,
+    /// The amount of crate versions in the database
+    pub crate_versions: u64,
+    /// The day crate versions were last fetched from the index, like `2020-01-31`
+    pub last_fetched_on: Option<String>,
+    /// Totals of the waste report as of the last time it was generated
+    pub waste: Option<WasteTotals>,
+    /// How many crates have a given total size of all their versions, as of the last time the waste report was generated
+    pub crate_sizes: Vec<SizeBucket>,
+    /// What the last run of the miner got done
+    pub last_run: Option<Throughput>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct WasteTotals {
+    pub crates: u64,
+    pub total_bytes: u64,
+    pub total_files: u64,
+    pub wasted_bytes: u64,
+    pub wasted_files: u64,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct SizeBucket {
+    /// Crates whose versions have at most this many bytes in total, or `null` for the last bucket
+    pub up_to_bytes: Option<u64>,
+    pub crates: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Throughput {
+    /// Seconds since the unix epoch
+    pub started_at: u64,
+    /// Seconds since the unix epoch
+    pub finished_at: u64,
+    pub tasks_finished: u64,
+    pub tasks_failed: u64,
+    pub tasks_per_hour: f64,
+    pub bytes_downloaded: u64,
+}
+
+fn seconds_since_epoch(at: SystemTime) -> u64 {
+    at.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+/// The sum of the counts of all days in `db`, along with the last day anything was fetched on
+pub(crate) fn context_totals(db: &persistence::Db) -> Result<(Context, Option<String>)> {
+    let connection = db.open_connection_no_async_with_busy_wait()?;
+    // There is one row of counts per day, with what was added on that day
+    let mut statement = connection.prepare(&format!(
+        "SELECT key, data FROM {} WHERE {} ORDER BY key ASC",
+        MetaTable::table_name(),
+        persistence::CONTEXT_CONDITION
+    ))?;
+    let mut totals = (Context::default(), None);
+    for row in statement.query_map([], |r| Ok((r.get::<_, String>(0)?, r.get::<_, Vec<u8>>(1)?)))? {
+        let (day, context) = row?;
+        totals = (totals.0 + &Context::from(context.as_slice()), Some(day));
+    }
+    Ok(totals)
+}
+
+/// Gather the latest statistics of the database at `db_path`
+pub fn collect_blocking(db_path: impl AsRef<Path>) -> Result<Stats> {
+    let db_path = db_path.as_ref();
+    let db = persistence::Db::open(db_path)?;
+    let mut stats = Stats::default();
+
+    let (context, last_fetched_on) = context_totals(&db)?;
+    stats.crates = context.counts.crates as u64;
+    stats.crate_versions = context.counts.crate_versions;
+    stats.last_fetched_on = last_fetched_on;
+
+    let cache_dir = db_path
+        .join("reports")
+        .join(crate::engine::stage::report::cycle::CURRENT)
+        .join("waste")
+        .join("__incremental_cache__");
+    if let Some(Report::CrateCollection {
+        total_size_in_bytes,
+        total_files,
+        info_by_crate,
+        ..
+    }) = waste::read_latest_top_level_state(&cache_dir)
+    {
+        let mut totals = WasteTotals {
+            crates: info_by_crate.len() as u64,
+            total_bytes: total_size_in_bytes,
+            total_files,
+            ..Default::default()
+        };
+        stats.crate_sizes = SIZE_BUCKETS
+            .iter()
+            .map(|up_to| SizeBucket {
+                up_to_bytes: Some(*up_to).filter(|up_to| *up_to != u64::MAX),
+                crates: 0,
+            })
+            .collect();
+        for info in info_by_crate.values() {
+            totals.wasted_bytes += info.waste.total_bytes;
+            totals.wasted_files += info.waste.total_files;
+            let bucket = SIZE_BUCKETS
+                .iter()
+                .position(|up_to| info.all.total_bytes <= *up_to)
+                .expect("last bucket to take everything");
+            stats.crate_sizes[bucket].crates += 1;
+        }
+        stats.waste = Some(totals);
+    }
+
+    stats.last_run = db.open_run_summaries()?.most_recent()?.map(|run| {
+        let hours = run
+            .finished_at
+            .duration_since(run.started_at)
+            .unwrap_or_default()
+            .as_secs_f64()
+            / 3600.0;
+        Throughput {
+            started_at: seconds_since_epoch(run.started_at),
+            finished_at: seconds_since_epoch(run.finished_at),
+            tasks_finished: run.tasks_finished,
+            tasks_failed: run.tasks_failed,
+            tasks_per_hour: if hours > 0.0 {
+                run.tasks_finished as f64 / hours
+            } else {
+                0.0
+            },
+            bytes_downloaded: run.bytes_downloaded,
+        }
+    });
+    Ok(stats)
+}
+
+/// Write the latest statistics of the database at `db_path` as JSON to `out`
+pub fn write_blocking(db_path: impl AsRef<Path>, mut out: impl Write) -> Result<()> {
+    serde_json::to_writer_pretty(&mut out, &collect_blocking(db_path)?)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod stats_test;
@@ -0,0 +1,90 @@
+use crate::{
+    engine::report::waste::{AggregateFileInfo, Report, VersionInfo},
+    model::RunSummary,
+    persistence::temp_db::TempDb,
+    stats::collect_blocking,
+};
+use std::time::{Duration, SystemTime};
+
+#[test]
+fn stats_are_collected_from_aggregates() {
+    let db = TempDb::new("stats");
+    let dir = &db.dir;
+
+    let empty = collect_blocking(dir).unwrap();
+    assert_eq!(empty.crates, 0);
+    assert!(empty.waste.is_none() && empty.last_run.is_none() && empty.last_fetched_on.is_none());
+
+    db.open_context()
+        .unwrap()
+        .update_today(|c| {
+            c.counts.crates += 2;
+            c.counts.crate_versions += 5;
+        })
+        .unwrap();
+    let started_at = SystemTime::UNIX_EPOCH + Duration::from_secs(3600);
+    db.open_run_summaries()
+        .unwrap()
+        .record(
+            &mut prodash::Tree::new().add_child("record"),
+            &RunSummary {
+                started_at,
+                finished_at: started_at + Duration::from_secs(1800),
+                tasks_finished: 10,
+                bytes_downloaded: 1024,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    let crate_info = |total_bytes, wasted_bytes| VersionInfo {
+        all: AggregateFileInfo {
+            total_bytes,
+            total_files: 2,
+        },
+        waste: AggregateFileInfo {
+            total_bytes: wasted_bytes,
+            total_files: 1,
+        },
+        waste_latest_version: None,
+        potential_gains: None,
+        wasted_bytes_per_day: None,
+    };
+    let cache_dir = dir
+        .join("reports")
+        .join("current")
+        .join("waste")
+        .join("__incremental_cache__");
+    std::fs::create_dir_all(&cache_dir).unwrap();
+    std::fs::write(
+        cache_dir.join("__top-level-report__-waste-1.1.0.rmp"),
+        rmp_serde::to_vec(&Report::CrateCollection {
+            total_size_in_bytes: 2_000_000,
+            total_files: 4,
+            info_by_crate: vec![
+                ("small".to_string(), crate_info(1000, 10)),
+                ("large".to_string(), crate_info(1_999_000, 500)),
+            ]
+            .into_iter()
+            .collect(),
+            wasted_by_extension: Default::default(),
+        })
+        .unwrap(),
+    )
+    .unwrap();
+
+    let stats = collect_blocking(dir).unwrap();
+    assert_eq!((stats.crates, stats.crate_versions), (2, 5));
+    assert!(stats.last_fetched_on.is_some());
+    let waste = stats.waste.expect("waste totals");
+    assert_eq!(
+        (waste.crates, waste.total_bytes, waste.wasted_bytes, waste.wasted_files),
+        (2, 2_000_000, 510, 2)
+    );
+    assert_eq!(
+        stats.crate_sizes.iter().map(|b| b.crates).collect::<Vec<_>>(),
+        vec![1, 0, 0, 1, 0, 0]
+    );
+    assert_eq!(stats.crate_sizes.last().unwrap().up_to_bytes, None);
+    let last_run = stats.last_run.expect("a run");
+    assert_eq!((last_run.tasks_finished, last_run.tasks_per_hour), (10, 20.0));
+}
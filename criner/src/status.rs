@@ -0,0 +1,84 @@
+//! A summary of the state of a database, for checking on it without starting the TUI
+use crate::{
+    engine::summary,
+    persistence::{self, CrateTable, CrateVersionTable, TableAccess, TaskResultTable, TaskTable},
+    registry::Registry,
+    Result,
+};
+use std::{io::Write, path::Path};
+
+fn count(connection: &rusqlite::Connection, table_name: &str, registry: &Registry) -> Result<u64> {
+    let (condition, values) = registry.key_condition();
+    Ok(connection.query_row(
+        &format!("SELECT COUNT(*) FROM '{}' WHERE {}", table_name, condition),
+        rusqlite::params_from_iter(values),
+        |r| r.get::<_, i64>(0),
+    )? as u64)
+}
+
+/// Write how many crates, versions, tasks and results of `registry` are stored in the database at `db_path` to `out`,
+/// along with the outcome of the most recent check for gaps between the index of `registry` and the database and the
+/// summary of the last run of the miner.
+pub fn write_blocking(db_path: impl AsRef<Path>, registry: &Registry, mut out: impl Write) -> Result<()> {
+    let db = persistence::Db::open(db_path)?;
+    let connection = db.open_connection_no_async_with_busy_wait()?;
+    writeln!(out, "Database, crates of {}", registry.name)?;
+    writeln!(
+        out,
+        "  crates:         {}",
+        count(&connection, CrateTable::table_name(), registry)?
+    )?;
+    writeln!(
+        out,
+        "  crate versions: {}",
+        count(&connection, CrateVersionTable::table_name(), registry)?
+    )?;
+    writeln!(
+        out,
+        "  tasks:          {}",
+        count(&connection, TaskTable::table_name(), registry)?
+    )?;
+    writeln!(
+        out,
+        "  task results:   {}",
+        count(&connection, TaskResultTable::table_name(), registry)?
+    )?;
+
+    writeln!(out, "Index check")?;
+    match db.open_index_checks()?.most_recent(registry)? {
+        None => writeln!(out, "  never ran, it runs once a day as part of 'criner mine'")?,
+        Some(check) => {
+            writeln!(
+                out,
+                "  checked at {} against index commit {}",
+                humantime::format_rfc3339_seconds(check.checked_at),
+                check.index_commit
+            )?;
+            writeln!(out, "  crate versions in index:        {}", check.index_versions)?;
+            writeln!(out, "  missing in database:            {}", check.num_missing)?;
+            writeln!(out, "  repaired:                       {}", check.repaired)?;
+            writeln!(out, "  in database, but not in index:  {}", check.num_unknown_to_index)?;
+            if !check.missing.is_empty() {
+                writeln!(
+                    out,
+                    "  first missing crate versions{}:",
+                    if check.num_missing > check.missing.len() as u64 {
+                        format!(" ({} of {})", check.missing.len(), check.num_missing)
+                    } else {
+                        String::new()
+                    }
+                )?;
+                for key in check.missing.iter() {
+                    writeln!(out, "    {}", key)?;
+                }
+            }
+        }
+    }
+
+    writeln!(out, "Last run")?;
+    match db.open_run_summaries()?.most_recent()? {
+        None => writeln!(out, "  no run finished yet")?,
+        Some(run) => summary::write(&run, &mut out)?,
+    }
+    Ok(())
+}
@@ -0,0 +1,152 @@
+//! Reconstructing what happened to a crate version from recorded task events and error telemetry
+use crate::{
+    model::{CrateVersion, Task, TaskState},
+    persistence::{self, key_prefix_upper_bound, ErrorTelemetryTable, TableAccess, TaskTable, KEY_SEP_CHAR},
+    registry::Registry,
+    Result,
+};
+use rusqlite::params;
+use std::{
+    io::Write,
+    path::Path,
+    time::{Duration, UNIX_EPOCH},
+};
+
+enum Entry {
+    Event(persistence::TaskEvent),
+    /// Milliseconds since epoch, category, key and message
+    Error(i64, String, String, String),
+}
+
+impl Entry {
+    fn occurred_at_ms(&self) -> i64 {
+        match self {
+            Entry::Event(e) => e.occurred_at_ms,
+            Entry::Error(at, ..) => *at,
+        }
+    }
+}
+
+fn format_ms(ms: i64) -> String {
+    humantime::format_rfc3339_millis(UNIX_EPOCH + Duration::from_millis(ms as u64)).to_string()
+}
+
+/// Errors recorded for keys starting with `prefix`, as milliseconds since epoch, category, key and message
+fn errors_with_key_prefix(connection: &rusqlite::Connection, prefix: &str) -> Result<Vec<Entry>> {
+    let mut statement = connection.prepare(&format!(
+        "SELECT occurred_at, category, key, message FROM {} WHERE key >= ?1 AND key < ?2",
+        ErrorTelemetryTable::table_name()
+    ))?;
+    let errors = statement
+        .query_map(params![prefix, key_prefix_upper_bound(prefix)], |r| {
+            Ok(Entry::Error(
+                r.get::<_, i64>(0)? * 1000,
+                r.get(1)?,
+                r.get(2)?,
+                r.get(3)?,
+            ))
+        })?
+        .collect::<std::result::Result<_, _>>()?;
+    Ok(errors)
+}
+
+pub(crate) fn tasks_with_key_prefix(connection: &rusqlite::Connection, prefix: &str) -> Result<Vec<(String, Task)>> {
+    let mut statement = connection.prepare(&format!(
+        "SELECT key, data FROM {} WHERE key >= ?1 AND key < ?2 ORDER BY key",
+        TaskTable::table_name()
+    ))?;
+    let tasks = statement
+        .query_map(params![prefix, key_prefix_upper_bound(prefix)], |r| {
+            Ok((r.get::<_, String>(0)?, Task::from(r.get::<_, Vec<u8>>(1)?.as_slice())))
+        })?
+        .collect::<std::result::Result<_, _>>()?;
+    Ok(tasks)
+}
+
+fn describe_state(state: &TaskState) -> String {
+    match state {
+        TaskState::NotStarted => "not started".into(),
+        TaskState::Complete => "complete".into(),
+        TaskState::InProgress(_) => "in progress".into(),
+        TaskState::AttemptsWithFailure(errors) => format!(
+            "failed {} time(s), last with: {}",
+            errors.len(),
+            errors.last().map(String::as_str).unwrap_or("unknown error")
+        ),
+        TaskState::PermanentFailure(errors) => format!(
+            "failed for good after {} time(s), last with: {}",
+            errors.len(),
+            errors.last().map(String::as_str).unwrap_or("unknown error")
+        ),
+    }
+}
+
+/// Write the timeline of all task events and errors of `crate_name` of `registry` at `crate_version` to `out`, followed
+/// by the current state of each task. Events are kept for the most recent `TaskEventTable::MAX_EVENTS` only.
+pub fn write_blocking(
+    db_path: impl AsRef<Path>,
+    registry: &Registry,
+    crate_name: &str,
+    crate_version: &str,
+    mut out: impl Write,
+) -> Result<()> {
+    let db = persistence::Db::open(db_path)?;
+    let mut prefix = String::new();
+    CrateVersion::key_from(&registry.qualify(crate_name), crate_version, &mut prefix);
+    prefix.push(KEY_SEP_CHAR);
+
+    let mut entries: Vec<_> = db
+        .open_task_events()?
+        .with_key_prefix(&prefix)?
+        .into_iter()
+        .map(Entry::Event)
+        .collect();
+    let connection = db.open_connection_no_async_with_busy_wait()?;
+    entries.extend(errors_with_key_prefix(&connection, &prefix)?);
+    // Stable, so events recorded within the same millisecond keep their order
+    entries.sort_by_key(Entry::occurred_at_ms);
+
+    writeln!(out, "Timeline of {} {}", crate_name, crate_version)?;
+    if entries.is_empty() {
+        writeln!(out, "  no events recorded")?;
+    }
+    for entry in entries.iter() {
+        match entry {
+            Entry::Event(e) => writeln!(
+                out,
+                "  {}  {:<24} {}{}{}",
+                format_ms(e.occurred_at_ms),
+                e.key.trim_start_matches(prefix.as_str()),
+                e.event,
+                e.duration
+                    .map(|d| format!(" after {}", humantime::format_duration(d)))
+                    .unwrap_or_default(),
+                e.message.as_ref().map(|m| format!(": {}", m)).unwrap_or_default()
+            )?,
+            Entry::Error(at, category, key, message) => writeln!(
+                out,
+                "  {}  {:<24} {} error: {}",
+                format_ms(*at),
+                key.trim_start_matches(prefix.as_str()),
+                category,
+                message
+            )?,
+        }
+    }
+
+    writeln!(out, "Current state")?;
+    let tasks = tasks_with_key_prefix(&connection, &prefix)?;
+    if tasks.is_empty() {
+        writeln!(out, "  no tasks")?;
+    }
+    for (key, task) in tasks {
+        writeln!(
+            out,
+            "  {:<24} {} (stored at {})",
+            key.trim_start_matches(prefix.as_str()),
+            describe_state(&task.state),
+            humantime::format_rfc3339_seconds(task.stored_at)
+        )?;
+    }
+    Ok(())
+}
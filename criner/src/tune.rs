@@ -0,0 +1,224 @@
+use crate::{
+    engine::work::iobound,
+    model,
+    persistence::{Db, Durability, TableAccess},
+    registry::Registry,
+    Error, Result,
+};
+use bytesize::ByteSize;
+use std::{
+    fmt,
+    io::Read,
+    time::{Duration, SystemTime},
+};
+
+/// The crate archive downloaded to learn about the network when tuning for crates.io without a URL
+pub const CRATES_IO_SAMPLE_URL: &str = "https://static.crates.io/crates/serde/serde-1.0.104.crate";
+
+/// Configures the workload used to calibrate the settings
+pub struct Options {
+    /// The registry to recommend settings for mining
+    pub registry: Registry,
+    /// The URL of a crate archive which is downloaded repeatedly to learn about the network.
+    /// It must be set for registries other than crates.io, for which it defaults to `CRATES_IO_SAMPLE_URL`.
+    pub url: Option<String>,
+    /// The maximum amount of concurrent downloads to try
+    pub max_io_bound_processors: u32,
+    /// The amount of tasks to write per durability setting
+    pub db_writes: usize,
+}
+
+struct DownloadMeasurement {
+    concurrency: u32,
+    bytes_per_second: f64,
+}
+
+/// Settings for `criner mine` which seem to work best on this machine and network
+pub struct Recommendation {
+    pub io_bound_processors: u32,
+    pub cpu_bound_processors: u32,
+    pub durability: Durability,
+    downloads: Vec<DownloadMeasurement>,
+    extractions_per_second: f64,
+    writes_per_second: Vec<(Durability, f64)>,
+}
+
+impl fmt::Display for Recommendation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Measurements")?;
+        for m in self.downloads.iter() {
+            writeln!(
+                f,
+                "  {:>3} concurrent downloads: {}/s",
+                m.concurrency,
+                ByteSize(m.bytes_per_second as u64)
+            )?;
+        }
+        writeln!(f, "  extractions per core: {:.1}/s", self.extractions_per_second)?;
+        for (durability, writes) in self.writes_per_second.iter() {
+            writeln!(f, "  database writes with durability {:?}: {:.0}/s", durability, writes)?;
+        }
+        writeln!(f, "Recommendation")?;
+        writeln!(f, "  --io {}", self.io_bound_processors)?;
+        writeln!(f, "  --cpu {}", self.cpu_bound_processors)?;
+        writeln!(f, "  --durability {}", durability_arg(self.durability))
+    }
+}
+
+fn durability_arg(d: Durability) -> &'static str {
+    match d {
+        Durability::Full => "full",
+        Durability::Relaxed => "relaxed",
+        Durability::None => "none",
+    }
+}
+
+fn per_second(amount: f64, elapsed: Duration) -> f64 {
+    amount / elapsed.as_secs_f64().max(f64::EPSILON)
+}
+
+async fn download(client: &reqwest::Client, url: &str) -> Result<Vec<u8>> {
+    let response = client.get(url).send().await?;
+    if !response.status().is_success() {
+        return Err(Error::HttpStatus(response.status()));
+    }
+    Ok(response.bytes().await?.to_vec())
+}
+
+async fn measure_downloads(
+    client: &reqwest::Client,
+    url: &str,
+    max_io_bound_processors: u32,
+) -> Result<(Vec<u8>, Vec<DownloadMeasurement>)> {
+    let sample = download(client, url).await?;
+    let mut measurements = Vec::new();
+    let mut concurrency = 1;
+    while concurrency <= max_io_bound_processors.max(1) {
+        let start = SystemTime::now();
+        let bytes: usize = futures_util::future::try_join_all((0..concurrency).map(|_| download(client, url)))
+            .await?
+            .iter()
+            .map(Vec::len)
+            .sum();
+        let bytes_per_second = per_second(bytes as f64, start.elapsed()?);
+        log::info!(
+            "{} concurrent downloads: {}/s",
+            concurrency,
+            ByteSize(bytes_per_second as u64)
+        );
+        measurements.push(DownloadMeasurement {
+            concurrency,
+            bytes_per_second,
+        });
+        concurrency *= 2;
+    }
+    Ok((sample, measurements))
+}
+
+fn measure_extraction(crate_archive: &[u8]) -> Result<f64> {
+    let rounds = 10;
+    let mut buf = Vec::new();
+    let start = SystemTime::now();
+    for _ in 0..rounds {
+        let mut archive = tar::Archive::new(libflate::gzip::Decoder::new(crate_archive)?);
+        for e in archive.entries()? {
+            buf.clear();
+            e?.read_to_end(&mut buf)?;
+        }
+    }
+    Ok(per_second(rounds as f64, start.elapsed()?))
+}
+
+fn measure_db_writes(registry: &Registry, db_writes: usize) -> Result<Vec<(Durability, f64)>> {
+    let root = prodash::Tree::new();
+    let mut progress = root.add_child("does not matter");
+    let mut results = Vec::new();
+    for durability in [Durability::Full, Durability::Relaxed, Durability::None].iter() {
+        let path = std::env::temp_dir().join(format!("criner-tune-{}.db", std::process::id()));
+        let db = Db::open_with_durability(&path, *durability)?;
+        let tasks = db.open_tasks()?;
+        let mut task = iobound::default_persisted_download_task();
+        task.state = model::TaskState::AttemptsWithFailure(vec!["a typical error message of moderate length".into()]);
+        let mut key = String::new();
+        let crate_name = registry.qualify("some-crate-name");
+        let start = SystemTime::now();
+        for id in 0..db_writes {
+            key.clear();
+            task.fq_key(&crate_name, &format!("0.{}.0", id), &mut key);
+            tasks.insert(&mut progress, &key, &task)?;
+        }
+        let writes_per_second = per_second(db_writes as f64, start.elapsed()?);
+        log::info!(
+            "database writes with durability {:?}: {:.0}/s",
+            durability,
+            writes_per_second
+        );
+        results.push((*durability, writes_per_second));
+        drop(tasks);
+        std::fs::remove_dir_all(path)?;
+    }
+    Ok(results)
+}
+
+/// Run a short workload of downloads, extractions and database writes and derive settings from it.
+pub fn run_blocking(options: Options) -> Result<Recommendation> {
+    let Options {
+        registry,
+        url,
+        max_io_bound_processors,
+        db_writes,
+    } = options;
+    let url = match url {
+        Some(url) => url,
+        None if registry.is_crates_io() => CRATES_IO_SAMPLE_URL.into(),
+        None => {
+            return Err(Error::Message(format!(
+                "Crates of registry '{}' aren't on crates.io - provide the URL of one of its crate archives",
+                registry.name
+            )))
+        }
+    };
+    let client = reqwest::ClientBuilder::new().gzip(true).build()?;
+    let (sample, downloads) = futures_lite::future::block_on(crate::spawn(async move {
+        measure_downloads(&client, &url, max_io_bound_processors).await
+    }))?;
+    let writes_per_second = measure_db_writes(&registry, db_writes)?;
+    let extractions_per_second = measure_extraction(&sample)?;
+
+    // Add processors as long as they buy us at least 10% more throughput.
+    let mut best = &downloads[0];
+    for m in downloads.iter().skip(1) {
+        if m.bytes_per_second > best.bytes_per_second * 1.1 {
+            best = m;
+        }
+    }
+    let downloads_per_second = best.bytes_per_second / sample.len().max(1) as f64;
+    let num_cores = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1) as u32;
+    let cpu_bound_processors = ((downloads_per_second / extractions_per_second.max(f64::EPSILON)).ceil() as u32)
+        .max(1)
+        .min(num_cores);
+
+    let write_rate = |d: Durability| {
+        writes_per_second
+            .iter()
+            .find(|(durability, _)| *durability == d)
+            .map(|(_, w)| *w)
+            .unwrap_or_default()
+    };
+    let durability = if write_rate(Durability::Relaxed) < downloads_per_second * 2.0
+        && write_rate(Durability::None) > write_rate(Durability::Relaxed) * 2.0
+    {
+        Durability::None
+    } else {
+        Durability::Relaxed
+    };
+
+    Ok(Recommendation {
+        io_bound_processors: best.concurrency,
+        cpu_bound_processors,
+        durability,
+        downloads,
+        extractions_per_second,
+        writes_per_second,
+    })
+}
@@ -5,24 +5,100 @@ use futures_util::{
     future::{self, Either},
     FutureExt,
 };
+use once_cell::sync::Lazy;
 use std::{
     convert::TryInto,
     future::Future,
+    path::Path,
     time::{Duration, SystemTime},
 };
 
-pub fn parse_semver(version: &str) -> Semver {
+/// Parse `version` as semantic version, falling back to lenient parsing for versions which don't quite follow
+/// the specification, like `1.0.0-beta.01` or `1.2`. Pre-release and build metadata are dropped in that case.
+pub fn parse_semver(version: &str) -> Result<Semver> {
     use std::str::FromStr;
-    Semver::from_str(version)
-        .or_else(|_| {
-            Semver::from_str(
-                &version[..version
-                    .find('-')
-                    .or_else(|| version.find('+'))
-                    .expect("some prerelease version")],
-            )
-        })
-        .expect("semver parsing to work if violating prerelease versions are stripped")
+    Semver::from_str(version).or_else(|_| {
+        let core = &version[..version.find(['-', '+']).unwrap_or(version.len())];
+        let mut components = core.trim().split('.').map(|c| c.parse::<u64>().ok());
+        match (
+            components.next().flatten(),
+            components.next(),
+            components.next(),
+            components.next(),
+        ) {
+            (Some(major), minor, patch, None) => Semver::from_str(&format!(
+                "{}.{}.{}",
+                major,
+                minor
+                    .unwrap_or(Some(0))
+                    .ok_or_else(|| Error::InvalidVersion(version.to_owned()))?,
+                patch
+                    .unwrap_or(Some(0))
+                    .ok_or_else(|| Error::InvalidVersion(version.to_owned()))?
+            ))
+            .map_err(|_| Error::InvalidVersion(version.to_owned())),
+            _ => Err(Error::InvalidVersion(version.to_owned())),
+        }
+    })
+}
+
+/// While a file of this name exists in the working directory, stages finish the item at hand and then wait for it to be removed
+pub const PAUSE_FILE: &str = "criner.pause";
+const PAUSE_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+pub fn is_paused() -> bool {
+    std::path::Path::new(PAUSE_FILE).exists()
+}
+
+/// The TUI shows the pipeline state of the crate whose name is in a file of this name in the working directory.
+/// It's a file as the `prodash` TUI reads all keys from the terminal itself and doesn't pass them on.
+pub const INSPECT_FILE: &str = "criner.inspect";
+
+/// The name of the crate to inspect, as written to `INSPECT_FILE`
+pub fn inspected_crate() -> Option<String> {
+    std::fs::read_to_string(INSPECT_FILE)
+        .ok()
+        .and_then(|content| crate_name_to_inspect(&content))
+}
+
+/// The crate name in `content` of the `INSPECT_FILE`, ignoring surrounding whitespace like the newline of `echo`
+pub(crate) fn crate_name_to_inspect(content: &str) -> Option<String> {
+    Some(content.trim())
+        .filter(|name| !name.is_empty())
+        .map(ToOwned::to_owned)
+}
+
+/// Globs of files to select when extracting crates in addition to those given with `--select-globs`, one per line,
+/// are read from a file of this name in the working directory when mining starts
+pub const SELECT_GLOBS_FILE: &str = "criner.select-globs";
+
+/// The globs in `SELECT_GLOBS_FILE`, skipping empty lines and comments starting with `#`
+pub fn select_globs_from_file() -> Result<Vec<String>> {
+    match std::fs::read_to_string(SELECT_GLOBS_FILE) {
+        Ok(content) => Ok(content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(ToOwned::to_owned)
+            .collect()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Return once `PAUSE_FILE` doesn't exist, which is immediately unless criner was paused.
+pub async fn wait_while_paused(progress: &mut prodash::tree::Item, deadline: Option<SystemTime>) -> Result<()> {
+    if !is_paused() {
+        return Ok(());
+    }
+    progress.info(format!("Paused until '{}' is removed", PAUSE_FILE));
+    while is_paused() {
+        progress.halted("paused", None);
+        Timer::after(PAUSE_CHECK_INTERVAL).await;
+        check(deadline)?;
+    }
+    progress.info("Resumed");
+    Ok(())
 }
 
 pub async fn wait_with_progress(
@@ -84,6 +160,9 @@ where
     let time = desired_launch_at(time).time();
     loop {
         iteration += 1;
+        if is_paused() {
+            wait_while_paused(&mut make_progress(), deadline).await?;
+        }
         if let Err(err) = make_future().await {
             make_progress().fail(format!(
                 "{} : ignored by repeat_daily_at('{:?}',…) iteration {}",
@@ -112,13 +191,16 @@ where
     MakeFut: FnMut() -> Fut,
     MakeProgress: FnMut() -> prodash::tree::Item,
 {
-    let max_iterations = at_most.unwrap_or(std::usize::MAX);
+    let max_iterations = at_most.unwrap_or(usize::MAX);
     let mut iteration = 0;
     loop {
         if iteration == max_iterations {
             return Ok(());
         }
         iteration += 1;
+        if is_paused() {
+            wait_while_paused(&mut make_progress(), deadline).await?;
+        }
         if let Err(err) = make_future().await {
             make_progress().fail(format!(
                 "{} : ignored by repeat_every({}s,…) iteration {}",
@@ -132,6 +214,17 @@ where
     }
 }
 
+/// Write `contents` to a new file which then replaces the one at `path`, instead of overwriting the file in place.
+/// That way hard-links to the previous file keep their content, and readers never see a partially written file.
+pub fn write_replacing(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> std::io::Result<()> {
+    let path = path.as_ref();
+    let mut tmp_name = path.file_name().unwrap_or_default().to_owned();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+    std::fs::write(&tmp_path, contents)?;
+    std::fs::rename(tmp_path, path)
+}
+
 pub fn check(deadline: Option<SystemTime>) -> Result<()> {
     deadline
         .map(|d| {
@@ -148,12 +241,16 @@ pub async fn handle_ctrl_c_and_sigterm<F, T>(f: F) -> Result<T>
 where
     F: Future<Output = T> + Unpin,
 {
-    let (s, r) = async_channel::bounded(100);
-    ctrlc::set_handler(move || {
-        s.send(()).now_or_never();
-    })
-    .ok();
-    let selector = future::select(async move { r.recv().await }.boxed_local(), f);
+    // The handler can only be set once per process, so it is shared by all engines running in it, one after another
+    static INTERRUPTS: Lazy<async_channel::Receiver<()>> = Lazy::new(|| {
+        let (s, r) = async_channel::bounded(100);
+        ctrlc::set_handler(move || {
+            s.send(()).now_or_never();
+        })
+        .ok();
+        r
+    });
+    let selector = future::select(async move { INTERRUPTS.recv().await }.boxed_local(), f);
     match selector.await {
         Either::Left((_, _f)) => Err(Error::Interrupted),
         Either::Right((r, _interrupt)) => Ok(r),
@@ -192,3 +289,6 @@ where
         Either::Right((res, _delay)) => Ok(res),
     }
 }
+
+#[cfg(test)]
+mod parse_semver_test;
@@ -0,0 +1,29 @@
+use crate::utils::parse_semver;
+
+#[test]
+fn valid_versions_parse_strictly() {
+    let v = parse_semver("1.2.3-beta.1+build").unwrap();
+    assert_eq!((v.major(), v.minor(), v.patch()), (1, 2, 3));
+    assert_eq!(v.pre_release(), Some("beta.1"));
+}
+
+#[test]
+fn odd_versions_parse_leniently() {
+    for (version, expected) in &[
+        ("1.0.0-beta.01", (1, 0, 0)),
+        ("0.1.0-α", (0, 1, 0)),
+        ("1.2", (1, 2, 0)),
+        ("3", (3, 0, 0)),
+        ("01.2.3", (1, 2, 3)),
+    ] {
+        let v = parse_semver(version).unwrap_or_else(|err| panic!("{}: {}", version, err));
+        assert_eq!((v.major(), v.minor(), v.patch()), *expected, "{}", version);
+    }
+}
+
+#[test]
+fn garbage_is_an_error_and_never_panics() {
+    for version in &["", "-", "+", "x.y.z", "1.2.3.4", "1..2", "é"] {
+        assert!(parse_semver(version).is_err(), "{}", version);
+    }
+}
@@ -0,0 +1,120 @@
+//! Bootstrapping databases from snapshots served by `criner::mock_network`, including resuming interrupted downloads.
+//! Run with `cargo test --features mock-network`.
+use criner::{
+    bootstrap::{run_blocking, Options},
+    mock_network::Network,
+    Error,
+};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+const SNAPSHOT_PATH: &str = "/snapshots/db.msgpack.sqlite";
+
+struct Fixture {
+    dir: PathBuf,
+    network: Network,
+    snapshot: Vec<u8>,
+}
+
+impl Fixture {
+    /// Serve a snapshot of a small database along with its checksum file
+    fn new(name: &str) -> Fixture {
+        let dir = std::env::temp_dir().join(format!("criner-bootstrap-test-{}-{}", name, std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        let network = Network::start("mock", dir.join("registry-index"), &[]).unwrap();
+
+        let snapshot_path = dir.join("snapshot.sqlite");
+        rusqlite::Connection::open(&snapshot_path)
+            .unwrap()
+            .execute_batch("CREATE TABLE notes (text TEXT); INSERT INTO notes VALUES ('from the snapshot');")
+            .unwrap();
+        let snapshot = std::fs::read(&snapshot_path).unwrap();
+        network.serve_file(SNAPSHOT_PATH, snapshot.clone());
+        network.serve_file(
+            &format!("{}.sha256", SNAPSHOT_PATH),
+            format!("{}  db.msgpack.sqlite\n", hex::encode(Sha256::digest(&snapshot))),
+        );
+        Fixture { dir, network, snapshot }
+    }
+
+    fn db_path(&self) -> PathBuf {
+        self.dir.join("criner.db")
+    }
+
+    fn partial_path(&self) -> PathBuf {
+        self.db_path().join("db.msgpack.sqlite.part")
+    }
+
+    fn bootstrap(&self, sha256: Option<String>) -> criner::Result<()> {
+        run_blocking(Options {
+            url: format!("{}{}", self.network.base_url(), SNAPSHOT_PATH),
+            sha256,
+            db_path: self.db_path(),
+            registry: None,
+        })
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        std::fs::remove_dir_all(&self.dir).ok();
+    }
+}
+
+fn notes(db_path: &Path) -> String {
+    rusqlite::Connection::open(db_path.join("db.msgpack.sqlite"))
+        .unwrap()
+        .query_row("SELECT text FROM notes", [], |r| r.get(0))
+        .unwrap()
+}
+
+#[test]
+fn interrupted_downloads_are_resumed_and_verified_with_the_published_checksum() {
+    let fixture = Fixture::new("resume");
+    std::fs::create_dir_all(fixture.db_path()).unwrap();
+    std::fs::write(fixture.partial_path(), &fixture.snapshot[..fixture.snapshot.len() / 2]).unwrap();
+
+    fixture.bootstrap(None).unwrap();
+    assert_eq!(notes(&fixture.db_path()), "from the snapshot");
+    assert!(
+        !fixture.partial_path().exists(),
+        "the partial download became the database"
+    );
+    assert_eq!(
+        fixture.network.requests(),
+        vec![format!("{}.sha256", SNAPSHOT_PATH), SNAPSHOT_PATH.to_string()]
+    );
+
+    assert!(
+        matches!(fixture.bootstrap(None), Err(Error::Message(_))),
+        "existing databases are never replaced"
+    );
+}
+
+#[test]
+fn completely_downloaded_snapshots_are_not_downloaded_again() {
+    let fixture = Fixture::new("complete");
+    std::fs::create_dir_all(fixture.db_path()).unwrap();
+    std::fs::write(fixture.partial_path(), &fixture.snapshot).unwrap();
+
+    fixture
+        .bootstrap(Some(hex::encode(Sha256::digest(&fixture.snapshot)).to_uppercase()))
+        .unwrap();
+    assert_eq!(notes(&fixture.db_path()), "from the snapshot");
+    assert_eq!(fixture.network.requests(), vec![SNAPSHOT_PATH.to_string()]);
+}
+
+#[test]
+fn snapshots_not_matching_their_checksum_are_removed() {
+    let fixture = Fixture::new("mismatch");
+    assert!(matches!(
+        fixture.bootstrap(Some("0".repeat(64))),
+        Err(Error::ChecksumMismatch(_, _))
+    ));
+    assert!(!fixture.partial_path().exists(), "the next attempt starts from scratch");
+    assert!(!fixture.db_path().join("db.msgpack.sqlite").exists());
+
+    fixture.bootstrap(None).unwrap();
+    assert_eq!(notes(&fixture.db_path()), "from the snapshot");
+}
@@ -0,0 +1,293 @@
+//! Mining a registry served by `criner::mock_network` from fetching its index to writing reports, to catch regressions
+//! of the engine as a whole. Run with `cargo test --features mock-network`.
+use criner::{
+    mock_network::{Crate, Network},
+    run::{self, Allowlist, BackendKind, Durability, GlobStageRunSettings, NetworkSettings, Stage, StageRunSettings},
+};
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+fn every_second(at_most: Option<usize>) -> StageRunSettings {
+    StageRunSettings {
+        every: Duration::from_secs(1),
+        at_most,
+    }
+}
+
+/// Run all stages until `deadline_in` passed, not downloading the crates.io database
+fn mine(db: &Path, index: &Path, network: &Network, deadline_in: Duration, deterministic: bool) -> criner::Result<()> {
+    mine_with(db, index, network, deadline_in, deterministic, Stage::ALL, None)
+}
+
+/// Run the given `stages` for crates in the `allowlist` until `deadline_in` passed, not downloading the crates.io database
+fn mine_with(
+    db: &Path,
+    index: &Path,
+    network: &Network,
+    deadline_in: Duration,
+    deterministic: bool,
+    stages: &[Stage],
+    allowlist: Option<Allowlist>,
+) -> criner::Result<()> {
+    run::blocking(
+        db,
+        index,
+        run::Options {
+            registry: network.registry.clone(),
+            deadline: Some(SystemTime::now() + deadline_in),
+            io_bound_processors: 1,
+            cpu_bound_processors: 1,
+            cpu_o_bound_processors: 1,
+            fetch_settings: every_second(Some(1)),
+            process_settings: every_second(None),
+            report_settings: GlobStageRunSettings {
+                glob: None,
+                run: every_second(None),
+            },
+            network_settings: NetworkSettings {
+                max_connections_per_host: 1,
+                crawl_delay: Duration::default(),
+                ..Default::default()
+            },
+            deterministic,
+            stages: stages.to_vec(),
+            allowlist,
+            durability: Durability::None,
+            backend: BackendKind::SqlitePool(2),
+            ..Default::default()
+        },
+        Default::default(),
+        run::Frontend::Log,
+    )
+}
+
+fn files_below(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_owned()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(dir).into_iter().flatten().flatten() {
+            if entry.path().is_dir() {
+                dirs.push(entry.path());
+            } else {
+                files.push(entry.path());
+            }
+        }
+    }
+    files
+}
+
+fn is_reported(reports: &Path, name: &str) -> bool {
+    files_below(reports)
+        .iter()
+        .any(|path| path.to_string_lossy().contains(name) && path.extension().is_some_and(|ext| ext == "html"))
+}
+
+#[test]
+fn crates_are_fetched_downloaded_extracted_and_reported() {
+    let dir = std::env::temp_dir().join(format!("criner-pipeline-test-{}", std::process::id()));
+    std::fs::remove_dir_all(&dir).ok();
+    let network = Network::start(
+        "mock",
+        dir.join("registry-index"),
+        &[
+            Crate::new("tiny", "1.0.0"),
+            Crate::new("wasteful", "0.1.0").with_file("tests/fixtures/huge.bin", vec![0u8; 64 * 1024]),
+        ],
+    )
+    .unwrap();
+    let db = dir.join("criner.db");
+    let index = dir.join("index");
+
+    let waste_reports = db.join("reports").join("current").join("waste");
+    let is_reported = |name: &str| is_reported(&waste_reports, name);
+    for _attempt in 0..20 {
+        mine(&db, &index, &network, Duration::from_secs(3), false).unwrap();
+        if is_reported("tiny") && is_reported("wasteful") {
+            break;
+        }
+    }
+
+    let requests = network.requests();
+    assert!(requests.contains(&"/crates/tiny/1.0.0/download".to_string()));
+    assert!(requests.contains(&"/crates/wasteful/0.1.0/download".to_string()));
+    assert!(is_reported("tiny"), "{:?}", files_below(&waste_reports));
+    assert!(is_reported("wasteful"), "{:?}", files_below(&waste_reports));
+
+    let mut status = Vec::new();
+    criner::status::write_blocking(&db, &network.registry, &mut status).unwrap();
+    let status = String::from_utf8(status).unwrap();
+    assert!(status.contains("crate versions: 2"), "{}", status);
+    let wasteful = std::fs::read_to_string(waste_reports.join("mock").join("wasteful").join("0.1.0.html")).unwrap();
+    assert!(wasteful.contains("tests/fixtures/huge.bin"), "{}", wasteful);
+    let api: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(waste_reports.join("mock").join("wasteful").join("api.json")).unwrap())
+            .unwrap();
+    assert_eq!(api["crate_name"], "mock/wasteful");
+    assert_eq!(api["versions"]["0.1.0"]["is_lean"], false, "{}", api);
+    let errors = std::fs::read_to_string(db.join("reports").join("current").join("errors").join("index.html")).unwrap();
+    assert!(errors.contains("0 item(s) couldn't be reported"), "{}", errors);
+    std::fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn deterministic_runs_write_identical_reports() {
+    let dir = std::env::temp_dir().join(format!("criner-pipeline-deterministic-test-{}", std::process::id()));
+    std::fs::remove_dir_all(&dir).ok();
+    let network = Network::start(
+        "mock",
+        dir.join("registry-index"),
+        &[
+            Crate::new("a", "1.0.0"),
+            Crate::new("a", "1.1.0"),
+            Crate::new("b", "0.1.0"),
+        ],
+    )
+    .unwrap();
+
+    let mut reports_of_runs = Vec::new();
+    for run in 0..2 {
+        let db = dir.join(format!("criner-{}.db", run));
+        let waste_reports = db.join("reports").join("current").join("waste");
+        for _attempt in 0..20 {
+            mine(
+                &db,
+                &dir.join(format!("index-{}", run)),
+                &network,
+                Duration::from_secs(3),
+                true,
+            )
+            .unwrap();
+            if ["1.0.0", "1.1.0", "0.1.0"]
+                .iter()
+                .all(|version| is_reported(&waste_reports, version))
+            {
+                break;
+            }
+        }
+        let reports = db.join("reports").join("current");
+        let mut files: Vec<_> = files_below(&reports)
+            .into_iter()
+            .filter(|path| path.extension().is_some_and(|ext| ext == "html"))
+            .map(|path| {
+                let content = std::fs::read(&path).unwrap();
+                (path.strip_prefix(&reports).unwrap().to_owned(), content)
+            })
+            .collect();
+        files.sort();
+        reports_of_runs.push(files);
+    }
+
+    assert!(
+        reports_of_runs[0]
+            .iter()
+            .any(|(path, _)| path.ends_with("a/1.1.0.html")),
+        "{:?}",
+        reports_of_runs[0].iter().map(|(path, _)| path).collect::<Vec<_>>()
+    );
+    assert!(!reports_of_runs[0].iter().any(|(path, _)| path.starts_with("failures")));
+    assert_eq!(reports_of_runs[0], reports_of_runs[1]);
+    std::fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn stages_can_run_on_separate_invocations() {
+    let dir = std::env::temp_dir().join(format!("criner-pipeline-stages-test-{}", std::process::id()));
+    std::fs::remove_dir_all(&dir).ok();
+    let network = Network::start("mock", dir.join("registry-index"), &[Crate::new("tiny", "1.0.0")]).unwrap();
+    let db = dir.join("criner.db");
+    let index = dir.join("index");
+    let waste_reports = db.join("reports").join("current").join("waste");
+    let downloaded = || network.requests().contains(&"/crates/tiny/1.0.0/download".to_string());
+
+    for _attempt in 0..20 {
+        mine_with(
+            &db,
+            &index,
+            &network,
+            Duration::from_secs(3),
+            false,
+            &[Stage::Fetch, Stage::Download],
+            None,
+        )
+        .unwrap();
+        if downloaded() {
+            break;
+        }
+    }
+    assert!(downloaded());
+    assert!(!is_reported(&waste_reports, "tiny"), "reporting is disabled");
+
+    let requests_before = network.requests().len();
+    for _attempt in 0..20 {
+        mine_with(
+            &db,
+            &index,
+            &network,
+            Duration::from_secs(3),
+            false,
+            &[Stage::Extract, Stage::Report],
+            None,
+        )
+        .unwrap();
+        if is_reported(&waste_reports, "tiny") {
+            break;
+        }
+    }
+    assert!(is_reported(&waste_reports, "tiny"), "{:?}", files_below(&waste_reports));
+    assert_eq!(
+        network.requests().len(),
+        requests_before,
+        "neither the index nor crates are fetched"
+    );
+    std::fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn only_crates_in_the_allowlist_are_mined() {
+    let dir = std::env::temp_dir().join(format!("criner-pipeline-allowlist-test-{}", std::process::id()));
+    std::fs::remove_dir_all(&dir).ok();
+    let network = Network::start(
+        "mock",
+        dir.join("registry-index"),
+        &[
+            Crate::new("wanted", "1.0.0"),
+            Crate::new("wanted", "2.0.0"),
+            Crate::new("other", "1.0.0"),
+        ],
+    )
+    .unwrap();
+    let db = dir.join("criner.db");
+    let waste_reports = db.join("reports").join("current").join("waste");
+    for _attempt in 0..20 {
+        mine_with(
+            &db,
+            &dir.join("index"),
+            &network,
+            Duration::from_secs(3),
+            false,
+            Stage::ALL,
+            Some("wanted ^2".parse().unwrap()),
+        )
+        .unwrap();
+        if is_reported(&waste_reports, "wanted") {
+            break;
+        }
+    }
+
+    let requests = network.requests();
+    assert!(requests.contains(&"/crates/wanted/2.0.0/download".to_string()));
+    assert!(
+        !requests
+            .iter()
+            .any(|r| r.contains("/wanted/1.0.0/") || r.contains("/other/")),
+        "{:?}",
+        requests
+    );
+    let mut status = Vec::new();
+    criner::status::write_blocking(&db, &network.registry, &mut status).unwrap();
+    let status = String::from_utf8(status).unwrap();
+    assert!(status.contains("crate versions: 1"), "{}", status);
+    std::fs::remove_dir_all(dir).ok();
+}
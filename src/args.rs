@@ -15,6 +15,7 @@ pub struct Args {
 }
 
 #[derive(Debug, Clap)]
+#[allow(clippy::large_enum_variant)]
 pub enum SubCommands {
     /// Mine crates.io in an incorruptible and resumable fashion
     #[clap(display_order = 0)]
@@ -58,10 +59,18 @@ pub enum SubCommands {
         #[clap(long, alias = "cpu", value_name = "cpu", default_value = "4")]
         cpu_bound_processors: u32,
 
-        /// Path to the possibly existing crates.io repository clone. If unset, it will be cloned to a temporary spot.
+        /// Path to the possibly existing clone of the registry index. If unset, it will be cloned to a temporary spot.
         #[clap(short = 'c', long, name = "REPO")]
         repository: Option<PathBuf>,
 
+        /// The registry to mine, 'crates-io' or the name of a registry whose index is configured for cargo with the
+        /// CARGO_REGISTRIES_<NAME>_INDEX environment variable.
+        ///
+        /// All registries can share one database, as crates of registries other than crates.io are stored as '<registry>/<crate>'.
+        /// Fetching, checking the index and downloading is limited to the given registry, while reports cover the whole database.
+        #[clap(long, default_value = "crates-io", parse(try_from_str = criner::registry::Registry::parse))]
+        registry: criner::registry::Registry,
+
         /// The amount of time we can take for the computation. Specified in humantime, like 10s, 5min, or 2h, or '3h 2min 2s'
         #[clap(long, short = 't')]
         time_limit: Option<humantime::Duration>,
@@ -104,6 +113,208 @@ pub enum SubCommands {
         #[clap(long, short = 'g')]
         glob: Option<String>,
 
+        /// The maximum amount of concurrent connections to a single host, no matter how many io-bound processors are used.
+        ///
+        /// This keeps Criner a well-mannered crawler, the default is conservative to respect the crates.io crawler policy.
+        #[clap(long, default_value = "4")]
+        max_connections_per_host: usize,
+
+        /// The minimum time between two requests to hosts other than crates.io, specified in humantime, like 500ms or 2s.
+        ///
+        /// Hosts may ask for a longer delay in their robots.txt, which is respected as well.
+        #[clap(long, default_value = "1s")]
+        crawl_delay: humantime::Duration,
+
+        /// If set, the maximum amount of requests per second to a single host, like '2' or '0.5', shared by all io-bound processors.
+        ///
+        /// Use it to stay clear of abuse protections, bursts are limited to one second worth of requests. Requests are unlimited by default.
+        #[clap(long, parse(try_from_str = criner::run::parse_requests_per_second))]
+        max_requests_per_second: Option<f64>,
+
+        /// If set, the bandwidth all downloads may use together, like '10MiB/s' or '500KB/s'.
+        ///
+        /// Use it to share a connection with others without saturating it. Downloads are unlimited by default.
+        #[clap(long, parse(try_from_str = criner::run::parse_bandwidth))]
+        max_download_bandwidth: Option<u64>,
+
+        /// If set, limits for all downloads together by local time of day, taking precedence over --max-download-bandwidth.
+        ///
+        /// Comma separated windows like '22:00-06:00=full,06:00-22:00=1MiB/s,2workers', where 'full' lifts all limits,
+        /// a bandwidth limits the bytes per second and '<n>workers' the amount of concurrent downloads.
+        /// Further limits for the same window follow after a comma.
+        #[clap(long)]
+        download_schedule: Option<criner::run::DownloadSchedule>,
+
+        /// The IP version to use for downloads, one of 'any', '4' or '6'.
+        ///
+        /// Use it on networks with broken IPv6 (or IPv4) connectivity. With 'any', both are tried concurrently.
+        #[clap(long, default_value = "any")]
+        ip_version: criner::run::IpVersion,
+
+        /// Use the given IP address for a host instead of resolving it with DNS, like 'static.crates.io=151.101.2.137'.
+        ///
+        /// Can be specified multiple times, and helps on networks whose DNS resolvers can't be trusted.
+        #[clap(long = "resolve", parse(try_from_str = criner::run::parse_host_override), multiple_occurrences = true, number_of_values = 1)]
+        host_overrides: Vec<(String, std::net::IpAddr)>,
+
+        /// Resolve hosts with this nameserver instead of those of the operating system, like '1.1.1.1' or
+        /// '127.0.0.1:5353'.
+        ///
+        /// Can be specified multiple times to fall back to further nameservers. Hosts set with --resolve aren't looked up.
+        #[clap(long = "nameserver", parse(try_from_str = criner::run::parse_nameserver), multiple_occurrences = true, number_of_values = 1)]
+        nameservers: Vec<std::net::SocketAddr>,
+
+        /// Send all requests through this proxy, like 'socks5://localhost:1080' or 'http://proxy.example.com:3128'.
+        ///
+        /// Without it, the proxy in the HTTPS_PROXY or HTTP_PROXY environment variable is used, if set, unless the host
+        /// is listed in NO_PROXY. Use 'socks5h://' to have the proxy resolve host names.
+        #[clap(long, parse(try_from_str = criner::run::parse_proxy))]
+        proxy: Option<criner::run::Proxy>,
+
+        /// If set, download crates from URLs made from this template instead of where the index says, like
+        /// 'https://mirror.example.com/api/v1/crates/{crate}/{version}/download'.
+        ///
+        /// The markers are those of the 'dl' field in the 'config.json' of an index: {crate}, {version}, {prefix}, {lowerprefix}
+        /// and {sha256-checksum}. Without any of them, '/{crate}/{version}/download' is appended, so the base URL of a mirror
+        /// like 'https://mirror.example.com/api/v1/crates' works as well, which is why it can also be passed as --download-base-url.
+        /// Use it for mirrors and private registries with different path schemes.
+        #[clap(long, alias = "download-base-url", parse(try_from_str = criner::run::parse_download_url_template))]
+        download_url_template: Option<String>,
+
+        /// A template for download URLs of a mirror to download crates from if what was downloaded doesn't match the
+        /// checksum in the index, like 'https://mirror.example.com/{crate}/{crate}-{version}.crate'.
+        ///
+        /// The failed download is recorded as failed attempt of the task before the mirror is tried.
+        #[clap(long, parse(try_from_str = criner::run::parse_download_url_template))]
+        mirror_url_template: Option<String>,
+
+        /// When failed downloads and extractions are tried again, like 'max=5,base=30s'.
+        ///
+        /// A failed task is tried again after 'base', and the delay doubles with each failure up to a day, half of it being
+        /// random to spread out retries. After 'max' failed attempts it's given up on, as are downloads which don't exist
+        /// according to the server (404 or 410).
+        #[clap(long, default_value = "max=8,base=30s")]
+        task_retry: criner::run::RetryPolicy,
+
+        /// The kinds of artifacts to download for each crate version, comma separated.
+        ///
+        /// One or more of 'crate', 'repo.tar.gz' for tagged GitHub repositories, and 'docs-builds.json' for docs.rs builds.
+        /// Only crates are extracted and analysed further. docs.rs builds are downloaded again once a week, but only if they changed.
+        #[clap(long, default_value = "crate", use_delimiter = true, parse(try_from_str = criner::run::artifact::parse))]
+        artifacts: Vec<&'static criner::run::artifact::Kind>,
+
+        /// If set, keep full extraction results and downloaded artifacts only for the given amount of most recent versions of each crate.
+        ///
+        /// Once a day, results of older versions are reduced to the meta-data of their files, and their artifacts are deleted
+        /// once extracted. This keeps databases and assets from growing without bound, at the cost of not being able to
+        /// rerun content based reports for old versions.
+        #[clap(long)]
+        keep_full_results_for_latest: Option<usize>,
+
+        /// Keep downloaded artifacts in an S3 compatible object store like 's3://bucket/prefix' instead of the assets directory.
+        ///
+        /// Credentials are read from AWS_ACCESS_KEY_ID, AWS_SECRET_ACCESS_KEY and optionally AWS_SESSION_TOKEN,
+        /// the region from AWS_REGION, and AWS_ENDPOINT_URL selects an S3 compatible store other than AWS.
+        /// Partial downloads and crates.io database dumps remain in the local assets directory.
+        #[clap(long)]
+        assets: Option<String>,
+
+        /// If set, add crate versions to the database which the daily check finds in the crates.io index but not in the database.
+        ///
+        /// Such gaps can be caused by crashes or missed changes of the index, and are always shown by 'criner status'.
+        #[clap(long)]
+        repair_index_gaps: bool,
+
+        /// Globs of files to store when extracting crates in addition to manifests, the lock file and sources of targets, comma separated.
+        ///
+        /// Paths are relative to the crate root and '*' doesn't match '/', so '**/*.proto,deny.toml' selects all protobuf files
+        /// and 'deny.toml' at the root. Further globs are read from 'criner.select-globs' in the working directory, one per line.
+        /// Only crates extracted from now on are affected.
+        #[clap(long, use_delimiter = true)]
+        select_globs: Vec<String>,
+
+        /// The amount of bytes to store of each selected file when extracting crates, like '256KiB'.
+        ///
+        /// Larger files are cut off and marked as truncated, which keeps huge READMEs and vendored files from bloating the
+        /// database. Manifests and lock files are always stored in full. Only crates extracted from now on are affected.
+        #[clap(long, default_value = "128KiB", parse(try_from_str = criner::run::parse_size))]
+        max_stored_entry_size: u64,
+
+        /// If set, extract crates in two passes, and the contents of files only for the given amount of most recent versions of each crate.
+        ///
+        /// The first pass records the meta-data of all files and the manifest, which is enough for size and waste reports and
+        /// gets them going quickly on fresh databases. Later runs of the processing stage extract the contents of the most
+        /// recent versions. Reports which were generated from the first pass aren't updated afterwards.
+        #[clap(long)]
+        extract_contents_for_latest: Option<usize>,
+
+        /// Kinds of files which the waste report should not consider waste, comma separated.
+        ///
+        /// One or more of 'tests', 'benches', 'examples' and 'fixtures', which are waste by default. Files of these kinds
+        /// remain included in suggested fixes. Changing the kinds regenerates the waste report from scratch.
+        #[clap(long, use_delimiter = true)]
+        not_waste: Vec<criner::run::WasteCategory>,
+
+        /// If set, reports only depend on the state of the index and are the same for every run, for validating changes to them.
+        ///
+        /// Crates are reported in the order of their names one at a time, pages show the time of the index commit instead of
+        /// the time they were generated, and --download-schedule is ignored. The failure heatmap and integrity audit are skipped
+        /// as they are about the miner itself.
+        #[clap(long)]
+        deterministic: bool,
+
+        /// The stages of the pipeline to run, comma separated.
+        ///
+        /// One or more of 'fetch' for the index, its daily check and the crates.io database, 'download' for artifacts,
+        /// 'extract' for downloaded crates and 'report'. This allows to split the work between machines which share the
+        /// database, like fetching and downloading nightly on a small machine and extracting and reporting weekly on a big one.
+        /// Without 'download', only crates which were downloaded already are extracted.
+        #[clap(long, default_value = "fetch,download,extract,report", use_delimiter = true)]
+        stages: Vec<criner::run::Stage>,
+
+        /// If set, only fetch, download and extract crates named in this file, one per line, for mining a dependency tree only.
+        ///
+        /// Names may be followed by a version requirement like in Cargo.toml, as in 'tokio >=1.0, <2', to limit the versions
+        /// to mine. Empty lines and lines starting with '#' are ignored. Reports still cover all crates in the database.
+        #[clap(long)]
+        crates_file: Option<PathBuf>,
+
+        /// Trade crash safety for throughput, one of 'full', 'relaxed' or 'none'.
+        ///
+        /// 'full' syncs every database commit and every downloaded file to disk.
+        /// 'relaxed' syncs the database only in critical moments, and leaves downloads to the operating system.
+        /// 'none' never syncs, which is fastest for initial backfills, but a system crash may corrupt the database.
+        #[clap(long, default_value = "relaxed")]
+        durability: criner::run::Durability,
+
+        /// Where to store the items of tables, one of 'sqlite' or 'sqlite-pool[:<size>]'.
+        ///
+        /// 'sqlite' gives each table its own connection to the database, which is shared by everything using the table.
+        /// 'sqlite-pool' shares a pool of connections, 8 unless '<size>' is given, between all tables, so that threads don't
+        /// wait for each other to release a connection. Use 'criner bench db' to see which is faster on a machine.
+        #[clap(long, default_value = "sqlite")]
+        db_backend: criner::run::BackendKind,
+
+        /// If set, serve metrics in the text format of Prometheus at 'http://<address>/metrics', like '127.0.0.1:9100'.
+        ///
+        /// They include the amount of crates and crate versions, events of tasks by process, downloaded bytes
+        /// and the depth of queues, to be alerted when mining stalls.
+        #[clap(long)]
+        metrics_addr: Option<std::net::SocketAddr>,
+
+        /// If set, serve the published reports, the status of mining and metrics at 'http://<address>', like '0.0.0.0:8080'.
+        ///
+        /// The same is served by 'criner serve', see its help for details.
+        #[clap(long)]
+        serve_addr: Option<std::net::SocketAddr>,
+
+        /// If set, a TOML file with settings which override their arguments and are applied again whenever the file changes.
+        ///
+        /// It may contain 'io', 'cpu', 'cpu-o', 'max-requests-per-second', 'max-download-bandwidth' and 'report-glob',
+        /// to tune a long-running miner without restarting it. Removed settings fall back to their arguments.
+        #[clap(long)]
+        config: Option<PathBuf>,
+
         /// Path to the possibly existing database. It's used to persist all mining results.
         #[clap(default_value = "criner.db")]
         db_path: PathBuf,
@@ -113,23 +324,426 @@ pub enum SubCommands {
     /// Criner stores binary blobs internally and migrates them on the fly, which is optimized for raw performance.
     /// It's also impractical for exploring the data by hand, so the exported data will explode all types into
     /// tables with each column being a field. Foreign key relations are set accordingly to allow joins.
+    /// Conclusions of reports, like the waste per crate version and the suggested fix, are exported as `report_*` tables.
     /// Use this to get an overview of what's available, and possibly contribute a report generator which implements
     /// a query using raw data and writes it into reports.
     #[clap(display_order = 1)]
     #[clap(setting = clap::AppSettings::DisableVersion)]
     Export {
+        /// The format to export to, 'sqlite' for another database, or 'jsonl' or 'csv' for a directory with a file per table
+        ///
+        /// Flat files have the same tables and columns as the exported database, and blobs like the contents of crate entries are
+        /// hex-encoded. They feed into tools like pandas or BigQuery directly.
+        #[clap(long, default_value = "sqlite")]
+        format: criner::export::Format,
+
+        /// If set, only the crates of this registry are exported, 'crates-io' or a registry configured for cargo with
+        /// CARGO_REGISTRIES_<NAME>_INDEX. Previous exports can only be updated for the same registry.
+        #[clap(long, parse(try_from_str = criner::registry::Registry::parse))]
+        registry: Option<criner::registry::Registry>,
+
         /// The path to the source database in sqlite format
         input_db_path: PathBuf,
 
-        /// Path to which to write the exported data. If it exists the operation will fail.
+        /// Path to which to write the exported data, a directory unless the format is 'sqlite'.
+        ///
+        /// A database written by a previous export is updated with the rows which changed since, which takes a fraction of the
+        /// time of a full export. Otherwise the operation fails if the path exists.
         export_db_path: PathBuf,
     },
+    /// Maintenance operations on Criner databases
+    #[clap(display_order = 2)]
+    #[clap(setting = clap::AppSettings::DisableVersion)]
+    Db {
+        #[clap(subcommand)]
+        cmd: DbCommands,
+    },
+    /// Show how much is stored in the database, and whether the daily check found crate versions of the crates.io index to be missing
+    #[clap(display_order = 2)]
+    #[clap(setting = clap::AppSettings::DisableVersion)]
+    Status {
+        /// The registry whose crates to show, 'crates-io' or a registry configured for cargo with CARGO_REGISTRIES_<NAME>_INDEX
+        #[clap(long, default_value = "crates-io", parse(try_from_str = criner::registry::Registry::parse))]
+        registry: criner::registry::Registry,
+
+        /// Path to the database
+        #[clap(long, default_value = "criner.db")]
+        db_path: PathBuf,
+    },
+    /// Print registry-wide statistics as JSON: crate counts, the size distribution of crates, waste totals and the throughput of the last run
+    ///
+    /// They are read from aggregates kept up to date while mining, which makes it cheap to ask often, i.e. for monitoring.
+    /// Sizes and waste are as of the last time the waste report was generated.
+    #[clap(display_order = 2)]
+    #[clap(setting = clap::AppSettings::DisableVersion)]
+    Stats {
+        /// Path to the database
+        #[clap(long, default_value = "criner.db")]
+        db_path: PathBuf,
+    },
+    /// Serve the published reports along with the status of mining as JSON at '/status.json' and metrics at '/metrics'
+    ///
+    /// It runs next to a miner working on the same database, to watch its progress remotely without a web server of its own.
+    /// Use 'criner mine --serve-addr' to serve them from the miner itself.
+    #[clap(display_order = 2)]
+    #[clap(setting = clap::AppSettings::DisableVersion)]
+    Serve {
+        /// The port to listen on
+        #[clap(long, default_value = "8080")]
+        port: u16,
+
+        /// The address to listen on, '0.0.0.0' to serve other machines
+        #[clap(long, default_value = "127.0.0.1")]
+        bind: std::net::IpAddr,
+
+        /// Path to the database
+        #[clap(long, default_value = "criner.db")]
+        db_path: PathBuf,
+    },
+    /// Check a crate for waste before publishing it, printing wasted files and the Cargo.toml excerpt fixing them
+    ///
+    /// Uses the same analysis as the waste report of 'criner mine'.
+    #[clap(display_order = 2)]
+    #[clap(setting = clap::AppSettings::DisableVersion)]
+    CheckPackage {
+        /// A '.crate' archive, or the directory of a cargo project whose files are listed with 'cargo package --list'
+        path: PathBuf,
+
+        /// Kinds of files which should not be considered waste, comma separated.
+        ///
+        /// One or more of 'tests', 'benches', 'examples' and 'fixtures', which are waste by default.
+        #[clap(long, use_delimiter = true)]
+        not_waste: Vec<criner::run::WasteCategory>,
+
+        /// Fail if there is waste, for use in CI
+        #[clap(long)]
+        fail_on_waste: bool,
+
+        /// Compare the files with those of the greatest version of the crate in this database which isn't yanked,
+        /// pointing out large new files and new files which are waste.
+        ///
+        /// Versions which weren't extracted yet are downloaded from crates.io.
+        #[clap(long, value_name = "db-path")]
+        compare_with_db: Option<PathBuf>,
+
+        /// The registry the crate is published to, 'crates-io' or a registry configured for cargo with CARGO_REGISTRIES_<NAME>_INDEX
+        #[clap(long, default_value = "crates-io", parse(try_from_str = criner::registry::Registry::parse))]
+        registry: criner::registry::Registry,
+
+        /// Fail if comparing with the previous version resulted in warnings, for use in CI
+        #[clap(long, requires = "compare-with-db")]
+        fail_on_warnings: bool,
+    },
+    /// Maintain the reports generated by 'criner mine'
+    #[clap(display_order = 2)]
+    #[clap(setting = clap::AppSettings::DisableVersion)]
+    Reports {
+        #[clap(subcommand)]
+        cmd: ReportsCommands,
+    },
+    /// Print what is stored in the database as JSON, for ad-hoc inspection
+    #[clap(display_order = 2)]
+    #[clap(setting = clap::AppSettings::DisableVersion)]
+    Query {
+        #[clap(subcommand)]
+        cmd: QueryCommands,
+    },
+    /// Show what happened to a crate version and when: each task attempt with its duration, errors, and the current task states
+    #[clap(display_order = 2)]
+    #[clap(setting = clap::AppSettings::DisableVersion)]
+    Timeline {
+        /// The name of the crate
+        crate_name: String,
+
+        /// The version of the crate
+        crate_version: String,
+
+        /// The registry whose crates to show, 'crates-io' or a registry configured for cargo with CARGO_REGISTRIES_<NAME>_INDEX
+        #[clap(long, default_value = "crates-io", parse(try_from_str = criner::registry::Registry::parse))]
+        registry: criner::registry::Registry,
+
+        /// Path to the database
+        #[clap(long, default_value = "criner.db")]
+        db_path: PathBuf,
+    },
+    /// Analyse all versions of a crate once more with the next 'criner mine', i.e. after the analysis was improved
+    ///
+    /// Tasks and reports of all versions are forgotten, so they are extracted and reported once more with the current version
+    /// of each task and report, and downloaded if they are missing. Cached reports of the crate are removed from the reports of all crates too.
+    #[clap(display_order = 2)]
+    #[clap(setting = clap::AppSettings::DisableVersion)]
+    Reanalyze {
+        /// The name of the crate
+        crate_name: String,
+
+        /// The registry whose crates to reanalyze, 'crates-io' or a registry configured for cargo with CARGO_REGISTRIES_<NAME>_INDEX
+        #[clap(long, default_value = "crates-io", parse(try_from_str = criner::registry::Registry::parse))]
+        registry: criner::registry::Registry,
+
+        /// Path to the database
+        #[clap(long, default_value = "criner.db")]
+        db_path: PathBuf,
+    },
+    /// Print the version of a crate cargo would select for a version requirement, ignoring yanked versions
+    ///
+    /// Requirements use the syntax of Cargo.toml, like '1.2', '~0.3.1' or '>=1.0, <1.4'. Exits with an error if no
+    /// known version satisfies the requirement.
+    #[clap(display_order = 2)]
+    #[clap(setting = clap::AppSettings::DisableVersion)]
+    Resolve {
+        /// The name of the crate
+        crate_name: String,
+
+        /// The version requirement, like '^1.2'
+        requirement: String,
+
+        /// The registry whose crates to show, 'crates-io' or a registry configured for cargo with CARGO_REGISTRIES_<NAME>_INDEX
+        #[clap(long, default_value = "crates-io", parse(try_from_str = criner::registry::Registry::parse))]
+        registry: criner::registry::Registry,
+
+        /// Path to the database
+        #[clap(long, default_value = "criner.db")]
+        db_path: PathBuf,
+    },
+    /// Write the dependency graph of all crates for visualization, in the format of Graphviz or Gephi
+    ///
+    /// Each crate is represented by its greatest version which isn't yanked, and its normal and build dependencies.
+    /// Optional dependencies are dashed and build dependencies are gray in 'dot' output.
+    #[clap(display_order = 2)]
+    #[clap(setting = clap::AppSettings::DisableVersion)]
+    ExportGraph {
+        /// The format of the graph, 'dot' for Graphviz or 'gexf' for Gephi
+        #[clap(long, default_value = "dot")]
+        format: criner::graph::Format,
+
+        /// If set, only write this crate and all crates it depends on, directly or indirectly
+        #[clap(long)]
+        root: Option<String>,
+
+        /// The registry whose crates to show, 'crates-io' or a registry configured for cargo with CARGO_REGISTRIES_<NAME>_INDEX
+        #[clap(long, default_value = "crates-io", parse(try_from_str = criner::registry::Registry::parse))]
+        registry: criner::registry::Registry,
+
+        /// The file to write the graph to. If unset, it is written to stdout.
+        #[clap(long, short = 'o')]
+        output: Option<PathBuf>,
+
+        /// Path to the database
+        #[clap(long, default_value = "criner.db")]
+        db_path: PathBuf,
+    },
+    /// Show download counts, owners and publish dates of a crate as provided by the crates.io API
+    ///
+    /// Requests are limited to one per second as asked by the crates.io crawler policy, and responses are cached in the
+    /// database to only transfer what changed when asking again.
+    #[clap(display_order = 2)]
+    #[clap(setting = clap::AppSettings::DisableVersion)]
+    CratesIo {
+        /// The name of the crate
+        crate_name: String,
+
+        /// Path to the database
+        #[clap(long, default_value = "criner.db")]
+        db_path: PathBuf,
+    },
+    /// Start a new database from a published snapshot, to only mine what changed since instead of doing a full backfill
+    ///
+    /// The download is resumed if interrupted, and the snapshot is verified against its sha256 checksum before use.
+    #[clap(display_order = 2)]
+    #[clap(setting = clap::AppSettings::DisableVersion)]
+    Bootstrap {
+        /// The URL of the snapshot of a criner database file
+        #[clap(long)]
+        from_url: String,
+
+        /// The expected sha256 of the snapshot as hex string. If unset, it is read from '<from-url>.sha256'.
+        #[clap(long)]
+        sha256: Option<String>,
+
+        /// If set, only the crates of this registry are kept, 'crates-io' or a registry configured for cargo with
+        /// CARGO_REGISTRIES_<NAME>_INDEX. Otherwise the database holds the crates of all registries in the snapshot.
+        #[clap(long, parse(try_from_str = criner::registry::Registry::parse))]
+        registry: Option<criner::registry::Registry>,
+
+        /// Path to the database directory to create. It must not contain a database yet.
+        #[clap(default_value = "criner.db")]
+        db_path: PathBuf,
+    },
+    /// Publish a compacted snapshot of the database for others to bootstrap from, along with an exported version of it
+    ///
+    /// The output contains the database file, its exported form as produced by 'criner export', a '.sha256' checksum file
+    /// for each of these, and a 'manifest.json' with their sizes, checksums and row counts per table, which is written last.
+    #[clap(display_order = 2)]
+    #[clap(setting = clap::AppSettings::DisableVersion)]
+    PublishSnapshot {
+        /// The directory to write the snapshot to, or an 's3://bucket/prefix' URL to upload it to.
+        ///
+        /// Uploads are configured with the same environment variables as 'criner mine --assets', AWS_ACCESS_KEY_ID,
+        /// AWS_SECRET_ACCESS_KEY, AWS_SESSION_TOKEN, AWS_REGION and AWS_ENDPOINT_URL.
+        #[clap(long)]
+        out: String,
+
+        /// If set, publish a snapshot right away and then every day at the given local time.
+        /// Format is HH:MM, e.g. '14:30' for 2:30 pm or 03:15 for quarter past 3 in the morning.
+        #[clap(long, parse(try_from_str = parse_local_time))]
+        every_day_at: Option<time::Time>,
+
+        /// If set, the snapshot only holds the crates of this registry, 'crates-io' or a registry configured for cargo with
+        /// CARGO_REGISTRIES_<NAME>_INDEX. Otherwise it holds those of all registries in the database.
+        #[clap(long, parse(try_from_str = criner::registry::Registry::parse))]
+        registry: Option<criner::registry::Registry>,
+
+        /// Path to the database to take the snapshot of
+        #[clap(default_value = "criner.db")]
+        db_path: PathBuf,
+    },
+    /// Run a short calibrated workload and recommend settings for 'criner mine' on this machine and network
+    ///
+    /// It downloads a crate repeatedly with increasing concurrency, extracts it a few times and writes to a
+    /// temporary database with each durability setting. It takes less than a minute on reasonable connections.
+    #[clap(display_order = 3)]
+    #[clap(setting = clap::AppSettings::DisableVersion)]
+    Tune {
+        /// The registry to recommend settings for, 'crates-io' or a registry configured for cargo with CARGO_REGISTRIES_<NAME>_INDEX
+        #[clap(long, default_value = "crates-io", parse(try_from_str = criner::registry::Registry::parse))]
+        registry: criner::registry::Registry,
+
+        /// The URL of a crate archive to download repeatedly to measure the network.
+        /// Defaults to a crate of crates.io, and must be set for other registries.
+        #[clap(long)]
+        url: Option<String>,
+
+        /// The maximum amount of concurrent downloads to try. Concurrency is doubled until it is reached.
+        #[clap(long, default_value = "16")]
+        max_io: u32,
+
+        /// The amount of tasks to write to the temporary database per durability setting
+        #[clap(long, default_value = "500")]
+        db_writes: usize,
+    },
+    /// Measure the performance of parts of Criner, to catch regressions before they slow down real mining runs
+    #[clap(display_order = 4)]
+    #[clap(setting = clap::AppSettings::DisableVersion)]
+    Bench {
+        #[clap(subcommand)]
+        cmd: BenchCommands,
+    },
     #[cfg(feature = "migration")]
     /// A special purpose command only to be executed in special circumstances
     #[clap(display_order = 9)]
     Migrate,
 }
 
+#[derive(Debug, Clap)]
+pub enum DbCommands {
+    /// Merge all data of another database into the given one, i.e. to combine the results of multiple miners.
+    ///
+    /// Crates and their versions are combined, tasks keep their most advanced state, and downloaded assets
+    /// are copied if they are not yet present.
+    #[clap(setting = clap::AppSettings::DisableVersion)]
+    Merge {
+        /// If set, only the crates of this registry and their assets are merged, 'crates-io' or a registry configured for
+        /// cargo with CARGO_REGISTRIES_<NAME>_INDEX. Otherwise those of all registries are merged.
+        #[clap(long, parse(try_from_str = criner::registry::Registry::parse))]
+        registry: Option<criner::registry::Registry>,
+
+        /// The path to the database to merge into the destination database
+        other_db_path: PathBuf,
+
+        /// Path to the database to merge into. It will be created if it doesn't exist.
+        #[clap(default_value = "criner.db")]
+        db_path: PathBuf,
+    },
+    /// Print the item stored at a key as raw msgpack bytes in hex, and decoded into its model as JSON.
+    ///
+    /// Items which can't be decoded into their model are printed as decoded without schema along with the error, to help
+    /// debugging serialization issues and writing migrations.
+    #[clap(setting = clap::AppSettings::DisableVersion)]
+    DumpKey {
+        /// The table to read from, one of 'crate', 'crate_version', 'task', 'result', 'meta', 'index_check', 'run_summary'
+        /// or 'crates.io-crate'
+        table: String,
+
+        /// The key of the item, like 'serde:1.0.0' for crate versions
+        key: String,
+
+        /// Path to the database
+        #[clap(long, default_value = "criner.db")]
+        db_path: PathBuf,
+    },
+}
+
+#[derive(Debug, Clap)]
+pub enum ReportsCommands {
+    /// Remove superseded report cycles, cached states of previous versions of report generators and pages of crates and
+    /// versions which were deleted or yanked.
+    ///
+    /// Only the published cycle and the one generated right now are cleaned up, as older ones are kept as they were.
+    #[clap(setting = clap::AppSettings::DisableVersion)]
+    Gc {
+        /// The amount of most recently published report cycles to keep, including the current one
+        #[clap(long, default_value = "3")]
+        keep: usize,
+
+        /// Path to the database
+        #[clap(long, default_value = "criner.db")]
+        db_path: PathBuf,
+    },
+}
+
+#[derive(Debug, Clap)]
+pub enum QueryCommands {
+    /// Print all stored versions of a crate with their tasks and task results.
+    ///
+    /// Deltas between results of subsequent versions are resolved, and paths and contents of files are printed as text.
+    #[clap(setting = clap::AppSettings::DisableVersion)]
+    Crate {
+        /// The name of the crate
+        crate_name: String,
+
+        /// The registry whose crates to show, 'crates-io' or a registry configured for cargo with CARGO_REGISTRIES_<NAME>_INDEX
+        #[clap(long, default_value = "crates-io", parse(try_from_str = criner::registry::Registry::parse))]
+        registry: criner::registry::Registry,
+
+        /// Path to the database
+        #[clap(long, default_value = "criner.db")]
+        db_path: PathBuf,
+    },
+}
+
+#[derive(Debug, Clap)]
+pub enum BenchCommands {
+    /// Measure get, update and upsert throughput of the database with realistic keys and values under contention.
+    ///
+    /// Operations run on a temporary database which is removed afterwards.
+    #[clap(setting = clap::AppSettings::DisableVersion)]
+    Db {
+        /// The amount of operations to run for each kind of operation and amount of threads
+        #[clap(long, short = 'o', default_value = "5000")]
+        operations: usize,
+
+        /// The amount of distinct keys to operate on
+        #[clap(long, short = 'k', default_value = "1000")]
+        keys: usize,
+
+        /// The maximum amount of threads to use concurrently. Starting at one, it's doubled until reached.
+        #[clap(long, short = 't', default_value = "8")]
+        max_threads: usize,
+
+        /// The durability to use for the database, one of 'full', 'relaxed' or 'none'
+        #[clap(long, default_value = "relaxed")]
+        durability: criner::run::Durability,
+
+        /// Where to store the items of tables, one of 'sqlite' or 'sqlite-pool[:<size>]', or 'redb' if built with the 'redb'
+        /// feature.
+        ///
+        /// 'redb' stores the items of all tables in a redb database next to the sqlite one. As mining queries tables
+        /// with SQL, it can only be benchmarked for now.
+        #[clap(long, default_value = "sqlite")]
+        db_backend: criner::run::BackendKind,
+    },
+}
+
 impl Default for SubCommands {
     fn default() -> Self {
         SubCommands::Mine {
@@ -151,7 +765,36 @@ impl Default for SubCommands {
             report_every: std::time::Duration::from_secs(60).into(),
             report_at_most: None,
             db_path: PathBuf::from("criner.db"),
+            registry: Default::default(),
             glob: None,
+            max_connections_per_host: 4,
+            crawl_delay: std::time::Duration::from_secs(1).into(),
+            max_requests_per_second: None,
+            max_download_bandwidth: None,
+            download_schedule: None,
+            ip_version: Default::default(),
+            host_overrides: Vec::new(),
+            nameservers: Vec::new(),
+            proxy: None,
+            download_url_template: None,
+            mirror_url_template: None,
+            task_retry: Default::default(),
+            artifacts: vec![&criner::run::artifact::CRATE],
+            keep_full_results_for_latest: None,
+            assets: None,
+            repair_index_gaps: false,
+            select_globs: Vec::new(),
+            max_stored_entry_size: criner::run::DEFAULT_MAX_STORED_ENTRY_SIZE,
+            extract_contents_for_latest: None,
+            not_waste: Vec::new(),
+            deterministic: false,
+            stages: criner::run::Stage::ALL.to_vec(),
+            crates_file: None,
+            durability: Default::default(),
+            db_backend: Default::default(),
+            metrics_addr: None,
+            serve_addr: None,
+            config: None,
         }
     }
 }
@@ -11,11 +11,173 @@ pub fn run_blocking(args: Args) -> criner::error::Result<()> {
         #[cfg(feature = "migration")]
         Migrate => criner::migration::migrate("./criner.db"),
         Export {
+            format,
+            registry,
             input_db_path,
             export_db_path,
-        } => criner::export::run_blocking(input_db_path, export_db_path),
+        } => criner::export::run_blocking(input_db_path, export_db_path, format, registry.as_ref()),
+        Db { cmd } => match cmd {
+            DbCommands::Merge {
+                registry,
+                other_db_path,
+                db_path,
+            } => criner::db::merge_blocking(other_db_path, db_path, registry.as_ref()),
+            DbCommands::DumpKey { table, key, db_path } => {
+                criner::db::dump_key_blocking(db_path, &table, &key, std::io::stdout())
+            }
+        },
+        Bootstrap {
+            from_url,
+            sha256,
+            registry,
+            db_path,
+        } => criner::bootstrap::run_blocking(criner::bootstrap::Options {
+            url: from_url,
+            sha256,
+            db_path,
+            registry,
+        }),
+        PublishSnapshot {
+            out,
+            every_day_at,
+            registry,
+            db_path,
+        } => criner::snapshot::publish_blocking(criner::snapshot::Options {
+            db_path,
+            out,
+            every_day_at,
+            registry,
+        }),
+        Status { registry, db_path } => criner::status::write_blocking(db_path, &registry, std::io::stdout()),
+        Stats { db_path } => criner::stats::write_blocking(db_path, std::io::stdout()),
+        Serve { port, bind, db_path } => criner::serve::run_blocking(db_path, (bind, port).into()),
+        CheckPackage {
+            path,
+            not_waste,
+            fail_on_waste,
+            compare_with_db,
+            registry,
+            fail_on_warnings,
+        } => {
+            let baseline = compare_with_db.as_ref().map(|db_path| criner::check_package::Baseline {
+                db_path,
+                registry: &registry,
+            });
+            match criner::check_package::check_blocking(&path, &not_waste, baseline, std::io::stdout())? {
+                checked if fail_on_waste && checked.wasted_files != 0 => Err(criner::Error::Message(format!(
+                    "'{}' would publish {} wasted files",
+                    path.display(),
+                    checked.wasted_files
+                ))),
+                checked if fail_on_warnings && checked.warnings != 0 => Err(criner::Error::Message(format!(
+                    "'{}' differs from the previous version with {} warnings",
+                    path.display(),
+                    checked.warnings
+                ))),
+                _ => Ok(()),
+            }
+        }
+        Reports { cmd } => match cmd {
+            ReportsCommands::Gc { keep, db_path } => {
+                let collected = criner::reports::gc_blocking(db_path, keep)?;
+                println!("{}", collected);
+                Ok(())
+            }
+        },
+        Query { cmd } => match cmd {
+            QueryCommands::Crate {
+                crate_name,
+                registry,
+                db_path,
+            } => criner::query::write_crate_blocking(db_path, &registry, &crate_name, std::io::stdout()),
+        },
+        Timeline {
+            crate_name,
+            crate_version,
+            registry,
+            db_path,
+        } => criner::timeline::write_blocking(db_path, &registry, &crate_name, &crate_version, std::io::stdout()),
+        Reanalyze {
+            crate_name,
+            registry,
+            db_path,
+        } => {
+            let reset = criner::reanalyze::reset_blocking(db_path, &registry, &crate_name)?;
+            println!("{}", reset);
+            Ok(())
+        }
+        Resolve {
+            crate_name,
+            requirement,
+            registry,
+            db_path,
+        } => match criner::resolve::resolve_blocking(db_path, &registry, &crate_name, &requirement)? {
+            Some(version) => {
+                println!("{}", version);
+                Ok(())
+            }
+            None => Err(criner::Error::Message(format!(
+                "No known version of '{}' satisfies '{}'",
+                crate_name, requirement
+            ))),
+        },
+        ExportGraph {
+            format,
+            root,
+            registry,
+            output,
+            db_path,
+        } => match output {
+            Some(path) => criner::graph::write_blocking(
+                db_path,
+                &registry,
+                format,
+                root.as_deref(),
+                std::io::BufWriter::new(std::fs::File::create(path)?),
+            ),
+            None => criner::graph::write_blocking(db_path, &registry, format, root.as_deref(), std::io::stdout()),
+        },
+        CratesIo { crate_name, db_path } => {
+            criner::crates_io_api::write_blocking(db_path, &crate_name, std::io::stdout())
+        }
+        Tune {
+            registry,
+            url,
+            max_io,
+            db_writes,
+        } => {
+            let recommendation = criner::tune::run_blocking(criner::tune::Options {
+                registry,
+                url,
+                max_io_bound_processors: max_io,
+                db_writes,
+            })?;
+            print!("{}", recommendation);
+            Ok(())
+        }
+        Bench { cmd } => match cmd {
+            BenchCommands::Db {
+                operations,
+                keys,
+                max_threads,
+                durability,
+                db_backend,
+            } => {
+                for measurement in criner::bench::db_blocking(criner::bench::DbOptions {
+                    durability,
+                    backend: db_backend,
+                    keys,
+                    operations,
+                    max_threads,
+                })? {
+                    println!("{}", measurement);
+                }
+                Ok(())
+            }
+        },
         Mine {
             repository,
+            registry,
             db_path,
             fps,
             time_limit,
@@ -32,38 +194,101 @@ pub fn run_blocking(args: Args) -> criner::error::Result<()> {
             report_every,
             report_at_most,
             glob,
+            max_connections_per_host,
+            crawl_delay,
+            max_requests_per_second,
+            max_download_bandwidth,
+            download_schedule,
+            ip_version,
+            host_overrides,
+            nameservers,
+            proxy,
+            download_url_template,
+            mirror_url_template,
+            task_retry,
+            artifacts,
+            keep_full_results_for_latest,
+            assets,
+            repair_index_gaps,
+            select_globs,
+            max_stored_entry_size,
+            extract_contents_for_latest,
+            not_waste,
+            deterministic,
+            stages,
+            crates_file,
+            durability,
+            db_backend,
+            metrics_addr,
+            serve_addr,
+            config,
         } => criner::run::blocking(
             db_path,
-            repository.unwrap_or_else(|| std::env::temp_dir().join("criner-crates-io-bare-index.git")),
-            time_limit.map(|d| std::time::SystemTime::now().add(*d)),
-            io_bound_processors,
-            cpu_bound_processors,
-            cpu_o_bound_processors,
-            criner::run::StageRunSettings {
-                every: fetch_every.into(),
-                at_most: fetch_at_most,
-            },
-            criner::run::StageRunSettings {
-                every: process_every.into(),
-                at_most: process_at_most,
-            },
-            criner::run::GlobStageRunSettings {
-                run: criner::run::StageRunSettings {
-                    every: report_every.into(),
-                    at_most: report_at_most,
+            repository.unwrap_or_else(|| registry.default_index_path()),
+            criner::run::Options {
+                registry,
+                deadline: time_limit.map(|d| std::time::SystemTime::now().add(*d)),
+                io_bound_processors,
+                cpu_bound_processors,
+                cpu_o_bound_processors,
+                fetch_settings: criner::run::StageRunSettings {
+                    every: fetch_every.into(),
+                    at_most: fetch_at_most,
+                },
+                process_settings: criner::run::StageRunSettings {
+                    every: process_every.into(),
+                    at_most: process_at_most,
+                },
+                report_settings: criner::run::GlobStageRunSettings {
+                    run: criner::run::StageRunSettings {
+                        every: report_every.into(),
+                        at_most: report_at_most,
+                    },
+                    glob,
                 },
-                glob,
+                download_crates_io_database_every_24_hours_starting_at,
+                network_settings: criner::run::NetworkSettings {
+                    max_connections_per_host,
+                    crawl_delay: crawl_delay.into(),
+                    max_requests_per_second,
+                    max_download_bandwidth,
+                    download_schedule,
+                    connectivity: criner::run::Connectivity {
+                        ip_version,
+                        host_overrides,
+                        nameservers,
+                        proxy,
+                    },
+                    download_url_template,
+                    mirror_url_template,
+                    task_retry,
+                },
+                artifact_kinds: artifacts,
+                keep_full_results_for_latest_versions: keep_full_results_for_latest,
+                assets_url: assets,
+                repair_index_gaps,
+                select_globs,
+                max_stored_entry_size,
+                extract_contents_for_latest,
+                not_waste,
+                deterministic,
+                stages,
+                allowlist: crates_file.map(criner::run::Allowlist::from_file).transpose()?,
+                durability,
+                backend: db_backend,
+                metrics_addr,
+                serve_addr,
+                config,
             },
-            download_crates_io_database_every_24_hours_starting_at,
             criner::prodash::TreeOptions {
                 message_buffer_capacity: progress_message_scrollback_buffer_size,
                 ..criner::prodash::TreeOptions::default()
             }
             .create(),
             if no_gui {
-                None
+                criner::run::Frontend::Log
             } else {
-                Some(criner::prodash::render::tui::Options {
+                criner::run::Frontend::Tui(criner::prodash::render::tui::Options {
                     title: "Criner".into(),
                     frames_per_second: fps,
                     recompute_column_width_every_nth_frame: Option::from(fps as usize),